@@ -0,0 +1,92 @@
+/*
+ * oxid - C API
+ *
+ * Build the library with `cargo build --release --features ffi` and link
+ * against the resulting liboxid shared library. The shared library is built
+ * without the feature too, but then exports none of these functions.
+ */
+
+#ifndef OXID_H
+#define OXID_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum {
+    OXID_OK = 0,
+    OXID_ERROR = -1,
+    OXID_EINVALID = -2,
+    OXID_ENOTFOUND = -3,
+    OXID_ITEROVER = -4,
+} oxid_error;
+
+typedef struct oxid_repository oxid_repository;
+typedef struct oxid_revwalk oxid_revwalk;
+
+typedef struct {
+    uint8_t *data;
+    size_t len;
+} oxid_buf;
+
+/* Bits of oxid_status_entry.flags */
+#define OXID_STATUS_INDEX_NEW      (1u << 0)
+#define OXID_STATUS_INDEX_MODIFIED (1u << 1)
+#define OXID_STATUS_INDEX_DELETED  (1u << 2)
+#define OXID_STATUS_INDEX_RENAMED  (1u << 3)
+#define OXID_STATUS_WT_NEW         (1u << 7)
+#define OXID_STATUS_WT_MODIFIED    (1u << 8)
+#define OXID_STATUS_WT_DELETED     (1u << 9)
+#define OXID_STATUS_CONFLICTED     (1u << 15)
+
+typedef struct {
+    char *path;     /* untracked directories end in '/' */
+    char *old_path; /* rename source with OXID_STATUS_INDEX_RENAMED, else NULL */
+    uint32_t flags;
+} oxid_status_entry;
+
+typedef struct {
+    oxid_status_entry *entries;
+    size_t count;
+} oxid_status_list;
+
+/* Message for the last failed call on this thread, or NULL */
+const char *oxid_error_last(void);
+
+oxid_error oxid_repository_open(oxid_repository **out, const char *path);
+oxid_error oxid_repository_init(oxid_repository **out, const char *path);
+void oxid_repository_free(oxid_repository *repo);
+
+/* Reads the raw "type size\0content" bytes of an object; hash must be a
+ * full hex object id */
+oxid_error oxid_object_read(const oxid_repository *repo, const char *hash, oxid_buf *out);
+
+/* Writes an object and stores its NUL-terminated hex id in hash_out, which
+ * holds hash_len bytes; at least 41 are needed */
+oxid_error oxid_object_write(const oxid_repository *repo, const char *type,
+                             const uint8_t *data, size_t len, char *hash_out,
+                             size_t hash_len);
+
+void oxid_buf_free(oxid_buf *buf);
+
+/* Walks the history of a revision, newest commit first; repo must stay open
+ * until the walk is freed */
+oxid_error oxid_revwalk_new(oxid_revwalk **out, const oxid_repository *repo,
+                            const char *start);
+/* Stores the next commit's NUL-terminated hex id in hash_out (at least 41
+ * bytes); returns OXID_ITEROVER when the walk is done */
+oxid_error oxid_revwalk_next(oxid_revwalk *walk, char *hash_out, size_t hash_len);
+void oxid_revwalk_free(oxid_revwalk *walk);
+
+/* Lists the changed paths of the working tree, sorted by path */
+oxid_error oxid_status_read(const oxid_repository *repo, oxid_status_list *out);
+void oxid_status_list_free(oxid_status_list *list);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* OXID_H */
@@ -0,0 +1,465 @@
+//! Which commit last changed each line of a file
+//!
+//! [`blame`] starts with every line of the file suspected on the starting
+//! commit and visits commits newest first. A commit passes the lines its
+//! parents already had on to them, using a line diff against each parent
+//! in turn, and is blamed for the lines left over. A parent with the same
+//! content as the commit takes over all of its lines; a parent without
+//! the file is searched for a file it was renamed from.
+//!
+//! Like Git, root commits are reported as boundaries unless
+//! [`BlameOptions::show_root`] is set, and lines of a shallow clone are
+//! never passed beyond its shallow commits.
+
+use crate::diff::{diff, find_renames, split_lines, Hunk};
+use crate::objects::{Blob, TreeRef};
+use crate::repository::Repository;
+use crate::status::tree_files;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+/// The id standing for changes not committed yet
+pub const UNCOMMITTED: &str = "0000000000000000000000000000000000000000";
+
+/// How to blame a file
+#[derive(Debug, Clone, Default)]
+pub struct BlameOptions {
+    /// Don't treat root commits as boundaries (`--root`)
+    pub show_root: bool,
+    /// Content to blame instead of the starting commit's, usually the
+    /// working tree file; lines it doesn't share with the starting commit
+    /// are blamed on [`UNCOMMITTED`]
+    pub contents: Option<Vec<u8>>,
+}
+
+/// A run of lines of the final file that one commit is blamed for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlameEntry {
+    /// First line in the final file, from 0
+    pub final_start: usize,
+    /// First line in the file as `commit` has it, from 0
+    pub source_start: usize,
+    /// Number of lines
+    pub len: usize,
+    /// The commit the lines come from, or [`UNCOMMITTED`]
+    pub commit: String,
+    /// The file's path in that commit
+    pub path: Vec<u8>,
+    /// The first parent having the file, and the file's path there
+    pub previous: Option<(String, Vec<u8>)>,
+    /// The commit is a root commit or at the shallow boundary, so the lines
+    /// may be older
+    pub boundary: bool,
+}
+
+/// The result of [`blame`]
+#[derive(Debug, Clone)]
+pub struct Blame {
+    /// The content that was blamed
+    pub data: Vec<u8>,
+    /// Entries covering every line, in final line order, with adjacent
+    /// entries of the same commit merged
+    pub entries: Vec<BlameEntry>,
+}
+
+/// Lines of one commit's version of the file, still to be blamed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Lines {
+    final_start: usize,
+    source_start: usize,
+    len: usize,
+}
+
+/// A commit whose version of the file is suspected of some lines
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Suspect {
+    /// Committer time, so that the queue visits newest commits first
+    time: i64,
+    commit: String,
+    path: Vec<u8>,
+}
+
+/// Find the blob at a path in a tree
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `tree` - The root tree
+/// * `path` - A `/`-separated path
+pub fn blob_at(repo: &Repository, tree: &str, path: &[u8]) -> Result<Option<String>> {
+    let mut tree = tree.to_string();
+    let mut components = path.split(|&b| b == b'/').peekable();
+    while let Some(name) = components.next() {
+        let data = repo.read_tree(&tree)?;
+        let mut found = None;
+        for entry in TreeRef::new(&data).iter() {
+            let entry = entry?;
+            if entry.name == name {
+                found = Some((entry.is_tree(), entry.hash()));
+                break;
+            }
+        }
+        match (found, components.peek()) {
+            (Some((false, hash)), None) => return Ok(Some(hash)),
+            (Some((true, hash)), Some(_)) => tree = hash,
+            _ => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// Read a blob's content
+fn read_blob(repo: &Repository, hash: &str) -> Result<Vec<u8>> {
+    Ok(Blob::parse(&repo.read_object(hash)?)?.data)
+}
+
+/// Find the path a file had in a parent that doesn't have it by name
+///
+/// The parent's files that are gone in the commit are compared with the
+/// file, and the most similar one counts if it passes the rename
+/// threshold.
+fn renamed_from(
+    repo: &Repository,
+    parent_tree: &str,
+    tree: &str,
+    blob: &str,
+) -> Result<Option<(Vec<u8>, String)>> {
+    let ours = tree_files(repo, tree)?;
+    let deleted: Vec<(Vec<u8>, String)> = tree_files(repo, parent_tree)?
+        .into_iter()
+        .filter(|(path, _)| !ours.contains_key(path))
+        .map(|(path, (_, hash))| (path, hash))
+        .collect();
+    let hashes: Vec<&str> = deleted.iter().map(|(_, hash)| hash.as_str()).collect();
+    let pairs = find_renames(&hashes, &[blob], |hash| read_blob(repo, hash))?;
+    Ok(pairs.first().map(|&(i, _, _)| deleted[i].clone()))
+}
+
+/// Split lines into those a parent has too and those it doesn't
+///
+/// `hunks` turn the parent's version into the commit's; lines outside
+/// them are unchanged. Returns `(kept, passed)`, with the passed lines
+/// renumbered for the parent's version.
+fn split_unchanged(lines: &[Lines], hunks: &[Hunk], new_len: usize) -> (Vec<Lines>, Vec<Lines>) {
+    // Unchanged runs as (start in commit, end in commit, start in parent)
+    let mut unchanged = Vec::new();
+    let (mut old, mut new) = (0, 0);
+    for hunk in hunks {
+        if hunk.new_start > new {
+            unchanged.push((new, hunk.new_start, old));
+        }
+        old = hunk.old_end();
+        new = hunk.new_end();
+    }
+    if new_len > new {
+        unchanged.push((new, new_len, old));
+    }
+
+    let mut kept = Vec::new();
+    let mut passed = Vec::new();
+    for range in lines {
+        let end = range.source_start + range.len;
+        let mut at = range.source_start;
+        for &(start, stop, parent_start) in &unchanged {
+            if stop <= at || start >= end {
+                continue;
+            }
+            let from = start.max(at);
+            let to = stop.min(end);
+            if from > at {
+                kept.push(Lines {
+                    final_start: range.final_start + (at - range.source_start),
+                    source_start: at,
+                    len: from - at,
+                });
+            }
+            passed.push(Lines {
+                final_start: range.final_start + (from - range.source_start),
+                source_start: parent_start + (from - start),
+                len: to - from,
+            });
+            at = to;
+        }
+        if at < end {
+            kept.push(Lines {
+                final_start: range.final_start + (at - range.source_start),
+                source_start: at,
+                len: end - at,
+            });
+        }
+    }
+    (kept, passed)
+}
+
+/// Merge runs that continue each other in both versions
+fn coalesce(mut lines: Vec<Lines>) -> Vec<Lines> {
+    lines.sort_by_key(|l| l.final_start);
+    let mut merged: Vec<Lines> = Vec::with_capacity(lines.len());
+    for range in lines {
+        match merged.last_mut() {
+            Some(last)
+                if last.final_start + last.len == range.final_start
+                    && last.source_start + last.len == range.source_start =>
+            {
+                last.len += range.len;
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Blame the lines of a file
+///
+/// `found` is called with each entry as soon as its commit is known, in
+/// the order history is walked, as `--incremental` shows them.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `start` - The commit to start from
+/// * `path` - The file's path in `start`
+/// * `options` - Root handling and content to blame
+/// * `found` - Called with every entry once it is known
+///
+/// # Errors
+///
+/// Fails if `start` has no file at `path`
+pub fn blame(
+    repo: &Repository,
+    start: &str,
+    path: &[u8],
+    options: &BlameOptions,
+    mut found: impl FnMut(&BlameEntry) -> Result<()>,
+) -> Result<Blame> {
+    let start_tree = repo.read_commit(start)?.tree.clone();
+    let start_blob = blob_at(repo, &start_tree, path)?
+        .with_context(|| format!("no such path {} in {}", crate::quote::display(path), start))?;
+    let data = match &options.contents {
+        Some(contents) => contents.clone(),
+        None => read_blob(repo, &start_blob)?,
+    };
+    let line_count = split_lines(&data).len();
+
+    let mut entries = Vec::new();
+    let mut blamed = |lines: Vec<Lines>,
+                      commit: &str,
+                      path: &[u8],
+                      previous: &Option<(String, Vec<u8>)>,
+                      boundary: bool|
+     -> Result<()> {
+        for range in coalesce(lines) {
+            let entry = BlameEntry {
+                final_start: range.final_start,
+                source_start: range.source_start,
+                len: range.len,
+                commit: commit.to_string(),
+                path: path.to_vec(),
+                previous: previous.clone(),
+                boundary,
+            };
+            found(&entry)?;
+            entries.push(entry);
+        }
+        Ok(())
+    };
+
+    let all = vec![Lines {
+        final_start: 0,
+        source_start: 0,
+        len: line_count,
+    }];
+    let mut queue: BTreeMap<Suspect, Vec<Lines>> = BTreeMap::new();
+    let start_suspect = Suspect {
+        time: repo.read_commit(start)?.committer.time,
+        commit: start.to_string(),
+        path: path.to_vec(),
+    };
+    match &options.contents {
+        Some(contents) if line_count > 0 => {
+            let original = read_blob(repo, &start_blob)?;
+            let hunks = diff(&split_lines(&original), &split_lines(contents));
+            let (kept, passed) = split_unchanged(&all, &hunks, line_count);
+            let previous = Some((start.to_string(), path.to_vec()));
+            blamed(kept, UNCOMMITTED, path, &previous, false)?;
+            if !passed.is_empty() {
+                queue.insert(start_suspect, passed);
+            }
+        }
+        _ if line_count > 0 => {
+            queue.insert(start_suspect, all);
+        }
+        _ => {}
+    }
+
+    while let Some((suspect, lines)) = queue.pop_last() {
+        let commit = repo.read_commit(&suspect.commit)?;
+        let blob = blob_at(repo, &commit.tree, &suspect.path)?
+            .with_context(|| format!("{} lost its file", suspect.commit))?;
+
+        // Where each parent has the file, if anywhere
+        let mut origins = Vec::new();
+        for parent in &commit.parents {
+            let parent_commit = repo.read_commit(parent)?;
+            let origin = match blob_at(repo, &parent_commit.tree, &suspect.path)? {
+                Some(hash) => Some((suspect.path.clone(), hash)),
+                None => renamed_from(repo, &parent_commit.tree, &commit.tree, &blob)?,
+            };
+            if let Some((path, hash)) = origin {
+                origins.push((
+                    Suspect {
+                        time: parent_commit.committer.time,
+                        commit: parent.clone(),
+                        path,
+                    },
+                    hash,
+                ));
+            }
+        }
+        let previous = origins
+            .first()
+            .map(|(parent, _)| (parent.commit.clone(), parent.path.clone()));
+
+        let mut remaining = lines;
+        if let Some((parent, _)) = origins.iter().find(|(_, hash)| *hash == blob) {
+            // Nothing changed: the parent takes every line
+            queue.entry(parent.clone()).or_default().extend(remaining);
+            continue;
+        }
+        if !origins.is_empty() {
+            let content = read_blob(repo, &blob)?;
+            let ours = split_lines(&content);
+            for (parent, hash) in origins {
+                if remaining.is_empty() {
+                    break;
+                }
+                let theirs = read_blob(repo, &hash)?;
+                let hunks = diff(&split_lines(&theirs), &ours);
+                let (kept, passed) = split_unchanged(&remaining, &hunks, ours.len());
+                if !passed.is_empty() {
+                    queue.entry(parent).or_default().extend(passed);
+                }
+                remaining = kept;
+            }
+        }
+        if !remaining.is_empty() {
+            let boundary = commit.parents.is_empty() && !options.show_root;
+            blamed(
+                remaining,
+                &suspect.commit,
+                &suspect.path,
+                &previous,
+                boundary,
+            )?;
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.final_start);
+    let mut merged: Vec<BlameEntry> = Vec::with_capacity(entries.len());
+    for entry in entries {
+        match merged.last_mut() {
+            Some(last)
+                if last.commit == entry.commit
+                    && last.path == entry.path
+                    && last.final_start + last.len == entry.final_start
+                    && last.source_start + last.len == entry.source_start =>
+            {
+                last.len += entry.len;
+            }
+            _ => merged.push(entry),
+        }
+    }
+    Ok(Blame {
+        data,
+        entries: merged,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    /// The commit blamed for each line
+    fn owners(blame: &Blame) -> Vec<&str> {
+        blame
+            .entries
+            .iter()
+            .flat_map(|entry| std::iter::repeat_n(entry.commit.as_str(), entry.len))
+            .collect()
+    }
+
+    #[test]
+    fn test_blame_history() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder
+            .file("a.txt", "one\ntwo\nthree\n")
+            .commit("first")
+            .unwrap();
+        let second = builder
+            .file("a.txt", "one\n2\nthree\nfour\n")
+            .commit("second")
+            .unwrap();
+        let third = builder
+            .file("b.txt", "unrelated\n")
+            .commit("third")
+            .unwrap();
+        // Renamed, with the last line changed
+        builder.remove("a.txt");
+        let fourth = builder
+            .file("c.txt", "one\n2\nthree\nFOUR\n")
+            .commit("fourth")
+            .unwrap();
+
+        let repo = builder.repo();
+        let mut found = Vec::new();
+        let result = blame(repo, &fourth, b"c.txt", &BlameOptions::default(), |entry| {
+            found.push(entry.commit.clone());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(owners(&result), vec![&first, &second, &first, &fourth]);
+        // Newest first, as the walk finds them
+        assert_eq!(found, [&fourth, &second, &first, &first].map(String::clone));
+
+        let entry = &result.entries[1];
+        assert_eq!(
+            (entry.final_start, entry.source_start, entry.len),
+            (1, 1, 1)
+        );
+        assert_eq!(entry.path, b"a.txt");
+        assert_eq!(entry.previous, Some((first.clone(), b"a.txt".to_vec())));
+        assert!(!entry.boundary);
+        assert!(result.entries[0].boundary);
+        assert_eq!(result.entries[3].path, b"c.txt");
+        assert_eq!(result.entries[3].previous, Some((third, b"a.txt".to_vec())));
+
+        // Lines the working tree adds are not committed yet
+        let options = BlameOptions {
+            show_root: true,
+            contents: Some(b"zero\none\n2\nthree\nFOUR\n".to_vec()),
+        };
+        let result = blame(repo, &fourth, b"c.txt", &options, |_| Ok(())).unwrap();
+        assert_eq!(owners(&result)[..2], [UNCOMMITTED, &first]);
+        assert_eq!(result.entries[1].final_start, 1);
+        assert!(!result.entries[1].boundary);
+
+        assert!(blame(repo, &first, b"missing", &options, |_| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_blame_merge() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let base = builder.file("f", "a\nb\nc\n").commit("base").unwrap();
+        builder.branch("topic").unwrap().checkout("topic").unwrap();
+        let topic = builder.file("f", "a\nb\nc\nd\n").commit("topic").unwrap();
+        builder.checkout("main").unwrap();
+        let main = builder.file("f", "A\nb\nc\n").commit("main").unwrap();
+        builder.file("f", "A\nb\nc\nd\n");
+        let merge = builder.merge("topic", "merge").unwrap();
+
+        let options = BlameOptions::default();
+        let result = blame(builder.repo(), &merge, b"f", &options, |_| Ok(())).unwrap();
+        assert_eq!(owners(&result), vec![&main, &base, &base, &topic]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Cache of parsed commits and trees
+//!
+//! History walks and diffs look at the same commits and trees over and
+//! over. [`ObjectCache`] keeps the most recently used ones, parsed, so they
+//! are read and inflated only once. The cache is bounded by entry count,
+//! evicts the least recently used entry first, and can be shared between
+//! threads. Hit and miss counts are logged to `GIT_TRACE` when the cache
+//! is dropped.
+//!
+//! A repository's cache holds `oxid.objectCacheSize` objects, 1024 unless
+//! configured; 0 turns caching off.
+
+use crate::config::Config;
+use crate::objects::Commit;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// Entries a repository keeps unless configured otherwise
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Configuration key setting a repository's cache size
+pub const CAPACITY_KEY: &str = "oxid.objectCacheSize";
+
+/// The cache size a configuration asks for
+///
+/// # Arguments
+///
+/// * `config` - The repository's configuration
+pub fn capacity_from_config(config: &Config) -> Result<usize> {
+    match config.get_u64(CAPACITY_KEY)? {
+        Some(capacity) => Ok(usize::try_from(capacity)?),
+        None => Ok(DEFAULT_CAPACITY),
+    }
+}
+
+/// A cached object
+#[derive(Debug, Clone)]
+enum Cached {
+    Commit(Arc<Commit>),
+    /// Tree content without the header; walk it with [`crate::objects::TreeRef`]
+    Tree(Arc<[u8]>),
+}
+
+/// Lookup statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    capacity: usize,
+    /// Increases with every use; orders entries by recency
+    tick: u64,
+    entries: HashMap<String, (u64, Cached)>,
+    /// Hash of every entry by last use, oldest first
+    recency: BTreeMap<u64, String>,
+    stats: CacheStats,
+}
+
+impl Lru {
+    fn get(&mut self, hash: &str) -> Option<Cached> {
+        let (used, object) = self.entries.get_mut(hash)?;
+        self.tick += 1;
+        let hash = self
+            .recency
+            .remove(used)
+            .expect("cached entry has a recency");
+        *used = self.tick;
+        let object = object.clone();
+        self.recency.insert(self.tick, hash);
+        Some(object)
+    }
+
+    /// Count a lookup in the statistics
+    fn record(&mut self, hit: bool) {
+        if hit {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+    }
+
+    fn insert(&mut self, hash: &str, object: Cached) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.tick += 1;
+        if let Some((used, _)) = self.entries.insert(hash.to_string(), (self.tick, object)) {
+            self.recency.remove(&used);
+        }
+        self.recency.insert(self.tick, hash.to_string());
+        self.shrink();
+    }
+
+    /// Evict the least recently used entries until the cache fits
+    fn shrink(&mut self) {
+        while self.entries.len() > self.capacity {
+            let Some((_, hash)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&hash);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+/// A size-bounded, thread-safe LRU cache of parsed objects, keyed by hash
+#[derive(Debug)]
+pub struct ObjectCache {
+    lru: Mutex<Lru>,
+}
+
+impl ObjectCache {
+    /// Create a cache holding up to `capacity` objects; 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        ObjectCache {
+            lru: Mutex::new(Lru {
+                capacity,
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Lru> {
+        // A panic while holding the lock can't leave the map inconsistent
+        // in a way that matters for a cache
+        self.lru.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Maximum number of cached objects
+    pub fn capacity(&self) -> usize {
+        self.lock().capacity
+    }
+
+    /// Change the capacity, evicting entries if the cache is now too full
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut lru = self.lock();
+        lru.capacity = capacity;
+        lru.shrink();
+    }
+
+    /// Number of cached objects
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Whether nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hits, misses and evictions so far
+    pub fn stats(&self) -> CacheStats {
+        self.lock().stats
+    }
+
+    /// Drop every cached object, keeping the statistics
+    pub fn clear(&self) {
+        let mut lru = self.lock();
+        lru.entries.clear();
+        lru.recency.clear();
+    }
+
+    /// Look up a commit
+    pub fn get_commit(&self, hash: &str) -> Option<Arc<Commit>> {
+        let mut lru = self.lock();
+        let commit = match lru.get(hash) {
+            Some(Cached::Commit(commit)) => Some(commit),
+            _ => None,
+        };
+        lru.record(commit.is_some());
+        commit
+    }
+
+    /// Look up a tree's content
+    pub fn get_tree(&self, hash: &str) -> Option<Arc<[u8]>> {
+        let mut lru = self.lock();
+        let tree = match lru.get(hash) {
+            Some(Cached::Tree(tree)) => Some(tree),
+            _ => None,
+        };
+        lru.record(tree.is_some());
+        tree
+    }
+
+    /// Cache a parsed commit
+    pub fn insert_commit(&self, hash: &str, commit: Arc<Commit>) {
+        self.lock().insert(hash, Cached::Commit(commit));
+    }
+
+    /// Cache a tree's content (without the object header)
+    pub fn insert_tree(&self, hash: &str, tree: Arc<[u8]>) {
+        self.lock().insert(hash, Cached::Tree(tree));
+    }
+}
+
+impl Default for ObjectCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Drop for ObjectCache {
+    fn drop(&mut self) {
+        let stats = self.stats();
+        if stats.hits + stats.misses > 0 {
+            tracing::debug!(
+                hits = stats.hits,
+                misses = stats.misses,
+                evictions = stats.evictions,
+                "object cache"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Signature;
+
+    fn commit(message: &str) -> Arc<Commit> {
+        let sig = Signature::new("A U Thor", "author@example.com", 1112911993, -420);
+        Arc::new(Commit {
+            tree: "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+            parents: Vec::new(),
+            author: sig.clone(),
+            committer: sig,
+            message: message.to_string(),
+            extra_headers: Vec::new(),
+            gpgsig: None,
+        })
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = ObjectCache::new(2);
+        cache.insert_commit("a", commit("a\n"));
+        cache.insert_tree("b", Arc::from(&b"tree"[..]));
+        assert!(cache.get_commit("a").is_some());
+        cache.insert_commit("c", commit("c\n"));
+
+        // "b" was used least recently
+        assert!(cache.get_tree("b").is_none());
+        assert_eq!(cache.get_commit("a").unwrap().message, "a\n");
+        assert!(cache.get_commit("c").is_some());
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 3,
+                misses: 1,
+                evictions: 1
+            }
+        );
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get_commit("c").is_some());
+    }
+
+    #[test]
+    fn test_zero_capacity_disables_caching() {
+        let cache = ObjectCache::new(0);
+        cache.insert_commit("a", commit("a\n"));
+        assert!(cache.is_empty());
+        assert!(cache.get_commit("a").is_none());
+    }
+}
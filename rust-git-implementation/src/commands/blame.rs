@@ -0,0 +1,405 @@
+//! Show which commit last changed each line of a file (`oxid blame`)
+//!
+//! Without a revision the working tree file is blamed, and lines changed
+//! since `HEAD` show as "Not Committed Yet". Besides the default
+//! human-readable output there are the formats editor integrations parse:
+//!
+//! - `--porcelain`: one group per run of lines from the same commit,
+//!   opened by `<hash> <source line> <final line> <count>`; the commit's
+//!   author, committer, summary and file name follow the first time the
+//!   commit appears, and each line's content comes after a tab
+//! - `--line-porcelain`: the same, with the commit information repeated
+//!   for every line
+//! - `--incremental`: the groups without file content, written as soon as
+//!   each is known, in the order history is walked
+
+use crate::blame::{blame, Blame, BlameEntry, BlameOptions, UNCOMMITTED};
+use crate::diff::split_lines;
+use crate::mailmap::Mailmap;
+use crate::objects::Signature;
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::revision::resolve_commit;
+use anyhow::Result;
+use chrono::{FixedOffset, TimeZone};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+
+/// How `oxid blame` prints its result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlameFormat {
+    /// Hash, author, date and line number before each line
+    #[default]
+    Default,
+    /// `--porcelain`
+    Porcelain,
+    /// `--line-porcelain`
+    LinePorcelain,
+    /// `--incremental`
+    Incremental,
+}
+
+/// What the output says about a commit
+#[derive(Debug, Clone)]
+struct CommitInfo {
+    author: Signature,
+    committer: Signature,
+    summary: String,
+}
+
+/// Writes blame output, looking up each commit once
+struct Printer<'a> {
+    repo: &'a Repository,
+    mailmap: Mailmap,
+    paths: PathFormat,
+    /// The path blamed, for the uncommitted pseudo-commit's summary
+    path: Vec<u8>,
+    infos: HashMap<String, CommitInfo>,
+    /// Commits whose details have been written
+    shown: HashSet<String>,
+}
+
+/// Format a timezone offset in minutes as `+hhmm`
+fn format_tz(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!("{}{:02}{:02}", sign, offset.abs() / 60, offset.abs() % 60)
+}
+
+impl<'a> Printer<'a> {
+    fn new(repo: &'a Repository, path: &[u8]) -> Result<Self> {
+        Ok(Printer {
+            repo,
+            mailmap: Mailmap::read(repo)?,
+            paths: PathFormat::from_config(&repo.config()?, false)?,
+            path: path.to_vec(),
+            infos: HashMap::new(),
+            shown: HashSet::new(),
+        })
+    }
+
+    /// Author, committer and summary of a commit, with the mailmap applied
+    fn info(&mut self, hash: &str) -> Result<&CommitInfo> {
+        if !self.infos.contains_key(hash) {
+            let info = if hash == UNCOMMITTED {
+                let nobody = Signature::now("Not Committed Yet", "not.committed.yet");
+                let path = String::from_utf8_lossy(&self.path);
+                CommitInfo {
+                    author: nobody.clone(),
+                    committer: nobody,
+                    summary: format!("Version of {} from {}", path, path),
+                }
+            } else {
+                let commit = self.repo.read_commit(hash)?;
+                let map = |sig: &Signature| {
+                    let (name, email) = self.mailmap.resolve(Some(&sig.name), &sig.email);
+                    Signature {
+                        name: name.unwrap_or_else(|| sig.name.clone()),
+                        email,
+                        ..sig.clone()
+                    }
+                };
+                let subject = commit.message.lines().next().unwrap_or("");
+                CommitInfo {
+                    author: map(&commit.author),
+                    committer: map(&commit.committer),
+                    summary: match subject {
+                        "" => format!("({})", hash),
+                        subject => subject.to_string(),
+                    },
+                }
+            };
+            self.infos.insert(hash.to_string(), info);
+        }
+        Ok(&self.infos[hash])
+    }
+
+    /// Write a commit's details, unless already written and not `repeat`
+    ///
+    /// Returns whether anything was written.
+    fn details(&mut self, out: &mut dyn Write, entry: &BlameEntry, repeat: bool) -> Result<bool> {
+        if !self.shown.insert(entry.commit.clone()) && !repeat {
+            return Ok(false);
+        }
+        let info = self.info(&entry.commit)?;
+        for (role, sig) in [("author", &info.author), ("committer", &info.committer)] {
+            writeln!(out, "{} {}", role, sig.name)?;
+            writeln!(out, "{}-mail <{}>", role, sig.email)?;
+            writeln!(out, "{}-time {}", role, sig.time)?;
+            writeln!(out, "{}-tz {}", role, format_tz(sig.offset))?;
+        }
+        writeln!(out, "summary {}", info.summary)?;
+        if entry.boundary {
+            writeln!(out, "boundary")?;
+        }
+        Ok(true)
+    }
+
+    /// Write where an entry's lines came from: the parent the rest of the
+    /// file was passed to, and the file's name
+    fn filename(&self, out: &mut dyn Write, entry: &BlameEntry) -> Result<()> {
+        if let Some((parent, path)) = &entry.previous {
+            write!(out, "previous {} ", parent)?;
+            self.paths.write_line(out, path)?;
+        }
+        write!(out, "filename ")?;
+        self.paths.write_line(out, &entry.path)?;
+        Ok(())
+    }
+
+    /// Write an entry as `--incremental` shows it
+    fn incremental(&mut self, out: &mut dyn Write, entry: &BlameEntry) -> Result<()> {
+        writeln!(
+            out,
+            "{} {} {} {}",
+            entry.commit,
+            entry.source_start + 1,
+            entry.final_start + 1,
+            entry.len
+        )?;
+        self.details(out, entry, false)?;
+        self.filename(out, entry)?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// Write the whole result in `--porcelain` or `--line-porcelain` form
+    ///
+    /// After the first group of a commit, its file name is only repeated
+    /// if the commit had the file under several names.
+    fn porcelain(&mut self, out: &mut dyn Write, blame: &Blame, repeat: bool) -> Result<()> {
+        let lines = split_lines(&blame.data);
+        let mut paths: HashMap<&str, HashSet<&[u8]>> = HashMap::new();
+        for entry in &blame.entries {
+            paths.entry(&entry.commit).or_default().insert(&entry.path);
+        }
+        for entry in &blame.entries {
+            let renamed = paths[entry.commit.as_str()].len() > 1;
+            for i in 0..entry.len {
+                write!(
+                    out,
+                    "{} {} {}",
+                    entry.commit,
+                    entry.source_start + i + 1,
+                    entry.final_start + i + 1
+                )?;
+                let shown = if i == 0 {
+                    writeln!(out, " {}", entry.len)?;
+                    self.details(out, entry, repeat)?
+                } else {
+                    writeln!(out)?;
+                    repeat && self.details(out, entry, true)?
+                };
+                if shown || (i == 0 && renamed) {
+                    self.filename(out, entry)?;
+                }
+                write_line(out, lines[entry.final_start + i])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the result for reading: hash, author, date and line number
+    /// before each line, plus the file name when the file was renamed
+    fn human(&mut self, out: &mut dyn Write, blame: &Blame) -> Result<()> {
+        let lines = split_lines(&blame.data);
+        let show_name = blame.entries.iter().any(|entry| entry.path != self.path);
+        let mut longest_author = 0;
+        let mut longest_path = 0;
+        for entry in &blame.entries {
+            let author = self.info(&entry.commit)?.author.name.chars().count();
+            longest_author = longest_author.max(author);
+            longest_path = longest_path.max(String::from_utf8_lossy(&entry.path).chars().count());
+        }
+        let digits = lines.len().to_string().len();
+
+        for entry in &blame.entries {
+            let info = self.info(&entry.commit)?.clone();
+            let hash = match entry.boundary {
+                true => format!("^{}", &entry.commit[..7]),
+                false => entry.commit[..8].to_string(),
+            };
+            let zone = FixedOffset::east_opt(info.author.offset * 60)
+                .unwrap_or_else(|| FixedOffset::east_opt(0).expect("zero offset is valid"));
+            let date = zone
+                .timestamp_opt(info.author.time, 0)
+                .single()
+                .map(|date| date.format("%Y-%m-%d %H:%M:%S %z").to_string())
+                .unwrap_or_default();
+            for i in 0..entry.len {
+                write!(out, "{}", hash)?;
+                if show_name {
+                    let path = String::from_utf8_lossy(&entry.path);
+                    write!(out, " {:<width$}", path, width = longest_path)?;
+                }
+                let pad = longest_author - info.author.name.chars().count();
+                write!(
+                    out,
+                    " ({}{:pad$} {} {:>digits$}) ",
+                    info.author.name,
+                    "",
+                    date,
+                    entry.final_start + i + 1,
+                    pad = pad,
+                    digits = digits
+                )?;
+                out.write_all(lines[entry.final_start + i])?;
+                if !lines[entry.final_start + i].ends_with(b"\n") {
+                    writeln!(out)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write a line of the file after a tab, ending it with a newline
+fn write_line(out: &mut dyn Write, line: &[u8]) -> Result<()> {
+    out.write_all(b"\t")?;
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Blame a file and write the result
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `start` - The commit to start from
+/// * `path` - The file's path from the top of the worktree
+/// * `options` - Root handling and content to blame
+/// * `format` - How to print the result
+/// * `out` - Where to write it
+pub fn write_blame(
+    repo: &Repository,
+    start: &str,
+    path: &[u8],
+    options: &BlameOptions,
+    format: BlameFormat,
+    out: &mut dyn Write,
+) -> Result<()> {
+    let mut printer = Printer::new(repo, path)?;
+    if format == BlameFormat::Incremental {
+        blame(repo, start, path, options, |entry| {
+            printer.incremental(out, entry)
+        })?;
+        return Ok(());
+    }
+    let result = blame(repo, start, path, options, |_| Ok(()))?;
+    match format {
+        BlameFormat::Porcelain => printer.porcelain(out, &result, false),
+        BlameFormat::LinePorcelain => printer.porcelain(out, &result, true),
+        _ => printer.human(out, &result),
+    }
+}
+
+/// Print who last changed each line of a file
+///
+/// # Arguments
+///
+/// * `revision` - The commit to start from; without one, the working tree
+///   file is blamed against `HEAD`
+/// * `file` - The file, relative to the current directory
+/// * `format` - How to print the result
+/// * `show_root` - Don't treat root commits as boundaries
+pub fn run(
+    revision: Option<&str>,
+    file: &Path,
+    format: BlameFormat,
+    show_root: bool,
+) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let path = repo.index_path_of(file)?;
+    let mut options = BlameOptions {
+        show_root,
+        contents: None,
+    };
+    let start = match revision {
+        Some(revision) => resolve_commit(&repo, revision)?,
+        None => {
+            let worktree = repo.work_tree.join(crate::utils::path_from_bytes(&path)?);
+            options.contents = Some(match std::fs::symlink_metadata(&worktree) {
+                Ok(metadata) if metadata.file_type().is_symlink() => {
+                    crate::utils::path_to_bytes(&std::fs::read_link(&worktree)?)?
+                }
+                _ => std::fs::read(&worktree)?,
+            });
+            resolve_commit(&repo, "HEAD")?
+        }
+    };
+    let mut out = std::io::stdout().lock();
+    write_blame(&repo, &start, &path, &options, format, &mut out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    fn output(builder: &RepoBuilder, start: &str, format: BlameFormat) -> String {
+        let mut out = Vec::new();
+        let options = BlameOptions::default();
+        write_blame(builder.repo(), start, b"f", &options, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("f", "a\nb\n").commit("first").unwrap();
+        let second = builder
+            .file("f", "a\nB\nc")
+            .commit("second\n\nbody")
+            .unwrap();
+
+        let details = |hash: &str, time: i64, summary: &str| {
+            format!(
+                "author A U Thor\nauthor-mail <author@example.com>\n\
+                 author-time {time}\nauthor-tz -0700\n\
+                 committer C O Mitter\ncommitter-mail <committer@example.com>\n\
+                 committer-time {time}\ncommitter-tz -0700\nsummary {summary}\n{}",
+                if hash == first { "boundary\n" } else { "" }
+            )
+        };
+        let first_details = details(&first, 1112912053, "first");
+        let second_details = details(&second, 1112912113, "second");
+
+        assert_eq!(
+            output(&builder, &second, BlameFormat::Porcelain),
+            format!(
+                "{first} 1 1 1\n{first_details}filename f\n\ta\n\
+                 {second} 2 2 2\n{second_details}previous {first} f\nfilename f\n\tB\n\
+                 {second} 3 3\n\tc\n"
+            )
+        );
+        assert_eq!(
+            output(&builder, &second, BlameFormat::LinePorcelain),
+            format!(
+                "{first} 1 1 1\n{first_details}filename f\n\ta\n\
+                 {second} 2 2 2\n{second_details}previous {first} f\nfilename f\n\tB\n\
+                 {second} 3 3\n{second_details}previous {first} f\nfilename f\n\tc\n"
+            )
+        );
+        assert_eq!(
+            output(&builder, &second, BlameFormat::Incremental),
+            format!(
+                "{second} 2 2 2\n{second_details}previous {first} f\nfilename f\n\
+                 {first} 1 1 1\n{first_details}filename f\n"
+            )
+        );
+        assert_eq!(
+            output(&builder, &second, BlameFormat::Default),
+            format!(
+                "^{} (A U Thor 2005-04-07 15:14:13 -0700 1) a\n\
+                 {} (A U Thor 2005-04-07 15:15:13 -0700 2) B\n\
+                 {} (A U Thor 2005-04-07 15:15:13 -0700 3) c\n",
+                &first[..7],
+                &second[..8],
+                &second[..8]
+            )
+        );
+    }
+}
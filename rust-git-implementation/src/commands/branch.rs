@@ -0,0 +1,147 @@
+//! List branches (`oxid branch`)
+//!
+//! Branches can be filtered by reachability: `--contains` keeps those whose
+//! history includes a commit, `--merged` those whose tip is part of a
+//! commit's history, and `--no-contains` / `--no-merged` the rest.
+
+use crate::ref_filter::ReachFilter;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::fs;
+
+/// A branch and the commit it points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    /// Short name, e.g. `main`; `None` for a detached `HEAD`
+    pub name: Option<String>,
+    pub tip: String,
+    /// `HEAD` is on this branch
+    pub current: bool,
+}
+
+/// List local branches passing a filter, sorted by name
+///
+/// A detached `HEAD` is listed first, as Git does.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `filter` - Which branches to keep
+pub fn list_branches(repo: &Repository, filter: &ReachFilter) -> Result<Vec<Branch>> {
+    let head = fs::read_to_string(repo.git_dir.join("HEAD"))?;
+    let head = head.trim_end();
+    let current = head.strip_prefix("ref: refs/heads/");
+
+    let mut branches = Vec::new();
+    if current.is_none() {
+        branches.push(Branch {
+            name: None,
+            tip: head.to_string(),
+            current: true,
+        });
+    }
+    for (name, tip) in crate::refs::read_refs(&repo.git_dir)? {
+        if let Some(name) = name.strip_prefix("refs/heads/") {
+            branches.push(Branch {
+                current: Some(name) == current,
+                name: Some(name.to_string()),
+                tip,
+            });
+        }
+    }
+
+    filter.apply(repo, branches, |branch| Some(&branch.tip))
+}
+
+/// Format branches as `branch` lists them, marking the current one with `*`
+pub fn format_branches(branches: &[Branch]) -> String {
+    let mut out = String::new();
+    for branch in branches {
+        let marker = if branch.current { '*' } else { ' ' };
+        match &branch.name {
+            Some(name) => out.push_str(&format!("{} {}\n", marker, name)),
+            None => out.push_str(&format!(
+                "{} (HEAD detached at {})\n",
+                marker,
+                &branch.tip[..7.min(branch.tip.len())]
+            )),
+        }
+    }
+    out
+}
+
+/// List branches
+///
+/// # Arguments
+///
+/// * `filter` - Which branches to list
+pub fn run(filter: &ReachFilter) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    print!("{}", format_branches(&list_branches(&repo, filter)?));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_filters() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let base = builder.file("a", "a\n").commit("base").unwrap();
+        builder.branch("old").unwrap();
+        builder.branch("feature").unwrap();
+        builder.checkout("feature").unwrap();
+        let fix = builder.file("fix", "fix\n").commit("fix").unwrap();
+        builder.checkout("main").unwrap();
+        builder.file("b", "b\n").commit("main work").unwrap();
+
+        let repo = builder.repo();
+        let names = |filter: ReachFilter| -> Vec<String> {
+            list_branches(repo, &filter)
+                .unwrap()
+                .into_iter()
+                .filter_map(|b| b.name)
+                .collect()
+        };
+        let revs = |names: &[&str]| names.iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(
+            names(ReachFilter::default()),
+            vec!["feature", "main", "old"]
+        );
+        assert_eq!(
+            names(ReachFilter {
+                contains: revs(&[&fix]),
+                ..Default::default()
+            }),
+            vec!["feature"]
+        );
+        assert_eq!(
+            names(ReachFilter {
+                no_contains: revs(&[&fix]),
+                contains: revs(&[&base]),
+                ..Default::default()
+            }),
+            vec!["main", "old"]
+        );
+        assert_eq!(
+            names(ReachFilter {
+                merged: revs(&["HEAD"]),
+                ..Default::default()
+            }),
+            vec!["main", "old"]
+        );
+        assert_eq!(
+            names(ReachFilter {
+                no_merged: revs(&["main"]),
+                ..Default::default()
+            }),
+            vec!["feature"]
+        );
+
+        let out = format_branches(&list_branches(repo, &ReachFilter::default()).unwrap());
+        assert_eq!(out, "  feature\n* main\n  old\n");
+    }
+}
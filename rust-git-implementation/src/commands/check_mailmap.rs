@@ -0,0 +1,70 @@
+//! Show canonical names and emails
+
+use crate::mailmap::{self, Mailmap};
+use crate::repository::Repository;
+use anyhow::Result;
+use std::io::BufRead;
+
+/// Map a contact through the mailmap
+///
+/// Returns `Name <email>`, or `<email>` when no name is known.
+///
+/// # Arguments
+///
+/// * `mailmap` - The mappings to apply
+/// * `contact` - `Name <email>` or `<email>`
+pub fn check(mailmap: &Mailmap, contact: &str) -> Result<String> {
+    let (name, email) = mailmap::parse_contact(contact)?;
+    Ok(match mailmap.resolve(name.as_deref(), &email) {
+        (Some(name), email) => format!("{} <{}>", name, email),
+        (None, email) => format!("<{}>", email),
+    })
+}
+
+/// Print the canonical form of each contact
+///
+/// # Arguments
+///
+/// * `contacts` - Contacts given on the command line
+/// * `stdin` - Also read contacts from stdin, one per line
+pub fn run(contacts: &[String], stdin: bool) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let mailmap = Mailmap::read(&repo)?;
+    for contact in contacts {
+        println!("{}", check(&mailmap, contact)?);
+    }
+    if stdin {
+        for line in std::io::stdin().lock().lines() {
+            println!("{}", check(&mailmap, &line?)?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_check_reads_repository_mailmap() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(
+            dir.path().join(".mailmap"),
+            "Jane Doe <jane@example.com> <jane@old.example>\n",
+        )
+        .unwrap();
+        let mailmap = Mailmap::read(&repo).unwrap();
+
+        assert_eq!(
+            check(&mailmap, "jd <jane@old.example>").unwrap(),
+            "Jane Doe <jane@example.com>"
+        );
+        assert_eq!(
+            check(&mailmap, "<other@example.com>").unwrap(),
+            "<other@example.com>"
+        );
+        assert!(check(&mailmap, "no email").is_err());
+    }
+}
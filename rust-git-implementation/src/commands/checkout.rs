@@ -0,0 +1,279 @@
+//! Restore worktree files from the index
+
+use crate::index::{Conflict, IndexEntry};
+use crate::merge::{self, ConflictStyle, Labels};
+use crate::objects::FileMode;
+use crate::quote;
+use crate::repository::Repository;
+use crate::utils::path_from_bytes;
+use crate::worktree::{case_collisions, verify_path, WorkTree};
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options for `checkout -- <paths>`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckoutOptions {
+    /// `--conflict=<style>`: recreate conflict markers in this style
+    pub conflict: Option<ConflictStyle>,
+    /// `-m`/`--merge`: recreate conflict markers in the `merge.conflictStyle`
+    /// style
+    pub merge: bool,
+}
+
+/// Recreate the conflicted merge of a path from its index stages
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the staged blobs
+/// * `conflict` - The conflicted path
+/// * `style` - How to write conflicts
+pub fn remerge(repo: &Repository, conflict: &Conflict, style: ConflictStyle) -> Result<Vec<u8>> {
+    let (Some(ours), Some(theirs)) = (conflict.ours, conflict.theirs) else {
+        anyhow::bail!(
+            "path '{}' does not have all necessary versions",
+            quote::display(conflict.path)
+        );
+    };
+    let base = match conflict.base {
+        Some(base) => repo.read_staged(base)?,
+        None => Vec::new(),
+    };
+    let result = merge::merge(
+        &base,
+        &repo.read_staged(ours)?,
+        &repo.read_staged(theirs)?,
+        style,
+        &Labels::default(),
+    );
+    Ok(result.data)
+}
+
+/// Write the staged content of an index entry to a file
+///
+/// Symlinks and the executable bit are restored according to the entry's
+/// mode. Paths Git would refuse to check out are rejected before anything is
+/// touched.
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the staged blob
+/// * `worktree` - Where to write
+/// * `file` - Path to write, relative to the worktree root
+/// * `entry` - The index entry to check out
+pub fn checkout_entry(
+    repo: &Repository,
+    worktree: &mut dyn WorkTree,
+    file: &Path,
+    entry: &IndexEntry,
+) -> Result<()> {
+    verify_path(file)?;
+    let data = repo.read_staged(entry)?;
+    if entry.mode == FileMode::Symlink {
+        if worktree.exists(file) {
+            worktree.remove(file)?;
+        }
+        worktree.write_symlink(file, &data)
+    } else {
+        worktree.write(file, &data)?;
+        worktree.set_executable(file, entry.mode == FileMode::Executable)
+    }
+}
+
+/// Warn about written paths that differ only in case
+///
+/// On a case-insensitive filesystem only the last of each colliding group
+/// survives in the working tree.
+///
+/// # Arguments
+///
+/// * `files` - Paths written, relative to the worktree root
+pub fn warn_case_collisions(files: &[PathBuf]) {
+    let collisions = case_collisions(files);
+    if collisions.is_empty() {
+        return;
+    }
+    eprintln!(
+        "warning: the following paths have collided (e.g. case-sensitive paths\n\
+         on a case-insensitive filesystem) and only one from the same\n\
+         colliding group is in the working tree:"
+    );
+    for (first, second) in collisions {
+        eprintln!("  '{}'", first.display());
+        eprintln!("  '{}'", second.display());
+    }
+}
+
+/// Overwrite worktree files with their index versions
+///
+/// Conflicted paths are only checked out with `--conflict` or `--merge`,
+/// which rewrite the file with conflict markers. With `core.ignoreCase`,
+/// a path matches an index entry that differs only in case.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `paths` - Paths relative to the worktree root
+/// * `options` - Conflict handling
+pub fn checkout_paths(
+    repo: &Repository,
+    paths: &[impl AsRef<[u8]>],
+    options: &CheckoutOptions,
+) -> Result<()> {
+    let mut index = repo.read_index()?;
+    let mut worktree = repo.worktree()?;
+    let mut written = Vec::new();
+    let style = match options.conflict {
+        Some(style) => Some(style),
+        None if options.merge => Some(
+            repo.config()?
+                .get("merge.conflictStyle")
+                .map(ConflictStyle::from_str)
+                .transpose()?
+                .unwrap_or_default(),
+        ),
+        None => None,
+    };
+
+    for path in paths {
+        let path = path.as_ref();
+        let file = path_from_bytes(path)?;
+
+        if let Some(conflict) = index.conflicts().find(|c| c.path == path) {
+            let Some(style) = style else {
+                anyhow::bail!("path '{}' is unmerged", quote::display(path));
+            };
+            let data = remerge(repo, &conflict, style)?;
+            let executable = conflict
+                .ours
+                .is_some_and(|e| e.mode == FileMode::Executable);
+            worktree.write(file, &data)?;
+            worktree.set_executable(file, executable)?;
+            continue;
+        }
+
+        let entry = match index.get(path) {
+            Some(entry) if !entry.skip_worktree => entry.clone(),
+            _ => anyhow::bail!(
+                "pathspec '{}' did not match any file(s) known to oxid",
+                quote::display(path)
+            ),
+        };
+        // Write under the index's spelling of the path
+        let file = path_from_bytes(&entry.path)?;
+        checkout_entry(repo, &mut worktree, file, &entry)?;
+        written.push(file.to_path_buf());
+
+        // Record the new stat data so the file doesn't look modified
+        if let (Some(entry), Ok(metadata)) = (
+            index.get_mut(&entry.path),
+            fs::symlink_metadata(worktree.root().join(file)),
+        ) {
+            entry.set_stat(&metadata);
+        }
+    }
+
+    if index.ignore_case() {
+        warn_case_collisions(&written);
+    }
+    repo.write_index(&index)
+}
+
+/// Restore files in the working tree from the index
+///
+/// # Arguments
+///
+/// * `paths` - Files to restore
+/// * `options` - Conflict handling
+pub fn run(paths: &[String], options: &CheckoutOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let paths = paths
+        .iter()
+        .map(|p| repo.index_path_of(Path::new(p)))
+        .collect::<Result<Vec<_>>>()?;
+    checkout_paths(&repo, &paths, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::testing::store;
+
+    fn conflicted_repo() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = Index::new();
+        for (stage, content) in [
+            (1, &b"a\nb\nc\n"[..]),
+            (2, b"a\nours\nc\n"),
+            (3, b"a\ntheirs\nc\n"),
+        ] {
+            let mut entry =
+                IndexEntry::new("f.txt", FileMode::Regular, store(&repo, content).unwrap());
+            entry.stage = stage;
+            index.add(entry);
+        }
+        index.write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("f.txt"), b"resolved\n").unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn test_checkout_conflict_diff3() {
+        let (dir, repo) = conflicted_repo();
+        let options = CheckoutOptions {
+            conflict: Some(ConflictStyle::Diff3),
+            merge: false,
+        };
+        checkout_paths(&repo, &["f.txt".to_string()], &options).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("f.txt")).unwrap(),
+            "a\n<<<<<<< ours\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+
+        // The index still records the conflict
+        let index = Index::read(repo.index_path()).unwrap();
+        assert!(index.is_conflicted("f.txt"));
+    }
+
+    #[test]
+    fn test_checkout_unmerged_without_conflict_fails() {
+        let (dir, repo) = conflicted_repo();
+        let result = checkout_paths(&repo, &["f.txt".to_string()], &CheckoutOptions::default());
+        assert!(result.is_err());
+        assert_eq!(fs::read(dir.path().join("f.txt")).unwrap(), b"resolved\n");
+    }
+
+    #[test]
+    fn test_checkout_restores_index_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = Index::new();
+        index.add(IndexEntry::new(
+            "a.txt",
+            FileMode::Regular,
+            store(&repo, b"staged\n").unwrap(),
+        ));
+        let mut hidden =
+            IndexEntry::new("skip.txt", FileMode::Regular, store(&repo, b"x\n").unwrap());
+        hidden.skip_worktree = true;
+        index.add(hidden);
+        index.write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"edited\n").unwrap();
+
+        checkout_paths(&repo, &["a.txt".to_string()], &CheckoutOptions::default()).unwrap();
+        assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"staged\n");
+        let index = Index::read(repo.index_path()).unwrap();
+        assert_eq!(index.get("a.txt").unwrap().size, 7);
+
+        // Skip-worktree entries are not checked out
+        assert!(checkout_paths(
+            &repo,
+            &["skip.txt".to_string()],
+            &CheckoutOptions::default()
+        )
+        .is_err());
+        assert!(!dir.path().join("skip.txt").exists());
+    }
+}
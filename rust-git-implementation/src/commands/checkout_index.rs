@@ -0,0 +1,306 @@
+//! Copy files from the index to the working tree
+
+use crate::commands::checkout::{checkout_entry, warn_case_collisions};
+use crate::index::IndexEntry;
+use crate::objects::{Blob, FileMode, GitObject};
+use crate::quote;
+use crate::repository::Repository;
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+/// Options for `checkout-index`
+#[derive(Debug, Clone, Default)]
+pub struct CheckoutIndexOptions {
+    /// `-a`: check out every entry in the index
+    pub all: bool,
+    /// `-f`: overwrite existing files
+    pub force: bool,
+    /// `-u`: record the new stat data in the index
+    pub update_stat: bool,
+    /// `--prefix`: string prepended to every written path
+    pub prefix: Option<String>,
+    /// `--stage`: check out this conflict stage instead of stage 0
+    pub stage: Option<u8>,
+}
+
+/// What happened to a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The file was written
+    Written,
+    /// The file already matches the index
+    UpToDate,
+    /// A different file exists and `-f` wasn't given
+    Exists,
+    /// The path has no entry at the requested stage
+    Unmerged,
+    /// The path isn't in the index
+    Unknown,
+}
+
+/// Whether the worktree file already has the entry's content
+///
+/// # Arguments
+///
+/// * `worktree` - The working tree
+/// * `file` - The file, relative to the worktree root
+/// * `entry` - The index entry to compare with
+pub fn is_up_to_date(worktree: &dyn WorkTree, file: &Path, entry: &IndexEntry) -> bool {
+    let hash = if entry.mode == FileMode::Symlink {
+        worktree
+            .read_link(file)
+            .and_then(|target| Blob::new(target).hash())
+    } else {
+        worktree.hash_file(file)
+    };
+    hash.is_ok_and(|hash| hash == entry.hash)
+}
+
+/// Check out index entries
+///
+/// Returns each requested path with its outcome. Skip-worktree entries are
+/// left alone.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `paths` - Index paths to check out; ignored with `-a`
+/// * `options` - Which entries to write and where
+pub fn checkout_index(
+    repo: &Repository,
+    paths: &[impl AsRef<[u8]>],
+    options: &CheckoutIndexOptions,
+) -> Result<Vec<(Vec<u8>, Outcome)>> {
+    let mut index = repo.read_index()?;
+    let mut worktree = repo.worktree()?;
+    let stage = options.stage.unwrap_or(0);
+    let prefix = options.prefix.as_deref().unwrap_or("");
+
+    let requested: Vec<Vec<u8>> = if options.all {
+        index
+            .entries()
+            .iter()
+            .filter(|e| e.stage == stage && !e.skip_worktree)
+            .map(|e| e.path.clone())
+            .collect()
+    } else {
+        paths.iter().map(|path| path.as_ref().to_vec()).collect()
+    };
+
+    let mut outcomes = Vec::new();
+    let mut written = Vec::new();
+    for path in requested {
+        let entry = match stage {
+            0 => index.get(&path),
+            _ => index
+                .entries()
+                .iter()
+                .find(|e| e.path == path && e.stage == stage),
+        };
+        let Some(entry) = entry else {
+            let outcome = if index.is_conflicted(&path) {
+                Outcome::Unmerged
+            } else {
+                Outcome::Unknown
+            };
+            outcomes.push((path, outcome));
+            continue;
+        };
+        if entry.skip_worktree {
+            continue;
+        }
+
+        let target = [prefix.as_bytes(), &entry.path].concat();
+        let file = path_from_bytes(&target)?;
+        if worktree.exists(file) && !options.force {
+            let outcome = if prefix.is_empty() && is_up_to_date(&worktree, file, entry) {
+                Outcome::UpToDate
+            } else {
+                Outcome::Exists
+            };
+            outcomes.push((path, outcome));
+            continue;
+        }
+
+        checkout_entry(repo, &mut worktree, file, entry)?;
+        written.push(entry.path.clone());
+        outcomes.push((path, Outcome::Written));
+    }
+
+    if index.ignore_case() {
+        let files = written
+            .iter()
+            .map(|path| Ok(path_from_bytes(&[prefix.as_bytes(), path].concat())?.to_path_buf()))
+            .collect::<Result<Vec<_>>>()?;
+        warn_case_collisions(&files);
+    }
+
+    // Stat data only makes sense for files at their worktree location
+    if options.update_stat && prefix.is_empty() && stage == 0 {
+        for path in &written {
+            if let (Some(entry), Ok(metadata)) = (
+                index.get_mut(path),
+                fs::symlink_metadata(worktree.root().join(path_from_bytes(path)?)),
+            ) {
+                entry.set_stat(&metadata);
+            }
+        }
+        repo.write_index(&index)?;
+    }
+
+    Ok(outcomes)
+}
+
+/// Copy files from the index to the working tree
+///
+/// # Arguments
+///
+/// * `paths` - Files to check out
+/// * `options` - Which entries to write and where
+pub fn run(paths: &[String], options: &CheckoutIndexOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let paths = paths
+        .iter()
+        .map(|p| repo.index_path_of(Path::new(p)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut failed = false;
+    for (path, outcome) in checkout_index(&repo, &paths, options)? {
+        let path = quote::display(&path);
+        let message = match outcome {
+            Outcome::Written | Outcome::UpToDate => continue,
+            Outcome::Exists => format!("{} already exists, no checkout", path),
+            Outcome::Unmerged => format!("{} is unmerged", path),
+            Outcome::Unknown => format!("{} is not in the cache", path),
+        };
+        eprintln!("oxid checkout-index: {}", message);
+        failed = true;
+    }
+    if failed {
+        anyhow::bail!("some files could not be checked out");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Index;
+    use crate::testing::store;
+
+    fn setup() -> (tempfile::TempDir, Repository) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = Index::new();
+        index.add(IndexEntry::new(
+            "a.txt",
+            FileMode::Regular,
+            store(&repo, b"a\n").unwrap(),
+        ));
+        index.add(IndexEntry::new(
+            "dir/b.sh",
+            FileMode::Executable,
+            store(&repo, b"#!/bin/sh\n").unwrap(),
+        ));
+        for (stage, content) in [(2, &b"ours\n"[..]), (3, b"theirs\n")] {
+            let mut entry =
+                IndexEntry::new("c.txt", FileMode::Regular, store(&repo, content).unwrap());
+            entry.stage = stage;
+            index.add(entry);
+        }
+        index.write(repo.index_path()).unwrap();
+        (dir, repo)
+    }
+
+    #[test]
+    fn test_checkout_all_with_prefix() {
+        let (dir, repo) = setup();
+        let options = CheckoutIndexOptions {
+            all: true,
+            prefix: Some("export/".to_string()),
+            ..Default::default()
+        };
+        let outcomes = checkout_index(&repo, &[] as &[&str], &options).unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(
+            fs::read(dir.path().join("export/dir/b.sh")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+        assert!(!dir.path().join("a.txt").exists());
+        assert!(!dir.path().join("export/c.txt").exists());
+    }
+
+    #[test]
+    fn test_checkout_stage_and_existing_files() {
+        let (dir, repo) = setup();
+        fs::write(dir.path().join("a.txt"), b"a\n").unwrap();
+
+        let paths = vec!["a.txt".to_string(), "c.txt".to_string()];
+        let outcomes = checkout_index(&repo, &paths, &Default::default()).unwrap();
+        assert_eq!(
+            outcomes,
+            vec![
+                (b"a.txt".to_vec(), Outcome::UpToDate),
+                (b"c.txt".to_vec(), Outcome::Unmerged)
+            ]
+        );
+
+        let options = CheckoutIndexOptions {
+            stage: Some(3),
+            ..Default::default()
+        };
+        checkout_index(&repo, &["c.txt".to_string()], &options).unwrap();
+        assert_eq!(fs::read(dir.path().join("c.txt")).unwrap(), b"theirs\n");
+
+        // Existing files are only replaced with -f
+        fs::write(dir.path().join("a.txt"), b"edited\n").unwrap();
+        let outcomes = checkout_index(&repo, &paths[..1], &Default::default()).unwrap();
+        assert_eq!(outcomes[0].1, Outcome::Exists);
+        let options = CheckoutIndexOptions {
+            force: true,
+            update_stat: true,
+            ..Default::default()
+        };
+        checkout_index(&repo, &paths[..1], &options).unwrap();
+        assert_eq!(fs::read(dir.path().join("a.txt")).unwrap(), b"a\n");
+        let index = Index::read(repo.index_path()).unwrap();
+        assert_eq!(index.get("a.txt").unwrap().size, 2);
+    }
+
+    #[test]
+    fn test_checkout_refuses_invalid_paths() {
+        let (dir, repo) = setup();
+        let mut index = Index::read(repo.index_path()).unwrap();
+        let hash = store(&repo, b"#!/bin/sh\n").unwrap();
+        index.add(IndexEntry::new(
+            ".git/hooks/pre-commit",
+            FileMode::Executable,
+            hash,
+        ));
+        index.write(repo.index_path()).unwrap();
+
+        let paths = vec![".git/hooks/pre-commit".to_string()];
+        assert!(checkout_index(&repo, &paths, &Default::default()).is_err());
+        assert!(!dir.path().join(".git/hooks/pre-commit").exists());
+    }
+
+    #[test]
+    fn test_checkout_ignoring_case() {
+        let (dir, repo) = setup();
+        let paths = vec!["A.TXT".to_string()];
+        let outcomes = checkout_index(&repo, &paths, &Default::default()).unwrap();
+        assert_eq!(outcomes[0].1, Outcome::Unknown);
+
+        let config = dir.path().join(".git/config");
+        let mut text = fs::read_to_string(&config).unwrap();
+        text.push_str("[core]\n\tignoreCase = true\n");
+        fs::write(&config, text).unwrap();
+        let outcomes = checkout_index(&repo, &paths, &Default::default()).unwrap();
+        assert_eq!(outcomes, vec![(b"A.TXT".to_vec(), Outcome::Written)]);
+        // The file keeps the index's spelling
+        assert!(dir.path().join("a.txt").exists());
+    }
+}
@@ -0,0 +1,226 @@
+//! Create a commit object from a tree
+
+use crate::encoding;
+use crate::gpg::{GpgSigner, Signer};
+use crate::ident::{self, Role};
+use crate::objects::{Commit, GitObject, ObjectType};
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::io::Read;
+
+/// Options for `commit-tree`
+#[derive(Debug, Clone, Default)]
+pub struct CommitTreeOptions {
+    /// `-p`: parent commits, in order
+    pub parents: Vec<String>,
+    /// `-m`: message paragraphs
+    pub messages: Vec<String>,
+    /// `-F`: files to read the message from (`-` for stdin)
+    pub files: Vec<String>,
+    /// `-S` (`Some(true)`) or `--no-gpg-sign` (`Some(false)`); defaults
+    /// to `commit.gpgSign`
+    pub gpg_sign: Option<bool>,
+    /// Key given with `-S<keyid>`
+    pub key_id: Option<String>,
+}
+
+/// Build the commit message from `-m` and `-F` arguments
+///
+/// Each `-m` becomes its own paragraph, as with `git commit-tree`; `-F`
+/// contents are used as they are. Returns `None` if neither was given.
+///
+/// # Arguments
+///
+/// * `options` - The `-m` and `-F` values
+/// * `stdin` - Reader used for `-F -`
+pub fn build_message(options: &CommitTreeOptions, stdin: &mut dyn Read) -> Result<Option<String>> {
+    if options.messages.is_empty() && options.files.is_empty() {
+        return Ok(None);
+    }
+
+    let mut message = String::new();
+    for paragraph in &options.messages {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(paragraph);
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+    }
+    for file in &options.files {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        if file == "-" {
+            stdin.read_to_string(&mut message)?;
+        } else {
+            message.push_str(
+                &std::fs::read_to_string(file)
+                    .with_context(|| format!("could not read log file '{}'", file))?,
+            );
+        }
+    }
+    Ok(Some(message))
+}
+
+/// Check that an object exists and has the expected type
+fn expect_type(repo: &Repository, hash: &str, expected: ObjectType) -> Result<()> {
+    if !repo.has_object(hash) {
+        anyhow::bail!("not a valid object name {}", hash);
+    }
+    let (kind, _) = repo.object_header(hash)?;
+    if kind != expected {
+        anyhow::bail!(
+            "{} is a {}, not a {}",
+            hash,
+            kind.as_str(),
+            expected.as_str()
+        );
+    }
+    Ok(())
+}
+
+/// Write a commit object for `tree` and return its hash
+///
+/// Author and committer come from the environment and configuration; see
+/// [`ident`](crate::ident). The commit is stored in `i18n.commitEncoding`.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to write to
+/// * `tree` - Hash of the tree to commit
+/// * `parents` - Hashes of the parent commits
+/// * `message` - The full commit message
+/// * `signer` - Signs the commit, if given
+pub fn commit_tree(
+    repo: &Repository,
+    tree: &str,
+    parents: &[String],
+    message: String,
+    signer: Option<&dyn Signer>,
+) -> Result<String> {
+    expect_type(repo, tree, ObjectType::Tree)?;
+    for parent in parents {
+        expect_type(repo, parent, ObjectType::Commit)?;
+    }
+
+    let config = repo.config()?;
+    let mut extra_headers = Vec::new();
+    let commit_encoding = encoding::commit_encoding(&config);
+    if !encoding::is_utf8(commit_encoding) {
+        extra_headers.push(("encoding".to_string(), commit_encoding.to_string()));
+    }
+    let mut commit = Commit {
+        tree: tree.to_string(),
+        parents: parents.to_vec(),
+        author: ident::ident(&config, Role::Author)?,
+        committer: ident::ident(&config, Role::Committer)?,
+        message,
+        extra_headers,
+        gpgsig: None,
+    };
+    if let Some(signer) = signer {
+        commit.sign(signer)?;
+    }
+    let hash = commit.hash()?;
+    repo.write_object(&hash, &commit.to_bytes()?)?;
+    Ok(hash)
+}
+
+/// Create a commit from a tree and print its hash
+///
+/// Without `-m` or `-F`, the message is read from stdin.
+///
+/// # Arguments
+///
+/// * `tree` - Hash of the tree to commit
+/// * `options` - Parents, message sources and signing
+pub fn run(tree: &str, options: &CommitTreeOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let mut stdin = std::io::stdin();
+    let message = match build_message(options, &mut stdin)? {
+        Some(message) => message,
+        None => {
+            let mut message = String::new();
+            stdin.read_to_string(&mut message)?;
+            message
+        }
+    };
+    let config = repo.config()?;
+    let sign = match options.gpg_sign {
+        Some(sign) => sign,
+        None => config.get_bool("commit.gpgSign")?.unwrap_or(false),
+    };
+    let signer = if sign {
+        Some(GpgSigner::from_config(&config, options.key_id.as_deref())?)
+    } else {
+        None
+    };
+    let signer = signer.as_ref().map(|signer| signer as &dyn Signer);
+    println!(
+        "{}",
+        commit_tree(&repo, tree, &options.parents, message, signer)?
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Blob;
+
+    #[test]
+    fn test_build_message() {
+        let options = CommitTreeOptions {
+            messages: vec!["subject".to_string(), "body\n".to_string()],
+            files: vec!["-".to_string()],
+            ..Default::default()
+        };
+        let message = build_message(&options, &mut &b"from stdin\n"[..]).unwrap();
+        assert_eq!(message.unwrap(), "subject\n\nbody\n\nfrom stdin\n");
+
+        let none = build_message(&CommitTreeOptions::default(), &mut &b""[..]).unwrap();
+        assert!(none.is_none());
+    }
+
+    #[test]
+    fn test_commit_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = std::fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        std::fs::write(
+            repo.git_dir.join("config"),
+            format!("{}[user]\n\tname = T\n\temail = t@example.com\n", config),
+        )
+        .unwrap();
+
+        // The empty tree
+        let tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        repo.write_object(tree, b"tree 0\0").unwrap();
+        let root = commit_tree(&repo, tree, &[], "root\n".to_string(), None).unwrap();
+        let child = commit_tree(
+            &repo,
+            tree,
+            std::slice::from_ref(&root),
+            "child\n".to_string(),
+            None,
+        )
+        .unwrap();
+
+        let commit = Commit::parse(&repo.read_object(&child).unwrap()).unwrap();
+        assert_eq!(commit.tree, tree);
+        assert_eq!(commit.parents, vec![root.clone()]);
+        assert_eq!(commit.message, "child\n");
+
+        // Parents must be commits and the tree must be a tree
+        let blob = Blob::new(&b"x"[..]);
+        let blob_hash = blob.hash().unwrap();
+        repo.write_object(&blob_hash, &blob.to_bytes().unwrap())
+            .unwrap();
+        let blob_parent = std::slice::from_ref(&blob_hash);
+        assert!(commit_tree(&repo, tree, blob_parent, String::new(), None).is_err());
+        assert!(commit_tree(&repo, &root, &[], String::new(), None).is_err());
+        assert!(commit_tree(&repo, &"1".repeat(40), &[], String::new(), None).is_err());
+    }
+}
@@ -0,0 +1,48 @@
+//! Cross-check a repository against Git's rules and Git itself
+
+use crate::compat;
+use crate::repository::Repository;
+use anyhow::Result;
+
+/// Verify objects and refs of the repository at `path`
+///
+/// Every loose object is re-hashed and, for trees and commits, checked for
+/// canonical formatting. Refs are compared with `git for-each-ref` if Git
+/// is installed. Fails if any problem is found.
+///
+/// # Arguments
+///
+/// * `path` - Path inside the repository to check
+pub fn run(path: &str) -> Result<()> {
+    let repo = Repository::open_snapshot(path)?;
+    let (count, mut problems) = compat::check_objects(&repo)?;
+    println!("checked {} loose objects", count);
+
+    let packs = std::fs::read_dir(repo.common_dir.join("objects/pack"))
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "pack"))
+                .count()
+        })
+        .unwrap_or(0);
+    if packs > 0 {
+        println!("warning: {} packfiles not checked", packs);
+    }
+
+    let refs = crate::refs::read_refs(&repo.git_dir)?;
+    match compat::git_refs(&repo.git_dir)? {
+        Some(git_refs) => {
+            println!("compared {} refs with git for-each-ref", refs.len());
+            problems.extend(compat::compare_refs(&refs, &git_refs));
+        }
+        None => println!("git not found, skipping ref comparison"),
+    }
+
+    for problem in &problems {
+        println!("error: {}", problem);
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("{} problems found", problems.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,299 @@
+//! Compare the working tree with the index in Git's raw diff format
+
+use crate::index::{Index, IndexEntry};
+use crate::objects::{Blob, FileMode, GitObject};
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::status::{self, FileChange};
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::io::Write;
+
+/// The all-zero hash used for content that hasn't been hashed
+pub const NULL_HASH: &str = "0000000000000000000000000000000000000000";
+
+/// One line of raw diff output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawChange {
+    /// Mode before, `None` for a creation or an unmerged path
+    pub old_mode: Option<FileMode>,
+    /// Mode after, `None` for a deletion
+    pub new_mode: Option<FileMode>,
+    pub old_hash: String,
+    /// Worktree files aren't hashed, so this is usually all zeros
+    pub new_hash: String,
+    /// `M`, `A`, `D`, `T` (type change) or `U`
+    pub status: char,
+    pub path: Vec<u8>,
+}
+
+/// How to print raw changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RawFormat {
+    /// `:<old mode> <new mode> <old hash> <new hash> <status>\t<path>`
+    #[default]
+    Raw,
+    /// `<status>\t<path>`
+    NameStatus,
+    /// `<path>`
+    NameOnly,
+}
+
+/// Format changes for output
+///
+/// # Arguments
+///
+/// * `changes` - The changes to print
+/// * `format` - Which fields to show
+/// * `paths` - How to quote paths; with [`PathFormat::Nul`] (`-z`), NUL
+///   also replaces the tab before each path
+pub fn format_raw(changes: &[RawChange], format: RawFormat, paths: PathFormat) -> Vec<u8> {
+    let sep = if paths == PathFormat::Nul { '\0' } else { '\t' };
+    let mode = |m: Option<FileMode>| m.map_or("000000".to_string(), |m| m.as_str().to_string());
+
+    let mut out = Vec::new();
+    for change in changes {
+        match format {
+            RawFormat::Raw => out.extend_from_slice(
+                format!(
+                    ":{:0>6} {:0>6} {} {} {}{}",
+                    mode(change.old_mode),
+                    mode(change.new_mode),
+                    change.old_hash,
+                    change.new_hash,
+                    change.status,
+                    sep
+                )
+                .as_bytes(),
+            ),
+            RawFormat::NameStatus => {
+                out.extend_from_slice(format!("{}{}", change.status, sep).as_bytes())
+            }
+            RawFormat::NameOnly => {}
+        }
+        out.extend_from_slice(&paths.path(&change.path));
+        out.push(paths.terminator());
+    }
+    out
+}
+
+/// Compare the working tree with the index
+///
+/// Unmerged paths get a `U` line, followed by a comparison against "our"
+/// stage (stage 2) when the file exists.
+///
+/// # Arguments
+///
+/// * `index` - The index
+/// * `worktree` - The working tree
+/// * `file_mode` - Whether the executable bit is trusted (`core.fileMode`)
+/// * `symlinks` - Whether symlinks are supported (`core.symlinks`)
+pub fn diff_files(
+    index: &Index,
+    worktree: &dyn WorkTree,
+    file_mode: bool,
+    symlinks: bool,
+) -> Result<Vec<RawChange>> {
+    let content: HashMap<&[u8], FileChange> =
+        status::worktree_changes(index, worktree, file_mode, symlinks)?
+            .into_iter()
+            .map(|(entry, change)| (entry.path.as_slice(), change))
+            .collect();
+    let conflicts: HashMap<&[u8], Option<&IndexEntry>> =
+        index.conflicts().map(|c| (c.path, c.ours)).collect();
+
+    let mut changes = Vec::new();
+    let mut last_unmerged = None;
+    for entry in index.entries() {
+        let path = path_from_bytes(&entry.path)?;
+
+        if entry.stage != 0 {
+            if last_unmerged == Some(entry.path.as_slice()) {
+                continue;
+            }
+            last_unmerged = Some(entry.path.as_slice());
+            let exists = worktree.exists(path);
+            let new_mode = if exists {
+                Some(status::worktree_mode(
+                    worktree, path, entry, file_mode, symlinks,
+                )?)
+            } else {
+                None
+            };
+            changes.push(RawChange {
+                old_mode: None,
+                new_mode,
+                old_hash: NULL_HASH.to_string(),
+                new_hash: NULL_HASH.to_string(),
+                status: 'U',
+                path: entry.path.clone(),
+            });
+
+            if let (Some(Some(ours)), Some(new_mode)) =
+                (conflicts.get(entry.path.as_slice()), new_mode)
+            {
+                let hash = if new_mode == FileMode::Symlink {
+                    Blob::new(worktree.read_link(path)?).hash()?
+                } else {
+                    worktree.hash_file(path)?
+                };
+                if hash != ours.hash || new_mode != ours.mode {
+                    changes.push(RawChange {
+                        old_mode: Some(ours.mode),
+                        new_mode: Some(new_mode),
+                        old_hash: ours.hash.clone(),
+                        new_hash: NULL_HASH.to_string(),
+                        status: 'M',
+                        path: entry.path.clone(),
+                    });
+                }
+            }
+            continue;
+        }
+
+        let change = match content.get(entry.path.as_slice()) {
+            Some(FileChange::Deleted) => RawChange {
+                old_mode: Some(entry.mode),
+                new_mode: None,
+                old_hash: entry.hash.clone(),
+                new_hash: NULL_HASH.to_string(),
+                status: 'D',
+                path: entry.path.clone(),
+            },
+            Some(FileChange::Added) => RawChange {
+                old_mode: None,
+                new_mode: Some(status::worktree_mode(
+                    worktree, path, entry, file_mode, symlinks,
+                )?),
+                old_hash: NULL_HASH.to_string(),
+                new_hash: NULL_HASH.to_string(),
+                status: 'A',
+                path: entry.path.clone(),
+            },
+            // Renames are only detected between HEAD and the index
+            Some(FileChange::Modified | FileChange::Renamed) | None => {
+                if entry.assume_valid || entry.skip_worktree {
+                    continue;
+                }
+                let new_mode = status::worktree_mode(worktree, path, entry, file_mode, symlinks)?;
+                if !content.contains_key(entry.path.as_slice()) && new_mode == entry.mode {
+                    continue;
+                }
+                RawChange {
+                    old_mode: Some(entry.mode),
+                    new_mode: Some(new_mode),
+                    old_hash: entry.hash.clone(),
+                    new_hash: NULL_HASH.to_string(),
+                    status: 'M',
+                    path: entry.path.clone(),
+                }
+            }
+        };
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+/// Show worktree changes relative to the index
+///
+/// # Arguments
+///
+/// * `format` - Raw, name-status or name-only output
+/// * `nul` - Terminate fields with NUL (`-z`)
+pub fn run(format: RawFormat, nul: bool) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let config = repo.config()?;
+    let index = Index::read(repo.index_path())?;
+    let changes = diff_files(
+        &index,
+        &repo.worktree()?,
+        config.get_bool("core.fileMode")?.unwrap_or(true),
+        config.get_bool("core.symlinks")?.unwrap_or(true),
+    )?;
+    let paths = PathFormat::from_config(&config, nul)?;
+    std::io::stdout().write_all(&format_raw(&changes, format, paths))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{stage, stage_as};
+    use crate::worktree::MemoryWorkTree;
+    use std::path::Path;
+
+    #[test]
+    fn test_diff_files() {
+        let mut index = Index::new();
+        stage(&mut index, "a", b"a\n").unwrap();
+        stage(&mut index, "b", b"b\n").unwrap();
+        stage_as(&mut index, "c", FileMode::Regular, b"base\n", 1).unwrap();
+        stage_as(&mut index, "c", FileMode::Regular, b"main\n", 2).unwrap();
+        stage_as(&mut index, "c", FileMode::Regular, b"side\n", 3).unwrap();
+        stage(&mut index, "same", b"same\n").unwrap();
+        stage(&mut index, "x.sh", b"x\n").unwrap();
+
+        let mut worktree = MemoryWorkTree::new();
+        worktree.write(Path::new("a"), b"a\nmod\n").unwrap();
+        worktree.write(Path::new("c"), b"<<<<<<<\n").unwrap();
+        worktree.write(Path::new("same"), b"same\n").unwrap();
+        worktree.write(Path::new("x.sh"), b"x\n").unwrap();
+        worktree.set_executable(Path::new("x.sh"), true).unwrap();
+
+        let changes = diff_files(&index, &worktree, true, true).unwrap();
+        let summary: Vec<(char, &[u8])> = changes
+            .iter()
+            .map(|c| (c.status, c.path.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ('M', &b"a"[..]),
+                ('D', b"b"),
+                ('U', b"c"),
+                ('M', b"c"),
+                ('M', b"x.sh")
+            ]
+        );
+        assert_eq!(
+            format_raw(&changes[4..], RawFormat::Raw, PathFormat::default()),
+            format!(
+                ":100644 100755 {} {} M\tx.sh\n",
+                Blob::new(&b"x\n"[..]).hash().unwrap(),
+                NULL_HASH
+            )
+            .as_bytes()
+        );
+        assert!(
+            format_raw(&changes[2..3], RawFormat::Raw, PathFormat::default())
+                .starts_with(b":000000 100644 0000000000000000000000000000000000000000 ")
+        );
+
+        // Without core.fileMode the executable bit is ignored
+        let changes = diff_files(&index, &worktree, false, true).unwrap();
+        assert!(!changes.iter().any(|c| c.path == b"x.sh"));
+    }
+
+    #[test]
+    fn test_format_name_status_nul() {
+        let change = RawChange {
+            old_mode: Some(FileMode::Regular),
+            new_mode: None,
+            old_hash: NULL_HASH.to_string(),
+            new_hash: NULL_HASH.to_string(),
+            status: 'D',
+            path: b"gone\tfile".to_vec(),
+        };
+        let changes = [change];
+        assert_eq!(
+            format_raw(&changes, RawFormat::NameStatus, PathFormat::Nul),
+            b"D\0gone\tfile\0"
+        );
+        assert_eq!(
+            format_raw(&changes, RawFormat::NameOnly, PathFormat::default()),
+            b"\"gone\\tfile\"\n"
+        );
+    }
+}
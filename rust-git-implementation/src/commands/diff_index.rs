@@ -0,0 +1,181 @@
+//! Compare a tree with the index or the working tree in Git's raw diff
+//! format
+
+use crate::commands::diff_files::{format_raw, RawChange, RawFormat, NULL_HASH};
+use crate::commands::diff_tree::{compare, tree_entries};
+use crate::index::Index;
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status::{self, FileChange, TreeFiles};
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+use std::io::Write;
+
+/// Options for `diff-index`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffIndexOptions {
+    /// Which fields to show
+    pub format: RawFormat,
+    /// `--cached`: compare with the index only, ignoring the working tree
+    pub cached: bool,
+    /// `-z`: end fields with NUL and don't quote paths
+    pub nul: bool,
+}
+
+/// Compare a tree with the index, or with the working tree
+///
+/// With a working tree, files whose content differs from the index get the
+/// all-zero hash, as they aren't hashed, and missing files count as
+/// deleted. Unmerged paths get a `U` line either way.
+///
+/// # Arguments
+///
+/// * `tree` - The files of the tree
+/// * `index` - The index
+/// * `worktree` - The working tree, or `None` for `--cached`
+/// * `file_mode` - Whether the executable bit is trusted (`core.fileMode`)
+/// * `symlinks` - Whether symlinks are supported (`core.symlinks`)
+pub fn diff_index(
+    tree: &TreeFiles,
+    index: &Index,
+    worktree: Option<&dyn WorkTree>,
+    file_mode: bool,
+    symlinks: bool,
+) -> Result<Vec<RawChange>> {
+    let content: HashMap<&[u8], FileChange> = match worktree {
+        Some(worktree) => status::worktree_changes(index, worktree, file_mode, symlinks)?
+            .into_iter()
+            .map(|(entry, change)| (entry.path.as_slice(), change))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let mut files = TreeFiles::new();
+    let mut unmerged = BTreeSet::new();
+    for entry in index.entries() {
+        if entry.stage != 0 {
+            unmerged.insert(entry.path.clone());
+            continue;
+        }
+        let Some(worktree) = worktree else {
+            files.insert(entry.path.clone(), (entry.mode, entry.hash.clone()));
+            continue;
+        };
+        let path = path_from_bytes(&entry.path)?;
+        let file = match content.get(entry.path.as_slice()) {
+            Some(FileChange::Deleted) => continue,
+            Some(_) => (
+                status::worktree_mode(worktree, path, entry, file_mode, symlinks)?,
+                NULL_HASH.to_string(),
+            ),
+            None if entry.assume_valid || entry.skip_worktree => (entry.mode, entry.hash.clone()),
+            None => (
+                status::worktree_mode(worktree, path, entry, file_mode, symlinks)?,
+                entry.hash.clone(),
+            ),
+        };
+        files.insert(entry.path.clone(), file);
+    }
+
+    let mut tree = tree.clone();
+    tree.retain(|path, _| !unmerged.contains(path));
+    let mut changes = compare(&tree, &files);
+    changes.extend(unmerged.into_iter().map(|path| RawChange {
+        old_mode: None,
+        new_mode: None,
+        old_hash: NULL_HASH.to_string(),
+        new_hash: NULL_HASH.to_string(),
+        status: 'U',
+        path,
+    }));
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Show changes between a tree and the index or working tree
+///
+/// # Arguments
+///
+/// * `tree` - A tree-ish revision
+/// * `options` - Which side to compare with and how to print
+pub fn run(tree: &str, options: &DiffIndexOptions) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let config = repo.config()?;
+    let tree = tree_entries(&repo, Some(&revision::resolve_tree(&repo, tree)?), true)?;
+    let index = Index::read(repo.index_path())?;
+    let worktree = repo.worktree()?;
+    let changes = diff_index(
+        &tree,
+        &index,
+        (!options.cached).then_some(&worktree as &dyn WorkTree),
+        config.get_bool("core.fileMode")?.unwrap_or(true),
+        config.get_bool("core.symlinks")?.unwrap_or(true),
+    )?;
+    let paths = PathFormat::from_config(&config, options.nul)?;
+    std::io::stdout().write_all(&format_raw(&changes, options.format, paths))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Blob, FileMode, GitObject};
+    use crate::testing::{stage, stage_as};
+    use crate::worktree::MemoryWorkTree;
+    use std::path::Path;
+
+    fn blob(data: &[u8]) -> String {
+        Blob::new(data).hash().unwrap()
+    }
+
+    #[test]
+    fn test_diff_index() {
+        let tree = TreeFiles::from([
+            (b"a".to_vec(), (FileMode::Regular, blob(b"a\n"))),
+            (b"b".to_vec(), (FileMode::Regular, blob(b"b\n"))),
+            (b"c".to_vec(), (FileMode::Regular, blob(b"c\n"))),
+            (b"gone".to_vec(), (FileMode::Regular, blob(b"gone\n"))),
+        ]);
+        let mut index = Index::new();
+        stage(&mut index, "a", b"a\n").unwrap();
+        stage(&mut index, "b", b"staged\n").unwrap();
+        stage_as(&mut index, "c", FileMode::Regular, b"ours\n", 2).unwrap();
+        stage_as(&mut index, "c", FileMode::Regular, b"theirs\n", 3).unwrap();
+        stage(&mut index, "new", b"new\n").unwrap();
+
+        let summary = |changes: &[RawChange]| -> Vec<(char, Vec<u8>, String)> {
+            changes
+                .iter()
+                .map(|c| (c.status, c.path.clone(), c.new_hash.clone()))
+                .collect()
+        };
+        let cached = diff_index(&tree, &index, None, true, true).unwrap();
+        assert_eq!(
+            summary(&cached),
+            vec![
+                ('M', b"b".to_vec(), blob(b"staged\n")),
+                ('U', b"c".to_vec(), NULL_HASH.to_string()),
+                ('D', b"gone".to_vec(), NULL_HASH.to_string()),
+                ('A', b"new".to_vec(), blob(b"new\n")),
+            ]
+        );
+
+        // Against the working tree, "a" is edited and "new" is missing
+        let mut worktree = MemoryWorkTree::new();
+        worktree.write(Path::new("a"), b"edited\n").unwrap();
+        worktree.write(Path::new("b"), b"staged\n").unwrap();
+        let changes = diff_index(&tree, &index, Some(&worktree), true, true).unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                ('M', b"a".to_vec(), NULL_HASH.to_string()),
+                ('M', b"b".to_vec(), blob(b"staged\n")),
+                ('U', b"c".to_vec(), NULL_HASH.to_string()),
+                ('D', b"gone".to_vec(), NULL_HASH.to_string()),
+            ]
+        );
+    }
+}
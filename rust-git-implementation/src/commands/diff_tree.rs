@@ -0,0 +1,246 @@
+//! Compare two trees in Git's raw diff format
+
+use crate::commands::diff_files::{format_raw, RawChange, RawFormat, NULL_HASH};
+use crate::objects::{FileMode, ObjectType};
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status::TreeFiles;
+use crate::tree_walk::TreeWalk;
+use anyhow::Result;
+use std::io::Write;
+
+/// Options for `diff-tree`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffTreeOptions {
+    /// Which fields to show
+    pub format: RawFormat,
+    /// `-r`: compare files in subtrees instead of the subtrees themselves
+    pub recursive: bool,
+    /// `--root`: show a root commit as adding all its files
+    pub root: bool,
+    /// `-z`: end fields with NUL and don't quote paths
+    pub nul: bool,
+}
+
+/// The entries of a tree by path
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the trees
+/// * `tree` - Hash of the tree, or `None` for an empty one
+/// * `recursive` - List the files below subtrees instead of the subtrees
+pub fn tree_entries(repo: &Repository, tree: Option<&str>, recursive: bool) -> Result<TreeFiles> {
+    let Some(tree) = tree else {
+        return Ok(TreeFiles::new());
+    };
+    let walk = TreeWalk::new(repo, tree);
+    let walk = if recursive { walk } else { walk.max_depth(0) };
+    walk.map(|entry| entry.map(|entry| (entry.path, (entry.mode, entry.hash))))
+        .collect()
+}
+
+/// Compare two sets of entries
+///
+/// Changes come in Git's tree order, where a subtree sorts as if its name
+/// ended in `/`. An entry that turns from a tree into a file or back is
+/// reported as deleted and added; other changes of kind are `T`.
+///
+/// # Arguments
+///
+/// * `old` - Entries before
+/// * `new` - Entries after
+pub fn compare(old: &TreeFiles, new: &TreeFiles) -> Vec<RawChange> {
+    // The executable bit is the only change that keeps the kind
+    let kind = |mode: FileMode| match mode {
+        FileMode::Executable => FileMode::Regular,
+        mode => mode,
+    };
+    let change =
+        |path: &[u8], old: Option<&(FileMode, String)>, new: Option<&(FileMode, String)>| {
+            let status = match (old, new) {
+                (None, _) => 'A',
+                (_, None) => 'D',
+                (Some((old, _)), Some((new, _))) if kind(*old) != kind(*new) => 'T',
+                _ => 'M',
+            };
+            RawChange {
+                old_mode: old.map(|(mode, _)| *mode),
+                new_mode: new.map(|(mode, _)| *mode),
+                old_hash: old.map_or(NULL_HASH, |(_, hash)| hash).to_string(),
+                new_hash: new.map_or(NULL_HASH, |(_, hash)| hash).to_string(),
+                status,
+                path: path.to_vec(),
+            }
+        };
+
+    let mut changes = Vec::new();
+    for (path, before) in old {
+        match new.get(path) {
+            Some(after) if after == before => {}
+            Some(after) if (before.0 == FileMode::Tree) != (after.0 == FileMode::Tree) => {
+                changes.push(change(path, Some(before), None));
+                changes.push(change(path, None, Some(after)));
+            }
+            after => changes.push(change(path, Some(before), after)),
+        }
+    }
+    for (path, after) in new {
+        if !old.contains_key(path) {
+            changes.push(change(path, None, Some(after)));
+        }
+    }
+
+    changes.sort_by_cached_key(|c| {
+        let tree = c.new_mode.or(c.old_mode) == Some(FileMode::Tree);
+        [&c.path[..], if tree { b"/" } else { b"" }].concat()
+    });
+    changes
+}
+
+/// Compare two trees
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the trees
+/// * `old` - Hash of the old tree, or `None` to compare against nothing
+/// * `new` - Hash of the new tree
+/// * `recursive` - Compare files in subtrees instead of the subtrees
+pub fn diff_tree(
+    repo: &Repository,
+    old: Option<&str>,
+    new: &str,
+    recursive: bool,
+) -> Result<Vec<RawChange>> {
+    Ok(compare(
+        &tree_entries(repo, old, recursive)?,
+        &tree_entries(repo, Some(new), recursive)?,
+    ))
+}
+
+/// Compare two trees, or a commit with its first parent
+///
+/// Given one commit, its id is printed before the changes. A root commit
+/// shows nothing unless `--root` is given.
+///
+/// # Arguments
+///
+/// * `objects` - One commit, or two tree-ish revisions
+/// * `options` - Output options
+pub fn run(objects: &[String], options: &DiffTreeOptions) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let paths = PathFormat::from_config(&repo.config()?, options.nul)?;
+    let mut out = Vec::new();
+
+    let changes = match objects {
+        [commit] => {
+            let hash = revision::resolve(&repo, commit)?;
+            let (kind, hash) = revision::peel(&repo, &hash)?;
+            if kind != ObjectType::Commit {
+                anyhow::bail!("{} is a {}, not a commit", commit, kind.as_str());
+            }
+            let commit = repo.read_commit(&hash)?;
+            let parent = match commit.parents.first() {
+                Some(parent) => Some(repo.read_commit(parent)?.tree.clone()),
+                None if options.root => None,
+                None => return Ok(()),
+            };
+            out.extend_from_slice(hash.as_bytes());
+            out.push(paths.terminator());
+            diff_tree(&repo, parent.as_deref(), &commit.tree, options.recursive)?
+        }
+        [old, new] => diff_tree(
+            &repo,
+            Some(&revision::resolve_tree(&repo, old)?),
+            &revision::resolve_tree(&repo, new)?,
+            options.recursive,
+        )?,
+        _ => anyhow::bail!("diff-tree takes one commit or two trees"),
+    };
+
+    out.extend_from_slice(&format_raw(&changes, options.format, paths));
+    std::io::stdout().write_all(&out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_diff_tree() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder
+            .file("a.b", "x\n")
+            .file("a/one", "1\n")
+            .file("a/two", "2\n")
+            .file("gone", "gone\n");
+        let first = builder.commit("first").unwrap();
+        builder
+            .remove("gone")
+            .file("a/two", "two\n")
+            .file("a.b", "y\n")
+            .file("new", "new\n");
+        let second = builder.commit("second").unwrap();
+
+        let repo = builder.repo();
+        let tree = |commit: &str| repo.read_commit(commit).unwrap().tree.clone();
+        let (old, new) = (tree(&first), tree(&second));
+        let summary = |changes: Vec<RawChange>| -> Vec<(char, String)> {
+            changes
+                .into_iter()
+                .map(|c| (c.status, String::from_utf8(c.path).unwrap()))
+                .collect()
+        };
+
+        // "a.b" sorts before the tree "a", as in Git's tree order
+        let changes = diff_tree(repo, Some(&old), &new, false).unwrap();
+        assert_eq!(changes[1].old_mode, Some(FileMode::Tree));
+        assert_eq!(
+            summary(changes),
+            [('M', "a.b"), ('M', "a"), ('D', "gone"), ('A', "new")]
+                .map(|(s, p)| (s, p.to_string()))
+        );
+        assert_eq!(
+            summary(diff_tree(repo, Some(&old), &new, true).unwrap()),
+            [('M', "a.b"), ('M', "a/two"), ('D', "gone"), ('A', "new")]
+                .map(|(s, p)| (s, p.to_string()))
+        );
+        assert_eq!(diff_tree(repo, None, &old, true).unwrap().len(), 4);
+        assert!(diff_tree(repo, Some(&old), &old, true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_compare_changes_of_kind() {
+        let hash = |c: char| c.to_string().repeat(40);
+        let old = TreeFiles::from([
+            (b"dir".to_vec(), (FileMode::Tree, hash('1'))),
+            (b"link".to_vec(), (FileMode::Symlink, hash('2'))),
+            (b"x".to_vec(), (FileMode::Regular, hash('3'))),
+        ]);
+        let new = TreeFiles::from([
+            (b"dir".to_vec(), (FileMode::Regular, hash('1'))),
+            (b"link".to_vec(), (FileMode::Regular, hash('2'))),
+            (b"x".to_vec(), (FileMode::Executable, hash('3'))),
+        ]);
+        let changes = compare(&old, &new);
+        let summary: Vec<(char, &[u8])> = changes
+            .iter()
+            .map(|c| (c.status, c.path.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ('A', &b"dir"[..]),
+                ('D', b"dir"),
+                ('T', b"link"),
+                ('M', b"x")
+            ]
+        );
+        assert_eq!(
+            format_raw(&changes[3..], RawFormat::Raw, PathFormat::default()),
+            format!(":100644 100755 {0} {0} M\tx\n", hash('3')).as_bytes()
+        );
+    }
+}
@@ -0,0 +1,205 @@
+//! Show worktree changes with an external diff tool
+
+use crate::external_tool::{self, TempDir, ToolKind};
+use crate::index::{Index, IndexEntry};
+use crate::objects::FileMode;
+use crate::quote;
+use crate::repository::Repository;
+use crate::status::worktree_changes;
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+/// Options for `difftool`
+#[derive(Debug, Clone, Default)]
+pub struct DifftoolOptions {
+    /// `--tool`: tool to use instead of `diff.tool`
+    pub tool: Option<String>,
+    /// `--[no-]prompt`: ask before launching the tool for each file;
+    /// defaults to `difftool.prompt`
+    pub prompt: Option<bool>,
+    /// `--dir-diff`: compare all changed files at once as two directories
+    pub dir_diff: bool,
+}
+
+/// Launch the diff tool on every modified file in a repository
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `options` - Tool, prompting and directory mode
+pub fn difftool(repo: &Repository, options: &DifftoolOptions) -> Result<()> {
+    let config = repo.config()?;
+    let tool = external_tool::resolve(&config, ToolKind::Diff, options.tool.as_deref())?;
+    let worktree = repo.worktree()?;
+    let index = Index::read(repo.index_path())?;
+    let modified: Vec<&IndexEntry> = worktree_changes(
+        &index,
+        &worktree,
+        config.get_bool("core.fileMode")?.unwrap_or(true),
+        config.get_bool("core.symlinks")?.unwrap_or(true),
+    )?
+    .into_iter()
+    .map(|(entry, _)| entry)
+    .collect();
+    if modified.is_empty() {
+        return Ok(());
+    }
+
+    if options.dir_diff {
+        return dir_diff(repo, &tool, &worktree, &modified);
+    }
+
+    let prompt = match options.prompt {
+        Some(prompt) => prompt,
+        None => config.get_bool("difftool.prompt")?.unwrap_or(true),
+    };
+
+    for (i, entry) in modified.iter().enumerate() {
+        if prompt {
+            println!(
+                "\nViewing ({}/{}): '{}'",
+                i + 1,
+                modified.len(),
+                quote::display(&entry.path)
+            );
+            if !external_tool::confirm(&format!("Launch '{}'", tool.name))? {
+                continue;
+            }
+        }
+
+        let path = path_from_bytes(&entry.path)?;
+        let temp = TempDir::new("oxid-difftool")?;
+        let name = path.file_name().unwrap_or_default().to_os_string();
+        let local = temp.write(Path::new("staged").join(&name), &repo.read_staged(entry)?)?;
+        // The tool sees the real worktree file, so edits made in it stick
+        let mut remote = worktree.root().join(path);
+        if !remote.exists() {
+            remote = temp.write(Path::new("deleted").join(&name), b"")?;
+        }
+
+        let success = external_tool::run(&tool, &[("LOCAL", &local), ("REMOTE", &remote)])?;
+        if !success && tool.trust_exit_code {
+            anyhow::bail!("external diff tool '{}' exited with an error", tool.name);
+        }
+    }
+    Ok(())
+}
+
+/// Compare staged and worktree versions as two directory trees
+///
+/// Files edited in the right-hand directory are copied back to the worktree
+/// when the tool exits.
+fn dir_diff(
+    repo: &Repository,
+    tool: &external_tool::Tool,
+    worktree: &dyn WorkTree,
+    modified: &[&IndexEntry],
+) -> Result<()> {
+    let temp = TempDir::new("oxid-difftool")?;
+    let left = temp.path().join("left");
+    let right = temp.path().join("right");
+    fs::create_dir_all(&left)?;
+    fs::create_dir_all(&right)?;
+
+    let mut copied = Vec::new();
+    for entry in modified {
+        let path = path_from_bytes(&entry.path)?;
+        temp.write(Path::new("left").join(path), &repo.read_staged(entry)?)?;
+        if worktree.exists(path) && entry.mode != FileMode::Symlink {
+            let content = worktree.read(path)?;
+            temp.write(Path::new("right").join(path), &content)?;
+            copied.push((path, content));
+        }
+    }
+
+    let success = external_tool::run(tool, &[("LOCAL", &left), ("REMOTE", &right)])?;
+
+    let mut worktree = repo.worktree()?;
+    for (path, original) in copied {
+        let content = fs::read(right.join(path))?;
+        if content != original {
+            worktree.write(path, &content)?;
+        }
+    }
+
+    if !success && tool.trust_exit_code {
+        anyhow::bail!("external diff tool '{}' exited with an error", tool.name);
+    }
+    Ok(())
+}
+
+/// Show changes between the index and the worktree using an external tool
+///
+/// # Arguments
+///
+/// * `options` - Tool, prompting and directory mode
+pub fn run(options: &DifftoolOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    difftool(&repo, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{stage, store};
+
+    #[cfg(unix)]
+    #[test]
+    fn test_difftool_runs_configured_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        store(&repo, b"old\n").unwrap();
+        let mut index = Index::new();
+        stage(&mut index, "a.txt", b"old\n").unwrap();
+        index.write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"new\n").unwrap();
+
+        let log = dir.path().join("log");
+        fs::write(
+            repo.git_dir.join("config"),
+            format!(
+                "[difftool \"rec\"]\ncmd = cat \"$LOCAL\" \"$REMOTE\" >> {}\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+
+        let options = DifftoolOptions {
+            tool: Some("rec".to_string()),
+            prompt: Some(false),
+            dir_diff: false,
+        };
+        difftool(&repo, &options).unwrap();
+        assert_eq!(fs::read_to_string(&log).unwrap(), "old\nnew\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_dir_diff_copies_edits_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        store(&repo, b"old\n").unwrap();
+        let mut index = Index::new();
+        stage(&mut index, "d/a.txt", b"old\n").unwrap();
+        index.write(repo.index_path()).unwrap();
+        fs::create_dir(dir.path().join("d")).unwrap();
+        fs::write(dir.path().join("d/a.txt"), b"new\n").unwrap();
+
+        fs::write(
+            repo.git_dir.join("config"),
+            "[difftool \"edit\"]\ncmd = cp \"$LOCAL/d/a.txt\" \"$REMOTE/d/a.txt\"\n",
+        )
+        .unwrap();
+
+        let options = DifftoolOptions {
+            tool: Some("edit".to_string()),
+            prompt: None,
+            dir_diff: true,
+        };
+        difftool(&repo, &options).unwrap();
+        assert_eq!(fs::read(dir.path().join("d/a.txt")).unwrap(), b"old\n");
+    }
+}
@@ -0,0 +1,281 @@
+//! Fetch from a remote repository (`oxid fetch <url>`)
+//!
+//! `http://` URLs use the dumb HTTP protocol. Schemes with a transport
+//! registered through [`crate::remote`] use it, and other transports go
+//! through a remote helper (see [`crate::remote_helper`]). Branches are stored as remote-tracking refs
+//! (`refs/remotes/<name>/<branch>`), the remote's `HEAD` as the symbolic
+//! ref `refs/remotes/<name>/HEAD`, and tags under `refs/tags/`. Existing
+//! tags are never moved. Refs the remote advertises with names Git would
+//! reject (see [`crate::ref_name`]) are skipped with a warning.
+
+use crate::dumb_http::Remote;
+use crate::remote_helper::{Helper, RefValue};
+use crate::repository::Repository;
+use anyhow::Result;
+use std::collections::BTreeMap;
+
+/// What happened to one local ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefUpdate {
+    /// A branch seen for the first time
+    NewBranch,
+    /// A tag seen for the first time
+    NewTag,
+    /// A branch that moved; holds the old value
+    Updated(String),
+    /// A tag that exists locally with another value and was left alone
+    Rejected,
+}
+
+/// A ref the fetch looked at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetchedRef {
+    /// The remote's short name, e.g. `main`
+    pub remote: String,
+    /// The local ref's full name
+    pub local: String,
+    pub hash: String,
+    pub update: RefUpdate,
+}
+
+/// Fetch the branches and tags of a remote repository
+///
+/// Returns the refs that changed or were rejected, in remote ref order.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to fetch into
+/// * `url` - The remote repository's URL
+/// * `name` - Name for the remote-tracking refs, e.g. `origin`
+pub fn fetch(repo: &Repository, url: &str, name: &str) -> Result<Vec<FetchedRef>> {
+    let (refs, head) = if let Some(transport) = crate::remote::open(url) {
+        let mut transport = transport?;
+        let refs = valid_refs(transport.refs()?);
+        let wants: Vec<String> = refs.values().cloned().collect();
+        crate::remote::fetch(repo, transport.as_mut(), &wants)?;
+        (refs, transport.head()?)
+    } else {
+        match crate::remote_helper::transport_for(url) {
+            Some((transport, address)) => {
+                let helper = Helper::spawn(repo, transport, name, address)?;
+                fetch_with_helper(repo, helper)?
+            }
+            None => {
+                let remote = Remote::new(repo, url)?;
+                let refs = valid_refs(remote.refs()?);
+                remote.fetch(repo, &refs.values().cloned().collect::<Vec<_>>())?;
+                (refs, remote.head()?)
+            }
+        }
+    };
+
+    let local_refs = crate::refs::read_refs(&repo.git_dir)?;
+    let mut fetched = Vec::new();
+    for (remote_ref, hash) in &refs {
+        let (short, local, is_tag) = if let Some(branch) = remote_ref.strip_prefix("refs/heads/") {
+            (branch, format!("refs/remotes/{}/{}", name, branch), false)
+        } else if let Some(tag) = remote_ref.strip_prefix("refs/tags/") {
+            (tag, remote_ref.clone(), true)
+        } else {
+            continue;
+        };
+        let update = match local_refs.get(&local) {
+            Some(old) if old == hash => continue,
+            Some(_) if is_tag => RefUpdate::Rejected,
+            Some(old) => RefUpdate::Updated(old.clone()),
+            None if is_tag => RefUpdate::NewTag,
+            None => RefUpdate::NewBranch,
+        };
+        if update != RefUpdate::Rejected {
+            repo.write_ref(&local, hash)?;
+        }
+        fetched.push(FetchedRef {
+            remote: short.to_string(),
+            local,
+            hash: hash.clone(),
+            update,
+        });
+    }
+
+    if let Some(head) = head.filter(|head| crate::ref_name::is_valid(head)) {
+        if let Some(branch) = head.strip_prefix("refs/heads/") {
+            repo.write_ref(
+                &format!("refs/remotes/{}/HEAD", name),
+                &format!("ref: refs/remotes/{}/{}", name, branch),
+            )?;
+        }
+    }
+    Ok(fetched)
+}
+
+/// Drop the refs whose names aren't valid, warning about each
+fn valid_refs(refs: BTreeMap<String, String>) -> BTreeMap<String, String> {
+    refs.into_iter()
+        .filter(|(name, _)| {
+            let valid = crate::ref_name::is_valid(name);
+            if !valid {
+                eprintln!("warning: ignoring ref with broken name {}", name);
+            }
+            valid
+        })
+        .collect()
+}
+
+/// Fetch the branches and tags a remote helper lists
+///
+/// Returns the remote's refs and the branch its `HEAD` points to. Only
+/// objects missing locally are requested.
+fn fetch_with_helper(
+    repo: &Repository,
+    mut helper: Helper,
+) -> Result<(BTreeMap<String, String>, Option<String>)> {
+    let mut refs = BTreeMap::new();
+    let mut head = None;
+    for listed in helper.list(false)? {
+        match listed.value {
+            RefValue::Symref(target) if listed.name == "HEAD" => head = Some(target),
+            RefValue::Hash(hash)
+                if listed.name.starts_with("refs/heads/")
+                    || listed.name.starts_with("refs/tags/") =>
+            {
+                refs.insert(listed.name, hash);
+            }
+            _ => {}
+        }
+    }
+    let refs = valid_refs(refs);
+    let wanted: Vec<(String, String)> = refs
+        .iter()
+        .filter(|(_, hash)| !repo.has_object(hash))
+        .map(|(name, hash)| (hash.clone(), name.clone()))
+        .collect();
+    helper.fetch(&wanted)?;
+    helper.finish()?;
+    Ok((refs, head))
+}
+
+/// Format the ref updates the way `git fetch` reports them
+pub fn format_updates(url: &str, fetched: &[FetchedRef]) -> String {
+    if fetched.is_empty() {
+        return String::new();
+    }
+    let mut out = format!("From {}\n", url);
+    for fetched in fetched {
+        let local = fetched
+            .local
+            .strip_prefix("refs/remotes/")
+            .or(fetched.local.strip_prefix("refs/tags/"))
+            .unwrap_or(&fetched.local);
+        let line = match &fetched.update {
+            RefUpdate::NewBranch => format!(" * [new branch]      {} -> {}", fetched.remote, local),
+            RefUpdate::NewTag => format!(" * [new tag]         {} -> {}", fetched.remote, local),
+            RefUpdate::Updated(old) => format!(
+                "   {}..{}  {} -> {}",
+                &old[..7],
+                &fetched.hash[..7],
+                fetched.remote,
+                local
+            ),
+            RefUpdate::Rejected => format!(
+                " ! [rejected]        {} -> {}  (would clobber existing tag)",
+                fetched.remote, local
+            ),
+        };
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Fetch from a remote repository
+///
+/// # Arguments
+///
+/// * `url` - The remote repository's URL
+/// * `name` - Name for the remote-tracking refs
+pub fn run(url: &str, name: &str) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let fetched = fetch(&repo, url, name)?;
+    eprint!("{}", format_updates(url, &fetched));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::{register_transport, Transport};
+    use crate::testing::RepoBuilder;
+    use std::io::Read;
+
+    /// Advertises refs the local repository already has the objects of
+    struct Advertise(BTreeMap<String, String>);
+
+    impl Transport for Advertise {
+        fn refs(&mut self) -> Result<BTreeMap<String, String>> {
+            Ok(self.0.clone())
+        }
+
+        fn fetch(&mut self, _: &[String], _: &[String]) -> Result<Box<dyn Read + '_>> {
+            anyhow::bail!("nothing to fetch")
+        }
+    }
+
+    #[test]
+    fn test_fetch_ignores_broken_ref_names() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder.file("a", "a\n").commit("first").unwrap();
+        let refs = BTreeMap::from([
+            ("refs/heads/main".to_string(), commit.clone()),
+            (
+                "refs/tags/../../../../victim-owned.txt".to_string(),
+                commit.clone(),
+            ),
+            ("refs/tags/v1.lock".to_string(), commit.clone()),
+        ]);
+        register_transport("test-broken-refs", move |_| {
+            Ok(Box::new(Advertise(refs.clone())))
+        });
+
+        let repo = builder.repo();
+        let fetched = fetch(repo, "test-broken-refs://remote", "origin").unwrap();
+        let locals: Vec<&str> = fetched.iter().map(|f| f.local.as_str()).collect();
+        assert_eq!(locals, ["refs/remotes/origin/main"]);
+        assert!(!repo
+            .git_dir
+            .join("refs/tags/../../../../victim-owned.txt")
+            .exists());
+        assert!(!repo.git_dir.join("refs/tags/v1.lock").exists());
+    }
+
+    #[test]
+    fn test_format_updates() {
+        let fetched = |remote: &str, local: &str, update| FetchedRef {
+            remote: remote.to_string(),
+            local: local.to_string(),
+            hash: "b".repeat(40),
+            update,
+        };
+        let out = format_updates(
+            "http://example.com/repo.git",
+            &[
+                fetched("main", "refs/remotes/origin/main", RefUpdate::NewBranch),
+                fetched(
+                    "next",
+                    "refs/remotes/origin/next",
+                    RefUpdate::Updated("a".repeat(40)),
+                ),
+                fetched("v1", "refs/tags/v1", RefUpdate::NewTag),
+                fetched("v0", "refs/tags/v0", RefUpdate::Rejected),
+            ],
+        );
+        assert_eq!(
+            out,
+            "From http://example.com/repo.git\n \
+             * [new branch]      main -> origin/main\n   \
+             aaaaaaa..bbbbbbb  next -> origin/next\n \
+             * [new tag]         v1 -> v1\n \
+             ! [rejected]        v0 -> v0  (would clobber existing tag)\n"
+        );
+        assert_eq!(format_updates("http://example.com/repo.git", &[]), "");
+    }
+}
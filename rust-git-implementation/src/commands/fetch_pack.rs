@@ -0,0 +1,132 @@
+//! Download objects for given remote refs (`oxid fetch-pack`)
+//!
+//! The plumbing under `fetch`: the objects reachable from the named refs
+//! are downloaded, but no local ref is touched. Each fetched ref is
+//! printed as `<object id> <ref name>`, so scripts can decide what to do
+//! with it. Refs are given as full names (`refs/heads/main`, `HEAD`) or
+//! object ids, on the command line or one per line with `--stdin`.
+//!
+//! The only transport is the dumb HTTP protocol (see
+//! [`crate::dumb_http`]).
+
+use crate::dumb_http::Remote;
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::io::BufRead;
+
+/// Options for `fetch-pack`
+#[derive(Debug, Clone, Default)]
+pub struct FetchPackOptions {
+    /// Fetch every remote ref instead of the named ones
+    pub all: bool,
+    /// Also read ref names from stdin, one per line
+    pub stdin: bool,
+}
+
+/// Fetch the objects for the wanted refs
+///
+/// Returns the object id and name of each ref fetched, in the order given
+/// (remote order with `all`).
+///
+/// # Arguments
+///
+/// * `repo` - The repository to fetch into
+/// * `url` - The remote repository's `http://` URL
+/// * `wanted` - Full ref names or object ids
+/// * `all` - Fetch every remote ref, ignoring `wanted`
+pub fn fetch_pack(
+    repo: &Repository,
+    url: &str,
+    wanted: &[String],
+    all: bool,
+) -> Result<Vec<(String, String)>> {
+    let remote = Remote::new(repo, url)?;
+    let refs = remote.refs()?;
+
+    let selected = if all {
+        refs.into_iter().map(|(name, hash)| (hash, name)).collect()
+    } else {
+        let mut selected = Vec::new();
+        for name in wanted {
+            let hash = if name == "HEAD" {
+                remote
+                    .head()?
+                    .and_then(|head| refs.get(&head).cloned())
+                    .context("remote HEAD doesn't point to a branch")?
+            } else if let Some(hash) = refs.get(name) {
+                hash.clone()
+            } else if name.len() == 40 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+                name.to_ascii_lowercase()
+            } else {
+                anyhow::bail!("no such remote ref {}", name);
+            };
+            selected.push((hash, name.clone()));
+        }
+        selected
+    };
+
+    let wants: Vec<String> = selected.iter().map(|(hash, _)| hash.clone()).collect();
+    remote.fetch(repo, &wants)?;
+    Ok(selected)
+}
+
+/// Fetch objects for remote refs and print them
+///
+/// # Arguments
+///
+/// * `url` - The remote repository's `http://` URL
+/// * `refs` - Refs named on the command line
+/// * `options` - How to choose the refs
+pub fn run(url: &str, refs: Vec<String>, options: &FetchPackOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let mut wanted = refs;
+    if options.stdin {
+        for line in std::io::stdin().lock().lines() {
+            let line = line?;
+            let name = line.trim();
+            if !name.is_empty() {
+                wanted.push(name.to_string());
+            }
+        }
+    }
+    if wanted.is_empty() && !options.all {
+        anyhow::bail!("no refs given to fetch; name some or use --all");
+    }
+    for (hash, name) in fetch_pack(&repo, url, &wanted, options.all)? {
+        println!("{} {}", hash, name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{serve_http, RepoBuilder};
+
+    #[test]
+    fn test_fetch_pack() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let tip = builder.file("a", "1\n").commit("first").unwrap();
+        builder.branch("topic").unwrap();
+        let remote = builder.repo();
+        crate::server_info::update_server_info(remote, false).unwrap();
+        let url = serve_http(remote.git_dir.clone()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let local = Repository::init(dir.path()).unwrap();
+        let wanted = vec!["HEAD".to_string(), "refs/heads/topic".to_string()];
+        assert_eq!(
+            fetch_pack(&local, &url, &wanted, false).unwrap(),
+            vec![
+                (tip.clone(), "HEAD".to_string()),
+                (tip.clone(), "refs/heads/topic".to_string())
+            ]
+        );
+        assert!(local.has_object(&tip));
+        // No local refs are created
+        assert!(crate::refs::read_refs(&local.git_dir).unwrap().is_empty());
+
+        assert_eq!(fetch_pack(&local, &url, &[], true).unwrap().len(), 2);
+        assert!(fetch_pack(&local, &url, &["refs/heads/nope".to_string()], false).is_err());
+    }
+}
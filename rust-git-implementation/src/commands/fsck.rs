@@ -0,0 +1,549 @@
+//! Verify the connectivity and validity of a repository's objects
+
+use crate::compat::{self, Problem};
+use crate::fsck::Severity;
+use crate::index::Index;
+use crate::objects::{parse_object, FileMode, ObjectType, TreeRef};
+use crate::quote;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Options for `fsck`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsckOptions {
+    /// `--unreachable`: list every object nothing leads to, instead of
+    /// only the dangling ones
+    pub unreachable: bool,
+    /// `--no-dangling`: don't list dangling objects
+    pub no_dangling: bool,
+    /// `--strict`: treat format warnings as errors
+    pub strict: bool,
+}
+
+/// Something `fsck` reports
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Finding {
+    /// A stored object that is corrupt, misnamed or badly formatted
+    Bad(Problem),
+    /// A ref, reflog or index entry naming an object that isn't stored
+    BadPointer { source: String, hash: String },
+    /// An object pointing to one that isn't stored
+    BrokenLink {
+        from_kind: ObjectType,
+        from: String,
+        kind: ObjectType,
+        hash: String,
+    },
+    /// An object pointed to as one type that is stored as another
+    WrongType {
+        hash: String,
+        expected: ObjectType,
+        actual: ObjectType,
+    },
+    /// An object something points to that isn't stored
+    Missing { kind: ObjectType, hash: String },
+    /// An unreachable object no other object points to
+    Dangling { kind: ObjectType, hash: String },
+    /// An object no ref, reflog or index entry leads to
+    Unreachable { kind: ObjectType, hash: String },
+}
+
+impl Finding {
+    /// How serious the finding is; `None` for dangling and unreachable
+    /// objects, which are normal after rewriting history
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - Whether format warnings count as errors (`--strict`)
+    pub fn severity(&self, strict: bool) -> Option<Severity> {
+        match self {
+            Finding::Bad(Problem::Fsck { id, .. }) => Some(id.severity(strict)),
+            Finding::Dangling { .. } | Finding::Unreachable { .. } => None,
+            _ => Some(Severity::Error),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::Bad(problem) => write!(f, "{}", problem),
+            Finding::BadPointer { source, hash } => {
+                write!(f, "{}: invalid sha1 pointer {}", source, hash)
+            }
+            Finding::BrokenLink {
+                from_kind,
+                from,
+                kind,
+                hash,
+            } => write!(
+                f,
+                "broken link from {:>7} {}\n              to {:>7} {}",
+                from_kind.as_str(),
+                from,
+                kind.as_str(),
+                hash
+            ),
+            Finding::WrongType {
+                hash,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "object {}: is a {}, not a {}",
+                hash,
+                actual.as_str(),
+                expected.as_str()
+            ),
+            Finding::Missing { kind, hash } => write!(f, "missing {} {}", kind.as_str(), hash),
+            Finding::Dangling { kind, hash } => write!(f, "dangling {} {}", kind.as_str(), hash),
+            Finding::Unreachable { kind, hash } => {
+                write!(f, "unreachable {} {}", kind.as_str(), hash)
+            }
+        }
+    }
+}
+
+/// The result of checking a repository
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    /// Number of stored objects checked
+    pub checked: usize,
+    /// Number of packfiles found; oxid can't read them yet, so when there
+    /// are any, connectivity isn't checked
+    pub packs: usize,
+    /// Problems and dangling objects, in the order Git reports them
+    pub findings: Vec<Finding>,
+}
+
+/// The objects an object points to, with the type each is expected to have
+///
+/// Submodule commits in trees are skipped, as they live in another
+/// repository.
+fn links(kind: ObjectType, content: &[u8]) -> Vec<(ObjectType, String)> {
+    let mut links = Vec::new();
+    match kind {
+        ObjectType::Blob => {}
+        ObjectType::Tree => {
+            for entry in TreeRef::new(content).iter().map_while(Result::ok) {
+                if entry.file_mode().ok() == Some(FileMode::Gitlink) {
+                    continue;
+                }
+                let kind = if entry.is_tree() {
+                    ObjectType::Tree
+                } else {
+                    ObjectType::Blob
+                };
+                links.push((kind, entry.hash()));
+            }
+        }
+        ObjectType::Commit | ObjectType::Tag => {
+            let mut tag_target = None;
+            let mut tag_type = None;
+            for line in content.split(|&b| b == b'\n') {
+                if line.is_empty() {
+                    break;
+                }
+                let Ok(line) = std::str::from_utf8(line) else {
+                    continue;
+                };
+                match line.split_once(' ') {
+                    Some(("tree", hash)) => links.push((ObjectType::Tree, hash.to_string())),
+                    Some(("parent", hash)) => links.push((ObjectType::Commit, hash.to_string())),
+                    Some(("object", hash)) => tag_target = Some(hash.to_string()),
+                    Some(("type", name)) => tag_type = ObjectType::from_str(name).ok(),
+                    _ => {}
+                }
+            }
+            if let (Some(hash), Some(kind)) = (tag_target, tag_type) {
+                links.push((kind, hash));
+            }
+        }
+    }
+    links
+}
+
+/// Objects named by reflog entries, by the log they're in
+///
+/// Old values count too, so commits dropped by `commit --amend` or a
+/// reset aren't reported as dangling while the reflog remembers them.
+fn reflog_entries(git_dir: &Path) -> Result<Vec<(String, String)>> {
+    let logs = git_dir.join("logs");
+    let mut entries = Vec::new();
+    if !logs.exists() {
+        return Ok(entries);
+    }
+    for file in walkdir::WalkDir::new(&logs) {
+        let file = file?;
+        if !file.file_type().is_file() {
+            continue;
+        }
+        let name = file
+            .path()
+            .strip_prefix(&logs)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        let content = fs::read(file.path())?;
+        for line in content.split(|&b| b == b'\n') {
+            let mut fields = line.splitn(3, |&b| b == b' ');
+            for hash in [fields.next(), fields.next()].into_iter().flatten() {
+                let Ok(hash) = std::str::from_utf8(hash) else {
+                    continue;
+                };
+                if !hash.is_empty() && hash.bytes().any(|b| b != b'0') {
+                    entries.push((format!("{}: reflog", name), hash.to_string()));
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Check every stored object, then walk from refs, `HEAD`, reflogs and the
+/// index to find missing and unreachable objects
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `options` - Which unreachable objects to list, and how strict to be
+pub fn fsck(repo: &Repository, options: &FsckOptions) -> Result<FsckReport> {
+    let mut report = FsckReport::default();
+    let mut stored: BTreeMap<String, ObjectType> = BTreeMap::new();
+    let mut edges: HashMap<String, Vec<(ObjectType, String)>> = HashMap::new();
+    let mut corrupt = HashSet::new();
+
+    for hash in repo.objects()? {
+        report.checked += 1;
+        let data = match repo.read_stored_object(&hash) {
+            Ok(data) => data,
+            Err(e) => {
+                report.findings.push(Finding::Bad(Problem::Corrupt {
+                    hash: hash.clone(),
+                    error: format!("{:#}", e),
+                }));
+                corrupt.insert(hash);
+                continue;
+            }
+        };
+        let actual = crate::utils::hash_data(&data);
+        if actual != hash {
+            report.findings.push(Finding::Bad(Problem::HashMismatch {
+                hash: hash.clone(),
+                actual,
+            }));
+            corrupt.insert(hash);
+            continue;
+        }
+        report.findings.extend(
+            compat::check_content(&hash, &data, options.strict)
+                .into_iter()
+                .map(Finding::Bad),
+        );
+        match parse_object(&data) {
+            Ok((kind, content)) => {
+                edges.insert(hash.clone(), links(kind, content));
+                stored.insert(hash, kind);
+            }
+            Err(_) => {
+                corrupt.insert(hash);
+            }
+        }
+    }
+
+    report.packs = fs::read_dir(repo.common_dir.join("objects/pack"))
+        .map(|dir| {
+            dir.filter_map(|e| e.ok())
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "pack"))
+                .count()
+        })
+        .unwrap_or(0);
+    if report.packs > 0 {
+        // Packed objects would all look missing
+        return Ok(report);
+    }
+
+    let mut roots: Vec<(String, String)> =
+        crate::refs::read_refs(&repo.git_dir)?.into_iter().collect();
+    if let Some(head) = repo.head()? {
+        roots.push(("HEAD".to_string(), head));
+    }
+    roots.extend(reflog_entries(&repo.common_dir)?);
+    if repo.git_dir != repo.common_dir {
+        // A linked worktree keeps the log of its own HEAD
+        roots.extend(reflog_entries(&repo.git_dir)?);
+    }
+    for entry in Index::read(repo.index_path())?.entries() {
+        if entry.intent_to_add || entry.mode == FileMode::Gitlink {
+            continue;
+        }
+        roots.push((
+            format!("index entry {}", quote::display(&entry.path)),
+            entry.hash.clone(),
+        ));
+    }
+
+    let mut reachable = HashSet::new();
+    let mut queue = VecDeque::new();
+    for (source, hash) in roots {
+        if stored.contains_key(&hash) {
+            if reachable.insert(hash.clone()) {
+                queue.push_back(hash);
+            }
+        } else if !corrupt.contains(&hash) {
+            report.findings.push(Finding::BadPointer { source, hash });
+        }
+    }
+
+    // The parents of shallow commits were never fetched
+    let shallow = repo.shallow_commits()?;
+    let mut missing = BTreeMap::new();
+    while let Some(hash) = queue.pop_front() {
+        for (kind, target) in &edges[&hash] {
+            if *kind == ObjectType::Commit
+                && stored[&hash] == ObjectType::Commit
+                && shallow.contains(&hash)
+            {
+                continue;
+            }
+            match stored.get(target) {
+                Some(&actual) => {
+                    if actual != *kind {
+                        report.findings.push(Finding::WrongType {
+                            hash: target.clone(),
+                            expected: *kind,
+                            actual,
+                        });
+                    }
+                    if reachable.insert(target.clone()) {
+                        queue.push_back(target.clone());
+                    }
+                }
+                None if corrupt.contains(target) => {}
+                None => {
+                    report.findings.push(Finding::BrokenLink {
+                        from_kind: stored[&hash],
+                        from: hash.clone(),
+                        kind: *kind,
+                        hash: target.clone(),
+                    });
+                    missing.insert(target.clone(), *kind);
+                }
+            }
+        }
+    }
+    report.findings.extend(
+        missing
+            .into_iter()
+            .map(|(hash, kind)| Finding::Missing { kind, hash }),
+    );
+
+    let referenced: HashSet<&String> = edges.values().flatten().map(|(_, hash)| hash).collect();
+    for (hash, &kind) in &stored {
+        if reachable.contains(hash) {
+            continue;
+        }
+        let hash = hash.clone();
+        if options.unreachable {
+            report.findings.push(Finding::Unreachable { kind, hash });
+        } else if !options.no_dangling && !referenced.contains(&hash) {
+            report.findings.push(Finding::Dangling { kind, hash });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Verify the objects of the current repository and report problems
+///
+/// Fails if any errors are found; dangling objects aren't errors.
+///
+/// # Arguments
+///
+/// * `options` - Which unreachable objects to list, and how strict to be
+pub fn run(options: &FsckOptions) -> Result<()> {
+    let mut repo = Repository::open_snapshot(".")?;
+    repo.set_replace_objects(false);
+    let report = fsck(&repo, options)?;
+    if report.packs > 0 {
+        eprintln!(
+            "warning: {} packfiles not checked, skipping connectivity",
+            report.packs
+        );
+    }
+
+    let mut errors = 0;
+    for finding in &report.findings {
+        let severity = finding.severity(options.strict);
+        if severity == Some(Severity::Error) {
+            errors += 1;
+        }
+        match finding {
+            Finding::Bad(_) | Finding::BadPointer { .. } | Finding::WrongType { .. } => {
+                let label = match severity {
+                    Some(Severity::Warning) => "warning",
+                    _ => "error",
+                };
+                println!("{}: {}", label, finding);
+            }
+            _ => println!("{}", finding),
+        }
+    }
+    if errors > 0 {
+        anyhow::bail!("{} errors found", errors);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Blob, GitObject};
+    use crate::testing::RepoBuilder;
+
+    fn store_blob(repo: &Repository, content: &[u8]) -> String {
+        let data = Blob::new(content).to_bytes().unwrap();
+        let hash = crate::utils::hash_data(&data);
+        repo.write_object(&hash, &data).unwrap();
+        hash
+    }
+
+    #[test]
+    fn test_clean_repository() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n").commit("one").unwrap();
+        builder.file("d/b.txt", "b\n").commit("two").unwrap();
+        builder.annotated_tag("v1", "release").unwrap();
+
+        let report = fsck(builder.repo(), &FsckOptions::default()).unwrap();
+        assert_eq!(report.checked, 8);
+        assert_eq!(report.findings, vec![]);
+    }
+
+    #[test]
+    fn test_shallow_repository() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("a.txt", "a\n").commit("one").unwrap();
+        let second = builder.file("a.txt", "b\n").commit("two").unwrap();
+        fs::write(builder.path().join(".git/shallow"), format!("{}\n", second)).unwrap();
+        fs::remove_file(builder.repo().object_path(&first).unwrap()).unwrap();
+
+        // The missing parent is expected; only the orphaned tree is left over
+        let repo = Repository::new(builder.path()).unwrap();
+        let report = fsck(&repo, &FsckOptions::default()).unwrap();
+        assert!(report.findings.iter().all(|f| f.severity(false).is_none()));
+        assert_eq!(report.findings.len(), 1);
+    }
+
+    #[test]
+    fn test_dangling_and_unreachable() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n").commit("one").unwrap();
+        let repo = builder.repo();
+        let blob = store_blob(repo, b"lost\n");
+
+        let report = fsck(repo, &FsckOptions::default()).unwrap();
+        assert_eq!(
+            report.findings,
+            vec![Finding::Dangling {
+                kind: ObjectType::Blob,
+                hash: blob.clone()
+            }]
+        );
+        assert_eq!(report.findings[0].severity(false), None);
+
+        let options = FsckOptions {
+            no_dangling: true,
+            ..Default::default()
+        };
+        assert!(fsck(repo, &options).unwrap().findings.is_empty());
+
+        // Deleting a branch leaves its commit dangling, next to the blob;
+        // the commit's new tree and blob are unreachable but not dangling
+        builder.branch("topic").unwrap().checkout("topic").unwrap();
+        let commit = builder.file("b.txt", "b\n").commit("two").unwrap();
+        builder.checkout("main").unwrap();
+        let repo = builder.repo();
+        fs::remove_file(repo.git_dir.join("refs/heads/topic")).unwrap();
+        let kinds = |options: &FsckOptions| -> Vec<String> {
+            fsck(repo, options)
+                .unwrap()
+                .findings
+                .iter()
+                .map(|f| f.to_string().split(' ').next().unwrap().to_string())
+                .collect()
+        };
+        let report = fsck(repo, &FsckOptions::default()).unwrap();
+        assert!(report.findings.contains(&Finding::Dangling {
+            kind: ObjectType::Commit,
+            hash: commit
+        }));
+        assert_eq!(kinds(&FsckOptions::default()), ["dangling", "dangling"]);
+        let options = FsckOptions {
+            unreachable: true,
+            ..Default::default()
+        };
+        assert_eq!(kinds(&options), ["unreachable"; 4]);
+    }
+
+    #[test]
+    fn test_missing_and_corrupt_objects() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n").file("b.txt", "b\n");
+        builder.commit("one").unwrap();
+        let repo = builder.repo();
+        let a = Blob::new(&b"a\n"[..]).hash().unwrap();
+        fs::remove_file(repo.object_path(&a).unwrap()).unwrap();
+
+        let report = fsck(repo, &FsckOptions::default()).unwrap();
+        assert_eq!(report.findings.len(), 2);
+        assert!(matches!(
+            &report.findings[0],
+            Finding::BrokenLink { from_kind: ObjectType::Tree, hash, .. } if *hash == a
+        ));
+        assert_eq!(
+            report.findings[1].to_string(),
+            format!("missing blob {}", a)
+        );
+
+        // Content stored under the wrong name
+        let data = Blob::new(&b"b\n"[..]).to_bytes().unwrap();
+        repo.write_object(&a, &data).unwrap();
+        let report = fsck(repo, &FsckOptions::default()).unwrap();
+        assert_eq!(
+            report.findings,
+            vec![Finding::Bad(Problem::HashMismatch {
+                hash: a,
+                actual: crate::utils::hash_data(&data)
+            })]
+        );
+        assert_eq!(report.findings[0].severity(false), Some(Severity::Error));
+    }
+
+    #[test]
+    fn test_bad_ref() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n").commit("one").unwrap();
+        let repo = builder.repo();
+        let bogus = "0123456789012345678901234567890123456789";
+        fs::write(
+            repo.git_dir.join("refs/heads/bogus"),
+            format!("{}\n", bogus),
+        )
+        .unwrap();
+
+        let report = fsck(repo, &FsckOptions::default()).unwrap();
+        assert_eq!(
+            report.findings,
+            vec![Finding::BadPointer {
+                source: "refs/heads/bogus".to_string(),
+                hash: bogus.to_string()
+            }]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+//! Print lines matching a pattern (`oxid grep`)
+//!
+//! Searches the tracked files in the working tree, the index with
+//! `--cached`, or the trees of the revisions given, so `oxid grep TODO
+//! v1.0` looks through v1.0 without checking it out. Matches in a revision
+//! are shown as `<rev>:<path>:<line>`, with paths relative to the current
+//! directory.
+
+use crate::grep::{FileMatches, Grep, MatchOptions, Matcher};
+use crate::index::Index;
+use crate::pathspec::Pathspec;
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::revision;
+use anyhow::Result;
+use std::io::Write;
+use std::path::Path;
+
+/// What to search and how to show the results
+#[derive(Debug, Clone, Default)]
+pub struct GrepOptions {
+    /// `--cached`: search the index instead of the working tree
+    pub cached: bool,
+    /// `-n`: prefix lines with their line number
+    pub line_number: bool,
+    /// `-l`: only show the names of files with matches
+    pub files_with_matches: bool,
+    /// `-c`: show the number of matching lines of each file
+    pub count: bool,
+    /// `--max-depth`: descend at most this many directories
+    pub max_depth: Option<usize>,
+}
+
+/// Express a path from the top of the repository relative to a directory
+///
+/// # Arguments
+///
+/// * `path` - The path
+/// * `cwd` - The directory, also from the top; empty for the top itself
+pub fn relative_path(path: &[u8], cwd: &[u8]) -> Vec<u8> {
+    let split = |p: &[u8]| -> Vec<Vec<u8>> {
+        p.split(|&b| b == b'/')
+            .filter(|c| !c.is_empty())
+            .map(<[u8]>::to_vec)
+            .collect()
+    };
+    let (path, cwd) = (split(path), split(cwd));
+    let common = path.iter().zip(&cwd).take_while(|(a, b)| a == b).count();
+    let mut parts = vec![b"..".to_vec(); cwd.len() - common];
+    parts.extend_from_slice(&path[common..]);
+    parts.join(&b'/')
+}
+
+/// Write the matches of one file
+///
+/// # Arguments
+///
+/// * `out` - Where to write
+/// * `file` - The file's matches
+/// * `prefix` - Put before the path, e.g. `v1.0:` for a revision
+/// * `cwd` - Show the path relative to this directory
+/// * `options` - Output options
+/// * `paths` - How to quote the file name
+pub fn write_matches(
+    out: &mut dyn Write,
+    file: &FileMatches,
+    prefix: &str,
+    cwd: &[u8],
+    options: &GrepOptions,
+    paths: PathFormat,
+) -> Result<()> {
+    let name = [prefix.as_bytes(), &relative_path(&file.path, cwd)].concat();
+    let name = paths.path(&name);
+    if options.files_with_matches {
+        out.write_all(&name)?;
+        out.write_all(b"\n")?;
+    } else if options.count {
+        out.write_all(&name)?;
+        writeln!(out, ":{}", file.lines.len())?;
+    } else if file.binary {
+        out.write_all(b"Binary file ")?;
+        out.write_all(&name)?;
+        out.write_all(b" matches\n")?;
+    } else {
+        for (number, line) in &file.lines {
+            out.write_all(&name)?;
+            if options.line_number {
+                write!(out, ":{}", number)?;
+            }
+            out.write_all(b":")?;
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Search for patterns and print the matches
+///
+/// Exits with status 1 if nothing matched.
+///
+/// # Arguments
+///
+/// * `patterns` - Patterns given with `-e`; if empty, the first argument
+///   is the pattern
+/// * `match_options` - Pattern syntax and matching options
+/// * `options` - What to search and how to show it
+/// * `args` - Revisions, then paths; the first argument that isn't a
+///   revision starts the paths
+/// * `paths` - Paths given after `--`
+pub fn run(
+    patterns: &[String],
+    match_options: MatchOptions,
+    options: &GrepOptions,
+    args: &[String],
+    paths: &[String],
+) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let (patterns, args) = match (patterns, args) {
+        ([], [pattern, rest @ ..]) => (std::slice::from_ref(pattern), rest),
+        ([], []) => anyhow::bail!("no pattern given"),
+        _ => (patterns, args),
+    };
+    let matcher = Matcher::new(patterns, match_options)?;
+
+    let mut revisions = Vec::new();
+    let mut pathspecs = Vec::new();
+    for (i, arg) in args.iter().enumerate() {
+        match revision::resolve_tree(&repo, arg) {
+            Ok(tree) => revisions.push((arg.as_str(), tree)),
+            // Everything before `--` has to be a revision
+            Err(e) if !paths.is_empty() => return Err(e),
+            Err(_) => {
+                pathspecs.extend_from_slice(&args[i..]);
+                break;
+            }
+        }
+    }
+    pathspecs.extend_from_slice(paths);
+    if options.cached && !revisions.is_empty() {
+        anyhow::bail!("both --cached and trees are given");
+    }
+
+    let cwd = repo.index_path_of(Path::new("."))?;
+    let mut specs = pathspecs
+        .iter()
+        .map(|path| repo.index_path_of(Path::new(path)))
+        .collect::<Result<Vec<_>>>()?;
+    if specs.is_empty() {
+        // Like Git, only search below the current directory
+        specs.push(cwd.clone());
+    }
+    let grep = Grep::new(&repo, &matcher)
+        .pathspec(Pathspec::new(specs))
+        .max_depth(options.max_depth);
+
+    let format = PathFormat::from_config(&repo.config()?, false)?;
+    let mut out = std::io::stdout().lock();
+    let mut matched = false;
+    let mut show = |found: Vec<FileMatches>, prefix: &str| -> Result<()> {
+        for file in &found {
+            write_matches(&mut out, file, prefix, &cwd, options, format)?;
+        }
+        matched |= !found.is_empty();
+        Ok(())
+    };
+    if !revisions.is_empty() {
+        for (name, tree) in &revisions {
+            show(grep.tree(tree)?, &format!("{}:", name))?;
+        }
+    } else {
+        let index = Index::read(repo.index_path())?;
+        if options.cached {
+            show(grep.index(&index)?, "")?;
+        } else {
+            show(grep.worktree(&index, &repo.worktree()?)?, "")?;
+        }
+    }
+
+    out.flush()?;
+    if !matched {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_matches() {
+        let file = FileMatches {
+            path: b"src/main.rs".to_vec(),
+            binary: false,
+            lines: vec![(2, b"// TODO".to_vec()), (7, b"todo!()".to_vec())],
+        };
+        let paths = PathFormat::from_config(&Default::default(), false).unwrap();
+        let write = |options: &GrepOptions, file: &FileMatches| {
+            let mut out = Vec::new();
+            write_matches(&mut out, file, "v1.0:", b"", options, paths).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let mut options = GrepOptions::default();
+        assert_eq!(
+            write(&options, &file),
+            "v1.0:src/main.rs:// TODO\nv1.0:src/main.rs:todo!()\n"
+        );
+        options.line_number = true;
+        assert_eq!(
+            write(&options, &file),
+            "v1.0:src/main.rs:2:// TODO\nv1.0:src/main.rs:7:todo!()\n"
+        );
+        options.count = true;
+        assert_eq!(write(&options, &file), "v1.0:src/main.rs:2\n");
+        options.files_with_matches = true;
+        assert_eq!(write(&options, &file), "v1.0:src/main.rs\n");
+
+        let binary = FileMatches {
+            binary: true,
+            ..file
+        };
+        assert_eq!(
+            write(&GrepOptions::default(), &binary),
+            "Binary file v1.0:src/main.rs matches\n"
+        );
+    }
+
+    #[test]
+    fn test_relative_path() {
+        assert_eq!(relative_path(b"src/main.rs", b""), b"src/main.rs");
+        assert_eq!(relative_path(b"src/main.rs", b"src"), b"main.rs");
+        assert_eq!(relative_path(b"README", b"src/deep"), b"../../README");
+        assert_eq!(relative_path(b"src/main.rs", b"src/deep"), b"../main.rs");
+    }
+}
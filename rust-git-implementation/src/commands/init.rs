@@ -1,24 +1,36 @@
 //! Repository initialization command
 
-use crate::repository::Repository;
+use crate::repository::{InitOptions, Repository};
 use anyhow::Result;
 
 /// Initialize a new oxid repository
 ///
 /// Creates a .git directory structure with all necessary subdirectories and files.
+/// Running it in an existing repository re-initializes it without touching
+/// existing files.
 ///
 /// # Arguments
 ///
 /// * `path` - Path where the repository should be initialized
+/// * `options` - Bare mode, initial branch, template and git dir location
 ///
 /// # Example
 ///
 /// ```no_run
-/// oxid::commands::init::run(".").unwrap();
+/// use oxid::repository::InitOptions;
+///
+/// oxid::commands::init::run(".", &InitOptions::default()).unwrap();
 /// ```
-pub fn run(path: &str) -> Result<()> {
-    Repository::init(path)?;
-    println!("Initialized empty oxid repository in {}/.git/", path);
+pub fn run(path: &str, options: &InitOptions) -> Result<()> {
+    let reinit = options.is_reinit(path.as_ref());
+    let repo = Repository::init_with(path, options)?;
+
+    let git_dir = repo.git_dir.display();
+    if reinit {
+        println!("Reinitialized existing oxid repository in {}/", git_dir);
+    } else {
+        println!("Initialized empty oxid repository in {}/", git_dir);
+    }
     Ok(())
 }
 
@@ -30,7 +42,7 @@ mod tests {
     #[test]
     fn test_init_creates_git_directory() {
         let dir = tempdir().unwrap();
-        run(dir.path().to_str().unwrap()).unwrap();
+        run(dir.path().to_str().unwrap(), &InitOptions::default()).unwrap();
 
         // Verify .git directory exists
         assert!(dir.path().join(".git").exists());
@@ -38,4 +50,13 @@ mod tests {
         assert!(dir.path().join(".git/refs/heads").exists());
         assert!(dir.path().join(".git/HEAD").exists());
     }
+
+    #[test]
+    fn test_init_twice_reinitializes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        run(path, &InitOptions::default()).unwrap();
+        assert!(InitOptions::default().is_reinit(dir.path()));
+        run(path, &InitOptions::default()).unwrap();
+    }
 }
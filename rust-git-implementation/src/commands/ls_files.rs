@@ -0,0 +1,249 @@
+//! List the files in the index
+
+use crate::index::{Extension, Index, IndexEntry};
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::io::Write;
+
+/// Output options for `ls-files`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LsFilesOptions {
+    /// `-s`: show mode, object hash and stage
+    pub stage: bool,
+    /// `-v`: prefix each path with a status tag, lowercase if assume-unchanged
+    pub tags: bool,
+    /// `-u`: only show unmerged entries (implies `stage`)
+    pub unmerged: bool,
+    /// `--debug`: dump stat data and flags after each entry, then the
+    /// index extensions
+    pub debug: bool,
+}
+
+/// Status tag shown by `ls-files -v`
+///
+/// `M` marks unmerged entries, `S` skip-worktree entries and `H` everything
+/// else; the tag is lowercased when the entry is marked assume-unchanged.
+pub fn tag(entry: &IndexEntry) -> char {
+    let tag = if entry.stage != 0 {
+        'M'
+    } else if entry.skip_worktree {
+        'S'
+    } else {
+        'H'
+    };
+    if entry.assume_valid {
+        tag.to_ascii_lowercase()
+    } else {
+        tag
+    }
+}
+
+/// Format one `ls-files` output record, including its terminator
+///
+/// # Arguments
+///
+/// * `entry` - The index entry
+/// * `options` - Which columns to show
+/// * `paths` - How to quote the path and end the record
+pub fn format_entry(entry: &IndexEntry, options: &LsFilesOptions, paths: PathFormat) -> Vec<u8> {
+    let mut line = Vec::new();
+    if options.tags {
+        line.extend_from_slice(format!("{} ", tag(entry)).as_bytes());
+    }
+    if options.stage || options.unmerged {
+        line.extend_from_slice(
+            format!(
+                "{:06o} {} {}\t",
+                entry.mode.as_u32(),
+                entry.hash,
+                entry.stage
+            )
+            .as_bytes(),
+        );
+    }
+    line.extend_from_slice(&paths.path(&entry.path));
+    line.push(paths.terminator());
+    if options.debug {
+        line.extend_from_slice(format_debug(entry).as_bytes());
+        line.push(b'\n');
+    }
+    line
+}
+
+/// Format the stat data and flags of an entry, as `ls-files --debug` does
+///
+/// This is what status compares against the worktree file to decide
+/// whether it may have changed.
+pub fn format_debug(entry: &IndexEntry) -> String {
+    format!(
+        "  ctime: {}:{}\n  mtime: {}:{}\n  dev: {}\tino: {}\n  uid: {}\tgid: {}\n  size: {}\tflags: {:x}",
+        entry.ctime_secs,
+        entry.ctime_nsecs,
+        entry.mtime_secs,
+        entry.mtime_nsecs,
+        entry.dev,
+        entry.ino,
+        entry.uid,
+        entry.gid,
+        entry.size,
+        entry.debug_flags()
+    )
+}
+
+/// Format an index extension as a header line followed by a hex dump
+pub fn format_extension(extension: &Extension) -> String {
+    let mut out = format!(
+        "extension {}: {} bytes",
+        String::from_utf8_lossy(&extension.signature),
+        extension.data.len()
+    );
+    for (i, chunk) in extension.data.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "\n  {:08x}  {:<47}  {}",
+            i * 16,
+            hex.join(" "),
+            text
+        ));
+    }
+    out
+}
+
+/// Show information about files in the index
+///
+/// # Arguments
+///
+/// * `options` - Which columns to show
+/// * `nul` - End each record with NUL and don't quote paths (`-z`)
+pub fn run(options: &LsFilesOptions, nul: bool) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let paths = PathFormat::from_config(&repo.config()?, nul)?;
+    let index = Index::read(repo.index_path())?;
+    let mut out = std::io::stdout().lock();
+    for entry in index.entries() {
+        if options.unmerged && entry.stage == 0 {
+            continue;
+        }
+        out.write_all(&format_entry(entry, options, paths))?;
+    }
+    if options.debug {
+        for extension in &index.extensions {
+            println!("{}", format_extension(extension));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::FileMode;
+
+    const HASH: &str = "78981922613b2afb6025042ff6bd878ac1994e85";
+
+    #[test]
+    fn test_tags() {
+        let mut entry = IndexEntry::new("a.txt", FileMode::Regular, HASH);
+        assert_eq!(tag(&entry), 'H');
+        entry.assume_valid = true;
+        assert_eq!(tag(&entry), 'h');
+        entry.skip_worktree = true;
+        assert_eq!(tag(&entry), 's');
+        entry.assume_valid = false;
+        assert_eq!(tag(&entry), 'S');
+        entry.stage = 2;
+        assert_eq!(tag(&entry), 'M');
+    }
+
+    #[test]
+    fn test_format_unmerged() {
+        let mut entry = IndexEntry::new("c.txt", FileMode::Regular, HASH);
+        entry.stage = 3;
+        let options = LsFilesOptions {
+            unmerged: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_entry(&entry, &options, PathFormat::default()),
+            format!("100644 {} 3\tc.txt\n", HASH).as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let mut entry = IndexEntry::new("d/b.txt", FileMode::Regular, HASH);
+        entry.assume_valid = true;
+        let options = LsFilesOptions {
+            stage: true,
+            tags: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_entry(&entry, &options, PathFormat::default()),
+            format!("h 100644 {} 0\td/b.txt\n", HASH).as_bytes()
+        );
+        assert_eq!(
+            format_entry(&entry, &LsFilesOptions::default(), PathFormat::default()),
+            b"d/b.txt\n"
+        );
+    }
+
+    #[test]
+    fn test_format_unusual_paths() {
+        let entry = IndexEntry::new(&b"caf\xe9\n.txt"[..], FileMode::Regular, HASH);
+        let options = LsFilesOptions::default();
+        assert_eq!(
+            format_entry(&entry, &options, PathFormat::default()),
+            b"\"caf\\351\\n.txt\"\n"
+        );
+        assert_eq!(
+            format_entry(&entry, &options, PathFormat::Quoted { non_ascii: false }),
+            b"\"caf\xe9\\n.txt\"\n"
+        );
+        assert_eq!(
+            format_entry(&entry, &options, PathFormat::Nul),
+            b"caf\xe9\n.txt\0"
+        );
+    }
+
+    #[test]
+    fn test_format_debug() {
+        let mut entry = IndexEntry::new("a.txt", FileMode::Regular, HASH);
+        entry.mtime_secs = 1792169310;
+        entry.mtime_nsecs = 267704263;
+        entry.size = 2;
+        entry.skip_worktree = true;
+        assert_eq!(
+            format_debug(&entry),
+            "  ctime: 0:0\n  mtime: 1792169310:267704263\n  dev: 0\tino: 0\n  \
+             uid: 0\tgid: 0\n  size: 2\tflags: 40004000"
+        );
+
+        entry.skip_worktree = false;
+        entry.assume_valid = true;
+        assert!(format_debug(&entry).ends_with("flags: 8000"));
+    }
+
+    #[test]
+    fn test_format_extension() {
+        let extension = Extension {
+            signature: *b"TREE",
+            data: b"\0-1 0\n".to_vec(),
+        };
+        assert_eq!(
+            format_extension(&extension),
+            "extension TREE: 6 bytes\n  00000000  00 2d 31 20 30 0a                                .-1 0."
+        );
+    }
+}
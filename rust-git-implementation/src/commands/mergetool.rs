@@ -0,0 +1,250 @@
+//! Resolve merge conflicts with an external merge tool
+
+use crate::external_tool::{self, TempDir, ToolKind};
+use crate::index::{Index, IndexEntry};
+use crate::objects::{Blob, FileMode, GitObject};
+use crate::quote;
+use crate::repository::Repository;
+use crate::utils::path_from_bytes;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fs;
+
+/// Options for `mergetool`
+#[derive(Debug, Clone, Default)]
+pub struct MergetoolOptions {
+    /// `--tool`: tool to use instead of `merge.tool`
+    pub tool: Option<String>,
+    /// `--[no-]prompt`: ask before launching the tool for each file;
+    /// defaults to `mergetool.prompt`
+    pub prompt: Option<bool>,
+}
+
+/// Run the merge tool on every conflicted file, staging the ones resolved
+///
+/// The tool gets `BASE`, `LOCAL` and `REMOTE` as temporary files and writes
+/// its result to `MERGED`, the worktree file. A merge counts as resolved when
+/// the tool succeeds if its exit code is trusted, and otherwise when it has
+/// changed the worktree file. The conflicted file is kept as `<path>.orig` unless
+/// `mergetool.keepBackup` is false.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `options` - Tool and prompting
+pub fn mergetool(repo: &Repository, options: &MergetoolOptions) -> Result<()> {
+    let config = repo.config()?;
+    let tool = external_tool::resolve(&config, ToolKind::Merge, options.tool.as_deref())?;
+    let prompt = match options.prompt {
+        Some(prompt) => prompt,
+        None => config.get_bool("mergetool.prompt")?.unwrap_or(true),
+    };
+    let keep_backup = config.get_bool("mergetool.keepBackup")?.unwrap_or(true);
+
+    let mut index = Index::read(repo.index_path())?;
+    let pending: Vec<(Vec<u8>, [Option<IndexEntry>; 3])> = index
+        .conflicts()
+        .map(|c| {
+            let stages = [c.base, c.ours, c.theirs].map(|s| s.cloned());
+            (c.path.to_vec(), stages)
+        })
+        .collect();
+    if pending.is_empty() {
+        println!("No files need merging");
+        return Ok(());
+    }
+
+    let mut unresolved = Vec::new();
+    for (path, stages) in &pending {
+        let shown = quote::display(path);
+        if prompt {
+            println!("\nNormal merge conflict for '{}':", shown);
+            if !external_tool::confirm(&format!("Launch '{}'", tool.name))? {
+                unresolved.push(shown);
+                continue;
+            }
+        }
+
+        let temp = TempDir::new("oxid-mergetool")?;
+        let name = path_from_bytes(path)?.file_name().unwrap_or_default();
+        let mut files = Vec::new();
+        for (label, stage) in ["BASE", "LOCAL", "REMOTE"].iter().zip(stages) {
+            let content = match stage {
+                Some(entry) => repo.read_staged(entry)?,
+                None => Vec::new(),
+            };
+            let mut file = OsString::from(format!("{}_", label));
+            file.push(name);
+            files.push(temp.write(file, &content)?);
+        }
+
+        let merged = repo.work_tree.join(path_from_bytes(path)?);
+        let before = fs::read(&merged).ok();
+        if keep_backup && before.is_some() {
+            fs::copy(&merged, format!("{}.orig", merged.display()))?;
+        }
+
+        let success = external_tool::run(
+            &tool,
+            &[
+                ("BASE", &files[0]),
+                ("LOCAL", &files[1]),
+                ("REMOTE", &files[2]),
+                ("MERGED", &merged),
+            ],
+        )?;
+        let after = fs::read(&merged).ok();
+        // With mergetool.<tool>.trustExitCode the exit code decides;
+        // otherwise the merged file has to have changed
+        let resolved = if tool.trust_exit_code {
+            success
+        } else {
+            after != before
+        };
+        let Some(content) = after.filter(|_| resolved) else {
+            eprintln!("merge of {} failed", shown);
+            unresolved.push(shown);
+            continue;
+        };
+
+        let blob = Blob::new(content);
+        let hash = blob.hash()?;
+        repo.write_object(&hash, &blob.to_bytes()?)?;
+        let mode = stages[1]
+            .as_ref()
+            .or(stages[2].as_ref())
+            .map_or(FileMode::Regular, |e| e.mode);
+        let mut entry = IndexEntry::new(path.as_slice(), mode, hash);
+        entry.set_stat(&fs::metadata(&merged)?);
+        index.remove(path);
+        index.add(entry);
+    }
+
+    repo.write_index(&index)?;
+    if !unresolved.is_empty() {
+        anyhow::bail!("Unresolved conflicts remain: {}", unresolved.join(", "));
+    }
+    Ok(())
+}
+
+/// Resolve merge conflicts using an external tool
+///
+/// # Arguments
+///
+/// * `options` - Tool and prompting
+pub fn run(options: &MergetoolOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    mergetool(&repo, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::store;
+
+    fn conflicted(repo: &Repository, path: &str) -> Index {
+        let mut index = Index::new();
+        for (stage, content) in [(1, &b"base\n"[..]), (2, b"ours\n"), (3, b"theirs\n")] {
+            let mut entry = IndexEntry::new(path, FileMode::Regular, store(repo, content).unwrap());
+            entry.stage = stage;
+            index.add(entry);
+        }
+        index
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mergetool_stages_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        conflicted(&repo, "a.txt").write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"<<<<<<< conflict\n").unwrap();
+        fs::write(
+            repo.git_dir.join("config"),
+            "[mergetool \"cat\"]\ncmd = cat \"$LOCAL\" \"$REMOTE\" > \"$MERGED\"\n",
+        )
+        .unwrap();
+
+        let options = MergetoolOptions {
+            tool: Some("cat".to_string()),
+            prompt: Some(false),
+        };
+        mergetool(&repo, &options).unwrap();
+
+        let index = Index::read(repo.index_path()).unwrap();
+        assert_eq!(index.entries().len(), 1);
+        let entry = index.get("a.txt").unwrap();
+        assert_eq!(
+            entry.hash,
+            Blob::new(&b"ours\ntheirs\n"[..]).hash().unwrap()
+        );
+        assert_eq!(
+            fs::read(dir.path().join("a.txt.orig")).unwrap(),
+            b"<<<<<<< conflict\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_untouched_file_stays_conflicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        conflicted(&repo, "a.txt").write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"conflict\n").unwrap();
+        fs::write(
+            repo.git_dir.join("config"),
+            "[mergetool \"noop\"]\ncmd = true\n[mergetool]\nkeepBackup = false\n",
+        )
+        .unwrap();
+
+        let options = MergetoolOptions {
+            tool: Some("noop".to_string()),
+            prompt: Some(false),
+        };
+        assert!(mergetool(&repo, &options).is_err());
+        assert_eq!(
+            Index::read(repo.index_path()).unwrap().conflicts().count(),
+            1
+        );
+        assert!(!dir.path().join("a.txt.orig").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code_only_counts_when_trusted() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = |trust: bool| {
+            fs::write(
+                repo.git_dir.join("config"),
+                format!(
+                    "[mergetool \"edit\"]\ncmd = echo done > \"$MERGED\" && false\n\
+                     trustExitCode = {}\n",
+                    trust
+                ),
+            )
+            .unwrap();
+        };
+        let options = MergetoolOptions {
+            tool: Some("edit".to_string()),
+            prompt: Some(false),
+        };
+
+        // Trusted: the failure wins even though the file changed
+        conflicted(&repo, "a.txt").write(repo.index_path()).unwrap();
+        fs::write(dir.path().join("a.txt"), b"conflict\n").unwrap();
+        config(true);
+        assert!(mergetool(&repo, &options).is_err());
+
+        // Untrusted: the changed file counts as resolved
+        fs::write(dir.path().join("a.txt"), b"conflict\n").unwrap();
+        config(false);
+        mergetool(&repo, &options).unwrap();
+        let index = Index::read(repo.index_path()).unwrap();
+        assert_eq!(index.conflicts().count(), 0);
+        assert_eq!(
+            index.get("a.txt").unwrap().hash,
+            Blob::new(&b"done\n"[..]).hash().unwrap()
+        );
+    }
+}
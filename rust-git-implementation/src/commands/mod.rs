@@ -2,7 +2,35 @@
 //!
 //! This module contains implementations of Git commands like init, add, commit, etc.
 
+pub mod blame;
+pub mod branch;
+pub mod check_mailmap;
+pub mod checkout;
+pub mod checkout_index;
+pub mod commit_tree;
+pub mod compat_check;
+pub mod diff_files;
+pub mod diff_index;
+pub mod diff_tree;
+pub mod difftool;
+pub mod fetch;
+pub mod fetch_pack;
+pub mod fsck;
+pub mod grep;
 pub mod init;
+pub mod ls_files;
+pub mod mergetool;
+pub mod read_tree;
+pub mod replace;
+pub mod rev_parse;
+pub mod sparse_checkout;
+pub mod status;
+pub mod stripspace;
+pub mod tag;
+pub mod update_index;
+pub mod update_server_info;
+pub mod upload_pack;
+pub mod var;
 // Uncomment as you implement each command
 // pub mod hash_object;
 // pub mod cat_file;
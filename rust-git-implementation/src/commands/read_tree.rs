@@ -0,0 +1,279 @@
+//! Read a tree into the index
+
+use crate::commands::checkout::checkout_entry;
+use crate::commands::checkout_index::is_up_to_date;
+use crate::index::{Index, IndexEntry};
+use crate::objects::FileMode;
+use crate::quote;
+use crate::repository::Repository;
+use crate::revision;
+use crate::status::{tree_files, TreeFiles};
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::fs;
+
+/// Options for `read-tree`
+#[derive(Debug, Clone, Default)]
+pub struct ReadTreeOptions {
+    /// `-m`: keep the stat data of entries the tree doesn't change, and
+    /// refuse to run over unmerged entries
+    pub merge: bool,
+    /// `-u`: update the working tree to match; needs `-m` or `--prefix`
+    pub update: bool,
+    /// `--prefix`: add the tree below this directory of the current index
+    pub prefix: Option<String>,
+}
+
+/// Whether the worktree file still has the content of `entry`
+///
+/// A missing file counts as up to date, as there is nothing to lose.
+fn unchanged(worktree: &dyn WorkTree, entry: &IndexEntry) -> Result<bool> {
+    let file = path_from_bytes(&entry.path)?;
+    Ok(!worktree.exists(file) || is_up_to_date(worktree, file, entry))
+}
+
+/// The index after reading `files` below `prefix` of `old`
+fn with_prefix(old: &Index, files: TreeFiles, prefix: &str) -> Result<Index> {
+    let dir = prefix.trim_end_matches('/');
+    let under = format!("{}/", dir);
+    if let Some(entry) = old
+        .entries()
+        .iter()
+        .find(|e| e.path == dir.as_bytes() || e.path.starts_with(under.as_bytes()))
+    {
+        anyhow::bail!(
+            "subdirectory '{}' already exists",
+            quote::display(&entry.path)
+        );
+    }
+
+    let mut index = old.clone();
+    for (path, (mode, hash)) in files {
+        index.add(IndexEntry::new(
+            [under.as_bytes(), &path].concat(),
+            mode,
+            hash,
+        ));
+    }
+    Ok(index)
+}
+
+/// Read a tree into the index, optionally updating the working tree
+///
+/// Without `--prefix` the index is replaced by the tree's files. With
+/// `-u`, files that change are checked out and files that leave the index
+/// are removed, but only after checking that none of them has local
+/// changes and that no untracked file is in the way.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `tree` - Hash of the tree to read
+/// * `options` - How to combine the tree with the index
+pub fn read_tree(repo: &Repository, tree: &str, options: &ReadTreeOptions) -> Result<()> {
+    if options.update && !options.merge && options.prefix.is_none() {
+        anyhow::bail!("-u is meaningless without -m or --prefix");
+    }
+    let old = repo.read_index()?;
+    if (options.merge || options.prefix.is_some()) && old.entries().iter().any(|e| e.stage != 0) {
+        anyhow::bail!("you need to resolve your current index first");
+    }
+
+    let files = tree_files(repo, tree)?;
+    let mut index = match &options.prefix {
+        Some(prefix) => with_prefix(&old, files, prefix)?,
+        None => {
+            let mut index = Index::new();
+            index.version = old.version;
+            index.set_ignore_case(old.ignore_case());
+            for (path, (mode, hash)) in files {
+                let entry = match old.get(&path) {
+                    Some(entry) if options.merge && entry.mode == mode && entry.hash == hash => {
+                        entry.clone()
+                    }
+                    _ => IndexEntry::new(path, mode, hash),
+                };
+                index.add(entry);
+            }
+            index
+        }
+    };
+
+    if options.update {
+        let mut worktree = repo.worktree()?;
+        let stale = |entry: &IndexEntry| {
+            old.get(&entry.path)
+                .is_none_or(|e| e.mode != entry.mode || e.hash != entry.hash)
+        };
+        let changed: Vec<IndexEntry> = index
+            .entries()
+            .iter()
+            .filter(|e| stale(e))
+            .cloned()
+            .collect();
+        let removed: Vec<&IndexEntry> = old
+            .entries()
+            .iter()
+            .filter(|e| index.get(&e.path).is_none())
+            .collect();
+
+        for entry in &changed {
+            match old.get(&entry.path) {
+                Some(previous) if !unchanged(&worktree, previous)? => anyhow::bail!(
+                    "Entry '{}' not uptodate. Cannot merge.",
+                    quote::display(&entry.path)
+                ),
+                None if worktree.exists(path_from_bytes(&entry.path)?) => anyhow::bail!(
+                    "Untracked working tree file '{}' would be overwritten by merge.",
+                    quote::display(&entry.path)
+                ),
+                _ => {}
+            }
+        }
+        for entry in &removed {
+            if !unchanged(&worktree, entry)? {
+                anyhow::bail!(
+                    "Entry '{}' not uptodate. Cannot merge.",
+                    quote::display(&entry.path)
+                );
+            }
+        }
+
+        for entry in removed {
+            let file = path_from_bytes(&entry.path)?;
+            if worktree.exists(file) {
+                worktree.remove(file)?;
+            }
+        }
+        for entry in changed {
+            // Submodules are left for the submodule commands
+            if entry.mode == FileMode::Gitlink {
+                continue;
+            }
+            let file = path_from_bytes(&entry.path)?;
+            checkout_entry(repo, &mut worktree, file, &entry)?;
+            if let (Some(entry), Ok(metadata)) = (
+                index.get_mut(&entry.path),
+                fs::symlink_metadata(worktree.root().join(file)),
+            ) {
+                entry.set_stat(&metadata);
+            }
+        }
+    }
+
+    repo.write_index(&index)
+}
+
+/// Read a tree into the index
+///
+/// # Arguments
+///
+/// * `trees` - The tree-ish to read; merges of several trees are not
+///   supported
+/// * `options` - How to combine the tree with the index
+pub fn run(trees: &[String], options: &ReadTreeOptions) -> Result<()> {
+    let [tree] = trees else {
+        anyhow::bail!("read-tree reads exactly one tree; merging several is not supported");
+    };
+    let repo = Repository::new(".")?;
+    read_tree(&repo, &revision::resolve_tree(&repo, tree)?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    fn paths(index: &Index) -> Vec<String> {
+        index
+            .entries()
+            .iter()
+            .map(|e| String::from_utf8(e.path.clone()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_read_tree_and_prefix() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n").file("dir/b.txt", "b\n");
+        let commit = builder.commit("first").unwrap();
+        let repo = builder.repo();
+        let tree = repo.read_commit(&commit).unwrap().tree.clone();
+
+        read_tree(repo, &tree, &Default::default()).unwrap();
+        let index = repo.read_index().unwrap();
+        assert_eq!(paths(&index), ["a.txt", "dir/b.txt"]);
+        assert!(!builder.path().join("a.txt").exists());
+
+        let options = ReadTreeOptions {
+            prefix: Some("copy/".to_string()),
+            update: true,
+            ..Default::default()
+        };
+        read_tree(repo, &tree, &options).unwrap();
+        let index = repo.read_index().unwrap();
+        assert_eq!(
+            paths(&index),
+            ["a.txt", "copy/a.txt", "copy/dir/b.txt", "dir/b.txt"]
+        );
+        assert_eq!(
+            fs::read(builder.path().join("copy/dir/b.txt")).unwrap(),
+            b"b\n"
+        );
+        assert!(read_tree(repo, &tree, &options).is_err());
+
+        let options = ReadTreeOptions {
+            update: true,
+            ..Default::default()
+        };
+        assert!(read_tree(repo, &tree, &options).is_err());
+    }
+
+    #[test]
+    fn test_merge_and_update() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder
+            .file("a.txt", "a\n")
+            .file("gone.txt", "gone\n")
+            .file("same.txt", "same\n");
+        let first = builder.commit("first").unwrap();
+        builder
+            .remove("gone.txt")
+            .file("a.txt", "changed\n")
+            .file("new.txt", "new\n");
+        let second = builder.commit("second").unwrap();
+        let repo = builder.repo();
+        let tree = |commit: &str| repo.read_commit(commit).unwrap().tree.clone();
+        let options = ReadTreeOptions {
+            merge: true,
+            update: true,
+            ..Default::default()
+        };
+
+        read_tree(repo, &tree(&first), &options).unwrap();
+        let before = repo.read_index().unwrap();
+        assert!(before.get("same.txt").unwrap().size > 0);
+
+        // Local changes to a file the tree changes stop the update
+        let a = builder.path().join("a.txt");
+        fs::write(&a, b"local\n").unwrap();
+        assert!(read_tree(repo, &tree(&second), &options).is_err());
+        fs::write(&a, b"a\n").unwrap();
+
+        // So does an untracked file in the way
+        let new = builder.path().join("new.txt");
+        fs::write(&new, b"untracked\n").unwrap();
+        assert!(read_tree(repo, &tree(&second), &options).is_err());
+        fs::remove_file(&new).unwrap();
+
+        read_tree(repo, &tree(&second), &options).unwrap();
+        let index = repo.read_index().unwrap();
+        assert_eq!(paths(&index), ["a.txt", "new.txt", "same.txt"]);
+        assert_eq!(fs::read(&a).unwrap(), b"changed\n");
+        assert_eq!(fs::read(&new).unwrap(), b"new\n");
+        assert!(!builder.path().join("gone.txt").exists());
+        // Unchanged entries keep their stat data
+        assert_eq!(index.get("same.txt"), before.get("same.txt"));
+    }
+}
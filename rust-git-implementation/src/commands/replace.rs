@@ -0,0 +1,384 @@
+//! Create, list and delete replace refs (`oxid replace`)
+//!
+//! A ref `refs/replace/<hash>` makes every read of `<hash>` return the
+//! object it points to instead, without rewriting the history that refers
+//! to `<hash>` (see [`Repository::replacement_of`]). `--graft` uses this to
+//! give a commit different parents, which is how histories are joined or
+//! cut after the fact.
+//!
+//! The command itself works on objects as stored, so existing replacements
+//! don't get in the way of creating or inspecting new ones.
+
+use crate::objects::{Commit, GitObject, ObjectType};
+use crate::repository::{replace_ref_base, Repository};
+use crate::revision::{resolve, resolve_commit};
+use anyhow::{Context, Result};
+use std::str::FromStr;
+
+/// How `--list` shows each replacement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ListFormat {
+    /// `<object>`
+    #[default]
+    Short,
+    /// `<object> -> <replacement>`
+    Medium,
+    /// `<object> (<type>) -> <replacement> (<type>)`
+    Long,
+}
+
+impl FromStr for ListFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "short" => Ok(ListFormat::Short),
+            "medium" => Ok(ListFormat::Medium),
+            "long" => Ok(ListFormat::Long),
+            _ => anyhow::bail!(
+                "invalid replace format '{}'; valid formats are 'short', 'medium' and 'long'",
+                s
+            ),
+        }
+    }
+}
+
+/// What `oxid replace` was asked to do
+#[derive(Debug, Clone)]
+pub enum ReplaceAction {
+    /// Make reads of `object` return `replacement`
+    Create {
+        object: String,
+        replacement: String,
+        force: bool,
+    },
+    /// Replace `commit` with a copy that has other parents
+    Graft {
+        commit: String,
+        parents: Vec<String>,
+        force: bool,
+    },
+    /// Delete the replace refs of these objects
+    Delete(Vec<String>),
+    /// List replacements whose object matches the pattern
+    List {
+        pattern: Option<String>,
+        format: ListFormat,
+    },
+}
+
+/// The replacements in the repository, as `(object, replacement)` pairs
+/// sorted by object
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `pattern` - Glob the replaced object's hash must match
+pub fn list_replacements(
+    repo: &Repository,
+    pattern: Option<&str>,
+) -> Result<Vec<(String, String)>> {
+    let base = replace_ref_base();
+    Ok(crate::refs::read_refs(&repo.git_dir)?
+        .into_iter()
+        .filter_map(|(name, replacement)| {
+            let object = name.strip_prefix(base.as_str())?.to_string();
+            Some((object, replacement))
+        })
+        .filter(|(object, _)| {
+            pattern.is_none_or(|p| crate::ignore::wildmatch(p.as_bytes(), object.as_bytes()))
+        })
+        .collect())
+}
+
+/// Format replacements for `--list`
+///
+/// # Arguments
+///
+/// * `repo` - The repository, for object types in the long format
+/// * `replacements` - `(object, replacement)` pairs
+/// * `format` - How much to show of each
+pub fn format_list(
+    repo: &Repository,
+    replacements: &[(String, String)],
+    format: ListFormat,
+) -> Result<String> {
+    let kind = |hash: &str| -> Result<ObjectType> {
+        Ok(repo
+            .odb()
+            .header(hash)
+            .with_context(|| format!("failed to resolve '{}' as a valid ref", hash))?
+            .0)
+    };
+
+    let mut out = String::new();
+    for (object, replacement) in replacements {
+        match format {
+            ListFormat::Short => out.push_str(object),
+            ListFormat::Medium => out.push_str(&format!("{} -> {}", object, replacement)),
+            ListFormat::Long => out.push_str(&format!(
+                "{} ({}) -> {} ({})",
+                object,
+                kind(object)?.as_str(),
+                replacement,
+                kind(replacement)?.as_str()
+            )),
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// The replace ref for an object, checking that it may be written
+fn replace_ref(repo: &Repository, object: &str, force: bool) -> Result<String> {
+    let name = format!("{}{}", replace_ref_base(), object);
+    if !force && crate::refs::read_refs(&repo.git_dir)?.contains_key(&name) {
+        anyhow::bail!("replace ref '{}' already exists", name);
+    }
+    Ok(name)
+}
+
+/// Make reads of one object return another
+///
+/// Both objects must exist and have the same type.
+///
+/// # Arguments
+///
+/// * `repo` - The repository, with replacement turned off
+/// * `object` - The object to replace
+/// * `replacement` - The object to read in its place
+/// * `force` - Overwrite an existing replacement of `object`
+///
+/// # Returns
+///
+/// The name of the replace ref written
+pub fn replace(repo: &Repository, object: &str, replacement: &str, force: bool) -> Result<String> {
+    let object = resolve(repo, object)?;
+    let replacement = resolve(repo, replacement)?;
+    if object == replacement {
+        anyhow::bail!("new object is the same as the old one: '{}'", object);
+    }
+
+    let (object_kind, _) = repo.object_header(&object)?;
+    let (replacement_kind, _) = repo.object_header(&replacement)?;
+    if object_kind != replacement_kind {
+        anyhow::bail!(
+            "Objects must be of the same type.\n'{}' points to a replaced object of type '{}'\nwhile '{}' points to a replacement object of type '{}'.",
+            object,
+            object_kind.as_str(),
+            replacement,
+            replacement_kind.as_str()
+        );
+    }
+
+    let name = replace_ref(repo, &object, force)?;
+    repo.write_ref(&name, &replacement)?;
+    Ok(name)
+}
+
+/// Replace a commit with a copy that has different parents
+///
+/// The copy keeps everything else, except a signature, which would no
+/// longer match; a warning is printed when one is dropped.
+///
+/// # Arguments
+///
+/// * `repo` - The repository, with replacement turned off
+/// * `commit` - The commit to graft
+/// * `parents` - Its new parents; none makes it a root commit
+/// * `force` - Overwrite an existing replacement of `commit`
+///
+/// # Returns
+///
+/// The hash of the new commit
+pub fn graft(repo: &Repository, commit: &str, parents: &[String], force: bool) -> Result<String> {
+    let original = resolve_commit(repo, commit)?;
+    let mut grafted = Commit::parse(&repo.read_object(&original)?)
+        .with_context(|| format!("could not parse {}", original))?;
+    grafted.parents = parents
+        .iter()
+        .map(|parent| resolve_commit(repo, parent))
+        .collect::<Result<_>>()?;
+    if grafted.gpgsig.take().is_some() {
+        eprintln!(
+            "warning: the original commit '{}' has a gpg signature\n\
+             warning: the signature will be removed in the replacement commit!",
+            commit
+        );
+    }
+
+    let data = grafted.to_bytes()?;
+    let hash = crate::utils::hash_data(&data);
+    if hash == original {
+        anyhow::bail!("new commit is the same as the old one: '{}'", original);
+    }
+    let name = replace_ref(repo, &original, force)?;
+    repo.write_object(&hash, &data)?;
+    repo.write_ref(&name, &hash)?;
+    Ok(hash)
+}
+
+/// Delete the replacement of an object
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `object` - The replaced object: its hash, or a name resolving to it
+///
+/// # Returns
+///
+/// The name of the deleted ref
+pub fn delete(repo: &Repository, object: &str) -> Result<String> {
+    let object = resolve(repo, object)?;
+    let name = format!("{}{}", replace_ref_base(), object);
+    if !crate::refs::read_refs(&repo.git_dir)?.contains_key(&name) {
+        anyhow::bail!("replace ref '{}' not found", object);
+    }
+    repo.delete_ref(&name)?;
+    Ok(name)
+}
+
+/// Create, delete or list replace refs
+///
+/// # Arguments
+///
+/// * `action` - What to do
+pub fn run(action: &ReplaceAction) -> Result<()> {
+    let mut repo = Repository::new(".")?;
+    repo.set_replace_objects(false);
+
+    match action {
+        ReplaceAction::Create {
+            object,
+            replacement,
+            force,
+        } => {
+            replace(&repo, object, replacement, *force)?;
+        }
+        ReplaceAction::Graft {
+            commit,
+            parents,
+            force,
+        } => {
+            graft(&repo, commit, parents, *force)?;
+        }
+        ReplaceAction::Delete(objects) => {
+            let mut failed = false;
+            for object in objects {
+                match delete(&repo, object) {
+                    Ok(name) => println!("Deleted replace ref '{}'", name_object(&name)),
+                    Err(e) => {
+                        eprintln!("error: {:#}", e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        ReplaceAction::List { pattern, format } => {
+            let replacements = list_replacements(&repo, pattern.as_deref())?;
+            print!("{}", format_list(&repo, &replacements, *format)?);
+        }
+    }
+    Ok(())
+}
+
+/// The replaced object's hash in a replace ref name
+fn name_object(name: &str) -> &str {
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_replace_blob() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder.file("a.txt", "old\n").commit("one").unwrap();
+        let head = builder.file("b.txt", "new\n").commit("two").unwrap();
+        let mut repo = Repository::new(builder.path()).unwrap();
+        repo.set_replace_objects(false);
+        let old = crate::utils::hash_data(b"blob 4\0old\n");
+        let new = crate::utils::hash_data(b"blob 4\0new\n");
+
+        replace(&repo, &old, &new, false).unwrap();
+        let err = replace(&repo, &old, &new, false).unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        let err = replace(&repo, &old, &head, true).unwrap_err();
+        assert!(err.to_string().contains("same type"), "{}", err);
+        assert_eq!(repo.read_object(&old).unwrap(), b"blob 4\0old\n");
+
+        // Reads of the old blob now return the new one
+        let replaced = Repository::new(builder.path()).unwrap();
+        assert_eq!(replaced.read_object(&old).unwrap(), b"blob 4\0new\n");
+        assert_eq!(replaced.object_header(&old).unwrap().1, 4);
+        assert_eq!(replaced.read_stored_object(&old).unwrap(), b"blob 4\0old\n");
+
+        let replacements = list_replacements(&repo, None).unwrap();
+        assert_eq!(replacements, vec![(old.clone(), new.clone())]);
+        assert_eq!(
+            format_list(&repo, &replacements, ListFormat::Long).unwrap(),
+            format!("{} (blob) -> {} (blob)\n", old, new)
+        );
+        let pattern = format!("{}*", &old[..4]);
+        assert_eq!(list_replacements(&repo, Some(&pattern)).unwrap().len(), 1);
+        assert!(list_replacements(&repo, Some("xyz*")).unwrap().is_empty());
+
+        assert_eq!(
+            delete(&repo, &old).unwrap(),
+            format!("refs/replace/{}", old)
+        );
+        assert!(delete(&repo, &old).is_err());
+        assert!(list_replacements(&repo, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_graft() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("a.txt", "a\n").commit("one").unwrap();
+        let second = builder.file("a.txt", "b\n").commit("two").unwrap();
+        let third = builder.file("a.txt", "c\n").commit("three").unwrap();
+        let mut repo = Repository::new(builder.path()).unwrap();
+        repo.set_replace_objects(false);
+
+        // Cut out the second commit
+        let grafted = graft(&repo, &third, std::slice::from_ref(&first), false).unwrap();
+
+        let repo = Repository::new(builder.path()).unwrap();
+        assert_eq!(repo.replacement_of(&third).unwrap(), grafted);
+        assert_eq!(repo.read_commit(&third).unwrap().parents, vec![first]);
+        assert_eq!(repo.read_commit(&third).unwrap().message, "three\n");
+        assert_eq!(repo.replacement_of(&second).unwrap(), second);
+    }
+
+    #[test]
+    fn test_replace_depth() {
+        let builder = RepoBuilder::new().unwrap();
+        let repo = builder.repo();
+        let hashes: Vec<String> = (0..7)
+            .map(|i| {
+                let data = format!("blob 1\0{}", i).into_bytes();
+                let hash = crate::utils::hash_data(&data);
+                repo.write_object(&hash, &data).unwrap();
+                hash
+            })
+            .collect();
+        for pair in hashes.windows(2) {
+            repo.write_ref(&format!("refs/replace/{}", pair[0]), &pair[1])
+                .unwrap();
+        }
+
+        let repo = Repository::new(builder.path()).unwrap();
+        assert_eq!(repo.replacement_of(&hashes[1]).unwrap(), hashes[6]);
+        let err = repo.replacement_of(&hashes[0]).unwrap_err();
+        assert!(
+            err.to_string().contains("replace depth too high"),
+            "{}",
+            err
+        );
+    }
+}
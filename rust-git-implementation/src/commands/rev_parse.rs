@@ -0,0 +1,149 @@
+//! Repository introspection for scripts and shell prompts
+
+use crate::repository::Repository;
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Options for `rev-parse`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RevParseOptions {
+    /// `--show-toplevel`: absolute path of the worktree root
+    pub show_toplevel: bool,
+    /// `--git-dir`: path of the git dir
+    pub git_dir: bool,
+    /// `--is-inside-git-dir`
+    pub is_inside_git_dir: bool,
+    /// `--is-inside-work-tree`
+    pub is_inside_work_tree: bool,
+    /// `--is-bare-repository`
+    pub is_bare_repository: bool,
+    /// `--is-shallow-repository`
+    pub is_shallow_repository: bool,
+}
+
+/// Answer the requested questions about a repository
+///
+/// Returns one line per requested option, in the order of the fields of
+/// [`RevParseOptions`].
+///
+/// # Arguments
+///
+/// * `repo` - The repository `cwd` belongs to
+/// * `cwd` - The directory the command runs in (canonicalized)
+/// * `options` - What to show
+pub fn rev_parse(repo: &Repository, cwd: &Path, options: &RevParseOptions) -> Result<Vec<String>> {
+    let inside_git_dir = cwd.starts_with(&repo.git_dir);
+    let inside_work_tree = !repo.bare && !inside_git_dir && cwd.starts_with(&repo.work_tree);
+
+    let mut lines = Vec::new();
+    if options.show_toplevel {
+        if repo.bare {
+            anyhow::bail!("this operation must be run in a work tree");
+        }
+        lines.push(repo.work_tree.display().to_string());
+    }
+    if options.git_dir {
+        // Like Git, keep the short form when run from the top of the worktree
+        let git_dir = if cwd == repo.work_tree && repo.git_dir == repo.work_tree.join(".git") {
+            PathBuf::from(".git")
+        } else if cwd == repo.git_dir {
+            PathBuf::from(".")
+        } else {
+            repo.git_dir.clone()
+        };
+        lines.push(git_dir.display().to_string());
+    }
+    if options.is_inside_git_dir {
+        lines.push(inside_git_dir.to_string());
+    }
+    if options.is_inside_work_tree {
+        lines.push(inside_work_tree.to_string());
+    }
+    if options.is_bare_repository {
+        let bare = repo.config()?.get_bool("core.bare")?.unwrap_or(repo.bare);
+        lines.push(bare.to_string());
+    }
+    if options.is_shallow_repository {
+        lines.push(repo.is_shallow()?.to_string());
+    }
+    Ok(lines)
+}
+
+/// Print information about the current repository
+///
+/// # Arguments
+///
+/// * `options` - What to show
+pub fn run(options: &RevParseOptions) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let cwd = std::env::current_dir()?.canonicalize()?;
+    for line in rev_parse(&repo, &cwd, options)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn all() -> RevParseOptions {
+        RevParseOptions {
+            show_toplevel: false,
+            git_dir: true,
+            is_inside_git_dir: true,
+            is_inside_work_tree: true,
+            is_bare_repository: true,
+            is_shallow_repository: true,
+        }
+    }
+
+    #[test]
+    fn test_rev_parse_in_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        let sub = sub.canonicalize().unwrap();
+        let repo = Repository::new(&sub).unwrap();
+
+        let options = RevParseOptions {
+            show_toplevel: true,
+            ..all()
+        };
+        assert_eq!(
+            rev_parse(&repo, &sub, &options).unwrap(),
+            vec![
+                repo.work_tree.display().to_string(),
+                repo.git_dir.display().to_string(),
+                "false".to_string(),
+                "true".to_string(),
+                "false".to_string(),
+                "false".to_string(),
+            ]
+        );
+        assert_eq!(
+            rev_parse(&repo, &repo.work_tree, &all()).unwrap()[0],
+            ".git"
+        );
+    }
+
+    #[test]
+    fn test_rev_parse_in_git_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        let git_dir = dir.path().join(".git").canonicalize().unwrap();
+        let repo = Repository::new(&git_dir).unwrap();
+
+        assert_eq!(
+            rev_parse(&repo, &git_dir, &all()).unwrap(),
+            vec![".", "true", "false", "false", "false"]
+        );
+        let options = RevParseOptions {
+            show_toplevel: true,
+            ..Default::default()
+        };
+        assert!(rev_parse(&repo, &git_dir, &options).is_err());
+    }
+}
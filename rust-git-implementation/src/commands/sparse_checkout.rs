@@ -0,0 +1,200 @@
+//! Show and apply sparse checkout patterns (`oxid sparse-checkout`)
+//!
+//! Turning sparse checkout on and editing the patterns is left to
+//! `git sparse-checkout` or to editing `.git/info/sparse-checkout` by
+//! hand; `reapply` then brings the working tree in line. See
+//! [`crate::sparse`] for both pattern forms.
+
+use crate::commands::checkout::checkout_entry;
+use crate::index::Index;
+use crate::objects::{Blob, FileMode, GitObject};
+use crate::quote::{self, PathFormat};
+use crate::repository::Repository;
+use crate::sparse::Sparse;
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// What [`reapply`] changed in the working tree
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reapplied {
+    /// Files written because they are now included
+    pub checked_out: Vec<Vec<u8>>,
+    /// Files removed because they are now excluded
+    pub removed: Vec<Vec<u8>>,
+    /// Excluded files kept because they have changes
+    pub kept: Vec<Vec<u8>>,
+}
+
+/// Update the index and working tree to match sparse patterns
+///
+/// Included entries lose their skip-worktree bit and are checked out if
+/// missing; excluded ones get the bit and their files are removed, along
+/// with directories left empty. Excluded files that differ from the index
+/// are kept, as are unmerged entries.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `index` - The index to update
+/// * `worktree` - The working tree
+/// * `sparse` - The patterns to apply
+pub fn reapply(
+    repo: &Repository,
+    index: &mut Index,
+    worktree: &mut dyn WorkTree,
+    sparse: &Sparse,
+) -> Result<Reapplied> {
+    let mut reapplied = Reapplied::default();
+    let paths: Vec<Vec<u8>> = index
+        .entries()
+        .iter()
+        .filter(|entry| entry.stage == 0)
+        .map(|entry| entry.path.clone())
+        .collect();
+
+    for path in paths {
+        let file = path_from_bytes(&path)?;
+        let Some(entry) = index.get_mut(&path) else {
+            continue;
+        };
+        if sparse.includes(&path) {
+            if !entry.skip_worktree {
+                continue;
+            }
+            entry.skip_worktree = false;
+            if !worktree.exists(file) {
+                checkout_entry(repo, worktree, file, entry)?;
+                if let Ok(metadata) = fs::symlink_metadata(repo.work_tree.join(file)) {
+                    entry.set_stat(&metadata);
+                }
+                reapplied.checked_out.push(path);
+            }
+        } else if !entry.skip_worktree {
+            if worktree.exists(file) {
+                let hash = if entry.mode == FileMode::Symlink {
+                    Blob::new(worktree.read_link(file)?).hash()?
+                } else {
+                    worktree.hash_file(file)?
+                };
+                if hash != entry.hash {
+                    reapplied.kept.push(path);
+                    continue;
+                }
+                worktree.remove(file)?;
+                remove_empty_parents(&repo.work_tree, file);
+                reapplied.removed.push(path);
+            }
+            entry.skip_worktree = true;
+        }
+    }
+    Ok(reapplied)
+}
+
+/// Remove the directories above a deleted file that are now empty
+fn remove_empty_parents(root: &Path, file: &Path) {
+    let mut dir = file.parent();
+    while let Some(parent) = dir.filter(|d| !d.as_os_str().is_empty()) {
+        if fs::remove_dir(root.join(parent)).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+}
+
+/// Print the sparse checkout patterns
+///
+/// In cone mode the included directories are listed, one per line;
+/// otherwise the pattern file is printed as it is.
+pub fn list() -> Result<()> {
+    let repo = Repository::new(".")?;
+    let sparse = Sparse::for_repo(&repo)?
+        .context("this worktree is not sparse (sparse-checkout file may not exist)")?;
+    let mut out = std::io::stdout().lock();
+    match sparse {
+        Sparse::Cone { recursive, .. } => {
+            let paths = PathFormat::from_config(&repo.config()?, false)?;
+            for dir in recursive {
+                paths.write_line(&mut out, &dir)?;
+            }
+        }
+        Sparse::Patterns(_) => {
+            out.write_all(&fs::read(repo.git_dir.join("info/sparse-checkout"))?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Bring the working tree in line with the sparse checkout patterns
+pub fn run_reapply() -> Result<()> {
+    let repo = Repository::new(".")?;
+    let sparse = Sparse::for_repo(&repo)?
+        .context("must be in a sparse-checkout to reapply sparsity patterns")?;
+    let mut index = Index::read(repo.index_path())?;
+    let reapplied = reapply(&repo, &mut index, &mut repo.worktree()?, &sparse)?;
+    repo.write_index(&index)?;
+
+    if !reapplied.kept.is_empty() {
+        eprintln!(
+            "warning: The following paths are not up to date and were left despite sparse patterns:"
+        );
+        for path in &reapplied.kept {
+            eprintln!("\t{}", quote::display(path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{stage, store};
+
+    /// Stage a file, with its blob, and check it out
+    fn add_file(repo: &Repository, index: &mut Index, path: &str, content: &[u8]) {
+        store(repo, content).unwrap();
+        stage(index, path, content).unwrap();
+        let file = repo.work_tree.join(path);
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(file, content).unwrap();
+    }
+
+    #[test]
+    fn test_reapply() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = Index::new();
+        add_file(&repo, &mut index, "README", b"readme\n");
+        add_file(&repo, &mut index, "docs/a.md", b"a\n");
+        add_file(&repo, &mut index, "src/main.rs", b"main\n");
+        add_file(&repo, &mut index, "src/lib.rs", b"lib\n");
+        fs::write(dir.path().join("src/lib.rs"), b"edited\n").unwrap();
+
+        // Full patterns, as a checked-in legacy file would have them
+        let sparse = Sparse::parse(b"/*\n!/*/\n/docs/\n", false);
+        let mut worktree = repo.worktree().unwrap();
+        let reapplied = reapply(&repo, &mut index, &mut worktree, &sparse).unwrap();
+        assert_eq!(reapplied.removed, vec![b"src/main.rs".to_vec()]);
+        assert_eq!(reapplied.kept, vec![b"src/lib.rs".to_vec()]);
+        assert!(index.get("src/main.rs").unwrap().skip_worktree);
+        assert!(!index.get("src/lib.rs").unwrap().skip_worktree);
+        assert!(!index.get("docs/a.md").unwrap().skip_worktree);
+        assert!(!dir.path().join("src/main.rs").exists());
+
+        // Widening the patterns brings the file back
+        let sparse = Sparse::parse(b"/*\n!/*/\n/docs/\n/src/\n", true);
+        let reapplied = reapply(&repo, &mut index, &mut worktree, &sparse).unwrap();
+        assert_eq!(reapplied.checked_out, vec![b"src/main.rs".to_vec()]);
+        assert_eq!(fs::read(dir.path().join("src/main.rs")).unwrap(), b"main\n");
+        assert!(!index.entries().iter().any(|e| e.skip_worktree));
+
+        // Removing the last file of a directory removes the directory
+        let sparse = Sparse::parse(b"/*\n!/*/\n/src/\n", true);
+        let reapplied = reapply(&repo, &mut index, &mut worktree, &sparse).unwrap();
+        assert_eq!(reapplied.removed, vec![b"docs/a.md".to_vec()]);
+        assert!(!dir.path().join("docs").exists());
+    }
+}
@@ -0,0 +1,433 @@
+//! Show the working tree status
+
+use crate::config::Config;
+use crate::ignore::Ignore;
+use crate::quote::PathFormat;
+use crate::repository::Repository;
+use crate::status::{tree_files, IgnoreSubmodules, Status, TreeFiles};
+use anyhow::Result;
+use std::fs;
+use std::io::Write;
+
+/// Output format for `status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusFormat {
+    /// Sections with hints, for humans
+    #[default]
+    Long,
+    /// `XY path` lines (`-s`)
+    Short,
+    /// Short format that stays stable across versions (`--porcelain`);
+    /// modified submodules are always `M`
+    Porcelain,
+}
+
+/// Name of the branch `HEAD` points to, or `None` when detached
+fn head_branch(repo: &Repository) -> Result<Option<String>> {
+    let head = fs::read_to_string(repo.git_dir.join("HEAD"))?;
+    Ok(head
+        .trim_end()
+        .strip_prefix("ref: refs/heads/")
+        .map(str::to_string))
+}
+
+/// Whether status should detect renames (`status.renames`, falling back
+/// to `diff.renames`; on by default)
+fn renames_enabled(config: &Config) -> Result<bool> {
+    for key in ["status.renames", "diff.renames"] {
+        match config.get(key) {
+            // Copy detection isn't supported; renames still are
+            Some(value) if value.eq_ignore_ascii_case("copies") => return Ok(true),
+            Some(_) => return Ok(config.get_bool(key)?.unwrap_or(true)),
+            None => {}
+        }
+    }
+    Ok(true)
+}
+
+/// Write a path, or `old -> new` for a rename
+///
+/// With `-z`, a rename is written as the new path, NUL and the old path,
+/// as Git does.
+fn push_path(out: &mut Vec<u8>, status: &Status, path: &[u8], paths: PathFormat) {
+    match (status.renames.get(path), paths) {
+        (Some(from), PathFormat::Nul) => {
+            out.extend_from_slice(path);
+            out.push(0);
+            out.extend_from_slice(from);
+        }
+        (Some(from), _) => {
+            out.extend_from_slice(&paths.path(from));
+            out.extend_from_slice(b" -> ");
+            out.extend_from_slice(&paths.path(path));
+        }
+        (None, _) => out.extend_from_slice(&paths.path(path)),
+    }
+}
+
+/// Format the status in the short format
+///
+/// The first column is the index status, the second the worktree status.
+/// A modified submodule shows `M` for new commits, `m` for modified
+/// content and `?` for untracked content.
+///
+/// # Arguments
+///
+/// * `status` - The status to show
+/// * `paths` - How to quote paths and end lines
+pub fn format_short(status: &Status, paths: PathFormat) -> Vec<u8> {
+    format_lines(status, false, paths)
+}
+
+/// Format the status for scripts (`--porcelain`)
+///
+/// Like [`format_short`], except that every modified submodule shows `M`.
+pub fn format_porcelain(status: &Status, paths: PathFormat) -> Vec<u8> {
+    format_lines(status, true, paths)
+}
+
+fn format_lines(status: &Status, porcelain: bool, paths: PathFormat) -> Vec<u8> {
+    let mut lines: Vec<(&[u8], [char; 2])> = Vec::new();
+    for (path, change) in &status.staged {
+        lines.push((path, [change.short_code(), ' ']));
+    }
+    for (path, change) in &status.unstaged {
+        let code = match status.submodules.get(path) {
+            Some(submodule) if !porcelain => submodule.short_code(),
+            _ => change.short_code(),
+        };
+        match lines.iter_mut().find(|(p, _)| p == path) {
+            Some((_, codes)) => codes[1] = code,
+            None => lines.push((path, [' ', code])),
+        }
+    }
+    for (path, kind) in &status.unmerged {
+        let mut code = kind.short_code().chars();
+        lines.push((
+            path,
+            [code.next().unwrap_or(' '), code.next().unwrap_or(' ')],
+        ));
+    }
+    lines.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut out = Vec::new();
+    for (path, [x, y]) in lines {
+        out.extend_from_slice(format!("{}{} ", x, y).as_bytes());
+        push_path(&mut out, status, path, paths);
+        out.push(paths.terminator());
+    }
+    for path in &status.untracked {
+        out.extend_from_slice(b"?? ");
+        out.extend_from_slice(&paths.path(path));
+        out.push(paths.terminator());
+    }
+    out
+}
+
+/// Format the status in the long format
+///
+/// # Arguments
+///
+/// * `status` - The status to show
+/// * `branch` - Current branch, or `None` when `HEAD` is detached
+/// * `paths` - How to quote paths
+pub fn format_long(status: &Status, branch: Option<&str>, paths: PathFormat) -> Vec<u8> {
+    let mut out = match branch {
+        Some(branch) => format!("On branch {}\n", branch),
+        None => "Not currently on any branch.\n".to_string(),
+    }
+    .into_bytes();
+    if status.unborn {
+        out.extend_from_slice(b"\nNo commits yet\n\n");
+    }
+
+    // Each section ends with a blank line
+    if !status.staged.is_empty() {
+        out.extend_from_slice(b"Changes to be committed:\n");
+        if status.unborn {
+            out.extend_from_slice(b"  (use \"oxid rm --cached <file>...\" to unstage)\n");
+        } else {
+            out.extend_from_slice(b"  (use \"oxid restore --staged <file>...\" to unstage)\n");
+        }
+        for (path, change) in &status.staged {
+            let label = format!("{}:", change.description());
+            out.extend_from_slice(format!("\t{:<12}", label).as_bytes());
+            push_path(&mut out, status, path, paths);
+            out.push(b'\n');
+        }
+        out.push(b'\n');
+    }
+
+    if !status.unmerged.is_empty() {
+        out.extend_from_slice(b"Unmerged paths:\n");
+        out.extend_from_slice(b"  (use \"oxid add <file>...\" to mark resolution)\n");
+        for (path, kind) in &status.unmerged {
+            let label = format!("{}:", kind.description());
+            out.extend_from_slice(format!("\t{:<17}", label).as_bytes());
+            out.extend_from_slice(&paths.path(path));
+            out.push(b'\n');
+        }
+        out.push(b'\n');
+    }
+
+    if !status.unstaged.is_empty() {
+        out.extend_from_slice(b"Changes not staged for commit:\n");
+        out.extend_from_slice(b"  (use \"oxid add <file>...\" to update what will be committed)\n");
+        out.extend_from_slice(
+            b"  (use \"oxid checkout -- <file>...\" to discard changes in working directory)\n",
+        );
+        if status
+            .submodules
+            .values()
+            .any(|s| s.modified_content || s.untracked_content)
+        {
+            out.extend_from_slice(
+                b"  (commit or discard the untracked or modified content in submodules)\n",
+            );
+        }
+        for (path, change) in &status.unstaged {
+            let label = format!("{}:", change.description());
+            out.extend_from_slice(format!("\t{:<12}", label).as_bytes());
+            out.extend_from_slice(&paths.path(path));
+            if let Some(submodule) = status.submodules.get(path) {
+                out.extend_from_slice(format!(" ({})", submodule.description()).as_bytes());
+            }
+            out.push(b'\n');
+        }
+        out.push(b'\n');
+    }
+
+    if !status.untracked.is_empty() {
+        out.extend_from_slice(b"Untracked files:\n");
+        out.extend_from_slice(
+            b"  (use \"oxid add <file>...\" to include in what will be committed)\n",
+        );
+        for path in &status.untracked {
+            out.push(b'\t');
+            out.extend_from_slice(&paths.path(path));
+            out.push(b'\n');
+        }
+        out.push(b'\n');
+    }
+
+    if status.staged.is_empty() && status.unmerged.is_empty() {
+        let summary = if !status.unstaged.is_empty() {
+            "no changes added to commit (use \"oxid add\" and/or \"oxid commit -a\")\n"
+        } else if !status.untracked.is_empty() {
+            "nothing added to commit but untracked files present (use \"oxid add\" to track)\n"
+        } else if status.unborn {
+            "nothing to commit (create/copy files and use \"oxid add\" to track)\n"
+        } else {
+            "nothing to commit, working tree clean\n"
+        };
+        out.extend_from_slice(summary.as_bytes());
+    }
+    out
+}
+
+/// Compute the status of a repository as `status` reports it
+///
+/// Renames are detected unless `status.renames` turns them off, and
+/// submodules are checked according to `ignore_submodules`.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `ignore_submodules` - Which submodule changes to hide; defaults to
+///   `diff.ignoreSubmodules`, or showing everything
+pub fn repo_status(
+    repo: &Repository,
+    ignore_submodules: Option<IgnoreSubmodules>,
+) -> Result<Status> {
+    let head = repo.head()?;
+    let head_files = match &head {
+        Some(commit) => tree_files(repo, &repo.read_commit(commit)?.tree)?,
+        None => TreeFiles::new(),
+    };
+
+    let index = repo.read_index()?;
+    let ignore = Ignore::for_repo(repo)?;
+    let config = repo.config()?;
+    let mut status = Status::compute(
+        &index,
+        &head_files,
+        &repo.worktree()?,
+        ignore,
+        config.get_bool("core.fileMode")?.unwrap_or(true),
+        config.get_bool("core.symlinks")?.unwrap_or(true),
+    )?;
+    status.unborn = head.is_none();
+    if renames_enabled(&config)? {
+        status.detect_renames(repo, &index, &head_files)?;
+    }
+    let ignore_submodules = match (ignore_submodules, config.get("diff.ignoreSubmodules")) {
+        (Some(ignore), _) => ignore,
+        (None, Some(value)) => IgnoreSubmodules::from_str(value)?,
+        (None, None) => IgnoreSubmodules::None,
+    };
+    status.check_submodules(repo, &index, ignore_submodules)?;
+    Ok(status)
+}
+
+/// Show the working tree status
+///
+/// # Arguments
+///
+/// * `format` - Long or short output
+/// * `ignore_submodules` - Which submodule changes to hide; defaults to
+///   `diff.ignoreSubmodules`, or showing everything
+/// * `nul` - `-z`: end entries with NUL and don't quote paths
+pub fn run(
+    format: StatusFormat,
+    ignore_submodules: Option<IgnoreSubmodules>,
+    nul: bool,
+) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    let branch = head_branch(&repo)?;
+    let status = repo_status(&repo, ignore_submodules)?;
+
+    let paths = PathFormat::from_config(&repo.config()?, nul)?;
+    let out = match format {
+        StatusFormat::Long => format_long(&status, branch.as_deref(), paths),
+        StatusFormat::Short => format_short(&status, paths),
+        StatusFormat::Porcelain => format_porcelain(&status, paths),
+    };
+    std::io::stdout().write_all(&out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::ConflictKind;
+    use crate::status::{FileChange, SubmoduleState};
+
+    fn sample() -> Status {
+        Status {
+            unborn: true,
+            staged: vec![
+                (b"a.txt".to_vec(), FileChange::Added),
+                (b"b.txt".to_vec(), FileChange::Added),
+            ],
+            unmerged: vec![(b"c.txt".to_vec(), ConflictKind::DeletedByThem)],
+            unstaged: vec![(b"b.txt".to_vec(), FileChange::Modified)],
+            untracked: vec![b"new/".to_vec()],
+            ..Default::default()
+        }
+    }
+
+    fn short(status: &Status) -> String {
+        String::from_utf8(format_short(status, PathFormat::default())).unwrap()
+    }
+
+    fn long(status: &Status) -> String {
+        String::from_utf8(format_long(status, Some("main"), PathFormat::default())).unwrap()
+    }
+
+    #[test]
+    fn test_format_short() {
+        assert_eq!(short(&sample()), "A  a.txt\nAM b.txt\nUD c.txt\n?? new/\n");
+    }
+
+    #[test]
+    fn test_format_quoted_paths() {
+        let status = Status {
+            staged: vec![(b"caf\xe9".to_vec(), FileChange::Renamed)],
+            renames: [(b"caf\xe9".to_vec(), b"tab\there".to_vec())].into(),
+            untracked: vec![b"new\nline".to_vec()],
+            ..Default::default()
+        };
+        assert_eq!(
+            short(&status),
+            "R  \"tab\\there\" -> \"caf\\351\"\n?? \"new\\nline\"\n"
+        );
+        assert!(long(&status).contains("\trenamed:    \"tab\\there\" -> \"caf\\351\"\n"));
+        assert_eq!(
+            format_porcelain(&status, PathFormat::Quoted { non_ascii: false }),
+            b"R  \"tab\\there\" -> caf\xe9\n?? \"new\\nline\"\n"
+        );
+        assert_eq!(
+            format_porcelain(&status, PathFormat::Nul),
+            b"R  caf\xe9\0tab\there\0?? new\nline\0"
+        );
+    }
+
+    #[test]
+    fn test_format_long() {
+        let out = long(&sample());
+        assert!(out.starts_with("On branch main\n\nNo commits yet\n"));
+        assert!(out.contains("\tnew file:   a.txt\n"));
+        assert!(out.contains("\tdeleted by them: c.txt\n"));
+        assert!(out.contains("\tmodified:   b.txt\n"));
+        assert!(out.contains("Untracked files:\n"));
+        assert!(out.contains("\tnew/\n"));
+    }
+
+    #[test]
+    fn test_format_long_clean() {
+        let unborn = Status {
+            unborn: true,
+            ..Default::default()
+        };
+        let out = long(&unborn);
+        assert!(
+            out.ends_with("nothing to commit (create/copy files and use \"oxid add\" to track)\n")
+        );
+
+        let out = long(&Status::default());
+        assert_eq!(
+            out,
+            "On branch main\nnothing to commit, working tree clean\n"
+        );
+    }
+
+    #[test]
+    fn test_format_renames() {
+        let mut status = Status {
+            staged: vec![(b"new.txt".to_vec(), FileChange::Renamed)],
+            ..Default::default()
+        };
+        status
+            .renames
+            .insert(b"new.txt".to_vec(), b"old.txt".to_vec());
+        assert_eq!(short(&status), "R  old.txt -> new.txt\n");
+        assert!(long(&status).contains("\trenamed:    old.txt -> new.txt\n"));
+
+        assert!(renames_enabled(&Config::new()).unwrap());
+        let config = Config::parse("[diff]\n\trenames = false\n").unwrap();
+        assert!(!renames_enabled(&config).unwrap());
+        let config = Config::parse("[diff]\n\trenames = false\n[status]\n\trenames = copies\n");
+        assert!(renames_enabled(&config.unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_format_submodules() {
+        let mut status = Status {
+            unstaged: vec![
+                (b"dirty".to_vec(), FileChange::Modified),
+                (b"moved".to_vec(), FileChange::Modified),
+            ],
+            ..Default::default()
+        };
+        let dirty = SubmoduleState {
+            modified_content: true,
+            untracked_content: true,
+            ..Default::default()
+        };
+        let moved = SubmoduleState {
+            new_commits: true,
+            ..Default::default()
+        };
+        status.submodules.insert(b"dirty".to_vec(), dirty);
+        status.submodules.insert(b"moved".to_vec(), moved);
+
+        assert_eq!(short(&status), " m dirty\n M moved\n");
+        assert_eq!(
+            format_porcelain(&status, PathFormat::default()),
+            b" M dirty\n M moved\n"
+        );
+        let out = long(&status);
+        assert!(out.contains("\tmodified:   dirty (modified content, untracked content)\n"));
+        assert!(out.contains("\tmodified:   moved (new commits)\n"));
+    }
+}
@@ -0,0 +1,44 @@
+//! Clean up text the way commit messages are cleaned up
+
+use crate::message;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::io::Read;
+
+/// What `stripspace` does with comment lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CommentMode {
+    /// Keep them as text
+    #[default]
+    Keep,
+    /// `-s`: remove them
+    Strip,
+    /// `-c`: turn every line into a comment
+    CommentLines,
+}
+
+/// Read text from stdin, clean it up and print it
+///
+/// The comment prefix is `core.commentChar` when run inside a repository,
+/// `#` otherwise.
+///
+/// # Arguments
+///
+/// * `mode` - How to treat comment lines
+pub fn run(mode: CommentMode) -> Result<()> {
+    let comment = Repository::open_snapshot(".")
+        .and_then(|repo| repo.config())
+        .ok()
+        .and_then(|config| config.get("core.commentChar").map(str::to_string))
+        .unwrap_or_else(|| "#".to_string());
+
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    let out = match mode {
+        CommentMode::Keep => message::stripspace(&text, None),
+        CommentMode::Strip => message::stripspace(&text, Some(&comment)),
+        CommentMode::CommentLines => message::comment_lines(&text, &comment),
+    };
+    print!("{}", out);
+    Ok(())
+}
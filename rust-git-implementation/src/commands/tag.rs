@@ -0,0 +1,185 @@
+//! List tags (`oxid tag -l`)
+//!
+//! Tags can be narrowed down by name pattern, by reachability
+//! (`--contains`, `--merged` and their negations) and by the object they
+//! point at (`--points-at`), and sorted by version number
+//! (`--sort=version:refname`), which is how scripts find the first
+//! release containing a fix. With `--verify` each tag's signature is
+//! checked and the outcome shown next to its name.
+
+use crate::gpg::Verification;
+use crate::objects::ObjectType;
+use crate::ref_filter::{ReachFilter, SortKey};
+use crate::repository::Repository;
+use crate::revision::{peel_chain, resolve};
+use anyhow::Result;
+
+/// Which tags to list and in what order
+#[derive(Debug, Clone, Default)]
+pub struct TagOptions {
+    /// Glob patterns a tag name must match one of; empty matches all
+    pub patterns: Vec<String>,
+    /// Reachability conditions on the commit each tag points to
+    pub filter: ReachFilter,
+    /// `--points-at`: revisions the tag must point at, directly or
+    /// through annotated tags
+    pub points_at: Vec<String>,
+    /// `--sort`; falls back to `tag.sort`, then to `refname`
+    pub sort: Option<String>,
+    /// Verify signatures and show the outcome after each name
+    pub verify: bool,
+}
+
+/// A tag and what it points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    /// The name without `refs/tags/`
+    pub name: String,
+    /// The object the ref points to
+    pub target: String,
+    /// The object reached by peeling annotated tags
+    peeled: (ObjectType, String),
+}
+
+/// List tags passing the options, in sort order
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `options` - Which tags to list and how to sort them
+pub fn list_tags(repo: &Repository, options: &TagOptions) -> Result<Vec<Tag>> {
+    let points_at = options
+        .points_at
+        .iter()
+        .map(|name| resolve(repo, name))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut tags = Vec::new();
+    for (name, target) in crate::refs::read_refs(&repo.git_dir)? {
+        let Some(name) = name.strip_prefix("refs/tags/") else {
+            continue;
+        };
+        let matches_pattern = options.patterns.is_empty()
+            || options
+                .patterns
+                .iter()
+                .any(|pattern| crate::ignore::wildmatch(pattern.as_bytes(), name.as_bytes()));
+        if !matches_pattern {
+            continue;
+        }
+        let (kind, mut chain) = peel_chain(repo, &target)?;
+        if !points_at.is_empty() && !chain.iter().any(|object| points_at.contains(object)) {
+            continue;
+        }
+        tags.push(Tag {
+            name: name.to_string(),
+            target,
+            peeled: (
+                kind,
+                chain.pop().expect("the chain includes the tag's target"),
+            ),
+        });
+    }
+
+    let mut tags = options.filter.apply(repo, tags, |tag| match &tag.peeled {
+        (ObjectType::Commit, hash) => Some(hash.as_str()),
+        _ => None,
+    })?;
+
+    let config = repo.config()?;
+    let key = match options.sort.as_deref().or(config.get("tag.sort")) {
+        Some(key) => SortKey::parse(key, &config)?,
+        None => SortKey::default(),
+    };
+    tags.sort_by(|a, b| key.compare(&a.name, &b.name));
+    Ok(tags)
+}
+
+/// Verify the signature of a tag
+///
+/// Lightweight tags and annotated tags without a signature are
+/// [`Verification::Unsigned`].
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `tag` - A tag from [`list_tags`]
+pub fn verify_tag(repo: &Repository, tag: &Tag) -> Result<Verification> {
+    match repo.read_object_parsed(&tag.target)? {
+        (ObjectType::Tag, content) => crate::gpg::verify(&repo.config()?, &content),
+        _ => Ok(Verification::Unsigned),
+    }
+}
+
+/// List tags
+///
+/// # Arguments
+///
+/// * `options` - Which tags to list and how to sort them
+pub fn run(options: &TagOptions) -> Result<()> {
+    let repo = Repository::open_snapshot(".")?;
+    for tag in list_tags(&repo, options)? {
+        if options.verify {
+            println!("{} ({})", tag.name, verify_tag(&repo, &tag)?.description());
+        } else {
+            println!("{}", tag.name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_list_tags() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("a", "1\n").commit("first").unwrap();
+        builder.tag("v1.9").unwrap();
+        builder.annotated_tag("v1.10-rc1", "candidate").unwrap();
+        let fix = builder.file("a", "2\n").commit("fix").unwrap();
+        builder.annotated_tag("v1.10", "release").unwrap();
+        builder.tag("v2.0").unwrap();
+        builder.tag("latest").unwrap();
+
+        let repo = builder.repo();
+        let list = |options: TagOptions| -> Vec<String> {
+            let tags = list_tags(repo, &options).unwrap();
+            tags.into_iter().map(|tag| tag.name).collect()
+        };
+
+        assert_eq!(
+            list(TagOptions::default()),
+            vec!["latest", "v1.10", "v1.10-rc1", "v1.9", "v2.0"]
+        );
+        assert_eq!(
+            list(TagOptions {
+                patterns: vec!["v*".to_string()],
+                sort: Some("version:refname".to_string()),
+                ..Default::default()
+            }),
+            vec!["v1.9", "v1.10", "v1.10-rc1", "v2.0"]
+        );
+        assert_eq!(
+            list(TagOptions {
+                patterns: vec!["v*".to_string()],
+                filter: ReachFilter {
+                    contains: vec![fix.clone()],
+                    ..Default::default()
+                },
+                sort: Some("-v:refname".to_string()),
+                ..Default::default()
+            }),
+            vec!["v2.0", "v1.10"]
+        );
+        assert_eq!(
+            list(TagOptions {
+                points_at: vec![first],
+                ..Default::default()
+            }),
+            vec!["v1.10-rc1", "v1.9"]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Modify index entry flags
+
+use crate::index::Index;
+use crate::quote;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::path::Path;
+
+/// Flag changes requested on the command line
+///
+/// `None` leaves a flag untouched.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateIndexOptions {
+    /// `--[no-]assume-unchanged`
+    pub assume_unchanged: Option<bool>,
+    /// `--[no-]skip-worktree`
+    pub skip_worktree: Option<bool>,
+}
+
+/// Apply flag changes to the given index paths
+///
+/// # Arguments
+///
+/// * `index` - The index to modify
+/// * `paths` - Paths relative to the worktree root
+/// * `options` - Which flags to set or clear
+pub fn apply(
+    index: &mut Index,
+    paths: &[impl AsRef<[u8]>],
+    options: &UpdateIndexOptions,
+) -> Result<()> {
+    for path in paths {
+        let path = path.as_ref();
+        let entry = index
+            .get_mut(path)
+            .ok_or_else(|| anyhow::anyhow!("Unable to mark file {}", quote::display(path)))?;
+        if let Some(value) = options.assume_unchanged {
+            entry.assume_valid = value;
+        }
+        if let Some(value) = options.skip_worktree {
+            entry.skip_worktree = value;
+        }
+    }
+    Ok(())
+}
+
+/// Set or clear the assume-unchanged and skip-worktree bits
+///
+/// # Arguments
+///
+/// * `paths` - Files whose index entries to update
+/// * `options` - Which flags to set or clear
+pub fn run(paths: &[String], options: &UpdateIndexOptions) -> Result<()> {
+    let repo = Repository::new(".")?;
+    let mut index = Index::read(repo.index_path())?;
+
+    let paths = paths
+        .iter()
+        .map(|p| repo.index_path_of(Path::new(p)))
+        .collect::<Result<Vec<_>>>()?;
+    apply(&mut index, &paths, options)?;
+
+    repo.write_index(&index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexEntry;
+    use crate::objects::FileMode;
+
+    const HASH: &str = "78981922613b2afb6025042ff6bd878ac1994e85";
+
+    #[test]
+    fn test_apply_flags() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("a.txt", FileMode::Regular, HASH));
+        let paths = vec!["a.txt".to_string()];
+
+        let set = UpdateIndexOptions {
+            assume_unchanged: Some(true),
+            skip_worktree: Some(true),
+        };
+        apply(&mut index, &paths, &set).unwrap();
+        let entry = index.get("a.txt").unwrap();
+        assert!(entry.assume_valid && entry.skip_worktree);
+
+        let clear_one = UpdateIndexOptions {
+            assume_unchanged: Some(false),
+            skip_worktree: None,
+        };
+        apply(&mut index, &paths, &clear_one).unwrap();
+        let entry = index.get("a.txt").unwrap();
+        assert!(!entry.assume_valid && entry.skip_worktree);
+    }
+
+    #[test]
+    fn test_unknown_path_fails() {
+        let mut index = Index::new();
+        let result = apply(
+            &mut index,
+            &["missing".to_string()],
+            &UpdateIndexOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,17 @@
+//! Update auxiliary info for dumb servers (`oxid update-server-info`)
+//!
+//! See [`crate::server_info`] for the files written.
+
+use crate::repository::Repository;
+use anyhow::Result;
+
+/// Regenerate `info/refs` and `objects/info/packs`
+///
+/// # Arguments
+///
+/// * `force` - Rewrite the files even if they are up to date
+pub fn run(force: bool) -> Result<()> {
+    let repo = Repository::new(".")?;
+    crate::server_info::update_server_info(&repo, force)?;
+    Ok(())
+}
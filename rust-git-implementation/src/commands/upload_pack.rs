@@ -0,0 +1,28 @@
+//! Serve a repository to fetching clients (`oxid upload-pack`)
+//!
+//! Only `--advertise-refs` is supported: the ref advertisement is written
+//! to stdout and the command exits, as smart HTTP servers do for
+//! `info/refs?service=git-upload-pack`. See [`crate::upload_pack`].
+
+use crate::protocol::PktLineWriter;
+use crate::repository::Repository;
+use crate::upload_pack::UploadPack;
+use anyhow::Result;
+
+/// Advertise the refs of a repository
+///
+/// # Arguments
+///
+/// * `dir` - The repository to serve
+/// * `advertise_refs` - Only advertise refs, then exit
+pub fn run(dir: &str, advertise_refs: bool) -> Result<()> {
+    if !advertise_refs {
+        anyhow::bail!("serving packs isn't supported yet; use --advertise-refs");
+    }
+    let mut repo = Repository::new(dir)?;
+    // Clients get history as stored and apply their own replacements
+    repo.set_replace_objects(false);
+    let server = UploadPack::new(&repo)?;
+    let mut out = PktLineWriter::new(std::io::stdout().lock());
+    server.advertise(&mut out)
+}
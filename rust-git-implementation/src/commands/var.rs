@@ -0,0 +1,108 @@
+//! Show logical variables (`oxid var`)
+
+use crate::config::Config;
+use crate::ident::{self, Role};
+use crate::repository::Repository;
+use anyhow::Result;
+
+/// The variables `var` knows, in the order `var -l` lists them
+pub const VARIABLES: &[&str] = &[
+    "GIT_AUTHOR_IDENT",
+    "GIT_COMMITTER_IDENT",
+    "GIT_EDITOR",
+    "GIT_PAGER",
+    "GIT_DEFAULT_BRANCH",
+];
+
+/// Resolve a variable with the environment supplied by the caller
+fn lookup(name: &str, config: &Config, env: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let first = |keys: &[&str], key: &str, default: &str| {
+        env(keys[0])
+            .or_else(|| config.get(key).map(str::to_string))
+            .or_else(|| keys[1..].iter().find_map(|k| env(k)))
+            .unwrap_or_else(|| default.to_string())
+    };
+
+    Ok(match name {
+        "GIT_AUTHOR_IDENT" => ident::ident(config, Role::Author)?.to_string(),
+        "GIT_COMMITTER_IDENT" => ident::ident(config, Role::Committer)?.to_string(),
+        "GIT_EDITOR" => first(&["GIT_EDITOR", "VISUAL", "EDITOR"], "core.editor", "vi"),
+        "GIT_PAGER" => first(&["GIT_PAGER", "PAGER"], "core.pager", "less"),
+        "GIT_DEFAULT_BRANCH" => config
+            .get("init.defaultBranch")
+            .unwrap_or("main")
+            .to_string(),
+        _ => anyhow::bail!("unknown variable '{}'", name),
+    })
+}
+
+/// Resolve a logical variable
+///
+/// # Arguments
+///
+/// * `name` - One of [`VARIABLES`]
+/// * `config` - Configuration to read settings from
+pub fn var(name: &str, config: &Config) -> Result<String> {
+    lookup(name, config, |key| std::env::var(key).ok())
+}
+
+/// Print a variable, or all of them with `-l`
+///
+/// Works outside a repository, using only the global configuration.
+///
+/// # Arguments
+///
+/// * `name` - Variable to show; `None` lists all of them
+pub fn run(name: Option<&str>) -> Result<()> {
+    let config = match Repository::open_snapshot(".") {
+        Ok(repo) => repo.config()?,
+        Err(_) => Config::global()?,
+    };
+    match name {
+        Some(name) => println!("{}", var(name, &config)?),
+        None => {
+            for name in VARIABLES {
+                if let Ok(value) = var(name, &config) {
+                    println!("{}={}", name, value);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_and_pager_precedence() {
+        let config = Config::parse("[core]\n\teditor = nano\n").unwrap();
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |key: &str| {
+                vars.iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| v.to_string())
+            }
+        };
+
+        assert_eq!(lookup("GIT_EDITOR", &config, env(&[])).unwrap(), "nano");
+        assert_eq!(
+            lookup("GIT_EDITOR", &config, env(&[("GIT_EDITOR", "emacs")])).unwrap(),
+            "emacs"
+        );
+        assert_eq!(
+            lookup("GIT_EDITOR", &Config::new(), env(&[("EDITOR", "ed")])).unwrap(),
+            "ed"
+        );
+        assert_eq!(
+            lookup("GIT_PAGER", &config, env(&[("PAGER", "more")])).unwrap(),
+            "more"
+        );
+        assert_eq!(
+            lookup("GIT_DEFAULT_BRANCH", &config, env(&[])).unwrap(),
+            "main"
+        );
+        assert!(lookup("GIT_NOPE", &config, env(&[])).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! Cross-validation against Git
+//!
+//! oxid is a learning implementation, so it is useful to check that what it
+//! writes is exactly what Git would write. This module re-hashes stored
+//! objects, runs the [`fsck`](crate::fsck) format checks on trees and
+//! commits, and compares the refs oxid sees with the output of
+//! `git for-each-ref` when Git is installed.
+
+use crate::fsck::{self, FsckMsgId};
+use crate::objects::{parse_object, ObjectType};
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// A difference between oxid's view of a repository and Git's rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// An object whose content doesn't hash to its name
+    HashMismatch { hash: String, actual: String },
+    /// An object that can't be decompressed or parsed
+    Corrupt { hash: String, error: String },
+    /// A tree or commit failing a format check
+    Fsck { hash: String, id: FsckMsgId },
+    /// A ref whose value differs from what Git reports
+    RefMismatch {
+        name: String,
+        ours: Option<String>,
+        git: Option<String>,
+    },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::HashMismatch { hash, actual } => {
+                write!(f, "object {}: content hashes to {}", hash, actual)
+            }
+            Problem::Corrupt { hash, error } => write!(f, "object {}: {}", hash, error),
+            Problem::Fsck { hash, id } => write!(f, "object {}: {}", hash, id.as_str()),
+            Problem::RefMismatch { name, ours, git } => write!(
+                f,
+                "ref {}: oxid sees {}, git sees {}",
+                name,
+                ours.as_deref().unwrap_or("nothing"),
+                git.as_deref().unwrap_or("nothing")
+            ),
+        }
+    }
+}
+
+/// Check a single stored object
+///
+/// # Arguments
+///
+/// * `hash` - The name the object is stored under
+/// * `data` - Decompressed object data
+pub fn check_object(hash: &str, data: &[u8]) -> Vec<Problem> {
+    let actual = crate::utils::hash_data(data);
+    if actual != hash {
+        return vec![Problem::HashMismatch {
+            hash: hash.to_string(),
+            actual,
+        }];
+    }
+    check_content(hash, data, false)
+}
+
+/// Check a stored object's header and, for trees and commits, its format
+///
+/// Unlike [`check_object`], the hash isn't verified.
+///
+/// # Arguments
+///
+/// * `hash` - The name the object is stored under
+/// * `data` - Decompressed object data
+/// * `strict` - Whether to also flag tree modes Git tolerates (`--strict`)
+pub fn check_content(hash: &str, data: &[u8], strict: bool) -> Vec<Problem> {
+    let (kind, content) = match parse_object(data) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            return vec![Problem::Corrupt {
+                hash: hash.to_string(),
+                error: e.to_string(),
+            }]
+        }
+    };
+    let findings = match kind {
+        ObjectType::Tree => fsck::check_tree(content, strict),
+        ObjectType::Commit => fsck::check_commit(content),
+        ObjectType::Blob | ObjectType::Tag => Vec::new(),
+    };
+    findings
+        .into_iter()
+        .map(|id| Problem::Fsck {
+            hash: hash.to_string(),
+            id,
+        })
+        .collect()
+}
+
+/// Re-hash and check every loose object
+///
+/// Returns the number of objects checked and the problems found.
+pub fn check_objects(repo: &Repository) -> Result<(usize, Vec<Problem>)> {
+    let hashes: Vec<String> = repo.objects()?.collect();
+    let mut problems = Vec::new();
+    for hash in &hashes {
+        match repo.read_stored_object(hash) {
+            Ok(data) => problems.extend(check_object(hash, &data)),
+            Err(e) => problems.push(Problem::Corrupt {
+                hash: hash.clone(),
+                error: format!("{:#}", e),
+            }),
+        }
+    }
+    Ok((hashes.len(), problems))
+}
+
+/// Ask Git for the refs of a repository
+///
+/// Returns `None` if Git isn't installed.
+pub fn git_refs(git_dir: &Path) -> Result<Option<BTreeMap<String, String>>> {
+    let output = match Command::new("git")
+        .arg("--git-dir")
+        .arg(git_dir)
+        .args(["for-each-ref", "--format=%(objectname) %(refname)"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to run git"),
+    };
+    if !output.status.success() {
+        anyhow::bail!(
+            "git for-each-ref failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let refs = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, name)| (name.to_string(), hash.to_string()))
+        .collect();
+    Ok(Some(refs))
+}
+
+/// Compare two ref listings
+pub fn compare_refs(
+    ours: &BTreeMap<String, String>,
+    git: &BTreeMap<String, String>,
+) -> Vec<Problem> {
+    let mut names: Vec<&String> = ours.keys().chain(git.keys()).collect();
+    names.sort();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter(|name| ours.get(*name) != git.get(*name))
+        .map(|name| Problem::RefMismatch {
+            name: name.clone(),
+            ours: ours.get(name).cloned(),
+            git: git.get(name).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Blob, GitObject};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_check_objects() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let blob = Blob::new(&b"hello\n"[..]);
+        let hash = blob.hash().unwrap();
+        repo.write_object(&hash, &blob.to_bytes().unwrap()).unwrap();
+
+        // A tree stored under the wrong name, and an unsorted tree
+        let bogus = "0".repeat(40);
+        repo.write_object(&bogus, b"tree 0\0").unwrap();
+        let mut unsorted = Vec::new();
+        for name in ["b", "a"] {
+            unsorted.extend_from_slice(format!("100644 {}\0", name).as_bytes());
+            unsorted.extend_from_slice(&crate::utils::hex_to_bytes(&hash).unwrap());
+        }
+        let mut tree = format!("tree {}\0", unsorted.len()).into_bytes();
+        tree.extend_from_slice(&unsorted);
+        let tree_hash = crate::utils::hash_data(&tree);
+        repo.write_object(&tree_hash, &tree).unwrap();
+
+        let (count, problems) = check_objects(&repo).unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(
+            problems,
+            vec![
+                Problem::HashMismatch {
+                    hash: bogus,
+                    actual: "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string()
+                },
+                Problem::Fsck {
+                    hash: tree_hash,
+                    id: FsckMsgId::TreeNotSorted
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_compare_refs() {
+        let ours = BTreeMap::from([
+            ("refs/heads/main".to_string(), "a".to_string()),
+            ("refs/heads/only-ours".to_string(), "b".to_string()),
+        ]);
+        let git = BTreeMap::from([("refs/heads/main".to_string(), "a".to_string())]);
+
+        assert_eq!(
+            compare_refs(&ours, &git),
+            vec![Problem::RefMismatch {
+                name: "refs/heads/only-ours".to_string(),
+                ours: Some("b".to_string()),
+                git: None
+            }]
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Git configuration files
+//!
+//! Git stores settings in INI-like files:
+//!
+//! ```text
+//! [core]
+//!     bare = false
+//! [remote "origin"]
+//!     url = https://example.com/repo.git
+//! ```
+//!
+//! Keys are addressed as `section.key` or `section.subsection.key`. Section
+//! and key names are case-insensitive; subsection names are not. When a key
+//! appears more than once, the last value wins.
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single `key = value` line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    /// Normalized key (`section.subsection.key`)
+    key: String,
+    value: String,
+}
+
+/// A parsed configuration, possibly merged from several files
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    entries: Vec<Entry>,
+}
+
+/// Normalize a key: lowercase section and name, keep the subsection as-is
+fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) if first != last => format!(
+            "{}{}{}",
+            key[..first].to_lowercase(),
+            &key[first..last],
+            key[last..].to_lowercase()
+        ),
+        _ => key.to_lowercase(),
+    }
+}
+
+/// Parse a value, handling quotes, escapes and trailing comments
+fn parse_value(raw: &str) -> Result<String> {
+    let mut value = String::new();
+    let mut in_quotes = false;
+    let mut chars = raw.trim().chars();
+    // Whitespace is only kept when followed by more value text
+    let mut pending_space = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                in_quotes = !in_quotes;
+            }
+            '\\' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('b') => {
+                        value.pop();
+                    }
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some(other) => anyhow::bail!("Invalid escape sequence: \\{}", other),
+                    None => anyhow::bail!("Unexpected end of value"),
+                }
+            }
+            '#' | ';' if !in_quotes => break,
+            c if c.is_whitespace() && !in_quotes => pending_space.push(c),
+            c => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                value.push(c);
+            }
+        }
+    }
+
+    if in_quotes {
+        anyhow::bail!("Unterminated quoted value");
+    }
+    Ok(value)
+}
+
+/// Parse a section header like `[core]` or `[remote "origin"]`
+fn parse_section(line: &str) -> Result<String> {
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.split(']').next())
+        .with_context(|| format!("Invalid section header: {}", line))?;
+
+    match inner.split_once(char::is_whitespace) {
+        Some((name, sub)) => {
+            let sub = sub.trim();
+            let sub = sub
+                .strip_prefix('"')
+                .and_then(|s| s.strip_suffix('"'))
+                .with_context(|| format!("Invalid subsection: {}", line))?;
+            let sub = sub.replace("\\\"", "\"").replace("\\\\", "\\");
+            Ok(format!("{}.{}", name.to_lowercase(), sub))
+        }
+        // Deprecated [section.subsection] syntax: subsection is lowercased
+        None => Ok(inner.to_lowercase()),
+    }
+}
+
+impl Config {
+    /// Create an empty configuration
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse configuration text
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut section: Option<String> = None;
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                section =
+                    Some(parse_section(line).with_context(|| format!("line {}", number + 1))?);
+                continue;
+            }
+
+            let section = section
+                .as_ref()
+                .with_context(|| format!("line {}: key outside of a section", number + 1))?;
+
+            // A key without `=` is a boolean true
+            let (name, value) = match line.split_once('=') {
+                Some((name, value)) => (
+                    name.trim(),
+                    parse_value(value).with_context(|| format!("line {}", number + 1))?,
+                ),
+                None => (line, "true".to_string()),
+            };
+
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                anyhow::bail!("line {}: invalid key name '{}'", number + 1, name);
+            }
+
+            entries.push(Entry {
+                key: format!("{}.{}", section, name.to_lowercase()),
+                value,
+            });
+        }
+
+        Ok(Config { entries })
+    }
+
+    /// Read a configuration file, returning an empty config if it doesn't exist
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Config::new());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Bad config file {}", path.display()))
+    }
+
+    /// Read the system and global (per-user) configuration files
+    ///
+    /// Honors `GIT_CONFIG_NOSYSTEM` and `GIT_CONFIG_GLOBAL` like Git does.
+    pub fn global() -> Result<Self> {
+        let mut config = Config::new();
+        for path in global_paths() {
+            config.merge(Config::read(path)?);
+        }
+        Ok(config)
+    }
+
+    /// Append another configuration; its values take precedence
+    pub fn merge(&mut self, other: Config) {
+        self.entries.extend(other.entries);
+    }
+
+    /// Get the last value for a key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.key == key)
+            .map(|e| e.value.as_str())
+    }
+
+    /// Get every value for a multi-valued key, in file order
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        let key = normalize_key(key);
+        self.entries
+            .iter()
+            .filter(|e| e.key == key)
+            .map(|e| e.value.as_str())
+            .collect()
+    }
+
+    /// Get the keys of a section without subsection, with their last values
+    ///
+    /// Names are lowercase and listed in order of first appearance.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - The section name, e.g. `extensions`
+    pub fn section(&self, section: &str) -> Vec<(&str, &str)> {
+        let prefix = format!("{}.", section.to_lowercase());
+        let mut names: Vec<&str> = Vec::new();
+        for entry in &self.entries {
+            if let Some(name) = entry.key.strip_prefix(&prefix) {
+                if !name.contains('.') && !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+        names
+            .into_iter()
+            .map(|name| {
+                let value = self.get(&format!("{}{}", prefix, name)).unwrap_or("");
+                (name, value)
+            })
+            .collect()
+    }
+
+    /// Get a boolean value (`true`/`yes`/`on`/`1` or `false`/`no`/`off`/`0`)
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(value) => parse_bool(value)
+                .map(Some)
+                .with_context(|| format!("Invalid boolean for {}: '{}'", key, value)),
+        }
+    }
+
+    /// Get a non-negative integer, with an optional `k`, `m` or `g` suffix
+    pub fn get_u64(&self, key: &str) -> Result<Option<u64>> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(value) => parse_u64(value)
+                .map(Some)
+                .with_context(|| format!("Invalid number for {}: '{}'", key, value)),
+        }
+    }
+}
+
+/// Parse a Git boolean value
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a Git integer value with an optional unit suffix (`10k`, `5m`, `1g`)
+pub fn parse_u64(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (digits, shift) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 10),
+        'm' => (&value[..value.len() - 1], 20),
+        'g' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    digits.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+/// Paths of the system and global config files, lowest precedence first
+fn global_paths() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+
+    if std::env::var_os("GIT_CONFIG_NOSYSTEM").is_none() {
+        paths.push(PathBuf::from("/etc/gitconfig"));
+    }
+
+    if let Some(global) = std::env::var_os("GIT_CONFIG_GLOBAL") {
+        paths.push(PathBuf::from(global));
+        return paths;
+    }
+
+    let xdg = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")));
+    if let Some(xdg) = xdg {
+        paths.push(xdg.join("git/config"));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        paths.push(PathBuf::from(home).join(".gitconfig"));
+    }
+
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sections_and_keys() {
+        let config = Config::parse(
+            "[core]\n\
+             \trepositoryformatversion = 0\n\
+             \tBare = false\n\
+             [remote \"Origin\"]\n\
+             \turl = https://example.com/repo.git\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.get("core.repositoryformatversion"), Some("0"));
+        assert_eq!(config.get("CORE.bare"), Some("false"));
+        assert_eq!(
+            config.get("remote.Origin.url"),
+            Some("https://example.com/repo.git")
+        );
+        assert_eq!(config.get("remote.origin.url"), None);
+    }
+
+    #[test]
+    fn test_parse_values() {
+        let config = Config::parse(
+            "[a]\n\
+             quoted = \"  spaced  \" # comment\n\
+             escaped = one\\ttwo\n\
+             implicit\n\
+             inner = a   b ; comment\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.get("a.quoted"), Some("  spaced  "));
+        assert_eq!(config.get("a.escaped"), Some("one\ttwo"));
+        assert_eq!(config.get_bool("a.implicit").unwrap(), Some(true));
+        assert_eq!(config.get("a.inner"), Some("a   b"));
+
+        assert_eq!(parse_u64("12"), Some(12));
+        assert_eq!(parse_u64("2k"), Some(2048));
+        assert_eq!(parse_u64("1G"), Some(1 << 30));
+        assert_eq!(parse_u64("-1"), None);
+        assert_eq!(parse_u64("m"), None);
+    }
+
+    #[test]
+    fn test_last_value_wins_and_merge() {
+        let mut config = Config::parse("[user]\nname = A\n[user]\nname = B\n").unwrap();
+        assert_eq!(config.get("user.name"), Some("B"));
+        assert_eq!(config.get_all("user.name"), vec!["A", "B"]);
+
+        config.merge(Config::parse("[user]\nname = C\n").unwrap());
+        assert_eq!(config.get("user.name"), Some("C"));
+    }
+
+    #[test]
+    fn test_section() {
+        let config = Config::parse(
+            "[Extensions]\nobjectFormat = sha1\n[extensions \"sub\"]\nkey = x\n\
+             [extensions]\nnoop = 1\nobjectformat = sha256\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.section("extensions"),
+            vec![("objectformat", "sha256"), ("noop", "1")]
+        );
+        assert!(config.section("core").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        assert!(Config::parse("key = value\n").is_err());
+        assert!(Config::parse("[core]\nbad key = 1\n").is_err());
+        assert!(Config::parse("[core]\nkey = \"open\n").is_err());
+        assert!(Config::parse("[core]\nflag = maybe\n")
+            .unwrap()
+            .get_bool("core.flag")
+            .is_err());
+    }
+
+    #[test]
+    fn test_read_missing_file_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::read(dir.path().join("nope")).unwrap();
+        assert_eq!(config.get("core.bare"), None);
+    }
+}
@@ -0,0 +1,249 @@
+//! Date parsing
+//!
+//! Dates are `(seconds since the epoch, offset in minutes east of UTC)`
+//! pairs, the representation used in commit and tag headers.
+//!
+//! [`parse`] accepts exact dates in the formats Git understands for
+//! `GIT_AUTHOR_DATE` and `--date`: Git's internal format (`1112911993
+//! -0700` or `@1112911993`), RFC 2822, ISO 8601 and Git's default log
+//! format. [`approxidate`] additionally understands relative dates such as
+//! `yesterday` or `2 weeks ago`, as used by `--since` and `--until` and by
+//! expiry settings.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, TimeZone};
+
+/// A point in time with the timezone it was recorded in
+pub type Date = (i64, i32);
+
+/// Parse a `+hhmm`/`-hhmm` timezone into minutes
+pub fn parse_offset(tz: &str) -> Option<i32> {
+    let (sign, digits) = match tz.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+fn to_date(datetime: DateTime<FixedOffset>) -> Date {
+    (
+        datetime.timestamp(),
+        datetime.offset().local_minus_utc() / 60,
+    )
+}
+
+/// Formats that carry their own timezone
+const ZONED_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S %z",
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%d %H:%M:%S%z",
+    "%a %b %e %H:%M:%S %Y %z",
+];
+
+/// Formats interpreted in the local timezone
+const LOCAL_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%a %b %e %H:%M:%S %Y",
+];
+
+/// Parse an exact date
+///
+/// Dates without a timezone are taken to be in the timezone of `now`, and
+/// a bare `YYYY-MM-DD` gets the time of day of `now`, as in Git.
+///
+/// # Arguments
+///
+/// * `text` - The date to parse
+/// * `now` - The current time, in the local timezone
+pub fn parse_at(text: &str, now: DateTime<FixedOffset>) -> Result<Date> {
+    let text = text.trim();
+    let invalid = || anyhow::anyhow!("invalid date format: {}", text);
+
+    // Git's internal format, where `@` allows leaving out the timezone
+    let (raw, tz) = match text.split_once(' ') {
+        Some((seconds, tz)) => (seconds, Some(tz)),
+        None => (text, None),
+    };
+    let (raw, explicit) = match raw.strip_prefix('@') {
+        Some(seconds) => (seconds, true),
+        None => (raw, false),
+    };
+    if let Ok(seconds) = raw.parse() {
+        match tz.map(parse_offset) {
+            Some(Some(offset)) => return Ok((seconds, offset)),
+            None if explicit => return Ok((seconds, 0)),
+            _ => {}
+        }
+    }
+    if explicit {
+        return Err(invalid());
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc2822(text) {
+        return Ok(to_date(datetime));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Ok(to_date(datetime));
+    }
+    for format in ZONED_FORMATS {
+        if let Ok(datetime) = DateTime::parse_from_str(text, format) {
+            return Ok(to_date(datetime));
+        }
+    }
+
+    let naive = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(now.time()))
+        })
+        .ok_or_else(invalid)?;
+    let datetime = now
+        .offset()
+        .from_local_datetime(&naive)
+        .single()
+        .ok_or_else(invalid)?;
+    Ok(to_date(datetime))
+}
+
+/// Parse an exact date, relative to the current local time
+pub fn parse(text: &str) -> Result<Date> {
+    parse_at(text, chrono::Local::now().fixed_offset())
+}
+
+/// Parse an exact or relative date
+///
+/// Besides everything [`parse_at`] accepts, this understands `now`,
+/// `today`, `yesterday` and sums of `<n> <unit>` followed by `ago`
+/// (e.g. `1 day 12 hours ago`), where the unit is a second, minute, hour,
+/// day, week, month or year, singular or plural.
+///
+/// # Arguments
+///
+/// * `text` - The date to parse
+/// * `now` - The current time, in the local timezone
+pub fn approxidate_at(text: &str, now: DateTime<FixedOffset>) -> Result<Date> {
+    if let Ok(date) = parse_at(text, now) {
+        return Ok(date);
+    }
+    let invalid = || anyhow::anyhow!("invalid date format: {}", text);
+
+    let text = text.trim().to_ascii_lowercase();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    match words.as_slice() {
+        ["now"] | ["today"] => return Ok(to_date(now)),
+        ["yesterday"] => return Ok(to_date(now - Duration::days(1))),
+        [.., "ago"] => {}
+        _ => return Err(invalid()),
+    }
+
+    let mut datetime = now;
+    for pair in words[..words.len() - 1].chunks(2) {
+        let [count, unit] = pair else {
+            return Err(invalid());
+        };
+        let count: u32 = count.parse().map_err(|_| invalid())?;
+        let unit = unit.strip_suffix('s').unwrap_or(unit);
+        datetime = match unit {
+            "second" => datetime - Duration::seconds(count.into()),
+            "minute" => datetime - Duration::minutes(count.into()),
+            "hour" => datetime - Duration::hours(count.into()),
+            "day" => datetime - Duration::days(count.into()),
+            "week" => datetime - Duration::weeks(count.into()),
+            "month" => datetime
+                .checked_sub_months(Months::new(count))
+                .ok_or_else(invalid)?,
+            "year" => datetime
+                .checked_sub_months(Months::new(count * 12))
+                .ok_or_else(invalid)?,
+            _ => return Err(invalid()),
+        };
+    }
+    Ok(to_date(datetime))
+}
+
+/// Parse an exact or relative date, relative to the current local time
+pub fn approxidate(text: &str) -> Result<Date> {
+    approxidate_at(text, chrono::Local::now().fixed_offset())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<FixedOffset> {
+        // 2005-04-07 22:13:13 -0700
+        DateTime::parse_from_rfc3339("2005-04-07T22:13:13-07:00").unwrap()
+    }
+
+    #[test]
+    fn test_parse_exact_formats() {
+        let expected = (1112937193, 120);
+        for text in [
+            "1112937193 +0200",
+            "Fri, 08 Apr 2005 07:13:13 +0200",
+            "2005-04-08T07:13:13+02:00",
+            "2005-04-08 07:13:13 +0200",
+            "Fri Apr 8 07:13:13 2005 +0200",
+        ] {
+            assert_eq!(parse_at(text, now()).unwrap(), expected, "{}", text);
+        }
+        assert_eq!(parse_at("@1112937193", now()).unwrap(), (1112937193, 0));
+        assert_eq!(
+            parse_at("@1112937193 -0700", now()).unwrap(),
+            (1112937193, -420)
+        );
+    }
+
+    #[test]
+    fn test_parse_local_dates() {
+        assert_eq!(
+            parse_at("2005-04-07 22:13:13", now()).unwrap(),
+            (1112937193, -420)
+        );
+        // A bare date takes the current time of day
+        assert_eq!(
+            parse_at("2005-04-06", now()).unwrap(),
+            (1112937193 - 86400, -420)
+        );
+        assert!(parse_at("next tuesday", now()).is_err());
+        assert!(parse_at("2005-13-01", now()).is_err());
+    }
+
+    #[test]
+    fn test_approxidate() {
+        let base = 1112937193;
+        assert_eq!(approxidate_at("now", now()).unwrap(), (base, -420));
+        assert_eq!(
+            approxidate_at("yesterday", now()).unwrap(),
+            (base - 86400, -420)
+        );
+        assert_eq!(
+            approxidate_at("2 weeks ago", now()).unwrap(),
+            (base - 14 * 86400, -420)
+        );
+        assert_eq!(
+            approxidate_at("1 day 12 hours ago", now()).unwrap(),
+            (base - 36 * 3600, -420)
+        );
+        assert_eq!(
+            approxidate_at("1 month ago", now()).unwrap(),
+            (base - 31 * 86400, -420)
+        );
+        assert_eq!(
+            approxidate_at("2005-04-08 07:13:13 +0200", now()).unwrap(),
+            (base, 120)
+        );
+        assert!(approxidate_at("3 fortnights ago", now()).is_err());
+    }
+}
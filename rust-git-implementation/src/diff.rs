@@ -0,0 +1,344 @@
+//! Line-based diffs
+//!
+//! Uses Myers' O(ND) algorithm to find the shortest edit script between two
+//! sequences of lines, reported as hunks of changed lines. Also pairs
+//! removed and added files into renames by content similarity.
+
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A run of changed lines: `old[old_start..old_start + old_len]` is replaced
+/// by `new[new_start..new_start + new_len]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+}
+
+impl Hunk {
+    /// End of the hunk in the old sequence (exclusive)
+    pub fn old_end(&self) -> usize {
+        self.old_start + self.old_len
+    }
+
+    /// End of the hunk in the new sequence (exclusive)
+    pub fn new_end(&self) -> usize {
+        self.new_start + self.new_len
+    }
+}
+
+/// Split data into lines, keeping the `\n` terminators
+///
+/// A final line without a newline is kept as is.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    data.split_inclusive(|&b| b == b'\n').collect()
+}
+
+/// Compute the hunks that turn `old` into `new`
+///
+/// # Arguments
+///
+/// * `old` - The original lines
+/// * `new` - The changed lines
+pub fn diff<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Hunk> {
+    // Common prefix and suffix are cheap to strip and shrink the search
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut hunks = Vec::new();
+    let mut current: Option<Hunk> = None;
+    let (mut x, mut y) = (0, 0);
+    for (next_x, next_y) in matches(old_mid, new_mid)
+        .into_iter()
+        .chain(std::iter::once((old_mid.len(), new_mid.len())))
+    {
+        if next_x > x || next_y > y {
+            let hunk = current.get_or_insert(Hunk {
+                old_start: prefix + x,
+                old_len: 0,
+                new_start: prefix + y,
+                new_len: 0,
+            });
+            hunk.old_len += next_x - x;
+            hunk.new_len += next_y - y;
+        }
+        if let Some(hunk) = current.take() {
+            hunks.push(hunk);
+        }
+        // Step past the matched line
+        x = next_x + 1;
+        y = next_y + 1;
+    }
+    hunks
+}
+
+/// Positions `(i, j)` with `old[i] == new[j]` on a shortest edit path
+fn matches<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (n + m) as usize;
+    if max == 0 {
+        return Vec::new();
+    }
+
+    // v[k + offset] is the furthest x reached on diagonal k = x - y
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        let mut k = -d;
+        while k <= d {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                trace.push(v.clone());
+                break 'search;
+            }
+            k += 2;
+        }
+    }
+
+    // Walk the trace backwards, collecting the diagonal (matching) moves
+    let mut result = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize - 1).rev() {
+        let v = &trace[d as usize];
+        let k = x - y;
+        let prev_k =
+            if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                k + 1
+            } else {
+                k - 1
+            };
+        let prev_x = if d == 0 {
+            0
+        } else {
+            v[(prev_k + offset) as usize]
+        };
+        let prev_y = if d == 0 { 0 } else { prev_x - prev_k };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            result.push((x as usize, y as usize));
+        }
+        if d > 0 {
+            x = prev_x;
+            y = prev_y;
+        }
+    }
+    result.reverse();
+    result
+}
+
+/// Minimum similarity, in percent, for a pair of files to count as a rename
+pub const RENAME_THRESHOLD: u32 = 50;
+
+/// Estimate how similar two files are, in percent
+///
+/// Counts the bytes of lines the two have in common, ignoring order,
+/// relative to the larger file. Two empty files are identical.
+pub fn similarity(old: &[u8], new: &[u8]) -> u32 {
+    let larger = old.len().max(new.len());
+    if larger == 0 {
+        return 100;
+    }
+    let mut remaining: HashMap<&[u8], usize> = HashMap::new();
+    for line in split_lines(old) {
+        *remaining.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in split_lines(new) {
+        if let Some(count) = remaining.get_mut(line).filter(|c| **c > 0) {
+            *count -= 1;
+            common += line.len();
+        }
+    }
+    (common * 100 / larger) as u32
+}
+
+/// Pair removed files with added files that look like renames of them
+///
+/// Files with identical content are paired first. The rest are paired by
+/// similarity, most similar first, as long as they reach
+/// [`RENAME_THRESHOLD`]. Each file ends up in at most one pair.
+///
+/// Returns `(deleted index, added index, similarity)` for every pair.
+///
+/// # Arguments
+///
+/// * `deleted` - Blob hashes of the removed files
+/// * `added` - Blob hashes of the added files
+/// * `read` - Reads a blob's content
+pub fn find_renames(
+    deleted: &[&str],
+    added: &[&str],
+    mut read: impl FnMut(&str) -> Result<Vec<u8>>,
+) -> Result<Vec<(usize, usize, u32)>> {
+    let mut pairs = Vec::new();
+    let mut used_old = vec![false; deleted.len()];
+    let mut used_new = vec![false; added.len()];
+    for (j, new) in added.iter().enumerate() {
+        if let Some(i) = (0..deleted.len()).find(|&i| !used_old[i] && deleted[i] == *new) {
+            used_old[i] = true;
+            used_new[j] = true;
+            pairs.push((i, j, 100));
+        }
+    }
+
+    let old_data = (0..deleted.len())
+        .map(|i| match used_old[i] {
+            true => Ok(Vec::new()),
+            false => read(deleted[i]),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut candidates = Vec::new();
+    for (j, new) in added.iter().enumerate() {
+        if used_new[j] {
+            continue;
+        }
+        let new_data = read(new)?;
+        for (i, old) in old_data.iter().enumerate() {
+            if used_old[i] {
+                continue;
+            }
+            let score = similarity(old, &new_data);
+            if score >= RENAME_THRESHOLD {
+                candidates.push((score, i, j));
+            }
+        }
+    }
+
+    // Best first; ties go to the earliest paths
+    candidates.sort_by(|a, b| b.0.cmp(&a.0).then((a.2, a.1).cmp(&(b.2, b.1))));
+    for (score, i, j) in candidates {
+        if !used_old[i] && !used_new[j] {
+            used_old[i] = true;
+            used_new[j] = true;
+            pairs.push((i, j, score));
+        }
+    }
+    pairs.sort_by_key(|&(_, j, _)| j);
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<&[u8]> {
+        split_lines(text.as_bytes())
+    }
+
+    #[test]
+    fn test_split_lines() {
+        assert_eq!(lines("a\nb"), vec![&b"a\n"[..], b"b"]);
+        assert!(lines("").is_empty());
+    }
+
+    #[test]
+    fn test_identical() {
+        assert!(diff(&lines("a\nb\n"), &lines("a\nb\n")).is_empty());
+    }
+
+    #[test]
+    fn test_change_insert_delete() {
+        let old = lines("a\nb\nc\nd\n");
+        let new = lines("a\nB\nc\nd\ne\n");
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                Hunk {
+                    old_start: 1,
+                    old_len: 1,
+                    new_start: 1,
+                    new_len: 1
+                },
+                Hunk {
+                    old_start: 4,
+                    old_len: 0,
+                    new_start: 4,
+                    new_len: 1
+                },
+            ]
+        );
+
+        let hunks = diff(&lines("a\nb\nc\n"), &lines("c\n"));
+        assert_eq!(
+            hunks,
+            vec![Hunk {
+                old_start: 0,
+                old_len: 2,
+                new_start: 0,
+                new_len: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_hunks_rebuild_new() {
+        let old: Vec<char> = "abcabba".chars().collect();
+        let new: Vec<char> = "cbabac".chars().collect();
+        let hunks = diff(&old, &new);
+
+        // Applying the hunks to old must give new
+        let mut rebuilt = Vec::new();
+        let mut pos = 0;
+        for hunk in &hunks {
+            rebuilt.extend_from_slice(&old[pos..hunk.old_start]);
+            rebuilt.extend_from_slice(&new[hunk.new_start..hunk.new_end()]);
+            pos = hunk.old_end();
+        }
+        rebuilt.extend_from_slice(&old[pos..]);
+        assert_eq!(rebuilt, new);
+
+        // Myers finds an edit script of length 5 for this classic example
+        let edits: usize = hunks.iter().map(|h| h.old_len + h.new_len).sum();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn test_find_renames() {
+        let blobs: HashMap<&str, &[u8]> = [
+            ("old-a", &b"one\ntwo\nthree\nfour\n"[..]),
+            ("new-a", b"one\ntwo\nthree\nFOUR\n"),
+            ("old-b", b"unrelated\n"),
+            ("new-c", b"something else entirely\n"),
+            ("same", b"moved as is\n"),
+        ]
+        .into_iter()
+        .collect();
+        let read = |hash: &str| Ok(blobs[hash].to_vec());
+
+        assert_eq!(similarity(blobs["old-a"], blobs["new-a"]), 73);
+        assert_eq!(
+            find_renames(
+                &["old-a", "same", "old-b"],
+                &["same", "new-a", "new-c"],
+                read
+            )
+            .unwrap(),
+            vec![(1, 0, 100), (0, 1, 73)]
+        );
+    }
+}
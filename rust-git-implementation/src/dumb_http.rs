@@ -0,0 +1,236 @@
+//! Fetching over Git's "dumb" HTTP protocol
+//!
+//! A repository served as plain static files can still be fetched: the
+//! refs come from `info/refs` (written by `update-server-info`) and each
+//! object is downloaded from `objects/<xx>/<rest>`, starting at the wanted
+//! commits and following parents, trees and tag targets.
+//!
+//! Like Git, the client first asks for `info/refs?service=git-upload-pack`;
+//! a server speaking the smart protocol answers with an
+//! `application/x-git-upload-pack-advertisement`, anything else is treated
+//! as a dumb server. oxid doesn't speak the smart protocol yet, so smart
+//! servers are reported as unsupported. Objects that only exist in packs
+//! can't be fetched either, since oxid can't read packfiles.
+//!
+//! Downloads land in a [`Quarantine`](crate::quarantine::Quarantine) and
+//! are only moved into the repository once every object has been checked.
+
+use crate::limits::Limit;
+use crate::objects::{parse_object, Commit, ObjectType, TreeRef};
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet};
+
+/// Content type of a smart server's ref advertisement
+pub const SMART_CONTENT_TYPE: &str = "application/x-git-upload-pack-advertisement";
+
+/// A repository served over dumb HTTP
+pub struct Remote {
+    /// Base URL, without a trailing `/`
+    url: String,
+    /// Largest response accepted
+    max_size: u64,
+}
+
+impl Remote {
+    /// Connect to nothing yet; requests are made as needed
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The local repository, whose limits bound the downloads
+    /// * `url` - The remote repository's `http://` URL
+    pub fn new(repo: &Repository, url: &str) -> Result<Self> {
+        Ok(Remote {
+            url: url.trim_end_matches('/').to_string(),
+            max_size: repo.limits()?.max(Limit::ObjectSize),
+        })
+    }
+
+    fn get(&self, path: &str) -> Result<crate::http::Response> {
+        crate::http::get(&format!("{}/{}", self.url, path), self.max_size)
+    }
+
+    /// Read the remote's refs from `info/refs`
+    ///
+    /// Peeled entries (`<name>^{}`) are left out.
+    pub fn refs(&self) -> Result<BTreeMap<String, String>> {
+        let response = self.get("info/refs?service=git-upload-pack")?;
+        if !response.is_success() {
+            anyhow::bail!(
+                "{}/info/refs not found (HTTP {}): is this a Git repository, \
+                 and has update-server-info been run?",
+                self.url,
+                response.status
+            );
+        }
+        if response.content_type.as_deref() == Some(SMART_CONTENT_TYPE) {
+            anyhow::bail!(
+                "{} only speaks the smart HTTP protocol, which oxid doesn't support yet",
+                self.url
+            );
+        }
+
+        let text = String::from_utf8(response.body).context("info/refs is not UTF-8")?;
+        let mut refs = BTreeMap::new();
+        for line in text.lines() {
+            let (hash, name) = line
+                .split_once('\t')
+                .with_context(|| format!("malformed line in info/refs: {}", line))?;
+            if name.ends_with("^{}") {
+                continue;
+            }
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("malformed object id in info/refs: {}", hash);
+            }
+            refs.insert(name.to_string(), hash.to_string());
+        }
+        Ok(refs)
+    }
+
+    /// Read the branch the remote's `HEAD` points to, e.g. `refs/heads/main`
+    ///
+    /// Returns `None` for a detached or missing `HEAD`.
+    pub fn head(&self) -> Result<Option<String>> {
+        let response = self.get("HEAD")?;
+        if !response.is_success() {
+            return Ok(None);
+        }
+        Ok(String::from_utf8_lossy(&response.body)
+            .trim_end()
+            .strip_prefix("ref: ")
+            .map(str::to_string))
+    }
+
+    /// Download one loose object, checking that it hashes to its name
+    ///
+    /// Returns the decompressed object data.
+    fn download(&self, repo: &Repository, hash: &str) -> Result<Vec<u8>> {
+        let response = self.get(&format!("objects/{}/{}", &hash[..2], &hash[2..]))?;
+        if !response.is_success() {
+            let packs = self.get("objects/info/packs")?;
+            if packs.is_success() && packs.body.starts_with(b"P ") {
+                anyhow::bail!(
+                    "object {} is only in a pack on the remote, and oxid can't read packs yet",
+                    hash
+                );
+            }
+            anyhow::bail!("remote is missing object {}", hash);
+        }
+        let data = repo.limits()?.inflate_object(&response.body[..])?;
+        let actual = crate::utils::hash_data(&data);
+        if actual != hash {
+            anyhow::bail!("object {} from the remote hashes to {}", hash, actual);
+        }
+        Ok(data)
+    }
+
+    /// Fetch everything reachable from `wants` that the repository lacks
+    ///
+    /// Returns the hashes of the objects downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository to fetch into
+    /// * `wants` - Object ids to fetch, typically ref values from [`Remote::refs`]
+    pub fn fetch(&self, repo: &Repository, wants: &[String]) -> Result<Vec<String>> {
+        let _span = tracing::debug_span!("dumb_fetch", url = %self.url).entered();
+        let quarantine = repo.quarantine()?;
+        let mut seen = HashSet::new();
+        let mut pending = wants.to_vec();
+        while let Some(hash) = pending.pop() {
+            if !seen.insert(hash.clone()) {
+                continue;
+            }
+            // Ids from commit and tag text end up in paths and URLs
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                anyhow::bail!("invalid object id '{}' in fetched objects", hash);
+            }
+            let data = if repo.has_object(&hash) {
+                repo.read_stored_object(&hash)?
+            } else {
+                let data = self.download(repo, &hash)?;
+                quarantine.write_object(&hash, &data)?;
+                data
+            };
+
+            let (kind, content) = parse_object(&data)?;
+            match kind {
+                ObjectType::Commit => {
+                    // Not `CommitRef`: commits may be stored in other encodings
+                    let commit = Commit::parse_content(content)?;
+                    pending.push(commit.tree);
+                    pending.extend(commit.parents);
+                }
+                ObjectType::Tree => {
+                    for entry in TreeRef::new(content) {
+                        let entry = entry?;
+                        // Submodule commits live in another repository
+                        if entry.mode != b"160000" {
+                            pending.push(entry.hash());
+                        }
+                    }
+                }
+                ObjectType::Tag => {
+                    let target = std::str::from_utf8(content)
+                        .ok()
+                        .and_then(|text| text.lines().next())
+                        .and_then(|line| line.strip_prefix("object "))
+                        .with_context(|| format!("Malformed tag {}", hash))?;
+                    pending.push(target.to_string());
+                }
+                ObjectType::Blob => {}
+            }
+        }
+
+        quarantine.validate(|_hash, data| crate::fsck::check_received(data))?;
+        quarantine.migrate()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{serve_http, RepoBuilder};
+
+    #[test]
+    fn test_fetch() {
+        let mut builder = RepoBuilder::new().unwrap();
+        builder
+            .file("a", "1\n")
+            .file("dir/b", "2\n")
+            .commit("first")
+            .unwrap();
+        let tip = builder.file("a", "3\n").commit("second").unwrap();
+        let tag = builder.annotated_tag("v1", "release").unwrap();
+
+        let remote = builder.repo();
+        std::fs::create_dir_all(remote.git_dir.join("info")).unwrap();
+        std::fs::write(
+            remote.git_dir.join("info/refs"),
+            format!(
+                "{}\trefs/heads/main\n{}\trefs/tags/v1\n{}\trefs/tags/v1^{{}}\n",
+                tip, tag, tip
+            ),
+        )
+        .unwrap();
+        let url = serve_http(remote.git_dir.clone()).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let local = Repository::init(dir.path()).unwrap();
+        let remote = Remote::new(&local, &url).unwrap();
+        let refs = remote.refs().unwrap();
+        assert_eq!(refs.len(), 2);
+        assert_eq!(refs["refs/tags/v1"], tag);
+        assert_eq!(remote.head().unwrap().as_deref(), Some("refs/heads/main"));
+
+        let wants: Vec<String> = refs.into_values().collect();
+        // Two commits, three trees, three blobs and the tag
+        assert_eq!(remote.fetch(&local, &wants).unwrap().len(), 9);
+        assert_eq!(local.read_commit(&tip).unwrap().parents.len(), 1);
+        // Nothing left to download the second time
+        assert!(remote.fetch(&local, &wants).unwrap().is_empty());
+
+        let missing = "0123456789012345678901234567890123456789".to_string();
+        assert!(remote.fetch(&local, &[missing]).is_err());
+    }
+}
@@ -0,0 +1,113 @@
+//! Character encodings of commit messages
+//!
+//! Git stores commits as bytes. A commit whose text isn't UTF-8 names its
+//! encoding in an `encoding` header, as written when `i18n.commitEncoding`
+//! is set. When messages are shown they're converted to
+//! `i18n.logOutputEncoding`, which defaults to the commit encoding.
+//!
+//! Encodings are named by their usual labels (`ISO-8859-1`, `latin1`,
+//! `Shift_JIS`, `EUC-JP`, ...), looked up as in the WHATWG Encoding
+//! Standard.
+
+use crate::config::Config;
+use anyhow::Result;
+use encoding_rs::Encoding;
+
+/// The encoding assumed when none is given
+pub const DEFAULT: &str = "UTF-8";
+
+/// Whether an encoding label means UTF-8
+pub fn is_utf8(label: &str) -> bool {
+    label.eq_ignore_ascii_case("utf-8") || label.eq_ignore_ascii_case("utf8")
+}
+
+fn lookup(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| anyhow::anyhow!("unknown encoding '{}'", label))
+}
+
+/// Decode text stored in `label`
+///
+/// Bytes that aren't valid in the encoding become U+FFFD, as a terminal
+/// would show them.
+///
+/// # Arguments
+///
+/// * `bytes` - The stored text
+/// * `label` - Its encoding
+pub fn decode(bytes: &[u8], label: &str) -> Result<String> {
+    let (text, malformed) = lookup(label)?.decode_without_bom_handling(bytes);
+    if malformed {
+        tracing::warn!(encoding = label, "replacing bytes that are not valid text");
+    }
+    Ok(text.into_owned())
+}
+
+/// Encode text for storing in `label`
+///
+/// Fails if the text has characters the encoding can't represent.
+///
+/// # Arguments
+///
+/// * `text` - The text to encode
+/// * `label` - The encoding to store it in
+pub fn encode(text: &str, label: &str) -> Result<Vec<u8>> {
+    let encoding = lookup(label)?;
+    if encoding.output_encoding() != encoding {
+        anyhow::bail!("cannot store text as {}", label);
+    }
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable {
+        anyhow::bail!("text cannot be represented in {}", label);
+    }
+    Ok(bytes.into_owned())
+}
+
+/// The encoding new commit messages are stored in: `i18n.commitEncoding`
+pub fn commit_encoding(config: &Config) -> &str {
+    config.get("i18n.commitEncoding").unwrap_or(DEFAULT)
+}
+
+/// The encoding messages are shown in: `i18n.logOutputEncoding`, falling
+/// back to the commit encoding
+pub fn log_output_encoding(config: &Config) -> &str {
+    config
+        .get("i18n.logOutputEncoding")
+        .unwrap_or_else(|| commit_encoding(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let latin1 = b"caf\xe9\n";
+        let text = decode(latin1, "ISO-8859-1").unwrap();
+        assert_eq!(text, "caf\u{e9}\n");
+        assert_eq!(encode(&text, "latin1").unwrap(), latin1);
+        assert_eq!(
+            encode("\u{65e5}\u{672c}", "Shift_JIS").unwrap(),
+            b"\x93\xfa\x96\x7b"
+        );
+
+        assert!(encode("\u{65e5}", "ISO-8859-1").is_err());
+        assert!(encode("x", "UTF-16LE").is_err());
+        assert!(decode(b"x", "no-such-encoding").is_err());
+        assert_eq!(decode(b"caf\xe9", "UTF-8").unwrap(), "caf\u{fffd}");
+        assert!(is_utf8("utf8") && is_utf8("UTF-8") && !is_utf8("latin1"));
+    }
+
+    #[test]
+    fn test_config() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(commit_encoding(&config), "UTF-8");
+        assert_eq!(log_output_encoding(&config), "UTF-8");
+        let config = Config::parse("[i18n]\n\tcommitEncoding = ISO-8859-1\n").unwrap();
+        assert_eq!(log_output_encoding(&config), "ISO-8859-1");
+        let config =
+            Config::parse("[i18n]\n\tcommitEncoding = ISO-8859-1\n\tlogOutputEncoding = UTF-8\n")
+                .unwrap();
+        assert_eq!(log_output_encoding(&config), "UTF-8");
+    }
+}
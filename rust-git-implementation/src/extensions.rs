@@ -0,0 +1,179 @@
+//! Repository format version and extensions
+//!
+//! `core.repositoryFormatVersion` in a repository's config says how to read
+//! it. Version 0 is the original format; version 1 adds the `extensions`
+//! section, where each key names a change to the on-disk format that a
+//! program must implement before touching the repository. Reading a
+//! SHA-256 or reftable repository as if it were a plain one would go wrong
+//! in confusing ways, so like Git, oxid refuses to open repositories that
+//! use extensions it doesn't implement:
+//!
+//! | Extension           | Supported values   |
+//! |---------------------|--------------------|
+//! | `noop`, `noop-v1`   | any                |
+//! | `objectFormat`      | `sha1`             |
+//! | `refStorage`        | `files`            |
+//! | `worktreeConfig`    | any                |
+//! | `preciousObjects`   | any                |
+//! | `relativeWorktrees` | any                |
+//!
+//! `preciousObjects` asks not to delete objects, which oxid never does.
+//!
+//! Version 0 repositories predate extensions. Git ignores unknown keys
+//! there, but honors the few extensions older versions already knew about
+//! (`partialClone` among them) and refuses version 1 ones.
+
+use crate::config::Config;
+use anyhow::Result;
+
+/// Highest format version oxid reads
+pub const MAX_VERSION: u64 = 1;
+
+/// Extensions Git honors even in version 0 repositories
+const V0_EXTENSIONS: &[&str] = &["noop", "partialclone", "preciousobjects", "worktreeconfig"];
+
+/// Extensions Git only allows in version 1 repositories
+const V1_EXTENSIONS: &[&str] = &[
+    "noop-v1",
+    "objectformat",
+    "compatobjectformat",
+    "refstorage",
+    "relativeworktrees",
+    "submodulepathconfig",
+];
+
+/// Check whether oxid implements an extension with this value
+fn is_supported(name: &str, value: &str) -> bool {
+    match name {
+        "noop" | "noop-v1" | "worktreeconfig" | "preciousobjects" | "relativeworktrees" => true,
+        // SHA-256 repositories are refused until every object, tree and
+        // index path handles 32-byte ids
+        "objectformat" => value.eq_ignore_ascii_case("sha1"),
+        "refstorage" => value.eq_ignore_ascii_case("files"),
+        _ => false,
+    }
+}
+
+/// Fail unless oxid can read a repository with this configuration
+///
+/// # Arguments
+///
+/// * `config` - The repository's own `config` file (the one in the common
+///   dir, without global settings)
+///
+/// # Errors
+///
+/// Names the format version or every extension that stands in the way
+pub fn check(config: &Config) -> Result<()> {
+    let version = config.get_u64("core.repositoryFormatVersion")?.unwrap_or(0);
+    if version > MAX_VERSION {
+        anyhow::bail!(
+            "Expected git repo version <= {}, found {}",
+            MAX_VERSION,
+            version
+        );
+    }
+
+    let mut v1_only = Vec::new();
+    let mut unsupported = Vec::new();
+    for (name, value) in config.section("extensions") {
+        let known = V0_EXTENSIONS.contains(&name) || V1_EXTENSIONS.contains(&name);
+        if version == 0 && V1_EXTENSIONS.contains(&name) {
+            v1_only.push(name);
+        } else if (version > 0 || known) && !is_supported(name, value) {
+            unsupported.push(format!("{} = {}", name, value));
+        }
+    }
+
+    if !v1_only.is_empty() {
+        anyhow::bail!(
+            "repo version is 0, but v1-only extension{} found:\n\t{}",
+            if v1_only.len() == 1 { "" } else { "s" },
+            v1_only.join("\n\t")
+        );
+    }
+    if !unsupported.is_empty() {
+        anyhow::bail!(
+            "unsupported repository extension{} found:\n\t{}\n\
+             This repository needs Git, or a newer oxid, to be read safely.",
+            if unsupported.len() == 1 { "" } else { "s" },
+            unsupported.join("\n\t")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_config(text: &str) -> Result<()> {
+        check(&Config::parse(text).unwrap())
+    }
+
+    #[test]
+    fn test_supported_extensions() {
+        check_config("[core]\n\trepositoryformatversion = 0\n").unwrap();
+        check_config(
+            "[core]\n\trepositoryformatversion = 1\n\
+             [extensions]\n\tobjectFormat = sha1\n\trefStorage = files\n\
+             \tworktreeConfig = true\n",
+        )
+        .unwrap();
+        let error = check_config(
+            "[core]\n\trepositoryformatversion = 1\n[extensions]\n\tobjectFormat = sha256\n",
+        );
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("unsupported repository extension found:\n\tobjectformat = sha256"));
+
+        // Version 0 ignores extensions older Git didn't know
+        check_config("[core]\n\trepositoryformatversion = 0\n[extensions]\n\tfuture = 1\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unsupported_extensions() {
+        let error = check_config(
+            "[core]\n\trepositoryformatversion = 1\n\
+             [extensions]\n\trefStorage = reftable\n\tfuture = yes\n",
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.starts_with(
+                "unsupported repository extensions found:\n\trefstorage = reftable\n\tfuture = yes\n"
+            ),
+            "{}",
+            error
+        );
+
+        let error = check_config(
+            "[core]\n\trepositoryformatversion = 1\n[extensions]\n\tobjectformat = md5\n",
+        );
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("objectformat = md5"));
+
+        // Git honors partial clones in version 0 repositories too
+        let error = check_config("[extensions]\n\tpartialclone = origin\n");
+        assert!(error
+            .unwrap_err()
+            .to_string()
+            .contains("partialclone = origin"));
+
+        let error = check_config("[extensions]\n\tobjectformat = sha256\n");
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "repo version is 0, but v1-only extension found:\n\tobjectformat"
+        );
+
+        let error = check_config("[core]\n\trepositoryformatversion = 2\n");
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "Expected git repo version <= 1, found 2"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! External diff and merge tools (`difftool`, `mergetool`)
+//!
+//! A tool is a shell command run with the files to compare in environment
+//! variables, as Git does:
+//!
+//! - `LOCAL`, `REMOTE`: the two sides of a diff, or ours and theirs in a merge
+//! - `BASE`: the common ancestor (merge only)
+//! - `MERGED`: the worktree file receiving the result (merge only)
+//!
+//! The command comes from `difftool.<tool>.cmd` / `mergetool.<tool>.cmd`, or
+//! from one of the built-in presets (`vimdiff`, `meld`, `vscode`, `kdiff3`).
+//! The tool name is given with `--tool` or configured in `diff.tool` /
+//! `merge.tool`.
+
+use crate::config::Config;
+use anyhow::{Context, Result};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether a tool is used to view a diff or to resolve a merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolKind {
+    Diff,
+    Merge,
+}
+
+impl ToolKind {
+    /// Config section holding per-tool settings
+    fn section(&self) -> &str {
+        match self {
+            ToolKind::Diff => "difftool",
+            ToolKind::Merge => "mergetool",
+        }
+    }
+
+    /// Config key naming the default tool
+    fn default_key(&self) -> &str {
+        match self {
+            ToolKind::Diff => "diff.tool",
+            ToolKind::Merge => "merge.tool",
+        }
+    }
+}
+
+/// A resolved external tool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tool {
+    pub name: String,
+    /// Shell command, referring to the files as `$LOCAL`, `$REMOTE`, ...
+    pub cmd: String,
+    /// Whether a non-zero exit status means the tool failed
+    pub trust_exit_code: bool,
+}
+
+/// Built-in tool commands: (name, diff command, merge command, trust exit code)
+const PRESETS: &[(&str, &str, &str, bool)] = &[
+    (
+        "vimdiff",
+        r#"vim -R -f -d "$LOCAL" "$REMOTE""#,
+        r#"vim -f -d -c '4wincmd w | wincmd J' "$LOCAL" "$BASE" "$REMOTE" "$MERGED""#,
+        false,
+    ),
+    (
+        "meld",
+        r#"meld "$LOCAL" "$REMOTE""#,
+        r#"meld "$LOCAL" "$BASE" "$REMOTE" --output "$MERGED""#,
+        false,
+    ),
+    (
+        "vscode",
+        r#"code --wait --diff "$LOCAL" "$REMOTE""#,
+        r#"code --wait --merge "$REMOTE" "$LOCAL" "$BASE" "$MERGED""#,
+        false,
+    ),
+    (
+        "kdiff3",
+        r#"kdiff3 "$LOCAL" "$REMOTE""#,
+        r#"kdiff3 --auto "$BASE" "$LOCAL" "$REMOTE" -o "$MERGED""#,
+        true,
+    ),
+];
+
+/// Names of the built-in tools
+pub fn presets() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, ..)| *name).collect()
+}
+
+/// Find the tool to run
+///
+/// # Arguments
+///
+/// * `config` - Effective repository configuration
+/// * `kind` - Diff or merge tool
+/// * `name` - Tool given on the command line, overriding `diff.tool`/`merge.tool`
+pub fn resolve(config: &Config, kind: ToolKind, name: Option<&str>) -> Result<Tool> {
+    let name = match name.or_else(|| config.get(kind.default_key())) {
+        Some(name) => name.to_string(),
+        None => anyhow::bail!(
+            "No {} tool configured; set {} or use --tool (built-in: {})",
+            match kind {
+                ToolKind::Diff => "diff",
+                ToolKind::Merge => "merge",
+            },
+            kind.default_key(),
+            presets().join(", ")
+        ),
+    };
+
+    let section = kind.section();
+    let preset = PRESETS.iter().find(|(preset, ..)| *preset == name);
+    let cmd = match config.get(&format!("{}.{}.cmd", section, name)) {
+        Some(cmd) => cmd.to_string(),
+        None => match (preset, kind) {
+            (Some((_, diff, _, _)), ToolKind::Diff) => diff.to_string(),
+            (Some((_, _, merge, _)), ToolKind::Merge) => merge.to_string(),
+            (None, _) => anyhow::bail!("Unknown tool '{}': set {}.{}.cmd", name, section, name),
+        },
+    };
+
+    // Custom commands are not trusted unless configured otherwise
+    let default_trust = config.get(&format!("{}.{}.cmd", section, name)).is_none()
+        && preset.is_some_and(|(_, _, _, trust)| *trust);
+    let trust_exit_code = match config.get_bool(&format!("{}.{}.trustExitCode", section, name))? {
+        Some(value) => value,
+        None => config
+            .get_bool(&format!("{}.trustExitCode", section))?
+            .unwrap_or(default_trust),
+    };
+
+    Ok(Tool {
+        name,
+        cmd,
+        trust_exit_code,
+    })
+}
+
+/// Run a tool through the shell, returning whether it exited successfully
+///
+/// # Arguments
+///
+/// * `tool` - The tool to run
+/// * `vars` - Environment variables naming the files (`LOCAL`, `REMOTE`, ...)
+pub fn run(tool: &Tool, vars: &[(&str, &Path)]) -> Result<bool> {
+    let _span = tracing::debug_span!("external_tool", tool = %tool.name).entered();
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(&tool.cmd)
+        .envs(vars.iter().map(|(name, path)| (*name, path.as_os_str())))
+        .status()
+        .with_context(|| format!("Failed to run {}", tool.name))?;
+    Ok(status.success())
+}
+
+/// Ask a yes/no question on the terminal; an empty answer means yes
+pub fn confirm(question: &str) -> Result<bool> {
+    print!("{} [Y/n]? ", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(!matches!(answer.trim(), "n" | "N" | "no"))
+}
+
+/// A temporary directory removed when dropped
+///
+/// The name has a random suffix and is created exclusively (and only
+/// readable by the user), so another user of a shared `/tmp` can't guess it
+/// or plant files in it beforehand.
+pub struct TempDir {
+    dir: tempfile::TempDir,
+}
+
+impl TempDir {
+    /// Create a fresh directory under the system temp directory
+    pub fn new(prefix: &str) -> Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("{}-", prefix))
+            .tempdir()
+            .with_context(|| {
+                format!(
+                    "Unable to create a temporary directory in {}",
+                    std::env::temp_dir().display()
+                )
+            })?;
+        Ok(TempDir { dir })
+    }
+
+    /// Path of the directory
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Write a file below the directory, creating parent directories
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Path relative to the temporary directory
+    /// * `data` - File contents
+    pub fn write(&self, name: impl AsRef<Path>, data: &[u8]) -> Result<PathBuf> {
+        let path = self.path().join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_preset() {
+        let config = Config::parse("[diff]\ntool = meld\n").unwrap();
+        let tool = resolve(&config, ToolKind::Diff, None).unwrap();
+        assert_eq!(tool.cmd, r#"meld "$LOCAL" "$REMOTE""#);
+        assert!(!tool.trust_exit_code);
+
+        // --tool overrides diff.tool
+        let tool = resolve(&config, ToolKind::Merge, Some("kdiff3")).unwrap();
+        assert!(tool.cmd.contains("$MERGED"));
+        assert!(tool.trust_exit_code);
+    }
+
+    #[test]
+    fn test_resolve_custom_command() {
+        let config = Config::parse(
+            "[merge]\ntool = mine\n\
+             [mergetool \"mine\"]\ncmd = mine $BASE $MERGED\ntrustExitCode = true\n",
+        )
+        .unwrap();
+        let tool = resolve(&config, ToolKind::Merge, None).unwrap();
+        assert_eq!(tool.name, "mine");
+        assert_eq!(tool.cmd, "mine $BASE $MERGED");
+        assert!(tool.trust_exit_code);
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let config = Config::new();
+        assert!(resolve(&config, ToolKind::Diff, None).is_err());
+        assert!(resolve(&config, ToolKind::Diff, Some("unknown")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_passes_files() {
+        let dir = TempDir::new("oxid-test-tool").unwrap();
+        let local = dir.write("a/local", b"one").unwrap();
+        let out = dir.path().join("out");
+        let tool = Tool {
+            name: "cat".to_string(),
+            cmd: r#"cat "$LOCAL" > "$MERGED""#.to_string(),
+            trust_exit_code: true,
+        };
+
+        assert!(run(&tool, &[("LOCAL", &local), ("MERGED", &out)]).unwrap());
+        assert_eq!(fs::read(&out).unwrap(), b"one");
+
+        let failing = Tool {
+            cmd: "exit 1".to_string(),
+            ..tool
+        };
+        assert!(!run(&failing, &[]).unwrap());
+
+        let path = dir.path().to_path_buf();
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,708 @@
+//! C API for embedding oxid in non-Rust programs
+//!
+//! Enabled with the `ffi` cargo feature. The matching declarations live in
+//! `include/oxid.h`. Every function returns an [`OxidError`] code; when a call
+//! fails, [`oxid_error_last`] returns a message describing what went wrong.
+//!
+//! Handles and buffers handed out by the library must be released with the
+//! corresponding `_free` function. A panic inside the library is caught at
+//! the boundary and reported as [`OxidError::Error`] instead of unwinding
+//! into C.
+//!
+//! Exposed so far: repository open/init, object read/write, history walks
+//! and working tree status.
+
+use crate::commands::status::repo_status;
+use crate::objects::ObjectType;
+use crate::repository::Repository;
+use crate::status::FileChange;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+/// Result codes returned by every C API function
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OxidError {
+    /// The call succeeded
+    Ok = 0,
+    /// Generic failure, see `oxid_error_last`
+    Error = -1,
+    /// A required pointer argument was null or a string was not UTF-8
+    InvalidArgument = -2,
+    /// The repository or object does not exist
+    NotFound = -3,
+    /// A walk has no more items
+    IterOver = -4,
+}
+
+/// Opaque repository handle
+pub struct OxidRepository {
+    inner: Repository,
+}
+
+/// A byte buffer owned by the library
+#[repr(C)]
+pub struct OxidBuf {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+/// Opaque history walk handle
+///
+/// Commits come out newest first by committer date, each once.
+pub struct OxidRevwalk {
+    repo: *const OxidRepository,
+    queue: BinaryHeap<(i64, String)>,
+    seen: HashSet<String>,
+}
+
+/// The path was added to the index
+pub const OXID_STATUS_INDEX_NEW: u32 = 1 << 0;
+/// The path was modified in the index
+pub const OXID_STATUS_INDEX_MODIFIED: u32 = 1 << 1;
+/// The path was deleted from the index
+pub const OXID_STATUS_INDEX_DELETED: u32 = 1 << 2;
+/// The path was renamed in the index; see [`OxidStatusEntry::old_path`]
+pub const OXID_STATUS_INDEX_RENAMED: u32 = 1 << 3;
+/// The path is untracked
+pub const OXID_STATUS_WT_NEW: u32 = 1 << 7;
+/// The worktree file differs from the index
+pub const OXID_STATUS_WT_MODIFIED: u32 = 1 << 8;
+/// The worktree file is missing
+pub const OXID_STATUS_WT_DELETED: u32 = 1 << 9;
+/// The path has conflict stages
+pub const OXID_STATUS_CONFLICTED: u32 = 1 << 15;
+
+/// One changed path
+#[repr(C)]
+pub struct OxidStatusEntry {
+    /// NUL-terminated path relative to the worktree root; untracked
+    /// directories end in `/`
+    pub path: *mut c_char,
+    /// The rename source when `OXID_STATUS_INDEX_RENAMED` is set, else null
+    pub old_path: *mut c_char,
+    /// `OXID_STATUS_*` bits
+    pub flags: u32,
+}
+
+/// The changed paths of a working tree, sorted by path
+#[repr(C)]
+pub struct OxidStatusList {
+    pub entries: *mut OxidStatusEntry,
+    pub count: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+fn fail(code: OxidError, err: impl std::fmt::Display) -> OxidError {
+    set_last_error(format!("{:#}", err));
+    code
+}
+
+/// Run the body of an entry point, turning a panic into an error code
+fn guard(body: impl FnOnce() -> OxidError) -> OxidError {
+    catch_unwind(AssertUnwindSafe(body))
+        .unwrap_or_else(|_| fail(OxidError::Error, "internal error in oxid"))
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+/// Copy a hex id and its NUL into a caller's buffer of `len` bytes
+unsafe fn write_hash(hash: &str, out: *mut c_char, len: usize) -> OxidError {
+    if len <= hash.len() {
+        return fail(
+            OxidError::InvalidArgument,
+            "hash_out must hold at least 41 bytes",
+        );
+    }
+    ptr::copy_nonoverlapping(hash.as_ptr() as *const c_char, out, hash.len());
+    *out.add(hash.len()) = 0;
+    OxidError::Ok
+}
+
+/// Get the message of the last error raised on this thread
+///
+/// Returns null if no error has occurred. The string stays valid until the
+/// next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn oxid_error_last() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+/// Open the repository containing `path`
+///
+/// # Safety
+///
+/// `out` must be a valid pointer and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_repository_open(
+    out: *mut *mut OxidRepository,
+    path: *const c_char,
+) -> OxidError {
+    guard(|| {
+        let Some(path) = str_arg(path) else {
+            return fail(OxidError::InvalidArgument, "path must be a UTF-8 string");
+        };
+        if out.is_null() {
+            return fail(OxidError::InvalidArgument, "out must not be null");
+        }
+
+        match Repository::new(path) {
+            Ok(repo) => {
+                *out = Box::into_raw(Box::new(OxidRepository { inner: repo }));
+                OxidError::Ok
+            }
+            Err(e) => fail(OxidError::NotFound, e),
+        }
+    })
+}
+
+/// Initialize a new repository at `path`
+///
+/// # Safety
+///
+/// `out` must be a valid pointer and `path` a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_repository_init(
+    out: *mut *mut OxidRepository,
+    path: *const c_char,
+) -> OxidError {
+    guard(|| {
+        let Some(path) = str_arg(path) else {
+            return fail(OxidError::InvalidArgument, "path must be a UTF-8 string");
+        };
+        if out.is_null() {
+            return fail(OxidError::InvalidArgument, "out must not be null");
+        }
+
+        match Repository::init(path) {
+            Ok(repo) => {
+                *out = Box::into_raw(Box::new(OxidRepository { inner: repo }));
+                OxidError::Ok
+            }
+            Err(e) => fail(OxidError::Error, e),
+        }
+    })
+}
+
+/// Release a repository handle
+///
+/// # Safety
+///
+/// `repo` must be null or a handle returned by this library that has not
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_repository_free(repo: *mut OxidRepository) {
+    if !repo.is_null() {
+        drop(Box::from_raw(repo));
+    }
+}
+
+/// Read an object, returning its raw `type size\0content` bytes
+///
+/// # Safety
+///
+/// `repo` must be a live handle, `hash` a NUL-terminated string and `out` a
+/// valid pointer. The buffer must be released with [`oxid_buf_free`].
+/// `hash` must be a full hex object id; anything else is refused with
+/// [`OxidError::InvalidArgument`].
+#[no_mangle]
+pub unsafe extern "C" fn oxid_object_read(
+    repo: *const OxidRepository,
+    hash: *const c_char,
+    out: *mut OxidBuf,
+) -> OxidError {
+    guard(|| {
+        let Some(hash) = str_arg(hash) else {
+            return fail(OxidError::InvalidArgument, "hash must be a UTF-8 string");
+        };
+        if repo.is_null() || out.is_null() {
+            return fail(OxidError::InvalidArgument, "invalid argument");
+        }
+        let repo = &(*repo).inner;
+        if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return fail(
+                OxidError::InvalidArgument,
+                format!("'{}' is not a full object id", hash),
+            );
+        }
+
+        match repo.read_object(hash) {
+            Ok(data) => {
+                let mut data = data.into_boxed_slice();
+                *out = OxidBuf {
+                    data: data.as_mut_ptr(),
+                    len: data.len(),
+                };
+                std::mem::forget(data);
+                OxidError::Ok
+            }
+            Err(e) => fail(OxidError::NotFound, e),
+        }
+    })
+}
+
+/// Write an object of the given type and store its hash in `hash_out`
+///
+/// The hash is written as NUL-terminated hex, so `hash_len` must be at
+/// least 41. A smaller buffer is refused with [`OxidError::InvalidArgument`] before
+/// anything is written.
+///
+/// # Safety
+///
+/// `repo` must be a live handle, `kind` a NUL-terminated object type name
+/// (`"blob"`, `"tree"`, ...), `data` must point to `len` readable bytes and
+/// `hash_out` to `hash_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_object_write(
+    repo: *const OxidRepository,
+    kind: *const c_char,
+    data: *const u8,
+    len: usize,
+    hash_out: *mut c_char,
+    hash_len: usize,
+) -> OxidError {
+    guard(|| {
+        let Some(kind) = str_arg(kind) else {
+            return fail(OxidError::InvalidArgument, "type must be a UTF-8 string");
+        };
+        if repo.is_null() || hash_out.is_null() || (data.is_null() && len > 0) {
+            return fail(OxidError::InvalidArgument, "invalid argument");
+        }
+        let repo = &(*repo).inner;
+        let kind = match ObjectType::from_str(kind) {
+            Ok(kind) => kind,
+            Err(e) => return fail(OxidError::InvalidArgument, e),
+        };
+        if hash_len < 41 {
+            return fail(
+                OxidError::InvalidArgument,
+                "hash_out must hold at least 41 bytes",
+            );
+        }
+
+        let content = if len == 0 {
+            &[][..]
+        } else {
+            std::slice::from_raw_parts(data, len)
+        };
+        let mut object = format!("{} {}\0", kind.as_str(), content.len()).into_bytes();
+        object.extend_from_slice(content);
+
+        let hash = crate::utils::hash_data(&object);
+        if let Err(e) = repo.write_object(&hash, &object) {
+            return fail(OxidError::Error, e);
+        }
+
+        write_hash(&hash, hash_out, hash_len)
+    })
+}
+
+/// Release a buffer returned by the library
+///
+/// # Safety
+///
+/// `buf` must be null or point to a buffer filled in by this library that has
+/// not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_buf_free(buf: *mut OxidBuf) {
+    if buf.is_null() || (*buf).data.is_null() {
+        return;
+    }
+    let slice = ptr::slice_from_raw_parts_mut((*buf).data, (*buf).len);
+    drop(Box::from_raw(slice));
+    (*buf).data = ptr::null_mut();
+    (*buf).len = 0;
+}
+
+/// Start walking the history of a revision
+///
+/// # Safety
+///
+/// `out` must be a valid pointer, `repo` a live handle that outlives the
+/// walk and `start` a NUL-terminated revision such as `"HEAD"`. The walk
+/// must be released with [`oxid_revwalk_free`].
+#[no_mangle]
+pub unsafe extern "C" fn oxid_revwalk_new(
+    out: *mut *mut OxidRevwalk,
+    repo: *const OxidRepository,
+    start: *const c_char,
+) -> OxidError {
+    guard(|| {
+        let Some(start) = str_arg(start) else {
+            return fail(OxidError::InvalidArgument, "start must be a UTF-8 string");
+        };
+        if out.is_null() || repo.is_null() {
+            return fail(OxidError::InvalidArgument, "invalid argument");
+        }
+
+        let inner = &(*repo).inner;
+        let commit = crate::revision::resolve_commit(inner, start)
+            .and_then(|hash| Ok((inner.read_commit(&hash)?.committer.time, hash)));
+        match commit {
+            Ok((time, hash)) => {
+                let walk = OxidRevwalk {
+                    repo,
+                    queue: BinaryHeap::from([(time, hash.clone())]),
+                    seen: HashSet::from([hash]),
+                };
+                *out = Box::into_raw(Box::new(walk));
+                OxidError::Ok
+            }
+            Err(e) => fail(OxidError::NotFound, e),
+        }
+    })
+}
+
+/// Store the next commit's id in `hash_out`
+///
+/// Returns [`OxidError::IterOver`] once every commit has been returned.
+/// The id is written as NUL-terminated hex, so `hash_len` must be at least
+/// 41.
+///
+/// # Safety
+///
+/// `walk` must be a live walk whose repository is still open and
+/// `hash_out` must point to `hash_len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_revwalk_next(
+    walk: *mut OxidRevwalk,
+    hash_out: *mut c_char,
+    hash_len: usize,
+) -> OxidError {
+    guard(|| {
+        if walk.is_null() || hash_out.is_null() {
+            return fail(OxidError::InvalidArgument, "invalid argument");
+        }
+        if hash_len < 41 {
+            return fail(
+                OxidError::InvalidArgument,
+                "hash_out must hold at least 41 bytes",
+            );
+        }
+        let walk = &mut *walk;
+        let Some((_, hash)) = walk.queue.pop() else {
+            return OxidError::IterOver;
+        };
+
+        let repo = &(*walk.repo).inner;
+        let parents = match repo.read_commit(&hash) {
+            Ok(commit) => commit.parents.clone(),
+            Err(e) => return fail(OxidError::Error, e),
+        };
+        for parent in parents {
+            if !walk.seen.insert(parent.clone()) {
+                continue;
+            }
+            match repo.read_commit(&parent) {
+                Ok(commit) => walk.queue.push((commit.committer.time, parent)),
+                Err(e) => return fail(OxidError::Error, e),
+            }
+        }
+        write_hash(&hash, hash_out, hash_len)
+    })
+}
+
+/// Release a history walk
+///
+/// # Safety
+///
+/// `walk` must be null or a walk returned by this library that has not
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_revwalk_free(walk: *mut OxidRevwalk) {
+    if !walk.is_null() {
+        drop(Box::from_raw(walk));
+    }
+}
+
+/// Collect the status of each changed path, as `oxid status` reports it
+///
+/// # Safety
+///
+/// `repo` must be a live handle and `out` a valid pointer. The list must be
+/// released with [`oxid_status_list_free`].
+#[no_mangle]
+pub unsafe extern "C" fn oxid_status_read(
+    repo: *const OxidRepository,
+    out: *mut OxidStatusList,
+) -> OxidError {
+    guard(|| {
+        if repo.is_null() || out.is_null() {
+            return fail(OxidError::InvalidArgument, "invalid argument");
+        }
+        let status = match repo_status(&(*repo).inner, None) {
+            Ok(status) => status,
+            Err(e) => return fail(OxidError::Error, e),
+        };
+
+        let mut flags: BTreeMap<Vec<u8>, u32> = BTreeMap::new();
+        for (path, change) in &status.staged {
+            *flags.entry(path.clone()).or_default() |= match change {
+                FileChange::Added => OXID_STATUS_INDEX_NEW,
+                FileChange::Modified => OXID_STATUS_INDEX_MODIFIED,
+                FileChange::Deleted => OXID_STATUS_INDEX_DELETED,
+                FileChange::Renamed => OXID_STATUS_INDEX_RENAMED,
+            };
+        }
+        for (path, change) in &status.unstaged {
+            *flags.entry(path.clone()).or_default() |= match change {
+                FileChange::Deleted => OXID_STATUS_WT_DELETED,
+                _ => OXID_STATUS_WT_MODIFIED,
+            };
+        }
+        for (path, _) in &status.unmerged {
+            *flags.entry(path.clone()).or_default() |= OXID_STATUS_CONFLICTED;
+        }
+        for path in &status.untracked {
+            *flags.entry(path.clone()).or_default() |= OXID_STATUS_WT_NEW;
+        }
+
+        // Git paths never contain NUL
+        let c_path = |path: &[u8]| CString::new(path).unwrap_or_default().into_raw();
+        let entries: Box<[OxidStatusEntry]> = flags
+            .into_iter()
+            .map(|(path, flags)| OxidStatusEntry {
+                old_path: status
+                    .renames
+                    .get(&path)
+                    .map_or(ptr::null_mut(), |old| c_path(old)),
+                path: c_path(&path),
+                flags,
+            })
+            .collect();
+        let count = entries.len();
+        *out = OxidStatusList {
+            entries: Box::into_raw(entries) as *mut OxidStatusEntry,
+            count,
+        };
+        OxidError::Ok
+    })
+}
+
+/// Release a status list and its paths
+///
+/// # Safety
+///
+/// `list` must be null or point to a list filled in by
+/// [`oxid_status_read`] that has not already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn oxid_status_list_free(list: *mut OxidStatusList) {
+    if list.is_null() || (*list).entries.is_null() {
+        return;
+    }
+    let entries = Box::from_raw(ptr::slice_from_raw_parts_mut(
+        (*list).entries,
+        (*list).count,
+    ));
+    for entry in entries.iter() {
+        for path in [entry.path, entry.old_path] {
+            if !path.is_null() {
+                drop(CString::from_raw(path));
+            }
+        }
+    }
+    (*list).entries = ptr::null_mut();
+    (*list).count = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_init_write_read_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut repo = ptr::null_mut();
+            assert_eq!(
+                oxid_repository_init(&mut repo, path.as_ptr()),
+                OxidError::Ok
+            );
+
+            let kind = CString::new("blob").unwrap();
+            let content = b"Hello World\n";
+            let mut hash = [0 as c_char; 41];
+            let code = oxid_object_write(
+                repo,
+                kind.as_ptr(),
+                content.as_ptr(),
+                content.len(),
+                hash.as_mut_ptr(),
+                hash.len(),
+            );
+            assert_eq!(code, OxidError::Ok);
+            let hash_str = CStr::from_ptr(hash.as_ptr()).to_str().unwrap();
+            assert_eq!(hash_str, "557db03de997c86a4a028e1ebd3a1ceb225be238");
+
+            let mut buf = OxidBuf {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            assert_eq!(
+                oxid_object_read(repo, hash.as_ptr(), &mut buf),
+                OxidError::Ok
+            );
+            let data = std::slice::from_raw_parts(buf.data, buf.len);
+            assert_eq!(data, b"blob 12\0Hello World\n");
+
+            oxid_buf_free(&mut buf);
+            oxid_repository_free(repo);
+        }
+    }
+
+    #[test]
+    fn test_bad_arguments_are_refused() {
+        let dir = tempdir().unwrap();
+        let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut repo = ptr::null_mut();
+            assert_eq!(
+                oxid_repository_init(&mut repo, path.as_ptr()),
+                OxidError::Ok
+            );
+
+            // Too small for the hash and its NUL
+            let kind = CString::new("blob").unwrap();
+            let mut hash = [0 as c_char; 40];
+            let code = oxid_object_write(
+                repo,
+                kind.as_ptr(),
+                b"x".as_ptr(),
+                1,
+                hash.as_mut_ptr(),
+                hash.len(),
+            );
+            assert_eq!(code, OxidError::InvalidArgument);
+            assert_eq!(hash, [0; 40]);
+
+            let mut buf = OxidBuf {
+                data: ptr::null_mut(),
+                len: 0,
+            };
+            for id in ["\u{e9}\u{e9}", "..", &"g".repeat(40), "557db03de9"] {
+                let id = CString::new(id).unwrap();
+                assert_eq!(
+                    oxid_object_read(repo, id.as_ptr(), &mut buf),
+                    OxidError::InvalidArgument
+                );
+            }
+            assert!(buf.data.is_null());
+
+            oxid_repository_free(repo);
+        }
+    }
+
+    #[test]
+    fn test_revwalk_and_status() {
+        let mut builder = crate::testing::RepoBuilder::new().unwrap();
+        builder.file("a.txt", "a\n");
+        let first = builder.commit("first").unwrap();
+        builder.branch("side").unwrap().file("b.txt", "b\n");
+        let side = builder.commit("side").unwrap();
+        builder.checkout("main").unwrap().file("c.txt", "c\n");
+        let second = builder.commit("second").unwrap();
+        let merge = builder.merge("side", "merge").unwrap();
+        std::fs::write(builder.path().join("new.txt"), b"new\n").unwrap();
+        let path = CString::new(builder.path().to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut repo = ptr::null_mut();
+            assert_eq!(
+                oxid_repository_open(&mut repo, path.as_ptr()),
+                OxidError::Ok
+            );
+
+            let mut walk = ptr::null_mut();
+            let start = CString::new("HEAD").unwrap();
+            assert_eq!(
+                oxid_revwalk_new(&mut walk, repo, start.as_ptr()),
+                OxidError::Ok
+            );
+            let mut hash = [0 as c_char; 41];
+            let mut hashes = Vec::new();
+            while oxid_revwalk_next(walk, hash.as_mut_ptr(), hash.len()) == OxidError::Ok {
+                hashes.push(CStr::from_ptr(hash.as_ptr()).to_str().unwrap().to_string());
+            }
+            assert_eq!(hashes, [merge, second, side, first]);
+            assert_eq!(
+                oxid_revwalk_next(walk, hash.as_mut_ptr(), hash.len()),
+                OxidError::IterOver
+            );
+            oxid_revwalk_free(walk);
+
+            let missing = CString::new("no-such-branch").unwrap();
+            let mut walk = ptr::null_mut();
+            assert_eq!(
+                oxid_revwalk_new(&mut walk, repo, missing.as_ptr()),
+                OxidError::NotFound
+            );
+            assert!(walk.is_null());
+
+            // The builder commits without touching the index, so every
+            // committed file looks deleted from it
+            let mut list = OxidStatusList {
+                entries: ptr::null_mut(),
+                count: 0,
+            };
+            assert_eq!(oxid_status_read(repo, &mut list), OxidError::Ok);
+            let entries: Vec<(&str, u32)> = std::slice::from_raw_parts(list.entries, list.count)
+                .iter()
+                .map(|e| (CStr::from_ptr(e.path).to_str().unwrap(), e.flags))
+                .collect();
+            assert_eq!(
+                entries,
+                [
+                    ("a.txt", OXID_STATUS_INDEX_DELETED),
+                    ("b.txt", OXID_STATUS_INDEX_DELETED),
+                    ("c.txt", OXID_STATUS_INDEX_DELETED),
+                    ("new.txt", OXID_STATUS_WT_NEW),
+                ]
+            );
+            oxid_status_list_free(&mut list);
+            assert!(list.entries.is_null());
+
+            oxid_repository_free(repo);
+        }
+    }
+
+    #[test]
+    fn test_panics_become_errors() {
+        assert_eq!(guard(|| panic!("boom")), OxidError::Error);
+        let message = unsafe { CStr::from_ptr(oxid_error_last()) };
+        assert_eq!(message.to_str().unwrap(), "internal error in oxid");
+    }
+
+    #[test]
+    fn test_open_missing_repository_sets_error() {
+        let dir = tempdir().unwrap();
+        let path = CString::new(dir.path().to_str().unwrap()).unwrap();
+
+        unsafe {
+            let mut repo = ptr::null_mut();
+            let code = oxid_repository_open(&mut repo, path.as_ptr());
+            assert_eq!(code, OxidError::NotFound);
+            assert!(repo.is_null());
+            assert!(!oxid_error_last().is_null());
+        }
+    }
+}
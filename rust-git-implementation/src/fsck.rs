@@ -0,0 +1,501 @@
+//! Object integrity checks
+//!
+//! Trees received from other repositories are untrusted: a hostile tree can
+//! contain entries like `.GIT`, `..` or `git~1` which, once checked out, write
+//! into the `.git` directory on case-insensitive (HFS+) or NTFS filesystems.
+//! This module detects such entries, along with trees and commits that are
+//! malformed or not in the canonical form Git writes, using the same message
+//! IDs as Git's `fsck` so the output is familiar.
+
+use crate::objects::{parse_object, ObjectType, TreeRef};
+use crate::utils::is_object_id;
+use anyhow::Result;
+
+/// How serious a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found while checking an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsckMsgId {
+    /// A tree entry with an empty name
+    EmptyName,
+    /// A tree entry name containing `/`
+    FullPathname,
+    /// A tree entry named `.`
+    HasDot,
+    /// A tree entry named `..`
+    HasDotdot,
+    /// A tree entry that would be treated as `.git` on some filesystem
+    HasDotgit,
+    /// A mode with a leading zero (e.g. `040000`)
+    ZeroPaddedFilemode,
+    /// A mode Git never writes (e.g. `100664`)
+    BadFilemode,
+    /// A tree that can't be parsed
+    BadTree,
+    /// Tree entries not in Git's canonical order
+    TreeNotSorted,
+    /// Two tree entries with the same name
+    DuplicateEntries,
+    /// A commit without a `tree` header
+    MissingTree,
+    /// A commit whose `tree` header isn't a valid object id
+    BadTreeSha1,
+    /// A commit with a malformed `parent` header
+    BadParentSha1,
+    /// A commit without an `author` header
+    MissingAuthor,
+    /// A commit without a `committer` header
+    MissingCommitter,
+    /// An author or committer without `<email>`
+    MissingEmail,
+    /// An author or committer with a malformed timestamp
+    BadDate,
+    /// An author or committer with a malformed timezone
+    BadTimezone,
+}
+
+impl FsckMsgId {
+    /// The camelCase identifier Git uses (e.g. in `fsck.<msg-id>` config)
+    pub fn as_str(&self) -> &str {
+        match self {
+            FsckMsgId::EmptyName => "emptyName",
+            FsckMsgId::FullPathname => "fullPathname",
+            FsckMsgId::HasDot => "hasDot",
+            FsckMsgId::HasDotdot => "hasDotdot",
+            FsckMsgId::HasDotgit => "hasDotgit",
+            FsckMsgId::ZeroPaddedFilemode => "zeroPaddedFilemode",
+            FsckMsgId::BadFilemode => "badFilemode",
+            FsckMsgId::BadTree => "badTree",
+            FsckMsgId::TreeNotSorted => "treeNotSorted",
+            FsckMsgId::DuplicateEntries => "duplicateEntries",
+            FsckMsgId::MissingTree => "missingTree",
+            FsckMsgId::BadTreeSha1 => "badTreeSha1",
+            FsckMsgId::BadParentSha1 => "badParentSha1",
+            FsckMsgId::MissingAuthor => "missingAuthor",
+            FsckMsgId::MissingCommitter => "missingCommitter",
+            FsckMsgId::MissingEmail => "missingEmail",
+            FsckMsgId::BadDate => "badDate",
+            FsckMsgId::BadTimezone => "badTimezone",
+        }
+    }
+
+    /// Severity of the finding
+    ///
+    /// Malformed objects are always errors. Otherwise, in strict mode (as
+    /// used for `receive.fsckObjects` and `transfer.fsckObjects`), every
+    /// finding is an error.
+    pub fn severity(&self, strict: bool) -> Severity {
+        let always_error = matches!(
+            self,
+            FsckMsgId::BadTree
+                | FsckMsgId::TreeNotSorted
+                | FsckMsgId::DuplicateEntries
+                | FsckMsgId::MissingTree
+                | FsckMsgId::BadTreeSha1
+                | FsckMsgId::BadParentSha1
+                | FsckMsgId::MissingAuthor
+                | FsckMsgId::MissingCommitter
+        );
+        if strict || always_error {
+            Severity::Error
+        } else {
+            Severity::Warning
+        }
+    }
+}
+
+/// Modes Git writes for tree entries
+const VALID_MODES: [&[u8]; 5] = [b"100644", b"100755", b"120000", b"40000", b"160000"];
+
+/// Check a single tree entry's mode and name
+///
+/// Returns every problem found; an empty vector means the entry is fine.
+///
+/// # Arguments
+///
+/// * `mode` - The entry's mode as stored, e.g. `100644`
+/// * `name` - The entry's name
+/// * `strict` - Whether to also flag modes Git tolerates (`--strict`)
+pub fn check_tree_entry(mode: &[u8], name: &[u8], strict: bool) -> Vec<FsckMsgId> {
+    let mut problems = Vec::new();
+
+    if mode.first() == Some(&b'0') {
+        problems.push(FsckMsgId::ZeroPaddedFilemode);
+    } else if !VALID_MODES.contains(&mode) {
+        // Only very old Git versions wrote 100664; like Git, it is only
+        // reported in strict mode
+        if strict || mode != b"100664" {
+            problems.push(FsckMsgId::BadFilemode);
+        }
+    }
+
+    if name.is_empty() {
+        problems.push(FsckMsgId::EmptyName);
+    }
+    if name.contains(&b'/') {
+        problems.push(FsckMsgId::FullPathname);
+    }
+    if name == b"." {
+        problems.push(FsckMsgId::HasDot);
+    }
+    if name == b".." {
+        problems.push(FsckMsgId::HasDotdot);
+    }
+    if is_dot_git(name) || is_hfs_dot_git(name) || is_ntfs_dot_git(name) {
+        problems.push(FsckMsgId::HasDotgit);
+    }
+
+    problems
+}
+
+/// Check a tree object's content: every entry, and their order
+///
+/// Git sorts tree entries by name, comparing a subdirectory as if its name
+/// ended with `/`. Trees in any other order hash differently from the ones
+/// Git would write for the same content.
+///
+/// # Arguments
+///
+/// * `data` - Tree content, without the object header
+/// * `strict` - Whether to also flag modes Git tolerates (`--strict`)
+pub fn check_tree(data: &[u8], strict: bool) -> Vec<FsckMsgId> {
+    let mut problems = Vec::new();
+    let mut previous: Option<(Vec<u8>, &[u8])> = None;
+
+    for entry in TreeRef::new(data) {
+        let Ok(entry) = entry else {
+            problems.push(FsckMsgId::BadTree);
+            break;
+        };
+        let (mode, name) = (entry.mode, entry.name);
+
+        for problem in check_tree_entry(mode, name, strict) {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+
+        let mut key = name.to_vec();
+        if entry.is_tree() {
+            key.push(b'/');
+        }
+        if let Some((previous_key, previous_name)) = &previous {
+            if *previous_name == name {
+                if !problems.contains(&FsckMsgId::DuplicateEntries) {
+                    problems.push(FsckMsgId::DuplicateEntries);
+                }
+            } else if *previous_key > key && !problems.contains(&FsckMsgId::TreeNotSorted) {
+                problems.push(FsckMsgId::TreeNotSorted);
+            }
+        }
+        previous = Some((key, name));
+    }
+
+    problems
+}
+
+/// Check a commit object's headers
+///
+/// A commit starts with `tree`, any number of `parent` lines, then `author`
+/// and `committer` identities of the form `Name <email> <seconds> <+hhmm>`.
+pub fn check_commit(data: &[u8]) -> Vec<FsckMsgId> {
+    let mut problems = Vec::new();
+    let header_end = data
+        .windows(2)
+        .position(|w| w == b"\n\n")
+        .map_or(data.len(), |i| i + 1);
+    let mut lines = data[..header_end]
+        .split(|&b| b == b'\n')
+        .filter(|l| !l.is_empty())
+        .peekable();
+
+    match lines.next().and_then(|l| l.strip_prefix(b"tree ")) {
+        Some(id) if is_object_id(id) => {}
+        Some(_) => problems.push(FsckMsgId::BadTreeSha1),
+        None => {
+            problems.push(FsckMsgId::MissingTree);
+            return problems;
+        }
+    }
+
+    while let Some(id) = lines.peek().and_then(|l| l.strip_prefix(b"parent ")) {
+        if !is_object_id(id) {
+            problems.push(FsckMsgId::BadParentSha1);
+        }
+        lines.next();
+    }
+
+    for (header, missing) in [
+        (&b"author "[..], FsckMsgId::MissingAuthor),
+        (&b"committer "[..], FsckMsgId::MissingCommitter),
+    ] {
+        match lines.next().and_then(|l| l.strip_prefix(header)) {
+            Some(ident) => problems.extend(check_ident(ident)),
+            None => {
+                problems.push(missing);
+                return problems;
+            }
+        }
+    }
+
+    problems
+}
+
+/// Check a `Name <email> <seconds> <+hhmm>` identity
+fn check_ident(ident: &[u8]) -> Option<FsckMsgId> {
+    let Some(close) = ident.iter().position(|&b| b == b'>') else {
+        return Some(FsckMsgId::MissingEmail);
+    };
+    if !ident[..close].contains(&b'<') {
+        return Some(FsckMsgId::MissingEmail);
+    }
+
+    let Some(rest) = ident[close + 1..].strip_prefix(b" ") else {
+        return Some(FsckMsgId::BadDate);
+    };
+    let mut parts = rest.split(|&b| b == b' ');
+    let date = parts.next().unwrap_or_default();
+    if date.is_empty()
+        || !date.iter().all(u8::is_ascii_digit)
+        || (date.len() > 1 && date[0] == b'0')
+    {
+        return Some(FsckMsgId::BadDate);
+    }
+    match parts.next() {
+        Some([b'+' | b'-', digits @ ..])
+            if digits.len() == 4 && digits.iter().all(u8::is_ascii_digit) =>
+        {
+            None
+        }
+        _ => Some(FsckMsgId::BadTimezone),
+    }
+}
+
+/// Reject an object received from another repository if it has errors
+///
+/// Warnings are let through, as Git does without `transfer.fsckObjects`.
+///
+/// # Arguments
+///
+/// * `data` - Decompressed object data, header included
+pub fn check_received(data: &[u8]) -> Result<()> {
+    let (kind, content) = parse_object(data)?;
+    let findings = match kind {
+        ObjectType::Tree => check_tree(content, false),
+        ObjectType::Commit => check_commit(content),
+        ObjectType::Blob | ObjectType::Tag => Vec::new(),
+    };
+    match findings
+        .into_iter()
+        .find(|id| id.severity(false) == Severity::Error)
+    {
+        Some(id) => anyhow::bail!("{}", id.as_str()),
+        None => Ok(()),
+    }
+}
+
+/// `.git` in any letter case
+pub fn is_dot_git(name: &[u8]) -> bool {
+    name.eq_ignore_ascii_case(b".git")
+}
+
+/// Code points HFS+ ignores when comparing file names
+fn is_hfs_ignorable(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200c}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{206a}'..='\u{206f}' | '\u{feff}'
+    )
+}
+
+/// A name HFS+ would treat as `.git`, e.g. `.g\u{200c}it`
+pub fn is_hfs_dot_git(name: &[u8]) -> bool {
+    let Ok(name) = std::str::from_utf8(name) else {
+        return false;
+    };
+    let stripped: String = name.chars().filter(|c| !is_hfs_ignorable(*c)).collect();
+    stripped.eq_ignore_ascii_case(".git")
+}
+
+/// A name NTFS would treat as `.git`
+///
+/// Catches the 8.3 short name `git~1`, trailing dots and spaces (which NTFS
+/// strips), alternate data streams (`.git::$INDEX_ALLOCATION`) and
+/// backslash-separated components.
+pub fn is_ntfs_dot_git(name: &[u8]) -> bool {
+    name.split(|&b| b == b'\\').any(|component| {
+        let lower = component.to_ascii_lowercase();
+        [&b".git"[..], &b"git~1"[..]].iter().any(|candidate| {
+            let Some(rest) = lower.strip_prefix(*candidate) else {
+                return false;
+            };
+            let rest = match rest.iter().position(|&b| b == b':') {
+                Some(colon) => &rest[..colon],
+                None => rest,
+            };
+            rest.iter().all(|&b| b == b' ' || b == b'.')
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_entries() {
+        assert!(check_tree_entry(b"100644", b"README.md", false).is_empty());
+        assert!(check_tree_entry(b"40000", b"src", false).is_empty());
+        assert!(check_tree_entry(b"120000", b"link", false).is_empty());
+        assert!(check_tree_entry(b"100644", b".gitignore", false).is_empty());
+        assert!(check_tree_entry(b"40000", b".github", false).is_empty());
+    }
+
+    #[test]
+    fn test_bad_modes() {
+        assert_eq!(
+            check_tree_entry(b"040000", b"src", false),
+            vec![FsckMsgId::ZeroPaddedFilemode]
+        );
+        assert_eq!(
+            check_tree_entry(b"100600", b"a", false),
+            vec![FsckMsgId::BadFilemode]
+        );
+        assert!(check_tree_entry(b"100664", b"a", false).is_empty());
+        assert_eq!(
+            check_tree_entry(b"100664", b"a", true),
+            vec![FsckMsgId::BadFilemode]
+        );
+    }
+
+    #[test]
+    fn test_path_tricks() {
+        assert_eq!(
+            check_tree_entry(b"40000", b"", false),
+            vec![FsckMsgId::EmptyName]
+        );
+        assert_eq!(
+            check_tree_entry(b"40000", b".", false),
+            vec![FsckMsgId::HasDot]
+        );
+        assert_eq!(
+            check_tree_entry(b"40000", b"..", false),
+            vec![FsckMsgId::HasDotdot]
+        );
+        assert_eq!(
+            check_tree_entry(b"100644", b"a/b", false),
+            vec![FsckMsgId::FullPathname]
+        );
+    }
+
+    #[test]
+    fn test_dot_git_variants() {
+        for name in [
+            &b".git"[..],
+            b".GIT",
+            b".Git",
+            b"git~1",
+            b"GIT~1",
+            b".git.",
+            b".git . ",
+            b".git::$INDEX_ALLOCATION",
+            b"foo\\.git",
+            ".g\u{200c}it".as_bytes(),
+            "\u{feff}.GIT".as_bytes(),
+        ] {
+            assert_eq!(
+                check_tree_entry(b"40000", name, false),
+                vec![FsckMsgId::HasDotgit],
+                "{}",
+                String::from_utf8_lossy(name)
+            );
+        }
+
+        assert!(!is_ntfs_dot_git(b".gitmodules"));
+        assert!(!is_ntfs_dot_git(b"git~10"));
+    }
+
+    fn tree(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (mode, name) in entries {
+            data.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+            data.extend_from_slice(&[0xab; 20]);
+        }
+        data
+    }
+
+    #[test]
+    fn test_check_tree() {
+        // "a.txt" < "a/" < "ab": the directory sorts as "a/"
+        assert!(check_tree(
+            &tree(&[("100644", "a.txt"), ("40000", "a"), ("100644", "ab")]),
+            false
+        )
+        .is_empty());
+        assert_eq!(
+            check_tree(&tree(&[("40000", "a"), ("100644", "a.txt")]), false),
+            vec![FsckMsgId::TreeNotSorted]
+        );
+        assert_eq!(
+            check_tree(&tree(&[("100644", "a"), ("100644", "a")]), false),
+            vec![FsckMsgId::DuplicateEntries]
+        );
+        assert_eq!(
+            check_tree(&tree(&[("100644", ".GIT")]), false),
+            vec![FsckMsgId::HasDotgit]
+        );
+
+        let mut truncated = tree(&[("100644", "a")]);
+        truncated.pop();
+        assert_eq!(check_tree(&truncated, false), vec![FsckMsgId::BadTree]);
+    }
+
+    #[test]
+    fn test_check_commit() {
+        let tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        let ident = "A U Thor <author@example.com> 1700000000 +0100";
+        let good = format!(
+            "tree {}\nparent {}\nauthor {}\ncommitter {}\n\nmessage\n",
+            tree, tree, ident, ident
+        );
+        assert!(check_commit(good.as_bytes()).is_empty());
+
+        assert_eq!(check_commit(b"author x\n"), vec![FsckMsgId::MissingTree]);
+        assert_eq!(
+            check_commit(format!("tree {}\nauthor {}\n\n", tree, ident).as_bytes()),
+            vec![FsckMsgId::MissingCommitter]
+        );
+        assert_eq!(
+            check_commit(format!("tree abc\nauthor {}\ncommitter {}\n", ident, ident).as_bytes()),
+            vec![FsckMsgId::BadTreeSha1]
+        );
+        assert_eq!(
+            check_commit(
+                format!(
+                    "tree {}\nauthor A <a> 17x +0100\ncommitter A a 1 +0000\n",
+                    tree
+                )
+                .as_bytes()
+            ),
+            vec![FsckMsgId::BadDate, FsckMsgId::MissingEmail]
+        );
+        assert_eq!(
+            check_commit(
+                format!("tree {}\nauthor A <a> 1 0100\ncommitter {}\n", tree, ident).as_bytes()
+            ),
+            vec![FsckMsgId::BadTimezone]
+        );
+    }
+
+    #[test]
+    fn test_strict_mode_escalates() {
+        assert_eq!(FsckMsgId::HasDotgit.severity(false), Severity::Warning);
+        assert_eq!(FsckMsgId::HasDotgit.severity(true), Severity::Error);
+        assert_eq!(FsckMsgId::HasDotgit.as_str(), "hasDotgit");
+        assert_eq!(FsckMsgId::TreeNotSorted.severity(false), Severity::Error);
+    }
+}
@@ -0,0 +1,184 @@
+//! Entry points for fuzzing the parsers
+//!
+//! Each function feeds arbitrary bytes to one parser and throws away the
+//! result; the only failure a fuzzer should ever find is a panic. The
+//! targets live in `fuzz/` and run with `cargo fuzz run <target>`.
+
+use crate::compat;
+use crate::fsck;
+use crate::index::Index;
+use crate::limits::Limits;
+use crate::mailmap::Mailmap;
+use crate::objects::Commit;
+use crate::protocol::PktLineReader;
+use crate::repository::Repository;
+use crate::unpack;
+use crate::utils::{hash_data, is_object_id};
+use sha1::{Digest, Sha1};
+use std::io::Read;
+
+/// Limits small enough that announced sizes can't exhaust memory
+const LIMITS: Limits = Limits {
+    max_object_size: 1 << 20,
+    max_delta_depth: 50,
+    max_tree_entries: 1 << 16,
+    max_pkt_line_length: crate::protocol::pktline::MAX_PACKET_LEN as u64,
+};
+
+/// A stored object (`<type> <size>\0<content>`), checked like `compat-check` does
+pub fn object(data: &[u8]) {
+    let _ = compat::check_object(&hash_data(data), data);
+}
+
+/// Commit content without the object header
+pub fn commit(data: &[u8]) {
+    if let Ok(commit) = Commit::parse_content(data) {
+        // Ids end up in object paths, so they must be well-formed
+        assert!(is_object_id(commit.tree.as_bytes()));
+        assert!(commit.parents.iter().all(|id| is_object_id(id.as_bytes())));
+    }
+    let _ = fsck::check_commit(data);
+}
+
+/// Tree content without the object header
+pub fn tree(data: &[u8]) {
+    let _ = fsck::check_tree(data, false);
+}
+
+/// An index file
+///
+/// The trailing checksum is appended here, since a fuzzer would hardly
+/// ever get it right and everything behind it would go untested.
+pub fn index(data: &[u8]) {
+    let mut file = data.to_vec();
+    file.extend_from_slice(&Sha1::digest(data));
+    if let Ok(index) = Index::parse(&file) {
+        let _ = index.to_bytes();
+    }
+}
+
+/// A `.mailmap` file
+pub fn mailmap(data: &[u8]) {
+    let mut mailmap = Mailmap::new();
+    mailmap.parse(&String::from_utf8_lossy(data));
+    let _ = mailmap.resolve(Some("A U Thor"), "author@example.com");
+}
+
+/// A pkt-line stream, read packet by packet and then as a sideband stream
+pub fn pktline(data: &[u8]) {
+    let mut reader = PktLineReader::new(data);
+    while let Ok(Some(_)) = reader.read_packet() {}
+
+    let mut pack = Vec::new();
+    let _ = PktLineReader::new(data)
+        .sideband(|_| {})
+        .read_to_end(&mut pack);
+}
+
+/// A delta: the first byte gives the length of the base after it, and the
+/// rest is applied to that base
+pub fn delta(data: &[u8]) {
+    let Some((&len, rest)) = data.split_first() else {
+        return;
+    };
+    let (base, delta) = rest.split_at((len as usize).min(rest.len()));
+    let _ = unpack::apply_delta(base, delta, &LIMITS);
+}
+
+/// A pack, unpacked into a scratch repository
+///
+/// The trailing checksum is appended here, as for [`index`].
+pub fn pack(data: &[u8]) {
+    thread_local! {
+        static REPO: (tempfile::TempDir, Repository) = {
+            let dir = tempfile::tempdir().expect("temporary directory");
+            let repo = Repository::init(dir.path()).expect("scratch repository");
+            (dir, repo)
+        };
+    }
+    let mut pack = data.to_vec();
+    pack.extend_from_slice(&Sha1::digest(data));
+    REPO.with(|(_, repo)| {
+        if let Ok(quarantine) = repo.quarantine() {
+            let _ = unpack::unpack_objects(&quarantine, &LIMITS, &pack[..]);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexEntry;
+    use crate::objects::{FileMode, GitObject, Signature};
+
+    /// Every prefix of `data`, and `data` with each byte flipped
+    fn mutations(data: &[u8]) -> Vec<Vec<u8>> {
+        let mut inputs: Vec<Vec<u8>> = (0..=data.len()).map(|n| data[..n].to_vec()).collect();
+        for i in 0..data.len() {
+            for bits in [0x01, 0x80, 0xff] {
+                let mut input = data.to_vec();
+                input[i] ^= bits;
+                inputs.push(input);
+            }
+        }
+        inputs
+    }
+
+    #[test]
+    fn test_mutated_inputs_do_not_panic() {
+        let sample_commit = Commit {
+            tree: "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+            parents: vec!["4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string()],
+            author: Signature::new("A U Thor", "author@example.com", 1112911993, -420),
+            committer: Signature::new("C O Mitter", "committer@example.com", 1112911993, -420),
+            message: "message\n".to_string(),
+            extra_headers: Vec::new(),
+            gpgsig: None,
+        };
+        let mut sample_tree = b"100644 a\0".to_vec();
+        sample_tree.extend_from_slice(&[0xab; 20]);
+        sample_tree.extend_from_slice(b"40000 dir\0");
+        sample_tree.extend_from_slice(&[0xcd; 20]);
+
+        let mut sample_index = Index::new();
+        sample_index.add(IndexEntry::new(
+            "dir/file",
+            FileMode::Regular,
+            "e69de29bb2d1d6434b8b29ae775a8c2e48c5391d",
+        ));
+        sample_index.add(IndexEntry::new(
+            "link",
+            FileMode::Symlink,
+            "e69de29bb2d1d6434b8b29ae775a8c2e48c5391d",
+        ));
+        let sample_index = sample_index.to_bytes().unwrap();
+
+        for input in mutations(&sample_commit.to_bytes().unwrap()) {
+            object(&input);
+        }
+        for input in mutations(&sample_commit.serialize().unwrap()) {
+            commit(&input);
+        }
+        for input in mutations(&sample_tree) {
+            tree(&input);
+        }
+        for input in mutations(&sample_index[..sample_index.len() - 20]) {
+            index(&input);
+        }
+        for input in mutations(b"Proper <proper@example.com> Commit <author@example.com>\n") {
+            mailmap(&input);
+        }
+        for input in mutations(b"000ewant 1234\n0001000a\x01PACK\n0009\x02done0000") {
+            pktline(&input);
+        }
+        // Base "hello world"; copy "hello " and insert "rust"
+        for input in mutations(b"\x0bhello world\x0b\x0a\x90\x06\x04rust") {
+            delta(&input);
+        }
+        let blob = b"blob 12\0hello world\n";
+        let sample_pack = unpack::write_pack([&blob[..]]).unwrap();
+        for input in mutations(&sample_pack[..sample_pack.len() - 20]) {
+            pack(&input);
+        }
+    }
+}
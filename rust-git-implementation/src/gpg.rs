@@ -0,0 +1,410 @@
+//! Signing objects and verifying signatures on tags
+//!
+//! A signed tag carries its signature at the end of the message, as an
+//! ASCII-armored block. Everything before the block is what was signed.
+//! Verification is delegated to GnuPG, as Git does: the signature and
+//! payload are handed to `gpg --verify` and its machine-readable status
+//! lines (`--status-fd`) decide the outcome.
+//!
+//! Signatures are made by a [`Signer`]. [`GpgSigner`] runs GnuPG like
+//! `git commit -S`; embedders whose keys live elsewhere (a hardware
+//! security module, a cloud KMS) implement the trait themselves and pass
+//! it to [`Commit::sign`](crate::objects::Commit::sign) or
+//! [`commit_tree`](crate::commands::commit_tree::commit_tree).
+//!
+//! The program comes from `gpg.openpgp.program` or `gpg.program` (default
+//! `gpg`) for OpenPGP signatures and from `gpg.x509.program` (default
+//! `gpgsm`) for X.509 ones. SSH signatures are not supported.
+
+use crate::config::Config;
+use crate::external_tool::TempDir;
+use crate::ident::{self, Role};
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Kinds of signature, recognised by the first line of their block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureFormat {
+    OpenPgp,
+    X509,
+    Ssh,
+}
+
+impl SignatureFormat {
+    /// Recognise the first line of a signature block
+    fn from_header(line: &[u8]) -> Option<Self> {
+        const HEADERS: [(&[u8], SignatureFormat); 4] = [
+            (b"-----BEGIN PGP SIGNATURE-----", SignatureFormat::OpenPgp),
+            (b"-----BEGIN PGP MESSAGE-----", SignatureFormat::OpenPgp),
+            (b"-----BEGIN SIGNED MESSAGE-----", SignatureFormat::X509),
+            (b"-----BEGIN SSH SIGNATURE-----", SignatureFormat::Ssh),
+        ];
+        HEADERS
+            .iter()
+            .find(|(header, _)| line.starts_with(header))
+            .map(|(_, format)| *format)
+    }
+}
+
+/// Produces signatures for objects
+pub trait Signer {
+    /// Sign a payload
+    ///
+    /// Returns a detached, ASCII-armored signature, e.g. a
+    /// `-----BEGIN PGP SIGNATURE-----` block.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The bytes to sign, e.g. from
+    ///   [`Commit::signing_payload`](crate::objects::Commit::signing_payload)
+    fn sign(&self, payload: &[u8]) -> Result<String>;
+}
+
+/// Signs by running GnuPG (or `gpgsm`) as Git does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpgSigner {
+    program: String,
+    key: String,
+}
+
+impl GpgSigner {
+    /// # Arguments
+    ///
+    /// * `program` - The signing program, called as `<program> -bsau <key>`
+    /// * `key` - Anything identifying the key to the program
+    pub fn new(program: impl Into<String>, key: impl Into<String>) -> Self {
+        GpgSigner {
+            program: program.into(),
+            key: key.into(),
+        }
+    }
+
+    /// The signer configured for the repository
+    ///
+    /// `gpg.format` picks the program as for verification. The key is
+    /// `key` if given, then `user.signingKey`, then the committer
+    /// identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The repository configuration
+    /// * `key` - Key id given on the command line, e.g. with `-S<keyid>`
+    pub fn from_config(config: &Config, key: Option<&str>) -> Result<Self> {
+        let program = match config.get("gpg.format").unwrap_or("openpgp") {
+            "openpgp" => config
+                .get("gpg.openpgp.program")
+                .or(config.get("gpg.program"))
+                .unwrap_or("gpg"),
+            "x509" => config.get("gpg.x509.program").unwrap_or("gpgsm"),
+            "ssh" => anyhow::bail!("SSH signatures are not supported"),
+            other => anyhow::bail!("invalid value for 'gpg.format': '{}'", other),
+        };
+        let key = match key.or(config.get("user.signingKey")) {
+            Some(key) => key.to_string(),
+            None => {
+                let committer = ident::ident(config, Role::Committer)?;
+                format!("{} <{}>", committer.name, committer.email)
+            }
+        };
+        Ok(Self::new(program, key))
+    }
+}
+
+impl Signer for GpgSigner {
+    fn sign(&self, payload: &[u8]) -> Result<String> {
+        let mut child = Command::new(&self.program)
+            .arg("--status-fd=2")
+            .arg("-bsau")
+            .arg(&self.key)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run {}", self.program))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(payload)?;
+        let output = child.wait_with_output()?;
+        let status = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() || !status.contains("[GNUPG:] SIG_CREATED ") {
+            anyhow::bail!(
+                "{} failed to sign the data:\n{}",
+                self.program,
+                status.trim_end()
+            );
+        }
+        String::from_utf8(output.stdout).context("signature is not valid UTF-8")
+    }
+}
+
+/// Outcome of checking a signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verification {
+    /// The signature is valid; `signer` is the key's user id
+    Good { signer: String },
+    /// The signature is valid but the key has expired or been revoked
+    Expired { signer: String },
+    /// The signature doesn't match the payload
+    Bad { signer: String },
+    /// The signature couldn't be checked, usually because the public key
+    /// is missing; `key` is empty for SSH signatures, which can't be
+    /// checked at all
+    Unknown { key: String },
+    /// There is no signature
+    Unsigned,
+}
+
+impl Verification {
+    /// Whether the signature is present and valid
+    pub fn is_good(&self) -> bool {
+        matches!(self, Verification::Good { .. })
+    }
+
+    /// Short human-readable description, e.g. `good signature from "A U Thor"`
+    pub fn description(&self) -> String {
+        match self {
+            Verification::Good { signer } => format!("good signature from \"{}\"", signer),
+            Verification::Expired { signer } => {
+                format!("good signature from expired or revoked key \"{}\"", signer)
+            }
+            Verification::Bad { signer } => format!("BAD signature from \"{}\"", signer),
+            Verification::Unknown { key } if key.is_empty() => {
+                "can't check signature: SSH signatures are not supported".to_string()
+            }
+            Verification::Unknown { key } => {
+                format!("can't check signature: no public key {}", key)
+            }
+            Verification::Unsigned => "unsigned".to_string(),
+        }
+    }
+}
+
+/// Split a signed object's content into the signed payload and the
+/// signature block
+///
+/// Returns `None` if the content has no signature. As in Git, the last
+/// block wins if there are several.
+///
+/// # Arguments
+///
+/// * `content` - Object content without its header, e.g. a tag
+pub fn split_signature(content: &[u8]) -> Option<(&[u8], &[u8], SignatureFormat)> {
+    let mut found = None;
+    let mut start = 0;
+    while start < content.len() {
+        if let Some(format) = SignatureFormat::from_header(&content[start..]) {
+            found = Some((start, format));
+        }
+        start += content[start..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .map_or(content.len() - start, |i| i + 1);
+    }
+    found.map(|(start, format)| (&content[..start], &content[start..], format))
+}
+
+/// Verify the signature of a signed object's content
+///
+/// # Arguments
+///
+/// * `config` - Configuration naming the verification programs
+/// * `content` - Object content without its header, e.g. a tag
+pub fn verify(config: &Config, content: &[u8]) -> Result<Verification> {
+    let Some((payload, signature, format)) = split_signature(content) else {
+        return Ok(Verification::Unsigned);
+    };
+    let program = match format {
+        SignatureFormat::OpenPgp => config
+            .get("gpg.openpgp.program")
+            .or(config.get("gpg.program"))
+            .unwrap_or("gpg"),
+        SignatureFormat::X509 => config.get("gpg.x509.program").unwrap_or("gpgsm"),
+        SignatureFormat::Ssh => return Ok(Verification::Unknown { key: String::new() }),
+    };
+
+    let dir = TempDir::new("oxid-gpg")?;
+    let signature_file = dir.write("signature", signature)?;
+    let mut child = Command::new(program)
+        .arg("--status-fd=1")
+        .arg("--verify")
+        .arg(&signature_file)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", program))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(payload)?;
+    let output = child.wait_with_output()?;
+    parse_status(
+        &String::from_utf8_lossy(&output.stdout),
+        output.status.success(),
+    )
+    .with_context(|| format!("{} gave no verification result", program))
+}
+
+/// Interpret GnuPG's status lines
+///
+/// As in Git, the signature only counts as good if there is exactly one
+/// signature, it is good, and the program exited successfully.
+///
+/// # Arguments
+///
+/// * `status` - The `--status-fd` output
+/// * `success` - Whether the program exited with status zero
+fn parse_status(status: &str, success: bool) -> Option<Verification> {
+    let mut results = Vec::new();
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, args) = line.split_once(' ').unwrap_or((line, ""));
+        // GOODSIG and friends give the key id, then the user id
+        let signer = || {
+            let (key, uid) = args.split_once(' ').unwrap_or((args, ""));
+            if uid.is_empty() { key } else { uid }.to_string()
+        };
+        let result = match keyword {
+            "GOODSIG" => Verification::Good { signer: signer() },
+            "EXPSIG" | "EXPKEYSIG" | "REVKEYSIG" => Verification::Expired { signer: signer() },
+            "BADSIG" => Verification::Bad { signer: signer() },
+            "ERRSIG" => {
+                let key = args.split(' ').next().unwrap_or_default().to_string();
+                Verification::Unknown { key }
+            }
+            _ => continue,
+        };
+        results.push(result);
+    }
+
+    // A bad or uncheckable signature spoils the lot
+    let worst = results
+        .iter()
+        .find(|result| matches!(result, Verification::Bad { .. }))
+        .or_else(|| {
+            results
+                .iter()
+                .find(|result| matches!(result, Verification::Unknown { .. }))
+        });
+    if let Some(worst) = worst {
+        return Some(worst.clone());
+    }
+    match results.pop()? {
+        Verification::Good { signer } | Verification::Expired { signer }
+            if !results.is_empty() || !success =>
+        {
+            Some(Verification::Bad { signer })
+        }
+        result => Some(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_signature() {
+        let tag = b"object 1234\ntype commit\ntag v1\n\nrelease\n\
+            -----BEGIN PGP SIGNATURE-----\n\nabc=\n-----END PGP SIGNATURE-----\n";
+        let (payload, signature, format) = split_signature(tag).unwrap();
+        assert_eq!(payload, b"object 1234\ntype commit\ntag v1\n\nrelease\n");
+        assert!(signature.starts_with(b"-----BEGIN PGP SIGNATURE-----\n"));
+        assert_eq!(format, SignatureFormat::OpenPgp);
+
+        assert!(split_signature(b"object 1234\n\nno signature here\n").is_none());
+        // The block must start a line
+        assert!(split_signature(b"message -----BEGIN PGP SIGNATURE-----\n").is_none());
+    }
+
+    #[test]
+    fn test_ssh_signatures_are_unknown() {
+        let tag = b"object 1234\ntype commit\ntag v1\n\nrelease\n\
+            -----BEGIN SSH SIGNATURE-----\nabc=\n-----END SSH SIGNATURE-----\n";
+        let result = verify(&Config::new(), tag).unwrap();
+        assert_eq!(result, Verification::Unknown { key: String::new() });
+        assert!(!result.is_good());
+        assert!(result.description().contains("SSH"));
+    }
+
+    #[test]
+    fn test_signer_from_config() {
+        let config = Config::parse("[gpg]\n\tprogram = my-gpg\n").unwrap();
+        assert_eq!(
+            GpgSigner::from_config(&config, Some("ABCD1234")).unwrap(),
+            GpgSigner::new("my-gpg", "ABCD1234")
+        );
+
+        let config =
+            Config::parse("[user]\n\tsigningKey = 5678\n[gpg]\n\tformat = x509\n").unwrap();
+        assert_eq!(
+            GpgSigner::from_config(&config, None).unwrap(),
+            GpgSigner::new("gpgsm", "5678")
+        );
+        let config = Config::parse("[gpg]\n\tformat = ssh\n").unwrap();
+        assert!(GpgSigner::from_config(&config, Some("k")).is_err());
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let good = "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 1234ABCD A U Thor <author@example.com>\n\
+            [GNUPG:] VALIDSIG 0000\n";
+        assert_eq!(
+            parse_status(good, true),
+            Some(Verification::Good {
+                signer: "A U Thor <author@example.com>".to_string()
+            })
+        );
+        assert_eq!(
+            parse_status("[GNUPG:] BADSIG 1234ABCD A U Thor\n", false),
+            Some(Verification::Bad {
+                signer: "A U Thor".to_string()
+            })
+        );
+        assert_eq!(
+            parse_status(
+                "[GNUPG:] ERRSIG 1234ABCD 1 8 00 1112912053 9 -\n[GNUPG:] NO_PUBKEY 1234ABCD\n",
+                false
+            ),
+            Some(Verification::Unknown {
+                key: "1234ABCD".to_string()
+            })
+        );
+        assert_eq!(parse_status("gpg: garbage\n", false), None);
+    }
+
+    #[test]
+    fn test_parse_status_needs_one_good_signature() {
+        let signer = || "A U Thor".to_string();
+        // A second, bad signature after a good one
+        assert_eq!(
+            parse_status(
+                "[GNUPG:] GOODSIG 1234ABCD A U Thor\n[GNUPG:] BADSIG 5678EF00 Mallory\n",
+                false
+            ),
+            Some(Verification::Bad {
+                signer: "Mallory".to_string()
+            })
+        );
+        // Two good signatures
+        assert_eq!(
+            parse_status(
+                "[GNUPG:] GOODSIG 1234ABCD A U Thor\n[GNUPG:] GOODSIG 1234ABCD A U Thor\n",
+                true
+            ),
+            Some(Verification::Bad { signer: signer() })
+        );
+        // A good signature, but gpg failed
+        assert_eq!(
+            parse_status("[GNUPG:] GOODSIG 1234ABCD A U Thor\n", false),
+            Some(Verification::Bad { signer: signer() })
+        );
+    }
+}
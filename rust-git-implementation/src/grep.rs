@@ -0,0 +1,495 @@
+//! Searching file contents for patterns
+//!
+//! [`Grep`] searches the tracked files of the working tree, the blobs
+//! staged in the index, or the blobs of any tree, such as a commit's, so a
+//! revision can be searched without checking it out. The work is spread
+//! over threads: for a tree, each subtree of the root is walked and
+//! searched by whichever thread is free, and results come back in tree
+//! order.
+//!
+//! Patterns are POSIX basic regular expressions by default, as in Git,
+//! where `\(`, `\)`, `\{`, `\}`, `\|`, `\+` and `\?` are operators and
+//! the bare characters are literal. [`Syntax::Extended`] takes the
+//! [`regex`] crate's syntax, which is close to POSIX extended regular
+//! expressions, and [`Syntax::Fixed`] plain strings.
+
+use crate::index::Index;
+use crate::objects::FileMode;
+use crate::pathspec::Pathspec;
+use crate::repository::Repository;
+use crate::tree_walk::{Entry, TreeWalk};
+use crate::utils::path_from_bytes;
+use crate::worktree::WorkTree;
+use anyhow::{Context, Result};
+use regex::bytes::{Regex, RegexBuilder};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Files with a NUL byte this early are reported as binary
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// How patterns are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// POSIX basic regular expressions (`-G`, the default)
+    #[default]
+    Basic,
+    /// Extended regular expressions (`-E`)
+    Extended,
+    /// Fixed strings (`-F`)
+    Fixed,
+}
+
+/// Options for building a [`Matcher`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchOptions {
+    pub syntax: Syntax,
+    /// `-i`: ignore case
+    pub ignore_case: bool,
+    /// `-w`: only match whole words
+    pub word: bool,
+}
+
+/// Decides which lines match the patterns
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    regex: Regex,
+}
+
+impl Matcher {
+    /// Compile patterns; a line matches if any of them does
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - The patterns, at least one
+    /// * `options` - Pattern syntax and matching options
+    pub fn new(patterns: &[String], options: MatchOptions) -> Result<Self> {
+        anyhow::ensure!(!patterns.is_empty(), "no pattern given");
+        let alternatives: Vec<String> = patterns
+            .iter()
+            .map(|pattern| match options.syntax {
+                Syntax::Basic => format!("(?:{})", basic_to_extended(pattern)),
+                Syntax::Extended => format!("(?:{})", pattern),
+                Syntax::Fixed => regex::escape(pattern),
+            })
+            .collect();
+        let mut regex = alternatives.join("|");
+        if options.word {
+            regex = format!(r"\b(?:{})\b", regex);
+        }
+        let regex = RegexBuilder::new(&regex)
+            .case_insensitive(options.ignore_case)
+            .build()
+            .with_context(|| format!("invalid pattern: {}", patterns.join(" or ")))?;
+        Ok(Matcher { regex })
+    }
+
+    /// Check whether a line, without its newline, matches
+    pub fn is_match(&self, line: &[u8]) -> bool {
+        self.regex.is_match(line)
+    }
+
+    /// Search a file's content
+    ///
+    /// Returns `None` if no line matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to report the matches under
+    /// * `data` - The file's content
+    pub fn search(&self, path: Vec<u8>, data: &[u8]) -> Option<FileMatches> {
+        if data.is_empty() {
+            return None;
+        }
+        let text = data.strip_suffix(b"\n").unwrap_or(data);
+        let lines: Vec<(usize, Vec<u8>)> = text
+            .split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, line)| self.is_match(line))
+            .map(|(i, line)| (i + 1, line.to_vec()))
+            .collect();
+        if lines.is_empty() {
+            return None;
+        }
+        Some(FileMatches {
+            path,
+            binary: data[..data.len().min(BINARY_CHECK_LEN)].contains(&0),
+            lines,
+        })
+    }
+}
+
+/// Rewrite a POSIX basic regular expression in the `regex` crate's syntax
+fn basic_to_extended(pattern: &str) -> String {
+    let mut out = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('(' | ')' | '{' | '}' | '|' | '+' | '?')) => out.push(c),
+                Some('<' | '>') => out.push_str(r"\b"),
+                Some(c) => {
+                    out.push('\\');
+                    out.push(c);
+                }
+                None => out.push_str(r"\\"),
+            },
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '[' => {
+                // Bracket expressions are copied, escaping what the regex
+                // crate would read as operators there; a `]` right after
+                // the opening bracket (or its `^`) is a literal
+                out.push('[');
+                if chars.peek() == Some(&'^') {
+                    out.push(chars.next().expect("peeked"));
+                }
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    out.push_str(r"\]");
+                }
+                while let Some(c) = chars.next() {
+                    match c {
+                        ']' => break,
+                        '[' if chars.peek() == Some(&':') => {
+                            out.push('[');
+                            for c in chars.by_ref() {
+                                out.push(c);
+                                if c == ']' {
+                                    break;
+                                }
+                            }
+                            continue;
+                        }
+                        '\\' | '[' | '&' | '~' => out.push('\\'),
+                        _ => {}
+                    }
+                    out.push(c);
+                }
+                out.push(']');
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// The matching lines of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatches {
+    /// The file's path from the top of the repository
+    pub path: Vec<u8>,
+    /// Whether the file looks binary, so its lines shouldn't be printed
+    pub binary: bool,
+    /// Line numbers, counting from 1, and the lines without newlines
+    pub lines: Vec<(usize, Vec<u8>)>,
+}
+
+/// Searches files with a [`Matcher`]
+pub struct Grep<'a> {
+    repo: &'a Repository,
+    matcher: &'a Matcher,
+    pathspec: Pathspec,
+    max_depth: Option<usize>,
+    threads: usize,
+}
+
+impl<'a> Grep<'a> {
+    /// Create a search using one thread per available CPU
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository
+    /// * `matcher` - The patterns to look for
+    pub fn new(repo: &'a Repository, matcher: &'a Matcher) -> Self {
+        Grep {
+            repo,
+            matcher,
+            pathspec: Pathspec::default(),
+            max_depth: None,
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Only search files the pathspec selects
+    pub fn pathspec(mut self, pathspec: Pathspec) -> Self {
+        self.pathspec = pathspec;
+        self
+    }
+
+    /// Only search files at most this many directories deep
+    pub fn max_depth(mut self, depth: Option<usize>) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    /// Set the number of threads; 1 searches on the calling thread only
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Search the blobs of a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `tree` - Hash of the tree, e.g. a commit's root tree
+    pub fn tree(&self, tree: &str) -> Result<Vec<FileMatches>> {
+        // The root's entries are the jobs: a blob is searched, a subtree
+        // walked and searched, each by whichever thread takes it
+        let top: Vec<Entry> = TreeWalk::new(self.repo, tree)
+            .pathspec(self.pathspec.clone())
+            .max_depth(0)
+            .collect::<Result<_>>()?;
+        self.parallel(&top, |entry, found| {
+            if entry.mode != FileMode::Tree {
+                return self.search_blob(entry, found);
+            }
+            if self.max_depth == Some(0) {
+                return Ok(());
+            }
+            let mut walk = TreeWalk::new(self.repo, &entry.hash)
+                .prefix(&entry.path)
+                .pathspec(self.pathspec.clone());
+            if let Some(depth) = self.max_depth {
+                walk = walk.max_depth(depth);
+            }
+            for entry in walk {
+                self.search_blob(&entry?, found)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Search the blobs staged in the index (`--cached`)
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index
+    pub fn index(&self, index: &Index) -> Result<Vec<FileMatches>> {
+        let entries: Vec<Entry> = index
+            .entries()
+            .iter()
+            .filter(|entry| entry.stage == 0 && self.selects(&entry.path))
+            .map(|entry| Entry {
+                path: entry.path.clone(),
+                mode: entry.mode,
+                hash: entry.hash.clone(),
+            })
+            .collect();
+        self.parallel(&entries, |entry, found| self.search_blob(entry, found))
+    }
+
+    /// Search the working tree copies of the files in the index
+    ///
+    /// Files missing from the working tree, skip-worktree entries and
+    /// submodules are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index listing the tracked files
+    /// * `worktree` - The working tree
+    pub fn worktree(&self, index: &Index, worktree: &dyn WorkTree) -> Result<Vec<FileMatches>> {
+        let mut paths: Vec<&[u8]> = index
+            .entries()
+            .iter()
+            .filter(|entry| !entry.skip_worktree && entry.mode != FileMode::Gitlink)
+            .map(|entry| entry.path.as_slice())
+            .filter(|path| self.selects(path))
+            .collect();
+        // Unmerged paths have several entries but one file
+        paths.dedup();
+        self.parallel(&paths, |path, found| {
+            let file = path_from_bytes(path)?;
+            if !worktree.exists(file) {
+                return Ok(());
+            }
+            let data = worktree.read(file)?;
+            found.extend(self.matcher.search(path.to_vec(), &data));
+            Ok(())
+        })
+    }
+
+    /// Check a path from the index against the pathspec and depth limit
+    fn selects(&self, path: &[u8]) -> bool {
+        let depth = path.iter().filter(|&&b| b == b'/').count();
+        self.max_depth.is_none_or(|max| depth <= max) && self.pathspec.matches(path)
+    }
+
+    /// Search a blob, skipping anything that isn't one
+    fn search_blob(&self, entry: &Entry, found: &mut Vec<FileMatches>) -> Result<()> {
+        if matches!(entry.mode, FileMode::Tree | FileMode::Gitlink) {
+            return Ok(());
+        }
+        let (_, data) = self.repo.read_object_parsed(&entry.hash)?;
+        found.extend(self.matcher.search(entry.path.clone(), &data));
+        Ok(())
+    }
+
+    /// Run jobs on the worker threads, keeping the results in job order
+    fn parallel<J: Sync>(
+        &self,
+        jobs: &[J],
+        run: impl Fn(&J, &mut Vec<FileMatches>) -> Result<()> + Sync,
+    ) -> Result<Vec<FileMatches>> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(Ok(Vec::new()));
+        let work = || loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(job) = jobs.get(i) else {
+                break;
+            };
+            let mut found = Vec::new();
+            let outcome = run(job, &mut found);
+            let mut results = results.lock().unwrap_or_else(|e| e.into_inner());
+            match (&mut *results, outcome) {
+                (Ok(done), Ok(())) => done.push((i, found)),
+                (Ok(_), Err(e)) => *results = Err(e),
+                (Err(_), _) => {}
+            }
+            if results.is_err() {
+                // Let the other threads run out of jobs
+                next.store(jobs.len(), Ordering::Relaxed);
+                break;
+            }
+        };
+        let threads = self.threads.min(jobs.len());
+        if threads <= 1 {
+            work();
+        } else {
+            std::thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(work);
+                }
+            });
+        }
+
+        let mut done = results.into_inner().unwrap_or_else(|e| e.into_inner())?;
+        done.sort_by_key(|(i, _)| *i);
+        Ok(done.into_iter().flat_map(|(_, found)| found).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::IndexEntry;
+    use crate::objects::{Blob, GitObject};
+    use crate::testing::RepoBuilder;
+
+    fn matcher(pattern: &str, syntax: Syntax) -> Matcher {
+        let options = MatchOptions {
+            syntax,
+            ..Default::default()
+        };
+        Matcher::new(&[pattern.to_string()], options).unwrap()
+    }
+
+    #[test]
+    fn test_basic_syntax() {
+        let basic = matcher(r"f\(o\+\)\|x{2}", Syntax::Basic);
+        assert!(basic.is_match(b"foo"));
+        assert!(basic.is_match(b"x{2}"));
+        assert!(!basic.is_match(b"xx"));
+
+        let basic = matcher(r"\<a+[]x[:digit:]]", Syntax::Basic);
+        assert!(basic.is_match(b"a+]"));
+        assert!(basic.is_match(b"(a+7)"));
+        assert!(!basic.is_match(b"ba+7"));
+        assert!(!basic.is_match(b"aa7"));
+
+        assert!(matcher("x{2}", Syntax::Extended).is_match(b"xx"));
+        assert!(matcher("a.c", Syntax::Fixed).is_match(b"a.c"));
+        assert!(!matcher("a.c", Syntax::Fixed).is_match(b"abc"));
+
+        let options = MatchOptions {
+            ignore_case: true,
+            word: true,
+            ..Default::default()
+        };
+        let words = Matcher::new(&["todo".to_string(), "fixme".to_string()], options).unwrap();
+        assert!(words.is_match(b"// TODO: later"));
+        assert!(words.is_match(b"FixMe"));
+        assert!(!words.is_match(b"todos"));
+    }
+
+    #[test]
+    fn test_grep_tree() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder
+            .file("README", "TODO: write\n")
+            .file("src/main.rs", "fn main() {}\n// TODO: args\n")
+            .file("src/deep/mod.rs", "// TODO: deep\n")
+            .file("data.bin", b"TODO\0\x01".to_vec())
+            .file("done.txt", "done\n")
+            .commit("first")
+            .unwrap();
+        let repo = builder.repo();
+        let tree = repo.read_commit(&commit).unwrap().tree.clone();
+        let todo = matcher("TODO", Syntax::Basic);
+
+        for threads in [1, 4] {
+            let grep = Grep::new(repo, &todo).with_threads(threads);
+            let found = grep.tree(&tree).unwrap();
+            let paths: Vec<&[u8]> = found.iter().map(|f| f.path.as_slice()).collect();
+            assert_eq!(
+                paths,
+                [
+                    &b"README"[..],
+                    b"data.bin",
+                    b"src/deep/mod.rs",
+                    b"src/main.rs"
+                ]
+            );
+            assert!(found[1].binary);
+            assert_eq!(found[3].lines, vec![(2, b"// TODO: args".to_vec())]);
+        }
+
+        let grep = Grep::new(repo, &todo)
+            .pathspec(Pathspec::new(["src", "README"]))
+            .max_depth(Some(1));
+        let found = grep.tree(&tree).unwrap();
+        let paths: Vec<&[u8]> = found.iter().map(|f| f.path.as_slice()).collect();
+        assert_eq!(paths, [&b"README"[..], b"src/main.rs"]);
+    }
+
+    #[test]
+    fn test_grep_index_and_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut index = Index::new();
+        for (path, content) in [
+            ("NEW", "TODO: new\n"),
+            ("gone", "TODO\n"),
+            ("src/main.rs", "// TODO\n"),
+        ] {
+            let blob = Blob::new(content.as_bytes());
+            let hash = blob.hash().unwrap();
+            repo.write_object(&hash, &blob.to_bytes().unwrap()).unwrap();
+            index.add(IndexEntry::new(path, FileMode::Regular, hash));
+            let file = dir.path().join(path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+        }
+        std::fs::write(dir.path().join("NEW"), "nothing\n").unwrap();
+        std::fs::remove_file(dir.path().join("gone")).unwrap();
+        std::fs::write(dir.path().join("untracked"), "TODO\n").unwrap();
+
+        let todo = matcher("TODO", Syntax::Basic);
+        let grep = Grep::new(&repo, &todo);
+        let paths = |found: Vec<FileMatches>| -> Vec<Vec<u8>> {
+            found.into_iter().map(|f| f.path).collect()
+        };
+        assert_eq!(
+            paths(grep.index(&index).unwrap()),
+            [&b"NEW"[..], b"gone", b"src/main.rs"]
+        );
+        let worktree = repo.worktree().unwrap();
+        assert_eq!(
+            paths(grep.worktree(&index, &worktree).unwrap()),
+            [b"src/main.rs"]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! A minimal HTTP/1.1 client
+//!
+//! Just enough HTTP for the dumb protocol: `GET` requests over plain TCP,
+//! following redirects, with `Content-Length`, chunked and
+//! close-delimited bodies. There is no TLS, so only `http://` URLs work.
+
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Redirects followed before giving up
+const MAX_REDIRECTS: usize = 5;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest status, header or chunk-size line accepted, newline included
+const MAX_LINE: u64 = 8192;
+
+/// A response to a `GET`
+#[derive(Debug, Clone)]
+pub struct Response {
+    /// Status code, e.g. 200
+    pub status: u16,
+    /// The `Content-Type` header, if any
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Whether the status is 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Split an `http://host[:port]/path` URL into host, port and path
+fn parse_url(url: &str) -> Result<(&str, u16, &str)> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None if url.starts_with("https://") => {
+            anyhow::bail!("https is not supported, only http: {}", url)
+        }
+        None => anyhow::bail!("not an http URL: {}", url),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (
+            host,
+            port.parse()
+                .with_context(|| format!("invalid port in {}", url))?,
+        ),
+        None => (authority, 80),
+    };
+    if host.is_empty() {
+        anyhow::bail!("no host in {}", url);
+    }
+    Ok((host, port, path))
+}
+
+/// Fetch a URL, following redirects
+///
+/// Any status is returned as a [`Response`]; only connection and protocol
+/// errors fail.
+///
+/// # Arguments
+///
+/// * `url` - An `http://` URL
+/// * `max_size` - Largest body to accept, in bytes
+pub fn get(url: &str, max_size: u64) -> Result<Response> {
+    let _span = tracing::debug_span!("http_get", url).entered();
+    let mut url = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let (response, location) = get_once(&url, max_size)?;
+        match location {
+            Some(location) if (300..400).contains(&response.status) => {
+                url = if location.starts_with('/') {
+                    let (host, port, _) = parse_url(&url)?;
+                    format!("http://{}:{}{}", host, port, location)
+                } else {
+                    location
+                };
+            }
+            _ => {
+                tracing::debug!(status = response.status, size = response.body.len());
+                return Ok(response);
+            }
+        }
+    }
+    anyhow::bail!("too many redirects fetching {}", url)
+}
+
+/// Make one request; returns the response and its `Location` header
+fn get_once(url: &str, max_size: u64) -> Result<(Response, Option<String>)> {
+    let (host, port, path) = parse_url(url)?;
+    let stream = TcpStream::connect((host, port))
+        .with_context(|| format!("Failed to connect to {}:{}", host, port))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        &stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: oxid/{}\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        crate::VERSION
+    )?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let status = line
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("malformed HTTP status line from {}", host))?;
+
+    let mut content_type = None;
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut location = None;
+    loop {
+        if read_line(&mut reader, &mut line)? == 0 {
+            anyhow::bail!("connection to {} closed in the headers", host);
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match name.to_ascii_lowercase().as_str() {
+            "content-type" => content_type = Some(value.to_string()),
+            "content-length" => content_length = value.parse::<u64>().ok(),
+            "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+            "location" => location = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    let body = if chunked {
+        read_chunked(&mut reader, max_size)?
+    } else {
+        let limit = content_length.unwrap_or(u64::MAX).min(max_size + 1);
+        let mut body = Vec::new();
+        reader.by_ref().take(limit).read_to_end(&mut body)?;
+        body
+    };
+    if body.len() as u64 > max_size {
+        anyhow::bail!("response from {} is larger than {} bytes", url, max_size);
+    }
+    Ok((
+        Response {
+            status,
+            content_type,
+            body,
+        },
+        location,
+    ))
+}
+
+/// Read one line into `line`, replacing its contents
+///
+/// Returns the number of bytes read, 0 at the end of the stream. Lines
+/// longer than [`MAX_LINE`] fail, so a hostile server can't make us
+/// buffer without bound.
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<usize> {
+    line.clear();
+    let read = reader.by_ref().take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        anyhow::bail!("HTTP line longer than {} bytes", MAX_LINE);
+    }
+    Ok(read)
+}
+
+/// Read a `Transfer-Encoding: chunked` body
+fn read_chunked(reader: &mut impl BufRead, max_size: u64) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        if read_line(reader, &mut line)? == 0 {
+            anyhow::bail!("connection closed in a chunked body");
+        }
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let size = u64::from_str_radix(size, 16).context("malformed chunk size")?;
+        if size == 0 {
+            return Ok(body);
+        }
+        if body.len() as u64 + size > max_size {
+            anyhow::bail!("response is larger than {} bytes", max_size);
+        }
+        if reader.take(size).read_to_end(&mut body)? as u64 != size {
+            anyhow::bail!("connection closed in a chunk");
+        }
+        // Each chunk ends with CRLF
+        read_line(reader, &mut line)?;
+        if line != "\r\n" {
+            anyhow::bail!("chunk is not followed by CRLF");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Answer one connection per canned response
+    fn serve<S: AsRef<[u8]> + Send + 'static>(responses: Vec<S>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while reader.read_line(&mut request).unwrap() > 2 {
+                    request.clear();
+                }
+                stream.write_all(response.as_ref()).unwrap();
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_get() {
+        let port = serve(vec![
+            "HTTP/1.1 302 Found\r\nLocation: /moved\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found",
+        ]);
+        let base = format!("http://127.0.0.1:{}", port);
+        let response = get(&format!("{}/start", base), 1024).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(response.body, b"hello world");
+
+        let response = get(&format!("{}/missing", base), 1024).unwrap();
+        assert!(!response.is_success());
+        assert_eq!(response.body, b"not found");
+
+        assert!(get("https://example.com/", 1024).is_err());
+    }
+
+    #[test]
+    fn test_read_chunked() {
+        let read = |body: &[u8]| read_chunked(&mut &body[..], 1024);
+        assert_eq!(read(b"5\r\nhello\r\n0\r\n\r\n").unwrap(), b"hello");
+        // Short body, missing CRLF after a chunk, missing last chunk
+        assert!(read(b"5\r\nhel").is_err());
+        assert!(read(b"5\r\nhelloX\r\n0\r\n\r\n").is_err());
+        assert!(read(b"5\r\nhello\r\n").is_err());
+        assert!(read(b"5\r\nhello").is_err());
+    }
+
+    #[test]
+    fn test_long_lines_are_refused() {
+        let long = "x".repeat(MAX_LINE as usize);
+        let mut line = String::new();
+        assert!(read_line(&mut long.as_bytes(), &mut line).is_err());
+        let chunk_size = format!("5;{}\r\nhello\r\n0\r\n\r\n", long);
+        assert!(read_chunked(&mut chunk_size.as_bytes(), 1024).is_err());
+
+        let port = serve(vec![format!(
+            "HTTP/1.1 200 OK\r\nX-Long: {}\r\n\r\nbody",
+            long
+        )]);
+        let error = get(&format!("http://127.0.0.1:{}/", port), 1024).unwrap_err();
+        assert!(error.to_string().contains("longer than"), "{}", error);
+    }
+}
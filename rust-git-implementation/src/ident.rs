@@ -0,0 +1,153 @@
+//! Author and committer identities
+//!
+//! Like Git, the name and email come from the `GIT_AUTHOR_*` and
+//! `GIT_COMMITTER_*` environment variables first, then from `user.name`
+//! and `user.email` in the configuration, and finally `EMAIL` for the
+//! email address. `GIT_AUTHOR_DATE` and `GIT_COMMITTER_DATE` replace the
+//! current time, in any format [`crate::date::parse`] understands.
+
+use crate::config::Config;
+use crate::date;
+use crate::objects::Signature;
+use anyhow::Result;
+
+/// Which identity of a commit to look up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    /// Prefix of the environment variables for this role
+    fn env_prefix(&self) -> &str {
+        match self {
+            Role::Author => "GIT_AUTHOR",
+            Role::Committer => "GIT_COMMITTER",
+        }
+    }
+
+    /// Name used in messages
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Author => "Author",
+            Role::Committer => "Committer",
+        }
+    }
+}
+
+/// Look up the identity for a role, stamped with the current time or the
+/// role's `*_DATE` override
+///
+/// # Arguments
+///
+/// * `config` - Configuration providing `user.name` and `user.email`
+/// * `role` - Author or committer
+///
+/// # Errors
+///
+/// Fails if no name or email is configured, or if the date override is
+/// not a valid date
+pub fn ident(config: &Config, role: Role) -> Result<Signature> {
+    resolve(config, role, |key| std::env::var(key).ok())
+}
+
+/// [`ident`] with the environment supplied by the caller
+fn resolve(config: &Config, role: Role, env: impl Fn(&str) -> Option<String>) -> Result<Signature> {
+    let prefix = role.env_prefix();
+    let name =
+        env(&format!("{}_NAME", prefix)).or_else(|| config.get("user.name").map(str::to_string));
+    let email = env(&format!("{}_EMAIL", prefix))
+        .or_else(|| config.get("user.email").map(str::to_string))
+        .or_else(|| env("EMAIL"));
+
+    let (name, email) = match (name, email) {
+        (Some(name), Some(email)) if !name.is_empty() => (name, email),
+        _ => anyhow::bail!(
+            "{} identity unknown\n\n\
+             *** Please tell me who you are.\n\n\
+             Run\n\n  \
+             git config --global user.email \"you@example.com\"\n  \
+             git config --global user.name \"Your Name\"\n",
+            role.as_str()
+        ),
+    };
+
+    match env(&format!("{}_DATE", prefix)) {
+        Some(text) => {
+            let (time, offset) = date::parse(&text)?;
+            Ok(Signature::new(name, email, time, offset))
+        }
+        None => Ok(Signature::now(name, email)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_environment_overrides_config() {
+        let config = Config::parse("[user]\n\tname = Config\n\temail = c@example.com\n").unwrap();
+
+        let sig = resolve(&config, Role::Author, env(&[("GIT_AUTHOR_NAME", "Env")])).unwrap();
+        assert_eq!(
+            (sig.name.as_str(), sig.email.as_str()),
+            ("Env", "c@example.com")
+        );
+
+        let sig = resolve(&config, Role::Committer, env(&[("GIT_AUTHOR_NAME", "Env")])).unwrap();
+        assert_eq!(sig.name, "Config");
+    }
+
+    #[test]
+    fn test_missing_identity() {
+        let config = Config::new();
+        assert!(resolve(&config, Role::Author, env(&[])).is_err());
+
+        let sig = resolve(
+            &config,
+            Role::Author,
+            env(&[("GIT_AUTHOR_NAME", "A"), ("EMAIL", "a@example.com")]),
+        )
+        .unwrap();
+        assert_eq!(sig.email, "a@example.com");
+    }
+
+    #[test]
+    fn test_date_override() {
+        let config = Config::parse("[user]\n\tname = A\n\temail = a@example.com\n").unwrap();
+
+        let sig = resolve(
+            &config,
+            Role::Committer,
+            env(&[("GIT_COMMITTER_DATE", "1112911993 -0700")]),
+        )
+        .unwrap();
+        assert_eq!((sig.time, sig.offset), (1112911993, -420));
+
+        let sig = resolve(
+            &config,
+            Role::Author,
+            env(&[("GIT_AUTHOR_DATE", "2005-04-07T22:13:13+02:00")]),
+        )
+        .unwrap();
+        assert_eq!((sig.time, sig.offset), (1112904793, 120));
+
+        assert!(resolve(
+            &config,
+            Role::Author,
+            env(&[("GIT_AUTHOR_DATE", "not a date")])
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,368 @@
+//! Ignore rules (`.gitignore`, `info/exclude`, `core.excludesFile`)
+//!
+//! Each source contributes a list of patterns. Patterns are checked from
+//! the most specific source to the least specific one — a directory's
+//! `.gitignore` before its parents', those before `info/exclude` and the
+//! global excludes file — and within a list from the last line up. The
+//! first pattern that matches decides; `!` patterns re-include a path.
+//!
+//! A pattern containing a slash is matched against the path relative to
+//! the directory of its `.gitignore`; one without a slash against the file
+//! name alone, at any depth. A trailing slash restricts a pattern to
+//! directories. Globs follow Git's wildmatch: `*` and `?` stop at slashes,
+//! `**` between slashes spans any number of directories.
+//!
+//! Patterns and paths are bytes, so rules for file names that aren't UTF-8
+//! work like any other.
+
+use crate::repository::Repository;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// One line of an ignore file
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    glob: Vec<u8>,
+    negated: bool,
+    dir_only: bool,
+    /// Matched against the whole relative path instead of the file name
+    anchored: bool,
+}
+
+impl Pattern {
+    fn parse(line: &[u8]) -> Option<Self> {
+        let mut line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() || line.starts_with(b"#") {
+            return None;
+        }
+        // Trailing spaces are dropped unless escaped with a backslash
+        while let [rest @ .., b' '] = line {
+            if rest.ends_with(b"\\") {
+                break;
+            }
+            line = rest;
+        }
+
+        let (negated, line) = match line.strip_prefix(b"!") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix(b"/") {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+        let anchored = line.contains(&b'/');
+        let glob = line.strip_prefix(b"/").unwrap_or(line).to_vec();
+        Some(Pattern {
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// Match a path relative to the pattern's directory
+    fn matches(&self, path: &[u8], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            wildmatch(&self.glob, path)
+        } else {
+            let name = path.rsplit(|&b| b == b'/').next().unwrap_or(path);
+            wildmatch(&self.glob, name)
+        }
+    }
+}
+
+/// A stack of ignore pattern lists
+///
+/// Directory walks push a child for every `.gitignore` they find; children
+/// share their parents, so each directory costs only its own patterns.
+#[derive(Debug, Clone, Default)]
+pub struct Ignore {
+    parent: Option<Arc<Ignore>>,
+    /// Directory the patterns are relative to: empty or ending in `/`
+    base: Vec<u8>,
+    patterns: Vec<Pattern>,
+}
+
+impl Ignore {
+    /// Create an empty set of rules, ignoring nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the repository-wide rules: `core.excludesFile` (by default
+    /// `$XDG_CONFIG_HOME/git/ignore`), then `.git/info/exclude`
+    pub fn for_repo(repo: &Repository) -> Result<Self> {
+        let config = repo.config()?;
+        let global = match config.get("core.excludesFile") {
+            Some(path) => Some(expand_home(path)),
+            None => std::env::var_os("XDG_CONFIG_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+                .map(|dir| dir.join("git/ignore")),
+        };
+
+        let mut ignore = Ignore::new();
+        if let Some(text) = global.and_then(|path| std::fs::read(path).ok()) {
+            ignore.add_patterns(text);
+        }
+        if let Ok(text) = std::fs::read(repo.common_dir.join("info/exclude")) {
+            ignore = Arc::new(ignore).child("", text);
+        }
+        Ok(ignore)
+    }
+
+    /// Add patterns to this list; later lines take precedence
+    pub fn add_patterns(&mut self, text: impl AsRef<[u8]>) {
+        self.patterns.extend(
+            text.as_ref()
+                .split(|&b| b == b'\n')
+                .filter_map(Pattern::parse),
+        );
+    }
+
+    /// Layer the contents of an ignore file on top of these rules
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Directory of the file relative to the work tree root, e.g.
+    ///   `src` for `src/.gitignore`; empty for the root
+    /// * `text` - The file's contents
+    pub fn child(self: &Arc<Self>, dir: impl AsRef<[u8]>, text: impl AsRef<[u8]>) -> Self {
+        let dir = dir.as_ref();
+        let dir = dir.strip_suffix(b"/").unwrap_or(dir);
+        let mut child = Ignore {
+            parent: Some(Arc::clone(self)),
+            base: if dir.is_empty() {
+                Vec::new()
+            } else {
+                [dir, b"/"].concat()
+            },
+            patterns: Vec::new(),
+        };
+        child.add_patterns(text);
+        child
+    }
+
+    /// Check whether a path is ignored
+    ///
+    /// Only the path itself is checked; callers walking a tree stop at
+    /// ignored directories, since their contents can't be re-included.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path relative to the work tree root, `/`-separated
+    /// * `is_dir` - Whether the path is a directory
+    pub fn is_ignored(&self, path: impl AsRef<[u8]>, is_dir: bool) -> bool {
+        self.decide(path, is_dir).unwrap_or(false)
+    }
+
+    /// Check a path against the patterns, telling "no pattern matched"
+    /// apart from "re-included by a `!` pattern"
+    ///
+    /// Returns `Some(true)` if the deciding pattern is positive,
+    /// `Some(false)` if it is negated and `None` if none matches.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path relative to the work tree root, `/`-separated
+    /// * `is_dir` - Whether the path is a directory
+    pub fn decide(&self, path: impl AsRef<[u8]>, is_dir: bool) -> Option<bool> {
+        let path = path.as_ref();
+        let mut rules = Some(self);
+        while let Some(ignore) = rules {
+            if let Some(relative) = path.strip_prefix(ignore.base.as_slice()) {
+                if let Some(pattern) = ignore
+                    .patterns
+                    .iter()
+                    .rev()
+                    .find(|p| p.matches(relative, is_dir))
+                {
+                    return Some(!pattern.negated);
+                }
+            }
+            rules = ignore.parent.as_deref();
+        }
+        None
+    }
+}
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Match text against a glob the way Git's wildmatch does with
+/// `WM_PATHNAME`: wildcards don't cross `/` except for `**` between
+/// slashes
+pub fn wildmatch(glob: &[u8], text: &[u8]) -> bool {
+    let (mut g, mut t) = (0, 0);
+    while g < glob.len() {
+        match glob[g] {
+            b'*' => {
+                let start = g;
+                while glob.get(g) == Some(&b'*') {
+                    g += 1;
+                }
+                let rest = &glob[g..];
+                let at_slash = start == 0 || glob[start - 1] == b'/';
+                if g - start > 1 && at_slash && (rest.is_empty() || rest[0] == b'/') {
+                    // `**`: zero or more whole directories
+                    let Some(rest) = rest.strip_prefix(b"/") else {
+                        return true;
+                    };
+                    return wildmatch(rest, &text[t..])
+                        || (t..text.len())
+                            .any(|i| text[i] == b'/' && wildmatch(rest, &text[i + 1..]));
+                }
+                for i in t..=text.len() {
+                    if wildmatch(rest, &text[i..]) {
+                        return true;
+                    }
+                    if i < text.len() && text[i] == b'/' {
+                        break;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if t >= text.len() || text[t] == b'/' {
+                    return false;
+                }
+            }
+            b'[' => {
+                let Some(&c) = text.get(t) else {
+                    return false;
+                };
+                match match_class(&glob[g + 1..], c) {
+                    Some((true, len)) if c != b'/' => g += len,
+                    Some(_) => return false,
+                    // No closing bracket: a literal `[`
+                    None if c == b'[' => {}
+                    None => return false,
+                }
+            }
+            b'\\' if g + 1 < glob.len() => {
+                g += 1;
+                if text.get(t) != Some(&glob[g]) {
+                    return false;
+                }
+            }
+            c => {
+                if text.get(t) != Some(&c) {
+                    return false;
+                }
+            }
+        }
+        g += 1;
+        t += 1;
+    }
+    t == text.len()
+}
+
+/// Match a character against a bracket expression
+///
+/// `class` starts just after the `[`. Returns whether `c` matched and the
+/// length of the expression up to and including the `]`, or `None` if the
+/// expression is not closed.
+pub(crate) fn match_class(class: &[u8], c: u8) -> Option<(bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(class.first(), Some(b'!' | b'^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut lo = *class.get(i)?;
+        if lo == b']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if lo == b'\\' {
+            i += 1;
+            lo = *class.get(i)?;
+        }
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&b| b != b']') {
+            let mut hi = class[i + 2];
+            i += 2;
+            if hi == b'\\' {
+                i += 1;
+                hi = *class.get(i)?;
+            }
+            matched |= (lo..=hi).contains(&c);
+        } else {
+            matched |= lo == c;
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildmatch() {
+        assert!(wildmatch(b"*.o", b"main.o"));
+        assert!(!wildmatch(b"*.o", b"src/main.o"));
+        assert!(wildmatch(b"src/*.rs", b"src/lib.rs"));
+        assert!(wildmatch(b"**/build", b"build"));
+        assert!(wildmatch(b"**/build", b"a/b/build"));
+        assert!(wildmatch(b"a/**/b", b"a/b"));
+        assert!(wildmatch(b"a/**/b", b"a/x/y/b"));
+        assert!(wildmatch(b"out/**", b"out/x/y"));
+        assert!(!wildmatch(b"out/**", b"out"));
+        assert!(wildmatch(b"file?.[ch]", b"file1.c"));
+        assert!(!wildmatch(b"file?.[!ch]", b"file1.c"));
+        assert!(wildmatch(b"[a-c]x", b"bx"));
+        assert!(wildmatch(b"\\#notes", b"#notes"));
+        assert!(wildmatch(b"[]]", b"]"));
+    }
+
+    #[test]
+    fn test_rules_and_precedence() {
+        let mut root = Ignore::new();
+        root.add_patterns("# comment\n*.log\n!keep.log\nbuild/\n/top\ndocs/*.tmp\n");
+        let root = Arc::new(root);
+
+        assert!(root.is_ignored("a.log", false));
+        assert!(root.is_ignored("deep/dir/a.log", false));
+        assert!(!root.is_ignored("keep.log", false));
+        assert!(root.is_ignored("build", true));
+        assert!(!root.is_ignored("build", false));
+        assert!(root.is_ignored("top", false));
+        assert!(!root.is_ignored("sub/top", false));
+        assert!(root.is_ignored("docs/x.tmp", false));
+        assert!(!root.is_ignored("docs/sub/x.tmp", false));
+
+        // A subdirectory's file overrides the parent's and is relative to it
+        let sub = root.child("sub", "!*.log\n/only-here\n");
+        assert!(!sub.is_ignored("sub/a.log", false));
+        assert!(root.is_ignored("other/a.log", false));
+        assert!(sub.is_ignored("sub/only-here", false));
+        assert!(!sub.is_ignored("only-here", false));
+    }
+
+    #[test]
+    fn test_non_utf8_names() {
+        let mut ignore = Ignore::new();
+        ignore.add_patterns(&b"caf\xe9*\r\n"[..]);
+        let ignore = Arc::new(ignore).child(&b"d\xfc"[..], "*.bak\n");
+        assert!(ignore.is_ignored(&b"caf\xe9.txt"[..], false));
+        assert!(ignore.is_ignored(&b"sub/caf\xe9"[..], false));
+        assert!(!ignore.is_ignored("caf\u{e9}.txt", false));
+        assert!(ignore.is_ignored(&b"d\xfc/x.bak"[..], false));
+        assert!(!ignore.is_ignored("d/x.bak", false));
+    }
+}
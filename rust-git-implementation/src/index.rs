@@ -1,32 +1,635 @@
 //! The Git index (staging area)
 //!
-//! The index is a binary file that tracks which files are staged for the next commit.
-//! This is a placeholder implementation - you'll build this in later lessons!
+//! The index is a binary file (`.git/index`) that tracks which files are
+//! staged for the next commit. Its layout is:
+//!
+//! ```text
+//! header:     "DIRC" | version (u32) | entry count (u32)
+//! entries:    stat data | mode | object id | flags | path, sorted by path
+//! extensions: signature (4 bytes) | size (u32) | data
+//! trailer:    SHA-1 of everything above
+//! ```
+//!
+//! Versions 2 and 3 are written; version 4 (prefix-compressed paths) can be
+//! read as well. Extensions whose signature starts with a capital letter
+//! are optional: unknown ones are kept and written back unchanged, except
+//! that the caches describing the entries (`TREE`, `UNTR`) are dropped as
+//! soon as an entry changes. Any other extension must be understood to read
+//! the index correctly (e.g. `link` for split indexes), so those are refused.
+
+use crate::objects::FileMode;
+use crate::utils::{bytes_to_hex, hex_to_bytes};
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+
+/// Flag bits in the 16-bit entry flags
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_STAGE_SHIFT: u16 = 12;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+/// Flag bits in the 16-bit extended flags (version 3+)
+const EXT_FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const EXT_FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// Size of an entry up to and including the flags field
+const ENTRY_FIXED_SIZE: usize = 62;
+
+/// Extensions caching facts about the entries, stale once they change
+const CACHE_EXTENSIONS: &[[u8; 4]] = &[*b"TREE", *b"UNTR"];
+
+/// Hash of the empty blob
+const EMPTY_BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+/// A single staged file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime_secs: u32,
+    pub ctime_nsecs: u32,
+    pub mtime_secs: u32,
+    pub mtime_nsecs: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: FileMode,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+    /// Object hash of the staged content
+    pub hash: String,
+    /// Merge stage: 0 for normal entries, 1-3 for conflicts
+    pub stage: u8,
+    /// `--assume-unchanged`: don't check the worktree file for changes
+    pub assume_valid: bool,
+    /// `--skip-worktree`: the file is intentionally absent from the worktree
+    pub skip_worktree: bool,
+    /// `add -N`: the path is recorded but its content is not staged yet
+    pub intent_to_add: bool,
+    /// Path relative to the worktree root, with `/` separators
+    ///
+    /// Paths are bytes, as in Git: they need not be UTF-8. See
+    /// [`crate::utils::path_from_bytes`] for the worktree path and
+    /// [`crate::quote`] for showing them.
+    pub path: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// Create an entry with empty stat data
+    pub fn new(path: impl Into<Vec<u8>>, mode: FileMode, hash: impl Into<String>) -> Self {
+        IndexEntry {
+            ctime_secs: 0,
+            ctime_nsecs: 0,
+            mtime_secs: 0,
+            mtime_nsecs: 0,
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash: hash.into(),
+            stage: 0,
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+            path: path.into(),
+        }
+    }
+
+    /// Record the stat data of the worktree file
+    ///
+    /// Status compares these fields against the file to tell whether it may
+    /// have changed without re-hashing its content; see
+    /// [`IndexEntry::matches_stat`]. Values are truncated to 32 bits as in
+    /// Git.
+    #[cfg(unix)]
+    pub fn set_stat(&mut self, metadata: &fs::Metadata) {
+        use std::os::unix::fs::MetadataExt;
+
+        self.ctime_secs = metadata.ctime() as u32;
+        self.ctime_nsecs = metadata.ctime_nsec() as u32;
+        self.mtime_secs = metadata.mtime() as u32;
+        self.mtime_nsecs = metadata.mtime_nsec() as u32;
+        self.dev = metadata.dev() as u32;
+        self.ino = metadata.ino() as u32;
+        self.uid = metadata.uid();
+        self.gid = metadata.gid();
+        self.size = metadata.len() as u32;
+    }
+
+    /// Record the stat data of the worktree file
+    ///
+    /// Only the modification time and size are available on this platform.
+    #[cfg(not(unix))]
+    pub fn set_stat(&mut self, metadata: &fs::Metadata) {
+        if let Ok(since_epoch) = metadata
+            .modified()
+            .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default())
+        {
+            self.mtime_secs = since_epoch.as_secs() as u32;
+            self.mtime_nsecs = since_epoch.subsec_nanos();
+        }
+        self.size = metadata.len() as u32;
+    }
+
+    /// Whether the worktree file's stat data is what was recorded
+    ///
+    /// A match means the content is unchanged, unless the file changed
+    /// within the same timestamp the index was written; see
+    /// [`Index::is_racy`]. A recorded size of zero only matches the empty
+    /// blob, since [`Index::write`] zeroes the size of racy entries.
+    pub fn matches_stat(&self, metadata: &fs::Metadata) -> bool {
+        let mut current = IndexEntry::new(Vec::new(), self.mode, String::new());
+        current.set_stat(metadata);
+        current.stat() == self.stat() && (self.size != 0 || self.hash == EMPTY_BLOB)
+    }
 
-use anyhow::Result;
+    /// The stat fields compared by [`IndexEntry::matches_stat`]
+    fn stat(&self) -> [u32; 9] {
+        [
+            self.ctime_secs,
+            self.ctime_nsecs,
+            self.mtime_secs,
+            self.mtime_nsecs,
+            self.dev,
+            self.ino,
+            self.uid,
+            self.gid,
+            self.size,
+        ]
+    }
+
+    /// Whether the entry's file was modified no earlier than `timestamp`
+    fn modified_since(&self, timestamp: (u32, u32)) -> bool {
+        (self.mtime_secs, self.mtime_nsecs) >= timestamp
+    }
+
+    /// Whether the entry needs the version 3 extended flags
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    /// The 16-bit flags field
+    fn flags(&self) -> u16 {
+        let mut flags = (self.path.len().min(FLAG_NAME_MASK as usize)) as u16;
+        flags |= ((self.stage as u16) << FLAG_STAGE_SHIFT) & FLAG_STAGE_MASK;
+        if self.assume_valid {
+            flags |= FLAG_ASSUME_VALID;
+        }
+        if self.is_extended() {
+            flags |= FLAG_EXTENDED;
+        }
+        flags
+    }
+
+    /// The 16-bit extended flags field (version 3+)
+    fn extended_flags(&self) -> u16 {
+        let mut extended = 0u16;
+        if self.skip_worktree {
+            extended |= EXT_FLAG_SKIP_WORKTREE;
+        }
+        if self.intent_to_add {
+            extended |= EXT_FLAG_INTENT_TO_ADD;
+        }
+        extended
+    }
+
+    /// Flags as shown by `ls-files --debug`
+    ///
+    /// The extended flags occupy the upper 16 bits and the name length is
+    /// masked out, matching Git's in-memory representation.
+    pub fn debug_flags(&self) -> u32 {
+        ((self.extended_flags() as u32) << 16) | (self.flags() & !FLAG_NAME_MASK) as u32
+    }
+}
+
+/// An index extension, kept as raw bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extension {
+    pub signature: [u8; 4],
+    pub data: Vec<u8>,
+}
 
 /// Represents the Git index (staging area)
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Index {
-    // TODO: Implement index entries
+    pub version: u32,
+    entries: Vec<IndexEntry>,
+    pub extensions: Vec<Extension>,
+    /// Modification time of the file the index was read from
+    timestamp: Option<(u32, u32)>,
+    /// Whether path lookups ignore ASCII case (`core.ignoreCase`)
+    ignore_case: bool,
+}
+
+/// Cursor over the index bytes with bounds-checked reads
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .context("Index file is truncated")?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    /// Read a version 4 offset varint (each continuation adds one)
+    fn varint(&mut self) -> Result<usize> {
+        let mut byte = self.take(1)?[0];
+        let mut value = (byte & 0x7f) as usize;
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+        }
+        Ok(value)
+    }
 }
 
 impl Index {
     /// Create a new empty index
     pub fn new() -> Self {
-        Index {}
+        Index {
+            version: 2,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+            timestamp: None,
+            ignore_case: false,
+        }
     }
 
     /// Read index from file
-    pub fn read(_path: &str) -> Result<Self> {
-        // TODO: Implement index reading
-        Ok(Index::new())
+    ///
+    /// A missing file is an empty index, as in a freshly initialized repository.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let _span = tracing::debug_span!("index_read").entered();
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Index::new());
+        }
+        let data =
+            fs::read(path).with_context(|| format!("Failed to read index {}", path.display()))?;
+        let mut index =
+            Self::parse(&data).with_context(|| format!("Corrupt index {}", path.display()))?;
+        index.timestamp = fs::metadata(path).ok().and_then(|m| file_time(&m));
+        Ok(index)
+    }
+
+    /// Parse the binary index format
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 12 + 20 {
+            anyhow::bail!("Index file is too short");
+        }
+
+        // Verify the trailing checksum first
+        let (body, checksum) = data.split_at(data.len() - 20);
+        if Sha1::digest(body)[..] != *checksum {
+            anyhow::bail!("Index checksum mismatch");
+        }
+
+        let mut reader = Reader { data: body, pos: 0 };
+        if reader.take(4)? != SIGNATURE {
+            anyhow::bail!("Bad index signature");
+        }
+        let version = reader.u32()?;
+        if !(2..=4).contains(&version) {
+            anyhow::bail!("Unsupported index version {}", version);
+        }
+        let count = reader.u32()? as usize;
+
+        let mut entries = Vec::with_capacity(count.min(body.len() / ENTRY_FIXED_SIZE));
+        let mut previous_path: Vec<u8> = Vec::new();
+        for _ in 0..count {
+            let entry = Self::parse_entry(&mut reader, version, &mut previous_path)?;
+            entries.push(entry);
+        }
+
+        let mut extensions = Vec::new();
+        while reader.pos < body.len() {
+            let signature: [u8; 4] = reader.take(4)?.try_into()?;
+            if !signature[0].is_ascii_uppercase() {
+                anyhow::bail!(
+                    "index uses {} extension, which we do not understand",
+                    String::from_utf8_lossy(&signature)
+                );
+            }
+            let size = reader.u32()? as usize;
+            let data = reader.take(size)?.to_vec();
+            extensions.push(Extension { signature, data });
+        }
+
+        Ok(Index {
+            version,
+            entries,
+            extensions,
+            timestamp: None,
+            ignore_case: false,
+        })
+    }
+
+    fn parse_entry(
+        reader: &mut Reader,
+        version: u32,
+        previous_path: &mut Vec<u8>,
+    ) -> Result<IndexEntry> {
+        let start = reader.pos;
+        let ctime_secs = reader.u32()?;
+        let ctime_nsecs = reader.u32()?;
+        let mtime_secs = reader.u32()?;
+        let mtime_nsecs = reader.u32()?;
+        let dev = reader.u32()?;
+        let ino = reader.u32()?;
+        let mode = FileMode::from_u32(reader.u32()?)?;
+        let uid = reader.u32()?;
+        let gid = reader.u32()?;
+        let size = reader.u32()?;
+        let hash = bytes_to_hex(reader.take(20)?);
+        let flags = reader.u16()?;
+
+        let extended_flags = if flags & FLAG_EXTENDED != 0 {
+            if version < 3 {
+                anyhow::bail!("Extended flags in a version {} index", version);
+            }
+            reader.u16()?
+        } else {
+            0
+        };
+
+        let path = if version == 4 {
+            // Path is stored as "drop N bytes of the previous path, then append"
+            let strip = reader.varint()?;
+            if strip > previous_path.len() {
+                anyhow::bail!("Invalid path prefix length in index");
+            }
+            previous_path.truncate(previous_path.len() - strip);
+            let suffix = Self::take_nul_terminated(reader)?;
+            previous_path.extend_from_slice(suffix);
+            previous_path.clone()
+        } else {
+            let path = Self::take_nul_terminated(reader)?.to_vec();
+            // Entries are padded with NULs to a multiple of 8 bytes
+            let consumed = reader.pos - start;
+            let padded = consumed.div_ceil(8) * 8;
+            reader.take(padded - consumed)?;
+            path
+        };
+
+        Ok(IndexEntry {
+            ctime_secs,
+            ctime_nsecs,
+            mtime_secs,
+            mtime_nsecs,
+            dev,
+            ino,
+            mode,
+            uid,
+            gid,
+            size,
+            hash,
+            stage: ((flags & FLAG_STAGE_MASK) >> FLAG_STAGE_SHIFT) as u8,
+            assume_valid: flags & FLAG_ASSUME_VALID != 0,
+            skip_worktree: extended_flags & EXT_FLAG_SKIP_WORKTREE != 0,
+            intent_to_add: extended_flags & EXT_FLAG_INTENT_TO_ADD != 0,
+            path,
+        })
+    }
+
+    /// Read a path up to (and consuming) its NUL terminator
+    fn take_nul_terminated<'a>(reader: &mut Reader<'a>) -> Result<&'a [u8]> {
+        let rest = &reader.data[reader.pos..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .context("Unterminated path in index")?;
+        let path = reader.take(len)?;
+        reader.take(1)?;
+        Ok(path)
+    }
+
+    /// Serialize with the size of entries modified since `now` zeroed
+    fn to_bytes_smudged(&self, now: Option<(u32, u32)>) -> Result<Vec<u8>> {
+        let Some(now) = now.filter(|&now| self.entries.iter().any(|e| e.modified_since(now)))
+        else {
+            return self.to_bytes();
+        };
+        let mut smudged = self.clone();
+        for entry in &mut smudged.entries {
+            if entry.modified_since(now) {
+                entry.size = 0;
+            }
+        }
+        smudged.to_bytes()
+    }
+
+    /// Serialize to the binary index format, including the checksum
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        // Version 3 is needed as soon as any entry uses extended flags
+        let version = if self.entries.iter().any(IndexEntry::is_extended) {
+            self.version.max(3)
+        } else {
+            self.version
+        };
+        // Version 4 path compression is read-only for now
+        let version = version.min(3);
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        for entry in &self.entries {
+            let start = data.len();
+            for value in [
+                entry.ctime_secs,
+                entry.ctime_nsecs,
+                entry.mtime_secs,
+                entry.mtime_nsecs,
+                entry.dev,
+                entry.ino,
+                entry.mode.as_u32(),
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&value.to_be_bytes());
+            }
+            data.extend_from_slice(&hex_to_bytes(&entry.hash)?);
+            data.extend_from_slice(&entry.flags().to_be_bytes());
+            if entry.is_extended() {
+                data.extend_from_slice(&entry.extended_flags().to_be_bytes());
+            }
+            data.extend_from_slice(&entry.path);
+
+            // At least one NUL, padded to a multiple of 8 bytes
+            let len = data.len() - start + 1;
+            let padded = len.div_ceil(8) * 8;
+            data.resize(start + padded, 0);
+        }
+
+        for extension in &self.extensions {
+            data.extend_from_slice(&extension.signature);
+            data.extend_from_slice(&(extension.data.len() as u32).to_be_bytes());
+            data.extend_from_slice(&extension.data);
+        }
+
+        let checksum = Sha1::digest(&data);
+        data.extend_from_slice(&checksum);
+        Ok(data)
+    }
+
+    /// Whether an entry's stat data can't be trusted
+    ///
+    /// A file modified in the same timestamp as the index was written may
+    /// have changed after its stat data was recorded, so its content has to
+    /// be compared. Entries of an index not read from a file are always
+    /// racy.
+    pub fn is_racy(&self, entry: &IndexEntry) -> bool {
+        self.timestamp
+            .is_none_or(|timestamp| entry.modified_since(timestamp))
     }
 
     /// Write index to file
-    pub fn write(&self, _path: &str) -> Result<()> {
-        // TODO: Implement index writing
+    ///
+    /// The new index is written to `index.lock` and renamed into place, so
+    /// readers never see a partially written file and concurrent writers fail.
+    /// As in Git, entries modified no earlier than the new file get their
+    /// recorded size zeroed, so that they are still compared by content once
+    /// the index is older than they are.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let _span = tracing::debug_span!("index_write").entered();
+        let path = path.as_ref();
+        let lock = path.with_extension("lock");
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+            .with_context(|| {
+                format!(
+                    "Unable to create {}: another oxid process may be running",
+                    lock.display()
+                )
+            })?;
+        let now = file.metadata().ok().and_then(|m| file_time(&m));
+        let written = self
+            .to_bytes_smudged(now)
+            .and_then(|data| Ok(file.write_all(&data).and_then(|_| file.sync_all())?));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&lock);
+            return Err(e);
+        }
+        fs::rename(&lock, path)?;
         Ok(())
     }
+
+    /// All entries, sorted by path and stage
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    /// Make [`get`](Self::get) and [`get_mut`](Self::get_mut) fall back to
+    /// a path that differs only in ASCII case, as with `core.ignoreCase`
+    pub fn set_ignore_case(&mut self, enabled: bool) {
+        self.ignore_case = enabled;
+    }
+
+    /// Whether lookups ignore case
+    pub fn ignore_case(&self) -> bool {
+        self.ignore_case
+    }
+
+    /// Find the stage 0 entry for a path
+    pub fn get(&self, path: impl AsRef<[u8]>) -> Option<&IndexEntry> {
+        self.position(path.as_ref()).map(|i| &self.entries[i])
+    }
+
+    /// Find the stage 0 entry for a path, mutably
+    ///
+    /// The caller may change the entry, so cache extensions are dropped.
+    pub fn get_mut(&mut self, path: impl AsRef<[u8]>) -> Option<&mut IndexEntry> {
+        let i = self.position(path.as_ref())?;
+        self.invalidate_caches();
+        Some(&mut self.entries[i])
+    }
+
+    /// Add an entry, replacing any existing entry with the same path and stage
+    pub fn add(&mut self, entry: IndexEntry) {
+        self.invalidate_caches();
+        match self.find(&entry.path, entry.stage) {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+
+    /// Remove every stage of a path, returning whether anything was removed
+    pub fn remove(&mut self, path: impl AsRef<[u8]>) -> bool {
+        let path = path.as_ref();
+        let before = self.entries.len();
+        self.entries.retain(|e| e.path != path);
+        let removed = self.entries.len() != before;
+        if removed {
+            self.invalidate_caches();
+        }
+        removed
+    }
+
+    /// Drop the extensions that describe the entries as they were read
+    fn invalidate_caches(&mut self) {
+        self.extensions
+            .retain(|extension| !CACHE_EXTENSIONS.contains(&extension.signature));
+    }
+
+    /// Iterate over conflicted paths, as left in the index by a merge
+    pub fn conflicts(&self) -> Conflicts<'_> {
+        Conflicts {
+            entries: &self.entries,
+        }
+    }
+
+    /// Whether a path has conflict stages
+    pub fn is_conflicted(&self, path: impl AsRef<[u8]>) -> bool {
+        (1..=3).any(|stage| self.find(path.as_ref(), stage).is_ok())
+    }
+
+    /// Position of the stage 0 entry for a path
+    ///
+    /// An exact match wins; otherwise, when ignoring case, the first entry
+    /// whose path differs only in ASCII case.
+    fn position(&self, path: &[u8]) -> Option<usize> {
+        match self.find(path, 0) {
+            Ok(i) => Some(i),
+            Err(_) if self.ignore_case => self
+                .entries
+                .iter()
+                .position(|e| e.stage == 0 && e.path.eq_ignore_ascii_case(path)),
+            Err(_) => None,
+        }
+    }
+
+    /// Binary search by (path, stage), comparing paths bytewise like Git
+    fn find(&self, path: &[u8], stage: u8) -> std::result::Result<usize, usize> {
+        self.entries
+            .binary_search_by(|e| e.path.as_slice().cmp(path).then(e.stage.cmp(&stage)))
+    }
 }
 
 impl Default for Index {
@@ -34,3 +637,359 @@ impl Default for Index {
         Self::new()
     }
 }
+
+/// A file's modification time as the index stores it
+fn file_time(metadata: &fs::Metadata) -> Option<(u32, u32)> {
+    let since_epoch = metadata
+        .modified()
+        .ok()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?;
+    Some((since_epoch.as_secs() as u32, since_epoch.subsec_nanos()))
+}
+
+/// The versions of a conflicted path
+///
+/// A missing stage means the path didn't exist on that side: no base for
+/// "added by both", no `ours` for "deleted by us", and so on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict<'a> {
+    pub path: &'a [u8],
+    /// Stage 1: the common ancestor
+    pub base: Option<&'a IndexEntry>,
+    /// Stage 2: the current branch
+    pub ours: Option<&'a IndexEntry>,
+    /// Stage 3: the branch being merged
+    pub theirs: Option<&'a IndexEntry>,
+}
+
+impl Conflict<'_> {
+    /// How the two sides disagree, based on which stages exist
+    pub fn kind(&self) -> ConflictKind {
+        match (
+            self.base.is_some(),
+            self.ours.is_some(),
+            self.theirs.is_some(),
+        ) {
+            (true, true, true) => ConflictKind::BothModified,
+            (false, true, true) => ConflictKind::BothAdded,
+            (true, true, false) => ConflictKind::DeletedByThem,
+            (true, false, true) => ConflictKind::DeletedByUs,
+            (false, true, false) => ConflictKind::AddedByUs,
+            (false, false, true) => ConflictKind::AddedByThem,
+            (_, false, false) => ConflictKind::BothDeleted,
+        }
+    }
+}
+
+/// The type of a merge conflict, as reported by `status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    BothModified,
+    BothAdded,
+    DeletedByUs,
+    DeletedByThem,
+    AddedByUs,
+    AddedByThem,
+    BothDeleted,
+}
+
+impl ConflictKind {
+    /// Description used in the long status format
+    pub fn description(&self) -> &str {
+        match self {
+            ConflictKind::BothModified => "both modified",
+            ConflictKind::BothAdded => "both added",
+            ConflictKind::DeletedByUs => "deleted by us",
+            ConflictKind::DeletedByThem => "deleted by them",
+            ConflictKind::AddedByUs => "added by us",
+            ConflictKind::AddedByThem => "added by them",
+            ConflictKind::BothDeleted => "both deleted",
+        }
+    }
+
+    /// Two-letter code used in the short status format
+    pub fn short_code(&self) -> &str {
+        match self {
+            ConflictKind::BothModified => "UU",
+            ConflictKind::BothAdded => "AA",
+            ConflictKind::DeletedByUs => "DU",
+            ConflictKind::DeletedByThem => "UD",
+            ConflictKind::AddedByUs => "AU",
+            ConflictKind::AddedByThem => "UA",
+            ConflictKind::BothDeleted => "DD",
+        }
+    }
+}
+
+/// Iterator over conflicted paths, returned by [`Index::conflicts`]
+pub struct Conflicts<'a> {
+    entries: &'a [IndexEntry],
+}
+
+impl<'a> Iterator for Conflicts<'a> {
+    type Item = Conflict<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Entries are sorted by (path, stage), so stages of a path are adjacent
+        let start = self.entries.iter().position(|e| e.stage != 0)?;
+        let path = self.entries[start].path.as_slice();
+        let len = self.entries[start..]
+            .iter()
+            .take_while(|e| e.path == path)
+            .count();
+        let stages = &self.entries[start..start + len];
+        self.entries = &self.entries[start + len..];
+
+        let stage = |n| stages.iter().find(|e| e.stage == n);
+        Some(Conflict {
+            path,
+            base: stage(1),
+            ours: stage(2),
+            theirs: stage(3),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const HASH_A: &str = "78981922613b2afb6025042ff6bd878ac1994e85";
+    const HASH_B: &str = "61780798228d17af2d34fce4cfbdf35556832472";
+
+    #[test]
+    fn test_entries_stay_sorted() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("d/b.txt", FileMode::Regular, HASH_B));
+        index.add(IndexEntry::new("a.txt", FileMode::Regular, HASH_A));
+        index.add(IndexEntry::new("d-c", FileMode::Regular, HASH_A));
+
+        let paths: Vec<&[u8]> = index.entries().iter().map(|e| e.path.as_slice()).collect();
+        // '-' sorts before '/' bytewise
+        assert_eq!(paths, vec![&b"a.txt"[..], b"d-c", b"d/b.txt"]);
+
+        // Re-adding replaces
+        index.add(IndexEntry::new("a.txt", FileMode::Executable, HASH_B));
+        assert_eq!(index.entries().len(), 3);
+        assert_eq!(index.get("a.txt").unwrap().mode, FileMode::Executable);
+
+        assert!(index.remove("d-c"));
+        assert!(!index.remove("d-c"));
+    }
+
+    #[test]
+    fn test_get_ignoring_case() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("Dir/File.txt", FileMode::Regular, HASH_A));
+        index.add(IndexEntry::new("dir/file.txt", FileMode::Regular, HASH_B));
+        assert!(index.get("DIR/FILE.TXT").is_none());
+
+        index.set_ignore_case(true);
+        // An exact match wins over a case-folded one
+        assert_eq!(index.get("dir/file.txt").unwrap().hash, HASH_B);
+        assert_eq!(index.get("DIR/FILE.TXT").unwrap().path, b"Dir/File.txt");
+        assert!(index.get_mut("dir/FILE.txt").is_some());
+        assert!(index.get("dir/other.txt").is_none());
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("clean", FileMode::Regular, HASH_A));
+        for (path, stage) in [("both", 2), ("both", 3), ("del", 1), ("del", 2)] {
+            let mut entry = IndexEntry::new(path, FileMode::Regular, HASH_B);
+            entry.stage = stage;
+            index.add(entry);
+        }
+
+        let conflicts: Vec<Conflict> = index.conflicts().collect();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].path, b"both");
+        assert!(conflicts[0].base.is_none());
+        assert_eq!(conflicts[0].ours.unwrap().stage, 2);
+        assert_eq!(conflicts[0].theirs.unwrap().stage, 3);
+        assert_eq!(conflicts[1].path, b"del");
+        assert!(conflicts[1].theirs.is_none());
+        assert_eq!(conflicts[0].kind(), ConflictKind::BothAdded);
+        assert_eq!(conflicts[1].kind(), ConflictKind::DeletedByThem);
+        assert_eq!(conflicts[1].kind().short_code(), "UD");
+
+        assert!(index.is_conflicted("del"));
+        assert!(!index.is_conflicted("clean"));
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index");
+
+        let mut index = Index::new();
+        let mut entry = IndexEntry::new("a.txt", FileMode::Regular, HASH_A);
+        entry.mtime_secs = 1_700_000_000;
+        entry.size = 2;
+        entry.skip_worktree = true;
+        index.add(entry);
+        let mut entry = IndexEntry::new("d/b.txt", FileMode::Executable, HASH_B);
+        entry.assume_valid = true;
+        index.add(entry);
+        index.extensions.push(Extension {
+            signature: *b"ABCD",
+            data: vec![1, 2, 3],
+        });
+
+        index.write(&path).unwrap();
+        assert!(!path.with_extension("lock").exists());
+
+        let read = Index::read(&path).unwrap();
+        // Extended flags force version 3
+        assert_eq!(read.version, 3);
+        assert_eq!(read.entries(), index.entries());
+        assert_eq!(read.extensions, index.extensions);
+    }
+
+    #[test]
+    fn test_extensions() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("a.txt", FileMode::Regular, HASH_A));
+        for signature in [*b"TREE", *b"UNTR", *b"REUC"] {
+            index.extensions.push(Extension {
+                signature,
+                data: vec![0],
+            });
+        }
+        let read = Index::parse(&index.to_bytes().unwrap()).unwrap();
+        assert_eq!(read.extensions.len(), 3);
+
+        // Changing an entry drops the caches but keeps the rest
+        let mut changed = read.clone();
+        changed.get_mut("a.txt").unwrap().hash = HASH_B.to_string();
+        let signatures = |index: &Index| -> Vec<[u8; 4]> {
+            index.extensions.iter().map(|e| e.signature).collect()
+        };
+        assert_eq!(signatures(&changed), vec![*b"REUC"]);
+        let mut changed = read.clone();
+        changed.add(IndexEntry::new("b.txt", FileMode::Regular, HASH_B));
+        assert_eq!(signatures(&changed), vec![*b"REUC"]);
+        let mut changed = read.clone();
+        assert!(!changed.remove("missing"));
+        assert_eq!(changed.extensions.len(), 3);
+        assert!(changed.remove("a.txt"));
+        assert_eq!(signatures(&changed), vec![*b"REUC"]);
+
+        // A split index can't be read without its shared part
+        index.extensions.push(Extension {
+            signature: *b"link",
+            data: vec![0; 20],
+        });
+        let error = Index::parse(&index.to_bytes().unwrap()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "index uses link extension, which we do not understand"
+        );
+    }
+
+    #[test]
+    fn test_read_missing_is_empty() {
+        let dir = tempdir().unwrap();
+        let index = Index::read(dir.path().join("index")).unwrap();
+        assert!(index.entries().is_empty());
+    }
+
+    #[test]
+    fn test_write_fails_when_locked() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index");
+        fs::write(path.with_extension("lock"), b"").unwrap();
+        assert!(Index::new().write(&path).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_index_is_rejected() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("a.txt", FileMode::Regular, HASH_A));
+        let mut data = index.to_bytes().unwrap();
+
+        // Flip a byte in the body: checksum no longer matches
+        data[20] ^= 0xff;
+        assert!(Index::parse(&data).is_err());
+
+        // Truncation is caught, not a panic
+        assert!(Index::parse(&data[..30]).is_err());
+    }
+
+    #[test]
+    fn test_parse_version_4_paths() {
+        // Hand-built v4 index: "dir/a" then "dir/b" (strip 1, append "b")
+        let mut body = Vec::new();
+        body.extend_from_slice(b"DIRC");
+        body.extend_from_slice(&4u32.to_be_bytes());
+        body.extend_from_slice(&2u32.to_be_bytes());
+        for (strip, suffix) in [(0u8, &b"dir/a"[..]), (1, b"b")] {
+            body.extend_from_slice(&[0; 24]);
+            body.extend_from_slice(&0o100644u32.to_be_bytes());
+            body.extend_from_slice(&[0; 12]);
+            body.extend_from_slice(&hex_to_bytes(HASH_A).unwrap());
+            body.extend_from_slice(&5u16.to_be_bytes());
+            body.push(strip);
+            body.extend_from_slice(suffix);
+            body.push(0);
+        }
+        let checksum = Sha1::digest(&body);
+        body.extend_from_slice(&checksum);
+
+        let index = Index::parse(&body).unwrap();
+        let paths: Vec<&[u8]> = index.entries().iter().map(|e| e.path.as_slice()).collect();
+        assert_eq!(paths, vec![&b"dir/a"[..], b"dir/b"]);
+    }
+
+    #[test]
+    fn test_stat_and_racy_entries() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        fs::write(&file, b"hello\n").unwrap();
+        let hour = std::time::Duration::from_secs(3600);
+        let set_modified = |time| {
+            fs::File::options()
+                .write(true)
+                .open(&file)
+                .unwrap()
+                .set_modified(time)
+                .unwrap()
+        };
+        set_modified(std::time::SystemTime::now() - hour);
+
+        let mut old = IndexEntry::new("a.txt", FileMode::Regular, HASH_A);
+        old.set_stat(&fs::metadata(&file).unwrap());
+        assert!(old.matches_stat(&fs::metadata(&file).unwrap()));
+        let mut index = Index::new();
+        index.add(old);
+        assert!(index.is_racy(&index.entries()[0]));
+
+        let path = dir.path().join("index");
+        index.write(&path).unwrap();
+        let index = Index::read(&path).unwrap();
+        assert!(!index.is_racy(&index.entries()[0]));
+        assert_eq!(index.entries()[0].size, 6);
+
+        // Modified after the index was written
+        fs::write(&file, b"hello, world\n").unwrap();
+        assert!(!index.entries()[0].matches_stat(&fs::metadata(&file).unwrap()));
+
+        // An entry newer than the index has its size zeroed when written
+        set_modified(std::time::SystemTime::now() + hour);
+        let mut index = index;
+        index
+            .get_mut("a.txt")
+            .unwrap()
+            .set_stat(&fs::metadata(&file).unwrap());
+        fs::remove_file(&path).unwrap();
+        index.write(&path).unwrap();
+        let index = Index::read(&path).unwrap();
+        let entry = &index.entries()[0];
+        assert!(index.is_racy(entry));
+        assert_eq!(entry.size, 0);
+        assert!(!entry.matches_stat(&fs::metadata(&file).unwrap()));
+    }
+}
@@ -10,6 +10,7 @@
 //! - **Objects**: Blobs, trees, commits, and tags stored in content-addressable database
 //! - **Repository**: Manages the .git directory and object database
 //! - **Index**: The staging area for preparing commits
+//! - **WorkTree**: Filesystem abstraction over the checked-out files
 //! - **Commands**: High-level operations (init, add, commit, etc.)
 //!
 //! # Example
@@ -23,17 +24,64 @@
 //! // Repository is now ready to use!
 //! ```
 
+pub mod blame;
+pub mod cache;
 pub mod commands;
+pub mod compat;
+pub mod config;
+pub mod date;
+pub mod diff;
+pub mod dumb_http;
+pub mod encoding;
+pub mod extensions;
+pub mod external_tool;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fsck;
+#[doc(hidden)]
+pub mod fuzz;
+pub mod gpg;
+pub mod grep;
+pub mod http;
+pub mod ident;
+pub mod ignore;
+pub mod index;
+pub mod limits;
+pub mod mailmap;
+pub mod merge;
+pub mod merge_base;
+pub mod message;
+pub mod object_stream;
 pub mod objects;
+pub mod odb;
+pub mod pathspec;
+pub mod protocol;
+pub mod quarantine;
+pub mod quote;
+pub mod ref_filter;
+pub mod ref_name;
+pub mod refs;
+pub mod remote;
+pub mod remote_helper;
 pub mod repository;
-pub mod index;
+pub mod revision;
+pub mod safe_directory;
+pub mod server_info;
+pub mod sparse;
+pub mod status;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod trace;
+pub mod tree_walk;
+pub mod unpack;
+pub mod upload_pack;
 pub mod utils;
+pub mod walk;
+pub mod worktree;
 
 // Re-export commonly used types
+pub use objects::{Blob, Commit, GitObject, ObjectType, Tree};
 pub use repository::Repository;
-pub use objects::{GitObject, ObjectType};
-// Uncomment as you implement each type
-// pub use objects::{Blob, Tree, Commit};
 
 /// Current version of oxid
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
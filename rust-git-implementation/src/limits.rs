@@ -0,0 +1,266 @@
+//! Resource limits for untrusted data
+//!
+//! Objects fetched from a remote are attacker-controlled: a few bytes of
+//! zlib can expand to gigabytes, and a tree or delta chain can be made
+//! arbitrarily large or deep. [`Limits`] bounds what the parsers accept;
+//! going over a limit fails with a [`LimitExceeded`] error, which callers
+//! can recover with `anyhow::Error::downcast_ref`.
+//!
+//! The limits are read from the configuration:
+//!
+//! | Key                    | Default | Applies to                      |
+//! |------------------------|---------|---------------------------------|
+//! | `oxid.maxObjectSize`   | 1g      | object content, after inflating |
+//! | `oxid.maxDeltaDepth`   | 4095    | delta chains in packfiles       |
+//! | `oxid.maxTreeEntries`  | 1000000 | entries of a single tree        |
+//! | `oxid.maxPktLineLength`| 65520   | pkt-lines of the wire protocol  |
+//!
+//! Sizes accept Git's `k`, `m` and `g` suffixes.
+
+use crate::config::Config;
+use crate::objects::parse_header;
+use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use std::fmt;
+use std::io::Read;
+
+/// A bounded quantity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    ObjectSize,
+    DeltaDepth,
+    TreeEntries,
+    PktLineLength,
+}
+
+impl Limit {
+    /// Configuration key setting this limit
+    pub fn config_key(&self) -> &str {
+        match self {
+            Limit::ObjectSize => "oxid.maxObjectSize",
+            Limit::DeltaDepth => "oxid.maxDeltaDepth",
+            Limit::TreeEntries => "oxid.maxTreeEntries",
+            Limit::PktLineLength => "oxid.maxPktLineLength",
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self {
+            Limit::ObjectSize => "object size",
+            Limit::DeltaDepth => "delta depth",
+            Limit::TreeEntries => "tree entry count",
+            Limit::PktLineLength => "pkt-line length",
+        }
+    }
+}
+
+/// Error for data going over one of the [`Limits`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub limit: Limit,
+    /// The offending value (or the first value found to be too large)
+    pub value: u64,
+    pub max: u64,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} exceeds the limit of {} (see {})",
+            self.limit.description(),
+            self.value,
+            self.max,
+            self.limit.config_key()
+        )
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Upper bounds on untrusted data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_object_size: u64,
+    pub max_delta_depth: u64,
+    pub max_tree_entries: u64,
+    pub max_pkt_line_length: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_object_size: 1 << 30,
+            // The deepest chain `git pack-objects --depth` produces
+            max_delta_depth: 4095,
+            max_tree_entries: 1_000_000,
+            // Largest pkt-line the protocol allows, including the length
+            max_pkt_line_length: 65520,
+        }
+    }
+}
+
+impl Limits {
+    /// Read the limits from the configuration, using defaults for unset keys
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let defaults = Self::default();
+        let get = |limit: Limit, default: u64| -> Result<u64> {
+            Ok(config.get_u64(limit.config_key())?.unwrap_or(default))
+        };
+        Ok(Self {
+            max_object_size: get(Limit::ObjectSize, defaults.max_object_size)?,
+            max_delta_depth: get(Limit::DeltaDepth, defaults.max_delta_depth)?,
+            max_tree_entries: get(Limit::TreeEntries, defaults.max_tree_entries)?,
+            max_pkt_line_length: get(Limit::PktLineLength, defaults.max_pkt_line_length)?,
+        })
+    }
+
+    /// Maximum for one limit
+    pub fn max(&self, limit: Limit) -> u64 {
+        match limit {
+            Limit::ObjectSize => self.max_object_size,
+            Limit::DeltaDepth => self.max_delta_depth,
+            Limit::TreeEntries => self.max_tree_entries,
+            Limit::PktLineLength => self.max_pkt_line_length,
+        }
+    }
+
+    /// Fail if `value` is over a limit
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Which limit to check
+    /// * `value` - The size, depth or count found
+    pub fn check(&self, limit: Limit, value: u64) -> std::result::Result<(), LimitExceeded> {
+        let max = self.max(limit);
+        if value > max {
+            return Err(LimitExceeded { limit, value, max });
+        }
+        Ok(())
+    }
+
+    /// Inflate a loose object without exceeding the object size limit
+    ///
+    /// The size announced in the header is checked before the content is
+    /// inflated, and no more than that size is ever inflated, so a
+    /// compressed bomb can't allocate more than the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `compressed` - The zlib stream of a loose object
+    ///
+    /// # Returns
+    ///
+    /// Decompressed object data, header included
+    pub fn inflate_object(&self, compressed: impl Read) -> Result<Vec<u8>> {
+        let mut decoder = ZlibDecoder::new(compressed);
+
+        // `<type> <size>\0` fits in 32 bytes for any valid object
+        let mut data = Vec::with_capacity(32);
+        let mut buf = [0u8; 32];
+        while !data.contains(&0) && data.len() < 32 {
+            let n = decoder.read(&mut buf[..32 - data.len()])?;
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        let (_, size, header_len) = parse_header(&data)?;
+        self.check(Limit::ObjectSize, size as u64)?;
+
+        // Read one byte past the announced size to catch objects that lie
+        let wanted = (header_len + size + 1).saturating_sub(data.len());
+        data.reserve(wanted);
+        decoder
+            .take(wanted as u64)
+            .read_to_end(&mut data)
+            .context("Corrupt object data")?;
+        let content = data.len() - header_len;
+        if content > size {
+            anyhow::bail!("Object size mismatch: header says {}, got more", size);
+        }
+        if content < size {
+            anyhow::bail!(
+                "Object size mismatch: header says {}, got {}",
+                size,
+                content
+            );
+        }
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+    use crate::utils::compress;
+
+    #[test]
+    fn test_from_config() {
+        let config =
+            Config::parse("[oxid]\n\tmaxObjectSize = 10m\n\tmaxTreeEntries = 5\n").unwrap();
+        let limits = Limits::from_config(&config).unwrap();
+        assert_eq!(limits.max_object_size, 10 << 20);
+        assert_eq!(limits.max_tree_entries, 5);
+        assert_eq!(limits.max_delta_depth, 4095);
+
+        let config = Config::parse("[oxid]\n\tmaxDeltaDepth = deep\n").unwrap();
+        assert!(Limits::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_inflate_object_limits() {
+        let limits = Limits {
+            max_object_size: 100,
+            ..Default::default()
+        };
+        let object = b"blob 5\0hello";
+        assert_eq!(
+            limits
+                .inflate_object(&compress(object).unwrap()[..])
+                .unwrap(),
+            object
+        );
+
+        // A bomb: small header, huge content
+        let mut bomb = b"blob 10\0".to_vec();
+        bomb.resize(bomb.len() + (10 << 20), 0);
+        let error = limits
+            .inflate_object(&compress(&bomb).unwrap()[..])
+            .unwrap_err();
+        assert!(error.to_string().contains("size mismatch"));
+
+        let big = format!("blob 101\0{}", "x".repeat(101));
+        let error = limits
+            .inflate_object(&compress(big.as_bytes()).unwrap()[..])
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<LimitExceeded>(),
+            Some(&LimitExceeded {
+                limit: Limit::ObjectSize,
+                value: 101,
+                max: 100
+            })
+        );
+    }
+
+    #[test]
+    fn test_hostile_commit_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let content = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            parent x\n\
+            author A U Thor <author@example.com> 1112911993 -0700\n\
+            committer A U Thor <author@example.com> 1112911993 -0700\n\ninitial\n";
+        let data = format!("commit {}\0{}", content.len(), content);
+        let hash = crate::utils::hash_data(data.as_bytes());
+        repo.write_object(&hash, data.as_bytes()).unwrap();
+
+        // An error, not a panic on the short id
+        assert!(crate::merge_base::reachable(&repo, &[&hash]).is_err());
+        assert!(repo.object_path("x").is_err());
+        assert!(repo.object_path("../../etc/passwd").is_err());
+        assert!(!repo.has_object("x"));
+    }
+}
@@ -0,0 +1,215 @@
+//! Identity mapping with `.mailmap`
+//!
+//! A mailmap maps the names and emails recorded in commits to canonical
+//! ones. Each line has one of these forms:
+//!
+//! ```text
+//! Proper Name <commit@email>
+//! <proper@email> <commit@email>
+//! Proper Name <proper@email> <commit@email>
+//! Proper Name <proper@email> Commit Name <commit@email>
+//! ```
+//!
+//! Emails match case-insensitively; the last form only applies when the
+//! commit name matches too. `#` starts a comment.
+
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::fs;
+
+/// One mailmap line
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    proper_name: Option<String>,
+    proper_email: Option<String>,
+    commit_name: Option<String>,
+    commit_email: String,
+}
+
+/// A set of identity mappings
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mailmap {
+    entries: Vec<Entry>,
+}
+
+/// Split `Name <email>` off the front of a string
+///
+/// Returns the name (if any), the email and the rest of the string.
+fn parse_name_and_email(text: &str) -> Option<(Option<String>, String, &str)> {
+    let open = text.find('<')?;
+    let close = open + text[open..].find('>')?;
+    let name = text[..open].trim();
+    let name = (!name.is_empty()).then(|| name.to_string());
+    Some((name, text[open + 1..close].to_string(), &text[close + 1..]))
+}
+
+/// Split a contact (`Name <email>` or `<email>`) into its parts
+///
+/// # Arguments
+///
+/// * `contact` - The contact to parse
+pub fn parse_contact(contact: &str) -> Result<(Option<String>, String)> {
+    match parse_name_and_email(contact) {
+        Some((name, email, rest)) if rest.trim().is_empty() => Ok((name, email)),
+        _ => anyhow::bail!("unable to parse contact: {}", contact),
+    }
+}
+
+impl Mailmap {
+    /// Create an empty mailmap
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse mailmap text, adding its entries to this mailmap
+    ///
+    /// Lines that don't parse are ignored, as Git does.
+    pub fn parse(&mut self, text: &str) {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("");
+            let Some((name, email, rest)) = parse_name_and_email(line) else {
+                continue;
+            };
+            let entry = match parse_name_and_email(rest) {
+                Some((commit_name, commit_email, _)) => Entry {
+                    proper_name: name,
+                    proper_email: Some(email),
+                    commit_name,
+                    commit_email,
+                },
+                None => Entry {
+                    proper_name: name,
+                    proper_email: None,
+                    commit_name: None,
+                    commit_email: email,
+                },
+            };
+            self.entries.push(entry);
+        }
+    }
+
+    /// Read the mailmap of a repository
+    ///
+    /// Reads `.mailmap` at the top of the worktree, then the file named by
+    /// `mailmap.file`; later entries take precedence.
+    pub fn read(repo: &Repository) -> Result<Self> {
+        let mut mailmap = Mailmap::new();
+        let mut files = Vec::new();
+        if !repo.bare {
+            files.push(repo.work_tree.join(".mailmap"));
+        }
+        if let Some(file) = repo.config()?.get("mailmap.file") {
+            files.push(repo.work_tree.join(file));
+        }
+        for file in files {
+            if file.exists() {
+                let text = fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                mailmap.parse(&text);
+            }
+        }
+        Ok(mailmap)
+    }
+
+    /// Whether the mailmap has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Map a name and email to their canonical form
+    ///
+    /// Entries naming the commit name win over entries matching only on
+    /// the email; among equally specific entries, later ones override the
+    /// fields they set.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name as recorded, if any
+    /// * `email` - Email as recorded
+    pub fn resolve(&self, name: Option<&str>, email: &str) -> (Option<String>, String) {
+        let matches = |entry: &&Entry, by_name: bool| {
+            entry.commit_email.eq_ignore_ascii_case(email)
+                && match (&entry.commit_name, name) {
+                    (Some(commit_name), Some(name)) if by_name => {
+                        commit_name.eq_ignore_ascii_case(name)
+                    }
+                    (None, _) => !by_name,
+                    _ => false,
+                }
+        };
+
+        let mut found: Vec<&Entry> = self.entries.iter().filter(|e| matches(e, true)).collect();
+        if found.is_empty() {
+            found = self.entries.iter().filter(|e| matches(e, false)).collect();
+        }
+
+        let mut resolved = (name.map(str::to_string), email.to_string());
+        for entry in found {
+            if let Some(proper_name) = &entry.proper_name {
+                resolved.0 = Some(proper_name.clone());
+            }
+            if let Some(proper_email) = &entry.proper_email {
+                resolved.1 = proper_email.clone();
+            }
+        }
+        resolved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Mailmap {
+        let mut mailmap = Mailmap::new();
+        mailmap.parse(
+            "# comment\n\
+             Jane Doe <jane@example.com>\n\
+             <jane@example.com> <jane@old.example>\n\
+             Joe Dev <joe@example.com> Joe <joe@laptop>\n\
+             Other <other@example.com> <JOE@laptop> # trailing comment\n\
+             not a mapping\n",
+        );
+        mailmap
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mailmap = sample();
+        assert_eq!(
+            mailmap.resolve(Some("jd"), "Jane@Example.com"),
+            (Some("Jane Doe".to_string()), "Jane@Example.com".to_string())
+        );
+        assert_eq!(
+            mailmap.resolve(Some("Jane"), "jane@old.example"),
+            (Some("Jane".to_string()), "jane@example.com".to_string())
+        );
+
+        // The name-specific entry wins over the email-only one
+        assert_eq!(
+            mailmap.resolve(Some("joe"), "joe@laptop"),
+            (Some("Joe Dev".to_string()), "joe@example.com".to_string())
+        );
+        assert_eq!(
+            mailmap.resolve(Some("Someone"), "joe@laptop"),
+            (Some("Other".to_string()), "other@example.com".to_string())
+        );
+        assert_eq!(
+            mailmap.resolve(None, "nobody@example.com"),
+            (None, "nobody@example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_contact() {
+        assert_eq!(
+            parse_contact("A U Thor <a@example.com>").unwrap(),
+            (Some("A U Thor".to_string()), "a@example.com".to_string())
+        );
+        assert_eq!(
+            parse_contact("<a@example.com>").unwrap(),
+            (None, "a@example.com".to_string())
+        );
+        assert!(parse_contact("a@example.com").is_err());
+    }
+}
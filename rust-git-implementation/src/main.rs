@@ -2,8 +2,8 @@
 //!
 //! Command-line interface for oxid
 
-use clap::{Parser, Subcommand};
 use anyhow::Result;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(name = "oxid")]
@@ -21,8 +21,644 @@ enum Commands {
         /// Path where to initialize the repository
         #[arg(default_value = ".")]
         path: String,
+
+        /// Create a bare repository
+        #[arg(long)]
+        bare: bool,
+
+        /// Name of the initial branch
+        #[arg(short = 'b', long = "initial-branch")]
+        initial_branch: Option<String>,
+
+        /// Directory from which templates will be used
+        #[arg(long)]
+        template: Option<String>,
+
+        /// Store the git dir elsewhere and link it with a .git file
+        #[arg(long = "separate-git-dir")]
+        separate_git_dir: Option<String>,
+
+        /// Hash function for object names; only sha1 is supported
+        #[arg(long = "object-format", value_name = "format")]
+        object_format: Option<String>,
+    },
+
+    /// Show information about files in the index
+    LsFiles {
+        /// Show mode, object hash and stage number
+        #[arg(short = 's', long = "stage")]
+        stage: bool,
+
+        /// Tag assume-unchanged files with lowercase letters
+        #[arg(short = 'v')]
+        tags: bool,
+
+        /// Show only unmerged entries
+        #[arg(short = 'u', long)]
+        unmerged: bool,
+
+        /// Show stat data, flags and index extensions
+        #[arg(long)]
+        debug: bool,
+
+        /// End each entry with NUL and don't quote paths
+        #[arg(short = 'z')]
+        nul: bool,
+    },
+
+    /// Show which commit last changed each line of a file
+    Blame {
+        /// Show the result in a format meant for machine consumption
+        #[arg(short = 'p', long)]
+        porcelain: bool,
+
+        /// Like --porcelain, with commit information on every line
+        #[arg(long = "line-porcelain")]
+        line_porcelain: bool,
+
+        /// Show the result as it is found, in a format for machines
+        #[arg(long)]
+        incremental: bool,
+
+        /// Don't treat root commits as boundaries
+        #[arg(long)]
+        root: bool,
+
+        /// An optional revision to start from, then the file
+        #[arg(required = true, num_args = 1..=2, value_name = "rev|file")]
+        args: Vec<String>,
+    },
+
+    /// List branches
+    Branch {
+        /// Only list branches that contain the commit (HEAD if not given)
+        #[arg(
+            long,
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        contains: Vec<String>,
+
+        /// Only list branches that don't contain the commit
+        #[arg(
+            long = "no-contains",
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        no_contains: Vec<String>,
+
+        /// Only list branches whose tips are reachable from the commit
+        #[arg(
+            long,
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        merged: Vec<String>,
+
+        /// Only list branches whose tips are not reachable from the commit
+        #[arg(
+            long = "no-merged",
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        no_merged: Vec<String>,
+    },
+
+    /// Restore working tree files from the index
+    Checkout {
+        /// Recreate conflict markers in the given style (merge or diff3)
+        #[arg(long)]
+        conflict: Option<String>,
+
+        /// Recreate conflict markers in the merge.conflictStyle style
+        #[arg(short = 'm', long)]
+        merge: bool,
+
+        /// Files to restore
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// Show canonical names and emails from the mailmap
+    CheckMailmap {
+        /// Also read contacts from stdin, one per line
+        #[arg(long)]
+        stdin: bool,
+
+        /// Contacts ("Name <email>" or "<email>")
+        contacts: Vec<String>,
+    },
+
+    /// Copy files from the index to the working tree
+    CheckoutIndex {
+        /// Check out all files in the index
+        #[arg(short = 'a', long)]
+        all: bool,
+
+        /// Overwrite existing files
+        #[arg(short = 'f', long)]
+        force: bool,
+
+        /// Update stat information in the index
+        #[arg(short = 'u', long = "index")]
+        update_stat: bool,
+
+        /// Prepend this string to every written path
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// Check out this conflict stage (1-3)
+        #[arg(long, value_parser = clap::value_parser!(u8).range(1..=3))]
+        stage: Option<u8>,
+
+        /// Files to check out
+        paths: Vec<String>,
+    },
+
+    /// Create a commit object from a tree
+    CommitTree {
+        /// Tree to commit
+        tree: String,
+
+        /// Parent commit (may be given more than once)
+        #[arg(short = 'p')]
+        parents: Vec<String>,
+
+        /// Message paragraph (may be given more than once)
+        #[arg(short = 'm')]
+        messages: Vec<String>,
+
+        /// Read the message from a file ("-" for stdin)
+        #[arg(short = 'F')]
+        files: Vec<String>,
+
+        /// Sign the commit, with the given key or the default one
+        #[arg(
+            short = 'S',
+            long = "gpg-sign",
+            value_name = "keyid",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "",
+            overrides_with = "no_gpg_sign"
+        )]
+        gpg_sign: Option<String>,
+
+        /// Don't sign the commit, even if commit.gpgSign is set
+        #[arg(long = "no-gpg-sign")]
+        no_gpg_sign: bool,
+    },
+
+    /// Check objects and refs against Git's rules and Git itself
+    CompatCheck {
+        /// Path inside the repository to check
+        #[arg(default_value = ".")]
+        path: String,
+    },
+
+    /// Verify the connectivity and validity of objects
+    Fsck {
+        /// Show every unreachable object, not only dangling ones
+        #[arg(long)]
+        unreachable: bool,
+
+        /// Don't show dangling objects
+        #[arg(long = "no-dangling")]
+        no_dangling: bool,
+
+        /// Treat format warnings as errors
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Compare files in the working tree and the index
+    DiffFiles {
+        /// Show the raw diff format (the default)
+        #[arg(long)]
+        raw: bool,
+
+        /// Show only names and status of changed files
+        #[arg(long = "name-status")]
+        name_status: bool,
+
+        /// Show only names of changed files
+        #[arg(long = "name-only")]
+        name_only: bool,
+
+        /// Separate fields with NUL instead of tab and newline
+        #[arg(short = 'z')]
+        nul: bool,
+
+        /// Recurse into subtrees (always the case for the index)
+        #[arg(short = 'r')]
+        recursive: bool,
+    },
+
+    /// Compare a tree with the working tree or the index
+    DiffIndex {
+        /// Compare with the index instead of the working tree
+        #[arg(long)]
+        cached: bool,
+
+        /// Show the raw diff format (the default)
+        #[arg(long)]
+        raw: bool,
+
+        /// Show only names and status of changed files
+        #[arg(long = "name-status")]
+        name_status: bool,
+
+        /// Show only names of changed files
+        #[arg(long = "name-only")]
+        name_only: bool,
+
+        /// Separate fields with NUL instead of tab and newline
+        #[arg(short = 'z')]
+        nul: bool,
+
+        /// Recurse into subtrees (always the case for the index)
+        #[arg(short = 'r')]
+        recursive: bool,
+
+        /// Tree-ish to compare against
+        tree: String,
+    },
+
+    /// Compare the content and mode of blobs found via two tree objects
+    DiffTree {
+        /// Show the raw diff format (the default)
+        #[arg(long)]
+        raw: bool,
+
+        /// Show only names and status of changed files
+        #[arg(long = "name-status")]
+        name_status: bool,
+
+        /// Show only names of changed files
+        #[arg(long = "name-only")]
+        name_only: bool,
+
+        /// Separate fields with NUL instead of tab and newline
+        #[arg(short = 'z')]
+        nul: bool,
+
+        /// Recurse into subtrees
+        #[arg(short = 'r')]
+        recursive: bool,
+
+        /// Show the changes of a root commit as additions
+        #[arg(long)]
+        root: bool,
+
+        /// One commit, or two tree-ish revisions
+        #[arg(required = true, num_args = 1..=2, value_name = "tree-ish")]
+        objects: Vec<String>,
+    },
+
+    /// Show changes using an external diff tool
+    Difftool {
+        /// Diff tool to use instead of diff.tool
+        #[arg(short = 't', long)]
+        tool: Option<String>,
+
+        /// Don't prompt before launching the tool
+        #[arg(short = 'y', long = "no-prompt", overrides_with = "prompt")]
+        no_prompt: bool,
+
+        /// Prompt before each invocation of the tool
+        #[arg(long)]
+        prompt: bool,
+
+        /// Compare all changed files at once as two directories
+        #[arg(short = 'd', long = "dir-diff")]
+        dir_diff: bool,
+    },
+
+    /// Download objects and refs from a repository served over dumb HTTP
+    Fetch {
+        /// Name for the remote-tracking refs (refs/remotes/<name>/...)
+        #[arg(short = 'o', long = "origin", default_value = "origin")]
+        name: String,
+
+        /// URL of the remote repository: http://, or <transport>::<address> for a remote helper
+        url: String,
+    },
+
+    /// Download objects for remote refs without updating local refs
+    FetchPack {
+        /// Fetch all remote refs
+        #[arg(long)]
+        all: bool,
+
+        /// Also read ref names from stdin, one per line
+        #[arg(long)]
+        stdin: bool,
+
+        /// URL of the remote repository (http:// only)
+        url: String,
+
+        /// Refs to fetch: full names, HEAD or object ids
+        refs: Vec<String>,
+    },
+
+    /// Print lines matching a pattern
+    Grep {
+        /// Search the files staged in the index instead of the working tree
+        #[arg(long)]
+        cached: bool,
+
+        /// Use extended regular expressions
+        #[arg(short = 'E', long = "extended-regexp")]
+        extended_regexp: bool,
+
+        /// Match fixed strings instead of regular expressions
+        #[arg(short = 'F', long = "fixed-strings")]
+        fixed_strings: bool,
+
+        /// Ignore case differences
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+
+        /// Only match whole words
+        #[arg(short = 'w', long = "word-regexp")]
+        word_regexp: bool,
+
+        /// Prefix lines with their line number
+        #[arg(short = 'n', long = "line-number")]
+        line_number: bool,
+
+        /// Only show the names of files with matches
+        #[arg(short = 'l', long = "files-with-matches")]
+        files_with_matches: bool,
+
+        /// Show the number of matching lines of each file
+        #[arg(short = 'c', long)]
+        count: bool,
+
+        /// Descend at most this many directories
+        #[arg(long = "max-depth", value_name = "depth")]
+        max_depth: Option<usize>,
+
+        /// A pattern to look for; may be given more than once
+        #[arg(short = 'e', value_name = "pattern")]
+        patterns: Vec<String>,
+
+        /// The pattern unless -e is given, then revisions and paths
+        #[arg(value_name = "pattern|rev|path")]
+        args: Vec<String>,
+
+        /// Paths to search
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Resolve merge conflicts using an external merge tool
+    Mergetool {
+        /// Merge tool to use instead of merge.tool
+        #[arg(short = 't', long)]
+        tool: Option<String>,
+
+        /// Don't prompt before launching the tool
+        #[arg(short = 'y', long = "no-prompt", overrides_with = "prompt")]
+        no_prompt: bool,
+
+        /// Prompt before each invocation of the tool
+        #[arg(long)]
+        prompt: bool,
+    },
+
+    /// Read a tree into the index
+    ReadTree {
+        /// Keep stat data of unchanged entries; refuse unmerged entries
+        #[arg(short = 'm')]
+        merge: bool,
+
+        /// Update the working tree to match the new index
+        #[arg(short = 'u')]
+        update: bool,
+
+        /// Read the tree below this directory of the current index
+        #[arg(long, value_name = "prefix")]
+        prefix: Option<String>,
+
+        /// Tree to read
+        #[arg(required = true, value_name = "tree-ish")]
+        trees: Vec<String>,
     },
 
+    /// Create, list and delete refs for replacing objects
+    Replace {
+        /// Overwrite an existing replacement
+        #[arg(short = 'f', long)]
+        force: bool,
+
+        /// Delete the replacements of the given objects
+        #[arg(short = 'd', long)]
+        delete: bool,
+
+        /// List replacements, optionally only those matching a pattern
+        #[arg(short = 'l', long)]
+        list: bool,
+
+        /// Replace a commit with a copy that has the given parents
+        #[arg(long)]
+        graft: bool,
+
+        /// How to list replacements: short, medium or long
+        #[arg(long, value_name = "format")]
+        format: Option<String>,
+
+        /// Objects to replace or delete, or the pattern to list
+        args: Vec<String>,
+    },
+
+    /// Show information about the repository
+    RevParse {
+        /// Show the absolute path of the top of the working tree
+        #[arg(long = "show-toplevel")]
+        show_toplevel: bool,
+
+        /// Show the path to the git dir
+        #[arg(long = "git-dir")]
+        git_dir: bool,
+
+        /// Whether the current directory is inside the git dir
+        #[arg(long = "is-inside-git-dir")]
+        is_inside_git_dir: bool,
+
+        /// Whether the current directory is inside the working tree
+        #[arg(long = "is-inside-work-tree")]
+        is_inside_work_tree: bool,
+
+        /// Whether the repository is bare
+        #[arg(long = "is-bare-repository")]
+        is_bare_repository: bool,
+
+        /// Whether the repository is a shallow clone
+        #[arg(long = "is-shallow-repository")]
+        is_shallow_repository: bool,
+    },
+
+    /// Show or apply sparse checkout patterns
+    SparseCheckout {
+        #[command(subcommand)]
+        command: SparseCheckoutCommand,
+    },
+
+    /// Show the working tree status
+    Status {
+        /// Give the output in the short format
+        #[arg(short = 's', long)]
+        short: bool,
+
+        /// Give the output in a stable, script-friendly format
+        #[arg(long)]
+        porcelain: bool,
+
+        /// Hide submodule changes: none, untracked, dirty or all (the default)
+        #[arg(
+            long = "ignore-submodules",
+            value_name = "when",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "all"
+        )]
+        ignore_submodules: Option<String>,
+
+        /// End each entry with NUL and don't quote paths; implies
+        /// --porcelain unless --short is given
+        #[arg(short = 'z')]
+        nul: bool,
+    },
+
+    /// Remove unnecessary whitespace from text on stdin
+    Stripspace {
+        /// Also remove comment lines
+        #[arg(short = 's', long = "strip-comments", conflicts_with = "comment_lines")]
+        strip_comments: bool,
+
+        /// Turn every line into a comment
+        #[arg(short = 'c', long = "comment-lines")]
+        comment_lines: bool,
+    },
+
+    /// List tags
+    Tag {
+        /// List tags (the only mode supported)
+        #[arg(short = 'l', long)]
+        list: bool,
+
+        /// Only list tags that contain the commit (HEAD if not given)
+        #[arg(
+            long,
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        contains: Vec<String>,
+
+        /// Only list tags that don't contain the commit
+        #[arg(
+            long = "no-contains",
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        no_contains: Vec<String>,
+
+        /// Only list tags whose commits are reachable from the commit
+        #[arg(
+            long,
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        merged: Vec<String>,
+
+        /// Only list tags whose commits are not reachable from the commit
+        #[arg(
+            long = "no-merged",
+            value_name = "commit",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        no_merged: Vec<String>,
+
+        /// Only list tags of the object (HEAD if not given)
+        #[arg(
+            long = "points-at",
+            value_name = "object",
+            num_args = 0..=1,
+            default_missing_value = "HEAD"
+        )]
+        points_at: Vec<String>,
+
+        /// Sort by refname or version:refname; prefix with - to reverse
+        #[arg(long, value_name = "key")]
+        sort: Vec<String>,
+
+        /// Verify tag signatures and show the result after each name
+        #[arg(short = 'v', long)]
+        verify: bool,
+
+        /// Only list tags matching one of the patterns
+        #[arg(value_name = "pattern")]
+        patterns: Vec<String>,
+    },
+
+    /// Show a logical variable, e.g. GIT_EDITOR
+    Var {
+        /// List all variables
+        #[arg(short = 'l', conflicts_with = "variable")]
+        list: bool,
+
+        /// Variable to show
+        #[arg(required_unless_present = "list")]
+        variable: Option<String>,
+    },
+
+    /// Modify index entry flags
+    UpdateIndex {
+        /// Don't check the working tree file for changes
+        #[arg(long = "assume-unchanged", overrides_with = "no_assume_unchanged")]
+        assume_unchanged: bool,
+
+        /// Check the working tree file for changes again
+        #[arg(long = "no-assume-unchanged")]
+        no_assume_unchanged: bool,
+
+        /// Leave the file out of the working tree
+        #[arg(long = "skip-worktree", overrides_with = "no_skip_worktree")]
+        skip_worktree: bool,
+
+        /// Track the working tree file again
+        #[arg(long = "no-skip-worktree")]
+        no_skip_worktree: bool,
+
+        /// Files to update
+        files: Vec<String>,
+    },
+
+    /// Update auxiliary info files for dumb servers
+    UpdateServerInfo {
+        /// Rewrite the files even if they are up to date
+        #[arg(short = 'f', long)]
+        force: bool,
+    },
+
+    /// Send objects to a fetching client
+    UploadPack {
+        /// Only advertise the refs, then exit
+        #[arg(long = "advertise-refs")]
+        advertise_refs: bool,
+
+        /// The repository to serve
+        directory: String,
+    },
     // Uncomment as you implement each command
 
     // /// Compute object ID and optionally create a blob
@@ -66,34 +702,488 @@ enum Commands {
     // },
 }
 
+#[derive(Subcommand)]
+enum SparseCheckoutCommand {
+    /// List the included directories (cone mode) or the patterns
+    List,
+    /// Update the working tree to match the patterns
+    Reapply,
+}
+
+/// The raw diff format selected by `--name-status` and `--name-only`
+fn raw_format(name_status: bool, name_only: bool) -> oxid::commands::diff_files::RawFormat {
+    use oxid::commands::diff_files::RawFormat;
+    if name_only {
+        RawFormat::NameOnly
+    } else if name_status {
+        RawFormat::NameStatus
+    } else {
+        RawFormat::Raw
+    }
+}
+
+/// Combine a `--flag`/`--no-flag` pair; `None` when neither was given
+fn tri_state(set: bool, clear: bool) -> Option<bool> {
+    if set {
+        Some(true)
+    } else if clear {
+        Some(false)
+    } else {
+        None
+    }
+}
+
 fn main() -> Result<()> {
+    oxid::trace::init_from_env();
+    let args: Vec<String> = std::env::args().collect();
+    tracing::debug!("built-in: {}", args.join(" "));
+
     let cli = Cli::parse();
+    let _span = tracing::info_span!("command", args = %args.join(" ")).entered();
 
     match cli.command {
-        Commands::Init { path } => {
-            oxid::commands::init::run(&path)?;
-        }
-
-        // Uncomment as you implement each command
-        // Commands::HashObject { write, file } => {
-        //     oxid::commands::hash_object::run(&file, write)?;
-        // }
-        // Commands::CatFile {
-        //     show_type,
-        //     pretty_print,
-        //     object,
-        // } => {
-        //     oxid::commands::cat_file::run(&object, show_type, pretty_print)?;
-        // }
-        // Commands::WriteTree => {
-        //     oxid::commands::write_tree::run()?;
-        // }
-        // Commands::Commit { message } => {
-        //     oxid::commands::commit::run(&message)?;
-        // }
-        // Commands::Add { files } => {
-        //     oxid::commands::add::run(&files)?;
-        // }
+        Commands::Init {
+            path,
+            bare,
+            initial_branch,
+            template,
+            separate_git_dir,
+            object_format,
+        } => {
+            if let Some(format) = object_format.filter(|format| format != "sha1") {
+                anyhow::bail!("object format '{}' is not supported", format);
+            }
+            let options = oxid::repository::InitOptions {
+                bare,
+                initial_branch,
+                template_dir: template.map(Into::into),
+                separate_git_dir: separate_git_dir.map(Into::into),
+            };
+            oxid::commands::init::run(&path, &options)?;
+        }
+        Commands::LsFiles {
+            stage,
+            tags,
+            unmerged,
+            debug,
+            nul,
+        } => {
+            let options = oxid::commands::ls_files::LsFilesOptions {
+                stage,
+                tags,
+                unmerged,
+                debug,
+            };
+            oxid::commands::ls_files::run(&options, nul)?;
+        }
+        Commands::Blame {
+            porcelain,
+            line_porcelain,
+            incremental,
+            root,
+            args,
+        } => {
+            use oxid::commands::blame::BlameFormat;
+            let format = if incremental {
+                BlameFormat::Incremental
+            } else if line_porcelain {
+                BlameFormat::LinePorcelain
+            } else if porcelain {
+                BlameFormat::Porcelain
+            } else {
+                BlameFormat::Default
+            };
+            let (revision, file) = match args.as_slice() {
+                [file] => (None, file),
+                [revision, file] => (Some(revision.as_str()), file),
+                _ => unreachable!("clap takes one or two arguments"),
+            };
+            oxid::commands::blame::run(revision, std::path::Path::new(file), format, root)?;
+        }
+        Commands::Branch {
+            contains,
+            no_contains,
+            merged,
+            no_merged,
+        } => {
+            let filter = oxid::ref_filter::ReachFilter {
+                contains,
+                no_contains,
+                merged,
+                no_merged,
+            };
+            oxid::commands::branch::run(&filter)?;
+        }
+        Commands::Checkout {
+            conflict,
+            merge,
+            paths,
+        } => {
+            let options = oxid::commands::checkout::CheckoutOptions {
+                conflict: conflict
+                    .as_deref()
+                    .map(oxid::merge::ConflictStyle::from_str)
+                    .transpose()?,
+                merge,
+            };
+            oxid::commands::checkout::run(&paths, &options)?;
+        }
+        Commands::CheckMailmap { stdin, contacts } => {
+            oxid::commands::check_mailmap::run(&contacts, stdin)?;
+        }
+        Commands::CheckoutIndex {
+            all,
+            force,
+            update_stat,
+            prefix,
+            stage,
+            paths,
+        } => {
+            let options = oxid::commands::checkout_index::CheckoutIndexOptions {
+                all,
+                force,
+                update_stat,
+                prefix,
+                stage,
+            };
+            oxid::commands::checkout_index::run(&paths, &options)?;
+        }
+        Commands::CommitTree {
+            tree,
+            parents,
+            messages,
+            files,
+            gpg_sign,
+            no_gpg_sign,
+        } => {
+            let options = oxid::commands::commit_tree::CommitTreeOptions {
+                parents,
+                messages,
+                files,
+                gpg_sign: if no_gpg_sign {
+                    Some(false)
+                } else {
+                    gpg_sign.as_ref().map(|_| true)
+                },
+                key_id: gpg_sign.filter(|key| !key.is_empty()),
+            };
+            oxid::commands::commit_tree::run(&tree, &options)?;
+        }
+        Commands::CompatCheck { path } => {
+            oxid::commands::compat_check::run(&path)?;
+        }
+        Commands::Fsck {
+            unreachable,
+            no_dangling,
+            strict,
+        } => {
+            let options = oxid::commands::fsck::FsckOptions {
+                unreachable,
+                no_dangling,
+                strict,
+            };
+            oxid::commands::fsck::run(&options)?;
+        }
+        Commands::DiffFiles {
+            raw: _,
+            name_status,
+            name_only,
+            nul,
+            recursive: _,
+        } => {
+            oxid::commands::diff_files::run(raw_format(name_status, name_only), nul)?;
+        }
+        Commands::DiffIndex {
+            cached,
+            raw: _,
+            name_status,
+            name_only,
+            nul,
+            recursive: _,
+            tree,
+        } => {
+            let options = oxid::commands::diff_index::DiffIndexOptions {
+                format: raw_format(name_status, name_only),
+                cached,
+                nul,
+            };
+            oxid::commands::diff_index::run(&tree, &options)?;
+        }
+        Commands::DiffTree {
+            raw: _,
+            name_status,
+            name_only,
+            nul,
+            recursive,
+            root,
+            objects,
+        } => {
+            let options = oxid::commands::diff_tree::DiffTreeOptions {
+                format: raw_format(name_status, name_only),
+                recursive,
+                root,
+                nul,
+            };
+            oxid::commands::diff_tree::run(&objects, &options)?;
+        }
+        Commands::Difftool {
+            tool,
+            no_prompt,
+            prompt,
+            dir_diff,
+        } => {
+            let options = oxid::commands::difftool::DifftoolOptions {
+                tool,
+                prompt: tri_state(prompt, no_prompt),
+                dir_diff,
+            };
+            oxid::commands::difftool::run(&options)?;
+        }
+        Commands::Fetch { name, url } => {
+            oxid::commands::fetch::run(&url, &name)?;
+        }
+        Commands::FetchPack {
+            all,
+            stdin,
+            url,
+            refs,
+        } => {
+            let options = oxid::commands::fetch_pack::FetchPackOptions { all, stdin };
+            oxid::commands::fetch_pack::run(&url, refs, &options)?;
+        }
+        Commands::Grep {
+            cached,
+            extended_regexp,
+            fixed_strings,
+            ignore_case,
+            word_regexp,
+            line_number,
+            files_with_matches,
+            count,
+            max_depth,
+            patterns,
+            args,
+            paths,
+        } => {
+            use oxid::grep::{MatchOptions, Syntax};
+            let match_options = MatchOptions {
+                syntax: if fixed_strings {
+                    Syntax::Fixed
+                } else if extended_regexp {
+                    Syntax::Extended
+                } else {
+                    Syntax::Basic
+                },
+                ignore_case,
+                word: word_regexp,
+            };
+            let options = oxid::commands::grep::GrepOptions {
+                cached,
+                line_number,
+                files_with_matches,
+                count,
+                max_depth,
+            };
+            oxid::commands::grep::run(&patterns, match_options, &options, &args, &paths)?;
+        }
+        Commands::Mergetool {
+            tool,
+            no_prompt,
+            prompt,
+        } => {
+            let options = oxid::commands::mergetool::MergetoolOptions {
+                tool,
+                prompt: tri_state(prompt, no_prompt),
+            };
+            oxid::commands::mergetool::run(&options)?;
+        }
+        Commands::ReadTree {
+            merge,
+            update,
+            prefix,
+            trees,
+        } => {
+            let options = oxid::commands::read_tree::ReadTreeOptions {
+                merge,
+                update,
+                prefix,
+            };
+            oxid::commands::read_tree::run(&trees, &options)?;
+        }
+        Commands::Replace {
+            force,
+            delete,
+            list,
+            graft,
+            format,
+            mut args,
+        } => {
+            use oxid::commands::replace::ReplaceAction;
+            if format.is_some() && (delete || graft || (!list && !args.is_empty())) {
+                anyhow::bail!("--format cannot be used when not listing");
+            }
+            let action = if delete {
+                if args.is_empty() {
+                    anyhow::bail!("-d needs at least one argument");
+                }
+                ReplaceAction::Delete(args)
+            } else if graft {
+                if args.is_empty() {
+                    anyhow::bail!("--graft needs at least one argument");
+                }
+                let commit = args.remove(0);
+                ReplaceAction::Graft {
+                    commit,
+                    parents: args,
+                    force,
+                }
+            } else if list || args.is_empty() {
+                if args.len() > 1 {
+                    anyhow::bail!("only one pattern can be given with -l");
+                }
+                ReplaceAction::List {
+                    pattern: args.pop(),
+                    format: format.as_deref().unwrap_or("short").parse()?,
+                }
+            } else {
+                let [object, replacement] = <[String; 2]>::try_from(args)
+                    .map_err(|_| anyhow::anyhow!("bad number of arguments"))?;
+                ReplaceAction::Create {
+                    object,
+                    replacement,
+                    force,
+                }
+            };
+            oxid::commands::replace::run(&action)?;
+        }
+        Commands::RevParse {
+            show_toplevel,
+            git_dir,
+            is_inside_git_dir,
+            is_inside_work_tree,
+            is_bare_repository,
+            is_shallow_repository,
+        } => {
+            let options = oxid::commands::rev_parse::RevParseOptions {
+                show_toplevel,
+                git_dir,
+                is_inside_git_dir,
+                is_inside_work_tree,
+                is_bare_repository,
+                is_shallow_repository,
+            };
+            oxid::commands::rev_parse::run(&options)?;
+        }
+        Commands::SparseCheckout { command } => match command {
+            SparseCheckoutCommand::List => oxid::commands::sparse_checkout::list()?,
+            SparseCheckoutCommand::Reapply => oxid::commands::sparse_checkout::run_reapply()?,
+        },
+        Commands::Status {
+            short,
+            porcelain,
+            ignore_submodules,
+            nul,
+        } => {
+            use oxid::commands::status::StatusFormat;
+            let format = if porcelain {
+                StatusFormat::Porcelain
+            } else if short {
+                StatusFormat::Short
+            } else if nul {
+                StatusFormat::Porcelain
+            } else {
+                StatusFormat::Long
+            };
+            let ignore_submodules = ignore_submodules
+                .as_deref()
+                .map(oxid::status::IgnoreSubmodules::from_str)
+                .transpose()?;
+            oxid::commands::status::run(format, ignore_submodules, nul)?;
+        }
+        Commands::Stripspace {
+            strip_comments,
+            comment_lines,
+        } => {
+            use oxid::commands::stripspace::CommentMode;
+            let mode = if strip_comments {
+                CommentMode::Strip
+            } else if comment_lines {
+                CommentMode::CommentLines
+            } else {
+                CommentMode::Keep
+            };
+            oxid::commands::stripspace::run(mode)?;
+        }
+        Commands::Tag {
+            list: _,
+            contains,
+            no_contains,
+            merged,
+            no_merged,
+            points_at,
+            sort,
+            verify,
+            patterns,
+        } => {
+            let options = oxid::commands::tag::TagOptions {
+                patterns,
+                filter: oxid::ref_filter::ReachFilter {
+                    contains,
+                    no_contains,
+                    merged,
+                    no_merged,
+                },
+                points_at,
+                // Like Git, the last key given wins
+                sort: sort.into_iter().last(),
+                verify,
+            };
+            oxid::commands::tag::run(&options)?;
+        }
+        Commands::Var { list: _, variable } => {
+            oxid::commands::var::run(variable.as_deref())?;
+        }
+        Commands::UpdateIndex {
+            assume_unchanged,
+            no_assume_unchanged,
+            skip_worktree,
+            no_skip_worktree,
+            files,
+        } => {
+            let options = oxid::commands::update_index::UpdateIndexOptions {
+                assume_unchanged: tri_state(assume_unchanged, no_assume_unchanged),
+                skip_worktree: tri_state(skip_worktree, no_skip_worktree),
+            };
+            oxid::commands::update_index::run(&files, &options)?;
+        }
+        Commands::UpdateServerInfo { force } => {
+            oxid::commands::update_server_info::run(force)?;
+        }
+        Commands::UploadPack {
+            advertise_refs,
+            directory,
+        } => {
+            oxid::commands::upload_pack::run(&directory, advertise_refs)?;
+        } // Uncomment as you implement each command
+          // Commands::HashObject { write, file } => {
+          //     oxid::commands::hash_object::run(&file, write)?;
+          // }
+          // Commands::CatFile {
+          //     show_type,
+          //     pretty_print,
+          //     object,
+          // } => {
+          //     oxid::commands::cat_file::run(&object, show_type, pretty_print)?;
+          // }
+          // Commands::WriteTree => {
+          //     oxid::commands::write_tree::run()?;
+          // }
+          // Commands::Commit { message } => {
+          //     oxid::commands::commit::run(&message)?;
+          // }
+          // Commands::Add { files } => {
+          //     oxid::commands::add::run(&files)?;
+          // }
     }
 
     Ok(())
@@ -0,0 +1,338 @@
+//! Three-way file merges
+//!
+//! Both sides are diffed against the common ancestor. Changes made by only
+//! one side are applied; overlapping changes become conflicts unless both
+//! sides made the same change. Conflicts are written with markers:
+//!
+//! ```text
+//! <<<<<<< ours
+//! our lines
+//! ||||||| base        (diff3 style only)
+//! original lines
+//! =======
+//! their lines
+//! >>>>>>> theirs
+//! ```
+
+use crate::diff::{diff, split_lines, Hunk};
+use anyhow::Result;
+
+/// How conflicts are written (`merge.conflictStyle`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictStyle {
+    /// Ours and theirs only; lines common to both are moved out of the conflict
+    #[default]
+    Merge,
+    /// Also show the base version between `|||||||` and `=======`
+    Diff3,
+}
+
+impl ConflictStyle {
+    /// Parse a style name (`merge` or `diff3`)
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "merge" => Ok(ConflictStyle::Merge),
+            "diff3" => Ok(ConflictStyle::Diff3),
+            _ => anyhow::bail!("unknown conflict style '{}'", s),
+        }
+    }
+}
+
+/// Labels written after the conflict markers
+#[derive(Debug, Clone, Copy)]
+pub struct Labels<'a> {
+    pub ours: &'a str,
+    pub base: &'a str,
+    pub theirs: &'a str,
+}
+
+impl Default for Labels<'_> {
+    fn default() -> Self {
+        Labels {
+            ours: "ours",
+            base: "base",
+            theirs: "theirs",
+        }
+    }
+}
+
+/// The result of a merge
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeResult {
+    /// Merged content, with conflict markers if there were conflicts
+    pub data: Vec<u8>,
+    /// Number of conflicting regions
+    pub conflicts: usize,
+}
+
+/// A change by one side: the hunk and which side made it
+#[derive(Clone, Copy)]
+struct Change {
+    hunk: Hunk,
+    theirs: bool,
+}
+
+/// Merge `ours` and `theirs`, both derived from `base`
+///
+/// # Arguments
+///
+/// * `base` - Common ancestor content
+/// * `ours` - Our version
+/// * `theirs` - Their version
+/// * `style` - How to write conflicts
+/// * `labels` - Names written after the markers
+pub fn merge(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    style: ConflictStyle,
+    labels: &Labels,
+) -> MergeResult {
+    let base_lines = split_lines(base);
+    let sides = [split_lines(ours), split_lines(theirs)];
+    let ours_hunks = diff(&base_lines, &sides[0]);
+    let theirs_hunks = diff(&base_lines, &sides[1]);
+
+    let mut changes: Vec<Change> = ours_hunks
+        .iter()
+        .map(|&hunk| Change {
+            hunk,
+            theirs: false,
+        })
+        .chain(
+            theirs_hunks
+                .iter()
+                .map(|&hunk| Change { hunk, theirs: true }),
+        )
+        .collect();
+    changes.sort_by_key(|c| (c.hunk.old_start, c.hunk.old_end()));
+
+    let mut out = Vec::new();
+    let mut conflicts = 0;
+    let mut base_pos = 0;
+    // Offset of each side relative to base, for base positions outside hunks
+    let mut deltas = [0isize; 2];
+
+    let mut i = 0;
+    while i < changes.len() {
+        // Collect every change overlapping the region, transitively
+        let mut start = changes[i].hunk.old_start;
+        let mut end = changes[i].hunk.old_end();
+        let mut group = vec![changes[i]];
+        i += 1;
+        while i < changes.len() && overlaps(start, end, &changes[i].hunk) {
+            start = start.min(changes[i].hunk.old_start);
+            end = end.max(changes[i].hunk.old_end());
+            group.push(changes[i]);
+            i += 1;
+        }
+
+        for line in &base_lines[base_pos..start] {
+            out.extend_from_slice(line);
+        }
+        base_pos = end;
+
+        // The lines each side has in place of base[start..end]
+        let mut replacement: [&[&[u8]]; 2] = [&[], &[]];
+        let mut touched = [false; 2];
+        for side in 0..2 {
+            let side_start = (start as isize + deltas[side]) as usize;
+            for change in group.iter().filter(|c| c.theirs as usize == side) {
+                deltas[side] += change.hunk.new_len as isize - change.hunk.old_len as isize;
+                touched[side] = true;
+            }
+            let side_end = (end as isize + deltas[side]) as usize;
+            replacement[side] = &sides[side][side_start..side_end];
+        }
+
+        if !touched[1] || replacement[0] == replacement[1] {
+            emit(&mut out, replacement[0]);
+        } else if !touched[0] {
+            emit(&mut out, replacement[1]);
+        } else {
+            conflicts += 1;
+            write_conflict(
+                &mut out,
+                replacement[0],
+                &base_lines[start..end],
+                replacement[1],
+                style,
+                labels,
+            );
+        }
+    }
+
+    for line in &base_lines[base_pos..] {
+        out.extend_from_slice(line);
+    }
+
+    MergeResult {
+        data: out,
+        conflicts,
+    }
+}
+
+/// Whether a hunk overlaps the base region `start..end`
+///
+/// Insertions at the same position also overlap, since their order is
+/// ambiguous.
+fn overlaps(start: usize, end: usize, hunk: &Hunk) -> bool {
+    (hunk.old_start < end && start < hunk.old_end()) || hunk.old_start == start
+}
+
+fn emit(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    for line in lines {
+        out.extend_from_slice(line);
+    }
+}
+
+/// Emit lines, making sure the last one ends with a newline so that a
+/// following marker starts on its own line
+fn emit_terminated(out: &mut Vec<u8>, lines: &[&[u8]]) {
+    emit(out, lines);
+    if lines.last().is_some_and(|l| !l.ends_with(b"\n")) {
+        out.push(b'\n');
+    }
+}
+
+fn marker(out: &mut Vec<u8>, c: u8, label: Option<&str>) {
+    out.extend_from_slice(&[c; 7]);
+    if let Some(label) = label {
+        out.push(b' ');
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(b'\n');
+}
+
+fn write_conflict(
+    out: &mut Vec<u8>,
+    ours: &[&[u8]],
+    base: &[&[u8]],
+    theirs: &[&[u8]],
+    style: ConflictStyle,
+    labels: &Labels,
+) {
+    let (mut ours, mut theirs) = (ours, theirs);
+    let mut suffix: &[&[u8]] = &[];
+
+    if style == ConflictStyle::Merge {
+        // Move lines both sides agree on out of the conflict
+        let prefix = ours.iter().zip(theirs).take_while(|(a, b)| a == b).count();
+        emit(out, &ours[..prefix]);
+        ours = &ours[prefix..];
+        theirs = &theirs[prefix..];
+
+        let common = ours
+            .iter()
+            .rev()
+            .zip(theirs.iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        suffix = &ours[ours.len() - common..];
+        ours = &ours[..ours.len() - common];
+        theirs = &theirs[..theirs.len() - common];
+    }
+
+    marker(out, b'<', Some(labels.ours));
+    emit_terminated(out, ours);
+    if style == ConflictStyle::Diff3 {
+        marker(out, b'|', Some(labels.base));
+        emit_terminated(out, base);
+    }
+    marker(out, b'=', None);
+    emit_terminated(out, theirs);
+    marker(out, b'>', Some(labels.theirs));
+    emit(out, suffix);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merge_text(base: &str, ours: &str, theirs: &str, style: ConflictStyle) -> (String, usize) {
+        let result = merge(
+            base.as_bytes(),
+            ours.as_bytes(),
+            theirs.as_bytes(),
+            style,
+            &Labels::default(),
+        );
+        (String::from_utf8(result.data).unwrap(), result.conflicts)
+    }
+
+    #[test]
+    fn test_clean_merge() {
+        let (merged, conflicts) = merge_text(
+            "a\nb\nc\nd\n",
+            "A\nb\nc\nd\n",
+            "a\nb\nc\nD\n",
+            ConflictStyle::Merge,
+        );
+        assert_eq!(merged, "A\nb\nc\nD\n");
+        assert_eq!(conflicts, 0);
+
+        // Same change on both sides
+        let (merged, conflicts) = merge_text("a\n", "b\n", "b\n", ConflictStyle::Merge);
+        assert_eq!((merged.as_str(), conflicts), ("b\n", 0));
+    }
+
+    #[test]
+    fn test_conflict_merge_style() {
+        let (merged, conflicts) = merge_text(
+            "a\nb\nc\n",
+            "a\nours\nc\n",
+            "a\ntheirs\nc\n",
+            ConflictStyle::Merge,
+        );
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_conflict_diff3_style() {
+        let (merged, _) = merge_text(
+            "a\nb\nc\n",
+            "a\nours\nc\n",
+            "a\ntheirs\nc\n",
+            ConflictStyle::Diff3,
+        );
+        assert_eq!(
+            merged,
+            "a\n<<<<<<< ours\nours\n||||||| base\nb\n=======\ntheirs\n>>>>>>> theirs\nc\n"
+        );
+    }
+
+    #[test]
+    fn test_merge_style_trims_common_lines() {
+        let (merged, conflicts) = merge_text(
+            "x\n",
+            "same\nours\nend\n",
+            "same\ntheirs\nend\n",
+            ConflictStyle::Merge,
+        );
+        assert_eq!(conflicts, 1);
+        assert_eq!(
+            merged,
+            "same\n<<<<<<< ours\nours\n=======\ntheirs\n>>>>>>> theirs\nend\n"
+        );
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        let (merged, _) = merge_text("a", "b", "c", ConflictStyle::Merge);
+        assert_eq!(merged, "<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n");
+    }
+
+    #[test]
+    fn test_conflict_style_names() {
+        assert_eq!(
+            ConflictStyle::from_str("diff3").unwrap(),
+            ConflictStyle::Diff3
+        );
+        assert!(ConflictStyle::from_str("zdiff4").is_err());
+    }
+}
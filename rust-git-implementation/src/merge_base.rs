@@ -0,0 +1,220 @@
+//! Reachability between commits
+//!
+//! [`merge_bases`] finds the best common ancestors of two commits the way
+//! Git does: both histories are walked newest first, marking each commit
+//! with the side(s) it is reachable from, until every commit still queued
+//! is known to lie below a common ancestor. [`is_ancestor`] and
+//! [`reachable`] answer the simpler questions branch and tag filters ask.
+//!
+//! In a shallow clone every walk stops at the commits listed in
+//! `.git/shallow`, whose parents were never fetched;
+//! [`shallow_boundary`] tells which of them a walk reaches.
+
+use crate::repository::Repository;
+use anyhow::Result;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+/// Below a common ancestor already found
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+/// Find the best common ancestors of two commits
+///
+/// Usually there is one; criss-cross merges can have several, none of
+/// which is an ancestor of another. Unrelated histories have none.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `one` - A commit
+/// * `two` - Another commit
+pub fn merge_bases(repo: &Repository, one: &str, two: &str) -> Result<Vec<String>> {
+    if one == two {
+        return Ok(vec![one.to_string()]);
+    }
+
+    let mut flags: HashMap<String, u8> = HashMap::new();
+    let mut queue = BinaryHeap::new();
+    for (hash, flag) in [(one, PARENT1), (two, PARENT2)] {
+        flags.insert(hash.to_string(), flag);
+        queue.push((repo.read_commit(hash)?.committer.time, hash.to_string()));
+    }
+
+    let mut candidates = Vec::new();
+    while queue.iter().any(|(_, hash)| flags[hash] & STALE == 0) {
+        let Some((_, hash)) = queue.pop() else {
+            break;
+        };
+        let mut flag = flags[&hash] & !RESULT;
+        if flag & (PARENT1 | PARENT2) == PARENT1 | PARENT2 {
+            if flags[&hash] & RESULT == 0 {
+                *flags.get_mut(&hash).expect("queued commits have flags") |= RESULT;
+                candidates.push(hash.clone());
+            }
+            flag |= STALE;
+        }
+        for parent in &repo.read_commit(&hash)?.parents {
+            let parent_flags = flags.entry(parent.clone()).or_default();
+            if *parent_flags & flag == flag {
+                continue;
+            }
+            *parent_flags |= flag;
+            queue.push((repo.read_commit(parent)?.committer.time, parent.clone()));
+        }
+    }
+
+    // A candidate reachable from another candidate is not a best base
+    let mut bases = Vec::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        let mut redundant = false;
+        for (j, other) in candidates.iter().enumerate() {
+            if i != j && is_ancestor(repo, candidate, other)? {
+                redundant = true;
+                break;
+            }
+        }
+        if !redundant {
+            bases.push(candidate.clone());
+        }
+    }
+    Ok(bases)
+}
+
+/// Check whether `ancestor` is reachable from `descendant`
+///
+/// A commit counts as its own ancestor.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `ancestor` - The commit to look for
+/// * `descendant` - The commit to start walking from
+pub fn is_ancestor(repo: &Repository, ancestor: &str, descendant: &str) -> Result<bool> {
+    // Nothing older than the ancestor can lead back to it, give or take
+    // a day of clock skew
+    let cutoff = repo.read_commit(ancestor)?.committer.time - 86400;
+    let mut seen = HashSet::new();
+    let mut pending = vec![descendant.to_string()];
+    while let Some(hash) = pending.pop() {
+        if hash == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(hash.clone()) {
+            continue;
+        }
+        let commit = repo.read_commit(&hash)?;
+        if commit.committer.time < cutoff {
+            continue;
+        }
+        pending.extend(commit.parents.iter().cloned());
+    }
+    Ok(false)
+}
+
+/// Collect every commit reachable from the given tips, tips included
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `tips` - Commits to start from
+pub fn reachable(repo: &Repository, tips: &[&str]) -> Result<HashSet<String>> {
+    let mut seen = HashSet::new();
+    let mut pending: Vec<String> = tips.iter().map(|tip| tip.to_string()).collect();
+    while let Some(hash) = pending.pop() {
+        if seen.contains(&hash) {
+            continue;
+        }
+        pending.extend(repo.read_commit(&hash)?.parents.iter().cloned());
+        seen.insert(hash);
+    }
+    Ok(seen)
+}
+
+/// The shallow commits reachable from the given tips
+///
+/// These are where history ends because a shallow clone or fetch left
+/// out their parents; empty for a complete repository.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `tips` - Commits to start from
+pub fn shallow_boundary(repo: &Repository, tips: &[&str]) -> Result<BTreeSet<String>> {
+    let shallow = repo.shallow_commits()?;
+    if shallow.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+    Ok(reachable(repo, tips)?
+        .into_iter()
+        .filter(|hash| shallow.contains(hash))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_merge_bases_and_ancestry() {
+        // base - a1 ------- merge
+        //      \           /
+        //       b1 - b2 --
+        let mut builder = RepoBuilder::new().unwrap();
+        let base = builder.file("f", "base\n").commit("base").unwrap();
+        builder.branch("side").unwrap();
+        let a1 = builder.file("a", "a\n").commit("a1").unwrap();
+        builder.checkout("side").unwrap();
+        let b1 = builder.file("b", "1\n").commit("b1").unwrap();
+        let b2 = builder.file("b", "2\n").commit("b2").unwrap();
+        builder.checkout("main").unwrap();
+        let merge = builder.merge("side", "merge").unwrap();
+
+        let repo = builder.repo();
+        assert_eq!(merge_bases(repo, &a1, &b2).unwrap(), vec![base.clone()]);
+        assert_eq!(merge_bases(repo, &merge, &b1).unwrap(), vec![b1.clone()]);
+        assert_eq!(merge_bases(repo, &a1, &a1).unwrap(), vec![a1.clone()]);
+
+        assert!(is_ancestor(repo, &base, &merge).unwrap());
+        assert!(is_ancestor(repo, &b2, &merge).unwrap());
+        assert!(!is_ancestor(repo, &merge, &b2).unwrap());
+        assert!(!is_ancestor(repo, &a1, &b2).unwrap());
+
+        let from_side = reachable(repo, &[&b2]).unwrap();
+        assert_eq!(from_side.len(), 3);
+        assert!(from_side.contains(&base) && !from_side.contains(&a1));
+    }
+
+    #[test]
+    fn test_shallow_history() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("f", "1\n").commit("first").unwrap();
+        let second = builder.file("f", "2\n").commit("second").unwrap();
+        let third = builder.file("f", "3\n").commit("third").unwrap();
+
+        // As a clone with --depth=2 would leave it
+        std::fs::write(builder.path().join(".git/shallow"), format!("{}\n", second)).unwrap();
+        std::fs::remove_file(builder.repo().object_path(&first).unwrap()).unwrap();
+
+        let repo = Repository::new(builder.path()).unwrap();
+        assert!(repo.is_shallow().unwrap());
+        assert!(repo.read_commit(&second).unwrap().parents.is_empty());
+        assert_eq!(
+            reachable(&repo, &[&third]).unwrap(),
+            HashSet::from([second.clone(), third.clone()])
+        );
+        assert!(is_ancestor(&repo, &second, &third).unwrap());
+        assert!(!is_ancestor(&repo, &third, &second).unwrap());
+        assert_eq!(
+            merge_bases(&repo, &second, &third).unwrap(),
+            vec![second.clone()]
+        );
+        assert_eq!(
+            shallow_boundary(&repo, &[&third]).unwrap(),
+            BTreeSet::from([second])
+        );
+        assert!(shallow_boundary(&repo, &[]).unwrap().is_empty());
+    }
+}
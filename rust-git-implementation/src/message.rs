@@ -0,0 +1,82 @@
+//! Commit and tag message cleanup
+//!
+//! Implements Git's `stripspace` rules, used whenever a message comes from
+//! an editor or a file: trailing whitespace is removed, runs of blank lines
+//! are collapsed, and leading and trailing blank lines are dropped.
+
+/// Clean up a message
+///
+/// Every line loses its trailing whitespace, consecutive blank lines are
+/// collapsed into one, blank lines at the start and end are removed, and
+/// the last line ends with a newline. An empty result stays empty.
+///
+/// # Arguments
+///
+/// * `text` - The message to clean up
+/// * `comment` - If set, lines starting with this prefix are removed
+pub fn stripspace(text: &str, comment: Option<&str>) -> String {
+    let mut out = String::new();
+    let mut blank_pending = false;
+    for line in text.lines() {
+        if comment.is_some_and(|prefix| line.starts_with(prefix)) {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_pending = !out.is_empty();
+            continue;
+        }
+        if blank_pending {
+            out.push('\n');
+            blank_pending = false;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Turn every line into a comment
+///
+/// Non-empty lines get the prefix and a space, empty lines just the prefix,
+/// as Git does when showing text in the commit message template.
+///
+/// # Arguments
+///
+/// * `text` - The text to comment out
+/// * `comment` - The comment prefix, usually `#` (`core.commentChar`)
+pub fn comment_lines(text: &str, comment: &str) -> String {
+    let mut out = String::new();
+    for line in text.lines() {
+        out.push_str(comment);
+        if !line.is_empty() {
+            out.push(' ');
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stripspace() {
+        assert_eq!(
+            stripspace("\n\n  \nsubject  \n\n\n\nbody\t\n# comment\n\n", None),
+            "subject\n\nbody\n# comment\n"
+        );
+        assert_eq!(
+            stripspace("subject\n# comment\n\n# more\nbody", Some("#")),
+            "subject\n\nbody\n"
+        );
+        assert_eq!(stripspace(" \n\n", None), "");
+    }
+
+    #[test]
+    fn test_comment_lines() {
+        assert_eq!(comment_lines("a\n\nb", "#"), "# a\n#\n# b\n");
+    }
+}
@@ -0,0 +1,255 @@
+//! Streaming reads and writes of loose objects
+//!
+//! [`Repository::read_object`] and [`Repository::write_object`] hold a whole
+//! object in memory, which doesn't work for multi-gigabyte files. An
+//! [`ObjectReader`] inflates an object's content as it is read, and an
+//! [`ObjectWriter`] deflates content as it is written; both hash on the
+//! way, so neither ever holds more than a buffer.
+//!
+//! Get them with [`Repository::read_object_stream`] and
+//! [`Repository::write_object_stream`].
+
+use crate::objects::{parse_header, ObjectType};
+use crate::odb::read_header;
+use crate::repository::Repository;
+use crate::utils::ObjectHasher;
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Reads the content of a stored object
+///
+/// The content is checked against the object's name as it is read: the
+/// read that reaches the end fails if the object is corrupt.
+pub struct ObjectReader<'a> {
+    hash: String,
+    kind: ObjectType,
+    size: u64,
+    remaining: u64,
+    decoder: Box<dyn Read + 'a>,
+    /// Taken once the content has been verified
+    hasher: Option<ObjectHasher>,
+}
+
+impl<'a> ObjectReader<'a> {
+    pub(crate) fn open(repo: &'a Repository, hash: &str) -> Result<Self> {
+        let mut decoder = repo.odb().read_stream(hash)?;
+        let header =
+            read_header(&mut decoder).with_context(|| format!("Corrupt object {}", hash))?;
+        let (kind, size, _) =
+            parse_header(&header).with_context(|| format!("Corrupt object {}", hash))?;
+        let size = size as u64;
+        Ok(ObjectReader {
+            hash: hash.to_string(),
+            kind,
+            size,
+            remaining: size,
+            decoder,
+            hasher: Some(ObjectHasher::new(kind, size)),
+        })
+    }
+
+    pub fn kind(&self) -> ObjectType {
+        self.kind
+    }
+
+    /// Content size announced in the object header
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Check the end of the stream and the hash, once
+    fn verify(&mut self) -> io::Result<()> {
+        let Some(hasher) = self.hasher.take() else {
+            return Ok(());
+        };
+        let corrupt = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if self.decoder.read(&mut [0u8])? != 0 {
+            return Err(corrupt(format!(
+                "object {} is longer than its header says",
+                self.hash
+            )));
+        }
+        let actual = hasher
+            .finish()
+            .map_err(|e| corrupt(format!("object {}: {}", self.hash, e)))?;
+        if actual != self.hash {
+            return Err(corrupt(format!(
+                "object {} hashes to {}",
+                self.hash, actual
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Read for ObjectReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            self.verify()?;
+            return Ok(0);
+        }
+        let wanted = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        let n = self.decoder.read(&mut buf[..wanted])?;
+        if n == 0 && wanted > 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("object {} ends {} bytes early", self.hash, self.remaining),
+            ));
+        }
+        self.remaining -= n as u64;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+/// Writes a new object
+///
+/// The content goes to a temporary file in the object directory, which
+/// [`ObjectWriter::finish`] hands to the repository's object storage
+/// under the object's hash (for loose objects, it is moved into place).
+/// Dropping the writer without finishing discards it.
+pub struct ObjectWriter<'a> {
+    repo: &'a Repository,
+    /// Taken by [`ObjectWriter::finish`]
+    state: Option<(ZlibEncoder<File>, ObjectHasher)>,
+    temp: PathBuf,
+}
+
+impl<'a> ObjectWriter<'a> {
+    pub(crate) fn create(repo: &'a Repository, kind: ObjectType, size: u64) -> Result<Self> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let temp = repo.common_dir.join("objects").join(format!(
+            "tmp_obj_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file =
+            File::create(&temp).with_context(|| format!("Failed to create {}", temp.display()))?;
+        let mut encoder = ZlibEncoder::new(file, Compression::default());
+        encoder.write_all(format!("{} {}\0", kind.as_str(), size).as_bytes())?;
+        Ok(ObjectWriter {
+            repo,
+            state: Some((encoder, ObjectHasher::new(kind, size))),
+            temp,
+        })
+    }
+
+    /// Store the object and return its hash
+    ///
+    /// # Errors
+    ///
+    /// Fails if the content written doesn't have the size given when the
+    /// writer was created.
+    pub fn finish(mut self) -> Result<String> {
+        let (encoder, hasher) = self.state.take().expect("finish takes the writer");
+        let hash = hasher.finish()?;
+        encoder.finish()?;
+
+        if !self.repo.has_object(&hash) {
+            self.repo.odb().import(&hash, &self.temp)?;
+        }
+        tracing::debug!(hash, "wrote object stream");
+        Ok(hash)
+    }
+}
+
+impl Write for ObjectWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (encoder, hasher) = self.state.as_mut().expect("not finished");
+        let n = encoder.write(buf)?;
+        hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.as_mut().expect("not finished").0.flush()
+    }
+}
+
+impl Drop for ObjectWriter<'_> {
+    fn drop(&mut self) {
+        // After a successful finish the file has usually been moved away
+        let _ = fs::remove_file(&self.temp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Blob, GitObject};
+
+    #[test]
+    fn test_stream_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let content: Vec<u8> = (0..200_000u32).flat_map(|i| i.to_le_bytes()).collect();
+
+        let mut writer = repo
+            .write_object_stream(ObjectType::Blob, content.len() as u64)
+            .unwrap();
+        for chunk in content.chunks(4096) {
+            writer.write_all(chunk).unwrap();
+        }
+        let hash = writer.finish().unwrap();
+        assert_eq!(hash, Blob::new(content.clone()).hash().unwrap());
+        // Same bytes as the buffered API
+        assert_eq!(
+            repo.read_object(&hash).unwrap(),
+            Blob::new(content.clone()).to_bytes().unwrap()
+        );
+
+        let mut reader = repo.read_object_stream(&hash).unwrap();
+        assert_eq!(reader.kind(), ObjectType::Blob);
+        assert_eq!(reader.size(), content.len() as u64);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, content);
+
+        // No temporary files are left behind
+        let leftovers = fs::read_dir(repo.git_dir.join("objects"))
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("tmp_obj_")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[test]
+    fn test_stream_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let mut writer = repo.write_object_stream(ObjectType::Blob, 10).unwrap();
+        writer.write_all(b"short").unwrap();
+        assert!(writer.finish().is_err());
+
+        // An object stored under the wrong name fails at the end of the read
+        let bogus = "0".repeat(40);
+        repo.write_object(&bogus, b"blob 5\0hello").unwrap();
+        let mut reader = repo.read_object_stream(&bogus).unwrap();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let bogus = "2".repeat(40);
+        repo.write_object(&bogus, b"blob 9\0hello").unwrap();
+        let mut reader = repo.read_object_stream(&bogus).unwrap();
+        let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        assert!(repo.read_object_stream(&"1".repeat(40)).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+//! Blob objects: file content
+
+use super::object::{parse_object, GitObject, ObjectType};
+use anyhow::Result;
+
+/// The content of a file, without name or mode
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    /// Create a blob from file content
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        Blob { data: data.into() }
+    }
+
+    /// Parse a stored object (`blob <size>\0<content>`)
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - Decompressed object data, as returned by
+    ///   [`Repository::read_object`](crate::repository::Repository::read_object)
+    pub fn parse(object: &[u8]) -> Result<Self> {
+        match parse_object(object)? {
+            (ObjectType::Blob, data) => Ok(Blob::new(data)),
+            (other, _) => anyhow::bail!("Not a blob: {}", other.as_str()),
+        }
+    }
+}
+
+impl GitObject for Blob {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Blob
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        Ok(self.data.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blob_hash_matches_git() {
+        let blob = Blob::new(&b"Hello World\n"[..]);
+        assert_eq!(
+            blob.hash().unwrap(),
+            "557db03de997c86a4a028e1ebd3a1ceb225be238"
+        );
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let blob = Blob::new(&b"content"[..]);
+        assert_eq!(Blob::parse(&blob.to_bytes().unwrap()).unwrap(), blob);
+
+        assert!(Blob::parse(b"tree 0\0").is_err());
+        assert!(Blob::parse(b"blob 3\0ab").is_err());
+        assert!(Blob::parse(b"blob 3").is_err());
+    }
+}
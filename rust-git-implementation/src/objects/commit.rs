@@ -0,0 +1,622 @@
+//! Commit objects: a tree with history and authorship
+//!
+//! [`CommitRef`] and [`SignatureRef`] borrow from the object data, for
+//! walks that look at many commits; [`Commit`] and [`Signature`] own their
+//! fields and are what gets built and serialized.
+
+use super::object::{parse_object, GitObject, ObjectType};
+use crate::date::parse_offset;
+use crate::encoding;
+use anyhow::{Context, Result};
+use std::fmt;
+
+/// Who made a change and when
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    /// Timezone offset in minutes east of UTC
+    pub offset: i32,
+}
+
+impl Signature {
+    /// Create a signature for a given time
+    pub fn new(name: impl Into<String>, email: impl Into<String>, time: i64, offset: i32) -> Self {
+        Signature {
+            name: name.into(),
+            email: email.into(),
+            time,
+            offset,
+        }
+    }
+
+    /// Create a signature for the current time in the local timezone
+    pub fn now(name: impl Into<String>, email: impl Into<String>) -> Self {
+        let now = chrono::Local::now();
+        Self::new(
+            name,
+            email,
+            now.timestamp(),
+            now.offset().local_minus_utc() / 60,
+        )
+    }
+
+    /// Parse an identity line (`Name <email> 1700000000 +0100`)
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - Header value of an `author`, `committer` or `tagger` line
+    pub fn parse(line: &str) -> Result<Self> {
+        SignatureRef::parse(line).map(SignatureRef::into_owned)
+    }
+}
+
+/// A [`Signature`] borrowing its name and email
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureRef<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    /// Timezone offset in minutes east of UTC
+    pub offset: i32,
+}
+
+impl<'a> SignatureRef<'a> {
+    /// Parse an identity line (`Name <email> 1700000000 +0100`)
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - Header value of an `author`, `committer` or `tagger` line
+    pub fn parse(line: &'a str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid identity: {}", line);
+        let open = line.find('<').ok_or_else(invalid)?;
+        let close = open + line[open..].find('>').ok_or_else(invalid)?;
+
+        let mut date = line[close + 1..].split_whitespace();
+        let time = date
+            .next()
+            .and_then(|t| t.parse().ok())
+            .ok_or_else(invalid)?;
+        let offset = date.next().and_then(parse_offset).ok_or_else(invalid)?;
+        Ok(SignatureRef {
+            name: line[..open].trim_end(),
+            email: &line[open + 1..close],
+            time,
+            offset,
+        })
+    }
+
+    /// Copy into an owned [`Signature`]
+    pub fn into_owned(self) -> Signature {
+        Signature::new(self.name, self.email, self.time, self.offset)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.offset < 0 { '-' } else { '+' };
+        let offset = self.offset.abs();
+        write!(
+            f,
+            "{} <{}> {} {}{:02}{:02}",
+            self.name,
+            self.email,
+            self.time,
+            sign,
+            offset / 60,
+            offset % 60
+        )
+    }
+}
+
+/// A commit: a tree, its parent commits, and who made it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commit {
+    /// Hash of the root tree
+    pub tree: String,
+    /// Hashes of the parent commits, in order
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub committer: Signature,
+    /// The message, including its trailing newline
+    pub message: String,
+    /// Other headers (`encoding`, `mergetag`, ...), in order, written after
+    /// `committer`; multi-line values have their continuation spaces
+    /// removed
+    pub extra_headers: Vec<(String, String)>,
+    /// ASCII-armored signature over the rest of the commit, stored in the
+    /// `gpgsig` header
+    pub gpgsig: Option<String>,
+}
+
+impl Commit {
+    /// Parse a stored object (`commit <size>\0<content>`)
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - Decompressed object data, as returned by
+    ///   [`Repository::read_object`](crate::repository::Repository::read_object)
+    pub fn parse(object: &[u8]) -> Result<Self> {
+        match parse_object(object)? {
+            (ObjectType::Commit, content) => Self::parse_content(content),
+            (other, _) => anyhow::bail!("Not a commit: {}", other.as_str()),
+        }
+    }
+
+    /// Parse commit content without the object header
+    ///
+    /// Text stored in another encoding, as named by the `encoding` header,
+    /// is decoded; serializing encodes it again. Commits that claim no
+    /// encoding but aren't UTF-8 get U+FFFD for the invalid bytes.
+    pub fn parse_content(content: &[u8]) -> Result<Self> {
+        let text = match stored_encoding(content) {
+            Some(label) if !encoding::is_utf8(label) => encoding::decode(content, label)?,
+            _ => match std::str::from_utf8(content) {
+                Ok(_) => return CommitRef::parse(content).map(CommitRef::into_owned),
+                Err(_) => encoding::decode(content, encoding::DEFAULT)?,
+            },
+        };
+        CommitRef::parse(text.as_bytes()).map(CommitRef::into_owned)
+    }
+
+    /// The encoding the commit is stored in, from its `encoding` header
+    pub fn encoding(&self) -> &str {
+        self.extra_header("encoding").unwrap_or(encoding::DEFAULT)
+    }
+
+    /// The message converted for display
+    ///
+    /// # Arguments
+    ///
+    /// * `label` - The output encoding, usually
+    ///   [`log_output_encoding`](crate::encoding::log_output_encoding)
+    pub fn message_in(&self, label: &str) -> Result<Vec<u8>> {
+        if encoding::is_utf8(label) {
+            Ok(self.message.clone().into_bytes())
+        } else {
+            encoding::encode(&self.message, label)
+        }
+    }
+
+    /// The value of the first extra header called `key`
+    pub fn extra_header(&self, key: &str) -> Option<&str> {
+        self.extra_headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The bytes a signature covers: the commit content without its
+    /// `gpgsig` and `gpgsig-sha256` headers
+    pub fn signing_payload(&self) -> Result<Vec<u8>> {
+        let mut out = self.headers(false);
+        out.push('\n');
+        out.push_str(&self.message);
+        self.encode(out)
+    }
+
+    /// Sign the commit, replacing any existing signature
+    ///
+    /// The hash changes, so sign before writing the commit.
+    ///
+    /// # Arguments
+    ///
+    /// * `signer` - Produces the signature over [`Commit::signing_payload`]
+    pub fn sign(&mut self, signer: &dyn crate::gpg::Signer) -> Result<()> {
+        self.gpgsig = Some(signer.sign(&self.signing_payload()?)?);
+        Ok(())
+    }
+
+    /// The header lines other than `gpgsig`
+    ///
+    /// # Arguments
+    ///
+    /// * `signatures` - Whether to include `gpgsig-sha256`
+    fn headers(&self, signatures: bool) -> String {
+        let mut out = format!("tree {}\n", self.tree);
+        for parent in &self.parents {
+            out.push_str(&format!("parent {}\n", parent));
+        }
+        out.push_str(&format!("author {}\n", self.author));
+        out.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in &self.extra_headers {
+            if signatures || key != "gpgsig-sha256" {
+                out.push_str(&format!("{} {}\n", key, fold(value)));
+            }
+        }
+        out
+    }
+
+    /// Store text in the commit's encoding
+    fn encode(&self, text: String) -> Result<Vec<u8>> {
+        let label = self.encoding();
+        if encoding::is_utf8(label) {
+            Ok(text.into_bytes())
+        } else {
+            encoding::encode(&text, label)
+                .with_context(|| format!("Cannot store commit as {}", label))
+        }
+    }
+}
+
+/// The `encoding` header of undecoded commit content
+fn stored_encoding(content: &[u8]) -> Option<&str> {
+    content
+        .split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty())
+        .find_map(|line| line.strip_prefix(b"encoding "))
+        .and_then(|value| std::str::from_utf8(value).ok())
+}
+
+/// A [`Commit`] borrowing from the object data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitRef<'a> {
+    /// Hash of the root tree
+    pub tree: &'a str,
+    /// Hashes of the parent commits, in order
+    pub parents: Vec<&'a str>,
+    pub author: SignatureRef<'a>,
+    pub committer: SignatureRef<'a>,
+    /// The message, including its trailing newline
+    pub message: &'a str,
+    /// Other headers, in order, with values as stored
+    pub extra_headers: Vec<(&'a str, &'a str)>,
+    /// The `gpgsig` header as stored: lines after the first still start
+    /// with the space that continues the header
+    pub gpgsig: Option<&'a str>,
+}
+
+impl<'a> CommitRef<'a> {
+    /// Parse commit content without the object header
+    ///
+    /// Headers other than the ones with a field of their own go to
+    /// `extra_headers`. The content must be UTF-8; use
+    /// [`Commit::parse_content`] for commits in other encodings. The
+    /// `tree` and `parent` values must be full object ids.
+    pub fn parse(content: &'a [u8]) -> Result<Self> {
+        let text = std::str::from_utf8(content).context("Commit is not valid UTF-8")?;
+        let (headers, message) = text.split_once("\n\n").unwrap_or((text, ""));
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut gpgsig = None;
+        let mut extra_headers = Vec::new();
+        for (key, value) in header_fields(headers) {
+            if matches!(key, "tree" | "parent") && !crate::utils::is_object_id(value.as_bytes()) {
+                anyhow::bail!("Commit has invalid {} '{}'", key, value);
+            }
+            match key {
+                "tree" => tree = Some(value),
+                "parent" => parents.push(value),
+                "author" => author = Some(SignatureRef::parse(value)?),
+                "committer" => committer = Some(SignatureRef::parse(value)?),
+                "gpgsig" => gpgsig = Some(value),
+                _ => extra_headers.push((key, value)),
+            }
+        }
+
+        Ok(CommitRef {
+            tree: tree.context("Commit has no tree")?,
+            parents,
+            author: author.context("Commit has no author")?,
+            committer: committer.context("Commit has no committer")?,
+            message,
+            extra_headers,
+            gpgsig,
+        })
+    }
+
+    /// The signature with the header's continuation spaces removed
+    pub fn signature(&self) -> Option<String> {
+        self.gpgsig.map(|value| format!("{}\n", unfold(value)))
+    }
+
+    /// Copy into an owned [`Commit`]
+    pub fn into_owned(self) -> Commit {
+        Commit {
+            tree: self.tree.to_string(),
+            parents: self.parents.into_iter().map(str::to_string).collect(),
+            author: self.author.into_owned(),
+            committer: self.committer.into_owned(),
+            message: self.message.to_string(),
+            extra_headers: self
+                .extra_headers
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), unfold(value)))
+                .collect(),
+            gpgsig: self.gpgsig.map(|value| format!("{}\n", unfold(value))),
+        }
+    }
+}
+
+/// Split header lines into keys and values
+///
+/// A line starting with a space continues the header before it, so a
+/// value can span several lines, e.g. a `gpgsig` signature block.
+fn header_fields(headers: &str) -> Vec<(&str, &str)> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    let line_end = |from: usize| {
+        headers[from..]
+            .find('\n')
+            .map_or(headers.len(), |i| from + i)
+    };
+    while start < headers.len() {
+        let mut end = line_end(start);
+        while headers[end..].starts_with("\n ") {
+            end = line_end(end + 1);
+        }
+        let field = &headers[start..end];
+        fields.push(field.split_once(' ').unwrap_or((field, "")));
+        start = end + 1;
+    }
+    fields
+}
+
+/// Turn a multi-line header value back into its lines
+fn unfold(value: &str) -> String {
+    value.replace("\n ", "\n")
+}
+
+/// Prefix each line of a header value after the first with a space
+fn fold(value: &str) -> String {
+    value.replace('\n', "\n ")
+}
+
+impl GitObject for Commit {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Commit
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut out = self.headers(true);
+        if let Some(signature) = &self.gpgsig {
+            // Continuation lines start with a space
+            let signature = signature.strip_suffix('\n').unwrap_or(signature);
+            out.push_str(&format!("gpgsig {}\n", fold(signature)));
+        }
+        out.push('\n');
+        out.push_str(&self.message);
+        self.encode(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Commit {
+        let sig = Signature::new("A U Thor", "author@example.com", 1112911993, -420);
+        Commit {
+            tree: "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+            parents: Vec::new(),
+            author: sig.clone(),
+            committer: sig,
+            message: "initial\n".to_string(),
+            extra_headers: Vec::new(),
+            gpgsig: None,
+        }
+    }
+
+    #[test]
+    fn test_commit_hash_matches_git() {
+        // git commit-tree with fixed GIT_AUTHOR_* / GIT_COMMITTER_* values
+        assert_eq!(
+            sample().hash().unwrap(),
+            "551742545a1086034ca8929263580794c5a96a33"
+        );
+    }
+
+    #[test]
+    fn test_parse_roundtrip() {
+        let mut commit = sample();
+        commit.parents = vec!["a".repeat(40), "b".repeat(40)];
+        assert_eq!(Commit::parse(&commit.to_bytes().unwrap()).unwrap(), commit);
+        assert!(Commit::parse(b"blob 0\0").is_err());
+        assert!(Commit::parse_content(b"tree abc\n\nno author\n").is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        let sig = Signature::parse("Jane Doe <jane@example.com> 1700000000 +0530").unwrap();
+        assert_eq!(sig.name, "Jane Doe");
+        assert_eq!(sig.offset, 330);
+        assert_eq!(
+            sig.to_string(),
+            "Jane Doe <jane@example.com> 1700000000 +0530"
+        );
+        assert!(Signature::parse("Jane <jane> 1700000000 0530").is_err());
+        assert!(Signature::parse("Jane jane 1700000000 +0530").is_err());
+    }
+
+    #[test]
+    fn test_signing() {
+        struct Fixed;
+        impl crate::gpg::Signer for Fixed {
+            fn sign(&self, payload: &[u8]) -> Result<String> {
+                assert!(!payload.windows(6).any(|w| w == b"gpgsig"));
+                Ok("-----BEGIN PGP SIGNATURE-----\n\nabc=\n-----END PGP SIGNATURE-----\n".into())
+            }
+        }
+
+        let mut commit = sample();
+        let unsigned = commit.serialize().unwrap();
+        assert_eq!(commit.signing_payload().unwrap(), unsigned);
+        commit.sign(&Fixed).unwrap();
+        assert_eq!(commit.signing_payload().unwrap(), unsigned);
+        // Laid out as `git commit -S` does
+        assert_eq!(
+            String::from_utf8(commit.serialize().unwrap()).unwrap(),
+            "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+             author A U Thor <author@example.com> 1112911993 -0700\n\
+             committer A U Thor <author@example.com> 1112911993 -0700\n\
+             gpgsig -----BEGIN PGP SIGNATURE-----\n \n abc=\n -----END PGP SIGNATURE-----\n\
+             \n\
+             initial\n"
+        );
+        commit.sign(&Fixed).unwrap();
+        assert_eq!(commit.signing_payload().unwrap(), unsigned);
+    }
+
+    #[test]
+    fn test_signed_commit_roundtrip() {
+        // Made by `git commit-tree -S` (with a stand-in for gpg)
+        let content = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author A U Thor <author@example.com> 1112911993 -0700\n\
+            committer A U Thor <author@example.com> 1112911993 -0700\n\
+            gpgsig -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n =abcd\n \
+            -----END PGP SIGNATURE-----\n\
+            \n\
+            initial\n";
+        let parsed = CommitRef::parse(content.as_bytes()).unwrap();
+        assert_eq!(
+            parsed.gpgsig,
+            Some("-----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n =abcd\n -----END PGP SIGNATURE-----")
+        );
+        assert_eq!(parsed.message, "initial\n");
+
+        let commit = parsed.into_owned();
+        assert_eq!(
+            commit.gpgsig.as_deref(),
+            Some("-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n=abcd\n-----END PGP SIGNATURE-----\n")
+        );
+        assert_eq!(commit.serialize().unwrap(), content.as_bytes());
+        assert_eq!(
+            commit.hash().unwrap(),
+            "17011b70fbdd5349a88657133d0f8add7afb6536"
+        );
+        // The payload is what gpg was given
+        assert!(!String::from_utf8(commit.signing_payload().unwrap())
+            .unwrap()
+            .contains("PGP"));
+    }
+
+    #[test]
+    fn test_extra_headers_roundtrip() {
+        // `git merge` of a signed tag, with i18n.commitEncoding set
+        let content = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            parent a0796e770ad1b064ec586d1d3b5f696187a07efe\n\
+            parent d193b57fd0f09dba38972e105b89beec0d5db594\n\
+            author A U Thor <author@example.com> 1112911993 -0700\n\
+            committer A U Thor <author@example.com> 1112911993 -0700\n\
+            encoding ISO-8859-1\n\
+            mergetag object d193b57fd0f09dba38972e105b89beec0d5db594\n \
+            type commit\n tag v1\n \
+            tagger A U Thor <author@example.com> 1112911993 -0700\n \n release\n \
+            -----BEGIN PGP SIGNATURE-----\n \n iQEzBAABCAAdFiEE\n =abcd\n \
+            -----END PGP SIGNATURE-----\n\
+            \n\
+            Merge tag 'v1'\n\nrelease\n\n# -----BEGIN PGP SIGNATURE-----\n#\n\
+            # iQEzBAABCAAdFiEE\n# =abcd\n# -----END PGP SIGNATURE-----\n\
+            # gpg: CRC error; 622D48 - 69B71D\n\
+            # gpg: premature eof while reading hashed signature data\n\
+            # gpg: verify signatures failed: Unknown system error\n";
+        let commit = Commit::parse_content(content.as_bytes()).unwrap();
+        assert_eq!(commit.extra_header("encoding"), Some("ISO-8859-1"));
+        let mergetag = commit.extra_header("mergetag").unwrap();
+        assert!(
+            mergetag.starts_with("object d193b57fd0f09dba38972e105b89beec0d5db594\ntype commit\n")
+        );
+        assert!(mergetag.ends_with("\n\nrelease\n-----BEGIN PGP SIGNATURE-----\n\niQEzBAABCAAdFiEE\n=abcd\n-----END PGP SIGNATURE-----"));
+
+        assert_eq!(commit.serialize().unwrap(), content.as_bytes());
+        assert_eq!(
+            commit.hash().unwrap(),
+            "6b7c8c9f141beb5db9d8366b983d11c3fa960089"
+        );
+        // Signatures of the mergetag are part of what the commit signs
+        assert_eq!(commit.signing_payload().unwrap(), content.as_bytes());
+
+        let mut commit = sample();
+        commit.extra_headers = vec![
+            ("gpgsig-sha256".to_string(), "sig\nnext".to_string()),
+            ("x-future".to_string(), "value".to_string()),
+        ];
+        let bytes = commit.serialize().unwrap();
+        assert!(String::from_utf8(bytes.clone())
+            .unwrap()
+            .ends_with("-0700\ngpgsig-sha256 sig\n next\nx-future value\n\ninitial\n"));
+        assert!(!String::from_utf8(commit.signing_payload().unwrap())
+            .unwrap()
+            .contains("gpgsig-sha256"));
+        assert_eq!(Commit::parse_content(&bytes).unwrap(), commit);
+    }
+
+    #[test]
+    fn test_encoding() {
+        // `git commit-tree` with i18n.commitEncoding = ISO-8859-1
+        let content = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author Ren\xe9 <a@example.com> 1112911993 -0700\n\
+            committer Ren\xe9 <a@example.com> 1112911993 -0700\n\
+            encoding ISO-8859-1\n\
+            \n\
+            caf\xe9\n";
+        let commit = Commit::parse_content(content).unwrap();
+        assert_eq!(commit.author.name, "Ren\u{e9}");
+        assert_eq!(commit.message, "caf\u{e9}\n");
+        assert_eq!(commit.encoding(), "ISO-8859-1");
+        assert_eq!(commit.serialize().unwrap(), content);
+        assert_eq!(
+            commit.hash().unwrap(),
+            "88d25fd41eef51b4ce56df20ff67045ec73dd506"
+        );
+        assert_eq!(
+            commit.message_in("UTF-8").unwrap(),
+            "caf\u{e9}\n".as_bytes()
+        );
+        assert_eq!(commit.message_in("latin1").unwrap(), b"caf\xe9\n");
+
+        // Text the encoding can't hold isn't silently mangled
+        let mut japanese = commit.clone();
+        japanese.message = "\u{65e5}\n".to_string();
+        assert!(japanese.serialize().is_err());
+
+        // Without an encoding header, invalid UTF-8 is still readable
+        let unlabeled = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            author Ren\xe9 <a@example.com> 1112911993 -0700\n\
+            committer Ren\xe9 <a@example.com> 1112911993 -0700\n\
+            \n\
+            caf\xe9\n";
+        let commit = Commit::parse_content(unlabeled).unwrap();
+        assert_eq!(commit.message, "caf\u{fffd}\n");
+        assert!(CommitRef::parse(unlabeled).is_err());
+    }
+
+    #[test]
+    fn test_commit_ref_borrows() {
+        let mut commit = sample();
+        commit.parents = vec!["a".repeat(40)];
+        let content = commit.serialize().unwrap();
+        let parsed = CommitRef::parse(&content).unwrap();
+        assert_eq!(parsed.parents, vec!["a".repeat(40)]);
+        assert_eq!(parsed.author.name, "A U Thor");
+        assert_eq!(parsed.message, "initial\n");
+
+        // The borrowed fields point into the object data
+        let range = content.as_ptr_range();
+        assert!(range.contains(&parsed.tree.as_ptr()));
+        assert!(range.contains(&parsed.author.email.as_ptr()));
+        assert_eq!(parsed.into_owned(), commit);
+    }
+
+    #[test]
+    fn test_commit_ref_rejects_bad_ids() {
+        let content = sample().serialize().unwrap();
+        assert!(CommitRef::parse(&content).is_ok());
+        for (from, to) in [
+            ("tree 4b825d", "tree 4B825D"),
+            ("tree 4b825d", "tree ../../"),
+            ("author", "parent x\nauthor"),
+        ] {
+            let content = String::from_utf8(content.clone())
+                .unwrap()
+                .replace(from, to);
+            assert!(CommitRef::parse(content.as_bytes()).is_err(), "{}", content);
+        }
+    }
+}
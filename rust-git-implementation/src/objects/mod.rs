@@ -6,13 +6,14 @@
 //! - Commit: Snapshot with metadata
 //! - Tag: Named reference to a commit
 
+pub mod blob;
+pub mod commit;
+pub mod mode;
 pub mod object;
-// Uncomment as you implement each type
-// pub mod blob;
-// pub mod tree;
-// pub mod commit;
+pub mod tree;
 
-pub use object::{GitObject, ObjectType};
-// pub use blob::Blob;
-// pub use tree::Tree;
-// pub use commit::Commit;
+pub use blob::Blob;
+pub use commit::{Commit, CommitRef, Signature, SignatureRef};
+pub use mode::FileMode;
+pub use object::{parse_header, parse_object, GitObject, ObjectType};
+pub use tree::{Tree, TreeEntry, TreeEntryRef, TreeRef};
@@ -0,0 +1,99 @@
+//! File modes of tree and index entries
+
+use anyhow::Result;
+
+/// The kind of a tree or index entry, encoded by Git as an octal mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FileMode {
+    /// Regular file (`100644`)
+    Regular,
+    /// Executable file (`100755`)
+    Executable,
+    /// Symbolic link (`120000`); the blob holds the link target
+    Symlink,
+    /// Subdirectory (`40000`)
+    Tree,
+    /// Submodule commit (`160000`)
+    Gitlink,
+}
+
+impl FileMode {
+    /// Convert to the octal string used in tree objects
+    pub fn as_str(&self) -> &str {
+        match self {
+            FileMode::Regular => "100644",
+            FileMode::Executable => "100755",
+            FileMode::Symlink => "120000",
+            FileMode::Tree => "40000",
+            FileMode::Gitlink => "160000",
+        }
+    }
+
+    /// Convert to the numeric mode stored in the index
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            FileMode::Regular => 0o100644,
+            FileMode::Executable => 0o100755,
+            FileMode::Symlink => 0o120000,
+            FileMode::Tree => 0o40000,
+            FileMode::Gitlink => 0o160000,
+        }
+    }
+
+    /// Parse a numeric mode
+    ///
+    /// Like Git, the group-writable `100664` that very old versions wrote
+    /// is read as a regular file; `fsck` is what reports it.
+    pub fn from_u32(mode: u32) -> Result<Self> {
+        match mode {
+            0o100644 | 0o100664 => Ok(FileMode::Regular),
+            0o100755 => Ok(FileMode::Executable),
+            0o120000 => Ok(FileMode::Symlink),
+            0o40000 => Ok(FileMode::Tree),
+            0o160000 => Ok(FileMode::Gitlink),
+            _ => anyhow::bail!("Invalid file mode: {:o}", mode),
+        }
+    }
+
+    /// Parse an octal mode string, e.g. `100644`
+    pub fn from_bytes(mode: &[u8]) -> Result<Self> {
+        let text = std::str::from_utf8(mode)?;
+        let value = u32::from_str_radix(text, 8)
+            .map_err(|_| anyhow::anyhow!("Invalid file mode: {}", text))?;
+        Self::from_u32(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_roundtrip() {
+        for mode in [
+            FileMode::Regular,
+            FileMode::Executable,
+            FileMode::Symlink,
+            FileMode::Tree,
+            FileMode::Gitlink,
+        ] {
+            assert_eq!(
+                FileMode::from_bytes(mode.as_str().as_bytes()).unwrap(),
+                mode
+            );
+            assert_eq!(FileMode::from_u32(mode.as_u32()).unwrap(), mode);
+        }
+    }
+
+    #[test]
+    fn test_legacy_mode() {
+        assert_eq!(FileMode::from_u32(0o100664).unwrap(), FileMode::Regular);
+        assert_eq!(FileMode::from_bytes(b"100664").unwrap().as_str(), "100644");
+    }
+
+    #[test]
+    fn test_invalid_mode() {
+        assert!(FileMode::from_bytes(b"100666").is_err());
+        assert!(FileMode::from_bytes(b"abc").is_err());
+    }
+}
@@ -23,6 +23,7 @@ impl ObjectType {
     }
 
     /// Parse object type from string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self> {
         match s {
             "blob" => Ok(ObjectType::Blob),
@@ -34,6 +35,48 @@ impl ObjectType {
     }
 }
 
+/// Parse the `<type> <size>\0` header at the start of a stored object
+///
+/// Returns the type, the content size the header announces, and the
+/// length of the header including the NUL.
+///
+/// # Arguments
+///
+/// * `data` - Decompressed object data; only the header needs to be present
+pub fn parse_header(data: &[u8]) -> Result<(ObjectType, usize, usize)> {
+    let nul = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| anyhow::anyhow!("Object header is not terminated"))?;
+    let header = std::str::from_utf8(&data[..nul])?;
+    let (kind, size) = header
+        .split_once(' ')
+        .ok_or_else(|| anyhow::anyhow!("Invalid object header: {}", header))?;
+    let kind = ObjectType::from_str(kind)?;
+    let size: usize = size
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid object size: {}", size))?;
+    Ok((kind, size, nul + 1))
+}
+
+/// Split a stored object into its type and content
+///
+/// # Arguments
+///
+/// * `data` - Decompressed object data (`<type> <size>\0<content>`)
+pub fn parse_object(data: &[u8]) -> Result<(ObjectType, &[u8])> {
+    let (kind, size, header_len) = parse_header(data)?;
+    let content = &data[header_len..];
+    if content.len() != size {
+        anyhow::bail!(
+            "Object size mismatch: header says {}, got {}",
+            size,
+            content.len()
+        );
+    }
+    Ok((kind, content))
+}
+
 /// Trait for all Git objects
 ///
 /// This trait defines the common behavior all Git objects must implement:
@@ -78,6 +121,26 @@ mod tests {
         assert_eq!(ObjectType::Tag.as_str(), "tag");
     }
 
+    #[test]
+    fn test_parse_object() {
+        let (kind, content) = parse_object(b"tree 3\0abc").unwrap();
+        assert_eq!(kind, ObjectType::Tree);
+        assert_eq!(content, b"abc");
+
+        assert!(parse_object(b"tree 4\0abc").is_err());
+        assert!(parse_object(b"bogus 0\0").is_err());
+        assert!(parse_object(b"blob 0").is_err());
+    }
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            parse_header(b"commit 1234\0tree").unwrap(),
+            (ObjectType::Commit, 1234, 12)
+        );
+        assert!(parse_header(b"blob x\0").is_err());
+    }
+
     #[test]
     fn test_object_type_from_str() {
         assert_eq!(ObjectType::from_str("blob").unwrap(), ObjectType::Blob);
@@ -0,0 +1,355 @@
+//! Tree objects: the entries of one directory
+//!
+//! A tree's content is a sequence of `<octal mode> <name>\0<20-byte id>`
+//! entries. [`TreeRef`] walks them in place, without copying names or ids;
+//! [`Tree`] owns its entries and is what gets built and serialized.
+
+use super::mode::FileMode;
+use super::object::{parse_object, GitObject, ObjectType};
+use crate::utils::{bytes_to_hex, hex_to_bytes};
+use anyhow::Result;
+use std::cmp::Ordering;
+
+/// One entry of a tree, borrowing from the object data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeEntryRef<'a> {
+    /// The mode as stored, e.g. `100644` or `40000`
+    pub mode: &'a [u8],
+    /// The entry name; not necessarily UTF-8
+    pub name: &'a [u8],
+    /// The raw object id
+    pub oid: &'a [u8; 20],
+}
+
+impl TreeEntryRef<'_> {
+    /// Parse the mode
+    pub fn file_mode(&self) -> Result<FileMode> {
+        FileMode::from_bytes(self.mode)
+    }
+
+    /// Whether the entry is a subdirectory
+    pub fn is_tree(&self) -> bool {
+        self.mode == b"40000" || self.mode == b"040000"
+    }
+
+    /// The object id as a hex string
+    pub fn hash(&self) -> String {
+        bytes_to_hex(self.oid)
+    }
+}
+
+/// A tree's content, parsed lazily as its entries are iterated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeRef<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> TreeRef<'a> {
+    /// Wrap tree content (without the object header)
+    pub fn new(data: &'a [u8]) -> Self {
+        TreeRef { data }
+    }
+
+    /// Iterate over the entries in stored order
+    ///
+    /// A malformed entry yields an error and ends the iteration.
+    pub fn iter(&self) -> TreeIter<'a> {
+        TreeIter { rest: self.data }
+    }
+}
+
+impl<'a> IntoIterator for TreeRef<'a> {
+    type Item = Result<TreeEntryRef<'a>>;
+    type IntoIter = TreeIter<'a>;
+
+    fn into_iter(self) -> TreeIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the entries of a [`TreeRef`]
+#[derive(Debug, Clone)]
+pub struct TreeIter<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for TreeIter<'a> {
+    type Item = Result<TreeEntryRef<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let rest = std::mem::take(&mut self.rest);
+        let space = rest.iter().position(|&b| b == b' ');
+        let nul = rest.iter().position(|&b| b == 0);
+        let (space, nul) = match (space, nul) {
+            (Some(space), Some(nul)) if nul > space && rest.len() >= nul + 21 => (space, nul),
+            _ => return Some(Err(anyhow::anyhow!("Malformed tree entry"))),
+        };
+        self.rest = &rest[nul + 21..];
+        Some(Ok(TreeEntryRef {
+            mode: &rest[..space],
+            name: &rest[space + 1..nul],
+            oid: rest[nul + 1..nul + 21].try_into().expect("20 bytes"),
+        }))
+    }
+}
+
+/// Compare entry names the way Git orders a tree
+///
+/// Names compare bytewise, except that a subdirectory's name is compared
+/// as if it ended with `/`: `a.txt` sorts before a directory `a`, which
+/// sorts before `a0`.
+pub fn entry_cmp(a: &[u8], a_is_tree: bool, b: &[u8], b_is_tree: bool) -> Ordering {
+    let len = a.len().min(b.len());
+    a[..len].cmp(&b[..len]).then_with(|| {
+        let next = |name: &[u8], is_tree| match name.get(len) {
+            Some(&byte) => Some(byte),
+            None if is_tree => Some(b'/'),
+            None => None,
+        };
+        next(a, a_is_tree).cmp(&next(b, b_is_tree))
+    })
+}
+
+/// One entry of a [`Tree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub mode: FileMode,
+    /// The entry name; not necessarily UTF-8, never containing `/`
+    pub name: Vec<u8>,
+    /// The raw object id
+    pub oid: [u8; 20],
+}
+
+impl TreeEntry {
+    /// Create an entry
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The kind of entry
+    /// * `name` - A single path component
+    /// * `hash` - The object id as a hex string
+    pub fn new(mode: FileMode, name: impl Into<Vec<u8>>, hash: &str) -> Result<Self> {
+        let name = name.into();
+        if name.is_empty() || name.contains(&b'/') || name.contains(&0) {
+            anyhow::bail!(
+                "Invalid tree entry name: {:?}",
+                String::from_utf8_lossy(&name)
+            );
+        }
+        let oid = hex_to_bytes(hash)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid object id: {}", hash))?;
+        Ok(TreeEntry { mode, name, oid })
+    }
+
+    /// Whether the entry is a subdirectory
+    pub fn is_tree(&self) -> bool {
+        self.mode == FileMode::Tree
+    }
+
+    /// The object id as a hex string
+    pub fn hash(&self) -> String {
+        bytes_to_hex(&self.oid)
+    }
+}
+
+impl TryFrom<TreeEntryRef<'_>> for TreeEntry {
+    type Error = anyhow::Error;
+
+    fn try_from(entry: TreeEntryRef<'_>) -> Result<Self> {
+        Ok(TreeEntry {
+            mode: entry.file_mode()?,
+            name: entry.name.to_vec(),
+            oid: *entry.oid,
+        })
+    }
+}
+
+/// The entries of one directory
+///
+/// Entries are kept in Git's order (see [`entry_cmp`]) whatever order they
+/// are added in, so the serialized tree hashes the same as Git's.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tree {
+    entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    /// An empty tree
+    pub fn new() -> Self {
+        Tree::default()
+    }
+
+    /// Parse a stored object (`tree <size>\0<entries>`)
+    ///
+    /// # Arguments
+    ///
+    /// * `object` - Decompressed object data, as returned by
+    ///   [`Repository::read_object`](crate::repository::Repository::read_object)
+    pub fn parse(object: &[u8]) -> Result<Self> {
+        match parse_object(object)? {
+            (ObjectType::Tree, content) => Self::parse_content(content),
+            (other, _) => anyhow::bail!("Not a tree: {}", other.as_str()),
+        }
+    }
+
+    /// Parse tree content (without the object header)
+    ///
+    /// Entries must have valid modes and unique names; their stored order
+    /// isn't checked (see [`crate::fsck::check_tree`]), they are sorted once
+    /// all are read.
+    pub fn parse_content(content: &[u8]) -> Result<Self> {
+        let mut entries = TreeRef::new(content)
+            .iter()
+            .map(|entry| TreeEntry::try_from(entry?))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Names are unique if they are after a plain bytewise sort
+        let mut names: Vec<&[u8]> = entries.iter().map(|e| e.name.as_slice()).collect();
+        names.sort_unstable();
+        if let Some(pair) = names.windows(2).find(|pair| pair[0] == pair[1]) {
+            anyhow::bail!("Duplicate tree entry: {}", String::from_utf8_lossy(pair[0]));
+        }
+
+        entries.sort_by(|a, b| entry_cmp(&a.name, a.is_tree(), &b.name, b.is_tree()));
+        Ok(Tree { entries })
+    }
+
+    /// Add an entry, replacing any entry with the same name
+    pub fn insert(&mut self, entry: TreeEntry) {
+        self.remove(&entry.name);
+        let position = self.entries.partition_point(|e| {
+            entry_cmp(&e.name, e.is_tree(), &entry.name, entry.is_tree()).is_lt()
+        });
+        self.entries.insert(position, entry);
+    }
+
+    /// Remove the entry with this name, returning it
+    pub fn remove(&mut self, name: &[u8]) -> Option<TreeEntry> {
+        let position = self.entries.iter().position(|e| e.name == name)?;
+        Some(self.entries.remove(position))
+    }
+
+    /// The entry with this name
+    pub fn get(&self, name: &[u8]) -> Option<&TreeEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+
+    /// The entries in Git's order
+    pub fn entries(&self) -> &[TreeEntry] {
+        &self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl GitObject for Tree {
+    fn object_type(&self) -> ObjectType {
+        ObjectType::Tree
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>> {
+        let mut content = Vec::new();
+        for entry in &self.entries {
+            content.extend_from_slice(entry.mode.as_str().as_bytes());
+            content.push(b' ');
+            content.extend_from_slice(&entry.name);
+            content.push(0);
+            content.extend_from_slice(&entry.oid);
+        }
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iterate_entries() {
+        let mut data = b"100644 a.txt\0".to_vec();
+        data.extend_from_slice(&[0x11; 20]);
+        data.extend_from_slice(b"40000 dir\0");
+        data.extend_from_slice(&[0x22; 20]);
+
+        let entries: Vec<_> = TreeRef::new(&data).iter().collect::<Result<_>>().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].name, b"a.txt");
+        assert_eq!(entries[0].file_mode().unwrap(), FileMode::Regular);
+        assert_eq!(entries[0].hash(), "11".repeat(20));
+        assert!(entries[1].is_tree());
+
+        // Truncated id: one error, then the iteration stops
+        let mut iter = TreeRef::new(&data[..data.len() - 1]).iter();
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_entry_order() {
+        let mut tree = Tree::new();
+        let blob = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        for (mode, name) in [
+            (FileMode::Regular, "a0"),
+            (FileMode::Tree, "a"),
+            (FileMode::Regular, "a.txt"),
+            (FileMode::Executable, "b"),
+        ] {
+            let hash = if mode == FileMode::Tree {
+                "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+            } else {
+                blob
+            };
+            tree.insert(TreeEntry::new(mode, name, hash).unwrap());
+        }
+        let names: Vec<&[u8]> = tree.entries().iter().map(|e| &e.name[..]).collect();
+        assert_eq!(names, [&b"a.txt"[..], b"a", b"a0", b"b"]);
+        // Same as `git mktree` for these entries
+        assert_eq!(
+            tree.hash().unwrap(),
+            "94776d820bfe8fc645fbdc833847bd0319cc8e70"
+        );
+
+        // Replacing an entry keeps one per name
+        tree.insert(TreeEntry::new(FileMode::Regular, "b", blob).unwrap());
+        assert_eq!(tree.entries().len(), 4);
+        assert_eq!(tree.get(b"b").unwrap().mode, FileMode::Regular);
+
+        assert_eq!(Tree::parse(&tree.to_bytes().unwrap()).unwrap(), tree);
+        assert!(Tree::parse(b"blob 0\0").is_err());
+        assert!(TreeEntry::new(FileMode::Regular, "a/b", blob).is_err());
+        assert!(TreeEntry::new(FileMode::Regular, "a", "abc").is_err());
+    }
+
+    #[test]
+    fn test_parse_duplicates() {
+        let mut data = Vec::new();
+        for _ in 0..2 {
+            data.extend_from_slice(b"100644 a\0");
+            data.extend_from_slice(&[0x11; 20]);
+        }
+        assert!(Tree::parse_content(&data).is_err());
+        assert!(Tree::parse_content(&data[..data.len() / 2]).is_ok());
+
+        // A file and a directory of the same name aren't adjacent in Git's
+        // order but are still duplicates
+        let mut data = Vec::new();
+        for entry in [&b"100644 a\0"[..], b"100644 a.b\0", b"40000 a\0"] {
+            data.extend_from_slice(entry);
+            data.extend_from_slice(&[0x11; 20]);
+        }
+        assert!(Tree::parse_content(&data).is_err());
+
+        // Entries stored out of order are sorted
+        let (a, a_b) = (&data[..29], &data[29..60]);
+        let tree = Tree::parse_content(&[a, a_b].concat()).unwrap();
+        let reversed = [a_b, a].concat();
+        assert_eq!(Tree::parse_content(&reversed).unwrap(), tree);
+    }
+}
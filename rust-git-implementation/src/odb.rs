@@ -0,0 +1,625 @@
+//! Object database backends
+//!
+//! [`Repository`](crate::repository::Repository) stores and finds objects
+//! through an [`OdbBackend`]. The default, [`LooseObjects`], keeps each
+//! object zlib-compressed in its own file under `objects/`, as Git does
+//! before packing, and reads objects it doesn't have from the alternate
+//! object directories it borrows from (see [`LooseObjects::with_alternates`]).
+//! Other storage (packfiles, a database, a remote service,
+//! memory for tests) plugs in with
+//! [`Repository::set_odb`](crate::repository::Repository::set_odb), and
+//! every command works with it unchanged.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use oxid::odb::MemoryObjects;
+//! use oxid::Repository;
+//!
+//! let mut repo = Repository::new(".").unwrap();
+//! repo.set_odb(Box::new(MemoryObjects::default()));
+//! ```
+
+use crate::limits::{Limit, Limits};
+use crate::objects::{parse_header, ObjectType};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+/// Storage for objects
+///
+/// Objects are passed around uncompressed with their `<type> <size>\0`
+/// header, as [`Repository::read_object`](crate::repository::Repository::read_object)
+/// returns them. Hashes are 40 lowercase hex characters; backends don't
+/// have to check that the data hashes to its name.
+pub trait OdbBackend: Send + Sync {
+    /// Read an object
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to read
+    /// * `limits` - Objects larger than the object size limit must be
+    ///   refused with [`LimitExceeded`](crate::limits::LimitExceeded)
+    fn read(&self, hash: &str, limits: &Limits) -> Result<Vec<u8>>;
+
+    /// Store an object; storing one that already exists is not an error
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash
+    /// * `data` - The object data, header included
+    fn write(&self, hash: &str, data: &[u8]) -> Result<()>;
+
+    /// Check whether an object is stored
+    fn exists(&self, hash: &str) -> bool;
+
+    /// The hashes of all stored objects, in sorted order
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = String> + '_>>;
+
+    /// Open an object for reading it as a stream, header included
+    ///
+    /// The default reads the whole object into memory; backends that can
+    /// do better should.
+    fn read_stream(&self, hash: &str) -> Result<Box<dyn Read + '_>> {
+        let unlimited = Limits {
+            max_object_size: u64::MAX,
+            ..Limits::default()
+        };
+        Ok(Box::new(Cursor::new(self.read(hash, &unlimited)?)))
+    }
+
+    /// Read the type and size of an object
+    fn header(&self, hash: &str) -> Result<(ObjectType, usize)> {
+        let mut stream = self.read_stream(hash)?;
+        let header =
+            read_header(&mut stream).with_context(|| format!("Corrupt object {}", hash))?;
+        let (kind, size, _) =
+            parse_header(&header).with_context(|| format!("Corrupt object {}", hash))?;
+        Ok((kind, size))
+    }
+
+    /// Store an object from a zlib-compressed loose object file
+    ///
+    /// Used to move in objects written outside the backend, like those of
+    /// a [`Quarantine`](crate::quarantine::Quarantine). The file may be
+    /// removed or renamed by the backend. The default inflates it and
+    /// calls [`OdbBackend::write`].
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash
+    /// * `path` - The compressed object file
+    fn import(&self, hash: &str, path: &Path) -> Result<()> {
+        let mut data = Vec::new();
+        flate2::read::ZlibDecoder::new(BufReader::new(fs::File::open(path)?))
+            .read_to_end(&mut data)
+            .with_context(|| format!("Corrupt object {}", hash))?;
+        self.write(hash, &data)
+    }
+}
+
+/// Read up to and including the NUL ending an object header
+///
+/// `<type> <size>\0` fits in 32 bytes for any valid object, so no more is
+/// read.
+pub(crate) fn read_header(stream: &mut dyn Read) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(32);
+    let mut byte = [0u8];
+    while header.last() != Some(&0) && header.len() < 32 {
+        if stream.read(&mut byte)? == 0 {
+            break;
+        }
+        header.push(byte[0]);
+    }
+    Ok(header)
+}
+
+/// How many levels of alternates of alternates are followed, as in Git
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// Compressed size from which loose objects are memory-mapped; smaller
+/// ones are cheaper to read than to map
+#[cfg(feature = "mmap")]
+pub const MMAP_THRESHOLD: u64 = 64 * 1024;
+
+/// Objects stored one per file, as `objects/XX/YYYY...`
+///
+/// Objects missing from the directory are looked up in its alternates,
+/// which are only ever read from.
+#[derive(Debug, Clone)]
+pub struct LooseObjects {
+    dir: PathBuf,
+    /// Other object directories to read from, in lookup order
+    alternates: Vec<PathBuf>,
+}
+
+impl LooseObjects {
+    /// # Arguments
+    ///
+    /// * `dir` - The object directory, usually `.git/objects`
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        LooseObjects {
+            dir: dir.into(),
+            alternates: Vec::new(),
+        }
+    }
+
+    /// Loose objects in `dir`, borrowing from its alternate object
+    /// directories
+    ///
+    /// Alternates are listed in `info/alternates`, one directory per line,
+    /// relative to `dir` unless absolute, and in
+    /// `GIT_ALTERNATE_OBJECT_DIRECTORIES`. Their own alternates are
+    /// followed too; see [`alternate_dirs`]. Alternates that can't be read
+    /// are skipped with a warning, as Git does.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The object directory, usually `.git/objects`
+    pub fn with_alternates(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        let env = std::env::var_os("GIT_ALTERNATE_OBJECT_DIRECTORIES");
+        let alternates = alternate_dirs(&dir, env.as_deref());
+        LooseObjects { dir, alternates }
+    }
+
+    /// The object directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The alternate object directories, in lookup order
+    pub fn alternates(&self) -> &[PathBuf] {
+        &self.alternates
+    }
+
+    /// The file an object is stored in
+    pub fn path(&self, hash: &str) -> Result<PathBuf> {
+        object_file(&self.dir, hash)
+    }
+
+    /// The file holding an object, in `dir` or an alternate
+    ///
+    /// Objects that aren't stored anywhere get their path in `dir`.
+    fn find(&self, hash: &str) -> Result<PathBuf> {
+        let local = self.path(hash)?;
+        if local.is_file() {
+            return Ok(local);
+        }
+        Ok(self
+            .alternates
+            .iter()
+            .filter_map(|dir| object_file(dir, hash).ok())
+            .find(|path| path.is_file())
+            .unwrap_or(local))
+    }
+
+    /// The compressed bytes of an object
+    ///
+    /// With the `mmap` feature, files of [`MMAP_THRESHOLD`] bytes or more
+    /// are mapped into memory and inflated from there, instead of being
+    /// copied through a buffer first.
+    fn open(&self, hash: &str) -> Result<Box<dyn Read>> {
+        let file = fs::File::open(self.find(hash)?)
+            .with_context(|| format!("Failed to read object {}", hash))?;
+        #[cfg(feature = "mmap")]
+        if file.metadata()?.len() >= MMAP_THRESHOLD {
+            // SAFETY: loose objects are written elsewhere and renamed into
+            // place, never changed where they are. Another program
+            // truncating the file while it is mapped would still crash us,
+            // a risk Git takes too.
+            let map = unsafe { memmap2::Mmap::map(&file) }
+                .with_context(|| format!("Failed to map object {}", hash))?;
+            return Ok(Box::new(Cursor::new(map)));
+        }
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// The loose object file for a hash in an object directory
+///
+/// # Errors
+///
+/// Fails unless `hash` is a full object id, so a malformed one can't
+/// name a file elsewhere
+pub(crate) fn object_file(dir: &Path, hash: &str) -> Result<PathBuf> {
+    if !crate::utils::is_object_id(hash.as_bytes()) {
+        anyhow::bail!("'{}' is not a valid object name", hash);
+    }
+    let (fanout, file) = hash.split_at(2);
+    Ok(dir.join(fanout).join(file))
+}
+
+/// Collect the alternate object directories of an object directory
+///
+/// Directories named in `info/alternates` come first, then those in
+/// `env`, each followed by its own alternates, depth first, up to five
+/// levels deep. Each directory is listed once, and never `objects_dir`
+/// itself. `#` lines and blank lines in `info/alternates` are skipped.
+///
+/// # Arguments
+///
+/// * `objects_dir` - The object directory whose alternates to find
+/// * `env` - The value of `GIT_ALTERNATE_OBJECT_DIRECTORIES`: paths
+///   separated like `PATH`, relative to the current directory
+pub fn alternate_dirs(objects_dir: &Path, env: Option<&std::ffi::OsStr>) -> Vec<PathBuf> {
+    let mut seen = vec![normalize(objects_dir)];
+    let mut found = Vec::new();
+    let mut direct = read_alternates(objects_dir);
+    if let Some(env) = env {
+        direct.extend(std::env::split_paths(env).filter(|p| !p.as_os_str().is_empty()));
+    }
+    for dir in direct {
+        add_alternate(dir, 0, &mut seen, &mut found);
+    }
+    found
+}
+
+/// Add an alternate and, recursively, its own alternates
+fn add_alternate(dir: PathBuf, depth: usize, seen: &mut Vec<PathBuf>, found: &mut Vec<PathBuf>) {
+    if depth > MAX_ALTERNATE_DEPTH {
+        tracing::warn!(dir = %dir.display(), "ignoring alternate object stores, nesting too deep");
+        return;
+    }
+    if !dir.is_dir() {
+        tracing::warn!(
+            dir = %dir.display(),
+            "object directory does not exist; check .git/objects/info/alternates"
+        );
+        return;
+    }
+    let key = normalize(&dir);
+    if seen.contains(&key) {
+        return;
+    }
+    seen.push(key);
+    let nested = read_alternates(&dir);
+    found.push(dir);
+    for nested in nested {
+        add_alternate(nested, depth + 1, seen, found);
+    }
+}
+
+/// The directories listed in an object directory's `info/alternates`
+fn read_alternates(objects_dir: &Path) -> Vec<PathBuf> {
+    let text = match fs::read(objects_dir.join("info/alternates")) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            tracing::warn!(dir = %objects_dir.display(), error = %e, "unable to read alternates");
+            return Vec::new();
+        }
+    };
+    text.split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(|line| crate::utils::path_from_bytes(line).ok())
+        .map(|path| objects_dir.join(path))
+        .collect()
+}
+
+/// A path to compare directories by: canonical if it exists
+fn normalize(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+impl OdbBackend for LooseObjects {
+    fn read(&self, hash: &str, limits: &Limits) -> Result<Vec<u8>> {
+        limits.inflate_object(self.open(hash)?)
+    }
+
+    /// Writes a temporary file and renames it into place; existing
+    /// objects are left alone, since readers may have them mapped
+    fn write(&self, hash: &str, data: &[u8]) -> Result<()> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let path = self.path(hash)?;
+        if path.is_file() {
+            return Ok(());
+        }
+        let parent = path.parent().context("Invalid object path")?;
+        fs::create_dir_all(parent)?;
+        let temp = parent.join(format!(
+            "tmp_obj_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, crate::utils::compress(data)?)?;
+        fs::rename(&temp, &path).with_context(|| format!("Failed to store object {}", hash))
+    }
+
+    fn exists(&self, hash: &str) -> bool {
+        self.find(hash).is_ok_and(|path| path.is_file())
+    }
+
+    /// Lists one fan-out directory at a time, so memory use doesn't grow
+    /// with the number of objects; objects in alternates are merged in
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = String> + '_>> {
+        if self.alternates.is_empty() {
+            return Ok(Box::new(loose_iter(&self.dir)?));
+        }
+        let mut hashes = std::collections::BTreeSet::new();
+        hashes.extend(loose_iter(&self.dir)?);
+        for dir in &self.alternates {
+            hashes.extend(loose_iter(dir)?);
+        }
+        Ok(Box::new(hashes.into_iter()))
+    }
+
+    fn read_stream(&self, hash: &str) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(flate2::read::ZlibDecoder::new(self.open(hash)?)))
+    }
+
+    /// Renames the file into place, so readers never see a partly written
+    /// object
+    fn import(&self, hash: &str, path: &Path) -> Result<()> {
+        let target = self.path(hash)?;
+        if target.exists() {
+            return Ok(());
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, &target).with_context(|| format!("Failed to store object {}", hash))
+    }
+}
+
+/// The hashes of the loose objects in one object directory, sorted
+fn loose_iter(dir: &Path) -> Result<LooseIter<'_>> {
+    let mut fanout = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        // Skips `info/`, `pack/`, quarantines and temporary files
+        if let Some(prefix) = entry.file_name().to_str().filter(|name| is_hex(name, 2)) {
+            fanout.push(prefix.to_string());
+        }
+    }
+    fanout.sort();
+    Ok(LooseIter {
+        dir,
+        fanout: fanout.into_iter(),
+        hashes: Vec::new().into_iter(),
+    })
+}
+
+/// Whether `name` is `len` lowercase hex digits, as in loose object paths
+fn is_hex(name: &str, len: usize) -> bool {
+    name.len() == len && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hashes of loose objects, fan-out directory by fan-out directory
+struct LooseIter<'a> {
+    dir: &'a Path,
+    fanout: std::vec::IntoIter<String>,
+    hashes: std::vec::IntoIter<String>,
+}
+
+impl Iterator for LooseIter<'_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(hash) = self.hashes.next() {
+                return Some(hash);
+            }
+            let prefix = self.fanout.next()?;
+            let entries = match fs::read_dir(self.dir.join(&prefix)) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    tracing::warn!(prefix, error = %e, "skipping unreadable object directory");
+                    continue;
+                }
+            };
+            let mut hashes: Vec<String> = entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                // SHA-1 or SHA-256 ids
+                .filter(|rest| is_hex(rest, 38) || is_hex(rest, 62))
+                .map(|rest| format!("{}{}", prefix, rest))
+                .collect();
+            hashes.sort();
+            self.hashes = hashes.into_iter();
+        }
+    }
+}
+
+/// Objects kept in memory, lost when dropped
+#[derive(Debug, Default)]
+pub struct MemoryObjects {
+    objects: RwLock<BTreeMap<String, Vec<u8>>>,
+}
+
+impl OdbBackend for MemoryObjects {
+    fn read(&self, hash: &str, limits: &Limits) -> Result<Vec<u8>> {
+        let objects = self.objects.read().unwrap_or_else(|e| e.into_inner());
+        let data = objects
+            .get(hash)
+            .with_context(|| format!("Failed to read object {}", hash))?;
+        let (_, size, _) = parse_header(data)?;
+        limits.check(Limit::ObjectSize, size as u64)?;
+        Ok(data.clone())
+    }
+
+    fn write(&self, hash: &str, data: &[u8]) -> Result<()> {
+        self.objects
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(hash.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, hash: &str) -> bool {
+        self.objects
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(hash)
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = String> + '_>> {
+        let objects = self.objects.read().unwrap_or_else(|e| e.into_inner());
+        Ok(Box::new(
+            objects.keys().cloned().collect::<Vec<_>>().into_iter(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Blob, GitObject};
+    use crate::repository::Repository;
+
+    #[test]
+    fn test_repository_with_memory_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut repo = Repository::init(dir.path()).unwrap();
+        repo.set_odb(Box::new(MemoryObjects::default()));
+
+        let blob = Blob::new(b"hello\n".to_vec());
+        let hash = blob.hash().unwrap();
+        repo.write_object(&hash, &blob.to_bytes().unwrap()).unwrap();
+        assert!(repo.has_object(&hash));
+        assert!(!repo.object_path(&hash).unwrap().exists());
+        assert_eq!(repo.read_object(&hash).unwrap(), b"blob 6\0hello\n");
+        assert_eq!(repo.object_header(&hash).unwrap(), (ObjectType::Blob, 6));
+        assert_eq!(
+            repo.objects().unwrap().collect::<Vec<_>>(),
+            vec![hash.clone()]
+        );
+
+        let mut content = String::new();
+        repo.read_object_stream(&hash)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "hello\n");
+
+        // Objects staged outside the backend are moved in
+        let staged = Blob::new(b"staged".to_vec());
+        let quarantine = repo.quarantine().unwrap();
+        quarantine
+            .write_object(&staged.hash().unwrap(), &staged.to_bytes().unwrap())
+            .unwrap();
+        quarantine.migrate().unwrap();
+        assert!(repo.has_object(&staged.hash().unwrap()));
+
+        repo.set_limits(Limits {
+            max_object_size: 5,
+            ..Limits::default()
+        });
+        assert!(repo.read_object(&hash).is_err());
+    }
+
+    #[test]
+    fn test_large_loose_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let odb = LooseObjects::new(dir.path());
+
+        // Data that doesn't compress, so the file is large enough to map
+        let mut state = 0x2545f491u32;
+        let content: Vec<u8> = (0..256 * 1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let blob = Blob::new(content.clone());
+        let hash = blob.hash().unwrap();
+        let data = blob.to_bytes().unwrap();
+        odb.write(&hash, &data).unwrap();
+        assert!(fs::metadata(odb.path(&hash).unwrap()).unwrap().len() > 64 * 1024);
+
+        assert_eq!(odb.read(&hash, &Limits::default()).unwrap(), data);
+        assert_eq!(
+            odb.header(&hash).unwrap(),
+            (ObjectType::Blob, content.len())
+        );
+        let mut streamed = Vec::new();
+        odb.read_stream(&hash)
+            .unwrap()
+            .read_to_end(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, data);
+
+        // Writing it again leaves the stored file alone
+        let modified = fs::metadata(odb.path(&hash).unwrap())
+            .unwrap()
+            .modified()
+            .unwrap();
+        odb.write(&hash, &data).unwrap();
+        let again = fs::metadata(odb.path(&hash).unwrap())
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified, again);
+        assert_eq!(odb.iter().unwrap().collect::<Vec<_>>(), vec![hash]);
+    }
+
+    #[test]
+    fn test_alternates() {
+        let dir = tempfile::tempdir().unwrap();
+        let shared = Repository::init(dir.path().join("shared")).unwrap();
+        let base = Repository::init(dir.path().join("base")).unwrap();
+        let blob = Blob::new(b"shared\n".to_vec());
+        let hash = blob.hash().unwrap();
+        shared
+            .write_object(&hash, &blob.to_bytes().unwrap())
+            .unwrap();
+        let deep = Blob::new(b"deep\n".to_vec());
+        base.write_object(&deep.hash().unwrap(), &deep.to_bytes().unwrap())
+            .unwrap();
+
+        // shared borrows from base, and the clone from shared, with a
+        // relative path
+        let shared_objects = shared.git_dir.join("objects");
+        fs::write(
+            shared_objects.join("info/alternates"),
+            format!("# pool\n{}\n", base.git_dir.join("objects").display()),
+        )
+        .unwrap();
+        let clone = Repository::init(dir.path().join("clone")).unwrap();
+        fs::write(
+            clone.git_dir.join("objects/info/alternates"),
+            "../../../shared/.git/objects\n",
+        )
+        .unwrap();
+
+        let repo = Repository::new(dir.path().join("clone")).unwrap();
+        assert!(repo.has_object(&hash));
+        assert_eq!(repo.read_object(&hash).unwrap(), b"blob 7\0shared\n");
+        assert_eq!(repo.object_header(&hash).unwrap(), (ObjectType::Blob, 7));
+        assert_eq!(
+            repo.read_object(&deep.hash().unwrap()).unwrap(),
+            b"blob 5\0deep\n"
+        );
+        let mut all = vec![hash.clone(), deep.hash().unwrap()];
+        all.sort();
+        assert_eq!(repo.objects().unwrap().collect::<Vec<_>>(), all);
+
+        // Writes stay local
+        let local = Blob::new(b"local\n".to_vec());
+        repo.write_object(&local.hash().unwrap(), &local.to_bytes().unwrap())
+            .unwrap();
+        assert!(repo.object_path(&local.hash().unwrap()).unwrap().is_file());
+        assert!(!shared.has_object(&local.hash().unwrap()));
+    }
+
+    #[test]
+    fn test_alternate_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let objects = dir.path().join("objects");
+        let other = dir.path().join("other");
+        fs::create_dir_all(objects.join("info")).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        // Cycles back to the object directory are cut
+        fs::write(objects.join("info/alternates"), "../objects\n../missing\n").unwrap();
+
+        assert_eq!(alternate_dirs(&objects, None), Vec::<PathBuf>::new());
+        let env = std::env::join_paths([&other, &other]).unwrap();
+        assert_eq!(alternate_dirs(&objects, Some(&env)), vec![other]);
+    }
+}
@@ -0,0 +1,207 @@
+//! Pathspecs: the paths and patterns commands are limited to
+//!
+//! A pathspec without wildcards names a file or a directory, and matches
+//! that path and everything below it. One with `*`, `?` or `[` is a glob
+//! matched against the whole path; as in Git, wildcards match `/` too, so
+//! `*.c` selects C files at any depth and `src/*` everything below `src`.
+//! A backslash escapes the next character.
+//!
+//! Paths are `/`-separated bytes relative to the top of the repository;
+//! commands turn what the user typed into that form first, e.g. with
+//! [`Repository::index_path_of`](crate::Repository::index_path_of).
+//! Magic like `:(exclude)` is not supported.
+
+use crate::ignore::match_class;
+
+/// One pattern of a [`Pathspec`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Item {
+    /// The pattern, without a trailing `/`
+    pattern: Vec<u8>,
+    /// How much of the pattern is free of wildcards; the whole pattern
+    /// for a literal path
+    literal: usize,
+}
+
+impl Item {
+    fn new(pattern: &[u8]) -> Self {
+        let mut pattern = pattern.to_vec();
+        while pattern.len() > 1 && pattern.ends_with(b"/") {
+            pattern.pop();
+        }
+        if pattern == b"." || pattern == b"/" {
+            pattern.clear();
+        }
+        let literal = pattern
+            .iter()
+            .position(|b| matches!(b, b'*' | b'?' | b'[' | b'\\'))
+            .unwrap_or(pattern.len());
+        Item { pattern, literal }
+    }
+
+    fn is_glob(&self) -> bool {
+        self.literal < self.pattern.len()
+    }
+
+    fn matches(&self, path: &[u8]) -> bool {
+        if self.is_glob() {
+            return glob_match(&self.pattern, path);
+        }
+        self.pattern.is_empty() || is_under(path, &self.pattern)
+    }
+
+    fn may_match_under(&self, dir: &[u8]) -> bool {
+        let literal = &self.pattern[..self.literal];
+        if literal.is_empty() || is_under(dir, literal) || is_under(literal, dir) {
+            return true;
+        }
+        // A glob's literal part may end inside a name: `src/ma*` can
+        // match below `src/main`
+        self.is_glob() && dir.starts_with(literal)
+    }
+}
+
+/// Whether `path` is `dir` or below it
+fn is_under(path: &[u8], dir: &[u8]) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest[0] == b'/')
+}
+
+/// Match text against a glob whose wildcards also match `/`
+fn glob_match(glob: &[u8], text: &[u8]) -> bool {
+    let (mut g, mut t) = (0, 0);
+    while g < glob.len() {
+        match glob[g] {
+            b'*' => {
+                let rest = &glob[g..];
+                let rest = &rest[rest.iter().take_while(|&&b| b == b'*').count()..];
+                return (t..=text.len()).any(|i| glob_match(rest, &text[i..]));
+            }
+            b'?' if t < text.len() => {}
+            b'[' => {
+                let Some(&c) = text.get(t) else {
+                    return false;
+                };
+                match match_class(&glob[g + 1..], c) {
+                    Some((true, len)) => g += len,
+                    Some(_) => return false,
+                    None if c == b'[' => {}
+                    None => return false,
+                }
+            }
+            b'\\' if g + 1 < glob.len() => {
+                g += 1;
+                if text.get(t) != Some(&glob[g]) {
+                    return false;
+                }
+            }
+            c => {
+                if text.get(t) != Some(&c) {
+                    return false;
+                }
+            }
+        }
+        g += 1;
+        t += 1;
+    }
+    t == text.len()
+}
+
+/// A set of pathspecs; a path matches if any of them does
+///
+/// An empty set matches every path.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pathspec {
+    items: Vec<Item>,
+}
+
+impl Pathspec {
+    /// Parse pathspecs
+    ///
+    /// # Arguments
+    ///
+    /// * `patterns` - Paths or globs relative to the top of the repository
+    pub fn new<I, P>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<[u8]>,
+    {
+        Pathspec {
+            items: patterns
+                .into_iter()
+                .map(|pattern| Item::new(pattern.as_ref()))
+                .collect(),
+        }
+    }
+
+    /// Whether no pathspecs were given, so everything matches
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Check whether a path is selected
+    pub fn matches(&self, path: impl AsRef<[u8]>) -> bool {
+        let path = path.as_ref();
+        self.is_empty() || self.items.iter().any(|item| item.matches(path))
+    }
+
+    /// Check whether anything below a directory could be selected
+    ///
+    /// Walks use this to skip directories without looking inside. It may
+    /// answer `true` for a directory with no matches in it, but never
+    /// `false` for one that has some.
+    pub fn may_match_under(&self, dir: impl AsRef<[u8]>) -> bool {
+        let dir = dir.as_ref();
+        self.is_empty() || self.items.iter().any(|item| item.may_match_under(dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal() {
+        let spec = Pathspec::new(["src/", "README"]);
+        assert!(spec.matches("README"));
+        assert!(spec.matches("src"));
+        assert!(spec.matches("src/main.rs"));
+        assert!(!spec.matches("src2/main.rs"));
+        assert!(!spec.matches("README.md"));
+
+        assert!(spec.may_match_under("src"));
+        assert!(spec.may_match_under("src/deep"));
+        assert!(!spec.may_match_under("docs"));
+
+        let spec = Pathspec::new(["docs/api/index.md"]);
+        assert!(spec.may_match_under("docs"));
+        assert!(spec.may_match_under("docs/api"));
+        assert!(!spec.may_match_under("docs/guide"));
+
+        assert!(Pathspec::new(["."]).matches("any/path"));
+        assert!(Pathspec::new(Vec::<&str>::new()).matches("any/path"));
+    }
+
+    #[test]
+    fn test_glob() {
+        let spec = Pathspec::new(["*.c"]);
+        assert!(spec.matches("main.c"));
+        assert!(spec.matches("src/lib/util.c"));
+        assert!(!spec.matches("main.h"));
+        assert!(spec.may_match_under("src"));
+
+        let spec = Pathspec::new(["src/ma?n.[ch]", "docs/\\*"]);
+        assert!(spec.matches("src/main.c"));
+        assert!(spec.matches("src/main.h"));
+        assert!(!spec.matches("src/main.rs"));
+        assert!(spec.matches("docs/*"));
+        assert!(!spec.matches("docs/a"));
+        assert!(spec.may_match_under("src"));
+        assert!(!spec.may_match_under("lib"));
+
+        let spec = Pathspec::new(["src/ma*"]);
+        assert!(spec.may_match_under("src/main"));
+        assert!(!spec.may_match_under("src/lib"));
+        assert!(spec.matches("src/main/deep.rs"));
+    }
+}
@@ -0,0 +1,295 @@
+//! Capability lists
+//!
+//! Both ends of a connection say which protocol features they support. In
+//! protocol v0/v1 the server appends its list to the first advertised ref,
+//! after a NUL byte, as space-separated `name` or `name=value` words; the
+//! client answers with the subset it wants on its first `want` line. In
+//! protocol v2 each capability is a pkt-line of its own.
+//!
+//! Capabilities neither side knows about are kept as they are, so a newer
+//! peer never breaks parsing; [`Capabilities::negotiate`] is what drops
+//! the ones we don't implement.
+
+use std::fmt;
+
+/// Objects in a pack may be deltas against an offset in the same pack
+pub const OFS_DELTA: &str = "ofs-delta";
+/// Pack data and progress multiplexed with up to 1000-byte packets
+pub const SIDE_BAND: &str = "side-band";
+/// Pack data and progress multiplexed with full-size packets
+pub const SIDE_BAND_64K: &str = "side-band-64k";
+/// The peer's software and version, e.g. `agent=git/2.43.0`
+pub const AGENT: &str = "agent";
+/// Hash algorithm of the repository; `sha1` when absent
+pub const OBJECT_FORMAT: &str = "object-format";
+/// Shallow clones and fetches (`shallow` and `deepen` lines)
+pub const SHALLOW: &str = "shallow";
+/// Partial clone filters (`filter blob:none`)
+pub const FILTER: &str = "filter";
+/// What a symbolic ref such as `HEAD` points to: `symref=HEAD:refs/heads/main`
+pub const SYMREF: &str = "symref";
+/// Tags pointing into the fetched history are sent too
+pub const INCLUDE_TAG: &str = "include-tag";
+/// Multiple common commits acknowledged during negotiation
+pub const MULTI_ACK_DETAILED: &str = "multi_ack_detailed";
+/// Progress messages are not wanted
+pub const NO_PROGRESS: &str = "no-progress";
+/// The pack may omit objects the client said it has
+pub const THIN_PACK: &str = "thin-pack";
+/// Push: the server reports the result of each ref update
+pub const REPORT_STATUS: &str = "report-status";
+/// Push: refs may be deleted
+pub const DELETE_REFS: &str = "delete-refs";
+/// Push: updates are applied all or nothing
+pub const ATOMIC: &str = "atomic";
+
+/// Our `agent` value
+pub fn agent() -> String {
+    format!("oxid/{}", crate::VERSION)
+}
+
+/// One capability: a name and an optional value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capability {
+    pub name: String,
+    pub value: Option<String>,
+}
+
+impl Capability {
+    /// Parse `name` or `name=value`
+    pub fn parse(word: &str) -> Self {
+        match word.split_once('=') {
+            Some((name, value)) => Capability {
+                name: name.to_string(),
+                value: Some(value.to_string()),
+            },
+            None => Capability {
+                name: word.to_string(),
+                value: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// An ordered list of capabilities
+///
+/// A name may appear more than once (Git advertises one `symref` per
+/// symbolic ref). Displays as the space-separated v0 form.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    list: Vec<Capability>,
+}
+
+impl Capabilities {
+    /// An empty list
+    pub fn new() -> Self {
+        Capabilities::default()
+    }
+
+    /// Parse a space-separated v0 list
+    pub fn parse(text: &str) -> Self {
+        Capabilities {
+            list: text.split_whitespace().map(Capability::parse).collect(),
+        }
+    }
+
+    /// Parse v2 capability lines, one capability per line
+    pub fn from_lines<'a>(lines: impl IntoIterator<Item = &'a str>) -> Self {
+        Capabilities {
+            list: lines
+                .into_iter()
+                .map(|line| line.trim_end_matches('\n'))
+                .filter(|line| !line.is_empty())
+                .map(Capability::parse)
+                .collect(),
+        }
+    }
+
+    /// Add a capability without a value
+    pub fn with(mut self, name: &str) -> Self {
+        self.push(name, None);
+        self
+    }
+
+    /// Add a capability with a value
+    pub fn with_value(mut self, name: &str, value: &str) -> Self {
+        self.push(name, Some(value));
+        self
+    }
+
+    /// Add a capability
+    ///
+    /// # Panics
+    ///
+    /// If the name or value contains whitespace, which can't be encoded.
+    pub fn push(&mut self, name: &str, value: Option<&str>) {
+        assert!(
+            !name.is_empty() && !name.contains(char::is_whitespace),
+            "invalid capability name '{}'",
+            name
+        );
+        assert!(
+            !value.is_some_and(|v| v.contains(char::is_whitespace)),
+            "invalid value for capability {}",
+            name
+        );
+        self.list.push(Capability {
+            name: name.to_string(),
+            value: value.map(str::to_string),
+        });
+    }
+
+    /// Whether the capability is in the list
+    pub fn contains(&self, name: &str) -> bool {
+        self.list.iter().any(|c| c.name == name)
+    }
+
+    /// The value of the first capability with this name
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.list
+            .iter()
+            .find(|c| c.name == name)
+            .and_then(|c| c.value.as_deref())
+    }
+
+    /// The values of every capability with this name
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.list
+            .iter()
+            .filter(move |c| c.name == name)
+            .filter_map(|c| c.value.as_deref())
+    }
+
+    /// The hash algorithm in use, `sha1` unless stated otherwise
+    pub fn object_format(&self) -> &str {
+        self.value(OBJECT_FORMAT).unwrap_or("sha1")
+    }
+
+    /// The targets of advertised symbolic refs, e.g. `HEAD` to `refs/heads/main`
+    pub fn symrefs(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.values(SYMREF)
+            .filter_map(|value| value.split_once(':'))
+    }
+
+    /// Pick the capabilities to request from a peer
+    ///
+    /// Keeps, in the order of `supported`, each capability we support that
+    /// the peer advertised. Unknown ones are dropped. The peer's `agent`
+    /// value isn't echoed; add our own with [`agent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `supported` - Names of the capabilities we implement
+    pub fn negotiate(&self, supported: &[&str]) -> Capabilities {
+        let mut chosen = Capabilities::new();
+        for name in supported {
+            if *name == AGENT {
+                continue;
+            }
+            // side-band-64k supersedes side-band
+            if *name == SIDE_BAND
+                && supported.contains(&SIDE_BAND_64K)
+                && self.contains(SIDE_BAND_64K)
+            {
+                continue;
+            }
+            if let Some(capability) = self.list.iter().find(|c| c.name == *name) {
+                chosen.list.push(capability.clone());
+            }
+        }
+        chosen
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Capability> {
+        self.list.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, capability) in self.list.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}", capability)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a capability list off a v0 ref advertisement or request line
+///
+/// The first ref line carries the list after a NUL byte. Returns the line
+/// without its newline and list, and the list if there was one.
+pub fn split_line(line: &str) -> (&str, Option<Capabilities>) {
+    let line = line.trim_end_matches('\n');
+    match line.split_once('\0') {
+        Some((rest, capabilities)) => (rest, Some(Capabilities::parse(capabilities))),
+        None => (line, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_advertisement() {
+        let line = "7217a7c7e582c46cec22a130adf4b9d7d950fba0 HEAD\0multi_ack thin-pack \
+                    side-band side-band-64k ofs-delta shallow symref=HEAD:refs/heads/main \
+                    object-format=sha1 agent=git/2.43.0 x-future=1\n";
+        let (rest, capabilities) = split_line(line);
+        assert_eq!(rest, "7217a7c7e582c46cec22a130adf4b9d7d950fba0 HEAD");
+        let capabilities = capabilities.unwrap();
+        assert!(capabilities.contains(OFS_DELTA));
+        assert!(!capabilities.contains(FILTER));
+        assert_eq!(capabilities.value(AGENT), Some("git/2.43.0"));
+        assert_eq!(capabilities.value("x-future"), Some("1"));
+        assert_eq!(capabilities.object_format(), "sha1");
+        assert_eq!(
+            capabilities.symrefs().collect::<Vec<_>>(),
+            vec![("HEAD", "refs/heads/main")]
+        );
+        assert!(split_line("abc refs/heads/x\n").1.is_none());
+
+        let chosen = capabilities
+            .negotiate(&[SIDE_BAND, SIDE_BAND_64K, OFS_DELTA, AGENT, FILTER])
+            .with_value(AGENT, &agent());
+        assert_eq!(
+            chosen.to_string(),
+            format!("side-band-64k ofs-delta agent=oxid/{}", crate::VERSION)
+        );
+        assert_eq!(Capabilities::parse(&chosen.to_string()), chosen);
+    }
+
+    #[test]
+    fn test_v2_lines() {
+        let capabilities = Capabilities::from_lines([
+            "agent=git/2.43.0\n",
+            "ls-refs=unborn\n",
+            "fetch=shallow filter\n",
+        ]);
+        assert!(capabilities.contains("ls-refs"));
+        assert_eq!(capabilities.value("fetch"), Some("shallow filter"));
+        assert_eq!(Capabilities::new().object_format(), "sha1");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_push_whitespace() {
+        Capabilities::new().with_value(AGENT, "oxid 1");
+    }
+}
@@ -0,0 +1,11 @@
+//! Git's wire protocol
+//!
+//! - capabilities: parsing and choosing the features each side supports
+//! - pktline: length-prefixed framing shared by every transport, with
+//!   sideband multiplexing
+
+pub mod capabilities;
+pub mod pktline;
+
+pub use capabilities::{Capabilities, Capability};
+pub use pktline::{Packet, PktLineReader, PktLineWriter, SidebandReader};
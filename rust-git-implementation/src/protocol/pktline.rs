@@ -0,0 +1,423 @@
+//! pkt-line framing
+//!
+//! Every message of the smart protocols travels in pkt-lines: four hex
+//! digits giving the packet length (the four digits included), then the
+//! payload. Lengths below 4 are special packets: `0000` flush (end of a
+//! section), `0001` delimiter and `0002` response end (protocol v2).
+//!
+//! During a fetch the pack arrives multiplexed ("sideband"): the first
+//! payload byte of each packet names a band, 1 for pack data, 2 for
+//! progress messages and 3 for a fatal error. [`SidebandReader`] turns
+//! band 1 back into a byte stream.
+//!
+//! Every packet read or written is traced with the
+//! [`PACKET`](crate::trace::PACKET) target, as `GIT_TRACE_PACKET` shows
+//! them: `packet: < ...` for packets read and `packet: > ...` for packets
+//! written.
+
+use crate::limits::{Limit, LimitExceeded, Limits};
+use anyhow::{Context, Result};
+use std::io::{self, Read, Write};
+
+/// Largest packet, length prefix included
+pub const MAX_PACKET_LEN: usize = 65520;
+/// Largest payload of a packet
+pub const MAX_DATA_LEN: usize = MAX_PACKET_LEN - 4;
+
+/// Sideband carrying the pack
+pub const BAND_DATA: u8 = 1;
+/// Sideband carrying progress messages
+pub const BAND_PROGRESS: u8 = 2;
+/// Sideband carrying a fatal error message
+pub const BAND_ERROR: u8 = 3;
+
+/// One packet read from a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Packet<'a> {
+    /// `0000`: end of a message or section
+    Flush,
+    /// `0001`: separates sections of a protocol v2 request or response
+    Delim,
+    /// `0002`: end of a protocol v2 response in stateless connections
+    ResponseEnd,
+    Data(&'a [u8]),
+}
+
+impl<'a> Packet<'a> {
+    /// The payload as text with its trailing newline removed
+    ///
+    /// Returns `None` for special packets and non-UTF-8 payloads.
+    pub fn as_text(&self) -> Option<&'a str> {
+        match self {
+            Packet::Data(data) => {
+                let text = std::str::from_utf8(data).ok()?;
+                Some(text.strip_suffix('\n').unwrap_or(text))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Reads packets from a byte stream
+pub struct PktLineReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    max_len: usize,
+}
+
+impl<R: Read> PktLineReader<R> {
+    /// Read packets of up to [`MAX_PACKET_LEN`] bytes
+    pub fn new(inner: R) -> Self {
+        PktLineReader {
+            inner,
+            buf: Vec::new(),
+            max_len: MAX_PACKET_LEN,
+        }
+    }
+
+    /// Read packets no longer than `oxid.maxPktLineLength`
+    ///
+    /// A longer packet fails with [`LimitExceeded`].
+    pub fn with_limits(inner: R, limits: &Limits) -> Self {
+        PktLineReader {
+            inner,
+            buf: Vec::new(),
+            max_len: limits.max(Limit::PktLineLength) as usize,
+        }
+    }
+
+    /// Read the next packet
+    ///
+    /// Returns `None` if the stream ends cleanly before a packet.
+    pub fn read_packet(&mut self) -> Result<Option<Packet<'_>>> {
+        let mut prefix = [0u8; 4];
+        let mut filled = 0;
+        while filled < 4 {
+            match self.inner.read(&mut prefix[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => anyhow::bail!("protocol error: stream ended inside a pkt-line length"),
+                n => filled += n,
+            }
+        }
+        let len = std::str::from_utf8(&prefix)
+            .ok()
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .with_context(|| {
+                format!(
+                    "protocol error: bad line length character: {}",
+                    String::from_utf8_lossy(&prefix)
+                )
+            })?;
+        let special = match len {
+            0 => Some(Packet::Flush),
+            1 => Some(Packet::Delim),
+            2 => Some(Packet::ResponseEnd),
+            3 => anyhow::bail!("protocol error: bad line length {}", len),
+            _ => None,
+        };
+        if let Some(packet) = special {
+            tracing::debug!(target: crate::trace::PACKET, "packet: < {:04}", len);
+            return Ok(Some(packet));
+        }
+        if len > self.max_len {
+            return Err(LimitExceeded {
+                limit: Limit::PktLineLength,
+                value: len as u64,
+                max: self.max_len as u64,
+            }
+            .into());
+        }
+
+        self.buf.resize(len - 4, 0);
+        self.inner
+            .read_exact(&mut self.buf)
+            .context("protocol error: stream ended inside a pkt-line")?;
+        tracing::debug!(target: crate::trace::PACKET, "packet: < {}", trace_text(&self.buf));
+        Ok(Some(Packet::Data(&self.buf)))
+    }
+
+    /// Read the next packet, failing at the end of the stream
+    pub fn expect_packet(&mut self) -> Result<Packet<'_>> {
+        self.read_packet()?
+            .context("protocol error: unexpected end of stream")
+    }
+
+    /// Demultiplex the sideband stream that follows
+    ///
+    /// # Arguments
+    ///
+    /// * `progress` - Called with each progress message (band 2)
+    pub fn sideband<'a, F>(&'a mut self, progress: F) -> SidebandReader<'a, R, F>
+    where
+        F: FnMut(&[u8]),
+    {
+        SidebandReader {
+            packets: self,
+            progress,
+            pending: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    /// The underlying stream
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Writes packets to a byte stream
+pub struct PktLineWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> PktLineWriter<W> {
+    pub fn new(inner: W) -> Self {
+        PktLineWriter { inner }
+    }
+
+    /// Write one data packet
+    ///
+    /// Fails if `data` is longer than [`MAX_DATA_LEN`].
+    pub fn write_data(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_DATA_LEN {
+            anyhow::bail!(
+                "pkt-line payload of {} bytes exceeds {}",
+                data.len(),
+                MAX_DATA_LEN
+            );
+        }
+        tracing::debug!(target: crate::trace::PACKET, "packet: > {}", trace_text(data));
+        write!(self.inner, "{:04x}", data.len() + 4)?;
+        self.inner.write_all(data)?;
+        Ok(())
+    }
+
+    /// Write a line of text, adding the newline the protocol expects
+    pub fn write_text(&mut self, text: &str) -> Result<()> {
+        if text.ends_with('\n') {
+            self.write_data(text.as_bytes())
+        } else {
+            self.write_data(format!("{}\n", text).as_bytes())
+        }
+    }
+
+    /// Write data on a sideband, split into as many packets as needed
+    ///
+    /// # Arguments
+    ///
+    /// * `band` - [`BAND_DATA`], [`BAND_PROGRESS`] or [`BAND_ERROR`]
+    /// * `data` - The bytes to send
+    pub fn write_band(&mut self, band: u8, data: &[u8]) -> Result<()> {
+        let mut packet = Vec::with_capacity(MAX_DATA_LEN);
+        for chunk in data.chunks(MAX_DATA_LEN - 1) {
+            packet.clear();
+            packet.push(band);
+            packet.extend_from_slice(chunk);
+            self.write_data(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Write a flush packet (`0000`) and flush the stream
+    pub fn flush_packet(&mut self) -> Result<()> {
+        tracing::debug!(target: crate::trace::PACKET, "packet: > 0000");
+        self.inner.write_all(b"0000")?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// Write a delimiter packet (`0001`)
+    pub fn delim_packet(&mut self) -> Result<()> {
+        tracing::debug!(target: crate::trace::PACKET, "packet: > 0001");
+        self.inner.write_all(b"0001")?;
+        Ok(())
+    }
+
+    /// Write a response-end packet (`0002`) and flush the stream
+    pub fn response_end_packet(&mut self) -> Result<()> {
+        tracing::debug!(target: crate::trace::PACKET, "packet: > 0002");
+        self.inner.write_all(b"0002")?;
+        self.inner.flush()?;
+        Ok(())
+    }
+
+    /// The underlying stream
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// A payload as Git traces it: without its trailing newline, and with
+/// bytes other than printable ASCII and tabs as octal escapes
+fn trace_text(data: &[u8]) -> String {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    let mut text = String::with_capacity(data.len());
+    for &b in data {
+        if b == b'\t' || b.is_ascii_graphic() || b == b' ' {
+            text.push(b as char);
+        } else {
+            text.push_str(&format!("\\{:o}", b));
+        }
+    }
+    text
+}
+
+/// The pack data of a sideband stream, readable as plain bytes
+///
+/// Progress messages go to a callback; an error message on band 3 fails
+/// the read. The stream ends at a flush packet.
+pub struct SidebandReader<'a, R, F> {
+    packets: &'a mut PktLineReader<R>,
+    progress: F,
+    pending: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read, F: FnMut(&[u8])> Read for SidebandReader<'_, R, F> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.pending.len() {
+            if self.done {
+                return Ok(0);
+            }
+            let packet = self
+                .packets
+                .read_packet()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:#}", e)))?;
+            match packet {
+                None | Some(Packet::Flush) => self.done = true,
+                Some(Packet::Data([BAND_DATA, data @ ..])) => {
+                    self.pending.clear();
+                    self.pending.extend_from_slice(data);
+                    self.pos = 0;
+                }
+                Some(Packet::Data([BAND_PROGRESS, message @ ..])) => (self.progress)(message),
+                Some(Packet::Data([BAND_ERROR, message @ ..])) => {
+                    return Err(io::Error::other(format!(
+                        "remote error: {}",
+                        String::from_utf8_lossy(message).trim_end()
+                    )))
+                }
+                Some(packet) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("protocol error: unexpected sideband packet {:?}", packet),
+                    ))
+                }
+            }
+        }
+        let n = out.len().min(self.pending.len() - self.pos);
+        out[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut writer = PktLineWriter::new(Vec::new());
+        writer.write_text("want 1234").unwrap();
+        writer.write_data(b"").unwrap();
+        writer.delim_packet().unwrap();
+        writer.write_text("done\n").unwrap();
+        writer.flush_packet().unwrap();
+        writer.response_end_packet().unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes, b"000ewant 1234\n000400010009done\n00000002");
+
+        let mut reader = PktLineReader::new(&bytes[..]);
+        assert_eq!(
+            reader.read_packet().unwrap().unwrap().as_text(),
+            Some("want 1234")
+        );
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::Data(b"")));
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::Delim));
+        assert_eq!(reader.expect_packet().unwrap(), Packet::Data(b"done\n"));
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::Flush));
+        assert_eq!(reader.read_packet().unwrap(), Some(Packet::ResponseEnd));
+        assert_eq!(reader.read_packet().unwrap(), None);
+        assert!(reader.expect_packet().is_err());
+
+        let too_long = vec![b'x'; MAX_DATA_LEN + 1];
+        assert!(PktLineWriter::new(Vec::new())
+            .write_data(&too_long)
+            .is_err());
+    }
+
+    #[test]
+    fn test_trace_text() {
+        assert_eq!(trace_text(b"want 1234 ofs-delta\n"), "want 1234 ofs-delta");
+        assert_eq!(
+            trace_text(b"\x02Counting objects\r"),
+            "\\2Counting objects\\15"
+        );
+        assert_eq!(trace_text(b"a\tb\0"), "a\tb\\0");
+    }
+
+    #[test]
+    fn test_malformed() {
+        let read = |bytes: &[u8]| PktLineReader::new(bytes).read_packet().map(|_| ());
+        assert!(read(b"zzzz").is_err());
+        assert!(read(b"0003").is_err());
+        assert!(read(b"00").is_err());
+        assert!(read(b"0010short").is_err());
+
+        let limits = Limits {
+            max_pkt_line_length: 8,
+            ..Limits::default()
+        };
+        let err = PktLineReader::with_limits(&b"0009hello"[..], &limits)
+            .read_packet()
+            .map(|_| ())
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>().map(|e| e.limit),
+            Some(Limit::PktLineLength)
+        );
+    }
+
+    #[test]
+    fn test_sideband() {
+        let pack = vec![7u8; MAX_DATA_LEN * 2];
+        let mut writer = PktLineWriter::new(Vec::new());
+        writer
+            .write_band(BAND_PROGRESS, b"Counting objects\r")
+            .unwrap();
+        writer.write_band(BAND_DATA, &pack).unwrap();
+        writer.write_band(BAND_PROGRESS, b"done\n").unwrap();
+        writer.flush_packet().unwrap();
+        writer.write_text("after").unwrap();
+        let bytes = writer.into_inner();
+
+        let mut progress = Vec::new();
+        let mut reader = PktLineReader::new(&bytes[..]);
+        let mut data = Vec::new();
+        reader
+            .sideband(|message| progress.extend_from_slice(message))
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, pack);
+        assert_eq!(progress, b"Counting objects\rdone\n");
+        // The packets after the flush are left for the caller
+        assert_eq!(
+            reader.read_packet().unwrap().unwrap().as_text(),
+            Some("after")
+        );
+
+        let mut writer = PktLineWriter::new(Vec::new());
+        writer
+            .write_band(BAND_ERROR, b"upload-pack: not our ref\n")
+            .unwrap();
+        let bytes = writer.into_inner();
+        let err = PktLineReader::new(&bytes[..])
+            .sideband(|_| {})
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "remote error: upload-pack: not our ref");
+    }
+}
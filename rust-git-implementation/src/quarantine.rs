@@ -0,0 +1,239 @@
+//! Object quarantine: staged writes to the object database
+//!
+//! Objects received from an untrusted source (a push, an import, a patch
+//! series) shouldn't become part of the repository until they have been
+//! checked. A [`Quarantine`] is a temporary object directory inside
+//! `objects/`, like Git's `incoming-*` directories: objects are written
+//! there, inspected with [`Quarantine::validate`], and then either moved
+//! into the repository with [`Quarantine::migrate`] or thrown away.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use oxid::Repository;
+//!
+//! let repo = Repository::new(".").unwrap();
+//! let staging = repo.quarantine().unwrap();
+//! let data = b"blob 5\0hello";
+//! staging.write_object(&oxid::utils::hash_data(data), data).unwrap();
+//! staging
+//!     .validate(|_hash, data| {
+//!         if data.len() > 1 << 20 {
+//!             anyhow::bail!("object too large");
+//!         }
+//!         Ok(())
+//!     })
+//!     .unwrap();
+//! staging.migrate().unwrap();
+//! ```
+
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A temporary object directory whose objects aren't in the repository yet
+///
+/// Dropping a quarantine without migrating it discards its objects.
+pub struct Quarantine<'a> {
+    repo: &'a Repository,
+    dir: PathBuf,
+}
+
+impl<'a> Quarantine<'a> {
+    /// Create an empty quarantine inside the repository's object directory
+    pub(crate) fn new(repo: &'a Repository) -> Result<Self> {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .subsec_nanos();
+        let dir = repo.common_dir.join("objects").join(format!(
+            "incoming-{}-{:08x}",
+            std::process::id(),
+            nanos
+        ));
+        fs::create_dir(&dir)
+            .with_context(|| format!("Failed to create quarantine {}", dir.display()))?;
+        Ok(Quarantine { repo, dir })
+    }
+
+    /// The quarantine's object directory
+    ///
+    /// Hooks that need to see the new objects can get them through
+    /// `GIT_OBJECT_DIRECTORY` set to this path, with the repository's
+    /// `objects` directory as an alternate.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    fn object_path(&self, hash: &str) -> Result<PathBuf> {
+        crate::odb::object_file(&self.dir, hash)
+    }
+
+    /// Write an object into the quarantine
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash (determines storage location)
+    /// * `data` - The object data to write (will be compressed)
+    pub fn write_object(&self, hash: &str, data: &[u8]) -> Result<()> {
+        let path = self.object_path(hash)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, crate::utils::compress(data)?)?;
+        Ok(())
+    }
+
+    /// Read an object, looking in the quarantine before the repository
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to read
+    pub fn read_object(&self, hash: &str) -> Result<Vec<u8>> {
+        let path = self.object_path(hash)?;
+        if path.is_file() {
+            let file = fs::File::open(&path)?;
+            return self
+                .repo
+                .limits()?
+                .inflate_object(std::io::BufReader::new(file));
+        }
+        self.repo.read_stored_object(hash)
+    }
+
+    /// List the hashes of the quarantined objects, in sorted order
+    pub fn objects(&self) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        for dir in fs::read_dir(&self.dir)? {
+            let dir = dir?;
+            let prefix = dir.file_name().to_string_lossy().into_owned();
+            for file in fs::read_dir(dir.path())? {
+                let rest = file?.file_name().to_string_lossy().into_owned();
+                hashes.push(format!("{}{}", prefix, rest));
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    /// Run a check over every quarantined object
+    ///
+    /// Every object is also verified to hash to its name. Stops at the
+    /// first failure.
+    ///
+    /// # Arguments
+    ///
+    /// * `check` - Called with each object's hash and decompressed data
+    pub fn validate<F>(&self, mut check: F) -> Result<()>
+    where
+        F: FnMut(&str, &[u8]) -> Result<()>,
+    {
+        for hash in self.objects()? {
+            let data = self.read_object(&hash)?;
+            let actual = crate::utils::hash_data(&data);
+            if actual != hash {
+                anyhow::bail!("object {} hashes to {}", hash, actual);
+            }
+            check(&hash, &data).with_context(|| format!("object {} rejected", hash))?;
+        }
+        Ok(())
+    }
+
+    /// Move the quarantined objects into the repository
+    ///
+    /// Each object is handed to the repository's
+    /// [`OdbBackend::import`](crate::odb::OdbBackend::import); loose objects
+    /// are renamed into place, so readers never see a partly written
+    /// object. Objects the repository already has are dropped.
+    ///
+    /// Returns the hashes of all quarantined objects.
+    pub fn migrate(self) -> Result<Vec<String>> {
+        let hashes = self.objects()?;
+        for hash in &hashes {
+            if self.repo.has_object(hash) {
+                continue;
+            }
+            self.repo
+                .odb()
+                .import(hash, &self.object_path(hash)?)
+                .with_context(|| format!("Failed to migrate object {}", hash))?;
+        }
+        Ok(hashes)
+    }
+
+    /// Throw away the quarantined objects
+    pub fn discard(self) {}
+}
+
+impl Drop for Quarantine<'_> {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::objects::{Blob, GitObject};
+    use crate::repository::Repository;
+    use tempfile::tempdir;
+
+    fn blob(content: &[u8]) -> (String, Vec<u8>) {
+        let blob = Blob::new(content);
+        (blob.hash().unwrap(), blob.to_bytes().unwrap())
+    }
+
+    #[test]
+    fn test_migrate() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let (existing, data) = blob(b"old");
+        repo.write_object(&existing, &data).unwrap();
+
+        let quarantine = repo.quarantine().unwrap();
+        let path = quarantine.path().to_path_buf();
+        let (hash, data) = blob(b"new");
+        quarantine.write_object(&hash, &data).unwrap();
+        quarantine.write_object(&existing, &blob(b"old").1).unwrap();
+
+        // Staged objects aren't visible to the repository yet
+        assert!(!repo.has_object(&hash));
+        assert_eq!(quarantine.read_object(&hash).unwrap(), data);
+        quarantine.validate(|_, _| Ok(())).unwrap();
+
+        let mut migrated = quarantine.migrate().unwrap();
+        migrated.sort();
+        let mut expected = vec![hash.clone(), existing];
+        expected.sort();
+        assert_eq!(migrated, expected);
+        assert_eq!(repo.read_object(&hash).unwrap(), data);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_rejected_objects_are_discarded() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+
+        let quarantine = repo.quarantine().unwrap();
+        let path = quarantine.path().to_path_buf();
+        let (hash, data) = blob(b"secret");
+        quarantine.write_object(&hash, &data).unwrap();
+        let result = quarantine.validate(|_, data| {
+            if data.ends_with(b"secret") {
+                anyhow::bail!("contains a secret");
+            }
+            Ok(())
+        });
+        assert!(result.is_err());
+
+        drop(quarantine);
+        assert!(!path.exists());
+        assert!(!repo.has_object(&hash));
+
+        // Objects stored under the wrong name fail validation
+        let quarantine = repo.quarantine().unwrap();
+        quarantine.write_object(&"0".repeat(40), &data).unwrap();
+        assert!(quarantine.validate(|_, _| Ok(())).is_err());
+        quarantine.discard();
+    }
+}
@@ -0,0 +1,167 @@
+//! Quoting paths for output
+//!
+//! Paths are bytes and may hold anything but NUL and `/`-separated empty
+//! components: newlines, tabs, control characters, Latin-1 or other
+//! non-UTF-8 bytes. Like Git, commands print such paths C-style, inside
+//! double quotes with backslash escapes, so every output line is still
+//! one path. Bytes of 0x80 and above are escaped too unless
+//! `core.quotePath` is false.
+//!
+//! With `-z`, paths are written as they are and records end with NUL
+//! instead of a newline.
+//!
+//! # Example
+//!
+//! ```
+//! use oxid::quote::quote;
+//!
+//! assert_eq!(quote(b"a b.txt", true), &b"a b.txt"[..]);
+//! assert_eq!(quote(b"tab\there", true), &b"\"tab\\there\""[..]);
+//! assert_eq!(quote("caf\u{e9}".as_bytes(), true), &b"\"caf\\303\\251\""[..]);
+//! ```
+
+use crate::config::Config;
+use anyhow::Result;
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// How paths are written in command output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathFormat {
+    /// Quote paths that need it, one record per line
+    Quoted {
+        /// Escape bytes of 0x80 and above (`core.quotePath`)
+        non_ascii: bool,
+    },
+    /// `-z`: paths as they are, each record ended by NUL
+    Nul,
+}
+
+impl Default for PathFormat {
+    fn default() -> Self {
+        PathFormat::Quoted { non_ascii: true }
+    }
+}
+
+impl PathFormat {
+    /// The format for a command's output
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Supplies `core.quotePath`
+    /// * `nul` - Whether `-z` was given
+    pub fn from_config(config: &Config, nul: bool) -> Result<Self> {
+        if nul {
+            return Ok(PathFormat::Nul);
+        }
+        Ok(PathFormat::Quoted {
+            non_ascii: config.get_bool("core.quotePath")?.unwrap_or(true),
+        })
+    }
+
+    /// A path as it should be printed
+    pub fn path<'a>(&self, path: &'a [u8]) -> Cow<'a, [u8]> {
+        match self {
+            PathFormat::Quoted { non_ascii } => quote(path, *non_ascii),
+            PathFormat::Nul => Cow::Borrowed(path),
+        }
+    }
+
+    /// The byte ending each record: newline, or NUL with `-z`
+    pub fn terminator(&self) -> u8 {
+        match self {
+            PathFormat::Quoted { .. } => b'\n',
+            PathFormat::Nul => 0,
+        }
+    }
+
+    /// Write a path followed by the record terminator
+    pub fn write_line(&self, out: &mut dyn Write, path: &[u8]) -> io::Result<()> {
+        out.write_all(&self.path(path))?;
+        out.write_all(&[self.terminator()])
+    }
+}
+
+/// Whether a byte must be escaped
+fn needs_quoting(byte: u8, non_ascii: bool) -> bool {
+    byte < 0x20 || byte == b'"' || byte == b'\\' || byte == 0x7f || (non_ascii && byte >= 0x80)
+}
+
+/// Quote a path C-style if it has bytes that need escaping
+///
+/// Paths that don't are returned unchanged and without quotes.
+///
+/// # Arguments
+///
+/// * `path` - The path
+/// * `non_ascii` - Whether bytes of 0x80 and above are escaped, as octal
+pub fn quote(path: &[u8], non_ascii: bool) -> Cow<'_, [u8]> {
+    if !path.iter().any(|&b| needs_quoting(b, non_ascii)) {
+        return Cow::Borrowed(path);
+    }
+    let mut out = Vec::with_capacity(path.len() + 8);
+    out.push(b'"');
+    for &byte in path {
+        if !needs_quoting(byte, non_ascii) {
+            out.push(byte);
+            continue;
+        }
+        out.push(b'\\');
+        match byte {
+            0x07 => out.push(b'a'),
+            0x08 => out.push(b'b'),
+            b'\t' => out.push(b't'),
+            b'\n' => out.push(b'n'),
+            0x0b => out.push(b'v'),
+            0x0c => out.push(b'f'),
+            b'\r' => out.push(b'r'),
+            b'"' | b'\\' => out.push(byte),
+            _ => out.extend_from_slice(format!("{:03o}", byte).as_bytes()),
+        }
+    }
+    out.push(b'"');
+    Cow::Owned(out)
+}
+
+/// A path for messages: quoted if needed, with non-ASCII bytes escaped
+pub fn display(path: &[u8]) -> String {
+    String::from_utf8_lossy(&quote(path, true)).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote(b"plain/path.txt", true), &b"plain/path.txt"[..]);
+        assert_eq!(
+            quote(b"a\"b\\c\nd\x01\x7f", true),
+            &b"\"a\\\"b\\\\c\\nd\\001\\177\""[..]
+        );
+        // Latin-1, not UTF-8
+        assert_eq!(quote(b"caf\xe9", true), &b"\"caf\\351\""[..]);
+        assert_eq!(quote(b"caf\xe9", false), &b"caf\xe9"[..]);
+        assert_eq!(quote(b"caf\xe9\t", false), &b"\"caf\xe9\\t\""[..]);
+        assert_eq!(display(b"caf\xe9"), "\"caf\\351\"");
+    }
+
+    #[test]
+    fn test_path_format() {
+        let config = Config::parse("[core]\n\tquotePath = false\n").unwrap();
+        let format = PathFormat::from_config(&config, false).unwrap();
+        assert_eq!(format, PathFormat::Quoted { non_ascii: false });
+        let mut out = Vec::new();
+        format.write_line(&mut out, b"caf\xe9").unwrap();
+        assert_eq!(out, b"caf\xe9\n");
+
+        let format = PathFormat::from_config(&config, true).unwrap();
+        let mut out = Vec::new();
+        format.write_line(&mut out, b"new\nline").unwrap();
+        assert_eq!(out, b"new\nline\0");
+        assert_eq!(
+            PathFormat::from_config(&Config::parse("").unwrap(), false).unwrap(),
+            PathFormat::default()
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Filtering and sorting refs for listings
+//!
+//! `branch` and `tag` select refs by reachability ([`ReachFilter`]) and
+//! order them by name, optionally comparing embedded version numbers
+//! ([`SortKey`]).
+
+use crate::config::Config;
+use crate::merge_base::{is_ancestor, reachable};
+use crate::repository::Repository;
+use crate::revision::resolve_commit;
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// Selects refs by the commits they reach; each field holds revision names
+///
+/// A ref passes if it passes every non-empty condition; within a
+/// condition any one of the commits is enough.
+#[derive(Debug, Clone, Default)]
+pub struct ReachFilter {
+    /// `--contains`: the ref's history includes the commit
+    pub contains: Vec<String>,
+    /// `--no-contains`: the ref's history doesn't include the commit
+    pub no_contains: Vec<String>,
+    /// `--merged`: the ref's commit is in the commit's history
+    pub merged: Vec<String>,
+    /// `--no-merged`: the ref's commit is not in the commit's history
+    pub no_merged: Vec<String>,
+}
+
+impl ReachFilter {
+    /// Whether the filter keeps everything
+    pub fn is_empty(&self) -> bool {
+        self.contains.is_empty()
+            && self.no_contains.is_empty()
+            && self.merged.is_empty()
+            && self.no_merged.is_empty()
+    }
+
+    /// Keep the items whose commit passes the filter
+    ///
+    /// Items without a commit (tags of trees or blobs) only pass an empty
+    /// filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository
+    /// * `items` - The refs to filter
+    /// * `commit` - The commit an item points to, if any
+    pub fn apply<T>(
+        &self,
+        repo: &Repository,
+        items: Vec<T>,
+        commit: impl Fn(&T) -> Option<&str>,
+    ) -> Result<Vec<T>> {
+        if self.is_empty() {
+            return Ok(items);
+        }
+        let contains = resolve_all(repo, &self.contains)?;
+        let no_contains = resolve_all(repo, &self.no_contains)?;
+        let merged = reachable_from(repo, &self.merged)?;
+        let no_merged = reachable_from(repo, &self.no_merged)?;
+
+        let mut kept = Vec::new();
+        for item in items {
+            let Some(tip) = commit(&item) else {
+                continue;
+            };
+            let keep = (self.merged.is_empty() || merged.contains(tip))
+                && !no_merged.contains(tip)
+                && (contains.is_empty() || contains_any(repo, tip, &contains)?)
+                && (no_contains.is_empty() || !contains_any(repo, tip, &no_contains)?);
+            if keep {
+                kept.push(item);
+            }
+        }
+        Ok(kept)
+    }
+}
+
+fn resolve_all(repo: &Repository, names: &[String]) -> Result<Vec<String>> {
+    names
+        .iter()
+        .map(|name| resolve_commit(repo, name))
+        .collect()
+}
+
+/// Commits reachable from any of the given revisions
+fn reachable_from(repo: &Repository, names: &[String]) -> Result<HashSet<String>> {
+    let tips = resolve_all(repo, names)?;
+    reachable(repo, &tips.iter().map(String::as_str).collect::<Vec<_>>())
+}
+
+/// Whether any of `commits` is in the history of `tip`
+fn contains_any(repo: &Repository, tip: &str, commits: &[String]) -> Result<bool> {
+    for commit in commits {
+        if is_ancestor(repo, commit, tip)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// How to order a listing (`--sort`, `tag.sort`)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SortKey {
+    /// Compare digit runs as numbers (`version:refname`)
+    pub version: bool,
+    /// Descending order (a leading `-`)
+    pub reverse: bool,
+    /// Pre-release suffixes that sort before the release
+    /// (`versionsort.suffix`)
+    pub suffixes: Vec<String>,
+}
+
+impl SortKey {
+    /// Parse a sort key: `refname` or `version:refname` (`v:refname`),
+    /// optionally prefixed with `-`
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key
+    /// * `config` - Configuration to read `versionsort.suffix` from
+    pub fn parse(key: &str, config: &Config) -> Result<Self> {
+        let (reverse, field) = match key.strip_prefix('-') {
+            Some(field) => (true, field),
+            None => (false, key),
+        };
+        let version = match field {
+            "refname" => false,
+            "version:refname" | "v:refname" => true,
+            _ => anyhow::bail!("unsupported sort key '{}'", key),
+        };
+        Ok(SortKey {
+            version,
+            reverse,
+            suffixes: config
+                .get_all("versionsort.suffix")
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        })
+    }
+
+    /// Compare two ref names
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        let order = if self.version {
+            version_cmp(a, b, &self.suffixes)
+        } else {
+            a.cmp(b)
+        };
+        if self.reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    }
+}
+
+/// Compare names containing version numbers, e.g. `v1.9` < `v1.10`
+///
+/// Runs of digits compare as numbers, everything else byte by byte. Where
+/// the names first differ, a name with one of `suffixes` there sorts
+/// before a name without, and earlier suffixes before later ones, so with
+/// `-rc` configured `v2.0-rc1` comes before `v2.0`.
+pub fn version_cmp(a: &str, b: &str, suffixes: &[String]) -> Ordering {
+    let first_difference = a
+        .bytes()
+        .zip(b.bytes())
+        .position(|(x, y)| x != y)
+        .unwrap_or(a.len().min(b.len()));
+    let suffix_at = |name: &str| {
+        suffixes.iter().position(|suffix| {
+            let start = first_difference.saturating_sub(suffix.len().saturating_sub(1));
+            (start..=first_difference).any(|i| {
+                name.get(i..)
+                    .is_some_and(|s| s.starts_with(suffix.as_str()))
+            })
+        })
+    };
+    match (suffix_at(a), suffix_at(b)) {
+        (Some(x), Some(y)) if x != y => return x.cmp(&y),
+        (Some(_), None) => return Ordering::Less,
+        (None, Some(_)) => return Ordering::Greater,
+        _ => {}
+    }
+
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    loop {
+        match (a.first(), b.first()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+                let (da, db) = (digits(a), digits(b));
+                let trim = |s: &[u8]| {
+                    let zeros = s.iter().take_while(|&&c| c == b'0').count();
+                    s[zeros..].to_vec()
+                };
+                let (na, nb) = (trim(&a[..da]), trim(&b[..db]));
+                let order = na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb));
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a = &a[da..];
+                b = &b[db..];
+            }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(y);
+                }
+                a = &a[1..];
+                b = &b[1..];
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_sort() {
+        let mut tags = vec!["v1.10", "v1.2", "v1.9.1", "v2.0", "v2.0-rc1", "v1.9"];
+        tags.sort_by(|a, b| version_cmp(a, b, &[]));
+        assert_eq!(
+            tags,
+            vec!["v1.2", "v1.9", "v1.9.1", "v1.10", "v2.0", "v2.0-rc1"]
+        );
+
+        let config = Config::parse("[versionsort]\n\tsuffix = -rc\n").unwrap();
+        let key = SortKey::parse("-version:refname", &config).unwrap();
+        tags.sort_by(|a, b| key.compare(a, b));
+        assert_eq!(
+            tags,
+            vec!["v2.0", "v2.0-rc1", "v1.10", "v1.9.1", "v1.9", "v1.2"]
+        );
+        assert!(SortKey::parse("creatordate", &config).is_err());
+    }
+}
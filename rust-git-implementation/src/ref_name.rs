@@ -0,0 +1,96 @@
+//! Ref name rules (`git check-ref-format`)
+//!
+//! Loose refs are files named after the ref, so a name decides where in
+//! the git dir a write lands. Names coming from outside, such as the refs
+//! a remote advertises, have to be checked before they are mapped to local
+//! refs: `refs/tags/../../config` must not reach the filesystem. A valid
+//! name is made of `/`-separated components, none of which
+//!
+//! - is empty, starts with `.` or ends with `.lock`,
+//! - contains `..`, `@{`, a control character, a space, or any of
+//!   `~ ^ : ? * [ \`,
+//!
+//! and the whole name doesn't end with `.` and isn't `@`. Like
+//! `git check-ref-format --allow-onelevel`, names without a `/` such as
+//! `HEAD` are accepted.
+
+use anyhow::Result;
+
+/// Check whether a ref name is valid
+pub fn is_valid(name: &str) -> bool {
+    if name.is_empty() || name == "@" || name.ends_with('.') {
+        return false;
+    }
+    if name.contains("..") || name.contains("@{") {
+        return false;
+    }
+    if name
+        .bytes()
+        .any(|b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b))
+    {
+        return false;
+    }
+    name.split('/').all(|component| {
+        !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+    })
+}
+
+/// Fail unless a ref name is valid
+///
+/// # Errors
+///
+/// Names the ref if it breaks any of the rules in the module docs
+pub fn check(name: &str) -> Result<()> {
+    if !is_valid(name) {
+        anyhow::bail!("'{}' is not a valid ref name", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ref_names() {
+        for name in [
+            "HEAD",
+            "refs/heads/main",
+            "refs/tags/v1.0",
+            "refs/heads/feature/a-b_c",
+            "refs/remotes/origin/HEAD",
+            "refs/heads/caf\u{e9}",
+        ] {
+            assert!(is_valid(name), "{}", name);
+        }
+        for name in [
+            "",
+            "@",
+            "refs/tags/../../../../victim-owned.txt",
+            "refs/heads/a..b",
+            "refs/heads/.hidden",
+            "refs/heads/main.lock",
+            "refs/heads/main/",
+            "/refs/heads/main",
+            "refs//heads",
+            "refs/heads/main.",
+            "refs/heads/a@{1}",
+            "refs/heads/a b",
+            "refs/heads/tab\there",
+            "refs/heads/del\u{7f}",
+            "refs/heads/a~1",
+            "refs/heads/a^",
+            "refs/heads/a:b",
+            "refs/heads/a?",
+            "refs/heads/a*",
+            "refs/heads/a[b",
+            "refs/heads/a\\b",
+        ] {
+            assert!(!is_valid(name), "{:?}", name);
+        }
+        assert_eq!(
+            check("refs/heads/a..b").unwrap_err().to_string(),
+            "'refs/heads/a..b' is not a valid ref name"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Reading refs from disk
+//!
+//! Refs live as loose files under `refs/` and as lines of `packed-refs`,
+//! with loose refs taking precedence. These readers list the direct refs
+//! without resolving symbolic ones such as `HEAD`.
+
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Read every direct ref (loose and packed), by full name
+///
+/// Symbolic refs are skipped, as `git for-each-ref` does. In a linked
+/// worktree, the shared refs come from the common dir and the
+/// worktree's own refs (such as `refs/bisect/`) from its git dir.
+pub fn read_refs(git_dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut refs = BTreeMap::new();
+    let common_dir = crate::repository::common_dir(git_dir);
+
+    let packed = common_dir.join("packed-refs");
+    if packed.exists() {
+        for line in fs::read_to_string(&packed)?.lines() {
+            if line.starts_with('#') || line.starts_with('^') {
+                continue;
+            }
+            if let Some((hash, name)) = line.split_once(' ') {
+                refs.insert(name.to_string(), hash.to_string());
+            }
+        }
+    }
+
+    // Loose refs override packed ones
+    read_loose_refs(&common_dir, &mut refs)?;
+    if common_dir != git_dir && git_dir.join("refs").is_dir() {
+        read_loose_refs(git_dir, &mut refs)?;
+    }
+
+    Ok(refs)
+}
+
+/// Add the loose direct refs under `<dir>/refs`
+fn read_loose_refs(dir: &Path, refs: &mut BTreeMap<String, String>) -> Result<()> {
+    for entry in walkdir::WalkDir::new(dir.join("refs")) {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let value = fs::read_to_string(entry.path())?;
+        let value = value.trim();
+        if value.starts_with("ref: ") {
+            continue;
+        }
+        let name = entry
+            .path()
+            .strip_prefix(dir)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        refs.insert(name, value.to_string());
+    }
+    Ok(())
+}
+
+/// Read the peeled values recorded in `packed-refs`
+///
+/// Maps each packed ref to its packed value and, for annotated tags, the
+/// object the tag chain ends at. Only files with the `fully-peeled` trait
+/// record every peeled value, so older files yield an empty map.
+pub fn read_packed_peeled(git_dir: &Path) -> Result<BTreeMap<String, (String, Option<String>)>> {
+    let mut refs = BTreeMap::new();
+    let packed = crate::repository::common_dir(git_dir).join("packed-refs");
+    let Ok(text) = fs::read_to_string(packed) else {
+        return Ok(refs);
+    };
+    let mut lines = text.lines().peekable();
+    match lines.next() {
+        Some(header) if header.starts_with('#') && header.contains(" fully-peeled") => {}
+        _ => return Ok(refs),
+    }
+    while let Some(line) = lines.next() {
+        let Some((hash, name)) = line.split_once(' ') else {
+            continue;
+        };
+        let peeled = lines
+            .next_if(|next| next.starts_with('^'))
+            .map(|next| next[1..].to_string());
+        refs.insert(name.to_string(), (hash.to_string(), peeled));
+    }
+    Ok(refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_read_refs() {
+        let dir = tempdir().unwrap();
+        let git_dir = dir.path();
+        fs::create_dir_all(git_dir.join("refs/heads/feature")).unwrap();
+        fs::create_dir_all(git_dir.join("refs/tags")).unwrap();
+        fs::write(git_dir.join("refs/heads/main"), "a".repeat(40) + "\n").unwrap();
+        fs::write(git_dir.join("refs/heads/feature/x"), "b".repeat(40)).unwrap();
+        fs::write(git_dir.join("refs/heads/link"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled\n{} refs/heads/main\n{} refs/tags/v1\n^{}\n",
+                "c".repeat(40),
+                "d".repeat(40),
+                "e".repeat(40)
+            ),
+        )
+        .unwrap();
+
+        let refs = read_refs(git_dir).unwrap();
+        assert_eq!(refs.len(), 3);
+        assert_eq!(refs["refs/heads/main"], "a".repeat(40));
+        assert_eq!(refs["refs/heads/feature/x"], "b".repeat(40));
+        assert_eq!(refs["refs/tags/v1"], "d".repeat(40));
+
+        // Only fully peeled files say which refs aren't tags
+        assert!(read_packed_peeled(git_dir).unwrap().is_empty());
+        let packed = fs::read_to_string(git_dir.join("packed-refs")).unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            packed.replace("peeled", "peeled fully-peeled sorted"),
+        )
+        .unwrap();
+        let peeled = read_packed_peeled(git_dir).unwrap();
+        assert_eq!(peeled["refs/heads/main"], ("c".repeat(40), None));
+        assert_eq!(
+            peeled["refs/tags/v1"],
+            ("d".repeat(40), Some("e".repeat(40)))
+        );
+    }
+}
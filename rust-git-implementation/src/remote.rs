@@ -0,0 +1,241 @@
+//! Custom transports
+//!
+//! Library users can plug in their own transport for a URL scheme, e.g. an
+//! experimental storage backend:
+//!
+//! ```no_run
+//! use oxid::remote::{register_transport, Transport};
+//! use std::collections::BTreeMap;
+//! use std::io::Read;
+//!
+//! struct Ipfs;
+//!
+//! impl Transport for Ipfs {
+//!     fn refs(&mut self) -> anyhow::Result<BTreeMap<String, String>> {
+//!         Ok(BTreeMap::new())
+//!     }
+//!
+//!     fn fetch(&mut self, wants: &[String], common: &[String]) -> anyhow::Result<Box<dyn Read + '_>> {
+//!         anyhow::bail!("nothing to fetch")
+//!     }
+//! }
+//!
+//! register_transport("ipfs", |_url| Ok(Box::new(Ipfs)));
+//! ```
+//!
+//! `oxid fetch ipfs://...` then lists the refs through the transport,
+//! offers the local ref tips with [`Transport::have`], and unpacks the pack
+//! it returns (see [`crate::unpack`]). Registered transports take
+//! precedence over remote helpers and the built-in HTTP transport.
+
+use crate::repository::Repository;
+use anyhow::Result;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// A way to reach remote repositories
+pub trait Transport {
+    /// The remote's refs, by full name
+    fn refs(&mut self) -> Result<BTreeMap<String, String>>;
+
+    /// The branch the remote's `HEAD` points to, e.g. `refs/heads/main`
+    fn head(&mut self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Offer an object the local repository has
+    ///
+    /// Returns whether the remote has it too; common objects and their
+    /// history can be left out of the pack. The default knows of nothing
+    /// in common.
+    fn have(&mut self, hash: &str) -> Result<bool> {
+        let _ = hash;
+        Ok(false)
+    }
+
+    /// Produce a pack of everything reachable from `wants` and not from
+    /// `common`
+    ///
+    /// # Arguments
+    ///
+    /// * `wants` - Object ids to fetch
+    /// * `common` - Objects both sides have, as acknowledged by [`Transport::have`]
+    fn fetch(&mut self, wants: &[String], common: &[String]) -> Result<Box<dyn Read + '_>>;
+}
+
+/// Creates a transport for a URL
+pub type TransportFactory = dyn Fn(&str) -> Result<Box<dyn Transport>> + Send + Sync;
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<TransportFactory>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, Arc<TransportFactory>>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Handle URLs of a scheme (`<scheme>://...`) with a custom transport
+///
+/// Replaces any transport registered for the scheme before.
+///
+/// # Arguments
+///
+/// * `scheme` - The URL scheme, e.g. `ipfs`
+/// * `factory` - Called with the full URL for each connection
+pub fn register_transport<F>(scheme: &str, factory: F)
+where
+    F: Fn(&str) -> Result<Box<dyn Transport>> + Send + Sync + 'static,
+{
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(scheme.to_ascii_lowercase(), Arc::new(factory));
+}
+
+/// Remove the transport for a scheme
+///
+/// Returns whether one was registered.
+pub fn unregister_transport(scheme: &str) -> bool {
+    registry()
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&scheme.to_ascii_lowercase())
+        .is_some()
+}
+
+/// Connect with the registered transport for a URL's scheme
+///
+/// Returns `None` if no transport is registered for it.
+pub fn open(url: &str) -> Option<Result<Box<dyn Transport>>> {
+    let (scheme, _) = url.split_once("://")?;
+    let factory = registry()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&scheme.to_ascii_lowercase())
+        .cloned()?;
+    Some(factory(url))
+}
+
+/// Fetch objects through a transport
+///
+/// Wanted objects the repository already has are skipped. The local ref
+/// tips are offered to the transport as haves, and the pack it returns is
+/// unpacked into a quarantine, checked, and moved into the repository.
+///
+/// Returns the hashes of the objects received.
+///
+/// # Arguments
+///
+/// * `repo` - The repository to fetch into
+/// * `transport` - The connection to fetch from
+/// * `wants` - Object ids to fetch, typically values from [`Transport::refs`]
+pub fn fetch(
+    repo: &Repository,
+    transport: &mut dyn Transport,
+    wants: &[String],
+) -> Result<Vec<String>> {
+    let wants: Vec<String> = wants
+        .iter()
+        .filter(|hash| !repo.has_object(hash))
+        .cloned()
+        .collect();
+    if wants.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut common = Vec::new();
+    let mut tips: Vec<String> = crate::refs::read_refs(&repo.git_dir)?
+        .into_values()
+        .filter(|hash| repo.has_object(hash))
+        .collect();
+    tips.sort();
+    tips.dedup();
+    for tip in tips {
+        if transport.have(&tip)? {
+            common.push(tip);
+        }
+    }
+
+    let limits = repo.limits()?;
+    let quarantine = repo.quarantine()?;
+    let pack = transport.fetch(&wants, &common)?;
+    crate::unpack::unpack_objects(&quarantine, &limits, pack)?;
+    for want in &wants {
+        quarantine
+            .read_object(want)
+            .map_err(|_| anyhow::anyhow!("transport didn't send wanted object {}", want))?;
+    }
+    quarantine.validate(|_hash, data| crate::fsck::check_received(data))?;
+    quarantine.migrate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    /// Serves every object of a repository, recording the haves offered
+    struct Local {
+        source: Repository,
+        offered: Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Transport for Local {
+        fn refs(&mut self) -> Result<BTreeMap<String, String>> {
+            crate::refs::read_refs(&self.source.git_dir)
+        }
+
+        fn have(&mut self, hash: &str) -> Result<bool> {
+            self.offered.lock().unwrap().push(hash.to_string());
+            Ok(self.source.has_object(hash))
+        }
+
+        fn fetch(&mut self, _wants: &[String], _common: &[String]) -> Result<Box<dyn Read + '_>> {
+            let objects = self
+                .source
+                .objects()?
+                .map(|hash| self.source.read_object(&hash))
+                .collect::<Result<Vec<_>>>()?;
+            let pack = crate::unpack::write_pack(objects.iter().map(|o| &o[..]))?;
+            Ok(Box::new(std::io::Cursor::new(pack)))
+        }
+    }
+
+    #[test]
+    fn test_registered_transport() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let tip = builder.file("a", "1\n").commit("first").unwrap();
+        let source_dir = builder.repo().git_dir.clone();
+        let offered = Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let seen = offered.clone();
+        register_transport("oxid-test", move |url| {
+            assert_eq!(url, "oxid-test://somewhere");
+            Ok(Box::new(Local {
+                source: Repository::new(source_dir.parent().unwrap())?,
+                offered: seen.clone(),
+            }))
+        });
+        assert!(open("http://example.com").is_none());
+        let mut transport = open("oxid-test://somewhere").unwrap().unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let local = Repository::init(dir.path()).unwrap();
+        let wants: Vec<String> = transport.refs().unwrap().into_values().collect();
+        assert_eq!(fetch(&local, transport.as_mut(), &wants).unwrap().len(), 3);
+        assert!(local.has_object(&tip));
+        // Nothing to do once the objects are here
+        assert!(fetch(&local, transport.as_mut(), &wants)
+            .unwrap()
+            .is_empty());
+        assert!(offered.lock().unwrap().is_empty());
+
+        // Local ref tips are offered as haves
+        std::fs::write(local.git_dir.join("refs/heads/main"), format!("{}\n", tip)).unwrap();
+        let next = builder.file("a", "2\n").commit("second").unwrap();
+        fetch(&local, transport.as_mut(), std::slice::from_ref(&next)).unwrap();
+        assert!(local.has_object(&next));
+        assert_eq!(*offered.lock().unwrap(), vec![tip]);
+
+        assert!(unregister_transport("oxid-test"));
+        assert!(open("oxid-test://somewhere").is_none());
+    }
+}
@@ -0,0 +1,424 @@
+//! Remote helpers (`git-remote-<transport>`)
+//!
+//! Transports oxid doesn't implement can be delegated to an external
+//! helper program, as Git does. A URL of the form `<transport>::<address>`,
+//! or one whose scheme oxid doesn't speak (`hg://...`), runs
+//! `git-remote-<transport> <remote name> <address>` from `PATH` with
+//! `GIT_DIR` set to the local repository, and talks to it over stdin and
+//! stdout with newline-terminated commands:
+//!
+//! - `capabilities`: the helper lists what it supports, one per line. A
+//!   leading `*` marks a capability we must understand or give up.
+//! - `list` / `list for-push`: the remote's refs as
+//!   `<value> <name> [attributes]`, where the value is an object id, `@<ref>`
+//!   for a symbolic ref or `?` when unknown.
+//! - `fetch <id> <name>`: a batch of lines ended by a blank line; the
+//!   helper stores the objects in `GIT_DIR` and answers with a blank line.
+//! - `push [+]<src>:<dst>`: a batch likewise; the helper answers
+//!   `ok <dst>` or `error <dst> [<why>]` for each ref, then a blank line.
+//!
+//! Every response ends with a blank line. Helpers usually write the fetched
+//! objects as packs, which oxid can't read yet, so fetched refs may name
+//! objects only Git can open.
+
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Capabilities this client implements
+const SUPPORTED: &[&str] = &["fetch", "push", "option", "check-connectivity"];
+
+/// URL schemes handled without a helper
+const NATIVE_SCHEMES: &[&str] = &["http", "https"];
+
+/// Find the helper for a URL
+///
+/// Returns the transport name and the address to pass to the helper, or
+/// `None` for URLs oxid handles itself.
+pub fn transport_for(url: &str) -> Option<(&str, &str)> {
+    if let Some((transport, address)) = url.split_once("::") {
+        if !transport.is_empty() && transport.chars().all(is_transport_char) {
+            return Some((transport, address));
+        }
+    }
+    let (scheme, _) = url.split_once("://")?;
+    if scheme.is_empty()
+        || !scheme.chars().all(is_transport_char)
+        || NATIVE_SCHEMES.contains(&scheme)
+    {
+        return None;
+    }
+    Some((scheme, url))
+}
+
+fn is_transport_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')
+}
+
+/// What a listed ref points at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    /// An object id
+    Hash(String),
+    /// Another ref (`@refs/heads/main`)
+    Symref(String),
+    /// Not known until fetched (`?`)
+    Unknown,
+}
+
+/// One line of a `list` response
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListedRef {
+    pub name: String,
+    pub value: RefValue,
+    /// Attributes after the name, e.g. `unchanged`
+    pub attributes: Vec<String>,
+}
+
+/// The result of pushing one ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushStatus {
+    /// The remote ref's full name
+    pub name: String,
+    /// `None` if the update succeeded, else the helper's reason
+    pub error: Option<String>,
+}
+
+/// A running helper
+pub struct Helper {
+    program: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    capabilities: Vec<String>,
+}
+
+impl Helper {
+    /// Start `git-remote-<transport>` from `PATH`
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The local repository, exported as `GIT_DIR`
+    /// * `transport` - The helper's transport name, e.g. `hg`
+    /// * `remote` - The remote's name, e.g. `origin`
+    /// * `address` - What the helper should connect to
+    pub fn spawn(repo: &Repository, transport: &str, remote: &str, address: &str) -> Result<Self> {
+        let program = format!("git-remote-{}", transport);
+        Self::spawn_program(repo, Path::new(&program), remote, address)
+    }
+
+    /// Start a helper program given by path
+    pub fn spawn_program(
+        repo: &Repository,
+        program: &Path,
+        remote: &str,
+        address: &str,
+    ) -> Result<Self> {
+        let name = program.display().to_string();
+        let mut child = Command::new(program)
+            .arg(remote)
+            .arg(address)
+            .env("GIT_DIR", &repo.git_dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to run remote helper {}", name))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut helper = Helper {
+            program: name,
+            child,
+            stdin,
+            stdout,
+            capabilities: Vec::new(),
+        };
+
+        helper.send("capabilities\n")?;
+        for line in helper.read_response()? {
+            match line.strip_prefix('*') {
+                Some(required) if !SUPPORTED.contains(&capability_name(required)) => {
+                    anyhow::bail!(
+                        "remote helper {} requires unknown capability {}",
+                        helper.program,
+                        required
+                    );
+                }
+                Some(required) => helper.capabilities.push(required.to_string()),
+                None => helper.capabilities.push(line),
+            }
+        }
+        Ok(helper)
+    }
+
+    /// The capabilities the helper announced, without `*` markers
+    pub fn capabilities(&self) -> &[String] {
+        &self.capabilities
+    }
+
+    /// Whether the helper announced a capability
+    pub fn supports(&self, name: &str) -> bool {
+        self.capabilities.iter().any(|c| capability_name(c) == name)
+    }
+
+    fn send(&mut self, text: &str) -> Result<()> {
+        self.stdin
+            .write_all(text.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .with_context(|| format!("remote helper {} exited", self.program))
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            anyhow::bail!("remote helper {} closed its output", self.program);
+        }
+        Ok(line.trim_end_matches('\n').to_string())
+    }
+
+    /// Read lines up to the blank line ending a response
+    fn read_response(&mut self) -> Result<Vec<String>> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+
+    fn require(&self, capability: &str) -> Result<()> {
+        if !self.supports(capability) {
+            anyhow::bail!(
+                "remote helper {} doesn't support {}",
+                self.program,
+                capability
+            );
+        }
+        Ok(())
+    }
+
+    /// Set a transport option, e.g. `verbosity 1`
+    ///
+    /// Returns whether the helper accepted it.
+    pub fn option(&mut self, name: &str, value: &str) -> Result<bool> {
+        if !self.supports("option") {
+            return Ok(false);
+        }
+        self.send(&format!("option {} {}\n", name, value))?;
+        match self.read_line()?.as_str() {
+            "ok" => Ok(true),
+            "unsupported" => Ok(false),
+            other => anyhow::bail!("option {}: {}", name, other),
+        }
+    }
+
+    /// List the remote's refs
+    ///
+    /// # Arguments
+    ///
+    /// * `for_push` - Ask for the refs as a push would see them
+    pub fn list(&mut self, for_push: bool) -> Result<Vec<ListedRef>> {
+        self.send(if for_push {
+            "list for-push\n"
+        } else {
+            "list\n"
+        })?;
+        let mut refs = Vec::new();
+        for line in self.read_response()? {
+            let mut words = line.split(' ');
+            let (Some(value), Some(name)) = (words.next(), words.next()) else {
+                anyhow::bail!("malformed list line from remote helper: {}", line);
+            };
+            let value = if value == "?" {
+                RefValue::Unknown
+            } else if let Some(target) = value.strip_prefix('@') {
+                RefValue::Symref(target.to_string())
+            } else if value.len() == 40 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+                RefValue::Hash(value.to_ascii_lowercase())
+            } else {
+                anyhow::bail!("malformed object id from remote helper: {}", value);
+            };
+            refs.push(ListedRef {
+                name: name.to_string(),
+                value,
+                attributes: words.map(str::to_string).collect(),
+            });
+        }
+        Ok(refs)
+    }
+
+    /// Have the helper download the objects for some refs
+    ///
+    /// # Arguments
+    ///
+    /// * `refs` - `(object id, ref name)` pairs from [`Helper::list`]
+    pub fn fetch(&mut self, refs: &[(String, String)]) -> Result<()> {
+        self.require("fetch")?;
+        if refs.is_empty() {
+            return Ok(());
+        }
+        let mut batch = String::new();
+        for (hash, name) in refs {
+            batch.push_str(&format!("fetch {} {}\n", hash, name));
+        }
+        batch.push('\n');
+        self.send(&batch)?;
+        // `lock <file>` and `connectivity-ok` may precede the blank line
+        self.read_response()?;
+        Ok(())
+    }
+
+    /// Have the helper update remote refs
+    ///
+    /// Returns one status per ref the helper reported on.
+    ///
+    /// # Arguments
+    ///
+    /// * `specs` - Refspecs such as `refs/heads/main:refs/heads/main`, with
+    ///   a leading `+` to force
+    pub fn push(&mut self, specs: &[String]) -> Result<Vec<PushStatus>> {
+        self.require("push")?;
+        if specs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut batch = String::new();
+        for spec in specs {
+            batch.push_str(&format!("push {}\n", spec));
+        }
+        batch.push('\n');
+        self.send(&batch)?;
+
+        let mut statuses = Vec::new();
+        for line in self.read_response()? {
+            let status = if let Some(name) = line.strip_prefix("ok ") {
+                PushStatus {
+                    name: name.to_string(),
+                    error: None,
+                }
+            } else if let Some(rest) = line.strip_prefix("error ") {
+                let (name, why) = rest.split_once(' ').unwrap_or((rest, "failed"));
+                PushStatus {
+                    name: name.to_string(),
+                    error: Some(why.to_string()),
+                }
+            } else {
+                anyhow::bail!("unexpected push response from remote helper: {}", line);
+            };
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+
+    /// End the session and wait for the helper to exit
+    pub fn finish(self) -> Result<()> {
+        let Helper {
+            program,
+            mut child,
+            stdin,
+            ..
+        } = self;
+        drop(stdin);
+        let status = child.wait()?;
+        if !status.success() {
+            anyhow::bail!("remote helper {} failed: {}", program, status);
+        }
+        Ok(())
+    }
+}
+
+/// The name of a capability line such as `refspec refs/heads/*:...`
+fn capability_name(line: &str) -> &str {
+    line.split(' ').next().unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_for() {
+        assert_eq!(
+            transport_for("hg::https://example.com/repo"),
+            Some(("hg", "https://example.com/repo"))
+        );
+        assert_eq!(
+            transport_for("s3://bucket/repo"),
+            Some(("s3", "s3://bucket/repo"))
+        );
+        assert_eq!(transport_for("http://example.com/repo.git"), None);
+        assert_eq!(transport_for("/srv/repo.git"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_helper_session() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path().join("repo")).unwrap();
+        let script = dir.path().join("git-remote-test");
+        let main = "a".repeat(40);
+        std::fs::write(
+            &script,
+            format!(
+                r#"#!/bin/sh
+test -d "$GIT_DIR" || exit 1
+while read cmd args; do
+    case "$cmd" in
+    capabilities) printf 'fetch\npush\n*option\n\n' ;;
+    option) echo unsupported ;;
+    list) printf '@refs/heads/main HEAD\n{main} refs/heads/main\n? refs/heads/lazy\n\n' ;;
+    fetch) while read line && test -n "$line"; do :; done
+        echo "$args" >"$GIT_DIR/fetched"; echo ;;
+    push) while read line && test -n "$line"; do :; done
+        printf 'ok %s\nerror refs/heads/x non-fast-forward\n\n' "${{args#*:}}" ;;
+    "") ;;
+    esac
+done
+"#
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut helper = Helper::spawn_program(&repo, &script, "origin", "somewhere").unwrap();
+        assert!(helper.supports("fetch") && helper.supports("option"));
+        assert!(!helper.option("depth", "1").unwrap());
+
+        let refs = helper.list(false).unwrap();
+        assert_eq!(refs.len(), 3);
+        assert_eq!(
+            refs[0].value,
+            RefValue::Symref("refs/heads/main".to_string())
+        );
+        assert_eq!(refs[1].value, RefValue::Hash(main.clone()));
+        assert_eq!(refs[2].value, RefValue::Unknown);
+
+        helper
+            .fetch(&[(main.clone(), "refs/heads/main".to_string())])
+            .unwrap();
+        let fetched = std::fs::read_to_string(repo.git_dir.join("fetched")).unwrap();
+        assert_eq!(fetched.trim(), format!("{} refs/heads/main", main));
+
+        let statuses = helper
+            .push(&["refs/heads/main:refs/heads/main".to_string()])
+            .unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                PushStatus {
+                    name: "refs/heads/main".to_string(),
+                    error: None
+                },
+                PushStatus {
+                    name: "refs/heads/x".to_string(),
+                    error: Some("non-fast-forward".to_string())
+                }
+            ]
+        );
+        helper.finish().unwrap();
+    }
+}
@@ -1,8 +1,15 @@
 //! Repository structure and operations
 
+use crate::cache::ObjectCache;
+use crate::config::Config;
+use crate::limits::Limits;
+use crate::objects::{parse_header, Commit, ObjectType};
+use crate::odb::{LooseObjects, OdbBackend};
 use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
 /// Represents an oxid/Git repository
 ///
@@ -11,16 +18,104 @@ use std::path::{Path, PathBuf};
 /// - Working tree with project files
 pub struct Repository {
     /// Path to the .git directory
+    ///
+    /// For a linked worktree this is its own directory under
+    /// `.git/worktrees/`, holding `HEAD`, the index and `config.worktree`.
     pub git_dir: PathBuf,
-    /// Path to the working directory
+    /// Where the data shared by all worktrees lives: objects, refs and
+    /// `config`; the same as `git_dir` except in linked worktrees
+    pub common_dir: PathBuf,
+    /// Path to the working directory (the git dir itself for bare repositories)
     pub work_tree: PathBuf,
+    /// Whether the repository has no working tree
+    pub bare: bool,
+    /// Opened with [`Repository::open_snapshot`]: writes are refused
+    snapshot: bool,
+    /// Limits for object parsing, read from the config on first use
+    limits: OnceLock<Limits>,
+    /// Whether reads go through `refs/replace/`; see [`Repository::set_replace_objects`]
+    replace_objects: bool,
+    /// Replacement objects by the hash they replace, read on first use
+    replacements: OnceLock<BTreeMap<String, String>>,
+    /// Commits at the edge of a shallow clone, read on first use
+    shallow: OnceLock<BTreeSet<String>>,
+    /// Recently parsed commits and trees, sized from the config on first use
+    cache: OnceLock<ObjectCache>,
+    /// Where objects are stored
+    odb: Box<dyn OdbBackend>,
+}
+
+/// Options for [`Repository::init_with`]
+#[derive(Debug, Clone, Default)]
+pub struct InitOptions {
+    /// Create a bare repository (no working tree, git dir at `path`)
+    pub bare: bool,
+    /// Branch `HEAD` points to; defaults to `init.defaultBranch` or `main`
+    pub initial_branch: Option<String>,
+    /// Directory whose files are copied into the new git dir; defaults to
+    /// `GIT_TEMPLATE_DIR` or `init.templateDir`
+    pub template_dir: Option<PathBuf>,
+    /// Store the git dir here and leave a `.git` file pointing to it
+    pub separate_git_dir: Option<PathBuf>,
+}
+
+impl InitOptions {
+    /// Where the git dir for a repository at `path` goes
+    pub fn git_dir(&self, path: &Path) -> PathBuf {
+        if let Some(separate) = &self.separate_git_dir {
+            separate.clone()
+        } else if self.bare {
+            path.to_path_buf()
+        } else {
+            path.join(".git")
+        }
+    }
+
+    /// Whether initializing at `path` would re-initialize an existing repository
+    pub fn is_reinit(&self, path: &Path) -> bool {
+        self.git_dir(path).join("HEAD").exists()
+    }
+
+    /// Resolve the initial branch name from the options or global config
+    fn initial_branch(&self) -> Result<String> {
+        let branch = match &self.initial_branch {
+            Some(branch) => branch.clone(),
+            None => Config::global()?
+                .get("init.defaultBranch")
+                .unwrap_or("main")
+                .to_string(),
+        };
+
+        if branch.starts_with('-')
+            || branch == "HEAD"
+            || !crate::ref_name::is_valid(&format!("refs/heads/{}", branch))
+        {
+            anyhow::bail!("Invalid initial branch name: '{}'", branch);
+        }
+        Ok(branch)
+    }
+
+    /// Resolve the template directory from the options, environment or config
+    fn template_dir(&self) -> Option<PathBuf> {
+        self.template_dir
+            .clone()
+            .or_else(|| std::env::var_os("GIT_TEMPLATE_DIR").map(PathBuf::from))
+            .or_else(|| {
+                Config::global()
+                    .ok()?
+                    .get("init.templateDir")
+                    .map(PathBuf::from)
+            })
+    }
 }
 
 impl Repository {
     /// Open an existing repository
     ///
     /// Searches for a .git directory starting from the given path
-    /// and walking up the directory tree.
+    /// and walking up the directory tree. The search honors the
+    /// `GIT_CEILING_DIRECTORIES` and `GIT_DISCOVERY_ACROSS_FILESYSTEM`
+    /// environment variables; see [`Repository::discover`].
     ///
     /// # Arguments
     ///
@@ -30,31 +125,269 @@ impl Repository {
     ///
     /// Returns an error if no repository is found
     pub fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let ceiling_dirs = std::env::var_os("GIT_CEILING_DIRECTORIES")
+            .map(|v| parse_ceiling_dirs(&v))
+            .unwrap_or_default();
+        let across_fs = std::env::var("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+            .ok()
+            .and_then(|v| crate::config::parse_bool(&v))
+            .unwrap_or(false);
+
+        Self::discover(path, &ceiling_dirs, across_fs)
+    }
+
+    /// Open an existing repository for reading only
+    ///
+    /// A snapshot never takes locks and never writes to the git dir: object
+    /// and index writes fail instead. Nothing is refreshed behind the
+    /// caller's back either, so tools that poll the repository (editor
+    /// plugins showing status, prompts) can't disturb the user's own
+    /// commands running at the same time. Discovery works as in
+    /// [`Repository::new`].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Starting path to search from
+    pub fn open_snapshot(path: impl AsRef<Path>) -> Result<Self> {
+        let mut repo = Self::new(path)?;
+        repo.snapshot = true;
+        Ok(repo)
+    }
+
+    /// Whether the repository was opened with [`Repository::open_snapshot`]
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot
+    }
+
+    /// Limits applied when reading objects
+    ///
+    /// Read from the configuration the first time they are needed (see
+    /// [`crate::limits`]) unless set with [`Repository::set_limits`].
+    pub fn limits(&self) -> Result<Limits> {
+        if let Some(limits) = self.limits.get() {
+            return Ok(*limits);
+        }
+        let limits = Limits::from_config(&self.config()?)?;
+        Ok(*self.limits.get_or_init(|| limits))
+    }
+
+    /// Override the limits applied when reading objects
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = OnceLock::from(limits);
+    }
+
+    /// Objects replaced through `refs/replace/<hash>`, keyed by the hash
+    /// they replace
+    ///
+    /// Refs are read the first time they are needed, under
+    /// `GIT_REPLACE_REF_BASE` if set (see [`replace_ref_base`]). The map is
+    /// empty when replacement is turned off, with
+    /// [`Repository::set_replace_objects`], `GIT_NO_REPLACE_OBJECTS` or
+    /// `core.useReplaceRefs = false`.
+    pub fn replacements(&self) -> Result<&BTreeMap<String, String>> {
+        if let Some(replacements) = self.replacements.get() {
+            return Ok(replacements);
+        }
+        let mut replacements = BTreeMap::new();
+        if self.replace_objects
+            && std::env::var_os("GIT_NO_REPLACE_OBJECTS").is_none()
+            && self
+                .config()?
+                .get_bool("core.useReplaceRefs")?
+                .unwrap_or(true)
+        {
+            let base = replace_ref_base();
+            for (name, hash) in crate::refs::read_refs(&self.git_dir)? {
+                if let Some(replaced) = name.strip_prefix(base.as_str()) {
+                    replacements.insert(replaced.to_string(), hash);
+                }
+            }
+        }
+        Ok(self.replacements.get_or_init(|| replacements))
+    }
+
+    /// Turn object replacement on or off
+    ///
+    /// Commands that must see objects as they are stored, such as
+    /// `replace` itself or `upload-pack`, turn it off. Cached objects are
+    /// dropped, since they may have been read through a replacement.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether reads follow `refs/replace/`
+    pub fn set_replace_objects(&mut self, enabled: bool) {
+        self.replace_objects = enabled;
+        self.replacements = OnceLock::new();
+        if let Some(cache) = self.cache.get() {
+            cache.clear();
+        }
+    }
+
+    /// The object that reads of `hash` return
+    ///
+    /// That's `hash` itself unless it has been replaced. A replacement may
+    /// be replaced in turn; like Git, at most 5 replacements are followed.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to look up
+    pub fn replacement_of(&self, hash: &str) -> Result<String> {
+        const MAX_DEPTH: usize = 5;
+
+        let replacements = self.replacements()?;
+        let mut current = hash;
+        for _ in 0..MAX_DEPTH {
+            match replacements.get(current) {
+                Some(next) => current = next,
+                None => return Ok(current.to_string()),
+            }
+        }
+        if replacements.contains_key(current) {
+            anyhow::bail!("replace depth too high for object {}", hash);
+        }
+        Ok(current.to_string())
+    }
+
+    /// Commits whose parents a shallow clone or fetch left out
+    ///
+    /// Read from `.git/shallow`, one hash per line, the first time they
+    /// are needed; empty for a complete repository.
+    /// [`Repository::read_commit`] gives these commits no parents, so
+    /// history walks stop at them instead of failing on missing objects.
+    pub fn shallow_commits(&self) -> Result<&BTreeSet<String>> {
+        if let Some(shallow) = self.shallow.get() {
+            return Ok(shallow);
+        }
+        let shallow = match fs::read_to_string(self.common_dir.join("shallow")) {
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e).context("Failed to read shallow"),
+        };
+        Ok(self.shallow.get_or_init(|| shallow))
+    }
+
+    /// Whether history was cut short by a shallow clone or fetch
+    pub fn is_shallow(&self) -> Result<bool> {
+        Ok(!self.shallow_commits()?.is_empty())
+    }
+
+    /// The object storage in use
+    pub fn odb(&self) -> &dyn OdbBackend {
+        self.odb.as_ref()
+    }
+
+    /// Store objects somewhere other than the loose object directory
+    ///
+    /// Objects already in the previous backend aren't copied over. See
+    /// [`crate::odb`].
+    ///
+    /// # Arguments
+    ///
+    /// * `odb` - The backend to read and write objects through
+    pub fn set_odb(&mut self, odb: Box<dyn OdbBackend>) {
+        self.odb = odb;
+        if let Some(cache) = self.cache.get() {
+            cache.clear();
+        }
+    }
+
+    /// Fail if the repository may not be written to
+    fn ensure_writable(&self) -> Result<()> {
+        if self.snapshot {
+            anyhow::bail!("repository was opened as a read-only snapshot");
+        }
+        Ok(())
+    }
+
+    /// Search for a repository with explicit discovery limits
+    ///
+    /// Starting at `path`, each directory is checked for a `.git` directory
+    /// before moving to its parent. The search stops without moving up into
+    /// any of the `ceiling_dirs`, and, unless `across_fs` is set, without
+    /// crossing onto a different filesystem (e.g. a network mount).
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Starting path to search from
+    /// * `ceiling_dirs` - Absolute directories the search must not enter
+    /// * `across_fs` - Whether to continue past filesystem boundaries
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no repository is found within the limits
+    pub fn discover(
+        path: impl AsRef<Path>,
+        ceiling_dirs: &[PathBuf],
+        across_fs: bool,
+    ) -> Result<Self> {
         let mut current = path.as_ref().canonicalize()?;
+        let ceiling_dirs: Vec<PathBuf> = ceiling_dirs
+            .iter()
+            .map(|dir| dir.canonicalize().unwrap_or_else(|_| dir.clone()))
+            .collect();
+        let start_device = device_id(&current)?;
 
         // Walk up directory tree looking for .git
         loop {
             let git_dir = current.join(".git");
-            if git_dir.exists() && git_dir.is_dir() {
-                return Ok(Repository {
-                    work_tree: current,
-                    git_dir,
-                });
+            let found = if git_dir.is_dir() {
+                Some(Repository::open_at(git_dir, current.clone(), false))
+            } else if git_dir.is_file() {
+                Some(Repository::open_at(
+                    read_git_file(&git_dir)?,
+                    current.clone(),
+                    false,
+                ))
+            } else if is_git_dir(&current) {
+                Some(Repository::open_at(current.clone(), current.clone(), true))
+            } else {
+                None
+            };
+
+            if let Some(repo) = found {
+                // Refuse repositories owned by other users (safe.directory)
+                crate::safe_directory::check(&current, &repo.git_dir)?;
+                // and ones in a format oxid would misread
+                crate::extensions::check(&Config::read(repo.common_dir.join("config"))?)
+                    .with_context(|| {
+                        format!("Cannot open repository at {}", repo.git_dir.display())
+                    })?;
+                return Ok(repo);
             }
 
-            // Move to parent directory
-            if !current.pop() {
+            // Move to parent directory, unless a limit stops us
+            let parent = match current.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => break,
+            };
+            if ceiling_dirs.contains(&parent) {
+                break;
+            }
+            if !across_fs && device_id(&parent)? != start_device {
                 anyhow::bail!(
-                    "Not an oxid repository (or any parent): {}",
-                    path.as_ref().display()
+                    "Not an oxid repository (or any parent up to mount point {})\n\
+                     Stopping at filesystem boundary (GIT_DISCOVERY_ACROSS_FILESYSTEM not set).",
+                    current.display()
                 );
             }
+            current = parent;
         }
+
+        anyhow::bail!(
+            "Not an oxid repository (or any parent): {}",
+            path.as_ref().display()
+        );
     }
 
     /// Initialize a new repository
     ///
     /// Creates a .git directory structure with all necessary files and folders.
+    /// Equivalent to [`Repository::init_with`] with default options.
     ///
     /// # Arguments
     ///
@@ -68,8 +401,43 @@ impl Repository {
     /// let repo = Repository::init("my-project").unwrap();
     /// ```
     pub fn init(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init_with(path, &InitOptions::default())
+    }
+
+    /// Initialize a new repository, or re-initialize an existing one
+    ///
+    /// Re-initializing is safe: missing directories and template files are
+    /// added, but existing files like `HEAD` and `config` are left untouched.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path where the repository should be initialized
+    /// * `options` - Bare mode, initial branch, template and git dir location
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use oxid::repository::{InitOptions, Repository};
+    ///
+    /// let options = InitOptions {
+    ///     bare: true,
+    ///     initial_branch: Some("trunk".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// let repo = Repository::init_with("project.git", &options).unwrap();
+    /// ```
+    pub fn init_with(path: impl AsRef<Path>, options: &InitOptions) -> Result<Self> {
         let work_tree = path.as_ref();
-        let git_dir = work_tree.join(".git");
+        let _span = tracing::debug_span!("init", path = %work_tree.display()).entered();
+        let git_dir = options.git_dir(work_tree);
+        let reinit = options.is_reinit(work_tree);
+
+        // Settle the options first, so a bad one leaves nothing behind
+        let new = if reinit {
+            None
+        } else {
+            Some(options.initial_branch()?)
+        };
 
         // Create directory structure
         fs::create_dir_all(&git_dir)?;
@@ -79,44 +447,209 @@ impl Repository {
         fs::create_dir_all(git_dir.join("refs/heads"))?;
         fs::create_dir_all(git_dir.join("refs/tags"))?;
 
-        // Create HEAD pointing to main branch
-        fs::write(git_dir.join("HEAD"), b"ref: refs/heads/main\n")?;
+        // Point the work tree at a separate git dir with a .git file
+        if let Some(separate) = &options.separate_git_dir {
+            fs::create_dir_all(work_tree)?;
+            let target = separate.canonicalize()?;
+            fs::write(
+                work_tree.join(".git"),
+                format!("gitdir: {}\n", target.display()),
+            )?;
+        }
 
-        // Create config file
-        let config = r#"[core]
-	repositoryformatversion = 0
-	filemode = false
-	bare = false
-"#;
-        fs::write(git_dir.join("config"), config)?;
+        if let Some(template) = options.template_dir() {
+            copy_template(&template, &git_dir)
+                .with_context(|| format!("Failed to copy template {}", template.display()))?;
+        }
 
-        // Create description file
-        fs::write(
-            git_dir.join("description"),
-            b"Unnamed oxid repository.\n",
-        )?;
+        if let Some(branch) = new {
+            // Create HEAD pointing to the initial branch
+            fs::write(
+                git_dir.join("HEAD"),
+                format!("ref: refs/heads/{}\n", branch),
+            )?;
+
+            // Create config file
+            let mut config = format!(
+                "[core]\n\
+                 \trepositoryformatversion = 0\n\
+                 \tfilemode = {}\n\
+                 \tbare = {}\n",
+                crate::worktree::probe_file_mode(&git_dir),
+                options.bare
+            );
+            // Like Git, only record symlink support when it is missing
+            if !crate::worktree::probe_symlinks(&git_dir) {
+                config.push_str("\tsymlinks = false\n");
+            }
+            if crate::worktree::probe_ignore_case(&git_dir) {
+                config.push_str("\tignorecase = true\n");
+            }
+            if crate::worktree::probe_precompose_unicode(&git_dir) {
+                config.push_str("\tprecomposeunicode = true\n");
+            }
+            fs::write(git_dir.join("config"), config)?;
+
+            // Create description file unless the template provided one
+            if !git_dir.join("description").exists() {
+                fs::write(git_dir.join("description"), b"Unnamed oxid repository.\n")?;
+            }
+        }
+
+        let git_dir = git_dir.canonicalize()?;
+        let work_tree = if options.bare {
+            git_dir.clone()
+        } else {
+            work_tree.to_path_buf()
+        };
+
+        Ok(Repository::open_at(git_dir, work_tree, options.bare))
+    }
+
+    /// A repository with loose object storage and no settings read yet
+    fn open_at(git_dir: PathBuf, work_tree: PathBuf, bare: bool) -> Self {
+        let common_dir = common_dir(&git_dir);
+        Repository {
+            odb: Box::new(LooseObjects::with_alternates(common_dir.join("objects"))),
+            git_dir,
+            common_dir,
+            work_tree,
+            bare,
+            snapshot: false,
+            limits: OnceLock::new(),
+            replace_objects: true,
+            replacements: OnceLock::new(),
+            shallow: OnceLock::new(),
+            cache: OnceLock::new(),
+        }
+    }
+
+    /// Read the effective configuration
+    ///
+    /// System and global settings are overridden by the repository's own
+    /// `config` file. With `extensions.worktreeConfig` set there, the
+    /// worktree's `config.worktree` comes last, so each worktree can have
+    /// its own `core.sparseCheckout`, `core.bare` and the like.
+    pub fn config(&self) -> Result<Config> {
+        let mut config = Config::global()?;
+        let shared = Config::read(self.common_dir.join("config"))?;
+        let per_worktree = shared
+            .get_bool("extensions.worktreeConfig")?
+            .unwrap_or(false);
+        config.merge(shared);
+        if per_worktree {
+            config.merge(Config::read(self.git_dir.join("config.worktree"))?);
+        }
+        Ok(config)
+    }
+
+    /// The file a ref is stored in when loose
+    ///
+    /// `HEAD` and the refs under `refs/worktree/`, `refs/bisect/` and
+    /// `refs/rewritten/` belong to a worktree; the rest are shared.
+    fn ref_path(&self, name: &str) -> PathBuf {
+        let per_worktree = !name.starts_with("refs/")
+            || ["refs/worktree/", "refs/bisect/", "refs/rewritten/"]
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if per_worktree {
+            self.git_dir.join(name)
+        } else {
+            self.common_dir.join(name)
+        }
+    }
+
+    /// Get the working tree as a [`WorkTree`](crate::worktree::WorkTree)
+    ///
+    /// Honors `core.symlinks`, `core.fileMode` and `core.precomposeUnicode`
+    /// from the repository configuration.
+    pub fn worktree(&self) -> Result<crate::worktree::FsWorkTree> {
+        let config = self.config()?;
+        let symlinks = config.get_bool("core.symlinks")?.unwrap_or(true);
+        let file_mode = config.get_bool("core.fileMode")?.unwrap_or(true);
+        let precompose = config.get_bool("core.precomposeUnicode")?.unwrap_or(false);
+        Ok(crate::worktree::FsWorkTree::new(&self.work_tree)
+            .with_symlinks(symlinks)
+            .with_file_mode(file_mode)
+            .with_precompose_unicode(precompose))
+    }
+
+    /// Get the path to the index file (`.git/index`)
+    pub fn index_path(&self) -> PathBuf {
+        self.git_dir.join("index")
+    }
+
+    /// Read `.git/index`
+    ///
+    /// Lookups ignore case when `core.ignoreCase` is set.
+    pub fn read_index(&self) -> Result<crate::index::Index> {
+        let mut index = crate::index::Index::read(self.index_path())?;
+        index.set_ignore_case(self.config()?.get_bool("core.ignoreCase")?.unwrap_or(false));
+        Ok(index)
+    }
+
+    /// Write the index to `.git/index`
+    ///
+    /// # Errors
+    ///
+    /// Fails for snapshots, without taking the index lock
+    pub fn write_index(&self, index: &crate::index::Index) -> Result<()> {
+        self.ensure_writable()?;
+        index.write(self.index_path())
+    }
 
-        Ok(Repository {
-            git_dir: git_dir.canonicalize()?,
-            work_tree: work_tree.to_path_buf(),
-        })
+    /// Convert a path given on the command line to an index path
+    ///
+    /// Relative paths are resolved against the current directory. The result
+    /// is relative to the worktree root and uses `/` separators.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - A path inside the worktree
+    pub fn index_path_of(&self, path: &Path) -> Result<Vec<u8>> {
+        let absolute = std::env::current_dir()?.join(path);
+        // Resolve `.`/`..` lexically: the file may not exist
+        let mut normalized = PathBuf::new();
+        for component in absolute.components() {
+            match component {
+                std::path::Component::ParentDir => {
+                    normalized.pop();
+                }
+                std::path::Component::CurDir => {}
+                other => normalized.push(other),
+            }
+        }
+        let root = self.work_tree.canonicalize()?;
+        let relative = normalized
+            .strip_prefix(&root)
+            .or_else(|_| normalized.strip_prefix(&self.work_tree))
+            .map_err(|_| anyhow::anyhow!("'{}' is outside repository", path.display()))?;
+        let parts = relative
+            .components()
+            .map(|c| crate::utils::path_to_bytes(Path::new(c.as_os_str())))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(parts.join(&b'/'))
     }
 
     /// Get the path to an object file for a given hash
     ///
-    /// Objects are stored as `.git/objects/XX/YYYYYYYY...`
-    /// where XX is the first 2 characters of the hash.
+    /// Loose objects are stored as `.git/objects/XX/YYYYYYYY...`
+    /// where XX is the first 2 characters of the hash. With another
+    /// [`OdbBackend`] the object may not be there. Fails if `hash` isn't a
+    /// full object id.
     ///
     /// # Arguments
     ///
     /// * `hash` - The object hash (40 character hex string)
-    pub fn object_path(&self, hash: &str) -> PathBuf {
-        let (dir, file) = hash.split_at(2);
-        self.git_dir.join("objects").join(dir).join(file)
+    pub fn object_path(&self, hash: &str) -> Result<PathBuf> {
+        crate::odb::object_file(&self.common_dir.join("objects"), hash)
     }
 
     /// Read an object from the object database
     ///
+    /// If the object has been replaced (see [`Repository::replacement_of`]),
+    /// the replacement is read instead.
+    ///
     /// # Arguments
     ///
     /// * `hash` - The object hash to read
@@ -124,11 +657,294 @@ impl Repository {
     /// # Returns
     ///
     /// Decompressed object data
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`crate::limits::LimitExceeded`] if the object is larger
+    /// than the configured maximum object size
     pub fn read_object(&self, hash: &str) -> Result<Vec<u8>> {
-        let path = self.object_path(hash);
-        let compressed = fs::read(&path)
-            .with_context(|| format!("Failed to read object {}", hash))?;
-        crate::utils::decompress(&compressed)
+        let replacement = self.replacement_of(hash)?;
+        if replacement != hash {
+            tracing::debug!(hash, replacement, "object is replaced");
+        }
+        self.read_stored_object(&replacement)
+    }
+
+    /// Read an object as stored, ignoring replacements
+    ///
+    /// For checking and transferring objects, where a replacement must not
+    /// stand in for the original. Otherwise like [`Repository::read_object`].
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to read
+    pub fn read_stored_object(&self, hash: &str) -> Result<Vec<u8>> {
+        let _span = tracing::debug_span!("read_object", hash).entered();
+        let data = self.odb.read(hash, &self.limits()?)?;
+        tracing::debug!(hash, size = data.len(), "read object");
+        Ok(data)
+    }
+
+    /// Read an object and split off its header
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to read
+    ///
+    /// # Returns
+    ///
+    /// The object's type and its content, without the header
+    ///
+    /// # Errors
+    ///
+    /// Fails if the header is malformed or its size doesn't match the
+    /// content, and like [`Repository::read_object`] otherwise
+    pub fn read_object_parsed(&self, hash: &str) -> Result<(ObjectType, Vec<u8>)> {
+        let mut data = self.read_object(hash)?;
+        let (kind, size, header_len) =
+            parse_header(&data).with_context(|| format!("Corrupt object {}", hash))?;
+        if data.len() - header_len != size {
+            anyhow::bail!(
+                "Corrupt object {}: header says {} bytes, found {}",
+                hash,
+                size,
+                data.len() - header_len
+            );
+        }
+        data.drain(..header_len);
+        Ok((kind, data))
+    }
+
+    /// Open an object for reading its content as a stream
+    ///
+    /// Unlike [`Repository::read_object`], the content isn't held in
+    /// memory, so the object size limit doesn't apply. Replacements are
+    /// followed. See [`ObjectReader`](crate::object_stream::ObjectReader).
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to read
+    pub fn read_object_stream(&self, hash: &str) -> Result<crate::object_stream::ObjectReader<'_>> {
+        let _span = tracing::debug_span!("read_object_stream", hash).entered();
+        crate::object_stream::ObjectReader::open(self, &self.replacement_of(hash)?)
+    }
+
+    /// Start writing an object whose content is streamed in
+    ///
+    /// Write exactly `size` bytes of content, then call
+    /// [`ObjectWriter::finish`](crate::object_stream::ObjectWriter::finish)
+    /// to store the object and get its hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - The object type
+    /// * `size` - The content size, which goes in the header first
+    pub fn write_object_stream(
+        &self,
+        kind: ObjectType,
+        size: u64,
+    ) -> Result<crate::object_stream::ObjectWriter<'_>> {
+        self.ensure_writable()?;
+        crate::object_stream::ObjectWriter::create(self, kind, size)
+    }
+
+    /// Resolve `HEAD` to a commit hash
+    ///
+    /// Returns `None` when `HEAD` points to a branch without commits.
+    pub fn head(&self) -> Result<Option<String>> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).context("Failed to read HEAD")?;
+        let head = head.trim_end();
+        match head.strip_prefix("ref: ") {
+            Some(name) => Ok(crate::refs::read_refs(&self.git_dir)?.remove(name)),
+            None => Ok(Some(head.to_string())),
+        }
+    }
+
+    /// Point a ref at a value, creating it if needed
+    ///
+    /// The ref is written through a lock file, so readers never see a
+    /// partial value.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The ref's full name, e.g. `refs/heads/main`
+    /// * `value` - An object hash, or `ref: <name>` for a symbolic ref
+    ///
+    /// # Errors
+    ///
+    /// Fails for names [`crate::ref_name`] doesn't accept, so a ref can't
+    /// be written outside the refs
+    pub fn write_ref(&self, name: &str, value: &str) -> Result<()> {
+        self.ensure_writable()?;
+        crate::ref_name::check(name)?;
+        if let Some(target) = value.strip_prefix("ref: ") {
+            crate::ref_name::check(target)?;
+        }
+        let path = self.ref_path(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        replace_locked(&path, format!("{}\n", value).as_bytes())
+            .with_context(|| format!("Failed to update {}", name))
+    }
+
+    /// Delete a ref, loose or packed
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The ref's full name
+    ///
+    /// # Errors
+    ///
+    /// Fails if the ref doesn't exist or its name isn't valid
+    pub fn delete_ref(&self, name: &str) -> Result<()> {
+        self.ensure_writable()?;
+        crate::ref_name::check(name)?;
+        let path = self.ref_path(name);
+        let loose = path.is_file();
+        if loose {
+            fs::remove_file(&path).with_context(|| format!("Failed to delete {}", name))?;
+        }
+
+        let packed_path = self.common_dir.join("packed-refs");
+        let mut packed = false;
+        if packed_path.exists() {
+            let mut kept = String::new();
+            let mut removing = false;
+            for line in fs::read_to_string(&packed_path)?.lines() {
+                // A peeled value belongs to the ref on the line before
+                if line.starts_with('^') && removing {
+                    continue;
+                }
+                removing = !line.starts_with('#')
+                    && line
+                        .split_once(' ')
+                        .is_some_and(|(_, ref_name)| ref_name == name);
+                if removing {
+                    packed = true;
+                    continue;
+                }
+                kept.push_str(line);
+                kept.push('\n');
+            }
+            if packed {
+                replace_locked(&packed_path, kept.as_bytes())
+                    .context("Failed to update packed-refs")?;
+            }
+        }
+
+        if !loose && !packed {
+            anyhow::bail!("ref {} does not exist", name);
+        }
+        Ok(())
+    }
+
+    /// Read and parse a commit, going through the object cache
+    ///
+    /// Like Git, commits at the shallow boundary (see
+    /// [`Repository::shallow_commits`]) are returned without parents.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The commit's hash
+    pub fn read_commit(&self, hash: &str) -> Result<Arc<Commit>> {
+        if let Some(commit) = self.object_cache()?.get_commit(hash) {
+            return Ok(commit);
+        }
+        let mut commit = Commit::parse(&self.read_object(hash)?)
+            .with_context(|| format!("Failed to parse commit {}", hash))?;
+        if self.shallow_commits()?.contains(hash) {
+            commit.parents.clear();
+        }
+        let commit = Arc::new(commit);
+        self.object_cache()?
+            .insert_commit(hash, Arc::clone(&commit));
+        Ok(commit)
+    }
+
+    /// Read a tree's content, going through the object cache
+    ///
+    /// Walk the entries with [`TreeRef`](crate::objects::TreeRef).
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The tree's hash
+    pub fn read_tree(&self, hash: &str) -> Result<Arc<[u8]>> {
+        if let Some(tree) = self.object_cache()?.get_tree(hash) {
+            return Ok(tree);
+        }
+        let tree: Arc<[u8]> = match self.read_object_parsed(hash)? {
+            (ObjectType::Tree, content) => Arc::from(content),
+            (other, _) => anyhow::bail!("{} is a {}, not a tree", hash, other.as_str()),
+        };
+        self.object_cache()?.insert_tree(hash, Arc::clone(&tree));
+        Ok(tree)
+    }
+
+    /// The cache behind [`Repository::read_commit`] and [`Repository::read_tree`]
+    ///
+    /// Created the first time it is needed, holding as many objects as
+    /// `oxid.objectCacheSize` says; see [`crate::cache`].
+    pub fn object_cache(&self) -> Result<&ObjectCache> {
+        if let Some(cache) = self.cache.get() {
+            return Ok(cache);
+        }
+        let capacity = crate::cache::capacity_from_config(&self.config()?)?;
+        Ok(self.cache.get_or_init(|| ObjectCache::new(capacity)))
+    }
+
+    /// Check whether an object is stored
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to look up
+    pub fn has_object(&self, hash: &str) -> bool {
+        hash.len() == 40 && hash.chars().all(|c| c.is_ascii_hexdigit()) && self.odb.exists(hash)
+    }
+
+    /// Read the type and size of an object without parsing its content
+    ///
+    /// Only the start of the object is inflated, so this stays cheap for
+    /// large blobs. Like [`Repository::read_object`], this describes the
+    /// replacement of a replaced object.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object hash to inspect
+    pub fn object_header(&self, hash: &str) -> Result<(crate::objects::ObjectType, usize)> {
+        self.odb.header(&self.replacement_of(hash)?)
+    }
+
+    /// List the hashes of all stored objects, in sorted order
+    ///
+    /// Lists what the [`OdbBackend`] holds. For loose objects, every
+    /// two-digit fan-out directory of `objects/` is read in turn, as the
+    /// iterator gets there; `info/`, `pack/` and anything not named like an
+    /// object are skipped. oxid can't read packfiles yet, so packed objects
+    /// aren't listed.
+    pub fn objects(&self) -> Result<impl Iterator<Item = String> + '_> {
+        self.odb.iter()
+    }
+
+    /// Start a quarantine for objects that need checking before use
+    ///
+    /// See [`Quarantine`](crate::quarantine::Quarantine).
+    pub fn quarantine(&self) -> Result<crate::quarantine::Quarantine<'_>> {
+        self.ensure_writable()?;
+        crate::quarantine::Quarantine::new(self)
+    }
+
+    /// Read the content staged for an index entry
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The index entry whose blob to read
+    pub fn read_staged(&self, entry: &crate::index::IndexEntry) -> Result<Vec<u8>> {
+        // Intent-to-add entries record the empty blob, which may not be stored
+        if entry.intent_to_add {
+            return Ok(Vec::new());
+        }
+        Ok(crate::objects::Blob::parse(&self.read_object(&entry.hash)?)?.data)
     }
 
     /// Write an object to the object database
@@ -138,24 +954,149 @@ impl Repository {
     /// * `hash` - The object hash (determines storage location)
     /// * `data` - The object data to write (will be compressed)
     pub fn write_object(&self, hash: &str, data: &[u8]) -> Result<()> {
-        let path = self.object_path(hash);
+        let _span = tracing::debug_span!("write_object", hash).entered();
+        self.ensure_writable()?;
+        self.odb.write(hash, data)?;
+        tracing::debug!(hash, size = data.len(), "wrote object");
 
-        // Create parent directory if it doesn't exist
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        Ok(())
+    }
+}
+
+/// Where replacement refs live: `GIT_REPLACE_REF_BASE`, or `refs/replace/`
+///
+/// The result always ends with `/`.
+pub fn replace_ref_base() -> String {
+    match std::env::var("GIT_REPLACE_REF_BASE") {
+        Ok(base) if !base.is_empty() => format!("{}/", base.trim_end_matches('/')),
+        _ => "refs/replace/".to_string(),
+    }
+}
+
+/// Check whether a directory looks like a git dir (e.g. a bare repository)
+///
+/// A linked worktree's git dir has a `commondir` file instead of objects
+/// and refs of its own.
+fn is_git_dir(path: &Path) -> bool {
+    path.join("HEAD").is_file()
+        && (path.join("commondir").is_file()
+            || (path.join("objects").is_dir() && path.join("refs").is_dir()))
+}
+
+/// The directory holding the data a git dir shares with other worktrees
+///
+/// A linked worktree's git dir names it in its `commondir` file, relative
+/// to the git dir unless absolute; any other git dir is its own common
+/// dir.
+///
+/// # Arguments
+///
+/// * `git_dir` - The git dir of a worktree
+pub fn common_dir(git_dir: &Path) -> PathBuf {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(target) => git_dir.join(target.trim_end_matches(['\n', '\r'])),
+        Err(_) => git_dir.to_path_buf(),
+    }
+}
+
+/// Resolve a `.git` file (`gitdir: <path>`) to the directory it points to
+fn read_git_file(path: &Path) -> Result<PathBuf> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let target = content
+        .trim_end()
+        .strip_prefix("gitdir: ")
+        .with_context(|| format!("Invalid gitfile format: {}", path.display()))?;
+
+    // Relative paths are relative to the directory containing the .git file
+    let base = path.parent().unwrap_or(Path::new("."));
+    let git_dir = base.join(target);
+    if !git_dir.is_dir() {
+        anyhow::bail!("Not a git repository: {}", git_dir.display());
+    }
+    Ok(git_dir)
+}
+
+/// Replace a file through `<file>.lock`, as Git does
+///
+/// The lock is created exclusively, so a concurrent writer makes this fail
+/// instead of both overwriting each other; it is removed again on failure.
+fn replace_locked(path: &Path, contents: &[u8]) -> Result<()> {
+    let lock = path.with_file_name(format!(
+        "{}.lock",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let mut file = match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            anyhow::bail!("Unable to create '{}': File exists.", lock.display())
         }
+        Err(e) => return Err(e).with_context(|| format!("Unable to create '{}'", lock.display())),
+    };
+    let written =
+        std::io::Write::write_all(&mut file, contents).and_then(|_| fs::rename(&lock, path));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&lock);
+        return Err(e.into());
+    }
+    Ok(())
+}
 
-        // Compress and write
-        let compressed = crate::utils::compress(data)?;
-        fs::write(&path, compressed)?;
+/// Recursively copy template files into a git dir without overwriting
+fn copy_template(template: &Path, git_dir: &Path) -> Result<()> {
+    if !template.is_dir() {
+        // Git only warns about a missing template directory
+        tracing::warn!("templates not found in {}", template.display());
+        return Ok(());
+    }
 
-        Ok(())
+    for entry in walkdir::WalkDir::new(template).min_depth(1) {
+        let entry = entry?;
+        let target = git_dir.join(entry.path().strip_prefix(template)?);
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)?;
+        } else if !target.exists() {
+            fs::copy(entry.path(), &target)?;
+        }
     }
+    Ok(())
+}
+
+/// Parse a `GIT_CEILING_DIRECTORIES` value
+///
+/// Entries are separated by the platform path separator (`:` on Unix);
+/// empty and relative entries are ignored, as Git does.
+pub fn parse_ceiling_dirs(value: &std::ffi::OsStr) -> Vec<PathBuf> {
+    std::env::split_paths(value)
+        .filter(|p| p.is_absolute())
+        .collect()
+}
+
+/// Identify the filesystem a path lives on
+#[cfg(unix)]
+fn device_id(path: &Path) -> Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(path)?.dev())
+}
+
+/// Identify the filesystem a path lives on
+///
+/// Not available on this platform, so every path is treated as being on
+/// the same filesystem.
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Result<u64> {
+    Ok(0)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::objects::GitObject;
+    use crate::worktree::WorkTree;
     use tempfile::tempdir;
 
     #[test]
@@ -171,13 +1112,153 @@ mod tests {
         assert!(repo.git_dir.join("config").exists());
     }
 
+    #[test]
+    fn test_init_bare_with_initial_branch() {
+        let dir = tempdir().unwrap();
+        let options = InitOptions {
+            bare: true,
+            initial_branch: Some("trunk".to_string()),
+            ..Default::default()
+        };
+        let repo = Repository::init_with(dir.path(), &options).unwrap();
+
+        assert!(repo.bare);
+        assert!(!dir.path().join(".git").exists());
+        assert!(Repository::new(dir.path()).unwrap().bare);
+        assert!(dir.path().join("objects").exists());
+        let head = fs::read_to_string(dir.path().join("HEAD")).unwrap();
+        assert_eq!(head, "ref: refs/heads/trunk\n");
+        let config = fs::read_to_string(dir.path().join("config")).unwrap();
+        assert!(config.contains("bare = true"));
+    }
+
+    #[test]
+    fn test_init_rejects_invalid_branch() {
+        let dir = tempdir().unwrap();
+        let options = InitOptions {
+            initial_branch: Some("bad name".to_string()),
+            ..Default::default()
+        };
+        assert!(Repository::init_with(dir.path(), &options).is_err());
+
+        for branch in ["bad..name", "-b", "HEAD", "x.lock", "a/.b", ""] {
+            let options = InitOptions {
+                initial_branch: Some(branch.to_string()),
+                ..Default::default()
+            };
+            let Err(error) = Repository::init_with(dir.path(), &options) else {
+                panic!("initialized with branch '{}'", branch);
+            };
+            assert_eq!(
+                error.to_string(),
+                format!("Invalid initial branch name: '{}'", branch)
+            );
+        }
+        // Nothing was created
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_reinit_keeps_existing_files() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        fs::write(repo.git_dir.join("HEAD"), b"ref: refs/heads/dev\n").unwrap();
+        fs::remove_dir_all(repo.git_dir.join("refs/tags")).unwrap();
+
+        assert!(InitOptions::default().is_reinit(dir.path()));
+        Repository::init(dir.path()).unwrap();
+
+        let head = fs::read_to_string(repo.git_dir.join("HEAD")).unwrap();
+        assert_eq!(head, "ref: refs/heads/dev\n");
+        assert!(repo.git_dir.join("refs/tags").exists());
+    }
+
+    #[test]
+    fn test_init_with_template() {
+        let dir = tempdir().unwrap();
+        let template = dir.path().join("template");
+        fs::create_dir_all(template.join("hooks")).unwrap();
+        fs::write(template.join("hooks/pre-commit"), b"#!/bin/sh\n").unwrap();
+        fs::write(template.join("description"), b"From template\n").unwrap();
+
+        let options = InitOptions {
+            template_dir: Some(template),
+            ..Default::default()
+        };
+        let repo = Repository::init_with(dir.path().join("repo"), &options).unwrap();
+
+        assert!(repo.git_dir.join("hooks/pre-commit").exists());
+        let description = fs::read_to_string(repo.git_dir.join("description")).unwrap();
+        assert_eq!(description, "From template\n");
+    }
+
+    #[test]
+    fn test_init_separate_git_dir() {
+        let dir = tempdir().unwrap();
+        let work = dir.path().join("work");
+        let storage = dir.path().join("storage.git");
+        let options = InitOptions {
+            separate_git_dir: Some(storage.clone()),
+            ..Default::default()
+        };
+        Repository::init_with(&work, &options).unwrap();
+
+        assert!(work.join(".git").is_file());
+        assert!(storage.join("HEAD").exists());
+
+        // Discovery follows the .git file
+        let repo = Repository::new(&work).unwrap();
+        assert_eq!(
+            repo.git_dir.canonicalize().unwrap(),
+            storage.canonicalize().unwrap()
+        );
+        assert_eq!(repo.work_tree, work.canonicalize().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_init_probes_file_mode() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = repo.config().unwrap();
+        assert_eq!(config.get_bool("core.fileMode").unwrap(), Some(true));
+    }
+
+    #[test]
+    fn test_init_probes_ignore_case() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = repo.config().unwrap();
+        let expected = crate::worktree::probe_ignore_case(dir.path());
+        assert_eq!(
+            config.get_bool("core.ignoreCase").unwrap().unwrap_or(false),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_worktree_honors_core_symlinks() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        fs::write(
+            repo.git_dir.join("config"),
+            format!("{}\tsymlinks = false\n", config),
+        )
+        .unwrap();
+
+        let mut wt = repo.worktree().unwrap();
+        wt.write_symlink(Path::new("link"), b"target").unwrap();
+        assert!(!dir.path().join("link").is_symlink());
+    }
+
     #[test]
     fn test_object_path() {
         let dir = tempdir().unwrap();
         let repo = Repository::init(dir.path()).unwrap();
 
         let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
-        let path = repo.object_path(hash);
+        let path = repo.object_path(hash).unwrap();
 
         let expected = repo
             .git_dir
@@ -205,6 +1286,167 @@ mod tests {
         assert_eq!(data.to_vec(), read_data);
     }
 
+    #[test]
+    fn test_read_object_parsed() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+        repo.write_object(hash, b"blob 12\0Hello World\n").unwrap();
+        let (kind, content) = repo.read_object_parsed(hash).unwrap();
+        assert_eq!(kind, ObjectType::Blob);
+        assert_eq!(content, b"Hello World\n");
+
+        // Loose objects are checked as they are inflated; other backends
+        // may hand back whatever they stored
+        let mut repo = repo;
+        repo.set_odb(Box::new(crate::odb::MemoryObjects::default()));
+        let short = "1".repeat(40);
+        repo.write_object(&short, b"blob 20\0Hello World\n")
+            .unwrap();
+        let error = repo.read_object_parsed(&short).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("Corrupt object {}: header says 20 bytes, found 12", short)
+        );
+        let garbled = "2".repeat(40);
+        repo.write_object(&garbled, b"blob\0Hello World\n").unwrap();
+        assert!(repo.read_object_parsed(&garbled).is_err());
+    }
+
+    #[test]
+    fn test_object_enumeration() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+        assert!(!repo.has_object(hash));
+        repo.write_object(hash, b"blob 11\0Hello World").unwrap();
+        fs::write(repo.git_dir.join("objects/info/packs"), b"").unwrap();
+        // Not objects: upper case, temporary files, stray directories
+        fs::create_dir_all(repo.git_dir.join("objects/AB")).unwrap();
+        fs::write(repo.git_dir.join("objects/AB").join("c".repeat(38)), b"").unwrap();
+        fs::write(repo.git_dir.join("objects/55/tmp_obj_1"), b"").unwrap();
+        fs::create_dir_all(repo.git_dir.join("objects/xyz")).unwrap();
+
+        assert!(repo.has_object(hash));
+        assert!(!repo.has_object("../config"));
+        assert_eq!(repo.objects().unwrap().collect::<Vec<_>>(), vec![hash]);
+        assert_eq!(
+            repo.object_header(hash).unwrap(),
+            (crate::objects::ObjectType::Blob, 11)
+        );
+
+        // Only the header is inflated: a large object reads the same
+        let big = crate::objects::Blob::new(vec![b'x'; 1 << 20]);
+        let big_hash = big.hash().unwrap();
+        repo.write_object(&big_hash, &big.to_bytes().unwrap())
+            .unwrap();
+        assert_eq!(
+            repo.object_header(&big_hash).unwrap(),
+            (crate::objects::ObjectType::Blob, 1 << 20)
+        );
+
+        fs::write(repo.object_path(hash).unwrap(), b"not zlib").unwrap();
+        assert!(repo.object_header(hash).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_is_read_only() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+        repo.write_object(hash, b"blob 11\0Hello World").unwrap();
+
+        let snapshot = Repository::open_snapshot(dir.path()).unwrap();
+        assert!(snapshot.is_snapshot());
+        assert!(snapshot.read_object(hash).is_ok());
+        assert!(snapshot.write_object(hash, b"blob 0\0").is_err());
+        assert!(snapshot.write_index(&crate::index::Index::new()).is_err());
+        assert!(!repo.git_dir.join("index").exists());
+        assert!(!repo.git_dir.join("index.lock").exists());
+    }
+
+    #[test]
+    fn test_read_commit_and_tree_are_cached() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let tree = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        repo.write_object(tree, b"tree 0\0").unwrap();
+        let sig = crate::objects::Signature::new("A U Thor", "a@example.com", 1112911993, -420);
+        let commit = Commit {
+            tree: tree.to_string(),
+            parents: Vec::new(),
+            author: sig.clone(),
+            committer: sig,
+            message: "initial\n".to_string(),
+            extra_headers: Vec::new(),
+            gpgsig: None,
+        };
+        let hash = commit.hash().unwrap();
+        repo.write_object(&hash, &commit.to_bytes().unwrap())
+            .unwrap();
+
+        let first = repo.read_commit(&hash).unwrap();
+        assert_eq!(*first, commit);
+        assert!(Arc::ptr_eq(&first, &repo.read_commit(&hash).unwrap()));
+        assert!(repo.read_tree(&first.tree).unwrap().is_empty());
+        assert!(repo.read_tree(&hash).is_err());
+
+        let stats = repo.object_cache().unwrap().stats();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+        assert_eq!(repo.object_cache().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_object_cache_size_from_config() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let mut config = fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        config.push_str("[oxid]\n\tobjectCacheSize = 1\n");
+        fs::write(repo.git_dir.join("config"), config).unwrap();
+
+        let mut repo = Repository::new(dir.path()).unwrap();
+        let empty = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+        repo.write_object(empty, b"tree 0\0").unwrap();
+        let blob = crate::objects::Blob::new(b"x\n").hash().unwrap();
+        let mut tree = crate::objects::Tree::new();
+        tree.insert(
+            crate::objects::TreeEntry::new(crate::objects::FileMode::Regular, "x", &blob).unwrap(),
+        );
+        let hash = tree.hash().unwrap();
+        repo.write_object(&hash, &tree.to_bytes().unwrap()).unwrap();
+
+        repo.read_tree(empty).unwrap();
+        repo.read_tree(&hash).unwrap();
+        let cache = repo.object_cache().unwrap();
+        assert_eq!(cache.capacity(), 1);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.stats().evictions, 1);
+
+        // Swapping the backend empties the cache but keeps its size
+        let odb = LooseObjects::new(repo.common_dir.join("objects"));
+        repo.set_odb(Box::new(odb));
+        assert!(repo.object_cache().unwrap().is_empty());
+        assert_eq!(repo.object_cache().unwrap().capacity(), 1);
+    }
+
+    #[test]
+    fn test_read_object_enforces_limits() {
+        let dir = tempdir().unwrap();
+        let mut repo = Repository::init(dir.path()).unwrap();
+        let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+        repo.write_object(hash, b"blob 12\0Hello World\n").unwrap();
+        assert!(repo.read_object(hash).is_ok());
+
+        repo.set_limits(crate::limits::Limits {
+            max_object_size: 11,
+            ..Default::default()
+        });
+        let error = repo.read_object(hash).unwrap_err();
+        assert!(error
+            .downcast_ref::<crate::limits::LimitExceeded>()
+            .is_some());
+    }
+
     #[test]
     fn test_new_finds_repository() {
         let dir = tempdir().unwrap();
@@ -217,4 +1459,170 @@ mod tests {
         let repo = Repository::new(&subdir).unwrap();
         assert_eq!(repo.work_tree, dir.path().canonicalize().unwrap());
     }
+
+    #[test]
+    fn test_discover_stops_at_ceiling() {
+        let dir = tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+
+        let nested = dir.path().join("a/b");
+        fs::create_dir_all(&nested).unwrap();
+
+        // The repository root itself as a ceiling stops the search
+        let ceiling = vec![dir.path().to_path_buf()];
+        assert!(Repository::discover(&nested, &ceiling, true).is_err());
+
+        // A ceiling below the start directory has no effect
+        let ceiling = vec![nested.join("c")];
+        let repo = Repository::discover(&nested, &ceiling, true).unwrap();
+        assert_eq!(repo.work_tree, dir.path().canonicalize().unwrap());
+
+        // The starting directory is always checked
+        let ceiling = vec![dir.path().to_path_buf()];
+        assert!(Repository::discover(dir.path(), &ceiling, false).is_ok());
+    }
+
+    #[test]
+    fn test_write_ref_refuses_broken_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hash = "a".repeat(40);
+        for name in [
+            "refs/tags/../../escape",
+            "refs/heads/.hidden",
+            "refs/heads/x/",
+        ] {
+            assert!(repo.write_ref(name, &hash).is_err(), "{}", name);
+        }
+        assert!(!dir.path().join(".git/escape").exists());
+        assert!(repo
+            .write_ref("HEAD", "ref: refs/heads/../../escape")
+            .is_err());
+        assert!(repo.delete_ref("refs/heads/../../HEAD").is_err());
+        repo.write_ref("refs/heads/ok", &hash).unwrap();
+    }
+
+    #[test]
+    fn test_write_ref_respects_lock() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        repo.write_ref("refs/heads/main", &a).unwrap();
+
+        let lock = repo.git_dir.join("refs/heads/main.lock");
+        fs::write(&lock, "held\n").unwrap();
+        let error = repo.write_ref("refs/heads/main", &b).unwrap_err();
+        assert!(format!("{:#}", error).contains(&format!(
+            "Unable to create '{}': File exists.",
+            lock.display()
+        )));
+        // Neither the ref nor someone else's lock is touched
+        let path = repo.git_dir.join("refs/heads/main");
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("{}\n", a));
+        assert_eq!(fs::read_to_string(&lock).unwrap(), "held\n");
+        fs::remove_file(&lock).unwrap();
+
+        fs::write(
+            repo.git_dir.join("packed-refs"),
+            format!("{} refs/tags/v1\n", a),
+        )
+        .unwrap();
+        let lock = repo.git_dir.join("packed-refs.lock");
+        fs::write(&lock, "").unwrap();
+        assert!(repo.delete_ref("refs/tags/v1").is_err());
+        fs::remove_file(&lock).unwrap();
+        repo.delete_ref("refs/tags/v1").unwrap();
+        assert!(!lock.exists());
+    }
+
+    #[test]
+    fn test_new_refuses_unknown_extensions() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let config = fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        fs::write(
+            repo.git_dir.join("config"),
+            config.replace("repositoryformatversion = 0", "repositoryformatversion = 1")
+                + "[extensions]\n\trefStorage = reftable\n",
+        )
+        .unwrap();
+
+        let error = Repository::new(dir.path()).err().unwrap();
+        assert!(format!("{:#}", error).contains("refstorage = reftable"));
+    }
+
+    #[test]
+    fn test_parse_ceiling_dirs() {
+        let dirs = parse_ceiling_dirs(std::ffi::OsStr::new("/a::relative:/b/c"));
+        assert_eq!(dirs, vec![PathBuf::from("/a"), PathBuf::from("/b/c")]);
+    }
+
+    #[test]
+    fn test_linked_worktree() {
+        let dir = tempdir().unwrap();
+        let main = Repository::init(dir.path().join("main")).unwrap();
+        let blob = crate::objects::Blob::new(b"shared\n");
+        let hash = blob.hash().unwrap();
+        main.write_object(&hash, &blob.to_bytes().unwrap()).unwrap();
+        main.write_ref("refs/heads/main", &hash).unwrap();
+
+        // What `git worktree add ../wt` leaves behind
+        let admin = main.git_dir.join("worktrees/wt");
+        fs::create_dir_all(&admin).unwrap();
+        fs::write(admin.join("HEAD"), format!("{}\n", hash)).unwrap();
+        fs::write(admin.join("commondir"), "../..\n").unwrap();
+        let wt = dir.path().join("wt");
+        fs::create_dir_all(&wt).unwrap();
+        fs::write(wt.join(".git"), format!("gitdir: {}\n", admin.display())).unwrap();
+
+        let mut config = fs::read_to_string(main.git_dir.join("config")).unwrap();
+        config.push_str("[extensions]\n\tworktreeConfig = true\n");
+        fs::write(main.git_dir.join("config"), config).unwrap();
+        fs::write(
+            main.git_dir.join("config.worktree"),
+            "[core]\n\tsparseCheckout = false\n",
+        )
+        .unwrap();
+        fs::write(
+            admin.join("config.worktree"),
+            "[core]\n\tsparseCheckout = true\n",
+        )
+        .unwrap();
+
+        let linked = Repository::new(&wt).unwrap();
+        assert_eq!(linked.git_dir, admin.canonicalize().unwrap());
+        assert_eq!(
+            linked.common_dir.canonicalize().unwrap(),
+            main.git_dir.canonicalize().unwrap()
+        );
+
+        // Objects and branches are shared, HEAD and config.worktree aren't
+        assert!(linked.has_object(&hash));
+        assert_eq!(linked.head().unwrap(), Some(hash.clone()));
+        let sparse = |repo: &Repository| {
+            repo.config()
+                .unwrap()
+                .get_bool("core.sparseCheckout")
+                .unwrap()
+        };
+        assert_eq!(sparse(&linked), Some(true));
+        assert_eq!(sparse(&main), Some(false));
+
+        linked.write_ref("refs/heads/topic", &hash).unwrap();
+        linked.write_ref("refs/bisect/bad", &hash).unwrap();
+        let refs = crate::refs::read_refs(&main.git_dir).unwrap();
+        assert!(refs.contains_key("refs/heads/topic"));
+        assert!(!refs.contains_key("refs/bisect/bad"));
+        let refs = crate::refs::read_refs(&linked.git_dir).unwrap();
+        assert!(refs.contains_key("refs/bisect/bad"));
+
+        // Without the extension, config.worktree is ignored
+        let config = fs::read_to_string(main.git_dir.join("config")).unwrap();
+        fs::write(
+            main.git_dir.join("config"),
+            config.replace("worktreeConfig = true", "worktreeConfig = false"),
+        )
+        .unwrap();
+        assert_eq!(sparse(&linked), None);
+    }
 }
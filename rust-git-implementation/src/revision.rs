@@ -0,0 +1,173 @@
+//! Resolving revision names to objects
+//!
+//! Understands what most commands need: `HEAD`, full and abbreviated object
+//! ids, and ref names, which are looked up the way Git does (`<name>`,
+//! `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+//! `refs/remotes/<name>`, `refs/remotes/<name>/HEAD`). Annotated tags can
+//! be peeled to the commit they point at.
+
+use crate::objects::ObjectType;
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+
+/// Abbreviated ids shorter than this are not looked up
+pub const MIN_ABBREV: usize = 4;
+
+/// Resolve a revision name to an object id
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `name` - `HEAD`, an object id (possibly abbreviated) or a ref name
+pub fn resolve(repo: &Repository, name: &str) -> Result<String> {
+    if name == "HEAD" || name == "@" {
+        return repo
+            .head()?
+            .with_context(|| format!("ambiguous argument '{}': HEAD has no commits", name));
+    }
+
+    let refs = crate::refs::read_refs(&repo.git_dir)?;
+    let candidates = [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+        format!("refs/remotes/{}/HEAD", name),
+    ];
+    if let Some(hash) = candidates.iter().find_map(|r| refs.get(r)) {
+        return Ok(hash.clone());
+    }
+
+    if name.len() >= MIN_ABBREV && name.len() <= 40 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+        let prefix = name.to_ascii_lowercase();
+        let mut matches = repo.objects()?.filter(|hash| hash.starts_with(&prefix));
+        match (matches.next(), matches.next()) {
+            (Some(hash), None) => return Ok(hash),
+            (Some(_), Some(_)) => anyhow::bail!("short object ID {} is ambiguous", name),
+            (None, _) => {}
+        }
+    }
+    anyhow::bail!(
+        "ambiguous argument '{}': unknown revision or path not in the working tree",
+        name
+    )
+}
+
+/// Follow annotated tags until reaching a non-tag object
+///
+/// Returns the type and id of that object.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `hash` - Any object id
+pub fn peel(repo: &Repository, hash: &str) -> Result<(ObjectType, String)> {
+    let (kind, mut chain) = peel_chain(repo, hash)?;
+    Ok((
+        kind,
+        chain.pop().expect("the chain includes the starting object"),
+    ))
+}
+
+/// Like [`peel`], but returns every object on the way: the starting
+/// object, any tags it leads through, and finally the non-tag object
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `hash` - Any object id
+pub fn peel_chain(repo: &Repository, hash: &str) -> Result<(ObjectType, Vec<String>)> {
+    let mut chain = vec![hash.to_string()];
+    // Tag chains this long are certainly cycles or corruption
+    for _ in 0..64 {
+        let hash = chain.last().expect("the chain is never empty");
+        let (kind, content) = repo.read_object_parsed(hash)?;
+        if kind != ObjectType::Tag {
+            return Ok((kind, chain));
+        }
+        let target = std::str::from_utf8(&content)
+            .ok()
+            .and_then(|text| text.lines().next())
+            .and_then(|line| line.strip_prefix("object "))
+            .with_context(|| format!("Malformed tag {}", hash))?
+            .to_string();
+        chain.push(target);
+    }
+    anyhow::bail!("tag chain starting at {} is too deep", hash)
+}
+
+/// Resolve a revision name to a commit, peeling tags
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `name` - A revision name as accepted by [`resolve`]
+pub fn resolve_commit(repo: &Repository, name: &str) -> Result<String> {
+    match peel(repo, &resolve(repo, name)?)? {
+        (ObjectType::Commit, hash) => Ok(hash),
+        (kind, _) => anyhow::bail!("{} is a {}, not a commit", name, kind.as_str()),
+    }
+}
+
+/// Resolve a revision name to a tree, peeling tags and commits
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `name` - A revision name as accepted by [`resolve`]
+pub fn resolve_tree(repo: &Repository, name: &str) -> Result<String> {
+    match peel(repo, &resolve(repo, name)?)? {
+        (ObjectType::Commit, hash) => Ok(repo.read_commit(&hash)?.tree.clone()),
+        (ObjectType::Tree, hash) => Ok(hash),
+        (kind, _) => anyhow::bail!("{} is a {}, not a tree", name, kind.as_str()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_resolve_names() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("a", "a\n").commit("first").unwrap();
+        builder.tag("v1").unwrap();
+        let tag = builder.annotated_tag("v1-annotated", "release").unwrap();
+        builder.branch("topic").unwrap();
+        let second = builder.file("a", "b\n").commit("second").unwrap();
+
+        let repo = builder.repo();
+        assert_eq!(resolve(repo, "HEAD").unwrap(), second);
+        assert_eq!(resolve(repo, "main").unwrap(), second);
+        assert_eq!(resolve(repo, "refs/heads/topic").unwrap(), first);
+        assert_eq!(resolve(repo, "v1").unwrap(), first);
+        assert_eq!(resolve(repo, &second[..7]).unwrap(), second);
+        assert_eq!(resolve(repo, "v1-annotated").unwrap(), tag);
+        assert_eq!(resolve_commit(repo, "v1-annotated").unwrap(), first);
+        assert!(resolve(repo, "missing").is_err());
+    }
+
+    #[test]
+    fn test_peel_nested_tags() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder.file("a", "a\n").commit("first").unwrap();
+        let inner = builder.annotated_tag("inner", "inner").unwrap();
+        let outer = builder.tag_object("outer", &inner, "outer").unwrap();
+
+        let repo = builder.repo();
+        assert_eq!(
+            peel_chain(repo, &outer).unwrap(),
+            (
+                ObjectType::Commit,
+                vec![outer.clone(), inner, commit.clone()]
+            )
+        );
+        assert_eq!(
+            peel(repo, &outer).unwrap(),
+            (ObjectType::Commit, commit.clone())
+        );
+        assert_eq!(resolve_commit(repo, "outer").unwrap(), commit);
+    }
+}
@@ -0,0 +1,177 @@
+//! Repository ownership checks (`safe.directory`)
+//!
+//! A repository's config can run arbitrary commands (e.g. `core.fsmonitor`),
+//! so operating on a repository created by another user is dangerous on
+//! shared machines: anyone able to create `/tmp/.git` could take over every
+//! user who runs oxid below `/tmp`. Like Git (CVE-2022-24765), oxid refuses
+//! to open repositories owned by someone else unless the directory is listed
+//! in the `safe.directory` key of the system or global config. When a `.git`
+//! file points elsewhere, the file and the git dir it names have to be owned
+//! by the user as well, since the config lives in the git dir.
+//!
+//! Recognized `safe.directory` values:
+//!
+//! - `*`: trust every repository
+//! - `/some/path`: trust exactly this directory
+//! - `/some/path/*`: trust every directory below this one
+//! - an empty value: forget all previous entries
+
+use crate::config::Config;
+use anyhow::Result;
+use std::path::Path;
+
+/// Fail if a repository is owned by another user and not listed in
+/// `safe.directory`
+///
+/// # Arguments
+///
+/// * `path` - The top of the work tree, or the git dir of a bare
+///   repository; this is the path `safe.directory` lists
+/// * `git_dir` - The repository's git dir, possibly named by a `.git` file
+pub fn check(path: &Path, git_dir: &Path) -> Result<()> {
+    if is_owned(path, git_dir)? {
+        return Ok(());
+    }
+
+    let config = Config::global()?;
+    if is_listed(path, &config.get_all("safe.directory")) {
+        return Ok(());
+    }
+
+    anyhow::bail!(
+        "detected dubious ownership in repository at '{}'\n\
+         To add an exception for this directory, call:\n\n\
+         \tgit config --global --add safe.directory {}",
+        path.display(),
+        path.display()
+    );
+}
+
+/// Check whether the current user owns the repository, its `.git` file if
+/// it has one, and the git dir
+fn is_owned(path: &Path, git_dir: &Path) -> Result<bool> {
+    let gitfile = path.join(".git");
+    if gitfile.is_file() && !is_owned_by_current_user(&gitfile)? {
+        return Ok(false);
+    }
+    Ok(is_owned_by_current_user(path)? && is_owned_by_current_user(git_dir)?)
+}
+
+/// Check whether `path` matches one of the `safe.directory` values
+pub fn is_listed(path: &Path, values: &[&str]) -> bool {
+    let mut safe = false;
+
+    for value in values {
+        // An empty value resets the list
+        if value.is_empty() {
+            safe = false;
+            continue;
+        }
+        if *value == "*" {
+            safe = true;
+            continue;
+        }
+
+        let matched = match value.strip_suffix("/*") {
+            // `/x/*` covers what is below `/x`, but not `/x` itself
+            Some(prefix) => path
+                .strip_prefix(prefix)
+                .is_ok_and(|rest| rest.components().next().is_some()),
+            None => {
+                let listed = Path::new(value);
+                listed == path || listed.canonicalize().is_ok_and(|p| p == path)
+            }
+        };
+        safe |= matched;
+    }
+
+    safe
+}
+
+/// Check whether the current user owns `path`
+///
+/// When running as root through `sudo`, the invoking user (`SUDO_UID`) is
+/// also accepted, as Git does.
+#[cfg(unix)]
+pub fn is_owned_by_current_user(path: &Path) -> Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let owner = std::fs::metadata(path)?.uid();
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let euid = unsafe { libc::geteuid() };
+    if owner == euid {
+        return Ok(true);
+    }
+
+    if euid == 0 {
+        let sudo_uid = std::env::var("SUDO_UID").ok().and_then(|v| v.parse().ok());
+        return Ok(sudo_uid == Some(owner));
+    }
+
+    Ok(false)
+}
+
+/// Check whether the current user owns `path`
+///
+/// Ownership is not checked on this platform.
+#[cfg(not(unix))]
+pub fn is_owned_by_current_user(_path: &Path) -> Result<bool> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_own_directory_is_safe() {
+        let dir = tempdir().unwrap();
+        assert!(is_owned_by_current_user(dir.path()).unwrap());
+        assert!(check(dir.path(), dir.path()).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_gitdir_of_git_file_is_checked() {
+        let work_tree = tempdir().unwrap();
+        let git_dir = tempdir().unwrap();
+        std::fs::write(
+            work_tree.path().join(".git"),
+            format!("gitdir: {}\n", git_dir.path().display()),
+        )
+        .unwrap();
+        assert!(is_owned(work_tree.path(), git_dir.path()).unwrap());
+
+        // Handing the git dir to another user takes some privileges
+        let path = std::ffi::CString::new(git_dir.path().to_str().unwrap()).unwrap();
+        // SAFETY: path is a valid NUL-terminated string
+        if unsafe { libc::chown(path.as_ptr(), 1, 1) } != 0 {
+            return;
+        }
+        assert!(!is_owned(work_tree.path(), git_dir.path()).unwrap());
+    }
+
+    #[test]
+    fn test_is_listed() {
+        let path = Path::new("/srv/repos/project");
+
+        assert!(!is_listed(path, &[]));
+        assert!(is_listed(path, &["*"]));
+        assert!(is_listed(path, &["/srv/repos/project"]));
+        assert!(is_listed(path, &["/srv/repos/*"]));
+        assert!(!is_listed(path, &["/srv/other"]));
+        assert!(!is_listed(path, &["/srv/rep/*"]));
+
+        // A trailing `/*` needs at least one more component
+        assert!(!is_listed(path, &["/srv/repos/project/*"]));
+        assert!(is_listed(
+            Path::new("/srv/repos/project/sub"),
+            &["/srv/repos/project/*"]
+        ));
+
+        // An empty value clears earlier entries
+        assert!(!is_listed(path, &["*", ""]));
+        assert!(is_listed(path, &["", "/srv/repos/project"]));
+    }
+}
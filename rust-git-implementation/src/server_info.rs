@@ -0,0 +1,151 @@
+//! Auxiliary files for dumb-protocol clients
+//!
+//! A repository served as static files can't answer questions, so it has
+//! to carry the answers: `info/refs` lists every ref (with the peeled
+//! value of annotated tags on a following `<name>^{}` line) and
+//! `objects/info/packs` lists the packfiles. Both are regenerated by
+//! [`update_server_info`], which `update-server-info` runs and which should
+//! be run whenever refs change in a repository served this way.
+
+use crate::repository::Repository;
+use crate::revision::peel_chain;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Render `info/refs`
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+pub fn info_refs(repo: &Repository) -> Result<String> {
+    let packed = crate::refs::read_packed_peeled(&repo.git_dir)?;
+    let mut out = String::new();
+    for (name, hash) in crate::refs::read_refs(&repo.git_dir)? {
+        out.push_str(&format!("{}\t{}\n", hash, name));
+        // Annotated tags also advertise the object they lead to
+        if let Some(peeled) = peeled_value(repo, &packed, &name, &hash)? {
+            out.push_str(&format!("{}\t{}^{{}}\n", peeled, name));
+        }
+    }
+    Ok(out)
+}
+
+/// The object an annotated tag ref leads to, or `None` for other refs
+///
+/// packed-refs knows it unless a loose ref has moved since, which matters
+/// because packed objects can't be read.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `packed` - The repository's [`crate::refs::read_packed_peeled`]
+/// * `name` - The ref's full name
+/// * `hash` - The ref's value
+pub(crate) fn peeled_value(
+    repo: &Repository,
+    packed: &BTreeMap<String, (String, Option<String>)>,
+    name: &str,
+    hash: &str,
+) -> Result<Option<String>> {
+    match packed.get(name) {
+        Some((packed_hash, peeled)) if packed_hash == hash => Ok(peeled.clone()),
+        _ => {
+            let (_, mut chain) =
+                peel_chain(repo, hash).with_context(|| format!("Failed to read {}", name))?;
+            Ok(chain.pop().filter(|_| !chain.is_empty()))
+        }
+    }
+}
+
+/// Render `objects/info/packs`
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+pub fn info_packs(repo: &Repository) -> Result<String> {
+    let mut packs = Vec::new();
+    if let Ok(entries) = fs::read_dir(repo.common_dir.join("objects/pack")) {
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.starts_with("pack-") && name.ends_with(".pack") {
+                packs.push(name);
+            }
+        }
+    }
+    packs.sort();
+    let mut out = String::new();
+    for pack in packs {
+        out.push_str(&format!("P {}\n", pack));
+    }
+    out.push('\n');
+    Ok(out)
+}
+
+/// Replace a file through a temporary file, unless it already has the
+/// content; returns whether it was written
+fn write_if_changed(path: &Path, content: &str, force: bool) -> Result<bool> {
+    if !force && fs::read_to_string(path).is_ok_and(|old| old == content) {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("tmp");
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(true)
+}
+
+/// Regenerate `info/refs` and `objects/info/packs`
+///
+/// Files whose content wouldn't change are left alone, so their
+/// timestamps (and any HTTP caching) stay valid, unless `force` is set.
+///
+/// Returns whether anything was written.
+///
+/// # Arguments
+///
+/// * `repo` - The repository
+/// * `force` - Rewrite the files even if they are up to date
+pub fn update_server_info(repo: &Repository, force: bool) -> Result<bool> {
+    let refs = write_if_changed(&repo.common_dir.join("info/refs"), &info_refs(repo)?, force)?;
+    let packs = write_if_changed(
+        &repo.common_dir.join("objects/info/packs"),
+        &info_packs(repo)?,
+        force,
+    )?;
+    Ok(refs || packs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_update_server_info() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder.file("a", "a\n").commit("first").unwrap();
+        let tag = builder.annotated_tag("v1", "release").unwrap();
+        builder.tag("light").unwrap();
+
+        let repo = builder.repo();
+        assert!(update_server_info(repo, false).unwrap());
+        assert_eq!(
+            fs::read_to_string(repo.common_dir.join("info/refs")).unwrap(),
+            format!(
+                "{c}\trefs/heads/main\n{c}\trefs/tags/light\n{t}\trefs/tags/v1\n{c}\trefs/tags/v1^{{}}\n",
+                c = commit,
+                t = tag
+            )
+        );
+        assert_eq!(
+            fs::read_to_string(repo.common_dir.join("objects/info/packs")).unwrap(),
+            "\n"
+        );
+        assert!(!update_server_info(repo, false).unwrap());
+        assert!(update_server_info(repo, true).unwrap());
+    }
+}
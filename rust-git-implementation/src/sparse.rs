@@ -0,0 +1,273 @@
+//! Sparse checkout patterns (`.git/info/sparse-checkout`)
+//!
+//! With `core.sparseCheckout` set, only the paths the patterns select are
+//! kept in the working tree; the rest are marked skip-worktree in the
+//! index. The file comes in two forms:
+//!
+//! - Cone mode (`core.sparseCheckoutCone`), where the file only lists
+//!   directories, in the shape `git sparse-checkout set --cone` writes:
+//!
+//!   ```text
+//!   /*
+//!   !/*/
+//!   /docs/
+//!   !/docs/*/
+//!   /docs/api/
+//!   ```
+//!
+//!   Files at the top level are always included, `/docs/api/` includes
+//!   everything below `docs/api`, and `!/docs/*/` includes the files
+//!   directly in `docs` but none of its other subdirectories.
+//!
+//! - Full patterns, the older form many repositories still check in, with
+//!   `.gitignore` syntax where a match includes a path instead of ignoring
+//!   it. A path no pattern decides takes the decision of its closest
+//!   parent directory that one does, and is left out if none does.
+//!
+//! Like Git, a file that doesn't have the cone shape is read as full
+//! patterns, with a warning, even when cone mode is configured.
+
+use crate::ignore::Ignore;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::collections::BTreeSet;
+
+/// The patterns of a sparse checkout
+#[derive(Debug, Clone)]
+pub enum Sparse {
+    /// Directories in cone mode
+    Cone {
+        /// Directories included with everything below them
+        recursive: BTreeSet<Vec<u8>>,
+        /// Directories whose files, but not subdirectories, are included
+        parents: BTreeSet<Vec<u8>>,
+    },
+    /// Full `.gitignore`-style patterns
+    Patterns(Ignore),
+}
+
+/// Why a pattern file isn't in cone form
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotCone {
+    /// The offending line
+    pub line: Vec<u8>,
+    /// Whether it is a `!` pattern
+    pub negated: bool,
+}
+
+impl Sparse {
+    /// Read the patterns of a repository
+    ///
+    /// Returns `None` unless `core.sparseCheckout` is set and the
+    /// `info/sparse-checkout` file exists; Git then checks out every path.
+    pub fn for_repo(repo: &Repository) -> Result<Option<Self>> {
+        let config = repo.config()?;
+        if !config.get_bool("core.sparseCheckout")?.unwrap_or(false) {
+            return Ok(None);
+        }
+        let Ok(text) = std::fs::read(repo.git_dir.join("info/sparse-checkout")) else {
+            return Ok(None);
+        };
+        let cone = config.get_bool("core.sparseCheckoutCone")?.unwrap_or(false);
+        Ok(Some(Sparse::parse(&text, cone)))
+    }
+
+    /// Parse a pattern file
+    ///
+    /// With `cone` set, a file that isn't in cone form is read as full
+    /// patterns after a warning.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The file's contents
+    /// * `cone` - Whether cone mode is configured
+    pub fn parse(text: &[u8], cone: bool) -> Self {
+        if cone {
+            match Sparse::parse_cone(text) {
+                Ok(sparse) => return sparse,
+                Err(NotCone { line, negated }) => {
+                    tracing::warn!(
+                        "unrecognized {}pattern: '{}'",
+                        if negated { "negative " } else { "" },
+                        String::from_utf8_lossy(&line)
+                    );
+                    tracing::warn!("disabling cone pattern matching");
+                }
+            }
+        }
+        let mut patterns = Ignore::new();
+        patterns.add_patterns(text);
+        Sparse::Patterns(patterns)
+    }
+
+    /// Parse a pattern file in cone form
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The file's contents
+    ///
+    /// # Errors
+    ///
+    /// Returns the first line that doesn't fit the cone shape
+    pub fn parse_cone(text: &[u8]) -> std::result::Result<Self, NotCone> {
+        let mut recursive = BTreeSet::new();
+        let mut parents = BTreeSet::new();
+        for line in text.split(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() || line.starts_with(b"#") || line == b"/*" || line == b"!/*/" {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix(b"!") {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let not_cone = || NotCone {
+                line: line.to_vec(),
+                negated,
+            };
+            let dir = if negated {
+                pattern.strip_suffix(b"/*/")
+            } else {
+                pattern.strip_suffix(b"/")
+            };
+            let dir = dir
+                .and_then(|dir| dir.strip_prefix(b"/"))
+                .and_then(unescape)
+                .filter(|dir| !dir.is_empty())
+                .ok_or_else(not_cone)?;
+            if negated {
+                // `!/a/*/` narrows an earlier `/a/` to the files in `a`
+                if !recursive.remove(&dir) {
+                    return Err(not_cone());
+                }
+                parents.insert(dir);
+            } else {
+                recursive.insert(dir);
+            }
+        }
+        // The leading directories of an included one hold files too
+        for dir in recursive.iter().chain(parents.clone().iter()) {
+            let mut end = dir.len();
+            while let Some(slash) = dir[..end].iter().rposition(|&b| b == b'/') {
+                parents.insert(dir[..slash].to_vec());
+                end = slash;
+            }
+        }
+        Ok(Sparse::Cone { recursive, parents })
+    }
+
+    /// Check whether a file belongs in the working tree
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file's path relative to the work tree root
+    pub fn includes(&self, path: &[u8]) -> bool {
+        match self {
+            Sparse::Cone { recursive, parents } => {
+                let Some(slash) = path.iter().rposition(|&b| b == b'/') else {
+                    return true;
+                };
+                if parents.contains(&path[..slash]) {
+                    return true;
+                }
+                path.iter()
+                    .enumerate()
+                    .filter(|&(_, &b)| b == b'/')
+                    .any(|(i, _)| recursive.contains(&path[..i]))
+            }
+            Sparse::Patterns(patterns) => {
+                // Undecided paths take the decision of their parent
+                let mut end = path.len();
+                let mut is_dir = false;
+                loop {
+                    if let Some(included) = patterns.decide(&path[..end], is_dir) {
+                        return included;
+                    }
+                    match path[..end].iter().rposition(|&b| b == b'/') {
+                        Some(slash) => end = slash,
+                        None => return false,
+                    }
+                    is_dir = true;
+                }
+            }
+        }
+    }
+}
+
+/// Remove backslash escapes from a cone directory, rejecting wildcards
+fn unescape(dir: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(dir.len());
+    let mut bytes = dir.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\\' => out.push(*bytes.next()?),
+            b'*' | b'?' | b'[' => return None,
+            _ => out.push(byte),
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cone() {
+        let sparse = Sparse::parse(b"/*\n!/*/\n/docs/\n!/docs/*/\n/docs/api/\n/src/\n", true);
+        assert!(matches!(sparse, Sparse::Cone { .. }));
+        for (path, included) in [
+            ("README", true),
+            ("docs/index.md", true),
+            ("docs/guide/intro.md", false),
+            ("docs/api/v1/call.md", true),
+            ("src/main.rs", true),
+            ("src/deep/mod.rs", true),
+            ("tests/it.rs", false),
+            ("srcx/a", false),
+        ] {
+            assert_eq!(sparse.includes(path.as_bytes()), included, "{}", path);
+        }
+
+        // Leading directories of a cone have their files included
+        let sparse = Sparse::parse_cone(b"/*\n!/*/\n/a/b/c/\n").unwrap();
+        assert!(sparse.includes(b"a/file"));
+        assert!(sparse.includes(b"a/b/file"));
+        assert!(!sparse.includes(b"a/x/file"));
+    }
+
+    #[test]
+    fn test_not_cone() {
+        assert_eq!(
+            Sparse::parse_cone(b"/*\n!/*/\n*.md\n").unwrap_err(),
+            NotCone {
+                line: b"*.md".to_vec(),
+                negated: false
+            }
+        );
+        assert!(Sparse::parse_cone(b"!/docs/*/\n").unwrap_err().negated);
+        assert!(Sparse::parse_cone(b"/do*s/\n").is_err());
+        assert!(Sparse::parse_cone(b"/do\\*s/\n").is_ok());
+    }
+
+    #[test]
+    fn test_full_patterns() {
+        let text = b"/*\n!/*/\n/docs/\n!/docs/drafts/\n*.md\n";
+        // Cone mode falls back to full patterns for files like this one
+        let sparse = Sparse::parse(text, true);
+        assert!(matches!(sparse, Sparse::Patterns(_)));
+        for (path, included) in [
+            ("README", true),
+            ("docs/guide/intro.txt", true),
+            ("docs/drafts/next.txt", false),
+            ("docs/drafts/next.md", true),
+            ("src/main.rs", false),
+            ("src/notes.md", true),
+        ] {
+            assert_eq!(sparse.includes(path.as_bytes()), included, "{}", path);
+        }
+
+        // Nothing matches: nothing is included
+        assert!(!Sparse::parse(b"# empty\n", false).includes(b"a.txt"));
+    }
+}
@@ -0,0 +1,775 @@
+//! Working tree status
+//!
+//! Compares the index against `HEAD` and the working tree and sorts every
+//! path into one of the sections `status` shows: staged changes, unmerged
+//! paths, unstaged changes and untracked files. Paths are bytes, as in the
+//! index.
+
+use crate::diff::find_renames;
+use crate::ignore::Ignore;
+use crate::index::{ConflictKind, Index, IndexEntry};
+use crate::objects::{Blob, FileMode, GitObject};
+use crate::repository::Repository;
+use crate::tree_walk::TreeWalk;
+use crate::utils::path_from_bytes;
+use crate::walk::Walker;
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// The files of a tree by path, with their mode and blob hash
+pub type TreeFiles = BTreeMap<Vec<u8>, (FileMode, String)>;
+
+/// How a path differs between two versions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    Added,
+    Modified,
+    Deleted,
+    /// Staged under a new path; see [`Status::renames`]
+    Renamed,
+}
+
+impl FileChange {
+    /// Label used in the long status format
+    pub fn description(&self) -> &str {
+        match self {
+            FileChange::Added => "new file",
+            FileChange::Modified => "modified",
+            FileChange::Deleted => "deleted",
+            FileChange::Renamed => "renamed",
+        }
+    }
+
+    /// Letter used in the short status format
+    pub fn short_code(&self) -> char {
+        match self {
+            FileChange::Added => 'A',
+            FileChange::Modified => 'M',
+            FileChange::Deleted => 'D',
+            FileChange::Renamed => 'R',
+        }
+    }
+}
+
+/// Which submodule changes to report (`--ignore-submodules`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IgnoreSubmodules {
+    /// Report new commits, modified and untracked content
+    #[default]
+    None,
+    /// Don't report untracked content
+    Untracked,
+    /// Only report new commits
+    Dirty,
+    /// Don't look at submodules at all
+    All,
+}
+
+impl IgnoreSubmodules {
+    /// Parse a setting name (`none`, `untracked`, `dirty` or `all`)
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(IgnoreSubmodules::None),
+            "untracked" => Ok(IgnoreSubmodules::Untracked),
+            "dirty" => Ok(IgnoreSubmodules::Dirty),
+            "all" => Ok(IgnoreSubmodules::All),
+            _ => anyhow::bail!("bad --ignore-submodules argument: {}", s),
+        }
+    }
+}
+
+/// How a submodule differs from what the superproject records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubmoduleState {
+    /// Its `HEAD` is not the commit in the superproject's index
+    pub new_commits: bool,
+    /// It has staged or unstaged changes
+    pub modified_content: bool,
+    /// It has untracked files
+    pub untracked_content: bool,
+}
+
+impl SubmoduleState {
+    /// Whether nothing differs
+    pub fn is_clean(&self) -> bool {
+        !self.new_commits && !self.modified_content && !self.untracked_content
+    }
+
+    /// The note shown after the path in the long format, e.g.
+    /// `new commits, modified content`
+    pub fn description(&self) -> String {
+        let notes = [
+            (self.new_commits, "new commits"),
+            (self.modified_content, "modified content"),
+            (self.untracked_content, "untracked content"),
+        ];
+        let notes: Vec<&str> = notes
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, note)| *note)
+            .collect();
+        notes.join(", ")
+    }
+
+    /// Letter used in the short format: `M` for new commits, `m` for
+    /// modified content, `?` for untracked content only
+    pub fn short_code(&self) -> char {
+        if self.new_commits {
+            'M'
+        } else if self.modified_content {
+            'm'
+        } else {
+            '?'
+        }
+    }
+}
+
+/// The state of a working tree
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Status {
+    /// `HEAD` points to a branch without commits
+    pub unborn: bool,
+    /// Index changes relative to `HEAD`
+    pub staged: Vec<(Vec<u8>, FileChange)>,
+    /// Original path of each staged rename, by new path
+    pub renames: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Paths with conflict stages
+    pub unmerged: Vec<(Vec<u8>, ConflictKind)>,
+    /// Worktree changes relative to the index
+    pub unstaged: Vec<(Vec<u8>, FileChange)>,
+    /// What changed in each modified submodule; see
+    /// [`Status::check_submodules`]
+    pub submodules: BTreeMap<Vec<u8>, SubmoduleState>,
+    /// Files not in the index; directories without tracked files are
+    /// reported once, with a trailing `/`
+    pub untracked: Vec<Vec<u8>>,
+}
+
+impl Status {
+    /// Compute the status of a working tree
+    ///
+    /// Renames are not detected; see [`Status::detect_renames`].
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index
+    /// * `head` - The files of `HEAD`'s tree; empty on an unborn branch
+    /// * `worktree` - The working tree
+    /// * `ignore` - Ignore rules from outside the working tree
+    /// * `file_mode` - Whether the executable bit is trusted (`core.fileMode`)
+    /// * `symlinks` - Whether symlinks are supported (`core.symlinks`)
+    pub fn compute(
+        index: &Index,
+        head: &TreeFiles,
+        worktree: &dyn WorkTree,
+        ignore: Ignore,
+        file_mode: bool,
+        symlinks: bool,
+    ) -> Result<Self> {
+        let staged = staged_changes(index, head);
+        let unmerged = index
+            .conflicts()
+            .map(|c| (c.path.to_vec(), c.kind()))
+            .collect();
+        let unstaged = worktree_changes(index, worktree, file_mode, symlinks)?
+            .into_iter()
+            .map(|(e, change)| (e.path.clone(), change))
+            .collect();
+
+        Ok(Status {
+            unborn: false,
+            staged,
+            renames: BTreeMap::new(),
+            unmerged,
+            unstaged,
+            submodules: BTreeMap::new(),
+            untracked: untracked(index, worktree, ignore)?,
+        })
+    }
+
+    /// Turn staged deletions and additions of similar files into renames
+    ///
+    /// When the index ignores case, a path whose spelling changed only in
+    /// case is a rename regardless of its content.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository holding the blobs
+    /// * `index` - The index the status was computed from
+    /// * `head` - The files of `HEAD`'s tree
+    pub fn detect_renames(
+        &mut self,
+        repo: &Repository,
+        index: &Index,
+        head: &TreeFiles,
+    ) -> Result<()> {
+        let paths = |kind| -> Vec<&[u8]> {
+            self.staged
+                .iter()
+                .filter(|(_, change)| *change == kind)
+                .map(|(path, _)| path.as_slice())
+                .collect()
+        };
+        let (mut deleted, mut added) = (paths(FileChange::Deleted), paths(FileChange::Added));
+        let mut renames = BTreeMap::new();
+        if index.ignore_case() {
+            added.retain(|new| {
+                match deleted.iter().position(|old| old.eq_ignore_ascii_case(new)) {
+                    Some(i) => {
+                        renames.insert(new.to_vec(), deleted.remove(i).to_vec());
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+        let old: Vec<&str> = deleted.iter().map(|p| head[*p].1.as_str()).collect();
+        let new: Vec<&str> = added
+            .iter()
+            .map(|p| index.get(p).map_or("", |e| e.hash.as_str()))
+            .collect();
+
+        let read = |hash: &str| Ok(repo.read_object_parsed(hash)?.1);
+        renames.extend(
+            find_renames(&old, &new, read)?
+                .into_iter()
+                .map(|(i, j, _)| (added[j].to_vec(), deleted[i].to_vec())),
+        );
+
+        let sources: BTreeSet<&Vec<u8>> = renames.values().collect();
+        self.staged
+            .retain(|(path, change)| *change != FileChange::Deleted || !sources.contains(path));
+        for (path, change) in &mut self.staged {
+            if renames.contains_key(path) {
+                *change = FileChange::Renamed;
+            }
+        }
+        self.renames.extend(renames);
+        Ok(())
+    }
+
+    /// Report submodules whose checkout differs from the recorded commit
+    ///
+    /// Submodules that are not checked out are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The superproject
+    /// * `index` - The index the status was computed from
+    /// * `ignore` - Which changes to leave out
+    pub fn check_submodules(
+        &mut self,
+        repo: &Repository,
+        index: &Index,
+        ignore: IgnoreSubmodules,
+    ) -> Result<()> {
+        if ignore == IgnoreSubmodules::All {
+            return Ok(());
+        }
+        for entry in index.entries() {
+            if entry.stage != 0 || entry.mode != FileMode::Gitlink {
+                continue;
+            }
+            let dir = repo.work_tree.join(path_from_bytes(&entry.path)?);
+            if !dir.join(".git").exists() {
+                continue;
+            }
+            let state = submodule_state(&Repository::open_snapshot(&dir)?, &entry.hash, ignore)?;
+            if state.is_clean() {
+                continue;
+            }
+            if !self.unstaged.iter().any(|(path, _)| *path == entry.path) {
+                self.unstaged
+                    .push((entry.path.clone(), FileChange::Modified));
+                self.unstaged.sort_by(|a, b| a.0.cmp(&b.0));
+            }
+            self.submodules.insert(entry.path.clone(), state);
+        }
+        Ok(())
+    }
+
+    /// Whether there is nothing to report
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty()
+            && self.unmerged.is_empty()
+            && self.unstaged.is_empty()
+            && self.untracked.is_empty()
+    }
+}
+
+/// Compare a submodule's checkout with the commit the superproject records
+fn submodule_state(
+    submodule: &Repository,
+    recorded: &str,
+    ignore: IgnoreSubmodules,
+) -> Result<SubmoduleState> {
+    let head = submodule.head()?;
+    let mut state = SubmoduleState {
+        new_commits: head.as_deref() != Some(recorded),
+        ..Default::default()
+    };
+    if ignore == IgnoreSubmodules::Dirty {
+        return Ok(state);
+    }
+
+    let head_files = match &head {
+        Some(commit) => tree_files(submodule, &submodule.read_commit(commit)?.tree)?,
+        None => TreeFiles::new(),
+    };
+    let index = Index::read(submodule.index_path())?;
+    let ignore_rules = Ignore::for_repo(submodule)?;
+    let config = submodule.config()?;
+    let mut status = Status::compute(
+        &index,
+        &head_files,
+        &submodule.worktree()?,
+        ignore_rules,
+        config.get_bool("core.fileMode")?.unwrap_or(true),
+        config.get_bool("core.symlinks")?.unwrap_or(true),
+    )?;
+    status.check_submodules(submodule, &index, ignore)?;
+    state.modified_content =
+        !status.staged.is_empty() || !status.unmerged.is_empty() || !status.unstaged.is_empty();
+    state.untracked_content = ignore != IgnoreSubmodules::Untracked && !status.untracked.is_empty();
+    Ok(state)
+}
+
+/// List every file of a tree, descending into subtrees
+///
+/// # Arguments
+///
+/// * `repo` - The repository holding the trees
+/// * `tree` - Hash of the root tree
+pub fn tree_files(repo: &Repository, tree: &str) -> Result<TreeFiles> {
+    TreeWalk::new(repo, tree)
+        .map(|entry| entry.map(|entry| (entry.path, (entry.mode, entry.hash))))
+        .collect()
+}
+
+/// Compare the index against `HEAD`
+///
+/// Conflicted paths are left to [`Index::conflicts`], and intent-to-add
+/// entries count as unstaged.
+///
+/// # Arguments
+///
+/// * `index` - The index
+/// * `head` - The files of `HEAD`'s tree
+pub fn staged_changes(index: &Index, head: &TreeFiles) -> Vec<(Vec<u8>, FileChange)> {
+    let mut changes = Vec::new();
+    for entry in index.entries() {
+        if entry.stage != 0 || entry.intent_to_add {
+            continue;
+        }
+        match head.get(&entry.path) {
+            None => changes.push((entry.path.clone(), FileChange::Added)),
+            Some((mode, hash)) if *mode != entry.mode || *hash != entry.hash => {
+                changes.push((entry.path.clone(), FileChange::Modified))
+            }
+            Some(_) => {}
+        }
+    }
+    let indexed: BTreeSet<&[u8]> = index.entries().iter().map(|e| e.path.as_slice()).collect();
+    for path in head.keys() {
+        if !indexed.contains(path.as_slice()) {
+            changes.push((path.clone(), FileChange::Deleted));
+        }
+    }
+    changes.sort_by(|a, b| a.0.cmp(&b.0));
+    changes
+}
+
+/// The mode a worktree file would be staged with
+///
+/// Without `core.fileMode` the executable bit can't be trusted, and without
+/// `core.symlinks` symlinks are checked out as plain files, so the index
+/// mode is kept in those cases.
+///
+/// # Arguments
+///
+/// * `worktree` - The working tree
+/// * `path` - The entry's path in the working tree
+/// * `entry` - The index entry for the path
+/// * `file_mode` - Whether the executable bit is trusted (`core.fileMode`)
+/// * `symlinks` - Whether symlinks are supported (`core.symlinks`)
+pub fn worktree_mode(
+    worktree: &dyn WorkTree,
+    path: &Path,
+    entry: &IndexEntry,
+    file_mode: bool,
+    symlinks: bool,
+) -> Result<FileMode> {
+    let mode = worktree.mode(path)?;
+    Ok(match (entry.mode, mode) {
+        (FileMode::Regular | FileMode::Executable, FileMode::Regular | FileMode::Executable)
+            if !file_mode =>
+        {
+            entry.mode
+        }
+        (FileMode::Symlink, FileMode::Regular) if !symlinks => entry.mode,
+        // A checked-out submodule is a directory
+        (FileMode::Gitlink, FileMode::Tree) => entry.mode,
+        _ => mode,
+    })
+}
+
+/// Find index entries whose worktree file differs from the staged content
+///
+/// A changed mode, such as a file made executable, counts as a
+/// modification too; see [`worktree_mode`]. Files whose stat data matches
+/// the entry are not read, unless the match is racy (see
+/// [`Index::is_racy`]). Entries marked
+/// assume-unchanged or skip-worktree are not looked at, and conflicted
+/// paths are reported separately by [`Index::conflicts`].
+///
+/// # Arguments
+///
+/// * `index` - The index to compare against
+/// * `worktree` - The working tree
+/// * `file_mode` - Whether the executable bit is trusted (`core.fileMode`)
+/// * `symlinks` - Whether symlinks are supported (`core.symlinks`)
+pub fn worktree_changes<'a>(
+    index: &'a Index,
+    worktree: &dyn WorkTree,
+    file_mode: bool,
+    symlinks: bool,
+) -> Result<Vec<(&'a IndexEntry, FileChange)>> {
+    let _span = tracing::debug_span!("status_worktree", entries = index.entries().len()).entered();
+    let mut changes = Vec::new();
+    for entry in index.entries() {
+        if entry.stage != 0 || entry.assume_valid || entry.skip_worktree {
+            continue;
+        }
+        let path = path_from_bytes(&entry.path)?;
+        if entry.mode == FileMode::Gitlink {
+            // The checkout itself is looked at by Status::check_submodules
+            if worktree.list_dir(path).is_err() {
+                changes.push((entry, FileChange::Deleted));
+            }
+            continue;
+        }
+        if !worktree.exists(path) {
+            changes.push((entry, FileChange::Deleted));
+            continue;
+        }
+        if entry.intent_to_add {
+            changes.push((entry, FileChange::Added));
+            continue;
+        }
+        if worktree_mode(worktree, path, entry, file_mode, symlinks)? != entry.mode {
+            changes.push((entry, FileChange::Modified));
+            continue;
+        }
+        if !index.is_racy(entry)
+            && worktree
+                .metadata(path)
+                .is_some_and(|metadata| entry.matches_stat(&metadata))
+        {
+            continue;
+        }
+        let hash = if entry.mode == FileMode::Symlink {
+            Blob::new(worktree.read_link(path)?).hash()?
+        } else {
+            worktree.hash_file(path)?
+        };
+        if hash != entry.hash {
+            changes.push((entry, FileChange::Modified));
+        }
+    }
+    Ok(changes)
+}
+
+/// List files that are not in the index
+///
+/// Ignored files are left out. A directory containing no tracked files is
+/// listed as `dir/` instead of file by file, as Git does; so is a nested
+/// repository that is not a submodule.
+///
+/// # Arguments
+///
+/// * `index` - The index
+/// * `worktree` - The working tree
+/// * `ignore` - Ignore rules from outside the working tree; `.gitignore`
+///   files are read during the walk
+pub fn untracked(index: &Index, worktree: &dyn WorkTree, ignore: Ignore) -> Result<Vec<Vec<u8>>> {
+    let _span = tracing::debug_span!("status_untracked").entered();
+    let tracked: BTreeSet<&[u8]> = index.entries().iter().map(|e| e.path.as_slice()).collect();
+    let tracked_dirs: BTreeSet<&[u8]> = tracked.iter().flat_map(|path| parents(path)).collect();
+
+    let walk = Walker::new(worktree, ignore).run()?;
+    let files = walk.files.into_iter().map(|path| (path, false));
+    let repositories = walk.repositories.into_iter().map(|path| (path, true));
+    let mut untracked = BTreeSet::new();
+    for (path, is_repo) in files.chain(repositories) {
+        if tracked.contains(path.as_slice()) {
+            continue;
+        }
+
+        // Report the outermost directory that has no tracked files
+        let untracked_dir = parents(&path).find(|dir| !tracked_dirs.contains(dir));
+        match untracked_dir {
+            Some(dir) => untracked.insert([dir, b"/"].concat()),
+            None if is_repo => untracked.insert([&path[..], b"/"].concat()),
+            None => untracked.insert(path),
+        };
+    }
+    Ok(untracked.into_iter().collect())
+}
+
+/// The directories leading to a path, outermost first
+fn parents(path: &[u8]) -> impl Iterator<Item = &[u8]> {
+    path.iter()
+        .enumerate()
+        .filter(|&(_, &b)| b == b'/')
+        .map(move |(i, _)| &path[..i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::stage;
+    use crate::testing::RepoBuilder;
+    use crate::worktree::MemoryWorkTree;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_worktree_changes() {
+        let mut index = Index::new();
+        stage(&mut index, "same", b"one").unwrap();
+        stage(&mut index, "changed", b"one").unwrap();
+        stage(&mut index, "deleted", b"one").unwrap();
+        stage(&mut index, "ignored", b"one").unwrap();
+        index.get_mut("ignored").unwrap().assume_valid = true;
+
+        let mut worktree = MemoryWorkTree::new();
+        worktree.write(Path::new("same"), b"one").unwrap();
+        worktree.write(Path::new("changed"), b"two").unwrap();
+        worktree.write(Path::new("ignored"), b"two").unwrap();
+
+        let changes: Vec<(&[u8], FileChange)> = worktree_changes(&index, &worktree, true, true)
+            .unwrap()
+            .into_iter()
+            .map(|(e, change)| (e.path.as_slice(), change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                (&b"changed"[..], FileChange::Modified),
+                (b"deleted", FileChange::Deleted)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_worktree_changes_trusts_stat_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        fs::write(&file, b"one\n").unwrap();
+        let past = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(past)
+            .unwrap();
+
+        // Staged with other content, but the stat data of the file
+        let mut index = Index::new();
+        stage(&mut index, "a", b"two\n").unwrap();
+        index
+            .get_mut("a")
+            .unwrap()
+            .set_stat(&fs::metadata(&file).unwrap());
+        let worktree = crate::worktree::FsWorkTree::new(dir.path());
+
+        // Racy until the index is on disk, so the content is compared
+        assert_eq!(
+            worktree_changes(&index, &worktree, true, true).unwrap()[0].1,
+            FileChange::Modified
+        );
+
+        // A matching stat says the file is unchanged without reading it
+        let path = dir.path().join("index");
+        index.write(&path).unwrap();
+        let index = Index::read(&path).unwrap();
+        assert!(worktree_changes(&index, &worktree, true, true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_worktree_changes_mode() {
+        let mut index = Index::new();
+        stage(&mut index, "script", b"echo\n").unwrap();
+        let mut worktree = MemoryWorkTree::new();
+        worktree.write(Path::new("script"), b"echo\n").unwrap();
+        worktree.set_executable(Path::new("script"), true).unwrap();
+
+        // `chmod +x script`: git status shows ` M script`
+        let changes = worktree_changes(&index, &worktree, true, true).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].1, FileChange::Modified);
+
+        // Unless the executable bit isn't trusted
+        assert!(worktree_changes(&index, &worktree, false, true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_untracked_collapses_directories() {
+        let mut index = Index::new();
+        stage(&mut index, "src/main.rs", b"").unwrap();
+
+        let mut worktree = MemoryWorkTree::new();
+        for path in [
+            "src/main.rs",
+            "src/new.rs",
+            "docs/a.md",
+            "docs/b/c.md",
+            "top",
+        ] {
+            worktree.write(Path::new(path), b"").unwrap();
+        }
+
+        assert_eq!(
+            untracked(&index, &worktree, Ignore::new()).unwrap(),
+            vec![&b"docs/"[..], b"src/new.rs", b"top"]
+        );
+
+        // Directories holding only ignored files are not listed
+        let mut ignore = Ignore::new();
+        ignore.add_patterns("*.md\n");
+        assert_eq!(
+            untracked(&index, &worktree, ignore).unwrap(),
+            vec![&b"src/new.rs"[..], b"top"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_untracked_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut index = Index::new();
+        let hash = Blob::new(&b""[..]).hash().unwrap();
+        index.add(IndexEntry::new(&b"caf\xe9"[..], FileMode::Regular, hash));
+
+        let mut worktree = MemoryWorkTree::new();
+        for path in [&b"caf\xe9"[..], b"caf\xea", "caf\u{e9}".as_bytes()] {
+            let path = Path::new(std::ffi::OsStr::from_bytes(path));
+            worktree.write(path, b"").unwrap();
+        }
+        assert_eq!(
+            untracked(&index, &worktree, Ignore::new()).unwrap(),
+            vec![&b"caf\xc3\xa9"[..], b"caf\xea"]
+        );
+        assert!(worktree_changes(&index, &worktree, true, true)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_compute_reports_conflicts() {
+        let mut index = Index::new();
+        stage(&mut index, "a", b"a").unwrap();
+        for stage in [1, 2, 3] {
+            let mut entry = IndexEntry::new("c", FileMode::Regular, "0".repeat(40));
+            entry.stage = stage;
+            index.add(entry);
+        }
+        let mut worktree = MemoryWorkTree::new();
+        worktree.write(Path::new("a"), b"a").unwrap();
+        worktree.write(Path::new("c"), b"<<<<<<<").unwrap();
+
+        let status = Status::compute(
+            &index,
+            &TreeFiles::new(),
+            &worktree,
+            Ignore::new(),
+            true,
+            true,
+        )
+        .unwrap();
+        assert_eq!(status.staged, vec![(b"a".to_vec(), FileChange::Added)]);
+        assert_eq!(
+            status.unmerged,
+            vec![(b"c".to_vec(), ConflictKind::BothModified)]
+        );
+        assert!(status.unstaged.is_empty());
+        assert!(status.untracked.is_empty());
+    }
+
+    #[test]
+    fn test_staged_changes_and_renames() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let text = "line 1\nline 2\nline 3\nline 4\n";
+        builder
+            .file("a.txt", text)
+            .file("b.txt", "b\n")
+            .file("gone.txt", "gone\n");
+        let first = builder.commit("first").unwrap();
+        builder
+            .remove("a.txt")
+            .remove("gone.txt")
+            .file("dir/moved.txt", text.replace('4', "four"))
+            .file("b.txt", "changed\n")
+            .file("new.txt", "unrelated\n");
+        let second = builder.commit("second").unwrap();
+
+        let repo = builder.repo();
+        let head = tree_files(repo, &repo.read_commit(&first).unwrap().tree).unwrap();
+        let mut index = Index::new();
+        for (path, (mode, hash)) in
+            tree_files(repo, &repo.read_commit(&second).unwrap().tree).unwrap()
+        {
+            index.add(IndexEntry::new(path, mode, hash));
+        }
+
+        let mut status = Status {
+            staged: staged_changes(&index, &head),
+            ..Default::default()
+        };
+        status.detect_renames(repo, &index, &head).unwrap();
+        assert_eq!(
+            status.staged,
+            vec![
+                (b"b.txt".to_vec(), FileChange::Modified),
+                (b"dir/moved.txt".to_vec(), FileChange::Renamed),
+                (b"gone.txt".to_vec(), FileChange::Deleted),
+                (b"new.txt".to_vec(), FileChange::Added),
+            ]
+        );
+        assert_eq!(status.renames[&b"dir/moved.txt"[..]], b"a.txt");
+
+        // With core.ignoreCase a change of case alone is a rename, even to
+        // an unrelated (here empty) blob
+        let empty = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+        let mut index = Index::new();
+        index.add(IndexEntry::new("A.TXT", FileMode::Regular, empty));
+        index.add(IndexEntry::new(
+            "b.txt",
+            FileMode::Regular,
+            &head[&b"b.txt"[..]].1,
+        ));
+        index.add(IndexEntry::new(
+            "gone.txt",
+            FileMode::Regular,
+            &head[&b"gone.txt"[..]].1,
+        ));
+        index.set_ignore_case(true);
+        let mut status = Status {
+            staged: staged_changes(&index, &head),
+            ..Default::default()
+        };
+        status.detect_renames(repo, &index, &head).unwrap();
+        assert_eq!(
+            status.staged,
+            vec![(b"A.TXT".to_vec(), FileChange::Renamed)]
+        );
+        assert_eq!(status.renames[&b"A.TXT"[..]], b"a.txt");
+    }
+}
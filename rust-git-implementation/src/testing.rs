@@ -0,0 +1,422 @@
+//! Deterministic repository fixtures
+//!
+//! Enabled with the `testing` cargo feature. [`RepoBuilder`] scripts a
+//! history into a fresh temporary repository:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use oxid::testing::RepoBuilder;
+//!
+//! let mut builder = RepoBuilder::new()?;
+//! builder.file("README", "hello\n").commit("initial")?;
+//! builder.branch("topic")?.checkout("topic")?;
+//! builder.file("topic.txt", "work\n").commit("topic work")?;
+//! builder.checkout("main")?.merge("topic", "merge topic")?;
+//! builder.tag("v1.0")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Identities and timestamps follow Git's own test suite: commits are made
+//! by `A U Thor <author@example.com>` and `C O Mitter
+//! <committer@example.com>`, starting at 1112912053 -0700 and advancing a
+//! minute per commit or tag, so the same script always produces the same
+//! hashes. Only objects and refs are written; the worktree and index stay
+//! empty.
+//!
+//! [`store`] and [`stage`] write single blobs and index entries for tests
+//! that need an index, and [`serve_http`] publishes a directory over HTTP
+//! for transport tests.
+
+use crate::index::{Index, IndexEntry};
+use crate::objects::{Blob, Commit, FileMode, GitObject, Signature, Tree, TreeEntry};
+use crate::repository::{InitOptions, Repository};
+use crate::utils::hash_data;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Time of the first commit is `START_TIME + TICK`
+const START_TIME: i64 = 1112911993;
+const TICK: i64 = 60;
+const OFFSET: i32 = -7 * 60;
+
+/// Paths and contents of the files in a commit
+type Files = BTreeMap<String, Vec<u8>>;
+
+/// Builds a repository history with fixed identities and timestamps
+pub struct RepoBuilder {
+    dir: TempDir,
+    repo: Repository,
+    time: i64,
+    branch: String,
+    /// Files the next commit on the current branch will contain
+    files: Files,
+    /// Files of every commit made so far
+    snapshots: HashMap<String, Files>,
+}
+
+impl RepoBuilder {
+    /// Create a builder for an empty repository on branch `main`
+    pub fn new() -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let options = InitOptions {
+            initial_branch: Some("main".to_string()),
+            ..Default::default()
+        };
+        let repo = Repository::init_with(dir.path(), &options)?;
+        Ok(Self {
+            dir,
+            repo,
+            time: START_TIME,
+            branch: "main".to_string(),
+            files: Files::new(),
+            snapshots: HashMap::new(),
+        })
+    }
+
+    /// Top of the repository's worktree
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The repository being built
+    pub fn repo(&self) -> &Repository {
+        &self.repo
+    }
+
+    /// Keep the repository after the builder is dropped
+    ///
+    /// The directory is deleted when the returned [`TempDir`] is dropped.
+    pub fn into_tempdir(self) -> TempDir {
+        self.dir
+    }
+
+    /// Branch new commits go to
+    pub fn current_branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Set a file's contents for the next commit
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path relative to the top of the worktree, with `/` separators
+    /// * `contents` - New file contents
+    pub fn file(&mut self, path: &str, contents: impl Into<Vec<u8>>) -> &mut Self {
+        self.files.insert(path.to_string(), contents.into());
+        self
+    }
+
+    /// Leave a file out of the next commit
+    pub fn remove(&mut self, path: &str) -> &mut Self {
+        self.files.remove(path);
+        self
+    }
+
+    /// Commit the current files on the current branch
+    ///
+    /// Returns the hash of the new commit.
+    pub fn commit(&mut self, message: &str) -> Result<String> {
+        let parents = self.head()?.into_iter().collect();
+        self.write_commit(parents, message)
+    }
+
+    /// Merge a branch into the current branch
+    ///
+    /// The merge contains the current files plus the files of the other
+    /// branch's tip that the current branch doesn't have; set the files
+    /// beforehand to record a conflict resolution.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - Branch to merge
+    /// * `message` - Merge commit message
+    pub fn merge(&mut self, branch: &str, message: &str) -> Result<String> {
+        let ours = self
+            .head()?
+            .with_context(|| format!("branch '{}' has no commits", self.branch))?;
+        let theirs = self.tip(branch)?;
+        for (path, contents) in &self.snapshots[&theirs] {
+            self.files
+                .entry(path.clone())
+                .or_insert_with(|| contents.clone());
+        }
+        self.write_commit(vec![ours, theirs], message)
+    }
+
+    /// Create a branch at the current commit
+    pub fn branch(&mut self, name: &str) -> Result<&mut Self> {
+        let head = self
+            .head()?
+            .with_context(|| format!("branch '{}' has no commits", self.branch))?;
+        self.write_ref(&format!("refs/heads/{}", name), &head)?;
+        Ok(self)
+    }
+
+    /// Switch to an existing branch, taking over its files
+    pub fn checkout(&mut self, name: &str) -> Result<&mut Self> {
+        let tip = self.tip(name)?;
+        fs::write(
+            self.repo.git_dir.join("HEAD"),
+            format!("ref: refs/heads/{}\n", name),
+        )?;
+        self.branch = name.to_string();
+        self.files = self.snapshots[&tip].clone();
+        Ok(self)
+    }
+
+    /// Create a lightweight tag at the current commit
+    pub fn tag(&mut self, name: &str) -> Result<&mut Self> {
+        let head = self
+            .head()?
+            .with_context(|| format!("branch '{}' has no commits", self.branch))?;
+        self.write_ref(&format!("refs/tags/{}", name), &head)?;
+        Ok(self)
+    }
+
+    /// Create an annotated tag at the current commit
+    ///
+    /// Returns the hash of the tag object.
+    pub fn annotated_tag(&mut self, name: &str, message: &str) -> Result<String> {
+        let head = self
+            .head()?
+            .with_context(|| format!("branch '{}' has no commits", self.branch))?;
+        self.tag_object(name, &head, message)
+    }
+
+    /// Create an annotated tag of any object, e.g. of another tag
+    ///
+    /// Returns the hash of the tag object.
+    pub fn tag_object(&mut self, name: &str, target: &str, message: &str) -> Result<String> {
+        let (kind, _) = self.repo.object_header(target)?;
+        self.time += TICK;
+        let tagger = Signature::new("C O Mitter", "committer@example.com", self.time, OFFSET);
+        let content = format!(
+            "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
+            target,
+            kind.as_str(),
+            name,
+            tagger,
+            with_newline(message)
+        );
+        let hash = self.write_raw("tag", content.as_bytes())?;
+        self.write_ref(&format!("refs/tags/{}", name), &hash)?;
+        Ok(hash)
+    }
+
+    /// Commit the current branch points to, if any
+    pub fn head(&self) -> Result<Option<String>> {
+        let path = self.repo.git_dir.join("refs/heads").join(&self.branch);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?.trim().to_string()))
+    }
+
+    /// Commit a branch points to
+    fn tip(&self, branch: &str) -> Result<String> {
+        let path = self.repo.git_dir.join("refs/heads").join(branch);
+        let tip =
+            fs::read_to_string(&path).with_context(|| format!("no such branch: {}", branch))?;
+        Ok(tip.trim().to_string())
+    }
+
+    fn write_ref(&self, name: &str, hash: &str) -> Result<()> {
+        let path = self.repo.git_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, format!("{}\n", hash))?;
+        Ok(())
+    }
+
+    fn write_raw(&self, kind: &str, content: &[u8]) -> Result<String> {
+        let mut data = format!("{} {}\0", kind, content.len()).into_bytes();
+        data.extend_from_slice(content);
+        let hash = hash_data(&data);
+        self.repo.write_object(&hash, &data)?;
+        Ok(hash)
+    }
+
+    /// Write the trees for `files` below `prefix` and return the top one
+    fn write_tree(&self, files: &Files, prefix: &str) -> Result<String> {
+        let mut tree = Tree::new();
+        for path in files.keys().filter_map(|path| path.strip_prefix(prefix)) {
+            match path.split_once('/') {
+                Some((dir, _)) => {
+                    if tree.get(dir.as_bytes()).is_none() {
+                        let hash = self.write_tree(files, &format!("{}{}/", prefix, dir))?;
+                        tree.insert(TreeEntry::new(FileMode::Tree, dir, &hash)?);
+                    }
+                }
+                None => {
+                    let blob = Blob::new(files[&format!("{}{}", prefix, path)].clone());
+                    let hash = blob.hash()?;
+                    self.repo.write_object(&hash, &blob.to_bytes()?)?;
+                    tree.insert(TreeEntry::new(FileMode::Regular, path, &hash)?);
+                }
+            }
+        }
+        let hash = tree.hash()?;
+        self.repo.write_object(&hash, &tree.to_bytes()?)?;
+        Ok(hash)
+    }
+
+    fn write_commit(&mut self, parents: Vec<String>, message: &str) -> Result<String> {
+        self.time += TICK;
+        let commit = Commit {
+            tree: self.write_tree(&self.files, "")?,
+            parents,
+            author: Signature::new("A U Thor", "author@example.com", self.time, OFFSET),
+            committer: Signature::new("C O Mitter", "committer@example.com", self.time, OFFSET),
+            message: with_newline(message),
+            extra_headers: Vec::new(),
+            gpgsig: None,
+        };
+        let hash = commit.hash()?;
+        self.repo.write_object(&hash, &commit.to_bytes()?)?;
+        self.write_ref(&format!("refs/heads/{}", self.branch), &hash)?;
+        self.snapshots.insert(hash.clone(), self.files.clone());
+        Ok(hash)
+    }
+}
+
+/// Write a blob to a repository and return its hash
+pub fn store(repo: &Repository, content: &[u8]) -> Result<String> {
+    let blob = Blob::new(content);
+    let hash = blob.hash()?;
+    repo.write_object(&hash, &blob.to_bytes()?)?;
+    Ok(hash)
+}
+
+/// Add a regular file at stage 0 to an index
+///
+/// Only the entry is added; [`store`] the content too if the blob has to
+/// exist. Returns the blob's hash.
+///
+/// # Arguments
+///
+/// * `index` - The index to add to
+/// * `path` - Path of the file
+/// * `content` - Content the entry's hash is computed from
+pub fn stage(index: &mut Index, path: &str, content: &[u8]) -> Result<String> {
+    stage_as(index, path, FileMode::Regular, content, 0)
+}
+
+/// Add an entry with any mode and merge stage to an index
+///
+/// Like [`stage`], for executables, symlinks and conflicts.
+pub fn stage_as(
+    index: &mut Index,
+    path: &str,
+    mode: FileMode,
+    content: &[u8],
+    stage: u8,
+) -> Result<String> {
+    let hash = Blob::new(content).hash()?;
+    let mut entry = IndexEntry::new(path, mode, hash.clone());
+    entry.stage = stage;
+    index.add(entry);
+    Ok(hash)
+}
+
+/// Serve a directory as static files over HTTP, like a dumb Git server
+///
+/// The server runs on a background thread until the process exits.
+/// Query strings are ignored and missing files get a 404.
+///
+/// Returns the base URL, e.g. `http://127.0.0.1:41234`.
+pub fn serve_http(root: PathBuf) -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let Ok(clone) = stream.try_clone() else {
+                continue;
+            };
+            let mut reader = BufReader::new(clone);
+            let mut request = String::new();
+            let _ = reader.read_line(&mut request);
+            let mut header = String::new();
+            while reader.read_line(&mut header).is_ok_and(|n| n > 2) {
+                header.clear();
+            }
+            let path = request.split(' ').nth(1).unwrap_or("/");
+            let path = path.split('?').next().unwrap_or_default();
+            let _ = match fs::read(root.join(path.trim_start_matches('/'))) {
+                Ok(body) => write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                )
+                .and_then(|()| stream.write_all(&body)),
+                Err(_) => stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"),
+            };
+        }
+    });
+    Ok(format!("http://127.0.0.1:{}", port))
+}
+
+/// Terminate a message with a newline, as `git commit -m` does
+fn with_newline(message: &str) -> String {
+    if message.ends_with('\n') {
+        message.to_string()
+    } else {
+        format!("{}\n", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_is_deterministic() {
+        let build = || -> Result<(String, String)> {
+            let mut builder = RepoBuilder::new()?;
+            builder
+                .file("a.txt", "a\n")
+                .file("dir/b.txt", "b\n")
+                .commit("initial")?;
+            builder.branch("topic")?.checkout("topic")?;
+            builder.file("dir/c.txt", "c\n").commit("topic")?;
+            builder.checkout("main")?;
+            builder.file("a.txt", "changed\n").commit("change a")?;
+            let merge = builder.merge("topic", "merge topic")?;
+            let tag = builder.annotated_tag("v1.0", "version 1.0")?;
+            Ok((merge, tag))
+        };
+        assert_eq!(build().unwrap(), build().unwrap());
+    }
+
+    #[test]
+    fn test_commits_match_git() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder
+            .file("hello.txt", "Hello World\n")
+            .file("sub/dir.txt", "x\n")
+            .file("sub.txt", "y\n")
+            .commit("initial")
+            .unwrap();
+        // Same content and environment with git commit
+        assert_eq!(first, "778505c2ea416592e8c13ae784d0aaf11b3a34f1");
+
+        let second = builder.remove("sub.txt").commit("second").unwrap();
+        let commit = Commit::parse(&builder.repo().read_object(&second).unwrap()).unwrap();
+        assert_eq!(commit.parents, vec![first]);
+        assert_eq!(commit.committer.time, START_TIME + 2 * TICK);
+
+        builder.tag("v1").unwrap();
+        let tag = fs::read_to_string(builder.repo().git_dir.join("refs/tags/v1")).unwrap();
+        assert_eq!(tag.trim(), second);
+        assert!(builder.checkout("nope").is_err());
+    }
+}
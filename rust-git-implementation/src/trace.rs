@@ -0,0 +1,283 @@
+//! Tracing setup compatible with Git's `GIT_TRACE*` environment variables
+//!
+//! oxid is instrumented with the [`tracing`] crate. Nothing is printed unless
+//! one of these variables is set, mirroring Git:
+//!
+//! - `GIT_TRACE`: general trace events (commands run, objects read/written)
+//! - `GIT_TRACE_PACKET`: protocol packets, emitted with the [`PACKET`] target
+//! - `GIT_TRACE_PERFORMANCE`: timing of every instrumented span
+//!
+//! - `GIT_TRACE2_EVENT`: trace2-style JSON lines, one per finished region
+//!
+//! Each variable accepts `1`, `2` or `true` to log to stderr, or an absolute
+//! path to append to a file. `0`, `false` and the empty string disable it.
+//! The trace2 output can also be enabled with the `trace2.eventTarget` key in
+//! the system or global config.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::filter::filter_fn;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Target used for protocol packet traces (`GIT_TRACE_PACKET`)
+pub const PACKET: &str = "oxid::packet";
+
+/// Where a `GIT_TRACE*` variable sends its output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    Stderr,
+    File(PathBuf),
+}
+
+/// Interpret the value of a `GIT_TRACE*` variable
+///
+/// Returns `None` when tracing is disabled. Like Git, relative paths and
+/// other unrecognized values are treated as "log to stderr".
+pub fn parse_destination(value: &str) -> Option<Destination> {
+    match value.trim() {
+        "" | "0" | "false" => None,
+        "1" | "2" | "true" => Some(Destination::Stderr),
+        path if path.starts_with('/') => Some(Destination::File(PathBuf::from(path))),
+        _ => Some(Destination::Stderr),
+    }
+}
+
+fn destination_from_env(var: &str) -> Option<Destination> {
+    std::env::var(var).ok().and_then(|v| parse_destination(&v))
+}
+
+fn make_writer(dest: Destination) -> BoxMakeWriter {
+    match dest {
+        Destination::Stderr => BoxMakeWriter::new(std::io::stderr),
+        Destination::File(path) => match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => BoxMakeWriter::new(Mutex::new(file)),
+            Err(e) => {
+                eprintln!(
+                    "warning: could not open trace file {}: {}",
+                    path.display(),
+                    e
+                );
+                BoxMakeWriter::new(std::io::stderr)
+            }
+        },
+    }
+}
+
+/// Layer writing trace2-style `region_leave` events as JSON lines
+///
+/// Every closed span becomes one line with its category (the span target),
+/// label (the span name), nesting depth and elapsed time in seconds:
+///
+/// ```text
+/// {"event":"region_leave","category":"oxid::repository","label":"read_object","nesting":2,"t_rel":0.000131,...}
+/// ```
+pub struct Trace2Layer {
+    writer: Mutex<Box<dyn Write + Send>>,
+    start: Instant,
+}
+
+/// Start time stored in each span's extensions
+struct RegionStart(Instant);
+
+impl Trace2Layer {
+    /// Create a layer writing JSON lines to `writer`
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Trace2Layer {
+            writer: Mutex::new(Box::new(writer)),
+            start: Instant::now(),
+        }
+    }
+
+    /// Create a layer for a `GIT_TRACE2_EVENT` destination
+    pub fn for_destination(dest: Destination) -> Self {
+        match dest {
+            Destination::Stderr => Self::new(std::io::stderr()),
+            Destination::File(path) => {
+                match OpenOptions::new().create(true).append(true).open(&path) {
+                    Ok(file) => Self::new(file),
+                    Err(e) => {
+                        eprintln!(
+                            "warning: could not open trace file {}: {}",
+                            path.display(),
+                            e
+                        );
+                        Self::new(std::io::stderr())
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<S> Layer<S> for Trace2Layer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(RegionStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(start) = span.extensions().get::<RegionStart>().map(|s| s.0) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let event = serde_json::json!({
+            "event": "region_leave",
+            "time": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            "t_abs": now.duration_since(self.start).as_secs_f64(),
+            "t_rel": now.duration_since(start).as_secs_f64(),
+            "category": span.metadata().target(),
+            "label": span.name(),
+            "nesting": span.scope().count(),
+        });
+
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "{}", event);
+        }
+    }
+}
+
+/// Look up the trace2 destination from the environment, then config
+///
+/// Resolved once per process, so the global config isn't parsed again.
+fn trace2_destination() -> Option<Destination> {
+    static DESTINATION: OnceLock<Option<Destination>> = OnceLock::new();
+    DESTINATION
+        .get_or_init(|| {
+            if std::env::var_os("GIT_TRACE2_EVENT").is_some() {
+                return destination_from_env("GIT_TRACE2_EVENT");
+            }
+            let config = crate::config::Config::global().ok()?;
+            config.get("trace2.eventTarget").and_then(parse_destination)
+        })
+        .clone()
+}
+
+/// Install a global subscriber according to the `GIT_TRACE*` variables
+///
+/// `trace2.eventTarget` in the system or global config counts as well.
+/// When no destination is set, no subscriber is installed, so
+/// instrumentation stays free.
+pub fn init_from_env() {
+    let trace = destination_from_env("GIT_TRACE");
+    let packet = destination_from_env("GIT_TRACE_PACKET");
+    let performance = destination_from_env("GIT_TRACE_PERFORMANCE");
+    let trace2 = trace2_destination();
+
+    if trace.is_none() && packet.is_none() && performance.is_none() && trace2.is_none() {
+        return;
+    }
+
+    let trace_layer = trace.map(|dest| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(make_writer(dest))
+            .with_filter(filter_fn(|meta| {
+                meta.is_event() && meta.target().starts_with("oxid") && meta.target() != PACKET
+            }))
+    });
+
+    let packet_layer = packet.map(|dest| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(make_writer(dest))
+            .with_filter(filter_fn(|meta| meta.is_event() && meta.target() == PACKET))
+    });
+
+    let performance_layer = performance.map(|dest| {
+        tracing_subscriber::fmt::layer()
+            .with_ansi(false)
+            .with_writer(make_writer(dest))
+            .with_span_events(FmtSpan::CLOSE)
+            .with_filter(filter_fn(|meta| {
+                meta.is_span() && meta.target().starts_with("oxid")
+            }))
+    });
+
+    // Ignore the error if a subscriber was already installed (e.g. by an embedder)
+    let _ = tracing_subscriber::registry()
+        .with(trace_layer)
+        .with(packet_layer)
+        .with(performance_layer)
+        .with(trace2.map(|dest| {
+            Trace2Layer::for_destination(dest).with_filter(filter_fn(|meta| {
+                meta.is_span() && meta.target().starts_with("oxid")
+            }))
+        }))
+        .try_init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_destination() {
+        assert_eq!(parse_destination(""), None);
+        assert_eq!(parse_destination("0"), None);
+        assert_eq!(parse_destination("false"), None);
+        assert_eq!(parse_destination("1"), Some(Destination::Stderr));
+        assert_eq!(parse_destination("true"), Some(Destination::Stderr));
+        assert_eq!(
+            parse_destination("/tmp/trace.log"),
+            Some(Destination::File(PathBuf::from("/tmp/trace.log")))
+        );
+        assert_eq!(parse_destination("relative.log"), Some(Destination::Stderr));
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_trace2_region_events() {
+        let buf = SharedBuf::default();
+        let subscriber = tracing_subscriber::registry().with(Trace2Layer::new(buf.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = tracing::info_span!("outer").entered();
+            let _inner = tracing::info_span!("inner").entered();
+        });
+
+        let output = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+
+        // Inner region closes first
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["event"], "region_leave");
+        assert_eq!(events[0]["label"], "inner");
+        assert_eq!(events[0]["nesting"], 2);
+        assert_eq!(events[1]["label"], "outer");
+        assert_eq!(events[1]["nesting"], 1);
+        assert!(events[1]["t_rel"].as_f64().unwrap() >= events[0]["t_rel"].as_f64().unwrap());
+    }
+}
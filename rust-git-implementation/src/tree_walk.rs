@@ -0,0 +1,276 @@
+//! Recursive walks over tree objects
+//!
+//! [`TreeWalk`] visits the entries of a tree and its subtrees depth-first,
+//! in the order Git stores them, so a directory's contents follow it
+//! directly, as `git ls-tree -r` lists them. Subtrees are read one at a
+//! time as the walk reaches them, through the object cache, and the ones
+//! a [`Pathspec`] rules out are never read at all.
+//!
+//! Commands that list or read the files of a commit (ls-tree, checkout,
+//! archive, grep, blame) start here instead of recursing themselves.
+
+use crate::objects::{FileMode, TreeRef};
+use crate::pathspec::Pathspec;
+use crate::repository::Repository;
+use anyhow::Result;
+use std::sync::Arc;
+
+/// An entry found by a [`TreeWalk`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// `/`-separated path from the root of the walk; not necessarily UTF-8
+    pub path: Vec<u8>,
+    pub mode: FileMode,
+    /// Hash of the blob, tree or submodule commit
+    pub hash: String,
+}
+
+/// A tree being walked
+struct Frame {
+    data: Arc<[u8]>,
+    /// Offset of the next entry in `data`
+    pos: usize,
+    /// The tree's path with a trailing `/`, or empty for the root
+    prefix: Vec<u8>,
+    /// How many trees are above the entries
+    depth: usize,
+}
+
+/// Iterator over the entries below a tree
+///
+/// By default every blob, symlink and submodule is yielded, and trees are
+/// only descended into. A malformed tree or a missing object yields an
+/// error and ends the walk.
+pub struct TreeWalk<'a> {
+    repo: &'a Repository,
+    root: Option<String>,
+    prefix: Vec<u8>,
+    stack: Vec<Frame>,
+    pathspec: Pathspec,
+    max_depth: Option<usize>,
+    trees: bool,
+}
+
+impl<'a> TreeWalk<'a> {
+    /// Walk a tree
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository holding the trees
+    /// * `tree` - Hash of the root tree
+    pub fn new(repo: &'a Repository, tree: &str) -> Self {
+        TreeWalk {
+            repo,
+            root: Some(tree.to_string()),
+            prefix: Vec::new(),
+            stack: Vec::new(),
+            pathspec: Pathspec::default(),
+            max_depth: None,
+            trees: false,
+        }
+    }
+
+    /// Walk the tree as the one found at this path of a larger tree
+    ///
+    /// Paths then start with it, and pathspecs and the depth limit apply
+    /// as if the walk had started at the top, which lets walks of
+    /// different subtrees run side by side.
+    pub fn prefix(mut self, path: &[u8]) -> Self {
+        self.prefix = path.to_vec();
+        self
+    }
+
+    /// Only yield entries the pathspec selects
+    ///
+    /// Subtrees that can't hold a match are skipped without being read.
+    pub fn pathspec(mut self, pathspec: Pathspec) -> Self {
+        self.pathspec = pathspec;
+        self
+    }
+
+    /// Descend at most this many trees below the root
+    ///
+    /// With a depth of 0 only the root's own entries are visited. Subtrees
+    /// at the limit are yielded themselves, in place of their contents.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Also yield the trees the walk descends into, before their contents
+    /// (like `git ls-tree -r -t`)
+    pub fn trees(mut self, trees: bool) -> Self {
+        self.trees = trees;
+        self
+    }
+
+    fn enter(&mut self, tree: &str, prefix: Vec<u8>, depth: usize) -> Result<()> {
+        self.stack.push(Frame {
+            data: self.repo.read_tree(tree)?,
+            pos: 0,
+            prefix,
+            depth,
+        });
+        Ok(())
+    }
+
+    fn advance(&mut self) -> Result<Option<Entry>> {
+        if let Some(root) = self.root.take() {
+            let (prefix, depth) = match self.prefix.as_slice() {
+                b"" => (Vec::new(), 0),
+                path => {
+                    let depth = path.split(|&b| b == b'/').count();
+                    ([path, b"/"].concat(), depth)
+                }
+            };
+            self.enter(&root, prefix, depth)?;
+        }
+        while let Some(frame) = self.stack.last_mut() {
+            let Some(entry) = TreeRef::new(&frame.data[frame.pos..]).iter().next() else {
+                self.stack.pop();
+                continue;
+            };
+            let entry = entry?;
+            frame.pos += entry.mode.len() + entry.name.len() + 22;
+            let depth = frame.depth;
+            let found = Entry {
+                path: [&frame.prefix[..], entry.name].concat(),
+                mode: entry.file_mode()?,
+                hash: entry.hash(),
+            };
+
+            if found.mode != FileMode::Tree {
+                if self.pathspec.matches(&found.path) {
+                    return Ok(Some(found));
+                }
+                continue;
+            }
+            if !self.pathspec.may_match_under(&found.path) {
+                continue;
+            }
+            if self.max_depth.is_some_and(|max| depth >= max) {
+                return Ok(Some(found));
+            }
+            let prefix = [&found.path[..], b"/"].concat();
+            self.enter(&found.hash, prefix, depth + 1)?;
+            if self.trees {
+                return Ok(Some(found));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl Iterator for TreeWalk<'_> {
+    type Item = Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.advance().transpose();
+        if matches!(result, Some(Err(_))) {
+            self.stack.clear();
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    fn paths(walk: TreeWalk) -> Vec<String> {
+        walk.map(|entry| String::from_utf8(entry.unwrap().path).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_walk() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder
+            .file("README", "readme\n")
+            .file("src/main.rs", "fn main() {}\n")
+            .file("src/util/mod.rs", "\n")
+            .file("src.txt", "not a directory\n")
+            .file("docs/guide.md", "guide\n")
+            .commit("initial")
+            .unwrap();
+        let repo = builder.repo();
+        let tree = repo.read_commit(&commit).unwrap().tree.clone();
+
+        // Git's order: `src.txt` sorts before the directory `src`
+        assert_eq!(
+            paths(TreeWalk::new(repo, &tree)),
+            [
+                "README",
+                "docs/guide.md",
+                "src.txt",
+                "src/main.rs",
+                "src/util/mod.rs"
+            ]
+        );
+        assert_eq!(
+            paths(TreeWalk::new(repo, &tree).trees(true)),
+            [
+                "README",
+                "docs",
+                "docs/guide.md",
+                "src.txt",
+                "src",
+                "src/main.rs",
+                "src/util",
+                "src/util/mod.rs"
+            ]
+        );
+
+        let walk = TreeWalk::new(repo, &tree).max_depth(1);
+        let entries: Vec<Entry> = walk.collect::<Result<_>>().unwrap();
+        let util = entries.iter().find(|e| e.path == b"src/util").unwrap();
+        assert_eq!(util.mode, FileMode::Tree);
+        assert_eq!(entries.len(), 5);
+
+        let walk = TreeWalk::new(repo, &tree).pathspec(Pathspec::new(["src"]));
+        assert_eq!(paths(walk), ["src/main.rs", "src/util/mod.rs"]);
+        let walk = TreeWalk::new(repo, &tree).pathspec(Pathspec::new(["*.md", "src/util/"]));
+        assert_eq!(paths(walk), ["docs/guide.md", "src/util/mod.rs"]);
+
+        let src = TreeWalk::new(repo, &tree)
+            .max_depth(0)
+            .find_map(|entry| entry.ok().filter(|e| e.path == b"src"))
+            .unwrap();
+        let walk = TreeWalk::new(repo, &src.hash)
+            .prefix(b"src")
+            .pathspec(Pathspec::new(["src/main.rs", "src/util"]))
+            .max_depth(1);
+        assert_eq!(paths(walk), ["src/main.rs", "src/util"]);
+    }
+
+    #[test]
+    fn test_missing_subtree() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let commit = builder
+            .file("a.txt", "a\n")
+            .file("dir/b.txt", "b\n")
+            .commit("initial")
+            .unwrap();
+        let repo = builder.repo();
+        let tree = repo.read_commit(&commit).unwrap().tree.clone();
+        let subtree = TreeWalk::new(repo, &tree)
+            .max_depth(0)
+            .find_map(|entry| entry.ok().filter(|e| e.path == b"dir"))
+            .unwrap();
+        let object = repo
+            .git_dir
+            .join("objects")
+            .join(&subtree.hash[..2])
+            .join(&subtree.hash[2..]);
+        std::fs::remove_file(object).unwrap();
+
+        // Pathspecs keep the walk away from the missing tree
+        let walk = TreeWalk::new(repo, &tree).pathspec(Pathspec::new(["a.txt"]));
+        assert_eq!(paths(walk), ["a.txt"]);
+
+        let results: Vec<Result<Entry>> = TreeWalk::new(repo, &tree).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1].is_err());
+    }
+}
@@ -0,0 +1,409 @@
+//! Unpacking a packfile stream into loose objects
+//!
+//! Transports deliver objects as a pack: a `PACK` header with the object
+//! count, then each object as a type-and-size varint followed by its zlib
+//! data, then the SHA-1 of everything before. Objects may be deltas against
+//! an earlier object in the pack (`ofs-delta`) or against any object by id
+//! (`ref-delta`, as in thin packs).
+//!
+//! oxid can't store packs, so, like `git unpack-objects`, every object is
+//! resolved and written loose. Deltas are bounded by `oxid.maxDeltaDepth`
+//! and every object by `oxid.maxObjectSize`.
+
+use crate::limits::{Limit, Limits};
+use crate::objects::{parse_object, ObjectType};
+use crate::quarantine::Quarantine;
+use anyhow::{Context, Result};
+use flate2::bufread::ZlibDecoder;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// Hashes and counts the bytes consumed from a stream
+struct PackStream<R> {
+    inner: R,
+    hasher: Sha1,
+    offset: u64,
+}
+
+impl<R: BufRead> Read for PackStream<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(out.len());
+        out[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for PackStream<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // Already buffered, so this doesn't read
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.hasher.update(&buf[..amt]);
+        }
+        self.offset += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: BufRead> PackStream<R> {
+    fn byte(&mut self) -> Result<u8> {
+        let mut byte = [0u8];
+        self.read_exact(&mut byte)
+            .context("Pack ended in the middle of an object")?;
+        Ok(byte[0])
+    }
+
+    /// Inflate exactly `size` bytes, failing if the stream holds more or less
+    fn inflate(&mut self, size: u64) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size.min(1 << 20) as usize);
+        ZlibDecoder::new(&mut *self)
+            .take(size + 1)
+            .read_to_end(&mut data)
+            .context("Corrupt zlib data in pack")?;
+        if data.len() as u64 != size {
+            anyhow::bail!(
+                "Pack object size mismatch: header says {}, got {}",
+                size,
+                if data.len() as u64 > size {
+                    "more".to_string()
+                } else {
+                    data.len().to_string()
+                }
+            );
+        }
+        Ok(data)
+    }
+}
+
+/// Read a little-endian base-128 size from a delta
+fn delta_size(delta: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut size = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = *delta.get(*pos).context("Truncated delta header")?;
+        *pos += 1;
+        if shift > 56 {
+            anyhow::bail!("Delta size too large");
+        }
+        size |= u64::from(byte & 0x7f) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Apply a delta to its base
+///
+/// # Arguments
+///
+/// * `base` - Content of the base object
+/// * `delta` - The delta instructions, header included
+/// * `limits` - Bounds the size of the result
+pub fn apply_delta(base: &[u8], delta: &[u8], limits: &Limits) -> Result<Vec<u8>> {
+    let mut pos = 0;
+    let base_size = delta_size(delta, &mut pos)?;
+    if base_size != base.len() as u64 {
+        anyhow::bail!(
+            "Delta base size mismatch: expected {}, got {}",
+            base_size,
+            base.len()
+        );
+    }
+    let result_size = delta_size(delta, &mut pos)?;
+    limits.check(Limit::ObjectSize, result_size)?;
+
+    let mut result = Vec::with_capacity(result_size as usize);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // Copy from the base: which offset and size bytes are present
+            let mut field = |bits: u8, shift: u32| -> Result<usize> {
+                let mut value = 0usize;
+                for i in 0..bits {
+                    if op & (1 << (shift + i as u32)) != 0 {
+                        let byte = *delta.get(pos).context("Truncated delta")?;
+                        pos += 1;
+                        value |= (byte as usize) << (8 * i);
+                    }
+                }
+                Ok(value)
+            };
+            let offset = field(4, 0)?;
+            let size = match field(3, 4)? {
+                0 => 0x10000,
+                size => size,
+            };
+            let chunk = offset
+                .checked_add(size)
+                .and_then(|end| base.get(offset..end))
+                .context("Delta copies past the end of its base")?;
+            result.extend_from_slice(chunk);
+        } else if op != 0 {
+            let chunk = delta
+                .get(pos..pos + op as usize)
+                .context("Truncated delta")?;
+            result.extend_from_slice(chunk);
+            pos += op as usize;
+        } else {
+            anyhow::bail!("Invalid delta opcode 0");
+        }
+        if result.len() as u64 > result_size {
+            anyhow::bail!("Delta result is larger than announced");
+        }
+    }
+    if result.len() as u64 != result_size {
+        anyhow::bail!(
+            "Delta result size mismatch: expected {}, got {}",
+            result_size,
+            result.len()
+        );
+    }
+    Ok(result)
+}
+
+/// Unpack a pack into a quarantine
+///
+/// Bases of `ref-delta` objects are looked up in the quarantine and then
+/// the repository. Returns the ids of the unpacked objects in pack order.
+///
+/// # Arguments
+///
+/// * `quarantine` - Where to write the objects
+/// * `limits` - Bounds on object size and delta depth
+/// * `pack` - The pack stream, trailer included
+pub fn unpack_objects(
+    quarantine: &Quarantine,
+    limits: &Limits,
+    pack: impl Read,
+) -> Result<Vec<String>> {
+    let mut stream = PackStream {
+        inner: BufReader::new(pack),
+        hasher: Sha1::new(),
+        offset: 0,
+    };
+    let mut header = [0u8; 12];
+    stream
+        .read_exact(&mut header)
+        .context("Pack is too short")?;
+    if &header[..4] != b"PACK" {
+        anyhow::bail!("Not a pack: bad signature");
+    }
+    let version = u32::from_be_bytes(header[4..8].try_into().expect("4 bytes"));
+    if version != 2 && version != 3 {
+        anyhow::bail!("Unsupported pack version {}", version);
+    }
+    let count = u32::from_be_bytes(header[8..12].try_into().expect("4 bytes"));
+
+    // Offset of each object in the pack, and each object's delta depth
+    let mut by_offset: HashMap<u64, String> = HashMap::new();
+    let mut depths: HashMap<String, u64> = HashMap::new();
+    let mut hashes = Vec::with_capacity(count.min(1 << 16) as usize);
+    for _ in 0..count {
+        let offset = stream.offset;
+        let mut byte = stream.byte()?;
+        let kind = (byte >> 4) & 7;
+        let mut size = u64::from(byte & 0x0f);
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = stream.byte()?;
+            if shift > 57 {
+                anyhow::bail!("Pack object size too large");
+            }
+            size |= u64::from(byte & 0x7f) << shift;
+            shift += 7;
+        }
+        limits.check(Limit::ObjectSize, size)?;
+
+        let base =
+            match kind {
+                OBJ_OFS_DELTA => {
+                    let mut byte = stream.byte()?;
+                    let mut distance = u64::from(byte & 0x7f);
+                    while byte & 0x80 != 0 {
+                        byte = stream.byte()?;
+                        distance = distance
+                            .checked_add(1)
+                            .and_then(|d| d.checked_mul(128))
+                            .context("Bad delta base offset")?
+                            | u64::from(byte & 0x7f);
+                    }
+                    let base_offset = offset
+                        .checked_sub(distance)
+                        .context("Delta base offset before the start of the pack")?;
+                    Some(by_offset.get(&base_offset).cloned().with_context(|| {
+                        format!("No object at delta base offset {}", base_offset)
+                    })?)
+                }
+                OBJ_REF_DELTA => {
+                    let mut oid = [0u8; 20];
+                    stream
+                        .read_exact(&mut oid)
+                        .context("Pack ended in the middle of an object")?;
+                    Some(crate::utils::bytes_to_hex(&oid))
+                }
+                _ => None,
+            };
+        let data = stream.inflate(size)?;
+
+        let (kind, content, depth) = match base {
+            Some(base) => {
+                let base_data = quarantine
+                    .read_object(&base)
+                    .with_context(|| format!("Missing delta base {}", base))?;
+                let (base_kind, base_content) = parse_object(&base_data)?;
+                let depth = depths.get(&base).copied().unwrap_or(0) + 1;
+                limits.check(Limit::DeltaDepth, depth)?;
+                (base_kind, apply_delta(base_content, &data, limits)?, depth)
+            }
+            None => {
+                let kind = match kind {
+                    1 => ObjectType::Commit,
+                    2 => ObjectType::Tree,
+                    3 => ObjectType::Blob,
+                    4 => ObjectType::Tag,
+                    other => anyhow::bail!("Invalid object type {} in pack", other),
+                };
+                (kind, data, 0)
+            }
+        };
+
+        let mut object = format!("{} {}\0", kind.as_str(), content.len()).into_bytes();
+        object.extend_from_slice(&content);
+        let hash = crate::utils::hash_data(&object);
+        quarantine.write_object(&hash, &object)?;
+        by_offset.insert(offset, hash.clone());
+        if depth > 0 {
+            depths.insert(hash.clone(), depth);
+        }
+        hashes.push(hash);
+    }
+
+    let expected = format!("{:x}", stream.hasher.finalize_reset());
+    let mut trailer = [0u8; 20];
+    stream
+        .inner
+        .read_exact(&mut trailer)
+        .context("Pack is missing its checksum")?;
+    if crate::utils::bytes_to_hex(&trailer) != expected {
+        anyhow::bail!("Pack checksum mismatch");
+    }
+    Ok(hashes)
+}
+
+/// Build a pack of whole (non-delta) objects
+///
+/// Enough for tests and for transports serving loose objects.
+///
+/// # Arguments
+///
+/// * `objects` - Decompressed objects, headers included
+pub fn write_pack<'a>(objects: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>> {
+    let objects: Vec<&[u8]> = objects.into_iter().collect();
+    let mut pack = b"PACK".to_vec();
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+    for object in objects {
+        let (kind, content) = parse_object(object)?;
+        let kind: u8 = match kind {
+            ObjectType::Commit => 1,
+            ObjectType::Tree => 2,
+            ObjectType::Blob => 3,
+            ObjectType::Tag => 4,
+        };
+        let mut size = content.len() as u64;
+        let mut byte = (kind << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        while size > 0 {
+            pack.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        pack.push(byte);
+        pack.extend_from_slice(&crate::utils::compress(content)?);
+    }
+    let checksum = Sha1::digest(&pack);
+    pack.extend_from_slice(&checksum);
+    Ok(pack)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Repository;
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello world";
+        // Base size 11, result size 10: copy "hello " then insert "rust"
+        let delta = [11, 10, 0x90, 6, 4, b'r', b'u', b's', b't'];
+        let limits = Limits::default();
+        assert_eq!(apply_delta(base, &delta, &limits).unwrap(), b"hello rust");
+
+        // Copy past the end of the base
+        assert!(apply_delta(base, &[11, 20, 0x90, 20], &limits).is_err());
+        // Wrong base size
+        assert!(apply_delta(b"hello", &delta, &limits).is_err());
+    }
+
+    #[test]
+    fn test_unpack_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let blob = b"blob 12\0hello world\n".to_vec();
+        let blob_hash = crate::utils::hash_data(&blob);
+        let mut pack = write_pack([&blob[..]]).unwrap();
+
+        // Add a ref-delta against the blob and an ofs-delta against that
+        let body_end = pack.len() - 20;
+        pack.truncate(body_end);
+        pack[11] = 3;
+        let delta = [12, 11, 0x90, 6, 5, b'r', b'u', b's', b't', b'\n'];
+        let ref_delta_offset = pack.len();
+        pack.push((OBJ_REF_DELTA << 4) | delta.len() as u8);
+        pack.extend_from_slice(&crate::utils::hex_to_bytes(&blob_hash).unwrap());
+        pack.extend_from_slice(&crate::utils::compress(&delta).unwrap());
+        let second = [11, 6, 0x90, 6];
+        let distance = pack.len() - ref_delta_offset;
+        pack.push((OBJ_OFS_DELTA << 4) | second.len() as u8);
+        pack.push(distance as u8);
+        pack.extend_from_slice(&crate::utils::compress(&second).unwrap());
+        let checksum = Sha1::digest(&pack);
+        pack.extend_from_slice(&checksum);
+
+        let quarantine = repo.quarantine().unwrap();
+        let hashes = unpack_objects(&quarantine, &Limits::default(), &pack[..]).unwrap();
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[0], blob_hash);
+        assert_eq!(
+            quarantine.read_object(&hashes[1]).unwrap(),
+            b"blob 11\0hello rust\n"
+        );
+        assert_eq!(
+            quarantine.read_object(&hashes[2]).unwrap(),
+            b"blob 6\0hello "
+        );
+
+        // A chain of two deltas is over a depth limit of one
+        let limits = Limits {
+            max_delta_depth: 1,
+            ..Limits::default()
+        };
+        assert!(unpack_objects(&quarantine, &limits, &pack[..]).is_err());
+
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        assert!(unpack_objects(&quarantine, &Limits::default(), &pack[..]).is_err());
+        assert!(unpack_objects(&quarantine, &Limits::default(), &b"PACK"[..]).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+//! The serving side of fetch: ref advertisement and want checks
+//!
+//! A smart server starts by advertising its refs, as pkt-lines of
+//! `<object id> <name>`, the first carrying the capability list. Some refs
+//! shouldn't be shown to clients (review refs such as `refs/changes/*`,
+//! internal bookkeeping); they are configured with `transfer.hideRefs`
+//! and `uploadpack.hideRefs` (see [`HiddenRefs`]).
+//!
+//! Clients may only ask for (`want`) objects that were advertised, unless
+//! the server lets them fetch by exact object id (see
+//! [`UploadPack::check_want`]): CI systems use this to fetch the one
+//! commit they build without fetching whole branches. With
+//! `uploadpack.allowRefInWant`, protocol v2 clients can also ask for a ref
+//! by name (`want-ref`) and get whatever it points to when the server
+//! answers, with no race against the advertisement.
+//!
+//! oxid doesn't generate packs for clients yet, so only the advertisement
+//! (`upload-pack --advertise-refs`, as used for smart HTTP's `info/refs`)
+//! is served.
+
+use crate::config::Config;
+use crate::protocol::capabilities::{self, Capabilities, Capability};
+use crate::protocol::PktLineWriter;
+use crate::repository::Repository;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// Refs hidden from the advertisement
+///
+/// Each configured value is a ref name prefix, matched at a `/` boundary:
+/// `refs/changes` hides `refs/changes/01/1/1` but not `refs/changesets`.
+/// A leading `!` un-hides, and the last matching value wins, so
+/// `refs/pull` followed by `!refs/pull/keep` hides everything in
+/// `refs/pull` but `keep`. A `^` after the optional `!` matches the full
+/// ref name rather than the one seen inside a namespace; oxid has no
+/// namespaces, so both are the same.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HiddenRefs {
+    patterns: Vec<String>,
+}
+
+impl HiddenRefs {
+    /// Read `transfer.hideRefs` and `<section>.hideRefs`
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The repository configuration
+    /// * `section` - `uploadpack` or `receivepack`
+    pub fn from_config(config: &Config, section: &str) -> Result<Self> {
+        let mut patterns = Vec::new();
+        for key in [
+            "transfer.hideRefs".to_string(),
+            format!("{}.hideRefs", section),
+        ] {
+            for value in config.get_all(&key) {
+                if value.is_empty() {
+                    anyhow::bail!("missing value for '{}'", key);
+                }
+                patterns.push(value.trim_end_matches('/').to_string());
+            }
+        }
+        Ok(HiddenRefs { patterns })
+    }
+
+    /// Whether a ref is hidden
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The ref's full name, e.g. `refs/changes/01/1/1`
+    pub fn is_hidden(&self, name: &str) -> bool {
+        for pattern in self.patterns.iter().rev() {
+            let (negated, pattern) = match pattern.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, pattern.as_str()),
+            };
+            let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+            if let Some(rest) = name.strip_prefix(pattern) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    return !negated;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// One advertised ref
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdvertisedRef {
+    pub name: String,
+    pub hash: String,
+    /// For annotated tags, the object the tag chain ends at
+    pub peeled: Option<String>,
+}
+
+/// A repository's refs as a server presents them
+pub struct UploadPack<'a> {
+    repo: &'a Repository,
+    hidden: HiddenRefs,
+    allow_tip_sha1_in_want: bool,
+    allow_reachable_sha1_in_want: bool,
+    allow_any_sha1_in_want: bool,
+    allow_ref_in_want: bool,
+    /// `HEAD` (if it resolves) then every visible ref, by name
+    refs: Vec<AdvertisedRef>,
+    /// Tips of hidden refs
+    hidden_tips: HashSet<String>,
+    /// Every ref value, hidden or not, peeled
+    all_tips: Vec<String>,
+    /// Commits reachable from `all_tips`, walked on first use
+    reachable: OnceLock<HashSet<String>>,
+    /// The branch `HEAD` points to
+    head_target: Option<String>,
+}
+
+impl<'a> UploadPack<'a> {
+    /// Read the refs and the `uploadpack` settings
+    ///
+    /// # Arguments
+    ///
+    /// * `repo` - The repository being served
+    pub fn new(repo: &'a Repository) -> Result<Self> {
+        let config = repo.config()?;
+        let hidden = HiddenRefs::from_config(&config, "uploadpack")?;
+        let flag = |key: &str| -> Result<bool> { Ok(config.get_bool(key)?.unwrap_or(false)) };
+        // allowAnySHA1InWant implies the other two
+        let allow_any_sha1_in_want = flag("uploadpack.allowAnySHA1InWant")?;
+        let allow_tip_sha1_in_want =
+            allow_any_sha1_in_want || flag("uploadpack.allowTipSHA1InWant")?;
+        let allow_reachable_sha1_in_want =
+            allow_any_sha1_in_want || flag("uploadpack.allowReachableSHA1InWant")?;
+        let allow_ref_in_want = flag("uploadpack.allowRefInWant")?;
+
+        let packed = crate::refs::read_packed_peeled(&repo.git_dir)?;
+        let mut refs = Vec::new();
+        let mut hidden_tips = HashSet::new();
+        let mut all_tips = Vec::new();
+        let head = fs::read_to_string(repo.git_dir.join("HEAD")).context("Failed to read HEAD")?;
+        let head_target = head
+            .trim_end()
+            .strip_prefix("ref: ")
+            .map(str::to_string)
+            .filter(|target| !hidden.is_hidden(target));
+        if !hidden.is_hidden("HEAD") {
+            if let Some(hash) = repo.head()? {
+                refs.push(AdvertisedRef {
+                    name: "HEAD".to_string(),
+                    hash,
+                    peeled: None,
+                });
+            }
+        }
+        for (name, hash) in crate::refs::read_refs(&repo.git_dir)? {
+            let peeled = crate::server_info::peeled_value(repo, &packed, &name, &hash)?;
+            all_tips.push(peeled.clone().unwrap_or_else(|| hash.clone()));
+            if hidden.is_hidden(&name) {
+                hidden_tips.insert(hash);
+                continue;
+            }
+            refs.push(AdvertisedRef { name, hash, peeled });
+        }
+
+        Ok(UploadPack {
+            repo,
+            hidden,
+            allow_tip_sha1_in_want,
+            allow_reachable_sha1_in_want,
+            allow_any_sha1_in_want,
+            allow_ref_in_want,
+            refs,
+            hidden_tips,
+            all_tips,
+            reachable: OnceLock::new(),
+            head_target,
+        })
+    }
+
+    /// The refs shown to clients, `HEAD` first
+    pub fn refs(&self) -> &[AdvertisedRef] {
+        &self.refs
+    }
+
+    /// The hiding rules in effect
+    pub fn hidden(&self) -> &HiddenRefs {
+        &self.hidden
+    }
+
+    /// The capabilities sent with the advertisement
+    pub fn capabilities(&self) -> Capabilities {
+        let mut list = Capabilities::new();
+        if self.allow_tip_sha1_in_want {
+            list.push("allow-tip-sha1-in-want", None);
+        }
+        if self.allow_reachable_sha1_in_want {
+            list.push("allow-reachable-sha1-in-want", None);
+        }
+        if let Some(target) = &self.head_target {
+            if self.refs.first().is_some_and(|r| r.name == "HEAD") {
+                list.push(capabilities::SYMREF, Some(&format!("HEAD:{}", target)));
+            }
+        }
+        list.with_value(capabilities::OBJECT_FORMAT, "sha1")
+            .with_value(capabilities::AGENT, &capabilities::agent())
+    }
+
+    /// The `fetch` command as listed in a protocol v2 capability
+    /// advertisement, with `ref-in-want` if `want-ref` is allowed
+    pub fn fetch_capability(&self) -> Capability {
+        Capability {
+            name: "fetch".to_string(),
+            value: self.allow_ref_in_want.then(|| "ref-in-want".to_string()),
+        }
+    }
+
+    /// Write the protocol v0 ref advertisement, ending with a flush packet
+    pub fn advertise<W: Write>(&self, out: &mut PktLineWriter<W>) -> Result<()> {
+        let capabilities = self.capabilities();
+        if self.refs.is_empty() {
+            out.write_data(
+                format!("{} capabilities^{{}}\0{}\n", "0".repeat(40), capabilities).as_bytes(),
+            )?;
+        }
+        for (i, advertised) in self.refs.iter().enumerate() {
+            if i == 0 {
+                out.write_data(
+                    format!(
+                        "{} {}\0{}\n",
+                        advertised.hash, advertised.name, capabilities
+                    )
+                    .as_bytes(),
+                )?;
+            } else {
+                out.write_text(&format!("{} {}", advertised.hash, advertised.name))?;
+            }
+            if let Some(peeled) = &advertised.peeled {
+                out.write_text(&format!("{} {}^{{}}", peeled, advertised.name))?;
+            }
+        }
+        out.flush_packet()
+    }
+
+    /// Check that a client may ask for an object
+    ///
+    /// Advertised ref values (and peeled tags) are always allowed. Beyond
+    /// those, each setting lets clients ask for more by exact id:
+    ///
+    /// - `uploadpack.allowTipSHA1InWant`: tips of hidden refs
+    /// - `uploadpack.allowReachableSHA1InWant`: commits reachable from any
+    ///   ref, hidden or not
+    /// - `uploadpack.allowAnySHA1InWant`: any object the repository has
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The object id from a `want` line
+    pub fn check_want(&self, hash: &str) -> Result<()> {
+        if self.repo.has_object(hash) && self.is_allowed(hash)? {
+            return Ok(());
+        }
+        anyhow::bail!("upload-pack: not our ref {}", hash)
+    }
+
+    fn is_allowed(&self, hash: &str) -> Result<bool> {
+        let advertised = self
+            .refs
+            .iter()
+            .any(|r| r.hash == hash || r.peeled.as_deref() == Some(hash));
+        if advertised
+            || self.allow_any_sha1_in_want
+            || (self.allow_tip_sha1_in_want && self.hidden_tips.contains(hash))
+        {
+            return Ok(true);
+        }
+        if !self.allow_reachable_sha1_in_want {
+            return Ok(false);
+        }
+        if let Some(reachable) = self.reachable.get() {
+            return Ok(reachable.contains(hash));
+        }
+        let commits: Vec<&str> = self
+            .all_tips
+            .iter()
+            .filter(|tip| {
+                self.repo
+                    .object_header(tip)
+                    .is_ok_and(|(kind, _)| kind == crate::objects::ObjectType::Commit)
+            })
+            .map(String::as_str)
+            .collect();
+        let reachable = crate::merge_base::reachable(self.repo, &commits)?;
+        Ok(self.reachable.get_or_init(|| reachable).contains(hash))
+    }
+
+    /// Resolve a protocol v2 `want-ref`
+    ///
+    /// Returns the object the ref points to. Hidden refs are unknown, as
+    /// if they didn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The ref's full name, e.g. `refs/heads/main`
+    pub fn want_ref(&self, name: &str) -> Result<String> {
+        if !self.allow_ref_in_want {
+            anyhow::bail!("upload-pack: unexpected line: 'want-ref {}'", name);
+        }
+        self.refs
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| r.hash.clone())
+            .ok_or_else(|| anyhow::anyhow!("unknown ref {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RepoBuilder;
+
+    #[test]
+    fn test_hidden_refs() {
+        let config = Config::parse(
+            "[transfer]\n\thideRefs = refs/changes/\n\thideRefs = refs/pull\n\
+             [uploadpack]\n\thideRefs = !refs/pull/keep\n\thideRefs = ^HEAD\n",
+        )
+        .unwrap();
+        let hidden = HiddenRefs::from_config(&config, "uploadpack").unwrap();
+        assert!(hidden.is_hidden("refs/changes/01/1/1"));
+        assert!(hidden.is_hidden("refs/changes"));
+        assert!(!hidden.is_hidden("refs/changesets"));
+        assert!(hidden.is_hidden("refs/pull/1/head"));
+        assert!(!hidden.is_hidden("refs/pull/keep/x"));
+        assert!(hidden.is_hidden("HEAD"));
+        assert!(!hidden.is_hidden("refs/heads/main"));
+
+        // receive-pack doesn't see the uploadpack values
+        let hidden = HiddenRefs::from_config(&config, "receivepack").unwrap();
+        assert!(hidden.is_hidden("refs/pull/keep/x"));
+        assert!(!hidden.is_hidden("HEAD"));
+
+        let config = Config::parse("[transfer]\n\thideRefs =\n").unwrap();
+        assert!(HiddenRefs::from_config(&config, "uploadpack").is_err());
+    }
+
+    #[test]
+    fn test_advertise() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let tip = builder.file("a", "1\n").commit("first").unwrap();
+        let tag = builder.annotated_tag("v1", "release").unwrap();
+        let secret = builder.file("a", "2\n").commit("secret").unwrap();
+        let repo = builder.repo();
+        fs::create_dir_all(repo.git_dir.join("refs/changes")).unwrap();
+        fs::write(repo.git_dir.join("refs/changes/1"), format!("{}\n", secret)).unwrap();
+        fs::write(repo.git_dir.join("refs/heads/main"), format!("{}\n", tip)).unwrap();
+        let mut config = fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        config.push_str("[uploadpack]\n\thideRefs = refs/changes\n");
+        fs::write(repo.git_dir.join("config"), &config).unwrap();
+
+        let server = UploadPack::new(repo).unwrap();
+        let mut out = PktLineWriter::new(Vec::new());
+        server.advertise(&mut out).unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        let first = format!(
+            "{} HEAD\0symref=HEAD:refs/heads/main object-format=sha1 agent={}\n",
+            tip,
+            capabilities::agent()
+        );
+        assert_eq!(
+            out,
+            format!(
+                "{:04x}{}{:04x}{} refs/heads/main\n{:04x}{} refs/tags/v1\n{:04x}{} refs/tags/v1^{{}}\n0000",
+                first.len() + 4,
+                first,
+                61,
+                tip,
+                58,
+                tag,
+                61,
+                tip
+            )
+        );
+
+        assert!(server.check_want(&tip).is_ok());
+        assert!(server.check_want(&secret).is_err());
+
+        config.push_str("\tallowTipSHA1InWant = true\n");
+        fs::write(repo.git_dir.join("config"), &config).unwrap();
+        let server = UploadPack::new(repo).unwrap();
+        assert!(server.check_want(&secret).is_ok());
+        assert!(server.capabilities().contains("allow-tip-sha1-in-want"));
+    }
+
+    #[test]
+    fn test_fetch_by_id() {
+        let mut builder = RepoBuilder::new().unwrap();
+        let first = builder.file("a", "1\n").commit("first").unwrap();
+        let second = builder.file("a", "2\n").commit("second").unwrap();
+        let hidden_parent = builder.file("a", "3\n").commit("third").unwrap();
+        let hidden_tip = builder.file("a", "4\n").commit("fourth").unwrap();
+        let repo = builder.repo();
+        fs::write(
+            repo.git_dir.join("refs/heads/main"),
+            format!("{}\n", second),
+        )
+        .unwrap();
+        fs::create_dir_all(repo.git_dir.join("refs/ci")).unwrap();
+        fs::write(
+            repo.git_dir.join("refs/ci/run"),
+            format!("{}\n", hidden_tip),
+        )
+        .unwrap();
+        let tree = repo.read_commit(&first).unwrap().tree.clone();
+        let mut config = fs::read_to_string(repo.git_dir.join("config")).unwrap();
+        config.push_str("[uploadpack]\n\thideRefs = refs/ci\n");
+        let configure = |extra: &str| {
+            fs::write(repo.git_dir.join("config"), format!("{}{}", config, extra)).unwrap();
+            UploadPack::new(repo).unwrap()
+        };
+
+        let server = configure("");
+        assert!(server.check_want(&first).is_err());
+        assert!(server.want_ref("refs/heads/main").is_err());
+        assert_eq!(server.fetch_capability().to_string(), "fetch");
+
+        let server = configure("\tallowReachableSHA1InWant = true\n");
+        assert!(server
+            .capabilities()
+            .contains("allow-reachable-sha1-in-want"));
+        assert!(server.check_want(&first).is_ok());
+        // Reachable from a hidden ref counts too
+        assert!(server.check_want(&hidden_parent).is_ok());
+        assert!(server.check_want(&tree).is_err());
+        assert!(server.check_want(&"1".repeat(40)).is_err());
+
+        let server = configure("\tallowAnySHA1InWant = true\n");
+        assert!(server.check_want(&tree).is_ok());
+        assert!(server.check_want(&hidden_tip).is_ok());
+
+        let server = configure("\tallowRefInWant = true\n");
+        assert_eq!(server.fetch_capability().to_string(), "fetch=ref-in-want");
+        assert_eq!(server.want_ref("refs/heads/main").unwrap(), second);
+        assert!(server.want_ref("refs/ci/run").is_err());
+        assert!(server.want_ref("refs/heads/nope").is_err());
+    }
+}
@@ -1,11 +1,13 @@
-//! Utility functions for hashing and compression
+//! Utility functions for hashing, compression and paths
 
+use crate::objects::ObjectType;
 use anyhow::Result;
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use sha1::{Digest, Sha1};
 use std::io::{Read, Write};
+use std::path::Path;
 
 /// Compute SHA-1 hash of data
 ///
@@ -29,6 +31,109 @@ pub fn hash_data(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Whether `id` is a full object id: 40 lowercase hexadecimal characters
+///
+/// # Example
+///
+/// ```
+/// assert!(oxid::utils::is_object_id(b"557db03de997c86a4a028e1ebd3a1ceb225be238"));
+/// assert!(!oxid::utils::is_object_id(b"557DB03DE997C86A4A028E1EBD3A1CEB225BE238"));
+/// assert!(!oxid::utils::is_object_id(b"x"));
+/// ```
+pub fn is_object_id(id: &[u8]) -> bool {
+    id.len() == 40
+        && id
+            .iter()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(b))
+}
+
+/// Incremental hasher for Git objects
+///
+/// Computes the same hash as [`hash_data`] over `<type> <size>\0<content>`,
+/// but takes the content in pieces, so large files can be hashed while
+/// streaming from disk. It implements [`Write`], so [`std::io::copy`] can
+/// feed it directly.
+///
+/// # Example
+///
+/// ```
+/// use oxid::objects::ObjectType;
+/// use oxid::utils::ObjectHasher;
+///
+/// let mut hasher = ObjectHasher::new(ObjectType::Blob, 12);
+/// hasher.update(b"Hello ");
+/// hasher.update(b"World\n");
+/// assert_eq!(
+///     hasher.finish().unwrap(),
+///     "557db03de997c86a4a028e1ebd3a1ceb225be238"
+/// );
+/// ```
+pub struct ObjectHasher {
+    hasher: Sha1,
+    size: u64,
+    written: u64,
+}
+
+impl ObjectHasher {
+    /// Start hashing an object of the given type and content size
+    pub fn new(kind: ObjectType, size: u64) -> Self {
+        let mut hasher = Sha1::new();
+        hasher.update(format!("{} {}\0", kind.as_str(), size));
+        ObjectHasher {
+            hasher,
+            size,
+            written: 0,
+        }
+    }
+
+    /// Feed the next piece of content
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+        self.written += data.len() as u64;
+    }
+
+    /// Finish and return the hash
+    ///
+    /// # Errors
+    ///
+    /// Fails if the content fed doesn't match the size given to
+    /// [`ObjectHasher::new`], e.g. because a file changed while being read
+    pub fn finish(self) -> Result<String> {
+        if self.written != self.size {
+            anyhow::bail!(
+                "Object size mismatch: expected {} bytes, hashed {}",
+                self.size,
+                self.written
+            );
+        }
+        Ok(format!("{:x}", self.hasher.finalize()))
+    }
+}
+
+impl Write for ObjectHasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash an object whose content is read from a stream
+///
+/// # Arguments
+///
+/// * `kind` - The object type
+/// * `size` - Content size; the reader must yield exactly this many bytes
+/// * `reader` - Source of the content
+pub fn hash_reader(kind: ObjectType, size: u64, mut reader: impl Read) -> Result<String> {
+    let mut hasher = ObjectHasher::new(kind, size);
+    std::io::copy(&mut reader, &mut hasher)?;
+    hasher.finish()
+}
+
 /// Compress data using zlib
 ///
 /// # Arguments
@@ -60,6 +165,94 @@ pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
     Ok(result)
 }
 
+/// Encode bytes as a lowercase hexadecimal string
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to encode (e.g. a raw 20-byte object id)
+pub fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hexadecimal string into bytes
+///
+/// # Arguments
+///
+/// * `hex` - The hexadecimal string (e.g. a 40-character object hash)
+pub fn hex_to_bytes(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string: {}", hex);
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| anyhow::anyhow!("Invalid hex string: {}", hex))
+        })
+        .collect()
+}
+
+/// The worktree-relative filesystem path for a path from the index or a
+/// tree
+///
+/// Paths in Git are bytes. On Unix any bytes name a file; elsewhere the
+/// path must be UTF-8.
+///
+/// # Arguments
+///
+/// * `path` - The path, with `/` separators
+#[cfg(unix)]
+pub fn path_from_bytes(path: &[u8]) -> Result<&Path> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(Path::new(std::ffi::OsStr::from_bytes(path)))
+}
+
+/// The worktree-relative filesystem path for a path from the index or a
+/// tree
+///
+/// Paths in Git are bytes. On Unix any bytes name a file; elsewhere the
+/// path must be UTF-8, and checking out one that isn't fails with an error
+/// naming it.
+///
+/// # Arguments
+///
+/// * `path` - The path, with `/` separators
+#[cfg(not(unix))]
+pub fn path_from_bytes(path: &[u8]) -> Result<&Path> {
+    let Ok(text) = std::str::from_utf8(path) else {
+        anyhow::bail!(
+            "Cannot represent path {} on this filesystem: not valid UTF-8",
+            crate::quote::display(path)
+        );
+    };
+    Ok(Path::new(text))
+}
+
+/// The bytes Git stores for a worktree-relative path
+///
+/// # Arguments
+///
+/// * `path` - The path, relative to the worktree root
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path.as_os_str().as_bytes().to_vec())
+}
+
+/// The bytes Git stores for a worktree-relative path
+///
+/// # Arguments
+///
+/// * `path` - The path, relative to the worktree root
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Result<Vec<u8>> {
+    use anyhow::Context;
+    let path = path
+        .to_str()
+        .with_context(|| format!("Path is not valid UTF-8: {}", path.display()))?;
+    Ok(path.replace('\\', "/").into_bytes())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,7 +272,7 @@ mod tests {
 
     #[test]
     fn test_compress_decompress() {
-        let original = b"Hello World, this is a test of compression!";
+        let original = b"Hello World, this is a test of compression! Hello World, this is a test of compression!";
         let compressed = compress(original).unwrap();
         let decompressed = decompress(&compressed).unwrap();
 
@@ -91,15 +284,44 @@ mod tests {
 
     #[test]
     fn test_git_blob_hash() {
-        // Test that we compute the same hash as Git for "Hello World"
-        let content = b"Hello World";
+        // Test that we compute the same hash as Git for "Hello World\n"
+        let content = b"Hello World\n";
         let blob_data = format!("blob {}\0", content.len());
         let mut data = blob_data.as_bytes().to_vec();
         data.extend_from_slice(content);
 
         let hash = hash_data(&data);
 
-        // This is the known Git hash for "Hello World"
+        // This is the known Git hash for `echo "Hello World"`
         assert_eq!(hash, "557db03de997c86a4a028e1ebd3a1ceb225be238");
     }
+
+    #[test]
+    fn test_object_hasher_matches_hash_data() {
+        let content = vec![b'x'; 100_000];
+        let mut data = format!("blob {}\0", content.len()).into_bytes();
+        data.extend_from_slice(&content);
+
+        let mut hasher = ObjectHasher::new(ObjectType::Blob, content.len() as u64);
+        for chunk in content.chunks(4096) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finish().unwrap(), hash_data(&data));
+        assert_eq!(
+            hash_reader(ObjectType::Blob, content.len() as u64, &content[..]).unwrap(),
+            hash_data(&data)
+        );
+        assert!(hash_reader(ObjectType::Blob, 5, &b"four"[..]).is_err());
+    }
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let hash = "557db03de997c86a4a028e1ebd3a1ceb225be238";
+        let bytes = hex_to_bytes(hash).unwrap();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(bytes_to_hex(&bytes), hash);
+
+        assert!(hex_to_bytes("abc").is_err());
+        assert!(hex_to_bytes("zz").is_err());
+    }
 }
@@ -0,0 +1,225 @@
+//! Walking the working tree with ignore rules applied
+//!
+//! [`Walker`] lists every file of a working tree that is not ignored. Each
+//! directory's `.gitignore` is read on the way down, and ignored
+//! directories are pruned before they are entered, so large build output
+//! directories cost a single check. Subdirectories of the root are walked
+//! in parallel.
+//!
+//! Commands that look for files not in the index (status, add, clean,
+//! grep) start here instead of walking directories themselves.
+
+use crate::ignore::Ignore;
+use crate::utils::{path_from_bytes, path_to_bytes};
+use crate::worktree::WorkTree;
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// The result of a walk; paths are `/`-separated and sorted
+///
+/// Paths are bytes, as in the index, so file names that aren't UTF-8 come
+/// through unchanged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Walk {
+    /// Files and symlinks that are not ignored
+    pub files: Vec<Vec<u8>>,
+    /// Ignored paths; directories are listed once, with a trailing `/`,
+    /// and their contents are not visited
+    pub ignored: Vec<Vec<u8>>,
+    /// Directories holding their own repository, such as submodules;
+    /// they are not entered
+    pub repositories: Vec<Vec<u8>>,
+}
+
+/// Walks a working tree, applying ignore rules
+pub struct Walker<'a> {
+    worktree: &'a dyn WorkTree,
+    ignore: Arc<Ignore>,
+    threads: usize,
+}
+
+impl<'a> Walker<'a> {
+    /// Create a walker using one thread per available CPU
+    ///
+    /// # Arguments
+    ///
+    /// * `worktree` - The working tree to walk
+    /// * `ignore` - Rules from outside the tree, e.g. [`Ignore::for_repo`]
+    pub fn new(worktree: &'a dyn WorkTree, ignore: Ignore) -> Self {
+        Walker {
+            worktree,
+            ignore: Arc::new(ignore),
+            threads: std::thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+
+    /// Set the number of threads; 1 walks on the calling thread only
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Walk the whole tree
+    pub fn run(&self) -> Result<Walk> {
+        let mut walk = Walk::default();
+        let mut dirs = Vec::new();
+        self.visit(b"", &self.ignore, &mut walk, &mut dirs)?;
+
+        // Hand out the subdirectories of the root one at a time, so a
+        // single large directory doesn't hold up the others
+        let next = AtomicUsize::new(0);
+        let result = Mutex::new(Ok(walk));
+        let work = || {
+            let mut walk = Walk::default();
+            let mut outcome = Ok(());
+            loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some((dir, ignore)) = dirs.get(i) else {
+                    break;
+                };
+                outcome = self.walk_dir(dir, ignore, &mut walk);
+                if outcome.is_err() {
+                    break;
+                }
+            }
+            let mut result = result.lock().unwrap_or_else(|e| e.into_inner());
+            match (&mut *result, outcome) {
+                (Ok(total), Ok(())) => {
+                    total.files.append(&mut walk.files);
+                    total.ignored.append(&mut walk.ignored);
+                    total.repositories.append(&mut walk.repositories);
+                }
+                (Ok(_), Err(e)) => *result = Err(e),
+                (Err(_), _) => {}
+            }
+        };
+        let threads = self.threads.min(dirs.len());
+        if threads <= 1 {
+            work();
+        } else {
+            std::thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(work);
+                }
+            });
+        }
+
+        let mut walk = result.into_inner().unwrap_or_else(|e| e.into_inner())?;
+        walk.files.sort();
+        walk.ignored.sort();
+        walk.repositories.sort();
+        Ok(walk)
+    }
+
+    /// Walk a directory and everything below it on this thread
+    fn walk_dir(&self, dir: &[u8], ignore: &Arc<Ignore>, walk: &mut Walk) -> Result<()> {
+        let mut dirs = Vec::new();
+        self.visit(dir, ignore, walk, &mut dirs)?;
+        for (dir, ignore) in &dirs {
+            self.walk_dir(dir, ignore, walk)?;
+        }
+        Ok(())
+    }
+
+    /// List one directory, recording its files and collecting the
+    /// subdirectories still to visit along with the rules that apply there
+    fn visit(
+        &self,
+        dir: &[u8],
+        ignore: &Arc<Ignore>,
+        walk: &mut Walk,
+        dirs: &mut Vec<(Vec<u8>, Arc<Ignore>)>,
+    ) -> Result<()> {
+        let entries = self.worktree.list_dir(path_from_bytes(dir)?)?;
+        if !dir.is_empty() && entries.iter().any(|e| e.name == ".git") {
+            walk.repositories.push(dir.to_vec());
+            return Ok(());
+        }
+        let join = |name: &[u8]| match dir {
+            b"" => name.to_vec(),
+            _ => [dir, b"/", name].concat(),
+        };
+
+        let gitignore = join(b".gitignore");
+        let ignore = if entries.iter().any(|e| e.name == ".gitignore" && !e.is_dir) {
+            let text = self.worktree.read(path_from_bytes(&gitignore)?)?;
+            Arc::new(ignore.child(dir, text))
+        } else {
+            Arc::clone(ignore)
+        };
+
+        for entry in entries {
+            if entry.name == ".git" {
+                continue;
+            }
+            let path = join(&path_to_bytes(Path::new(&entry.name))?);
+            if ignore.is_ignored(&path, entry.is_dir) {
+                walk.ignored.push(if entry.is_dir {
+                    [&path[..], b"/"].concat()
+                } else {
+                    path
+                });
+            } else if entry.is_dir {
+                dirs.push((path, Arc::clone(&ignore)));
+            } else {
+                walk.files.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worktree::MemoryWorkTree;
+
+    #[test]
+    fn test_walk_prunes_ignored_directories() {
+        let mut worktree = MemoryWorkTree::new();
+        for (path, data) in [
+            (".gitignore", &b"target/\n*.log\n"[..]),
+            ("src/main.rs", b""),
+            ("src/debug.log", b""),
+            ("src/gen/.gitignore", b"*\n!.gitignore\n!keep.rs\n"),
+            ("src/gen/keep.rs", b""),
+            ("src/gen/out.rs", b""),
+            ("target/debug/app", b""),
+            ("docs/notes.md", b""),
+            ("vendor/lib/.git", b"gitdir: ../../.git/modules/lib\n"),
+            ("vendor/lib/src.rs", b""),
+        ] {
+            worktree.write(Path::new(path), data).unwrap();
+        }
+
+        let mut exclude = Ignore::new();
+        exclude.add_patterns("docs/\n");
+        for threads in [1, 4] {
+            let walk = Walker::new(&worktree, exclude.clone())
+                .with_threads(threads)
+                .run()
+                .unwrap();
+            assert_eq!(
+                walk.files,
+                [
+                    &b".gitignore"[..],
+                    b"src/gen/.gitignore",
+                    b"src/gen/keep.rs",
+                    b"src/main.rs"
+                ]
+            );
+            assert_eq!(
+                walk.ignored,
+                [
+                    &b"docs/"[..],
+                    b"src/debug.log",
+                    b"src/gen/out.rs",
+                    b"target/"
+                ]
+            );
+            assert_eq!(walk.repositories, [b"vendor/lib"]);
+        }
+    }
+}
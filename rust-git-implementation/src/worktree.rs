@@ -0,0 +1,1075 @@
+//! Working tree filesystem abstraction
+//!
+//! Commands like add, status and checkout need to read and write files in the
+//! working tree. Instead of calling `std::fs` directly they go through the
+//! [`WorkTree`] trait, which has three implementations:
+//!
+//! - [`FsWorkTree`]: the real filesystem rooted at the repository's work tree
+//! - [`MemoryWorkTree`]: an in-memory tree for deterministic tests
+//! - [`ReadOnly`]: a wrapper that rejects every modification
+//!
+//! All paths are relative to the root of the working tree. They are taken
+//! and returned as [`Path`]s, which on Unix hold any bytes, so file names
+//! that aren't UTF-8 pass through unchanged; convert to and from the byte
+//! paths of the index with [`crate::utils::path_from_bytes`] and
+//! [`crate::utils::path_to_bytes`].
+//!
+//! Symbolic links are stored by Git as blobs containing the link target
+//! (mode `120000`). Use [`WorkTree::read_link`] and [`WorkTree::write_symlink`]
+//! for them; on filesystems without symlink support (`core.symlinks = false`)
+//! the link is checked out as a plain file containing the target path.
+
+use crate::fsck::{is_dot_git, is_hfs_dot_git, is_ntfs_dot_git};
+use crate::objects::{Blob, FileMode, GitObject, ObjectType};
+use crate::utils::{hash_reader, path_from_bytes, path_to_bytes};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An entry of a working tree directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    /// File name within the directory, as the filesystem stores it
+    pub name: OsString,
+    /// Whether the entry is a directory (symlinks to directories are not)
+    pub is_dir: bool,
+}
+
+/// Operations on the files of a working tree
+///
+/// Working trees are `Sync` so that [`crate::walk`] can list directories
+/// from several threads.
+pub trait WorkTree: Sync {
+    /// Read the contents of a file
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Hash a file's contents as a blob, without storing it
+    ///
+    /// Implementations backed by files stream the contents instead of
+    /// reading them into memory.
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        Blob::new(self.read(path)?).hash()
+    }
+
+    /// Write a file, creating parent directories as needed
+    fn write(&mut self, path: &Path, data: &[u8]) -> Result<()>;
+
+    /// Remove a file
+    fn remove(&mut self, path: &Path) -> Result<()>;
+
+    /// Check whether a file or symlink exists
+    fn exists(&self, path: &Path) -> bool;
+
+    /// List all files and symlinks, sorted, skipping the `.git` directory
+    fn files(&self) -> Result<Vec<PathBuf>>;
+
+    /// List the entries of one directory, sorted by name
+    ///
+    /// An empty path lists the root. Nothing is skipped, not even `.git`.
+    fn list_dir(&self, dir: &Path) -> Result<Vec<DirEntry>>;
+
+    /// Get the mode Git would record for a path
+    fn mode(&self, path: &Path) -> Result<FileMode>;
+
+    /// Stat data of a file or symlink, for working trees on a filesystem
+    ///
+    /// Status skips hashing files whose stat data matches the index.
+    fn metadata(&self, _path: &Path) -> Option<fs::Metadata> {
+        None
+    }
+
+    /// Read the target of a symbolic link, as stored in its blob
+    fn read_link(&self, path: &Path) -> Result<Vec<u8>>;
+
+    /// Create a symbolic link pointing at `target`
+    fn write_symlink(&mut self, path: &Path, target: &[u8]) -> Result<()>;
+
+    /// Set or clear the executable bit of a file
+    fn set_executable(&mut self, path: &Path, executable: bool) -> Result<()>;
+}
+
+/// A working tree backed by the real filesystem
+pub struct FsWorkTree {
+    root: PathBuf,
+    symlinks: bool,
+    file_mode: bool,
+    precompose_unicode: bool,
+}
+
+impl FsWorkTree {
+    /// Create a working tree rooted at the given directory
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        FsWorkTree {
+            root: root.as_ref().to_path_buf(),
+            symlinks: true,
+            file_mode: true,
+            precompose_unicode: false,
+        }
+    }
+
+    /// Set whether listed paths are converted to NFC (`core.precomposeUnicode`)
+    ///
+    /// macOS stores file names decomposed (NFD), while Git repositories
+    /// created elsewhere record them precomposed (NFC). Without conversion an
+    /// accented name would show up as both untracked and deleted.
+    pub fn with_precompose_unicode(mut self, enabled: bool) -> Self {
+        self.precompose_unicode = enabled;
+        self
+    }
+
+    /// Set whether the executable bit is trusted (`core.fileMode`)
+    ///
+    /// When disabled, every file reports [`FileMode::Regular`] and
+    /// [`WorkTree::set_executable`] does nothing, for filesystems (FAT,
+    /// some network mounts) that cannot represent the bit.
+    pub fn with_file_mode(mut self, enabled: bool) -> Self {
+        self.file_mode = enabled;
+        self
+    }
+
+    /// Set whether symlinks can be created (`core.symlinks`)
+    ///
+    /// When disabled, symlinks are written as plain files containing the
+    /// link target.
+    pub fn with_symlinks(mut self, enabled: bool) -> Self {
+        self.symlinks = enabled;
+        self
+    }
+
+    /// Get the root directory of the working tree
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Join a worktree-relative path onto the root
+    ///
+    /// On Windows, paths beyond `MAX_PATH` get the `\\?\` prefix so deep
+    /// checkouts don't fail.
+    fn full_path(&self, path: &Path) -> PathBuf {
+        let full = self.root.join(path);
+        if cfg!(windows) {
+            long_path(&full)
+        } else {
+            full
+        }
+    }
+
+    /// Check a path is safe to create or remove
+    ///
+    /// Besides [`verify_path`], none of its leading directories may be a
+    /// symlink, which could point outside the worktree.
+    fn check_writable(&self, path: &Path) -> Result<()> {
+        verify_path(path)?;
+        for dir in path.ancestors().skip(1) {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            if self.full_path(dir).is_symlink() {
+                anyhow::bail!("'{}' is beyond a symbolic link", path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl WorkTree for FsWorkTree {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        fs::read(self.full_path(path)).with_context(|| format!("Failed to read {}", path.display()))
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        let file = fs::File::open(self.full_path(path))
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let size = file.metadata()?.len();
+        hash_reader(ObjectType::Blob, size, std::io::BufReader::new(file))
+            .with_context(|| format!("Failed to hash {}", path.display()))
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.check_writable(path)?;
+        let full = self.full_path(path);
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Replace a symlink instead of writing through it
+        if full.is_symlink() {
+            fs::remove_file(&full)?;
+        }
+        fs::write(&full, data).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        self.check_writable(path)?;
+        fs::remove_file(self.full_path(path))
+            .with_context(|| format!("Failed to remove {}", path.display()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        fs::symlink_metadata(self.full_path(path)).is_ok_and(|m| !m.is_dir())
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        let walker = walkdir::WalkDir::new(&self.root)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git");
+
+        for entry in walker {
+            let entry = entry?;
+            if entry.file_type().is_file() || entry.file_type().is_symlink() {
+                let relative = entry.path().strip_prefix(&self.root)?;
+                if self.precompose_unicode {
+                    files.push(precompose(relative));
+                } else {
+                    files.push(relative.to_path_buf());
+                }
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+
+    fn list_dir(&self, dir: &Path) -> Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+        let listing = fs::read_dir(self.full_path(dir))
+            .with_context(|| format!("Failed to list {}", dir.display()))?;
+        for entry in listing {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = if self.precompose_unicode {
+                precompose(Path::new(&name)).into_os_string()
+            } else {
+                name
+            };
+            entries.push(DirEntry {
+                name,
+                is_dir: entry.file_type()?.is_dir(),
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn mode(&self, path: &Path) -> Result<FileMode> {
+        let meta = fs::symlink_metadata(self.full_path(path))
+            .with_context(|| format!("Failed to stat {}", path.display()))?;
+        if meta.is_symlink() {
+            Ok(FileMode::Symlink)
+        } else if meta.is_dir() {
+            Ok(FileMode::Tree)
+        } else if self.file_mode && is_executable(&meta) {
+            Ok(FileMode::Executable)
+        } else {
+            Ok(FileMode::Regular)
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> Option<fs::Metadata> {
+        fs::symlink_metadata(self.full_path(path)).ok()
+    }
+
+    fn read_link(&self, path: &Path) -> Result<Vec<u8>> {
+        let full = self.full_path(path);
+        if !full.is_symlink() {
+            // Links checked out without symlink support are plain files
+            return self.read(path);
+        }
+        let target = fs::read_link(&full)
+            .with_context(|| format!("Failed to read link {}", path.display()))?;
+        path_to_bytes(&target)
+    }
+
+    fn write_symlink(&mut self, path: &Path, target: &[u8]) -> Result<()> {
+        if !self.symlinks {
+            return self.write(path, target);
+        }
+
+        self.check_writable(path)?;
+        let full = self.full_path(path);
+        if let Some(parent) = full.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::symlink_metadata(&full).is_ok() {
+            fs::remove_file(&full)?;
+        }
+        create_symlink(path_from_bytes(target)?, &full)
+            .with_context(|| format!("Failed to create symlink {}", path.display()))
+    }
+
+    fn set_executable(&mut self, path: &Path, executable: bool) -> Result<()> {
+        if !self.file_mode {
+            return Ok(());
+        }
+        set_executable_bit(&self.full_path(path), executable)
+            .with_context(|| format!("Failed to change mode of {}", path.display()))
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &fs::Metadata) -> bool {
+    false
+}
+
+/// Set the executable bits Git uses: 755 when executable, 644 otherwise,
+/// both filtered through the file's existing read/write permissions
+#[cfg(unix)]
+fn set_executable_bit(path: &Path, executable: bool) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = fs::metadata(path)?.permissions();
+    let mode = perms.mode();
+    let mode = if executable {
+        // Grant execute wherever read is granted
+        mode | ((mode & 0o444) >> 2)
+    } else {
+        mode & !0o111
+    };
+    perms.set_mode(mode);
+    fs::set_permissions(path, perms)
+}
+
+#[cfg(not(unix))]
+fn set_executable_bit(_path: &Path, _executable: bool) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Windows' classic path length limit
+const MAX_PATH: usize = 260;
+
+/// Add the `\\?\` prefix to absolute paths too long for the Win32 API
+///
+/// The prefix disables path normalization, so forward slashes are converted
+/// to backslashes first. Short, relative and already-prefixed paths are
+/// returned unchanged, as are paths that aren't valid Unicode.
+pub fn long_path(path: &Path) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    let is_absolute = text.as_bytes().get(1) == Some(&b':') || text.starts_with("\\\\");
+    if text.len() < MAX_PATH || !is_absolute || text.starts_with("\\\\?\\") {
+        return path.to_path_buf();
+    }
+
+    let text = text.replace('/', "\\");
+    match text.strip_prefix("\\\\") {
+        // UNC paths use the \\?\UNC\server\share form
+        Some(unc) => PathBuf::from(format!("\\\\?\\UNC\\{}", unc)),
+        None => PathBuf::from(format!("\\\\?\\{}", text)),
+    }
+}
+
+/// Device names Windows reserves in every directory
+const RESERVED_NAMES: [&str; 6] = ["CON", "PRN", "AUX", "NUL", "CONIN$", "CONOUT$"];
+
+/// Check whether a path component is a reserved Windows device name
+///
+/// Windows ignores extensions and trailing dots/spaces here, so `nul.txt`
+/// and `COM1 ` refer to devices just like `NUL` and `COM1`.
+pub fn is_windows_reserved_name(component: &str) -> bool {
+    let stem = component.split('.').next().unwrap_or("");
+    let stem = stem.trim_end_matches([' ', '.']).to_ascii_uppercase();
+
+    if RESERVED_NAMES.contains(&stem.as_str()) {
+        return true;
+    }
+    // COM1-COM9 and LPT1-LPT9 (Windows also accepts superscript digits)
+    match stem
+        .strip_prefix("COM")
+        .or_else(|| stem.strip_prefix("LPT"))
+    {
+        Some(digit) => matches!(
+            digit,
+            "1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³"
+        ),
+        None => false,
+    }
+}
+
+/// Check that a tree path can be written on Windows
+///
+/// Rejects reserved device names, characters NTFS forbids, backslashes
+/// (which Windows would treat as separators) and components ending in a
+/// dot or space (which Windows silently strips).
+pub fn check_windows_path(path: &str) -> Result<()> {
+    for component in path.split('/') {
+        if is_windows_reserved_name(component) {
+            anyhow::bail!(
+                "Invalid path '{}': '{}' is a reserved name on Windows",
+                path,
+                component
+            );
+        }
+        if let Some(c) = component
+            .chars()
+            .find(|c| "<>:\"|?*\\".contains(*c) || (*c as u32) < 32)
+        {
+            anyhow::bail!(
+                "Invalid path '{}': character {:?} is not allowed on Windows",
+                path,
+                c
+            );
+        }
+        if component.ends_with(['.', ' ']) && component != "." && component != ".." {
+            anyhow::bail!(
+                "Invalid path '{}': '{}' ends with a dot or space",
+                path,
+                component
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Refuse to write a path Git would never check out
+///
+/// Like Git's `verify_path`, this rejects absolute paths, empty, `.` and
+/// `..` components, and `.git` in any spelling the filesystem could treat
+/// as the repository directory. On Windows the path must also be
+/// representable there; see [`check_windows_path`].
+///
+/// # Arguments
+///
+/// * `path` - The path to write, relative to the worktree root
+pub fn verify_path(path: &Path) -> Result<()> {
+    let bytes = path_to_bytes(path)?;
+    let invalid = |component: &[u8]| {
+        matches!(component, b"" | b"." | b"..")
+            || is_dot_git(component)
+            || is_ntfs_dot_git(component)
+            || (cfg!(target_os = "macos") && is_hfs_dot_git(component))
+    };
+    if bytes.split(|&b| b == b'/').any(invalid) {
+        anyhow::bail!("Invalid path '{}'", path.display());
+    }
+    if cfg!(windows) {
+        let text = path
+            .to_str()
+            .with_context(|| format!("Cannot check out '{}': not valid Unicode", path.display()))?;
+        check_windows_path(&text.replace('\\', "/"))?;
+    }
+    Ok(())
+}
+
+/// Convert a path to Unicode NFC, leaving non-UTF-8 paths untouched
+pub fn precompose(path: &Path) -> PathBuf {
+    use unicode_normalization::UnicodeNormalization;
+    match path.to_str() {
+        Some(s) => PathBuf::from(s.nfc().collect::<String>()),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Check whether the filesystem under `dir` decomposes Unicode file names
+///
+/// Used by init to decide whether to set `core.precomposeUnicode = true`: a
+/// file created with a precomposed name is looked up by its decomposed form.
+pub fn probe_precompose_unicode(dir: &Path) -> bool {
+    let probe = dir.join(".oxid-\u{00c4}-probe");
+    let detected = fs::write(&probe, b"").is_ok()
+        && fs::symlink_metadata(dir.join(".oxid-A\u{0308}-probe")).is_ok();
+    let _ = fs::remove_file(&probe);
+    detected
+}
+
+/// Check whether the filesystem under `dir` ignores file name case
+///
+/// Used by init to decide whether to set `core.ignoreCase = true`. Like Git,
+/// this looks up a differently-cased name of a file known to exist.
+pub fn probe_ignore_case(dir: &Path) -> bool {
+    let probe = dir.join(".oxid-case-probe");
+    let detected = fs::write(&probe, b"").is_ok()
+        && fs::symlink_metadata(dir.join(".OXID-CASE-PROBE")).is_ok();
+    let _ = fs::remove_file(&probe);
+    detected
+}
+
+/// Find paths that would collide on a case-insensitive filesystem
+///
+/// Returns each pair of distinct paths that differ only in letter case, so
+/// checkout can refuse (or warn) instead of silently overwriting one file
+/// with the other on macOS or Windows.
+pub fn case_collisions(paths: &[PathBuf]) -> Vec<(PathBuf, PathBuf)> {
+    let mut seen: BTreeMap<OsString, &PathBuf> = BTreeMap::new();
+    let mut collisions = Vec::new();
+
+    for path in paths {
+        // Names that aren't Unicode can only be folded byte by byte
+        let key = match path.to_str() {
+            Some(text) => OsString::from(text.to_lowercase()),
+            None => path.as_os_str().to_ascii_lowercase(),
+        };
+        match seen.get(&key) {
+            Some(first) if *first != path => collisions.push(((*first).clone(), path.clone())),
+            Some(_) => {}
+            None => {
+                seen.insert(key, path);
+            }
+        }
+    }
+
+    collisions
+}
+
+/// Check whether the filesystem under `dir` keeps the executable bit
+///
+/// Used by init to decide the value of `core.fileMode`.
+pub fn probe_file_mode(dir: &Path) -> bool {
+    let probe = dir.join(".oxid-filemode-probe");
+    let supported = fs::write(&probe, b"")
+        .and_then(|_| set_executable_bit(&probe, true))
+        .and_then(|_| fs::metadata(&probe))
+        .is_ok_and(|meta| is_executable(&meta));
+    let _ = fs::remove_file(&probe);
+    supported
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Check whether symlinks can be created in a directory
+///
+/// Used by init to decide whether to set `core.symlinks = false`.
+pub fn probe_symlinks(dir: &Path) -> bool {
+    let link = dir.join(".oxid-symlink-probe");
+    let supported = create_symlink(Path::new("target"), &link).is_ok();
+    let _ = fs::remove_file(&link);
+    supported
+}
+
+/// A file stored in a [`MemoryWorkTree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum MemoryEntry {
+    File { data: Vec<u8>, executable: bool },
+    Symlink(Vec<u8>),
+}
+
+/// A working tree held entirely in memory
+#[derive(Debug, Clone, Default)]
+pub struct MemoryWorkTree {
+    files: BTreeMap<PathBuf, MemoryEntry>,
+}
+
+impl MemoryWorkTree {
+    /// Create an empty in-memory working tree
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a snapshot of every file in another working tree
+    pub fn snapshot(other: &dyn WorkTree) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for path in other.files()? {
+            let entry = match other.mode(&path)? {
+                FileMode::Symlink => MemoryEntry::Symlink(other.read_link(&path)?),
+                mode => MemoryEntry::File {
+                    data: other.read(&path)?,
+                    executable: mode == FileMode::Executable,
+                },
+            };
+            files.insert(path, entry);
+        }
+        Ok(MemoryWorkTree { files })
+    }
+}
+
+impl WorkTree for MemoryWorkTree {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(MemoryEntry::File { data, .. }) => Ok(data.clone()),
+            // Follow one level of symlink, relative to the link's directory
+            Some(MemoryEntry::Symlink(target)) => {
+                let target = path_from_bytes(target)?;
+                let mut resolved = path.parent().unwrap_or(Path::new("")).to_path_buf();
+                for component in target.components() {
+                    match component {
+                        std::path::Component::ParentDir => {
+                            resolved.pop();
+                        }
+                        std::path::Component::CurDir => {}
+                        other => resolved.push(other),
+                    }
+                }
+                match self.files.get(&resolved) {
+                    Some(MemoryEntry::File { data, .. }) => Ok(data.clone()),
+                    _ => anyhow::bail!("Dangling symlink: {}", path.display()),
+                }
+            }
+            None => anyhow::bail!("No such file: {}", path.display()),
+        }
+    }
+
+    fn write(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        // Like the filesystem, rewriting a file keeps its executable bit
+        let executable = matches!(
+            self.files.get(path),
+            Some(MemoryEntry::File {
+                executable: true,
+                ..
+            })
+        );
+        let entry = MemoryEntry::File {
+            data: data.to_vec(),
+            executable,
+        };
+        self.files.insert(path.to_path_buf(), entry);
+        Ok(())
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        self.files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| anyhow::anyhow!("No such file: {}", path.display()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn list_dir(&self, dir: &Path) -> Result<Vec<DirEntry>> {
+        // Directories exist implicitly, as prefixes of file paths
+        let mut entries = BTreeMap::new();
+        for path in self.files.keys() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let mut components = relative.components();
+            if let Some(name) = components.next() {
+                let name = name.as_os_str().to_os_string();
+                let is_dir = components.next().is_some();
+                *entries.entry(name).or_insert(false) |= is_dir;
+            }
+        }
+        Ok(entries
+            .into_iter()
+            .map(|(name, is_dir)| DirEntry { name, is_dir })
+            .collect())
+    }
+
+    fn mode(&self, path: &Path) -> Result<FileMode> {
+        match self.files.get(path) {
+            Some(MemoryEntry::File {
+                executable: true, ..
+            }) => Ok(FileMode::Executable),
+            Some(MemoryEntry::File { .. }) => Ok(FileMode::Regular),
+            Some(MemoryEntry::Symlink(_)) => Ok(FileMode::Symlink),
+            None => anyhow::bail!("No such file: {}", path.display()),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> Result<Vec<u8>> {
+        match self.files.get(path) {
+            Some(MemoryEntry::Symlink(target)) | Some(MemoryEntry::File { data: target, .. }) => {
+                Ok(target.clone())
+            }
+            None => anyhow::bail!("No such file: {}", path.display()),
+        }
+    }
+
+    fn write_symlink(&mut self, path: &Path, target: &[u8]) -> Result<()> {
+        self.files
+            .insert(path.to_path_buf(), MemoryEntry::Symlink(target.to_vec()));
+        Ok(())
+    }
+
+    fn set_executable(&mut self, path: &Path, executable: bool) -> Result<()> {
+        match self.files.get_mut(path) {
+            Some(MemoryEntry::File {
+                executable: bit, ..
+            }) => {
+                *bit = executable;
+                Ok(())
+            }
+            Some(MemoryEntry::Symlink(_)) => Ok(()),
+            None => anyhow::bail!("No such file: {}", path.display()),
+        }
+    }
+}
+
+/// A working tree wrapper that refuses all modifications
+///
+/// Useful for sandboxed environments where commands may inspect the working
+/// tree but must never change it.
+pub struct ReadOnly<W: WorkTree> {
+    inner: W,
+}
+
+impl<W: WorkTree> ReadOnly<W> {
+    /// Wrap a working tree so that writes and removals fail
+    pub fn new(inner: W) -> Self {
+        ReadOnly { inner }
+    }
+
+    /// Unwrap the underlying working tree
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: WorkTree> WorkTree for ReadOnly<W> {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.inner.read(path)
+    }
+
+    fn hash_file(&self, path: &Path) -> Result<String> {
+        self.inner.hash_file(path)
+    }
+
+    fn write(&mut self, path: &Path, _data: &[u8]) -> Result<()> {
+        anyhow::bail!("Working tree is read-only: cannot write {}", path.display())
+    }
+
+    fn remove(&mut self, path: &Path) -> Result<()> {
+        anyhow::bail!(
+            "Working tree is read-only: cannot remove {}",
+            path.display()
+        )
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        self.inner.exists(path)
+    }
+
+    fn files(&self) -> Result<Vec<PathBuf>> {
+        self.inner.files()
+    }
+
+    fn list_dir(&self, dir: &Path) -> Result<Vec<DirEntry>> {
+        self.inner.list_dir(dir)
+    }
+
+    fn mode(&self, path: &Path) -> Result<FileMode> {
+        self.inner.mode(path)
+    }
+
+    fn metadata(&self, path: &Path) -> Option<fs::Metadata> {
+        self.inner.metadata(path)
+    }
+
+    fn read_link(&self, path: &Path) -> Result<Vec<u8>> {
+        self.inner.read_link(path)
+    }
+
+    fn write_symlink(&mut self, path: &Path, _target: &[u8]) -> Result<()> {
+        anyhow::bail!(
+            "Working tree is read-only: cannot create {}",
+            path.display()
+        )
+    }
+
+    fn set_executable(&mut self, path: &Path, _executable: bool) -> Result<()> {
+        anyhow::bail!(
+            "Working tree is read-only: cannot change mode of {}",
+            path.display()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_fs_write_read_and_list() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join(".git/HEAD"), b"ref: refs/heads/main\n").unwrap();
+
+        let mut wt = FsWorkTree::new(dir.path());
+        wt.write(Path::new("src/main.rs"), b"fn main() {}").unwrap();
+        wt.write(Path::new("README"), b"hello").unwrap();
+
+        assert_eq!(wt.read(Path::new("src/main.rs")).unwrap(), b"fn main() {}");
+        assert!(wt.exists(Path::new("README")));
+        assert_eq!(
+            wt.hash_file(Path::new("README")).unwrap(),
+            Blob::new(&b"hello"[..]).hash().unwrap()
+        );
+
+        // The .git directory is never listed
+        let files = wt.files().unwrap();
+        assert_eq!(
+            files,
+            vec![PathBuf::from("README"), PathBuf::from("src/main.rs")]
+        );
+
+        wt.remove(Path::new("README")).unwrap();
+        assert!(!wt.exists(Path::new("README")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fs_symlinks() {
+        let dir = tempdir().unwrap();
+        let mut wt = FsWorkTree::new(dir.path());
+        wt.write(Path::new("target.txt"), b"data").unwrap();
+        wt.write_symlink(Path::new("link"), b"target.txt").unwrap();
+
+        assert!(probe_symlinks(dir.path()));
+        assert_eq!(wt.mode(Path::new("link")).unwrap(), FileMode::Symlink);
+        assert_eq!(wt.mode(Path::new("target.txt")).unwrap(), FileMode::Regular);
+        assert_eq!(wt.read_link(Path::new("link")).unwrap(), b"target.txt");
+        assert_eq!(wt.read(Path::new("link")).unwrap(), b"data");
+
+        // Dangling links are still listed
+        wt.write_symlink(Path::new("dangling"), b"nowhere").unwrap();
+        assert!(wt.exists(Path::new("dangling")));
+        assert_eq!(
+            wt.files().unwrap(),
+            vec![
+                PathBuf::from("dangling"),
+                PathBuf::from("link"),
+                PathBuf::from("target.txt")
+            ]
+        );
+
+        // Writing over a symlink replaces the link, not its target
+        wt.write(Path::new("link"), b"replaced").unwrap();
+        assert_eq!(wt.mode(Path::new("link")).unwrap(), FileMode::Regular);
+        assert_eq!(wt.read(Path::new("target.txt")).unwrap(), b"data");
+
+        // Nothing is written or removed through a symlinked directory
+        let outside = tempdir().unwrap();
+        fs::write(outside.path().join("file"), b"outside").unwrap();
+        wt.write_symlink(Path::new("dir"), &path_to_bytes(outside.path()).unwrap())
+            .unwrap();
+        assert!(wt.write(Path::new("dir/file"), b"x").is_err());
+        assert!(wt.write(Path::new("dir/new"), b"x").is_err());
+        assert!(wt.write_symlink(Path::new("dir/link"), b"x").is_err());
+        assert!(wt.remove(Path::new("dir/file")).is_err());
+        assert_eq!(fs::read(outside.path().join("file")).unwrap(), b"outside");
+        assert!(!outside.path().join("new").exists());
+    }
+
+    #[test]
+    fn test_verify_path() {
+        for path in ["a", "dir/.gitignore", "a/..b/c", ".github/x", "git~2"] {
+            assert!(verify_path(Path::new(path)).is_ok(), "{}", path);
+        }
+        for path in [
+            "",
+            "/etc/passwd",
+            "../escape",
+            "a/../../b",
+            "a//b",
+            "./a",
+            "a/",
+            ".git/config",
+            "sub/.GIT/hooks/x",
+            ".git. /x",
+            "GIT~1/config",
+        ] {
+            assert!(verify_path(Path::new(path)).is_err(), "{}", path);
+        }
+
+        let dir = tempdir().unwrap();
+        let mut wt = FsWorkTree::new(dir.path());
+        assert!(wt.write(Path::new("../escape"), b"x").is_err());
+        assert!(wt.write_symlink(Path::new(".git/hooks/x"), b"x").is_err());
+        assert!(!dir.path().join(".git").exists());
+    }
+
+    #[test]
+    fn test_fs_symlinks_disabled() {
+        let dir = tempdir().unwrap();
+        let mut wt = FsWorkTree::new(dir.path()).with_symlinks(false);
+        wt.write_symlink(Path::new("link"), b"target.txt").unwrap();
+
+        // The link becomes a plain file containing the target path
+        assert_eq!(wt.mode(Path::new("link")).unwrap(), FileMode::Regular);
+        assert_eq!(wt.read(Path::new("link")).unwrap(), b"target.txt");
+        assert_eq!(wt.read_link(Path::new("link")).unwrap(), b"target.txt");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_fs_executable_bit() {
+        let dir = tempdir().unwrap();
+        let mut wt = FsWorkTree::new(dir.path());
+        wt.write(Path::new("run.sh"), b"#!/bin/sh\n").unwrap();
+        assert_eq!(wt.mode(Path::new("run.sh")).unwrap(), FileMode::Regular);
+
+        assert!(probe_file_mode(dir.path()));
+        wt.set_executable(Path::new("run.sh"), true).unwrap();
+        assert_eq!(wt.mode(Path::new("run.sh")).unwrap(), FileMode::Executable);
+
+        // With core.fileMode = false the bit is ignored
+        let mut untrusted = FsWorkTree::new(dir.path()).with_file_mode(false);
+        assert_eq!(
+            untrusted.mode(Path::new("run.sh")).unwrap(),
+            FileMode::Regular
+        );
+        untrusted
+            .set_executable(Path::new("run.sh"), false)
+            .unwrap();
+        assert_eq!(wt.mode(Path::new("run.sh")).unwrap(), FileMode::Executable);
+
+        wt.set_executable(Path::new("run.sh"), false).unwrap();
+        assert_eq!(wt.mode(Path::new("run.sh")).unwrap(), FileMode::Regular);
+    }
+
+    #[test]
+    fn test_windows_reserved_names() {
+        for name in [
+            "CON", "nul", "Nul.txt", "aux.c", "COM1", "lpt9", "con ", "CONIN$",
+        ] {
+            assert!(is_windows_reserved_name(name), "{}", name);
+        }
+        for name in ["CONSOLE", "COM0", "COM10", "nullable", "readme.txt", ""] {
+            assert!(!is_windows_reserved_name(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_check_windows_path() {
+        assert!(check_windows_path("src/main.rs").is_ok());
+        assert!(check_windows_path("docs/.hidden/file").is_ok());
+        assert!(check_windows_path("src/aux/mod.rs").is_err());
+        assert!(check_windows_path("a:b").is_err());
+        assert!(check_windows_path("what?").is_err());
+        assert!(check_windows_path("dir\\file").is_err());
+        assert!(check_windows_path("trailing./file").is_err());
+        assert!(check_windows_path("file ").is_err());
+    }
+
+    #[test]
+    fn test_long_path() {
+        let short = Path::new("C:\\repo\\file.txt");
+        assert_eq!(long_path(short), short);
+
+        let deep = format!("C:\\repo/{}", "d/".repeat(140));
+        assert_eq!(
+            long_path(Path::new(&deep)),
+            PathBuf::from(format!("\\\\?\\{}", deep.replace('/', "\\")))
+        );
+
+        let unc = format!("\\\\server\\share\\{}", "d\\".repeat(140));
+        assert!(long_path(Path::new(&unc))
+            .to_string_lossy()
+            .starts_with("\\\\?\\UNC\\server\\share"));
+
+        // Relative paths cannot take the prefix
+        let relative = "d/".repeat(140);
+        assert_eq!(long_path(Path::new(&relative)), Path::new(&relative));
+    }
+
+    #[test]
+    fn test_precompose() {
+        let decomposed = Path::new("dir/A\u{0308}rger.txt");
+        assert_eq!(
+            precompose(decomposed),
+            PathBuf::from("dir/\u{00c4}rger.txt")
+        );
+
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("A\u{0308}.txt"), b"").unwrap();
+        let wt = FsWorkTree::new(dir.path()).with_precompose_unicode(true);
+        assert_eq!(wt.files().unwrap(), vec![PathBuf::from("\u{00c4}.txt")]);
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let paths = vec![
+            PathBuf::from("README"),
+            PathBuf::from("src/Main.rs"),
+            PathBuf::from("readme"),
+            PathBuf::from("src/main.rs"),
+            PathBuf::from("other"),
+        ];
+        assert_eq!(
+            case_collisions(&paths),
+            vec![
+                (PathBuf::from("README"), PathBuf::from("readme")),
+                (PathBuf::from("src/Main.rs"), PathBuf::from("src/main.rs")),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_names() {
+        use std::os::unix::ffi::OsStrExt;
+        let latin1 = Path::new(std::ffi::OsStr::from_bytes(b"caf\xe9"));
+
+        let dir = tempdir().unwrap();
+        let mut wt = FsWorkTree::new(dir.path());
+        wt.write(latin1, b"x").unwrap();
+        wt.write_symlink(Path::new("link"), b"caf\xe9").unwrap();
+        assert_eq!(
+            wt.files().unwrap(),
+            vec![latin1.to_path_buf(), "link".into()]
+        );
+        assert_eq!(wt.list_dir(Path::new("")).unwrap()[0].name, latin1);
+        assert_eq!(wt.read_link(Path::new("link")).unwrap(), b"caf\xe9");
+        assert_eq!(wt.read(Path::new("link")).unwrap(), b"x");
+
+        // Distinct names must not collide just because they aren't UTF-8
+        let paths = [
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"A\xe9")),
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"a\xea")),
+            PathBuf::from(std::ffi::OsStr::from_bytes(b"a\xe9")),
+        ];
+        assert_eq!(
+            case_collisions(&paths),
+            vec![(paths[0].clone(), paths[2].clone())]
+        );
+    }
+
+    #[test]
+    fn test_memory_worktree() {
+        let mut wt = MemoryWorkTree::new();
+        wt.write(Path::new("a.txt"), b"a").unwrap();
+
+        assert_eq!(wt.read(Path::new("a.txt")).unwrap(), b"a");
+        assert!(wt.read(Path::new("missing")).is_err());
+        assert!(wt.remove(Path::new("missing")).is_err());
+
+        wt.write_symlink(Path::new("dir/link"), b"../a.txt")
+            .unwrap();
+        assert_eq!(wt.mode(Path::new("dir/link")).unwrap(), FileMode::Symlink);
+        assert_eq!(wt.read_link(Path::new("dir/link")).unwrap(), b"../a.txt");
+        assert_eq!(wt.read(Path::new("dir/link")).unwrap(), b"a");
+
+        wt.set_executable(Path::new("a.txt"), true).unwrap();
+        wt.write(Path::new("a.txt"), b"new").unwrap();
+        assert_eq!(wt.mode(Path::new("a.txt")).unwrap(), FileMode::Executable);
+    }
+
+    #[test]
+    fn test_snapshot_is_read_only() {
+        let mut source = MemoryWorkTree::new();
+        source.write(Path::new("a.txt"), b"a").unwrap();
+
+        let mut snapshot = ReadOnly::new(MemoryWorkTree::snapshot(&source).unwrap());
+        source.write(Path::new("a.txt"), b"changed").unwrap();
+
+        assert_eq!(snapshot.read(Path::new("a.txt")).unwrap(), b"a");
+        assert!(snapshot.write(Path::new("b.txt"), b"b").is_err());
+        assert!(snapshot.remove(Path::new("a.txt")).is_err());
+    }
+}